@@ -0,0 +1,250 @@
+extern crate flac;
+
+use flac::StreamBuffer;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// Times decoding every FLAC file within a directory, through the same two
+// paths as `i16_bench`, and writes a report with a row for each file and
+// path to standard output, as JSON by default or as CSV with `--csv`.
+// Each row has the decode speed in megabytes of audio frames per second,
+// leaving out the metadata, and as a multiple of realtime, which makes
+// comparing against other decoders, or attaching numbers to a performance
+// issue, a matter of running this over the same files. Build with `--release` for numbers worth comparing.
+fn main() {
+  let args = env::args().skip(1).collect::<Vec<_>>();
+
+  let is_csv   = args.iter().any(|arg| arg == "--csv");
+  let mut rest = args.iter().filter(|arg| *arg != "--csv");
+
+  let directory = match rest.next() {
+    Some(directory) => directory.clone(),
+    None            => {
+      let _ = writeln!(io::stderr(),
+                       "Usage: bench_report <directory> [runs] [--csv]");
+
+      return;
+    }
+  };
+
+  let runs = rest.next().and_then(|runs| runs.parse().ok()).unwrap_or(10);
+
+  let mut filenames = match fs::read_dir(&directory) {
+    Ok(entries) => {
+      entries.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+             .filter(|path| {
+               path.extension().map_or(false, |extension| {
+                 extension.eq_ignore_ascii_case("flac")
+               })
+             })
+             .collect::<Vec<_>>()
+    }
+    Err(error)  => {
+      let _ = writeln!(io::stderr(), "{}: {}", directory, error);
+
+      return;
+    }
+  };
+
+  filenames.sort();
+
+  let mut rows = Vec::new();
+
+  for filename in &filenames {
+    match bench(filename, runs) {
+      Ok(file_rows) => rows.extend(file_rows),
+      Err(error)    => {
+        let _ = writeln!(io::stderr(), "{}: {}", filename.display(), error);
+      }
+    }
+  }
+
+  if is_csv {
+    write_csv(&rows);
+  } else {
+    write_json(&rows, runs);
+  }
+}
+
+// Timing of a single decode path over a single file.
+struct Row {
+  file: String,
+  path: &'static str,
+  audio_bytes: u64,
+  seconds: f64,
+  milliseconds: f64,
+}
+
+impl Row {
+  fn mb_per_second(&self) -> f64 {
+    per_second(self.audio_bytes as f64 / 1.0e6, self.milliseconds)
+  }
+
+  fn realtime(&self) -> f64 {
+    per_second(self.seconds, self.milliseconds)
+  }
+}
+
+// Rate of `amount` over the given time, with no time at all giving zero
+// rather than infinity, which JSON has no way of writing.
+fn per_second(amount: f64, milliseconds: f64) -> f64 {
+  if milliseconds > 0.0 { amount / (milliseconds / 1000.0) } else { 0.0 }
+}
+
+// Times each decode path over the file, with the whole file read into
+// memory up front so only decoding gets measured.
+fn bench(filename: &Path, runs: usize) -> Result<Vec<Row>, String> {
+  let mut bytes = Vec::new();
+
+  try!(File::open(filename).and_then(|mut file| file.read_to_end(&mut bytes))
+                           .map_err(|error| error.to_string()));
+
+  let mut stream = try!(StreamBuffer::from_buffer(&bytes)
+                          .map_err(|error| format!("{:?}", error)));
+  let info        = stream.info();
+  let audio_bytes = bytes.len() as u64 - stream.audio_offset();
+  let samples     = stream.iter::<i32>().count() as u64;
+
+  if let Some(error) = stream.decode_progress().error {
+    return Err(format!("{:?}", error));
+  }
+
+  let channels = info.channels as u64;
+  let seconds  = if channels == 0 {
+    0.0
+  } else {
+    (samples / channels) as f64 / info.sample_rate.get() as f64
+  };
+
+  let iter = time(runs, || {
+    let mut stream = StreamBuffer::from_buffer(&bytes).unwrap();
+
+    stream.iter::<i32>().fold(0, |sum: i64, sample| sum + sample as i64)
+  });
+
+  let into = time(runs, || {
+    let mut stream = StreamBuffer::from_buffer(&bytes).unwrap();
+    let info       = stream.info();
+    let channels   = info.channels as usize;
+    let mut output = vec![0; info.max_block_size as usize * channels];
+    let mut sum    = 0;
+
+    while let Ok(block_size) = stream.next_frame_into_i16(&mut output) {
+      if block_size == 0 {
+        break;
+      }
+
+      sum += output[0..(block_size * channels)].iter()
+                                               .fold(0, |sum: i64, &sample| {
+        sum + sample as i64
+      });
+    }
+
+    sum
+  });
+
+  let file = filename.file_name().unwrap_or(filename.as_os_str())
+                     .to_string_lossy().into_owned();
+
+  Ok(vec![("iter", iter), ("next_frame_into_i16", into)].into_iter()
+    .map(|(path, duration)| {
+      Row {
+        file: file.clone(),
+        path: path,
+        audio_bytes: audio_bytes,
+        seconds: seconds,
+        milliseconds: milliseconds(duration),
+      }
+    }).collect())
+}
+
+fn write_json(rows: &[Row], runs: usize) {
+  println!("{{");
+  println!("  \"runs\": {},", runs);
+  println!("  \"results\": [");
+
+  for (index, row) in rows.iter().enumerate() {
+    let separator = if index + 1 < rows.len() { "," } else { "" };
+
+    println!("    {{\"file\": \"{}\", \"path\": \"{}\", \"audio_bytes\": {}, \
+              \"seconds\": {:.3}, \"milliseconds\": {:.3}, \
+              \"mb_per_second\": {:.3}, \"realtime\": {:.1}}}{}",
+             json_escape(&row.file), row.path, row.audio_bytes, row.seconds,
+             row.milliseconds, row.mb_per_second(), row.realtime(),
+             separator);
+  }
+
+  println!("  ]");
+  println!("}}");
+}
+
+fn write_csv(rows: &[Row]) {
+  println!("file,path,audio_bytes,seconds,milliseconds,mb_per_second,\
+            realtime");
+
+  for row in rows {
+    println!("{},{},{},{:.3},{:.3},{:.3},{:.1}", csv_escape(&row.file),
+             row.path, row.audio_bytes, row.seconds, row.milliseconds,
+             row.mb_per_second(), row.realtime());
+  }
+}
+
+fn json_escape(value: &str) -> String {
+  let mut escaped = String::with_capacity(value.len());
+
+  for c in value.chars() {
+    match c {
+      '"'                 => escaped.push_str("\\\""),
+      '\\'                => escaped.push_str("\\\\"),
+      _ if c.is_control() => {
+        escaped.push_str(&format!("\\u{:04x}", c as u32));
+      }
+      _                   => escaped.push(c),
+    }
+  }
+
+  escaped
+}
+
+// Quotes a field holding a comma, quote, or line break.
+fn csv_escape(value: &str) -> String {
+  if value.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+    format!("\"{}\"", value.replace('"', "\"\""))
+  } else {
+    value.to_owned()
+  }
+}
+
+// Runs `f` the given number of times, returning the fastest run. The result
+// of `f` is printed to standard error when it differs between runs, which
+// also keeps the work from being optimized away.
+fn time<F: FnMut() -> i64>(runs: usize, mut f: F) -> Duration {
+  let mut fastest = None;
+  let mut result  = None;
+
+  for _ in 0..runs {
+    let start   = Instant::now();
+    let sum     = f();
+    let elapsed = start.elapsed();
+
+    if result.map_or(false, |result| result != sum) {
+      let _ = writeln!(io::stderr(), "checksum changed from {:?} to {}",
+                       result, sum);
+    }
+
+    result  = Some(sum);
+    fastest = Some(fastest.map_or(elapsed, |fastest: Duration| {
+      if elapsed < fastest { elapsed } else { fastest }
+    }));
+  }
+
+  fastest.unwrap_or_default()
+}
+
+fn milliseconds(duration: Duration) -> f64 {
+  duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1.0e6
+}
@@ -2,7 +2,7 @@ use std::io::{self, Write};
 use std::fs::File;
 
 use flac::StreamReader;
-use flac::metadata::{self, VorbisComment};
+use flac::metadata::VorbisComment;
 
 pub const USAGE: &'static str = "
 Usage: metadata comments [options] <filename>
@@ -66,17 +66,12 @@ pub fn run(args: &Arguments) {
   let stream = StreamReader::<File>::from_file(&args.arg_filename)
                  .expect("Couldn't parse file");
 
-  for meta in stream.metadata() {
-    match meta.data {
-      metadata::Data::VorbisComment(ref v) => {
-        if let Some(ref filename) = args.flag_export {
-          export_vorbis_comments(v, filename)
-            .expect("couldn't write to file")
-        } else {
-          print_vorbis_comments(v, &args)
-        }
-      }
-      _                                    => continue,
+  for v in stream.metadata().iter().filter_map(|m| m.as_vorbis_comment()) {
+    if let Some(ref filename) = args.flag_export {
+      export_vorbis_comments(v, filename)
+        .expect("couldn't write to file")
+    } else {
+      print_vorbis_comments(v, &args)
     }
   }
 }
@@ -1,7 +1,7 @@
 use std::fs::File;
 
 use flac::StreamReader;
-use flac::metadata::{self, SeekPoint};
+use flac::metadata::SeekPoint;
 
 pub const USAGE: &'static str = "
 Usage: metadata seektable <filename>
@@ -35,9 +35,8 @@ pub fn run(args: &Arguments) {
                  .expect("Couldn't parse file");
 
   for meta in stream.metadata() {
-    match meta.data {
-      metadata::Data::SeekTable(ref s) => print_seek_table(s),
-      _                                => continue,
+    if let Some(seek_table) = meta.as_seek_table() {
+      print_seek_table(seek_table);
     }
   }
 }
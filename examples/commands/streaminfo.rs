@@ -53,7 +53,8 @@ fn print_stream_info<P>(stream: &Stream<P>, args: &Arguments)
   }
 
   if no_flags || args.flag_sample_rate {
-    format_print!("{}{} Hz", "Sample rate: ", info.sample_rate, no_flags);
+    format_print!("{}{} Hz", "Sample rate: ", info.sample_rate.get(),
+                                              no_flags);
   }
 
   if no_flags || args.flag_channels {
@@ -61,7 +62,7 @@ fn print_stream_info<P>(stream: &Stream<P>, args: &Arguments)
   }
 
   if no_flags || args.flag_bits_per_sample {
-    format_print!("{}{}", "Bits per samples: ", info.bits_per_sample,
+    format_print!("{}{}", "Bits per samples: ", info.bits_per_sample.get(),
                                                 no_flags);
   }
 
@@ -43,7 +43,7 @@ pub fn list_block_names(filename: &str) {
       metadata::Data::VorbisComment(_) => "vorbis comment",
       metadata::Data::CueSheet(_)      => "cuesheet",
       metadata::Data::Picture(_)       => "picture",
-      metadata::Data::Unknown(_)       => "unknown",
+      metadata::Data::Unknown(..)      => "unknown",
     });
   }
 }
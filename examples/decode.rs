@@ -48,17 +48,17 @@ fn decode_file(input_file: &str, output_file: &str)
   let info = stream.info();
   let spec = hound::WavSpec {
     channels: info.channels as u16,
-    sample_rate: info.sample_rate,
-    bits_per_sample: info.bits_per_sample as u16,
+    sample_rate: info.sample_rate.get(),
+    bits_per_sample: info.bits_per_sample.get() as u16,
   };
 
   let mut output = try!(hound::WavWriter::create(output_file, spec));
 
-  if info.bits_per_sample <= 8 {
+  if info.bits_per_sample.get() <= 8 {
     for sample in stream.iter::<i8>() {
       try!(output.write_sample(sample));
     }
-  } else if info.bits_per_sample <= 16 {
+  } else if info.bits_per_sample.get() <= 16 {
     for sample in stream.iter::<i16>() {
       try!(output.write_sample(sample));
     }
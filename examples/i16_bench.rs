@@ -0,0 +1,93 @@
+extern crate flac;
+
+use flac::StreamBuffer;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+// Times decoding a FLAC file to interleaved 16-bit samples, once through
+// `Stream::iter` and once through `Stream::next_frame_into_i16`, with the
+// whole file read into memory up front so only decoding gets measured.
+// Build with `--release` for numbers worth comparing.
+fn main() {
+  let (filename, runs) = match (env::args().nth(1), env::args().nth(2)) {
+    (Some(filename), runs) => {
+      (filename, runs.and_then(|runs| runs.parse().ok()).unwrap_or(10))
+    }
+    (None, _)              => {
+      let _ = writeln!(io::stderr(), "Usage: i16_bench <input> [runs]");
+
+      return;
+    }
+  };
+
+  let mut bytes = Vec::new();
+
+  if let Err(error) = File::open(&filename)
+                        .and_then(|mut file| file.read_to_end(&mut bytes)) {
+    let _ = writeln!(io::stderr(), "{}: {}", filename, error);
+
+    return;
+  }
+
+  let iter = time(runs, || {
+    let mut stream = StreamBuffer::from_buffer(&bytes).unwrap();
+
+    stream.iter::<i16>().fold(0, |sum: i64, sample| sum + sample as i64)
+  });
+
+  let into = time(runs, || {
+    let mut stream = StreamBuffer::from_buffer(&bytes).unwrap();
+    let info       = stream.info();
+    let channels   = info.channels as usize;
+    let mut output = vec![0; info.max_block_size as usize * channels];
+    let mut sum    = 0;
+
+    while let Ok(block_size) = stream.next_frame_into_i16(&mut output) {
+      if block_size == 0 {
+        break;
+      }
+
+      sum += output[0..(block_size * channels)].iter()
+                                               .fold(0, |sum: i64, &sample| {
+        sum + sample as i64
+      });
+    }
+
+    sum
+  });
+
+  println!("iter:                {:>10.3} ms", milliseconds(iter));
+  println!("next_frame_into_i16: {:>10.3} ms", milliseconds(into));
+}
+
+// Runs `f` the given number of times, returning the fastest run. The result
+// of `f` is printed when it differs between runs, which also keeps the work
+// from being optimized away.
+fn time<F: FnMut() -> i64>(runs: usize, mut f: F) -> Duration {
+  let mut fastest = None;
+  let mut result  = None;
+
+  for _ in 0..runs {
+    let start   = Instant::now();
+    let sum     = f();
+    let elapsed = start.elapsed();
+
+    if result.map_or(false, |result| result != sum) {
+      println!("checksum changed from {:?} to {}", result, sum);
+    }
+
+    result  = Some(sum);
+    fastest = Some(fastest.map_or(elapsed, |fastest: Duration| {
+      if elapsed < fastest { elapsed } else { fastest }
+    }));
+  }
+
+  fastest.unwrap_or_default()
+}
+
+fn milliseconds(duration: Duration) -> f64 {
+  duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1.0e6
+}
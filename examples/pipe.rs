@@ -0,0 +1,51 @@
+extern crate flac;
+
+use flac::StreamReader;
+use flac::pcm::{Endian, PcmFormat, PcmReader};
+
+use std::env;
+use std::io::{self, Write};
+
+// Decodes FLAC from standard input into raw, interleaved samples on
+// standard output, which are signed and little endian unless
+// "--endian=big" or "--sign=unsigned" are passed in. Only the standard
+// streams get used, so this also runs within sandboxes without a file
+// system, like WASI.
+fn main() {
+  let mut format = PcmFormat::default();
+
+  for argument in env::args().skip(1) {
+    match argument.as_str() {
+      "--endian=little" => format.endian = Endian::Little,
+      "--endian=big"    => format.endian = Endian::Big,
+      "--sign=signed"   => format.is_signed = true,
+      "--sign=unsigned" => format.is_signed = false,
+      _                 => {
+        let _ = writeln!(io::stderr(), "unknown option: {}", argument);
+
+        return;
+      }
+    }
+  }
+
+  let stdin  = io::stdin();
+  let stdout = io::stdout();
+
+  let mut stream = match StreamReader::<io::StdinLock>::new(stdin.lock()) {
+    Ok(stream) => stream,
+    Err(error) => {
+      let _ = writeln!(io::stderr(), "{:?}", error);
+
+      return;
+    }
+  };
+
+  let mut reader = PcmReader::new(&mut stream, format);
+  let mut output = io::BufWriter::new(stdout.lock());
+
+  if let Err(error) = io::copy(&mut reader, &mut output) {
+    let _ = writeln!(io::stderr(), "{}", error);
+  }
+
+  let _ = output.flush();
+}
@@ -0,0 +1,26 @@
+extern crate flac;
+
+use std::env;
+
+fn main() {
+  let filename = env::args().nth(1).expect("Usage: simple <input>");
+
+  match flac::read(&filename) {
+    Ok((info, samples)) => {
+      println!("Channels: {}", info.channels);
+      println!("Sample rate: {}Hz", info.sample_rate.get());
+      println!("Bits per sample: {}", info.bits_per_sample.get());
+      println!("Decoded samples: {}", samples.len());
+    }
+    Err(error)          => println!("{:?}", error),
+  }
+
+  match flac::tags(&filename) {
+    Ok(tags)   => {
+      for (name, value) in &tags {
+        println!("{}: {}", name, value);
+      }
+    }
+    Err(error) => println!("{:?}", error),
+  }
+}
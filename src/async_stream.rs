@@ -0,0 +1,92 @@
+//! Decoding a FLAC stream as a `futures::Stream` of blocks.
+//!
+//! Only available with the `async` feature enabled. Frames get decoded on
+//! a separate thread, which stays at most a set number of blocks ahead of
+//! what has been taken from the stream, so a slow consumer holds back the
+//! decoding instead of letting decoded samples pile up.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! extern crate flac;
+//! extern crate futures;
+//!
+//! use flac::StreamReader;
+//! use futures::Stream;
+//!
+//! use std::fs::File;
+//!
+//! # fn main() {
+//! let stream = StreamReader::<File>::from_file("path/to/file.flac")
+//!                .unwrap();
+//!
+//! // Decode no more than four blocks ahead of what has been taken.
+//! for block in flac::async_stream::blocks::<i16, _>(stream, 4).wait() {
+//!   let block = block.unwrap();
+//!
+//!   // Samples are interleaved by channel within `block.samples`.
+//! }
+//! # }
+//! ```
+
+use futures::{Async, Poll};
+
+use queue::{self, Queue};
+use stream::{Block, Stream};
+use utility::{ErrorKind, Sample, SampleSize, StreamProducer};
+
+use std::sync::Arc;
+
+/// A `futures::Stream` over the decoded blocks of a FLAC stream.
+///
+/// Decoding stops once the stream is dropped. The error of a frame that
+/// fails to decode is the last item of the stream.
+pub struct AsyncBlocks<S> {
+  queue: Arc<Queue<S>>,
+}
+
+/// Starts decoding the stream on a separate thread, holding no more than
+/// `limit` decoded blocks that haven't been taken yet.
+///
+/// A limit of zero is treated as one, since the next block needs to be
+/// decoded before it can be taken.
+pub fn blocks<S, P>(stream: Stream<P>, limit: usize)
+                    -> AsyncBlocks<<S::Extended as Sample>::Normal>
+ where S: SampleSize + 'static,
+       P: StreamProducer + Send + 'static,
+       <S::Extended as Sample>::Normal: Send + 'static {
+  AsyncBlocks {
+    queue: queue::spawn::<S, P>(stream, limit),
+  }
+}
+
+impl<S> AsyncBlocks<S> {
+  /// Returns the number of decoded blocks waiting to be taken.
+  pub fn buffered_len(&self) -> usize {
+    self.queue.len()
+  }
+
+  /// Returns the largest number of decoded blocks held at once.
+  pub fn limit(&self) -> usize {
+    self.queue.limit()
+  }
+}
+
+impl<S> ::futures::Stream for AsyncBlocks<S> {
+  type Item  = Block<S>;
+  type Error = ErrorKind;
+
+  fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+    match self.queue.poll() {
+      Async::Ready(Some(block)) => block.map(|block| Async::Ready(Some(block))),
+      Async::Ready(None)        => Ok(Async::Ready(None)),
+      Async::NotReady           => Ok(Async::NotReady),
+    }
+  }
+}
+
+impl<S> Drop for AsyncBlocks<S> {
+  fn drop(&mut self) {
+    self.queue.close();
+  }
+}
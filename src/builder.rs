@@ -0,0 +1,637 @@
+//! Frames put together by hand, for generating test streams.
+//!
+//! `Encoder` picks how each channel gets stored on its own, which leaves no
+//! way of getting a specific kind of subframe into a stream. `FrameBuilder`
+//! takes the channels of a single frame along with the subframe each one
+//! gets stored as, and writes the frame with both of its CRCs filled in.
+//! Everything gets checked against the format before anything is written,
+//! so the frames that come out are always valid.
+//!
+//! # Examples
+//!
+//! ```
+//! use flac::builder::{ChannelAssignment, FrameBuilder, SubframeKind};
+//!
+//! let left  = [10, 20, 30, 40, 50, 60, 70, 80];
+//! let right = [10, 19, 31, 40, 49, 61, 70, 79];
+//!
+//! let bytes = FrameBuilder::new(44100, 16)
+//!               .with_frame_number(0)
+//!               .with_channel_assignment(ChannelAssignment::LeftSide)
+//!               .with_channel(&left, SubframeKind::Fixed(2))
+//!               .with_channel(&right, SubframeKind::Verbatim)
+//!               .encode()
+//!               .unwrap();
+//!
+//! assert_eq!(&bytes[0..2], &[0xff, 0xf8]);
+//! ```
+
+use frame::{self, NumberType, Header, MAX_CHANNELS};
+use subframe::{self, MAX_FIXED_ORDER, MAX_LPC_ORDER};
+use utility::{BitsPerSample, Sample, SampleRate};
+
+pub use frame::ChannelAssignment;
+
+use std::cmp;
+use std::error::Error;
+use std::fmt;
+
+// Largest block size the frame header is able to hold.
+const MAX_BLOCK_SIZE: usize = 65536;
+
+// Largest precision, in bits, of the LPC coefficients.
+const MAX_LPC_PRECISION: u32 = 15;
+
+// Largest shift, in bits, of the LPC prediction.
+const MAX_LPC_SHIFT: u32 = 15;
+
+/// How a channel gets stored within the frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SubframeKind {
+  /// A single value, which every sample of the channel needs to match.
+  Constant,
+  /// Every sample stored as is.
+  Verbatim,
+  /// Fixed linear prediction of the given order, from zero to four.
+  Fixed(usize),
+  /// FIR linear prediction, with the order being the number of
+  /// coefficients, from one to 32.
+  LPC {
+    /// Quantized coefficients, with the first one applying to the sample
+    /// right before the one being predicted.
+    coefficients: Vec<i32>,
+    /// Bits each coefficient is stored with, from one to 15.
+    precision: u32,
+    /// Bits the prediction gets shifted right by, from zero to 15.
+    shift: u32,
+  },
+}
+
+/// Reason a frame given to `FrameBuilder` can't be written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameError {
+  /// The number of channels isn't between one and eight.
+  ChannelCount {
+    /// Number of channels given.
+    channels: usize,
+  },
+  /// A channel assignment other than independent was given without
+  /// exactly two channels, or with 32 bits per sample, where the side
+  /// channel doesn't fit.
+  InvalidAssignment,
+  /// The bits per sample isn't between four and 32.
+  BitsPerSample {
+    /// Bits per sample given.
+    bits_per_sample: usize,
+  },
+  /// The sample rate isn't between 1Hz and 655350Hz.
+  SampleRate {
+    /// Sample rate given.
+    sample_rate: u32,
+  },
+  /// The block size isn't between one and 65536 samples.
+  BlockSize {
+    /// Number of samples, per channel, given.
+    block_size: usize,
+  },
+  /// A channel holds a different number of samples than the first one.
+  BlockSizeMismatch {
+    /// Channel with the wrong number of samples.
+    channel: usize,
+    /// Number of samples the channel holds.
+    block_size: usize,
+    /// Number of samples the first channel holds.
+    expected: usize,
+  },
+  /// A sample doesn't fit within the bits per sample.
+  SampleOutOfRange {
+    /// Channel of the sample.
+    channel: usize,
+    /// Index of the sample within the channel.
+    index: usize,
+    /// Value of the sample.
+    sample: i32,
+  },
+  /// The frame number is 2^31 or above, or the sample number 2^36 or
+  /// above.
+  NumberOutOfRange,
+  /// A constant subframe was picked for a channel with different samples.
+  NotConstant {
+    /// Channel the subframe was picked for.
+    channel: usize,
+  },
+  /// The order of the prediction is outside of what the format allows, or
+  /// above the block size.
+  InvalidOrder {
+    /// Channel the subframe was picked for.
+    channel: usize,
+    /// Order of the prediction.
+    order: usize,
+  },
+  /// The precision or shift of the LPC coefficients is outside of what the
+  /// format allows, or a coefficient doesn't fit within the precision.
+  InvalidCoefficients {
+    /// Channel the subframe was picked for.
+    channel: usize,
+  },
+  /// A residual value of the prediction doesn't fit within 32 bits.
+  ResidualOutOfRange {
+    /// Channel the subframe was picked for.
+    channel: usize,
+  },
+}
+
+impl fmt::Display for FrameError {
+  fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      FrameError::ChannelCount { channels } => {
+        write!(formatter, "{} channels isn't between 1 and {}", channels,
+               MAX_CHANNELS)
+      }
+      FrameError::InvalidAssignment => {
+        write!(formatter, "channel assignment doesn't fit the channels")
+      }
+      FrameError::BitsPerSample { bits_per_sample } => {
+        write!(formatter, "{} bits per sample isn't between 4 and 32",
+               bits_per_sample)
+      }
+      FrameError::SampleRate { sample_rate } => {
+        write!(formatter, "sample rate of {}Hz isn't between 1 and 655350",
+               sample_rate)
+      }
+      FrameError::BlockSize { block_size } => {
+        write!(formatter, "block size of {} isn't between 1 and {}",
+               block_size, MAX_BLOCK_SIZE)
+      }
+      FrameError::BlockSizeMismatch { channel, block_size, expected } => {
+        write!(formatter, "channel {} holds {} samples rather than {}",
+               channel, block_size, expected)
+      }
+      FrameError::SampleOutOfRange { channel, index, sample } => {
+        write!(formatter, "sample {} at index {} of channel {} is out of \
+                           range", sample, index, channel)
+      }
+      FrameError::NumberOutOfRange => {
+        write!(formatter, "frame or sample number is out of range")
+      }
+      FrameError::NotConstant { channel } => {
+        write!(formatter, "channel {} isn't constant", channel)
+      }
+      FrameError::InvalidOrder { channel, order } => {
+        write!(formatter, "order {} is invalid for channel {}", order,
+               channel)
+      }
+      FrameError::InvalidCoefficients { channel } => {
+        write!(formatter, "coefficients are invalid for channel {}",
+               channel)
+      }
+      FrameError::ResidualOutOfRange { channel } => {
+        write!(formatter, "residual of channel {} is out of range", channel)
+      }
+    }
+  }
+}
+
+impl Error for FrameError {
+  fn description(&self) -> &str {
+    match *self {
+      FrameError::ChannelCount { .. }        => "invalid channel count",
+      FrameError::InvalidAssignment          => "invalid channel assignment",
+      FrameError::BitsPerSample { .. }       => "invalid bits per sample",
+      FrameError::SampleRate { .. }          => "invalid sample rate",
+      FrameError::BlockSize { .. }           => "invalid block size",
+      FrameError::BlockSizeMismatch { .. }   => "block size mismatch",
+      FrameError::SampleOutOfRange { .. }    => "sample out of range",
+      FrameError::NumberOutOfRange           => "number out of range",
+      FrameError::NotConstant { .. }         => "channel isn't constant",
+      FrameError::InvalidOrder { .. }        => "invalid order",
+      FrameError::InvalidCoefficients { .. } => "invalid coefficients",
+      FrameError::ResidualOutOfRange { .. }  => "residual out of range",
+    }
+  }
+}
+
+// A channel, as stored, along with what gets written for it.
+enum Plan {
+  Constant(i64),
+  Verbatim,
+  Fixed(usize, Vec<i64>),
+  LPC(Vec<i32>, u32, u32, Vec<i64>),
+}
+
+/// Builder for a single frame, with the channels and how each one gets
+/// stored picked by hand.
+///
+/// The frame is numbered by frame, starting at zero, and has independent
+/// channels unless told otherwise. Sample rates and bits per sample that
+/// the frame header has no way of holding get left for `StreamInfo`,
+/// which the frame needs to be parsed with.
+#[derive(Clone, Debug)]
+pub struct FrameBuilder {
+  sample_rate: u32,
+  bits_per_sample: usize,
+  number: NumberType,
+  channel_assignment: ChannelAssignment,
+  channels: Vec<(Vec<i32>, SubframeKind)>,
+}
+
+impl FrameBuilder {
+  /// Constructs a builder for a frame without any channels.
+  pub fn new(sample_rate: u32, bits_per_sample: usize) -> Self {
+    FrameBuilder {
+      sample_rate: sample_rate,
+      bits_per_sample: bits_per_sample,
+      number: NumberType::Frame(0),
+      channel_assignment: ChannelAssignment::Independent,
+      channels: Vec::new(),
+    }
+  }
+
+  /// Numbers the frame by frame, for streams with a fixed block size.
+  pub fn with_frame_number(mut self, number: u32) -> Self {
+    self.number = NumberType::Frame(number);
+
+    self
+  }
+
+  /// Numbers the frame by its first sample, for streams with a variable
+  /// block size.
+  pub fn with_sample_number(mut self, number: u64) -> Self {
+    self.number = NumberType::Sample(number);
+
+    self
+  }
+
+  /// Sets how the two channels of a stereo frame get stored.
+  ///
+  /// Channels are still given as left and right, with the side and
+  /// midpoint channels worked out from them. The kind of subframe applies
+  /// to the channel as it's stored, so for `LeftSide` the second channel is
+  /// the side channel.
+  pub fn with_channel_assignment(mut self,
+                                 channel_assignment: ChannelAssignment)
+                                 -> Self {
+    self.channel_assignment = channel_assignment;
+
+    self
+  }
+
+  /// Adds the next channel, stored as the given kind of subframe.
+  pub fn with_channel(mut self, samples: &[i32], kind: SubframeKind)
+                      -> Self {
+    self.channels.push((samples.to_owned(), kind));
+
+    self
+  }
+
+  /// Checks the frame against the format and writes it, returning its
+  /// bytes.
+  ///
+  /// # Failures
+  ///
+  /// * `FrameError` for the first part of the frame the format doesn't
+  ///   allow. Nothing gets written when this happens.
+  pub fn encode(&self) -> Result<Vec<u8>, FrameError> {
+    let header = try!(self.header());
+    let buffer = try!(self.buffer(&header));
+
+    let block_size = header.block_size as usize;
+
+    let mut plans = Vec::with_capacity(self.channels.len());
+
+    for (channel, &(_, ref kind)) in self.channels.iter().enumerate() {
+      let start   = channel * block_size;
+      let samples = &buffer[start..(start + block_size)];
+
+      plans.push(try!(plan(channel, kind, samples)));
+    }
+
+    let mut bytes = Vec::new();
+
+    frame::encode_with(&header, &buffer, &mut bytes, |channel, samples,
+                                                      bits, writer| {
+      match plans[channel] {
+        Plan::Constant(sample)                                      => {
+          subframe::write_constant(sample, bits, writer)
+        }
+        Plan::Verbatim                                              => {
+          subframe::write_verbatim(samples, bits, writer)
+        }
+        Plan::Fixed(order, ref residual)                            => {
+          subframe::write_fixed(order, samples, residual, bits, writer)
+        }
+        Plan::LPC(ref coefficients, precision, shift, ref residual) => {
+          subframe::write_lpc(coefficients, precision, shift, samples,
+                              residual, bits, writer)
+        }
+      }
+    });
+
+    Ok(bytes)
+  }
+
+  // Checks everything the frame header holds.
+  fn header(&self) -> Result<Header, FrameError> {
+    let channels   = self.channels.len();
+    let block_size = self.channels.first()
+                         .map_or(0, |&(ref samples, _)| samples.len());
+
+    if channels == 0 || channels > MAX_CHANNELS {
+      return Err(FrameError::ChannelCount { channels: channels });
+    }
+
+    // Clamped first, so nothing past the range of `u8` wraps around into
+    // a valid bits per sample.
+    let bits_per_sample = cmp::min(self.bits_per_sample, 0xff) as u8;
+    let bits_per_sample = match BitsPerSample::new(bits_per_sample) {
+      Some(bits_per_sample) => bits_per_sample,
+      None                  => {
+        return Err(FrameError::BitsPerSample {
+          bits_per_sample: self.bits_per_sample,
+        });
+      }
+    };
+
+    let sample_rate = match SampleRate::new(self.sample_rate) {
+      Some(sample_rate) => sample_rate,
+      None              => {
+        return Err(FrameError::SampleRate { sample_rate: self.sample_rate });
+      }
+    };
+
+    if self.channel_assignment != ChannelAssignment::Independent &&
+       (channels != 2 || bits_per_sample.get() == 32) {
+      return Err(FrameError::InvalidAssignment);
+    }
+
+    if block_size == 0 || block_size > MAX_BLOCK_SIZE {
+      return Err(FrameError::BlockSize { block_size: block_size });
+    }
+
+    let is_valid_number = match self.number {
+      NumberType::Frame(number)  => number < (1 << 31),
+      NumberType::Sample(number) => number < (1 << 36),
+    };
+
+    if !is_valid_number {
+      return Err(FrameError::NumberOutOfRange);
+    }
+
+    Ok(Header {
+      block_size: block_size as u32,
+      sample_rate: sample_rate,
+      channels: channels as u8,
+      channel_assignment: self.channel_assignment,
+      bits_per_sample: bits_per_sample,
+      number: self.number,
+      crc: 0,
+    })
+  }
+
+  // Checks every sample, returning the channels as they get stored one
+  // after the other.
+  fn buffer(&self, header: &Header) -> Result<Vec<i64>, FrameError> {
+    let block_size = header.block_size as usize;
+    let min        = i32::min_for_bps(self.bits_per_sample);
+    let max        = i32::max_for_bps(self.bits_per_sample);
+
+    let mut buffer = Vec::with_capacity(block_size * self.channels.len());
+
+    for (channel, &(ref samples, _)) in self.channels.iter().enumerate() {
+      if samples.len() != block_size {
+        return Err(FrameError::BlockSizeMismatch {
+          channel: channel,
+          block_size: samples.len(),
+          expected: block_size,
+        });
+      }
+
+      for (index, &sample) in samples.iter().enumerate() {
+        if sample < min || sample > max {
+          return Err(FrameError::SampleOutOfRange {
+            channel: channel,
+            index: index,
+            sample: sample,
+          });
+        }
+
+        buffer.push(sample as i64);
+      }
+    }
+
+    if header.channels == 2 {
+      buffer = frame::decorrelate(header.channel_assignment, &buffer);
+    }
+
+    Ok(buffer)
+  }
+}
+
+// Checks the kind of subframe picked for a channel against its samples,
+// working out the residual for the predictions.
+fn plan(channel: usize, kind: &SubframeKind, samples: &[i64])
+        -> Result<Plan, FrameError> {
+  let invalid_order = |order| {
+    FrameError::InvalidOrder { channel: channel, order: order }
+  };
+  let out_of_range  = FrameError::ResidualOutOfRange { channel: channel };
+
+  match *kind {
+    SubframeKind::Constant                                     => {
+      let first = samples[0];
+
+      if samples.iter().all(|&sample| sample == first) {
+        Ok(Plan::Constant(first))
+      } else {
+        Err(FrameError::NotConstant { channel: channel })
+      }
+    }
+    SubframeKind::Verbatim                                     => {
+      Ok(Plan::Verbatim)
+    }
+    SubframeKind::Fixed(order)                                 => {
+      if order > MAX_FIXED_ORDER || order > samples.len() {
+        return Err(invalid_order(order));
+      }
+
+      subframe::fixed_residual(order, samples)
+        .map(|residual| Plan::Fixed(order, residual))
+        .ok_or(out_of_range)
+    }
+    SubframeKind::LPC { ref coefficients, precision, shift } => {
+      let order = coefficients.len();
+
+      if order == 0 || order > MAX_LPC_ORDER || order > samples.len() {
+        return Err(invalid_order(order));
+      }
+
+      let min = i32::min_for_bps(precision as usize);
+      let max = i32::max_for_bps(precision as usize);
+
+      let is_valid = precision >= 1 && precision <= MAX_LPC_PRECISION &&
+                     shift <= MAX_LPC_SHIFT &&
+                     coefficients.iter().all(|&coefficient| {
+                       coefficient >= min && coefficient <= max
+                     });
+
+      if !is_valid {
+        return Err(FrameError::InvalidCoefficients { channel: channel });
+      }
+
+      subframe::lpc_residual(coefficients, shift, samples)
+        .map(|residual| {
+          Plan::LPC(coefficients.clone(), precision, shift, residual)
+        })
+        .ok_or(out_of_range)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use frame::{frame_parser, decode};
+  use metadata::StreamInfo;
+
+  use nom::IResult;
+
+  // Parses and decodes a stereo frame of eight samples per channel,
+  // returning each channel one after the other.
+  fn decode_frame(bytes: &[u8], bits_per_sample: u8) -> Vec<i32> {
+    let stream_info = StreamInfo {
+      sample_rate: SampleRate::new(44100).unwrap(),
+      channels: 2,
+      bits_per_sample: BitsPerSample::new(bits_per_sample).unwrap(),
+      ..Default::default()
+    };
+
+    let mut output = vec![0; 16];
+
+    let frame = match frame_parser(bytes, &stream_info, &mut output) {
+      IResult::Done(rest, frame) => {
+        assert!(rest.is_empty());
+
+        frame
+      }
+      _                          => panic!("built frame fails to parse"),
+    };
+
+    for channel in 0..2 {
+      let start = channel * 8;
+      let end   = start + 8;
+
+      subframe::decode(&frame.subframes[channel], 8,
+                       &mut output[start..end]);
+    }
+
+    decode(frame.header.channel_assignment, &mut output);
+
+    output
+  }
+
+  #[test]
+  fn test_frame_builder() {
+    let left  = [100, 120, 90, 60, 80, 140, 130, 100];
+    let right = [-4, -4, -4, -4, -4, -4, -4, -4];
+    let lpc   = SubframeKind::LPC {
+      coefficients: vec![3, -1],
+      precision: 4,
+      shift: 1,
+    };
+
+    let kinds = [
+      (SubframeKind::Verbatim, SubframeKind::Constant),
+      (SubframeKind::Fixed(0), SubframeKind::Fixed(4)),
+      (SubframeKind::Fixed(2), SubframeKind::Verbatim),
+      (lpc.clone(), SubframeKind::Constant),
+    ];
+
+    for &(ref first, ref second) in &kinds {
+      let bytes = FrameBuilder::new(44100, 17)
+                    .with_sample_number(1 << 35)
+                    .with_channel(&left, first.clone())
+                    .with_channel(&right, second.clone())
+                    .encode()
+                    .unwrap();
+
+      let mut expected = left.to_vec();
+
+      expected.extend_from_slice(&right);
+
+      assert_eq!(decode_frame(&bytes, 17), expected);
+    }
+
+    let bytes = FrameBuilder::new(44100, 16)
+                  .with_channel_assignment(ChannelAssignment::MidpointSide)
+                  .with_channel(&left, lpc.clone())
+                  .with_channel(&right, SubframeKind::Fixed(1))
+                  .encode()
+                  .unwrap();
+
+    assert_eq!(&decode_frame(&bytes, 16)[0..8], &left[..]);
+  }
+
+  #[test]
+  fn test_frame_builder_errors() {
+    let samples = [1, 2, 3, 4];
+    let build   = |builder: FrameBuilder| builder.encode().unwrap_err();
+    let mono    = |kind| {
+      build(FrameBuilder::new(44100, 16).with_channel(&samples, kind))
+    };
+
+    assert_eq!(build(FrameBuilder::new(44100, 16)),
+               FrameError::ChannelCount { channels: 0 });
+    assert_eq!(build(FrameBuilder::new(44100, 33)
+                       .with_channel(&samples, SubframeKind::Verbatim)),
+               FrameError::BitsPerSample { bits_per_sample: 33 });
+    assert_eq!(build(FrameBuilder::new(44100, 272)
+                       .with_channel(&samples, SubframeKind::Verbatim)),
+               FrameError::BitsPerSample { bits_per_sample: 272 });
+    assert_eq!(build(FrameBuilder::new(0, 16)
+                       .with_channel(&samples, SubframeKind::Verbatim)),
+               FrameError::SampleRate { sample_rate: 0 });
+    assert_eq!(build(FrameBuilder::new(44100, 16)
+                       .with_channel_assignment(ChannelAssignment::LeftSide)
+                       .with_channel(&samples, SubframeKind::Verbatim)),
+               FrameError::InvalidAssignment);
+    assert_eq!(build(FrameBuilder::new(44100, 16)
+                       .with_channel(&samples, SubframeKind::Verbatim)
+                       .with_channel(&samples[1..], SubframeKind::Verbatim)),
+               FrameError::BlockSizeMismatch {
+                 channel: 1,
+                 block_size: 3,
+                 expected: 4,
+               });
+    assert_eq!(build(FrameBuilder::new(44100, 4)
+                       .with_channel(&[7, 8], SubframeKind::Verbatim)),
+               FrameError::SampleOutOfRange {
+                 channel: 0,
+                 index: 1,
+                 sample: 8,
+               });
+    assert_eq!(build(FrameBuilder::new(44100, 16)
+                       .with_frame_number(1 << 31)
+                       .with_channel(&samples, SubframeKind::Verbatim)),
+               FrameError::NumberOutOfRange);
+
+    assert_eq!(mono(SubframeKind::Constant),
+               FrameError::NotConstant { channel: 0 });
+    assert_eq!(mono(SubframeKind::Fixed(5)),
+               FrameError::InvalidOrder { channel: 0, order: 5 });
+    assert_eq!(mono(SubframeKind::LPC {
+                 coefficients: vec![1; 5],
+                 precision: 4,
+                 shift: 0,
+               }), FrameError::InvalidOrder { channel: 0, order: 5 });
+    assert_eq!(mono(SubframeKind::LPC {
+                 coefficients: vec![8],
+                 precision: 4,
+                 shift: 0,
+               }), FrameError::InvalidCoefficients { channel: 0 });
+    assert_eq!(mono(SubframeKind::LPC {
+                 coefficients: vec![1],
+                 precision: 4,
+                 shift: 16,
+               }), FrameError::InvalidCoefficients { channel: 0 });
+  }
+}
@@ -0,0 +1,221 @@
+//! Reading numbers and byte strings out of binary payloads.
+//!
+//! Blocks like `Application` and `Unknown` hand back their payload as plain
+//! bytes, which often hold structures of their own, like the chunks of a
+//! WAVE or AIFF file kept around as foreign metadata. `Cursor` reads
+//! through such a payload without pulling in a parser framework, covering
+//! the same big and little endian numbers the metadata writer uses. Reading
+//! past the end fails with `io::ErrorKind::UnexpectedEof` and leaves the
+//! cursor where it was, so a payload that's cut short never panics.
+//!
+//! # Examples
+//!
+//! ```
+//! use flac::bytes::Cursor;
+//!
+//! // A "LIST" chunk of a WAVE file, with its length stored little endian.
+//! let payload = b"LIST\x08\x00\x00\x00INFOabcd";
+//!
+//! let mut cursor = Cursor::new(payload);
+//!
+//! assert_eq!(cursor.read_bytes(4).unwrap(), b"LIST");
+//!
+//! let length = cursor.read_le_u32().unwrap() as usize;
+//! let data   = cursor.read_bytes(length).unwrap();
+//!
+//! assert_eq!(data, b"INFOabcd");
+//! assert!(cursor.is_empty());
+//! assert!(cursor.read_u8().is_err());
+//! ```
+
+use std::io;
+
+/// Position within a slice of bytes, reading numbers from the front of
+/// what's left.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cursor<'a> {
+  bytes: &'a [u8],
+  position: usize,
+}
+
+impl<'a> Cursor<'a> {
+  /// Constructs a cursor at the beginning of `bytes`.
+  pub fn new(bytes: &'a [u8]) -> Self {
+    Cursor {
+      bytes: bytes,
+      position: 0,
+    }
+  }
+
+  /// Returns the number of bytes read so far.
+  #[inline]
+  pub fn position(&self) -> usize {
+    self.position
+  }
+
+  /// Returns the bytes that haven't been read yet.
+  #[inline]
+  pub fn remaining(&self) -> &'a [u8] {
+    &self.bytes[self.position..]
+  }
+
+  /// Returns the number of bytes that haven't been read yet.
+  #[inline]
+  pub fn len(&self) -> usize {
+    self.bytes.len() - self.position
+  }
+
+  /// Returns whether every byte has been read.
+  #[inline]
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// Reads the next `length` bytes as they are.
+  ///
+  /// Fails with `io::ErrorKind::UnexpectedEof` when fewer bytes than that
+  /// are left.
+  pub fn read_bytes(&mut self, length: usize) -> io::Result<&'a [u8]> {
+    if length > self.len() {
+      return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                "payload ended early"));
+    }
+
+    let bytes = &self.bytes[self.position..(self.position + length)];
+
+    self.position += length;
+
+    Ok(bytes)
+  }
+
+  /// Moves past the next `length` bytes without reading them.
+  ///
+  /// Fails in the same way as `Cursor::read_bytes`.
+  #[inline]
+  pub fn skip(&mut self, length: usize) -> io::Result<()> {
+    self.read_bytes(length).map(|_| ())
+  }
+
+  /// Reads a single byte.
+  #[inline]
+  pub fn read_u8(&mut self) -> io::Result<u8> {
+    self.read_bytes(1).map(|bytes| bytes[0])
+  }
+
+  /// Reads a big endian 16-bit number.
+  #[inline]
+  pub fn read_be_u16(&mut self) -> io::Result<u16> {
+    self.read_number(2, true).map(|number| number as u16)
+  }
+
+  /// Reads a little endian 16-bit number.
+  #[inline]
+  pub fn read_le_u16(&mut self) -> io::Result<u16> {
+    self.read_number(2, false).map(|number| number as u16)
+  }
+
+  /// Reads a big endian 24-bit number, like the length within a metadata
+  /// block header.
+  #[inline]
+  pub fn read_be_u24(&mut self) -> io::Result<u32> {
+    self.read_number(3, true).map(|number| number as u32)
+  }
+
+  /// Reads a little endian 24-bit number.
+  #[inline]
+  pub fn read_le_u24(&mut self) -> io::Result<u32> {
+    self.read_number(3, false).map(|number| number as u32)
+  }
+
+  /// Reads a big endian 32-bit number.
+  #[inline]
+  pub fn read_be_u32(&mut self) -> io::Result<u32> {
+    self.read_number(4, true).map(|number| number as u32)
+  }
+
+  /// Reads a little endian 32-bit number.
+  #[inline]
+  pub fn read_le_u32(&mut self) -> io::Result<u32> {
+    self.read_number(4, false).map(|number| number as u32)
+  }
+
+  /// Reads a big endian 64-bit number.
+  #[inline]
+  pub fn read_be_u64(&mut self) -> io::Result<u64> {
+    self.read_number(8, true)
+  }
+
+  /// Reads a little endian 64-bit number.
+  #[inline]
+  pub fn read_le_u64(&mut self) -> io::Result<u64> {
+    self.read_number(8, false)
+  }
+
+  // Reads an unsigned number that's `length` bytes long, of at most eight
+  // bytes.
+  fn read_number(&mut self, length: usize, is_big_endian: bool)
+                 -> io::Result<u64> {
+    let bytes = try!(self.read_bytes(length));
+
+    let number = if is_big_endian {
+      bytes.iter().fold(0, |result, &byte| (result << 8) | byte as u64)
+    } else {
+      bytes.iter().rev().fold(0, |result, &byte| (result << 8) | byte as u64)
+    };
+
+    Ok(number)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use utility::WriteExtension;
+
+  use std::io;
+
+  #[test]
+  fn test_round_trip() {
+    let mut bytes = Vec::new();
+
+    bytes.write_u8(0x12).unwrap();
+    bytes.write_be_u16(0x1234).unwrap();
+    bytes.write_le_u16(0x1234).unwrap();
+    bytes.write_be_u24(0x123456).unwrap();
+    bytes.write_le_u24(0x123456).unwrap();
+    bytes.write_be_u32(0x12345678).unwrap();
+    bytes.write_le_u32(0x12345678).unwrap();
+    bytes.write_be_u64(0x123456789abcdef0).unwrap();
+    bytes.write_le_u64(0x123456789abcdef0).unwrap();
+
+    let mut cursor = Cursor::new(&bytes);
+
+    assert_eq!(cursor.read_u8().unwrap(), 0x12);
+    assert_eq!(cursor.read_be_u16().unwrap(), 0x1234);
+    assert_eq!(cursor.read_le_u16().unwrap(), 0x1234);
+    assert_eq!(cursor.read_be_u24().unwrap(), 0x123456);
+    assert_eq!(cursor.read_le_u24().unwrap(), 0x123456);
+    assert_eq!(cursor.read_be_u32().unwrap(), 0x12345678);
+    assert_eq!(cursor.read_le_u32().unwrap(), 0x12345678);
+    assert_eq!(cursor.read_be_u64().unwrap(), 0x123456789abcdef0);
+    assert_eq!(cursor.read_le_u64().unwrap(), 0x123456789abcdef0);
+    assert!(cursor.is_empty());
+  }
+
+  #[test]
+  fn test_unexpected_end() {
+    let bytes      = [1, 2, 3];
+    let mut cursor = Cursor::new(&bytes);
+
+    assert!(cursor.skip(1).is_ok());
+
+    let error = cursor.read_be_u32().unwrap_err();
+
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    assert_eq!(cursor.position(), 1);
+    assert_eq!(cursor.remaining(), &[2, 3]);
+    assert_eq!(cursor.read_le_u16().unwrap(), 0x0302);
+    assert!(cursor.read_bytes(1).is_err());
+    assert!(cursor.read_bytes(0).is_ok());
+  }
+}
@@ -0,0 +1,192 @@
+//! Code tables for the block size, sample rate, and sample size within a
+//! frame header.
+//!
+//! Each of these gets stored within the frame header as a short code, with
+//! the common values having a code of their own. Block sizes and sample
+//! rates without one get stored at the end of the header instead, while
+//! sample sizes without one fall back to `StreamInfo`. The tables and the
+//! helpers here are what the parser and encoder use, so external tools that
+//! go through frame headers work off the same values.
+//!
+//! # Examples
+//!
+//! ```
+//! use flac::codes;
+//!
+//! let (code, stored) = codes::block_size_code(4096);
+//!
+//! assert_eq!(code, 0b1100);
+//! assert_eq!(stored, None);
+//! assert_eq!(codes::BLOCK_SIZES[code as usize], Some(4096));
+//! assert_eq!(codes::block_size_from_code(code, None), Some(4096));
+//! ```
+
+/// Block size, in samples, for each of the four bit block size codes.
+///
+/// Code `0b0000` is reserved, while `0b0110` and `0b0111` mean the block
+/// size, minus one, is stored at the end of the header as an 8 or 16 bit
+/// number. Those are all `None`.
+pub const BLOCK_SIZES: [Option<u32>; 16] = [
+  None,        Some(192),   Some(576),   Some(1152),
+  Some(2304),  Some(4608),  None,        None,
+  Some(256),   Some(512),   Some(1024),  Some(2048),
+  Some(4096),  Some(8192),  Some(16384), Some(32768),
+];
+
+/// Sample rate, in hertz, for each of the four bit sample rate codes.
+///
+/// Code `0b0000` means the sample rate comes from `StreamInfo`, `0b1100`
+/// through `0b1110` mean it's stored at the end of the header, and `0b1111`
+/// is invalid since it could fool a frame sync. Those are all `None`.
+pub const SAMPLE_RATES: [Option<u32>; 16] = [
+  None,         Some(88200),  Some(176400), Some(192000),
+  Some(8000),   Some(16000),  Some(22050),  Some(24000),
+  Some(32000),  Some(44100),  Some(48000),  Some(96000),
+  None,         None,         None,         None,
+];
+
+/// Bits per sample for each of the three bit sample size codes.
+///
+/// Code `0b000` means the sample size comes from `StreamInfo`, while
+/// `0b011` and `0b111` are reserved. Those are all `None`.
+pub const SAMPLE_SIZES: [Option<usize>; 8] = [
+  None,     Some(8),  Some(12), None,
+  Some(16), Some(20), Some(24), None,
+];
+
+/// Returns the four bit code for a block size, along with the value and
+/// length, in bytes, stored at the end of the header when the block size
+/// doesn't have a code of its own.
+///
+/// Block sizes need to be between 1 and 65536 samples to be stored at all.
+pub fn block_size_code(block_size: u32) -> (u8, Option<(u32, usize)>) {
+  let found = BLOCK_SIZES.iter().position(|&size| size == Some(block_size));
+
+  match found {
+    Some(code)                => (code as u8, None),
+    None if block_size <= 256 => (0b0110, Some((block_size - 1, 1))),
+    None                      => (0b0111, Some((block_size - 1, 2))),
+  }
+}
+
+/// Returns the block size for a four bit code, with `stored` being the
+/// value at the end of the header, if there is one.
+///
+/// `None` is returned for the reserved code, or when the code needs a
+/// stored value that isn't given.
+pub fn block_size_from_code(code: u8, stored: Option<u32>) -> Option<u32> {
+  match code {
+    0b0110 | 0b0111 => stored.map(|value| value + 1),
+    _               => BLOCK_SIZES.get(code as usize).and_then(|&size| size),
+  }
+}
+
+/// Returns the four bit code for a sample rate, along with the value and
+/// length, in bytes, stored at the end of the header when the sample rate
+/// doesn't have a code of its own.
+///
+/// Sample rates that can't be stored within the header at all get the code
+/// for using the sample rate from `StreamInfo`.
+pub fn sample_rate_code(sample_rate: u32) -> (u8, Option<(u32, usize)>) {
+  let found = SAMPLE_RATES.iter().position(|&rate| rate == Some(sample_rate));
+
+  if let Some(code) = found {
+    (code as u8, None)
+  } else if sample_rate % 1000 == 0 && sample_rate / 1000 <= 0xff {
+    (0b1100, Some((sample_rate / 1000, 1)))
+  } else if sample_rate <= 0xffff {
+    (0b1101, Some((sample_rate, 2)))
+  } else if sample_rate % 10 == 0 && sample_rate / 10 <= 0xffff {
+    (0b1110, Some((sample_rate / 10, 2)))
+  } else {
+    (0b0000, None)
+  }
+}
+
+/// Returns the sample rate for a four bit code, with `stored` being the
+/// value at the end of the header, if there is one, and `stream_rate` the
+/// sample rate within `StreamInfo`.
+///
+/// `None` is returned for the invalid code, or when the code needs a stored
+/// value that isn't given.
+pub fn sample_rate_from_code(code: u8, stored: Option<u32>, stream_rate: u32)
+                             -> Option<u32> {
+  match code {
+    0b0000 => Some(stream_rate),
+    0b1100 => stored.map(|value| value * 1000),
+    0b1101 => stored,
+    0b1110 => stored.map(|value| value * 10),
+    _      => SAMPLE_RATES.get(code as usize).and_then(|&rate| rate),
+  }
+}
+
+/// Returns the three bit code for a sample size, where sample sizes without
+/// a code of their own get the code for using the one from `StreamInfo`.
+pub fn sample_size_code(bits_per_sample: usize) -> u8 {
+  SAMPLE_SIZES.iter().position(|&size| size == Some(bits_per_sample))
+              .map_or(0b000, |code| code as u8)
+}
+
+/// Returns the bits per sample for a three bit code, with `stream_size`
+/// being the bits per sample within `StreamInfo`.
+///
+/// `None` is returned for the reserved codes.
+pub fn sample_size_from_code(code: u8, stream_size: usize) -> Option<usize> {
+  match code {
+    0b000 => Some(stream_size),
+    _     => SAMPLE_SIZES.get(code as usize).and_then(|&size| size),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_block_size_code() {
+    assert_eq!(block_size_code(192), (0b0001, None));
+    assert_eq!(block_size_code(4608), (0b0101, None));
+    assert_eq!(block_size_code(4096), (0b1100, None));
+    assert_eq!(block_size_code(100), (0b0110, Some((99, 1))));
+    assert_eq!(block_size_code(1000), (0b0111, Some((999, 2))));
+
+    for block_size in 1..65537 {
+      let (code, stored) = block_size_code(block_size);
+      let stored         = stored.map(|(value, _)| value);
+
+      assert_eq!(block_size_from_code(code, stored), Some(block_size));
+    }
+
+    assert_eq!(block_size_from_code(0b0000, None), None);
+    assert_eq!(block_size_from_code(0b0111, None), None);
+  }
+
+  #[test]
+  fn test_sample_rate_code() {
+    assert_eq!(sample_rate_code(44100), (0b1001, None));
+    assert_eq!(sample_rate_code(12000), (0b1100, Some((12, 1))));
+    assert_eq!(sample_rate_code(11025), (0b1101, Some((11025, 2))));
+    assert_eq!(sample_rate_code(96010), (0b1110, Some((9601, 2))));
+    assert_eq!(sample_rate_code(96011), (0b0000, None));
+
+    for &sample_rate in &[1, 8000, 44100, 50000, 192000, 655350] {
+      let (code, stored) = sample_rate_code(sample_rate);
+      let stored         = stored.map(|(value, _)| value);
+
+      assert_eq!(sample_rate_from_code(code, stored, 0), Some(sample_rate));
+    }
+
+    assert_eq!(sample_rate_from_code(0b0000, None, 1000), Some(1000));
+    assert_eq!(sample_rate_from_code(0b1111, None, 1000), None);
+  }
+
+  #[test]
+  fn test_sample_size_code() {
+    assert_eq!(sample_size_code(16), 0b100);
+    assert_eq!(sample_size_code(17), 0b000);
+    assert_eq!(sample_size_from_code(0b110, 16), Some(24));
+    assert_eq!(sample_size_from_code(0b000, 17), Some(17));
+    assert_eq!(sample_size_from_code(0b011, 16), None);
+    assert_eq!(sample_size_from_code(0b111, 16), None);
+  }
+}
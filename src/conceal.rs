@@ -0,0 +1,193 @@
+//! Replacing the audio of frames that fail to decode.
+//!
+//! Decoding normally stops at the first frame that fails to decode. Once a
+//! concealer is set through `Stream::set_concealer`, a failed frame gets
+//! skipped over instead, up to the next frame sync code, and the concealer
+//! supplies the audio in its place, so playback carries on with the frames
+//! after it.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use flac::StreamReader;
+//! use flac::conceal::RepeatLast;
+//!
+//! use std::fs::File;
+//!
+//! let mut stream = StreamReader::<File>::from_file("path/to/file.flac")
+//!                    .unwrap();
+//!
+//! stream.set_concealer(RepeatLast);
+//!
+//! let samples  = stream.iter::<i16>().collect::<Vec<_>>();
+//! let progress = stream.decode_progress();
+//!
+//! println!("{} frames concealed", progress.concealed);
+//! ```
+
+use utility::ErrorKind;
+
+use std::cmp;
+
+/// Frame that failed to decode, which needs audio to take its place.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LostFrame {
+  /// Sample number, counted per channel, the frame was expected to start
+  /// at.
+  pub sample_number: u64,
+  /// Number of samples, per channel, expected within the frame.
+  ///
+  /// This comes from the frame header when it is still intact, otherwise
+  /// from the block size of the stream.
+  pub block_size: usize,
+  /// Number of channels within the stream.
+  pub channels: usize,
+  /// Size, in bits, of each sample within the stream.
+  pub bits_per_sample: usize,
+  /// Error the frame failed to decode with.
+  pub error: ErrorKind,
+}
+
+/// Policy for the audio that replaces a frame that failed to decode.
+///
+/// Samples are laid out one channel after another, in the order channels
+/// are stored in within FLAC, the same as the decode buffer.
+pub trait Conceal {
+  /// Fills `output` with `frame.block_size` samples for each channel.
+  ///
+  /// `previous` holds the samples of the frame right before, laid out the
+  /// same way, which is empty when there isn't one. The output starts out
+  /// as silence.
+  fn conceal(&mut self, frame: &LostFrame, previous: &[i32],
+             output: &mut [i32]);
+}
+
+impl<F> Conceal for F
+ where F: FnMut(&LostFrame, &[i32], &mut [i32]) {
+  #[inline]
+  fn conceal(&mut self, frame: &LostFrame, previous: &[i32],
+             output: &mut [i32]) {
+    self(frame, previous, output)
+  }
+}
+
+/// Replaces the frame with silence.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Silence;
+
+impl Conceal for Silence {
+  fn conceal(&mut self, _: &LostFrame, _: &[i32], output: &mut [i32]) {
+    for sample in output {
+      *sample = 0;
+    }
+  }
+}
+
+/// Replaces the frame by repeating the frame before it, or silence when
+/// there isn't one.
+///
+/// A previous frame shorter than the lost one gets repeated until the
+/// lost frame is filled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RepeatLast;
+
+impl Conceal for RepeatLast {
+  fn conceal(&mut self, frame: &LostFrame, previous: &[i32],
+             output: &mut [i32]) {
+    let length = previous.len() / cmp::max(frame.channels, 1);
+
+    if length == 0 {
+      return;
+    }
+
+    for (channel, samples) in output.chunks_mut(frame.block_size)
+                                    .enumerate() {
+      let start = channel * length;
+      let last  = &previous[start..(start + length)];
+
+      for (sample, &value) in samples.iter_mut().zip(last.iter().cycle()) {
+        *sample = value;
+      }
+    }
+  }
+}
+
+/// Replaces the frame by interpolating from the last sample of the frame
+/// before it down to silence, so the gap fades out instead of clicking.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Interpolate;
+
+impl Conceal for Interpolate {
+  fn conceal(&mut self, frame: &LostFrame, previous: &[i32],
+             output: &mut [i32]) {
+    let length = previous.len() / cmp::max(frame.channels, 1);
+
+    if length == 0 {
+      return;
+    }
+
+    let block_size = frame.block_size as i64;
+
+    for (channel, samples) in output.chunks_mut(frame.block_size)
+                                    .enumerate() {
+      let last = previous[(channel + 1) * length - 1] as i64;
+
+      for (i, sample) in samples.iter_mut().enumerate() {
+        *sample = (last * (block_size - i as i64) / block_size) as i32;
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use utility::ErrorKind;
+
+  fn lost_frame(block_size: usize) -> LostFrame {
+    LostFrame {
+      sample_number: 0,
+      block_size: block_size,
+      channels: 2,
+      bits_per_sample: 16,
+      error: ErrorKind::InvalidCRC16,
+    }
+  }
+
+  #[test]
+  fn test_silence() {
+    let mut output = [5; 8];
+
+    Silence.conceal(&lost_frame(4), &[1, 2, 3, 4], &mut output);
+
+    assert_eq!(output, [0; 8]);
+  }
+
+  #[test]
+  fn test_repeat_last() {
+    let frame      = lost_frame(4);
+    let mut output = [0; 8];
+
+    RepeatLast.conceal(&frame, &[], &mut output);
+
+    assert_eq!(output, [0; 8]);
+
+    RepeatLast.conceal(&frame, &[1, 2, 3, 4, 5, 6, 7, 8], &mut output);
+
+    assert_eq!(output, [1, 2, 3, 4, 5, 6, 7, 8]);
+
+    RepeatLast.conceal(&frame, &[1, 2, -1, -2], &mut output);
+
+    assert_eq!(output, [1, 2, 1, 2, -1, -2, -1, -2]);
+  }
+
+  #[test]
+  fn test_interpolate() {
+    let frame      = lost_frame(4);
+    let mut output = [0; 8];
+
+    Interpolate.conceal(&frame, &[0, 8, 0, -16], &mut output);
+
+    assert_eq!(output, [8, 6, 4, 2, -16, -12, -8, -4]);
+  }
+}
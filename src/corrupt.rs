@@ -0,0 +1,190 @@
+//! Helpers for damaging a valid FLAC buffer in realistic ways.
+//!
+//! Only available with the `corrupt` feature enabled. These are meant for
+//! testing how a player deals with bit flips, truncated downloads, and bad
+//! checksums, without needing a collection of broken files.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use flac::StreamBuffer;
+//! use flac::corrupt;
+//!
+//! use std::fs::File;
+//! use std::io::Read;
+//!
+//! let mut file   = File::open("path/to/file.flac").unwrap();
+//! let mut buffer = Vec::new();
+//!
+//! file.read_to_end(&mut buffer).unwrap();
+//!
+//! // Break the checksum of the third frame.
+//! corrupt::frame_crc(&mut buffer, 2).unwrap();
+//!
+//! let mut stream = StreamBuffer::from_buffer(&buffer).unwrap();
+//!
+//! for sample in stream.iter::<i16>() {
+//!   // Only the samples from the first two frames get decoded.
+//! }
+//! ```
+
+use stream::StreamBuffer;
+use utility::ErrorKind;
+
+use std::ops::Range;
+
+/// Returns the byte range of every frame within a FLAC buffer.
+///
+/// Finding frames stops at the first one that fails to parse.
+///
+/// # Failures
+///
+/// Fails in the same way as `Stream::from_buffer` does.
+pub fn frame_ranges(buffer: &[u8]) -> Result<Vec<Range<usize>>, ErrorKind> {
+  let mut stream = try!(StreamBuffer::from_buffer(buffer));
+
+  let ranges = stream.frame_ranges().map(|frame| {
+    (frame.range.start as usize)..(frame.range.end as usize)
+  });
+
+  Ok(ranges.collect())
+}
+
+// Finds the byte range of a single frame.
+fn frame_range(buffer: &[u8], frame: usize)
+               -> Result<Range<usize>, ErrorKind> {
+  let ranges = try!(frame_ranges(buffer));
+
+  ranges.get(frame).cloned().ok_or(ErrorKind::NotFound)
+}
+
+/// Flips a single bit, counting from the most significant bit of the first
+/// byte.
+///
+/// Bits past the end of the buffer are ignored.
+pub fn flip_bit(buffer: &mut [u8], bit: usize) {
+  if let Some(byte) = buffer.get_mut(bit / 8) {
+    *byte ^= 0b10000000 >> (bit % 8);
+  }
+}
+
+/// Flips a bit within the first four bytes of a frame's header, which holds
+/// the sync code, block size, sample rate, channels, and sample size.
+///
+/// # Failures
+///
+/// * `ErrorKind::NotFound` is returned when there is no frame at the given
+///   index.
+/// * Fails in the same way as `Stream::from_buffer` does.
+pub fn frame_header_bit(buffer: &mut [u8], frame: usize, bit: usize)
+                        -> Result<(), ErrorKind> {
+  let range = try!(frame_range(buffer, frame));
+
+  flip_bit(&mut buffer[range.start..], bit % 32);
+
+  Ok(())
+}
+
+/// Breaks the CRC-16 at the end of a frame, leaving everything else within
+/// the frame intact.
+///
+/// Fails in the same way as `corrupt::frame_header_bit` does.
+pub fn frame_crc(buffer: &mut [u8], frame: usize) -> Result<(), ErrorKind> {
+  let range = try!(frame_range(buffer, frame));
+
+  buffer[range.end - 1] ^= 0xff;
+
+  Ok(())
+}
+
+/// Cuts off the buffer halfway through a frame, like a download that ended
+/// early.
+///
+/// Fails in the same way as `corrupt::frame_header_bit` does.
+pub fn truncate_frame(buffer: &mut Vec<u8>, frame: usize)
+                      -> Result<(), ErrorKind> {
+  let range = try!(frame_range(buffer, frame));
+
+  buffer.truncate(range.start + (range.end - range.start) / 2);
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use stream::StreamBuffer;
+  use utility::ErrorKind;
+
+  use std::fs::File;
+  use std::io::Read;
+
+  fn read_file(filename: &str) -> Vec<u8> {
+    let mut file   = File::open(filename).unwrap();
+    let mut buffer = Vec::new();
+
+    file.read_to_end(&mut buffer).unwrap();
+
+    buffer
+  }
+
+  fn decoded_len(buffer: &[u8]) -> usize {
+    let mut stream = StreamBuffer::from_buffer(buffer).unwrap();
+
+    stream.iter::<i16>().count()
+  }
+
+  #[test]
+  fn test_flip_bit() {
+    let mut buffer = [0b00000000, 0b11111111];
+
+    flip_bit(&mut buffer, 0);
+    flip_bit(&mut buffer, 15);
+    flip_bit(&mut buffer, 16);
+
+    assert_eq!(buffer, [0b10000000, 0b11111110]);
+  }
+
+  #[test]
+  fn test_frame_ranges() {
+    let buffer = read_file("tests/assets/input-SVAUP.flac");
+    let ranges = frame_ranges(&buffer).unwrap();
+    let stream = StreamBuffer::from_buffer(&buffer).unwrap();
+
+    assert!(ranges.len() > 1);
+    assert_eq!(ranges[0].start as u64, stream.audio_offset());
+    assert_eq!(ranges[ranges.len() - 1].end, buffer.len());
+
+    for pair in ranges.windows(2) {
+      assert_eq!(pair[0].end, pair[1].start);
+    }
+  }
+
+  #[test]
+  fn test_corruptions() {
+    let buffer = read_file("tests/assets/input-SVAUP.flac");
+    let ranges = frame_ranges(&buffer).unwrap();
+    let total  = decoded_len(&buffer);
+    let last   = ranges.len() - 1;
+
+    let mut crc = buffer.clone();
+
+    assert!(frame_crc(&mut crc, last).is_ok());
+    assert!(decoded_len(&crc) < total);
+
+    let mut header = buffer.clone();
+
+    assert!(frame_header_bit(&mut header, last, 3).is_ok());
+    assert!(decoded_len(&header) < total);
+
+    let mut truncated = buffer.clone();
+
+    assert!(truncate_frame(&mut truncated, last).is_ok());
+    assert!(decoded_len(&truncated) < total);
+
+    let mut missing = buffer.clone();
+
+    assert_eq!(frame_crc(&mut missing, ranges.len()),
+               Err(ErrorKind::NotFound));
+  }
+}
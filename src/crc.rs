@@ -1,3 +1,35 @@
+//! Checksums guarding the frame header and the frame as a whole.
+//!
+//! Every frame header ends with a CRC-8 of the header bytes before it, and
+//! every frame ends with a CRC-16 of all the frame bytes before it. Both
+//! start from zero, go most significant bit first, and aren't inverted at
+//! the end, which makes the CRC-8 the common "CRC-8/SMBUS" and the CRC-16
+//! the one also known as "CRC-16/BUYPASS" or "CRC-16/UMTS". The parser and
+//! encoder both go through `crc8` and `crc16`, so tools that write or repair
+//! frames end up with the same bytes.
+//!
+//! # Examples
+//!
+//! ```
+//! use flac::crc::{crc8, crc16};
+//!
+//! // A frame header, with the CRC-8 as its last byte.
+//! let header = b"\xff\xf8\x53\x1c\xf0\x90\x80\x80\x2e";
+//!
+//! assert_eq!(crc8(&header[0..8]), header[8]);
+//! assert_eq!(crc8(header), 0);
+//!
+//! assert_eq!(crc16(b"123456789"), 0xfee8);
+//! ```
+
+/// Polynomial of the CRC-8, x^8 + x^2 + x^1 + x^0, without its leading
+/// term.
+pub const CRC_8_POLYNOMIAL: u8 = 0x07;
+
+/// Polynomial of the CRC-16, x^16 + x^15 + x^2 + x^0, without its leading
+/// term.
+pub const CRC_16_POLYNOMIAL: u16 = 0x8005;
+
 // Pre-generated crc-8 table.
 //
 // Using the polynomial, x^8 + x^2 + x^1 + x^0 (0b1_00000111) and the
@@ -84,7 +116,7 @@ const CRC_8_TABLE: [u8; 256] = [
 //     }
 //   }
 //
-//   crc_8_table[i] = crc;
+//   crc_16_table[i] = crc;
 // }
 // ```
 const CRC_16_TABLE: [u16; 256] = [
@@ -122,6 +154,10 @@ const CRC_16_TABLE: [u16; 256] = [
   0x8213, 0x0216, 0x021c, 0x8219, 0x0208, 0x820d, 0x8207, 0x0202
 ];
 
+/// Calculates the CRC-8 of `data`, like the one ending a frame header.
+///
+/// Running it over the header along with its stored CRC-8 gives zero when
+/// the two match.
 #[inline]
 pub fn crc8(data: &[u8]) -> u8 {
   data.iter().fold(0, |crc, byte| {
@@ -131,13 +167,22 @@ pub fn crc8(data: &[u8]) -> u8 {
   })
 }
 
+/// Calculates the CRC-16 of `data`, like the one ending a frame.
+///
+/// Running it over the frame along with its stored CRC-16, which is big
+/// endian, gives zero when the two match.
 #[inline]
 pub fn crc16(data: &[u8]) -> u16 {
-  data.iter().fold(0, |crc, byte| {
-    let index = (((crc >> 8) as u8) ^ byte) as usize;
+  data.iter().fold(0, |crc, &byte| update_crc16(crc, byte))
+}
 
-    (crc << 8) ^ unsafe { *CRC_16_TABLE.get_unchecked(index) }
-  })
+// Carries on the CRC-16 `crc` of the bytes so far with one more byte, for
+// checking a frame as its bytes come in.
+#[inline]
+pub(crate) fn update_crc16(crc: u16, byte: u8) -> u16 {
+  let index = (((crc >> 8) as u8) ^ byte) as usize;
+
+  (crc << 8) ^ unsafe { *CRC_16_TABLE.get_unchecked(index) }
 }
 
 #[cfg(test)]
@@ -154,6 +199,71 @@ mod tests {
     assert_eq!(crc8(&[0x4f, 0x9e, 0x4b]), 0x00);
   }
 
+  // Bit at a time versions, going straight by the polynomials.
+  fn slow_crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0, |crc, &byte| {
+      (0..8).fold(crc ^ byte, |crc, _| {
+        if (crc & 0x80) != 0 {
+          (crc << 1) ^ CRC_8_POLYNOMIAL
+        } else {
+          crc << 1
+        }
+      })
+    })
+  }
+
+  fn slow_crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0, |crc, &byte| {
+      (0..8).fold(crc ^ ((byte as u16) << 8), |crc, _| {
+        if (crc & 0x8000) != 0 {
+          (crc << 1) ^ CRC_16_POLYNOMIAL
+        } else {
+          crc << 1
+        }
+      })
+    })
+  }
+
+  #[test]
+  fn test_check_values() {
+    // The usual check values, calculated over the ASCII digits "1" to "9".
+    assert_eq!(crc8(b"123456789"), 0xf4);
+    assert_eq!(crc16(b"123456789"), 0xfee8);
+
+    assert_eq!(crc8(&[]), 0);
+    assert_eq!(crc16(&[]), 0);
+  }
+
+  #[test]
+  fn test_tables() {
+    for i in 0..256 {
+      assert_eq!(CRC_8_TABLE[i], slow_crc8(&[i as u8]));
+      assert_eq!(CRC_16_TABLE[i], slow_crc16(&[i as u8]));
+    }
+
+    let bytes = (0..1024).map(|i| (i * 7 + (i >> 3)) as u8)
+                         .collect::<Vec<_>>();
+
+    assert_eq!(crc8(&bytes), slow_crc8(&bytes));
+    assert_eq!(crc16(&bytes), slow_crc16(&bytes));
+  }
+
+  #[test]
+  fn test_residue() {
+    let bytes = b"\xff\xf8\x69\x18\x00\x00\x12\x34\x56\x78";
+
+    let mut with_crc8  = bytes.to_vec();
+    let mut with_crc16 = bytes.to_vec();
+    let crc            = crc16(bytes);
+
+    with_crc8.push(crc8(bytes));
+    with_crc16.push((crc >> 8) as u8);
+    with_crc16.push(crc as u8);
+
+    assert_eq!(crc8(&with_crc8), 0);
+    assert_eq!(crc16(&with_crc16), 0);
+  }
+
   #[test]
   fn test_crc16() {
     assert_eq!(crc16(&[0x00, 0x0a]), 0x003c);
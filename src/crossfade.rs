@@ -0,0 +1,369 @@
+//! Crossfading from the end of one stream into the start of another.
+//!
+//! Players moving from one track straight into the next, DJ style, fade the
+//! last few seconds of the outgoing track out while fading the incoming one
+//! in. `crossfade` seeks the outgoing stream to its tail and hands out the
+//! incoming stream's blocks with the tail mixed in, sample for sample, with
+//! the gain of each stream following a `FadeCurve`.
+//!
+//! The last block handed out ends where a frame of the incoming stream
+//! ends, with the samples past the fade left as they are, so decoding the
+//! incoming stream carries on from the next frame once the crossfade is
+//! done.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use flac::StreamReader;
+//! use flac::crossfade::{self, FadeCurve};
+//!
+//! use std::fs::File;
+//!
+//! let mut outgoing = StreamReader::<File>::from_file("path/to/a.flac")
+//!                      .unwrap();
+//! let mut incoming = StreamReader::<File>::from_file("path/to/b.flac")
+//!                      .unwrap();
+//!
+//! // Five seconds at 44.1kHz, after playing `a.flac` up to the last five
+//! // seconds.
+//! let length = 5 * 44100;
+//!
+//! for block in crossfade::crossfade(&mut outgoing, &mut incoming, length,
+//!                                   FadeCurve::EqualPower).unwrap() {
+//!   let block = block.unwrap();
+//!
+//!   // Samples are interleaved by channel within `block.samples`.
+//! }
+//!
+//! // The rest of `b.flac` picks up right after the crossfade.
+//! for block in incoming.blocks::<i32>() {
+//!   let block = block.unwrap();
+//! }
+//! ```
+
+use stream::{Block, Blocks, Stream};
+use utility::{ErrorKind, SeekableProducer, StreamProducer};
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::f64::consts::FRAC_PI_2;
+
+/// Shape of the gain of each stream over the length of a crossfade.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FadeCurve {
+  /// Gains change in a straight line, always adding up to one. Two
+  /// unrelated tracks sound quieter halfway through.
+  Linear,
+  /// Gains follow a quarter of a sine and cosine, keeping the combined
+  /// power the same throughout, which suits most music.
+  EqualPower,
+}
+
+impl FadeCurve {
+  /// Returns the gains of the outgoing and incoming streams at `position`,
+  /// going from zero at the start of the crossfade to one at the end.
+  pub fn gains(&self, position: f64) -> (f64, f64) {
+    let position = position.clamp(0.0, 1.0);
+
+    match *self {
+      FadeCurve::Linear     => (1.0 - position, position),
+      FadeCurve::EqualPower => {
+        let angle = position * FRAC_PI_2;
+
+        (angle.cos(), angle.sin())
+      }
+    }
+  }
+}
+
+/// Crossfades the last `length` samples of `outgoing` with the first
+/// `length` samples of `incoming`, returning an iterator over the mixed
+/// blocks.
+///
+/// `outgoing` gets seeked to the frame holding the start of its tail, and
+/// the samples before the tail are skipped, so the fade starts on the exact
+/// sample. `incoming` gets decoded from wherever it is, which is its first
+/// frame when it was just opened. The fade gets shortened to the length of
+/// the shorter stream. Mixed samples get clamped to the bits per sample of
+/// the streams, since the equal power curve can push two loud tracks past
+/// it.
+///
+/// # Failures
+///
+/// * `ErrorKind::Unsupported` is returned when the streams differ in
+///   sample rate, channels, or bits per sample, or when `outgoing` doesn't
+///   know its total number of samples.
+/// * Any error from seeking `outgoing` is returned as is.
+pub fn crossfade<'a, 'b, P, Q>(outgoing: &'a mut Stream<P>,
+                               incoming: &'b mut Stream<Q>, length: u64,
+                               curve: FadeCurve)
+                               -> Result<Crossfade<'a, 'b, P, Q>, ErrorKind>
+ where P: SeekableProducer,
+       Q: StreamProducer {
+  let info  = outgoing.info();
+  let other = incoming.info();
+
+  if info.sample_rate != other.sample_rate ||
+     info.channels != other.channels ||
+     info.bits_per_sample != other.bits_per_sample ||
+     info.total_samples == 0 {
+    return Err(ErrorKind::Unsupported);
+  }
+
+  let length = if other.total_samples > 0 {
+    cmp::min(length, cmp::min(info.total_samples, other.total_samples))
+  } else {
+    cmp::min(length, info.total_samples)
+  };
+
+  let start = info.total_samples - length;
+  let frame = try!(outgoing.seek(start));
+  let max   = (1i64 << (info.bits_per_sample.get() - 1)) - 1;
+
+  Ok(Crossfade {
+    outgoing: outgoing.blocks::<i32>(),
+    incoming: incoming.blocks::<i32>(),
+    curve: curve,
+    length: length,
+    position: 0,
+    skip: (start - frame) * info.channels as u64,
+    pending: VecDeque::new(),
+    channels: info.channels as usize,
+    min: -max - 1,
+    max: max,
+    is_finished: false,
+  })
+}
+
+/// An iterator over the blocks of a crossfade, see `crossfade`.
+///
+/// Each block comes from a frame of the incoming stream, numbered from the
+/// start of that stream. Iteration stops after the block the fade ends
+/// within, or after handing out the error of the first frame of either
+/// stream that fails to decode. When the outgoing stream runs out of frames
+/// early, its missing samples are treated as silence.
+pub struct Crossfade<'a, 'b, P, Q>
+ where P: 'a + StreamProducer,
+       Q: 'b + StreamProducer {
+  outgoing: Blocks<'a, P, i64>,
+  incoming: Blocks<'b, Q, i64>,
+  curve: FadeCurve,
+  length: u64,
+  // Number of samples, per channel, mixed so far.
+  position: u64,
+  // Number of outgoing samples left to skip before the tail starts.
+  skip: u64,
+  // Outgoing samples decoded but not yet mixed.
+  pending: VecDeque<i32>,
+  channels: usize,
+  min: i64,
+  max: i64,
+  is_finished: bool,
+}
+
+impl<'a, 'b, P, Q> Crossfade<'a, 'b, P, Q>
+ where P: StreamProducer,
+       Q: StreamProducer {
+  /// Returns the length of the fade, in samples per channel, after being
+  /// shortened to fit both streams.
+  #[inline]
+  pub fn len(&self) -> u64 {
+    self.length
+  }
+
+  /// Returns true when there is nothing to fade, because either stream is
+  /// empty or the length asked for was zero.
+  #[inline]
+  pub fn is_empty(&self) -> bool {
+    self.length == 0
+  }
+
+  // Makes sure a whole sample of every channel of the outgoing stream is
+  // pending, returning false once the outgoing stream has ended.
+  fn fill(&mut self) -> Result<bool, ErrorKind> {
+    while self.pending.len() < self.channels {
+      let block = match self.outgoing.next() {
+        Some(block) => try!(block),
+        None        => {
+          return match self.outgoing.decode_progress().error {
+            Some(kind) => Err(kind),
+            None       => Ok(false),
+          };
+        }
+      };
+
+      let skipped = cmp::min(self.skip, block.samples.len() as u64);
+
+      self.skip -= skipped;
+
+      self.pending.extend(&block.samples[(skipped as usize)..]);
+    }
+
+    Ok(true)
+  }
+}
+
+impl<'a, 'b, P, Q> Iterator for Crossfade<'a, 'b, P, Q>
+ where P: StreamProducer,
+       Q: StreamProducer {
+  type Item = Result<Block<i32>, ErrorKind>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.is_finished || self.position >= self.length {
+      return None;
+    }
+
+    let mut block = match self.incoming.next() {
+      Some(Ok(block)) => block,
+      result          => {
+        self.is_finished = true;
+
+        // A frame that fails to decode ends the blocks without an error,
+        // which is kept in the progress instead.
+        return result.or_else(|| {
+          self.incoming.decode_progress().error.map(Err)
+        });
+      }
+    };
+
+    let channels = self.channels;
+    let length   = cmp::min(block.len() as u64,
+                            self.length - self.position) as usize;
+
+    for i in 0..length {
+      let has_outgoing = match self.fill() {
+        Ok(has_outgoing) => has_outgoing,
+        Err(kind)        => {
+          self.is_finished = true;
+
+          return Some(Err(kind));
+        }
+      };
+
+      let position   = self.position as f64 / self.length as f64;
+      let (out, inc) = self.curve.gains(position);
+      let start      = i * channels;
+
+      for sample in &mut block.samples[start..(start + channels)] {
+        let outgoing = if has_outgoing {
+          self.pending.pop_front().unwrap_or(0)
+        } else {
+          0
+        };
+
+        let mixed = (outgoing as f64 * out + *sample as f64 * inc).round();
+
+        *sample = cmp::max(self.min, cmp::min(self.max, mixed as i64)) as i32;
+      }
+
+      self.position += 1;
+    }
+
+    Some(Ok(block))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use stream::{StreamBuffer, StreamReader};
+
+  use std::fs::File;
+  use std::io::Read;
+
+  fn open(filename: &str) -> StreamReader<File> {
+    StreamReader::<File>::from_file(filename).unwrap()
+  }
+
+  #[test]
+  fn test_gains() {
+    assert_eq!(FadeCurve::Linear.gains(0.25), (0.75, 0.25));
+    assert_eq!(FadeCurve::EqualPower.gains(0.0), (1.0, 0.0));
+    assert_eq!(FadeCurve::Linear.gains(2.0), (0.0, 1.0));
+
+    let (out, inc) = FadeCurve::EqualPower.gains(0.5);
+
+    assert!((out - inc).abs() < 1e-12);
+    assert!((out * out + inc * inc - 1.0).abs() < 1e-12);
+  }
+
+  #[test]
+  fn test_crossfade() {
+    let filename = "tests/assets/input-stereo-8bit.flac";
+    let samples  = open(filename).iter::<i32>().collect::<Vec<_>>();
+
+    let mut outgoing = open(filename);
+    let mut incoming = open(filename);
+    let info         = outgoing.info();
+    let channels     = info.channels as usize;
+    let total        = info.total_samples as usize;
+    let length       = 1500;
+
+    let blocks = crossfade(&mut outgoing, &mut incoming, length as u64,
+                           FadeCurve::Linear).unwrap()
+                   .map(|block| block.unwrap())
+                   .collect::<Vec<_>>();
+    let mixed  = blocks.iter().flat_map(|block| block.samples.clone())
+                       .collect::<Vec<_>>();
+
+    let tail     = &samples[((total - length) * channels)..];
+    let expected = (0..(length * channels)).map(|i| {
+      let position   = (i / channels) as f64 / length as f64;
+      let (out, inc) = FadeCurve::Linear.gains(position);
+
+      (tail[i] as f64 * out + samples[i] as f64 * inc).round() as i32
+    }).collect::<Vec<_>>();
+
+    assert_eq!(blocks[0].sample_number, 0);
+    assert_eq!(&mixed[..(length * channels)], &expected[..]);
+
+    // The block the fade ends within is handed out whole, with the
+    // incoming stream carrying on from the frame after it.
+    assert!(mixed.len() > length * channels);
+    assert_eq!(&mixed[(length * channels)..],
+               &samples[(length * channels)..mixed.len()]);
+
+    let next = incoming.blocks::<i32>().next().unwrap().unwrap();
+
+    assert_eq!(&next.samples[..],
+               &samples[mixed.len()..(mixed.len() + next.samples.len())]);
+  }
+
+  #[test]
+  fn test_crossfade_errors() {
+    let filename   = "tests/assets/input-stereo-8bit.flac";
+    let mut file   = File::open(filename).unwrap();
+    let mut buffer = Vec::new();
+
+    file.read_to_end(&mut buffer).unwrap();
+
+    let ranges = StreamBuffer::from_buffer(&buffer).unwrap().frame_ranges()
+                   .map(|frame| frame.range).collect::<Vec<_>>();
+
+    // Corrupts the middle of the second and the last frame, where the fade
+    // passes through both streams.
+    let mut corrupt = buffer.clone();
+
+    for range in &[&ranges[1], &ranges[ranges.len() - 1]] {
+      corrupt[(range.start + range.end) as usize / 2] ^= 0xff;
+    }
+
+    let fade = |outgoing: &[u8], incoming: &[u8]| {
+      let mut outgoing = StreamBuffer::from_buffer(outgoing).unwrap();
+      let mut incoming = StreamBuffer::from_buffer(incoming).unwrap();
+
+      crossfade(&mut outgoing, &mut incoming, 1500, FadeCurve::Linear)
+        .unwrap().collect::<Vec<_>>()
+    };
+
+    let failed = fade(&buffer, &corrupt);
+
+    assert_eq!(failed.len(), 2);
+    assert!(failed[0].is_ok() && failed[1].is_err());
+
+    let failed = fade(&corrupt, &buffer);
+
+    assert_eq!(failed.len(), 1);
+    assert!(failed[0].is_err());
+  }
+}
@@ -0,0 +1,802 @@
+//! Decoding a stream track by track, following its cue sheet.
+//!
+//! Album images keep a whole disc within a single stream, with a
+//! `CueSheet` marking where each track starts. `CueTracks` hands out a
+//! `Track` at a time, each one an iterator over only the samples of that
+//! track. The frames still get decoded once, in order, so tracks need to be
+//! read in the order they're handed out.
+//!
+//! The gap before a track, between its `INDEX 00` and `INDEX 01`, can be
+//! kept with the track before it, kept with the track itself, or skipped
+//! entirely, see `Pregap`.
+//!
+//! Tracks flagged with pre-emphasis within the cue sheet had their treble
+//! boosted when they were mastered, and sound too bright unless it gets
+//! undone on playback. The flag is returned by `Track::is_pre_emphasis`,
+//! and `CueTracks::with_deemphasis` applies the de-emphasis filter to the
+//! samples of those tracks.
+//!
+//! `export_tracks` goes one step further, encoding each track into a FLAC
+//! stream of its own, for splitting an album image into one file per track.
+//! `export_files` writes those tracks straight to files named after their
+//! tags.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use flac::StreamReader;
+//!
+//! use std::fs::File;
+//!
+//! let mut stream = StreamReader::<File>::from_file("path/to/album.flac")
+//!                    .unwrap();
+//!
+//! for track in stream.cue_tracks::<i16>() {
+//!   let number  = track.number();
+//!   let samples = track.collect::<Vec<_>>();
+//!
+//!   // Samples are interleaved by channel within `samples`.
+//! }
+//! ```
+
+use encoder::{Encoder, EncoderOptions};
+use metadata::{CueSheet, CueSheetTrack, StreamInfo};
+#[cfg(feature = "vorbis-comment")]
+use metadata::{Data, VorbisComment};
+use stream::{Block, Blocks, DecodeProgress, Stream};
+use utility::{ErrorKind, Sample, StreamProducer};
+
+use std::cmp;
+#[cfg(feature = "vorbis-comment")]
+use std::fs::{self, File};
+use std::io::{self, Seek, Write};
+use std::rc::Rc;
+use std::vec;
+use std::ops::Range;
+#[cfg(feature = "vorbis-comment")]
+use std::path::{Path, PathBuf};
+use std::cell::RefCell;
+
+// Number of samples, per channel, handed to the encoder at a time while
+// exporting.
+const EXPORT_CHUNK_SIZE: usize = 4096;
+
+/// Which track the gap before a track, from its `INDEX 00` to its
+/// `INDEX 01`, belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pregap {
+  /// The gap ends the track before it, the same as a disc that's played
+  /// from start to finish. The gap before the first track gets skipped,
+  /// since there's no track before it.
+  Append,
+  /// The gap starts the track it comes before.
+  Prepend,
+  /// The gap doesn't belong to any track.
+  Skip,
+}
+
+impl Default for Pregap {
+  #[inline]
+  fn default() -> Self {
+    Pregap::Append
+  }
+}
+
+/// Returns the number and the range of sample numbers of every track within
+/// the cue sheet, with the gaps before each track handled by `pregap`.
+///
+/// The last track of a cue sheet is the lead-out, which only marks where
+/// the audio ends, so it doesn't get a range of its own.
+pub fn track_ranges(cue_sheet: &CueSheet, pregap: Pregap)
+                    -> Vec<(u8, Range<u64>)> {
+  let tracks = &cue_sheet.tracks;
+
+  if tracks.len() < 2 {
+    return Vec::new();
+  }
+
+  let lead_out = tracks[tracks.len() - 1].offset;
+  let count    = tracks.len() - 1;
+
+  (0..count).map(|i| {
+    let track = &tracks[i];
+    let start = match pregap {
+      Pregap::Prepend => pregap_start(track),
+      _               => track_start(track),
+    };
+
+    let end = if i + 1 == count {
+      lead_out
+    } else {
+      let next = &tracks[i + 1];
+
+      match pregap {
+        Pregap::Append => track_start(next),
+        _              => pregap_start(next),
+      }
+    };
+
+    (track.number, start..cmp::max(start, end))
+  }).collect()
+}
+
+// Sample number of the track's `INDEX 01`, or its first index when there
+// isn't one.
+fn track_start(track: &CueSheetTrack) -> u64 {
+  track.indices.iter().find(|index| index.number == 1)
+       .or_else(|| track.indices.first())
+       .map_or(track.offset, |index| index.stream_offset(track))
+}
+
+// Sample number of the track's `INDEX 00`, which is the same as the start
+// of the track when there is no gap before it.
+fn pregap_start(track: &CueSheetTrack) -> u64 {
+  track.indices.iter().find(|index| index.number == 0)
+       .map_or_else(|| track_start(track), |index| index.stream_offset(track))
+}
+
+// Time constants, in seconds, of the pre-emphasis used by CDs.
+const EMPHASIS_POLE: f64 = 50e-6;
+const EMPHASIS_ZERO: f64 = 15e-6;
+
+// First order shelving filter undoing the 50/15 µs pre-emphasis of CDs,
+// made with the bilinear transform. It passes low frequencies as they are
+// and cuts the highest ones by about 10.5 dB, to 15/50 of their level.
+// Every tap of its impulse response is positive and they add up to one, so
+// the output never gets louder than the loudest input.
+struct Deemphasis {
+  b0: f64,
+  b1: f64,
+  a1: f64,
+  channel: usize,
+  // Previous input and output of each channel, empty until the first
+  // sample of the channel.
+  history: Vec<Option<(f64, f64)>>,
+}
+
+impl Deemphasis {
+  fn new(info: &StreamInfo) -> Self {
+    let sample_rate = info.sample_rate.get() as f64;
+    let channels    = cmp::max(info.channels as usize, 1);
+
+    let pole = 2.0 * sample_rate * EMPHASIS_POLE;
+    let zero = 2.0 * sample_rate * EMPHASIS_ZERO;
+
+    Deemphasis {
+      b0: (1.0 + zero) / (1.0 + pole),
+      b1: (1.0 - zero) / (1.0 + pole),
+      a1: (1.0 - pole) / (1.0 + pole),
+      channel: 0,
+      history: vec![None; channels],
+    }
+  }
+
+  // Filters the next sample, with samples interleaved by channel.
+  fn filter(&mut self, sample: i64) -> i64 {
+    let input = sample as f64;
+
+    // Starting out settled on the first sample keeps the filter from
+    // ringing at the start of a track, since the gain at DC is one.
+    let (previous_input, previous_output) =
+      self.history[self.channel].unwrap_or((input, input));
+
+    let output = self.b0 * input + self.b1 * previous_input -
+                 self.a1 * previous_output;
+
+    self.history[self.channel] = Some((input, output));
+    self.channel               = (self.channel + 1) % self.history.len();
+
+    output.round() as i64
+  }
+}
+
+// Decoded block that tracks take their samples from, shared between every
+// track.
+struct Decoder<'a, P, S>
+ where P: 'a + StreamProducer,
+       S: Sample {
+  blocks: Blocks<'a, P, S>,
+  block: Option<Block<S::Normal>>,
+  index: usize,
+  error: Option<ErrorKind>,
+}
+
+impl<'a, P, S> Decoder<'a, P, S>
+ where P: StreamProducer,
+       S: Sample,
+       S::Normal: Copy {
+  // Returns the next sample that lands within `range`, decoding and
+  // skipping over whatever comes before it.
+  fn next_sample(&mut self, range: &Range<u64>) -> Option<S::Normal> {
+    loop {
+      if let Some(ref block) = self.block {
+        let channels = cmp::max(block.channels, 1);
+
+        if self.index < block.samples.len() {
+          let number = block.sample_number + (self.index / channels) as u64;
+
+          if number >= range.end {
+            return None;
+          }
+
+          if number >= range.start {
+            self.index += 1;
+
+            return Some(block.samples[self.index - 1]);
+          }
+
+          let skip = (range.start - block.sample_number) as usize;
+
+          self.index = cmp::min(skip.saturating_mul(channels),
+                                block.samples.len());
+
+          continue;
+        }
+      }
+
+      match self.blocks.next() {
+        Some(Ok(block)) => {
+          self.block = Some(block);
+          self.index = 0;
+        }
+        Some(Err(kind)) => {
+          self.block = None;
+          self.error = Some(kind);
+
+          return None;
+        }
+        None            => {
+          // A frame that failed to decode ends the blocks as well, with the
+          // error kept in the progress instead.
+          self.block = None;
+          self.error = self.blocks.decode_progress().error;
+
+          return None;
+        }
+      }
+    }
+  }
+}
+
+/// An iterator over the tracks of a stream, as given by its cue sheet.
+///
+/// Each track decodes its samples from the same stream, so a track needs
+/// to be read before the next one is, otherwise the samples it skips get
+/// decoded and thrown away. Reading a track after a later one has been read
+/// returns nothing.
+pub struct CueTracks<'a, P, S>
+ where P: 'a + StreamProducer,
+       S: Sample {
+  ranges: vec::IntoIter<(u8, Range<u64>)>,
+  decoder: Rc<RefCell<Decoder<'a, P, S>>>,
+  emphasized: Vec<u8>,
+  is_deemphasis: bool,
+}
+
+impl<'a, P, S> CueTracks<'a, P, S>
+ where P: StreamProducer,
+       S: Sample {
+  /// Constructs an iterator over the tracks at each of the ranges, given
+  /// by `track_ranges`, decoding samples from `blocks`.
+  ///
+  /// The blocks are expected to start at the first frame of the stream.
+  pub fn new(blocks: Blocks<'a, P, S>, ranges: Vec<(u8, Range<u64>)>)
+             -> Self {
+    let decoder = Decoder {
+      blocks: blocks,
+      block: None,
+      index: 0,
+      error: None,
+    };
+
+    CueTracks {
+      ranges: ranges.into_iter(),
+      decoder: Rc::new(RefCell::new(decoder)),
+      emphasized: Vec::new(),
+      is_deemphasis: false,
+    }
+  }
+
+  /// Marks the tracks with the given numbers as having pre-emphasis, the
+  /// same as `CueSheetTrack::is_pre_emphasis` does.
+  ///
+  /// `Stream::cue_tracks` already marks them from the cue sheet, this is
+  /// for tracks constructed with `CueTracks::new`.
+  pub fn with_pre_emphasis(mut self, numbers: &[u8]) -> Self {
+    self.emphasized.extend_from_slice(numbers);
+
+    self
+  }
+
+  /// Applies the de-emphasis filter to the samples of tracks with
+  /// pre-emphasis when `is_deemphasis` is true.
+  ///
+  /// This is off by default, leaving samples exactly as they're decoded.
+  /// The filter is the standard 50/15 µs one, at the sample rate of the
+  /// stream, with its output rounded. It only ever lowers the level, so
+  /// samples stay within the bits per sample of the stream. Each track
+  /// starts the filter over, so tracks come out the same no matter which
+  /// ones were read before.
+  ///
+  /// # Examples
+  ///
+  /// ```no_run
+  /// use flac::StreamReader;
+  ///
+  /// use std::fs::File;
+  ///
+  /// let mut stream = StreamReader::<File>::from_file("path/to/album.flac")
+  ///                    .unwrap();
+  ///
+  /// for track in stream.cue_tracks::<i16>().with_deemphasis(true) {
+  ///   let is_filtered = track.is_pre_emphasis();
+  ///   let samples     = track.collect::<Vec<_>>();
+  /// }
+  /// ```
+  pub fn with_deemphasis(mut self, is_deemphasis: bool) -> Self {
+    self.is_deemphasis = is_deemphasis;
+
+    self
+  }
+}
+
+impl<'a, P, S> Iterator for CueTracks<'a, P, S>
+ where P: StreamProducer,
+       S: Sample {
+  type Item = Track<'a, P, S>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    self.ranges.next().map(|(number, range)| {
+      let is_pre_emphasis = self.emphasized.contains(&number);
+      let deemphasis      = if is_pre_emphasis && self.is_deemphasis {
+        Some(Deemphasis::new(&self.decoder.borrow().blocks.info()))
+      } else {
+        None
+      };
+
+      Track {
+        number: number,
+        range: range,
+        is_pre_emphasis: is_pre_emphasis,
+        decoder: self.decoder.clone(),
+        deemphasis: deemphasis,
+      }
+    })
+  }
+
+  #[inline]
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.ranges.size_hint()
+  }
+}
+
+/// An iterator over the samples of a single track, interleaved by channel.
+///
+/// Iteration stops at the end of the track, at the end of the stream, or at
+/// the first frame that fails to decode, which `Track::error` returns.
+pub struct Track<'a, P, S>
+ where P: 'a + StreamProducer,
+       S: Sample {
+  number: u8,
+  range: Range<u64>,
+  is_pre_emphasis: bool,
+  decoder: Rc<RefCell<Decoder<'a, P, S>>>,
+  deemphasis: Option<Deemphasis>,
+}
+
+impl<'a, P, S> Track<'a, P, S>
+ where P: StreamProducer,
+       S: Sample {
+  /// Returns the track number from the cue sheet.
+  #[inline]
+  pub fn number(&self) -> u8 {
+    self.number
+  }
+
+  /// Returns the sample numbers the track starts and ends at.
+  #[inline]
+  pub fn range(&self) -> Range<u64> {
+    self.range.clone()
+  }
+
+  /// Returns true when the cue sheet flags the track with pre-emphasis.
+  ///
+  /// The samples of the track have the de-emphasis filter applied when
+  /// `CueTracks::with_deemphasis` turned it on, otherwise they still have
+  /// pre-emphasis.
+  #[inline]
+  pub fn is_pre_emphasis(&self) -> bool {
+    self.is_pre_emphasis
+  }
+
+  /// Returns the error that stopped decoding, if any.
+  pub fn error(&self) -> Option<ErrorKind> {
+    self.decoder.borrow().error
+  }
+
+  /// Returns how far decoding has gotten, which puts the byte offset at the
+  /// end of the last block decoded.
+  pub fn decode_progress(&self) -> DecodeProgress {
+    self.decoder.borrow().blocks.decode_progress()
+  }
+}
+
+impl<'a, P, S> Iterator for Track<'a, P, S>
+ where P: StreamProducer,
+       S: Sample,
+       S::Normal: Copy + Into<i64> {
+  type Item = S::Normal;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let sample = self.decoder.borrow_mut().next_sample(&self.range);
+
+    match self.deemphasis {
+      Some(ref mut deemphasis) => sample.map(|sample| {
+        let value = deemphasis.filter(sample.into());
+
+        // Filtered values never get louder than the samples, so they
+        // always fit the sample type.
+        S::to_normal(S::from_i32_lossy(value as i32)).unwrap_or(sample)
+      }),
+      None                     => sample,
+    }
+  }
+}
+
+/// Track about to be exported by `export_tracks`.
+#[derive(Debug)]
+pub struct TrackExport {
+  /// Track number from the cue sheet.
+  pub number: u8,
+  /// Sample numbers, within the source stream, the track starts and ends
+  /// at.
+  pub range: Range<u64>,
+  /// International Standard Recording Code of the track, when the cue
+  /// sheet has one.
+  pub isrc: Option<String>,
+  /// Whether the cue sheet flags the track with pre-emphasis, which the
+  /// exported samples still have.
+  pub is_pre_emphasis: bool,
+  /// Tags embedded into the exported track.
+  ///
+  /// These are the tags of the source stream, with `TRACKNUMBER`,
+  /// `TRACKTOTAL`, and `ISRC` replaced by the ones from the cue sheet.
+  #[cfg(feature = "vorbis-comment")]
+  pub tags: VorbisComment,
+}
+
+#[cfg(feature = "vorbis-comment")]
+impl TrackExport {
+  /// Returns the filename of the track, filled in from its tags, see
+  /// `VorbisComment::format_filename`.
+  #[inline]
+  pub fn filename(&self, template: &str) -> String {
+    self.tags.format_filename(template)
+  }
+}
+
+/// Encodes each track of the stream's cue sheet into a FLAC stream of its
+/// own, returning the writer of each track once it's finished.
+///
+/// `create` gets called with each track before it's decoded, returning the
+/// writer the track gets encoded to along with the options to encode it
+/// with. The track's tags get embedded, unless the options already hold a
+/// `VorbisComment`. The stream is expected to be at its first frame, and
+/// the gap before each track gets handled by `pregap`.
+///
+/// # Failures
+///
+/// * `ErrorKind::NotFound` is returned when the stream has no `CueSheet`.
+/// * `ErrorKind::IO(io::ErrorKind::UnexpectedEof)` is returned when the
+///   stream ends before a track does.
+/// * Any error from decoding a frame, or from `create` and the encoder as
+///   `ErrorKind::IO`. Tracks written before the error are left as is.
+///
+/// # Examples
+///
+/// ```no_run
+/// use flac::{EncoderOptions, StreamReader};
+/// use flac::cue::{self, Pregap};
+///
+/// use std::fs::File;
+///
+/// let mut stream = StreamReader::<File>::from_file("path/to/album.flac")
+///                    .unwrap();
+///
+/// cue::export_tracks(&mut stream, Pregap::Append, |track| {
+///   let file = try!(File::create(format!("{:02}.flac", track.number)));
+///
+///   Ok((file, EncoderOptions::default()))
+/// }).unwrap();
+/// ```
+pub fn export_tracks<P, W, F>(stream: &mut Stream<P>, pregap: Pregap,
+                              create: F)
+                              -> Result<Vec<W>, ErrorKind>
+ where P: StreamProducer,
+       W: Write + Seek,
+       F: FnMut(&TrackExport) -> ::std::io::Result<(W, EncoderOptions)> {
+  let mut create = create;
+  let info       = stream.info();
+  let channels   = cmp::max(info.channels as usize, 1);
+
+  let (ranges, isrcs, emphasized) = {
+    let cue_sheet = try!(stream.metadata().iter().filter_map(|block| {
+      block.as_cue_sheet()
+    }).next().ok_or(ErrorKind::NotFound));
+
+    let ranges = track_ranges(cue_sheet, pregap);
+    let isrcs  = ranges.iter().map(|&(number, _)| {
+      cue_sheet.tracks.iter().find(|track| track.number == number)
+               .and_then(|track| isrc(track))
+    }).collect::<Vec<_>>();
+
+    let emphasized = cue_sheet.tracks.iter()
+                              .filter(|track| track.is_pre_emphasis)
+                              .map(|track| track.number)
+                              .collect::<Vec<_>>();
+
+    (ranges, isrcs, emphasized)
+  };
+
+  #[cfg(feature = "vorbis-comment")]
+  let source = stream.metadata().iter().filter_map(|block| {
+    block.as_vorbis_comment()
+  }).next().map(|comment| {
+    (comment.vendor_string.clone(), comment.comments.clone())
+  });
+
+  let count       = ranges.len();
+  let mut tracks  = CueTracks::new(stream.blocks::<i32>(), ranges);
+  let mut writers = Vec::with_capacity(count);
+
+  while let Some(mut track) = tracks.next() {
+    let isrc = isrcs[writers.len()].clone();
+
+    #[cfg(feature = "vorbis-comment")]
+    let tags = {
+      let mut tags = VorbisComment::new();
+
+      if let Some((ref vendor_string, ref comments)) = source {
+        tags.vendor_string = vendor_string.clone();
+        tags.comments      = comments.iter().filter(|&(name, _)| {
+          let name = name.to_uppercase();
+
+          name != "TRACKNUMBER" && name != "TRACKTOTAL" && name != "ISRC"
+        }).map(|(name, value)| (name.clone(), value.clone())).collect();
+      }
+
+      tags = tags.with_comment("TRACKNUMBER", &track.number().to_string())
+                 .with_comment("TRACKTOTAL", &count.to_string());
+
+      match isrc {
+        Some(ref isrc) => tags.with_comment("ISRC", isrc),
+        None           => tags,
+      }
+    };
+
+    let export = TrackExport {
+      number: track.number(),
+      range: track.range(),
+      isrc: isrc,
+      is_pre_emphasis: emphasized.contains(&track.number()),
+      #[cfg(feature = "vorbis-comment")]
+      tags: tags,
+    };
+
+    let (writer, options) = try!(create(&export).map_err(to_error));
+
+    #[cfg(feature = "vorbis-comment")]
+    let options = embed_tags(options, export.tags);
+
+    let mut track_info = info;
+
+    track_info.total_samples = export.range.end - export.range.start;
+
+    let mut encoder = try!(Encoder::new(writer, track_info, options)
+                             .map_err(to_error));
+    let mut samples = Vec::with_capacity(EXPORT_CHUNK_SIZE * channels);
+    let mut written = 0;
+
+    loop {
+      samples.clear();
+      samples.extend(track.by_ref().take(EXPORT_CHUNK_SIZE * channels));
+
+      if samples.is_empty() {
+        break;
+      }
+
+      try!(encoder.write(&samples).map_err(to_error));
+
+      written += samples.len() as u64;
+    }
+
+    if let Some(error) = track.error() {
+      return Err(error);
+    }
+
+    // The stream ran out before the end of the track, without any frame
+    // failing to decode.
+    if written != track_info.total_samples * channels as u64 {
+      return Err(ErrorKind::IO(io::ErrorKind::UnexpectedEof));
+    }
+
+    writers.push(try!(encoder.finish().map_err(to_error)));
+  }
+
+  Ok(writers)
+}
+
+/// Exports each track of the stream's cue sheet into a file of its own
+/// within `directory`, returning the path of each file.
+///
+/// Each file gets named by filling in `template` with the tags of its
+/// track, see `VorbisComment::format_filename`, and any directory the
+/// template names gets created. `options` gets called with each track for
+/// the options to encode it with, otherwise this works the same as
+/// `export_tracks`.
+///
+/// # Failures
+///
+/// Fails in the same way as `export_tracks` does, along with
+/// `ErrorKind::IO` for a file or directory that can't be created.
+///
+/// # Examples
+///
+/// ```no_run
+/// use flac::{EncoderOptions, StreamReader};
+/// use flac::cue::{self, Pregap};
+///
+/// use std::fs::File;
+/// use std::path::Path;
+///
+/// let mut stream = StreamReader::<File>::from_file("path/to/album.flac")
+///                    .unwrap();
+///
+/// let paths = cue::export_files(&mut stream, Pregap::Append,
+///                               Path::new("path/to/tracks"),
+///                               "{tracknumber:02} - {title}.flac",
+///                               |_| EncoderOptions::default()).unwrap();
+/// ```
+#[cfg(feature = "vorbis-comment")]
+pub fn export_files<P, F>(stream: &mut Stream<P>, pregap: Pregap,
+                          directory: &Path, template: &str, options: F)
+                          -> Result<Vec<PathBuf>, ErrorKind>
+ where P: StreamProducer,
+       F: FnMut(&TrackExport) -> EncoderOptions {
+  let mut options = options;
+  let mut paths   = Vec::new();
+
+  try!(export_tracks(stream, pregap, |track| {
+    let path = directory.join(track.filename(template));
+
+    if let Some(parent) = path.parent() {
+      try!(fs::create_dir_all(parent));
+    }
+
+    let file = try!(File::create(&path));
+
+    paths.push(path);
+
+    Ok((file, options(track)))
+  }));
+
+  Ok(paths)
+}
+
+// Adds the tags to the metadata, unless there's a `VorbisComment` already.
+#[cfg(feature = "vorbis-comment")]
+fn embed_tags(options: EncoderOptions, tags: VorbisComment)
+              -> EncoderOptions {
+  let mut options = options;
+
+  let has_tags = options.metadata.iter().any(|data| {
+    match *data {
+      Data::VorbisComment(_) => true,
+      _                      => false,
+    }
+  });
+
+  if !has_tags {
+    options.metadata.push(Data::VorbisComment(tags));
+  }
+
+  options
+}
+
+// Returns the ISRC of the track, leaving out one that's only padding.
+fn isrc(track: &CueSheetTrack) -> Option<String> {
+  let isrc = track.isrc.split('\0').next().unwrap_or("");
+
+  if isrc.is_empty() { None } else { Some(isrc.to_owned()) }
+}
+
+fn to_error(error: ::std::io::Error) -> ErrorKind {
+  ErrorKind::IO(error.kind())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use metadata::CueSheetTrackIndex;
+  use utility::{BitsPerSample, SampleRate};
+
+  fn track(offset: u64, number: u8, indices: &[(u64, u8)])
+           -> CueSheetTrack {
+    CueSheetTrack {
+      offset: offset,
+      number: number,
+      isrc: String::new(),
+      is_audio: true,
+      is_pre_emphasis: false,
+      indices: indices.iter().map(|&(offset, number)| {
+        CueSheetTrackIndex {
+          offset: offset,
+          number: number,
+        }
+      }).collect(),
+    }
+  }
+
+  #[test]
+  fn test_track_ranges() {
+    let cue_sheet = CueSheet {
+      media_catalog_number: String::new(),
+      lead_in: 88200,
+      is_cd: true,
+      tracks: vec![
+        track(0, 1, &[(0, 0), (588, 1)]),
+        track(5880, 2, &[(0, 1)]),
+        track(11760, 3, &[(0, 0), (1176, 1), (2352, 2)]),
+        track(17640, 170, &[]),
+      ],
+    };
+
+    assert_eq!(track_ranges(&cue_sheet, Pregap::Append),
+               vec![(1, 588..5880), (2, 5880..12936), (3, 12936..17640)]);
+    assert_eq!(track_ranges(&cue_sheet, Pregap::Prepend),
+               vec![(1, 0..5880), (2, 5880..11760), (3, 11760..17640)]);
+    assert_eq!(track_ranges(&cue_sheet, Pregap::Skip),
+               vec![(1, 588..5880), (2, 5880..11760), (3, 12936..17640)]);
+
+    let lead_out = CueSheet {
+      media_catalog_number: String::new(),
+      lead_in: 0,
+      is_cd: false,
+      tracks: vec![track(0, 170, &[])],
+    };
+
+    assert!(track_ranges(&lead_out, Pregap::default()).is_empty());
+  }
+
+  #[test]
+  fn test_deemphasis() {
+    let mut info = StreamInfo::default();
+
+    info.sample_rate     = SampleRate::new(44100).unwrap();
+    info.channels        = 2;
+    info.bits_per_sample = BitsPerSample::new(16).unwrap();
+
+    // Steady levels pass through untouched on each channel.
+    let mut deemphasis = Deemphasis::new(&info);
+    let dc             = (0..200).map(|_| {
+      (deemphasis.filter(1000), deemphasis.filter(-3000))
+    }).last();
+
+    assert_eq!(dc, Some((1000, -3000)));
+
+    // The highest frequency comes out at 15/50 of its level.
+    info.channels = 1;
+
+    let mut deemphasis = Deemphasis::new(&info);
+    let nyquist        = (0..400).map(|i| {
+      deemphasis.filter(if i % 2 == 0 { 10000 } else { -10000 })
+    }).last().unwrap();
+
+    assert!((nyquist + 3000).abs() <= 1, "{} at nyquist", nyquist);
+
+    // The filter starts out settled on the first sample.
+    let mut deemphasis = Deemphasis::new(&info);
+
+    assert_eq!(deemphasis.filter(5000), 5000);
+  }
+}
@@ -0,0 +1,233 @@
+//! Hashing used for checking decoded audio against the MD5 signature
+//! within `StreamInfo`.
+//!
+//! Any hash implementation can be plugged in through `Digest`, so there is
+//! no hashing crate that has to be pulled in. A small MD5 implementation,
+//! `Md5`, comes with the `md5` feature, which is on by default.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use flac::StreamReader;
+//! use flac::digest::Digest;
+//!
+//! use std::fs::File;
+//!
+//! // Counts the bytes instead of hashing them, with the count in place of
+//! // the signature.
+//! struct Counter(u64);
+//!
+//! impl Digest for Counter {
+//!   fn update(&mut self, bytes: &[u8]) {
+//!     self.0 += bytes.len() as u64;
+//!   }
+//!
+//!   fn finish(self) -> [u8; 16] {
+//!     let mut signature = [0; 16];
+//!
+//!     for i in 0..8 {
+//!       signature[i] = (self.0 >> (i * 8)) as u8;
+//!     }
+//!
+//!     signature
+//!   }
+//! }
+//!
+//! let mut stream = StreamReader::<File>::from_file("path/to/file.flac")
+//!                    .unwrap();
+//!
+//! let signature = stream.md5_sum_with(Counter(0)).unwrap();
+//! ```
+
+/// A hash producing a 16 byte signature from bytes fed to it a piece at a
+/// time.
+pub trait Digest {
+  /// Feeds more bytes into the hash.
+  fn update(&mut self, bytes: &[u8]);
+
+  /// Returns the signature of every byte fed into the hash.
+  fn finish(self) -> [u8; 16];
+}
+
+#[cfg(feature = "md5")]
+pub use self::md5::Md5;
+
+#[cfg(feature = "md5")]
+mod md5 {
+  use super::Digest;
+
+  // Number of bits each word gets rotated by within each round.
+  const SHIFTS: [u32; 64] = [
+    7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22,
+    5,  9, 14, 20, 5,  9, 14, 20, 5,  9, 14, 20, 5,  9, 14, 20,
+    4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23,
+    6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+  ];
+
+  // Integer part of the sines of 1 through 64, scaled by 2^32.
+  const CONSTANTS: [u32; 64] = [
+    0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee,
+    0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+    0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be,
+    0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+    0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa,
+    0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+    0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed,
+    0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+    0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c,
+    0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+    0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05,
+    0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+    0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039,
+    0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+    0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1,
+    0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
+  ];
+
+  /// MD5 hash, which is what the signature within `StreamInfo` uses.
+  #[derive(Clone)]
+  pub struct Md5 {
+    state: [u32; 4],
+    block: [u8; 64],
+    length: usize,
+    total: u64,
+  }
+
+  impl Md5 {
+    /// Constructs a hash that hasn't been fed any bytes.
+    pub fn new() -> Self {
+      Md5 {
+        state: [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476],
+        block: [0; 64],
+        length: 0,
+        total: 0,
+      }
+    }
+
+    // Mixes a full 64 byte block into the state.
+    fn compress(&mut self) {
+      let mut words = [0u32; 16];
+
+      for (i, word) in words.iter_mut().enumerate() {
+        let bytes = &self.block[(i * 4)..(i * 4 + 4)];
+
+        *word = (bytes[0] as u32)         | ((bytes[1] as u32) << 8) |
+                ((bytes[2] as u32) << 16) | ((bytes[3] as u32) << 24);
+      }
+
+      let mut a = self.state[0];
+      let mut b = self.state[1];
+      let mut c = self.state[2];
+      let mut d = self.state[3];
+
+      for i in 0..64 {
+        let (f, g) = match i / 16 {
+          0 => ((b & c) | (!b & d), i),
+          1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+          2 => (b ^ c ^ d,          (3 * i + 5) % 16),
+          _ => (c ^ (b | !d),       (7 * i) % 16),
+        };
+
+        let sum = a.wrapping_add(f)
+                   .wrapping_add(CONSTANTS[i])
+                   .wrapping_add(words[g]);
+
+        a = d;
+        d = c;
+        c = b;
+        b = b.wrapping_add(sum.rotate_left(SHIFTS[i]));
+      }
+
+      self.state[0] = self.state[0].wrapping_add(a);
+      self.state[1] = self.state[1].wrapping_add(b);
+      self.state[2] = self.state[2].wrapping_add(c);
+      self.state[3] = self.state[3].wrapping_add(d);
+    }
+  }
+
+  impl Default for Md5 {
+    #[inline]
+    fn default() -> Self {
+      Md5::new()
+    }
+  }
+
+  impl Digest for Md5 {
+    fn update(&mut self, bytes: &[u8]) {
+      self.total = self.total.wrapping_add(bytes.len() as u64);
+
+      for &byte in bytes {
+        self.block[self.length] = byte;
+        self.length            += 1;
+
+        if self.length == 64 {
+          self.compress();
+
+          self.length = 0;
+        }
+      }
+    }
+
+    fn finish(mut self) -> [u8; 16] {
+      let bits = self.total.wrapping_mul(8);
+
+      self.update(&[0x80]);
+
+      while self.length != 56 {
+        self.update(&[0]);
+      }
+
+      let mut length = [0; 8];
+
+      for (i, byte) in length.iter_mut().enumerate() {
+        *byte = (bits >> (i * 8)) as u8;
+      }
+
+      self.update(&length);
+
+      let mut signature = [0; 16];
+
+      for (i, word) in self.state.iter().enumerate() {
+        for j in 0..4 {
+          signature[i * 4 + j] = (word >> (j * 8)) as u8;
+        }
+      }
+
+      signature
+    }
+  }
+
+  #[cfg(test)]
+  mod tests {
+    use super::*;
+    use digest::Digest;
+
+    fn md5(bytes: &[u8]) -> [u8; 16] {
+      let mut hash = Md5::new();
+
+      hash.update(bytes);
+
+      hash.finish()
+    }
+
+    #[test]
+    fn test_md5() {
+      assert_eq!(md5(b""), [ 0xd4, 0x1d, 0x8c, 0xd9, 0x8f, 0x00, 0xb2, 0x04
+                           , 0xe9, 0x80, 0x09, 0x98, 0xec, 0xf8, 0x42, 0x7e
+                           ]);
+      assert_eq!(md5(b"abc"), [ 0x90, 0x01, 0x50, 0x98, 0x3c, 0xd2, 0x4f, 0xb0
+                              , 0xd6, 0x96, 0x3f, 0x7d, 0x28, 0xe1, 0x7f, 0x72
+                              ]);
+
+      let bytes = (0..1000).map(|i| (i * 31) as u8).collect::<Vec<_>>();
+
+      let mut hash = Md5::new();
+
+      for chunk in bytes.chunks(37) {
+        hash.update(chunk);
+      }
+
+      assert_eq!(hash.finish(), md5(&bytes));
+    }
+  }
+}
@@ -0,0 +1,1632 @@
+//! Encoding of audio samples into a FLAC stream.
+//!
+//! # Examples
+//!
+//! ```
+//! use flac::{
+//!   BitsPerSample, Encoder, EncoderOptions, SampleRate, StreamBuffer,
+//! };
+//! use flac::metadata::StreamInfo;
+//!
+//! use std::io::Cursor;
+//!
+//! let mut info = StreamInfo::default();
+//!
+//! info.sample_rate     = SampleRate::new(44100).unwrap();
+//! info.channels        = 2;
+//! info.bits_per_sample = BitsPerSample::new(16).unwrap();
+//!
+//! let samples = [0, 0, 100, -100, 200, -200, 300, -300];
+//!
+//! let mut encoder = Encoder::new(Cursor::new(Vec::new()), info,
+//!                                EncoderOptions::default()).unwrap();
+//!
+//! encoder.write(&samples).unwrap();
+//!
+//! let bytes      = encoder.finish().unwrap().into_inner();
+//! let mut stream = StreamBuffer::from_buffer(&bytes).unwrap();
+//!
+//! assert_eq!(stream.info().total_samples, 4);
+//! assert!(stream.iter::<i32>().eq(samples.iter().cloned()));
+//! ```
+
+use frame::{self, ChannelAssignment, NumberType, Header, MAX_CHANNELS};
+use metadata::{self, Data, Metadata, SeekPoint, StreamInfo, Type};
+#[cfg(feature = "vorbis-comment")]
+use metadata::VorbisComment;
+#[cfg(feature = "md5")]
+use digest::{Digest, Md5};
+#[cfg(feature = "md5")]
+use pcm::{PcmFormat, sample_width};
+#[cfg(feature = "md5")]
+use stream::StreamBuffer;
+use subframe::MAX_SUBFRAME_BITS;
+#[cfg(feature = "vorbis-comment")]
+use utility::ErrorKind;
+
+pub use subframe::{
+  encode_residual, rice_parameter, search_partitions, RiceCoding,
+};
+
+use std::cmp;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Cursor, Seek, SeekFrom, Write};
+#[cfg(feature = "vorbis-comment")]
+use std::io::Read;
+use std::mem;
+
+/// Vendor string written into the `VorbisComment` of encoded streams by
+/// default, naming this crate and its version.
+pub const DEFAULT_VENDOR: &'static str = concat!("flac-rs ",
+                                                 env!("CARGO_PKG_VERSION"));
+
+/// Name of the comment holding the encoder settings, when
+/// `EncoderOptions::is_settings_tagged` is set.
+pub const SETTINGS_COMMENT: &'static str = "ENCODER_OPTIONS";
+
+// Sample number used by placeholder seek points.
+const PLACEHOLDER: u64 = 0xffffffffffffffff;
+
+// Largest frame size, in bytes, that fits within `StreamInfo`.
+const MAX_FRAME_SIZE: u32 = 0xffffff;
+
+// Largest frame header, in bytes, with the longest frame number and the
+// block size and sample rate both stored at the end of the header.
+const MAX_FRAME_HEADER_SIZE: u64 = 16;
+
+// Most bytes `Encoder::in_memory` reserves up front. The total samples can
+// claim a stream far larger than what actually gets written, so past this
+// the buffer grows as frames get written.
+const MAX_RESERVED_SIZE: u64 = 64 * 1024 * 1024;
+
+/// How the channel assignment of each frame gets picked for stereo
+/// streams.
+///
+/// Storing the difference between the channels, instead of both channels
+/// as is, is one of the larger gains in compression for most music. Streams
+/// that aren't stereo, or that have 32 bits per sample, always use
+/// independent channels since the side channel would need an extra bit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StereoMode {
+  /// Always stores the left and right channels as is.
+  Independent,
+  /// Picks the channel assignment from an estimate of how well each one
+  /// compresses, which costs about the same as encoding a single frame.
+  Estimate,
+  /// Encodes every frame with each of the four channel assignments and
+  /// keeps the smallest, which is around four times slower.
+  Exhaustive,
+}
+
+impl Default for StereoMode {
+  fn default() -> Self {
+    StereoMode::Estimate
+  }
+}
+
+/// Spacing between the points within a generated seek table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SeekPointInterval {
+  /// A seek point every given number of samples, per channel.
+  Samples(u64),
+  /// A seek point every given number of seconds, going by the sample rate
+  /// of the stream.
+  Seconds(u64),
+}
+
+impl SeekPointInterval {
+  // Number of samples between each seek point at the given sample rate.
+  fn to_samples(&self, sample_rate: u32) -> u64 {
+    match *self {
+      SeekPointInterval::Samples(samples) => samples,
+      SeekPointInterval::Seconds(seconds) => {
+        seconds.saturating_mul(sample_rate as u64)
+      }
+    }
+  }
+}
+
+/// Reason samples given to the encoder were turned away.
+///
+/// Returned as the inner error of an `io::ErrorKind::InvalidInput` error,
+/// which `io::Error::get_ref` gives access to. Samples that get turned away
+/// leave the encoder as it was, so encoding is able to carry on with the
+/// next samples.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputError {
+  /// The number of samples isn't a multiple of the number of channels.
+  ChannelMismatch {
+    /// Number of samples given.
+    samples: usize,
+    /// Number of channels the stream was constructed with.
+    channels: usize,
+  },
+  /// A sample doesn't fit within the bits per sample of the stream.
+  SampleOutOfRange {
+    /// Index of the sample within the samples given.
+    index: usize,
+    /// Value of the sample.
+    sample: i32,
+    /// Bits per sample the stream was constructed with.
+    bits_per_sample: u8,
+  },
+  /// A block given to `Encoder::write_block` holds more samples, per
+  /// channel, than the block size.
+  BlockSizeMismatch {
+    /// Number of samples, per channel, given.
+    block_size: usize,
+    /// Block size of the stream.
+    expected: usize,
+  },
+  /// A block was given to `Encoder::write_block` while samples from
+  /// `Encoder::write` were still waiting on a full frame.
+  PartialFrame {
+    /// Number of samples, per channel, waiting on a full frame.
+    held: usize,
+  },
+  /// Samples were given after a block shorter than the block size, which
+  /// is only allowed to be the last block.
+  AfterShortBlock,
+}
+
+impl fmt::Display for InputError {
+  fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      InputError::ChannelMismatch { samples, channels } => {
+        write!(formatter, "{} samples aren't a multiple of {} channels",
+               samples, channels)
+      }
+      InputError::SampleOutOfRange { index, sample, bits_per_sample } => {
+        write!(formatter, "sample {} at index {} doesn't fit within {} bits",
+               sample, index, bits_per_sample)
+      }
+      InputError::BlockSizeMismatch { block_size, expected } => {
+        write!(formatter, "block of {} samples is larger than {}",
+               block_size, expected)
+      }
+      InputError::PartialFrame { held } => {
+        write!(formatter, "block given with {} samples still held", held)
+      }
+      InputError::AfterShortBlock => {
+        write!(formatter, "samples given after the last, short, block")
+      }
+    }
+  }
+}
+
+impl Error for InputError {
+  fn description(&self) -> &str {
+    match *self {
+      InputError::ChannelMismatch { .. }   => "channel count mismatch",
+      InputError::SampleOutOfRange { .. }  => "sample out of range",
+      InputError::BlockSizeMismatch { .. } => "block size mismatch",
+      InputError::PartialFrame { .. }      => "partial frame held",
+      InputError::AfterShortBlock          => "samples after short block",
+    }
+  }
+}
+
+impl From<InputError> for io::Error {
+  fn from(error: InputError) -> Self {
+    io::Error::new(io::ErrorKind::InvalidInput, error)
+  }
+}
+
+/// Options for how the stream gets encoded and which metadata gets
+/// embedded.
+///
+/// Samples given to the encoder need to be interleaved by the number of
+/// channels within `StreamInfo` and fit within its bits per sample, or
+/// they get turned away with an `InputError`.
+#[derive(Debug)]
+pub struct EncoderOptions {
+  /// Number of samples, per channel, within each frame.
+  ///
+  /// Only the last frame is allowed to be shorter, which `Encoder::finish`
+  /// writes out from whatever samples are left.
+  pub block_size: u16,
+  /// Number of bytes of padding placed after all other metadata, which
+  /// leaves room for editing tags later without rewriting the file. Zero
+  /// leaves out the padding block.
+  pub padding: u32,
+  /// Additional metadata blocks to embed, like `VorbisComment` and
+  /// `Picture`.
+  ///
+  /// The blocks are written in the order of seek table, vorbis comment,
+  /// cue sheet, application, picture, unknown, then padding. Blocks of the
+  /// same type keep the order they were given in.
+  pub metadata: Vec<Data>,
+  /// Spacing between each point within a generated seek table, either in
+  /// samples or in seconds.
+  ///
+  /// Requires `StreamInfo::total_samples` to be set, since the seek table
+  /// gets reserved before any audio is written and filled in by
+  /// `Encoder::finish`. `None` leaves out the seek table.
+  pub seek_point_interval: Option<SeekPointInterval>,
+  /// How the channel assignment of each frame gets picked for stereo
+  /// streams.
+  pub stereo_mode: StereoMode,
+  /// Size each frame gets padded toward, as a fraction of the largest frame
+  /// written so far, which smooths out the bitrate when streaming at the
+  /// cost of compression.
+  ///
+  /// Frames shorter than the block size are padded toward the same
+  /// fraction of their share. Needs to be above zero and at most one, with
+  /// `None` leaving each frame as small as it gets. See
+  /// `frame::encode_padded` for how frames get padded.
+  pub frame_size_floor: Option<f32>,
+  /// Vendor string filled into any `VorbisComment` that doesn't have one,
+  /// defaulting to `DEFAULT_VENDOR`. `None` leaves it empty.
+  ///
+  /// Only used with the `vorbis-comment` feature, and no `VorbisComment`
+  /// gets added just for the vendor string.
+  pub vendor_string: Option<String>,
+  /// Adds the settings that affect how the audio gets encoded, like the
+  /// block size, as a comment named `SETTINGS_COMMENT`.
+  ///
+  /// Only used with the `vorbis-comment` feature, where an empty
+  /// `VorbisComment` gets added for it when there isn't one.
+  pub is_settings_tagged: bool,
+}
+
+impl EncoderOptions {
+  /// Returns the settings that affect how the audio gets encoded, in the
+  /// form written out by `is_settings_tagged`.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use flac::EncoderOptions;
+  ///
+  /// let options = EncoderOptions::default();
+  ///
+  /// assert_eq!(options.settings(), "block_size=4096 stereo_mode=Estimate");
+  /// ```
+  pub fn settings(&self) -> String {
+    let mut settings = format!("block_size={} stereo_mode={:?}",
+                               self.block_size, self.stereo_mode);
+
+    if let Some(floor) = self.frame_size_floor {
+      settings.push_str(&format!(" frame_size_floor={}", floor));
+    }
+
+    settings
+  }
+
+  /// Adds the tags from the `LIST` `INFO` chunk of the WAVE file read from
+  /// `reader`, for carrying them over when encoding from WAVE.
+  ///
+  /// The tags get mapped to vorbis comments as described by
+  /// `metadata::read_riff_info` and added to the `VorbisComment` within
+  /// `metadata`, with one added when there isn't any. Comments already
+  /// there win over the ones from the file.
+  ///
+  /// # Failures
+  ///
+  /// Fails in the same way as `metadata::read_riff_info` does, leaving the
+  /// metadata as it was.
+  ///
+  /// # Examples
+  ///
+  /// ```no_run
+  /// use flac::EncoderOptions;
+  ///
+  /// use std::fs::File;
+  ///
+  /// let mut options = EncoderOptions::default();
+  ///
+  /// options.embed_riff_info(File::open("path/to/input.wav").unwrap())
+  ///        .unwrap();
+  /// ```
+  #[cfg(feature = "vorbis-comment")]
+  pub fn embed_riff_info<R: Read>(&mut self, reader: R)
+                                  -> Result<(), ErrorKind> {
+    let tags = try!(metadata::read_riff_info(reader));
+
+    let has_comment = self.metadata.iter().any(|data| {
+      if let Data::VorbisComment(_) = *data { true } else { false }
+    });
+
+    if !has_comment {
+      self.metadata.push(Data::VorbisComment(VorbisComment::new()));
+    }
+
+    for data in self.metadata.iter_mut() {
+      if let Data::VorbisComment(ref mut comment) = *data {
+        for (name, value) in &tags.comments {
+          let is_tagged = comment.comments.keys().any(|key| {
+            key.eq_ignore_ascii_case(name)
+          });
+
+          if !is_tagged {
+            comment.comments.insert(name.clone(), value.clone());
+          }
+        }
+
+        break;
+      }
+    }
+
+    Ok(())
+  }
+}
+
+impl Default for EncoderOptions {
+  fn default() -> Self {
+    EncoderOptions {
+      block_size: 4096,
+      padding: 8192,
+      metadata: Vec::new(),
+      seek_point_interval: None,
+      stereo_mode: StereoMode::default(),
+      frame_size_floor: None,
+      vendor_string: Some(DEFAULT_VENDOR.to_owned()),
+      is_settings_tagged: false,
+    }
+  }
+}
+
+/// Bookkeeping for the fields of `StreamInfo` that are only known once
+/// every frame has been written.
+///
+/// Each frame written gets passed to `add_frame`, which is enough for
+/// working out the block and frame size limits along with the total
+/// samples. Following the format, the minimum block size leaves out the
+/// last frame, unless it's the only frame, since the last frame is allowed
+/// to be shorter.
+///
+/// # Examples
+///
+/// ```
+/// use flac::StreamInfoAccumulator;
+/// use flac::metadata::StreamInfo;
+///
+/// let mut accumulator = StreamInfoAccumulator::new();
+/// let mut info        = StreamInfo::default();
+///
+/// accumulator.add_frame(4096, 3211);
+/// accumulator.add_frame(4096, 2810);
+/// accumulator.add_frame(1024, 502);
+/// accumulator.apply(&mut info);
+///
+/// assert_eq!((info.min_block_size, info.max_block_size), (4096, 4096));
+/// assert_eq!((info.min_frame_size, info.max_frame_size), (502, 3211));
+/// assert_eq!(info.total_samples, 9216);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StreamInfoAccumulator {
+  frames: u64,
+  min_block_size: u16,
+  max_block_size: u16,
+  last_block_size: u16,
+  min_frame_size: u32,
+  max_frame_size: u32,
+  total_samples: u64,
+}
+
+impl StreamInfoAccumulator {
+  /// Constructs an accumulator that hasn't seen any frames.
+  #[inline]
+  pub fn new() -> Self {
+    Default::default()
+  }
+
+  /// Records a frame with the given block size, in samples per channel, and
+  /// size, in bytes, of the whole frame.
+  ///
+  /// Frame sizes too large for `StreamInfo` are capped at the largest size
+  /// it can hold.
+  pub fn add_frame(&mut self, block_size: u16, frame_size: usize) {
+    let frame_size = cmp::min(frame_size, MAX_FRAME_SIZE as usize) as u32;
+
+    if self.frames == 0 {
+      self.min_block_size = block_size;
+      self.min_frame_size = frame_size;
+    } else {
+      // The block before this one is no longer the last.
+      self.min_block_size = if self.frames == 1 {
+        self.last_block_size
+      } else {
+        cmp::min(self.min_block_size, self.last_block_size)
+      };
+      self.min_frame_size = cmp::min(self.min_frame_size, frame_size);
+    }
+
+    self.max_block_size  = cmp::max(self.max_block_size, block_size);
+    self.max_frame_size  = cmp::max(self.max_frame_size, frame_size);
+    self.last_block_size = block_size;
+    self.frames         += 1;
+    self.total_samples  += block_size as u64;
+  }
+
+  /// Returns the number of frames seen so far.
+  #[inline]
+  pub fn frames(&self) -> u64 {
+    self.frames
+  }
+
+  /// Returns the number of samples, per channel, within the frames seen so
+  /// far.
+  #[inline]
+  pub fn total_samples(&self) -> u64 {
+    self.total_samples
+  }
+
+  /// Fills in the block sizes, frame sizes, and total samples of `info`.
+  ///
+  /// The block sizes are left as is when no frames have been seen, and the
+  /// frame sizes are set to zero, which marks them as unknown.
+  pub fn apply(&self, info: &mut StreamInfo) {
+    if self.frames > 0 {
+      info.min_block_size = self.min_block_size;
+      info.max_block_size = self.max_block_size;
+    }
+
+    info.min_frame_size = self.min_frame_size;
+    info.max_frame_size = self.max_frame_size;
+    info.total_samples  = self.total_samples;
+  }
+}
+
+/// Returns the most bytes the audio frames of a stream could take up once
+/// encoded, which is meant for sizing a buffer ahead of time.
+///
+/// Every frame is assumed to need its largest header and to store each
+/// sample uncoded, with an extra bit for the side channel of stereo
+/// streams, which the encoder never goes over. `None` is returned when the
+/// total samples aren't known, or when the size doesn't fit within 64 bits.
+pub fn max_audio_size(info: &StreamInfo, block_size: u16) -> Option<u64> {
+  if info.total_samples == 0 || block_size == 0 {
+    return None;
+  }
+
+  let channels  = info.channels as u64;
+  let bits      = info.bits_per_sample.get() as u64 + 1;
+  let max_frame = |samples: u64| {
+    samples.checked_mul(bits * channels).map(|subframe_bits| {
+      MAX_FRAME_HEADER_SIZE + channels + (subframe_bits + 7) / 8 + 2
+    })
+  };
+
+  let block_size = block_size as u64;
+  let frames     = info.total_samples / block_size;
+  let remainder  = info.total_samples % block_size;
+  let last       = if remainder > 0 { max_frame(remainder) } else { Some(0) };
+
+  max_frame(block_size)
+    .and_then(|size| size.checked_mul(frames))
+    .and_then(|size| last.and_then(|last| size.checked_add(last)))
+}
+
+/// FLAC encoder that writes a complete stream, metadata included.
+///
+/// All metadata is written when the encoder is constructed, with
+/// `StreamInfo` and the seek table getting filled in once `Encoder::finish`
+/// is called. That is why the writer needs to be seekable. The MD5
+/// signature within `StreamInfo` gets filled in as well with the `md5`
+/// feature enabled, which it is by default, otherwise it's left as all
+/// zeros, which marks it as unknown.
+pub struct Encoder<W: Write + Seek> {
+  writer: W,
+  info: StreamInfo,
+  block_size: usize,
+  channels: Vec<Vec<i64>>,
+  frame_number: u32,
+  start: u64,
+  audio_offset: u64,
+  frame_offset: u64,
+  accumulator: StreamInfoAccumulator,
+  seek_table: Option<(u64, usize)>,
+  seek_point_interval: u64,
+  seek_target: u64,
+  seek_points: Vec<SeekPoint>,
+  stereo_mode: StereoMode,
+  frame_size_floor: Option<f32>,
+  peak_frame_size: usize,
+  is_finished: bool,
+  bytes: Vec<u8>,
+  #[cfg(feature = "md5")]
+  md5: Md5,
+}
+
+fn invalid_input<T>(message: &str) -> io::Result<T> {
+  Err(io::Error::new(io::ErrorKind::InvalidInput, message))
+}
+
+// Rank used for placing metadata blocks in a sane order.
+fn block_order(data_type: Type) -> usize {
+  match data_type {
+    Type::StreamInfo    => 0,
+    Type::SeekTable     => 1,
+    Type::VorbisComment => 2,
+    Type::CueSheet      => 3,
+    Type::Application   => 4,
+    Type::Picture       => 5,
+    Type::Unknown       => 6,
+    Type::Padding       => 7,
+  }
+}
+
+// Wraps block data into `Metadata` with its length filled in.
+fn to_metadata(data: Data) -> Metadata {
+  let block  = Metadata::new(false, 0, data);
+  let length = block.bytes_len() - 4;
+
+  Metadata::new(false, length as u32, block.data)
+}
+
+// Fills in the vendor string and settings of every `VorbisComment`, adding
+// one for the settings when there isn't any.
+#[cfg(feature = "vorbis-comment")]
+fn tag_metadata(metadata: &mut Vec<Data>, vendor_string: Option<String>,
+                settings: Option<String>) {
+  let has_comment = metadata.iter().any(|data| {
+    if let Data::VorbisComment(_) = *data { true } else { false }
+  });
+
+  if settings.is_some() && !has_comment {
+    metadata.push(Data::VorbisComment(VorbisComment::new()));
+  }
+
+  for data in metadata.iter_mut() {
+    if let Data::VorbisComment(ref mut comment) = *data {
+      if let Some(ref vendor_string) = vendor_string {
+        if comment.vendor_string.is_empty() {
+          comment.vendor_string = vendor_string.clone();
+        }
+      }
+
+      if let Some(ref settings) = settings {
+        comment.comments.insert(SETTINGS_COMMENT.to_owned(),
+                                settings.clone());
+      }
+    }
+  }
+}
+
+#[cfg(not(feature = "vorbis-comment"))]
+fn tag_metadata(_: &mut Vec<Data>, _: Option<String>, _: Option<String>) {
+}
+
+// Feeds samples into the MD5 signature, in the same layout
+// `Stream::md5_sum_with` feeds decoded ones.
+#[cfg(feature = "md5")]
+fn hash_samples(md5: &mut Md5, samples: &[i32], bits_per_sample: u8) {
+  let format    = PcmFormat::default();
+  let width     = sample_width(bits_per_sample);
+  let mut bytes = vec![0; samples.len() * width];
+
+  for (sample, output) in samples.iter().zip(bytes.chunks_mut(width)) {
+    format.pack(*sample as i64, output);
+  }
+
+  md5.update(&bytes);
+}
+
+/// Encodes samples into memory and decodes them back, checking that the
+/// MD5 signature of the decoded samples matches both the one written into
+/// `StreamInfo` and the one of the samples given.
+///
+/// This ties the encoder, the decoder, and the MD5 signature together, so
+/// any of them going wrong for a signal makes the check fail. Only
+/// available with the `md5` feature enabled.
+///
+/// # Failures
+///
+/// * Any error `Encoder::new` or `Encoder::write` returns for the stream
+///   information, options, or samples given.
+/// * `io::ErrorKind::InvalidData` is returned when the encoded stream fails
+///   to decode.
+///
+/// # Examples
+///
+/// ```
+/// use flac::{BitsPerSample, EncoderOptions, SampleRate};
+/// use flac::encoder::check_round_trip;
+/// use flac::metadata::StreamInfo;
+///
+/// let mut info = StreamInfo::default();
+///
+/// info.sample_rate     = SampleRate::new(44100).unwrap();
+/// info.channels        = 2;
+/// info.bits_per_sample = BitsPerSample::new(16).unwrap();
+///
+/// let samples = (0..8192).map(|i| (i % 512) - 256).collect::<Vec<_>>();
+///
+/// assert!(check_round_trip(info, EncoderOptions::default(), &samples)
+///           .unwrap());
+/// ```
+#[cfg(feature = "md5")]
+pub fn check_round_trip(info: StreamInfo, options: EncoderOptions,
+                        samples: &[i32]) -> io::Result<bool> {
+  let mut md5     = Md5::new();
+  let mut encoder = try!(Encoder::in_memory(info, options));
+
+  hash_samples(&mut md5, samples, info.bits_per_sample.get());
+
+  try!(encoder.write(samples));
+
+  let bytes    = try!(encoder.finalize());
+  let expected = md5.finish();
+
+  let invalid_data = |kind| {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", kind))
+  };
+
+  let mut stream = try!(StreamBuffer::from_buffer(&bytes)
+                          .map_err(&invalid_data));
+  let written    = stream.info().md5_sum;
+  let decoded    = try!(stream.md5_sum_with(Md5::new())
+                          .map_err(&invalid_data));
+
+  Ok(decoded == written && decoded == expected)
+}
+
+impl<W> Encoder<W> where W: Write + Seek {
+  /// Constructs an encoder that writes the "fLaC" header and all metadata
+  /// to `writer` right away.
+  ///
+  /// Only the sample rate, channels, bits per sample, and total samples are
+  /// used from `info`, where total samples is allowed to be zero when it
+  /// isn't known ahead of time.
+  ///
+  /// # Failures
+  ///
+  /// * `io::ErrorKind::InvalidInput` is returned when the stream
+  ///   information or the options are outside of what FLAC supports.
+  /// * `io::ErrorKind::InvalidInput` is returned when `StreamInfo` is
+  ///   within the extra metadata, or when a seek table is both given and
+  ///   generated.
+  /// * `io::ErrorKind::InvalidInput` is returned when generating a seek
+  ///   table without knowing the total samples.
+  /// * Any error returned from the writer.
+  pub fn new(writer: W, info: StreamInfo, options: EncoderOptions)
+             -> io::Result<Self> {
+    let mut writer  = writer;
+    let mut info    = info;
+    let block_size  = options.block_size;
+    let channels    = info.channels as usize;
+    let interval    = options.seek_point_interval.map_or(0, |interval| {
+      interval.to_samples(info.sample_rate.get())
+    });
+
+    if channels == 0 || channels > MAX_CHANNELS {
+      return invalid_input("channels must be between 1 and 8");
+    }
+
+    if block_size < 16 {
+      return invalid_input("block size must be at least 16");
+    }
+
+    if options.frame_size_floor.map_or(false, |floor| {
+      !(floor > 0.0 && floor <= 1.0)
+    }) {
+      return invalid_input("frame size floor must be above 0 and at most 1");
+    }
+
+    if options.seek_point_interval.is_some() && interval == 0 {
+      return invalid_input("seek point interval must be larger than zero");
+    }
+
+    let settings = if options.is_settings_tagged {
+      Some(options.settings())
+    } else {
+      None
+    };
+
+    let mut metadata = options.metadata;
+
+    tag_metadata(&mut metadata, options.vendor_string, settings);
+
+    let mut blocks = metadata.into_iter().map(to_metadata)
+                             .collect::<Vec<_>>();
+
+    if blocks.iter().any(|block| block.is_stream_info()) {
+      return invalid_input("StreamInfo within the extra metadata");
+    }
+
+    let seek_point_count = if interval > 0 {
+      if info.total_samples == 0 {
+        return invalid_input("seek table needs the total samples");
+      }
+
+      if blocks.iter().any(|block| block.is_seek_table()) {
+        return invalid_input("seek table is both given and generated");
+      }
+
+      let count = (info.total_samples + interval - 1) / interval;
+
+      blocks.push(to_metadata(Data::SeekTable((0..count).map(|_| {
+        SeekPoint {
+          sample_number: PLACEHOLDER,
+          stream_offset: 0,
+          frame_samples: 0,
+        }
+      }).collect())));
+
+      count as usize
+    } else {
+      0
+    };
+
+    if options.padding > 0 {
+      blocks.push(to_metadata(Data::Padding(options.padding)));
+    }
+
+    blocks.sort_by_key(|block| block_order(block.data_type()));
+
+    info.min_block_size = block_size;
+    info.max_block_size = block_size;
+    info.min_frame_size = 0;
+    info.max_frame_size = 0;
+    info.md5_sum        = [0; 16];
+
+    blocks.insert(0, to_metadata(Data::StreamInfo(info)));
+
+    let start = try!(writer.seek(SeekFrom::Current(0)));
+    let bytes = try!(metadata::serialize_blocks(&blocks));
+
+    // Offset of the seek table's data, after "fLaC" and every block before
+    // it along with their headers.
+    let seek_table = if seek_point_count > 0 {
+      let offset = blocks.iter().take_while(|block| !block.is_seek_table())
+                         .fold(4, |result, block| result + block.bytes_len());
+
+      Some((start + offset as u64 + 4, seek_point_count))
+    } else {
+      None
+    };
+
+    try!(writer.write_all(b"fLaC"));
+    try!(writer.write_all(&bytes));
+
+    Ok(Encoder {
+      writer: writer,
+      info: info,
+      block_size: block_size as usize,
+      channels: vec![Vec::with_capacity(block_size as usize); channels],
+      frame_number: 0,
+      start: start,
+      audio_offset: 4 + bytes.len() as u64,
+      frame_offset: 4 + bytes.len() as u64,
+      accumulator: StreamInfoAccumulator::new(),
+      seek_table: seek_table,
+      seek_point_interval: interval,
+      seek_target: 0,
+      seek_points: Vec::with_capacity(seek_point_count),
+      stereo_mode: options.stereo_mode,
+      frame_size_floor: options.frame_size_floor,
+      peak_frame_size: 0,
+      is_finished: false,
+      bytes: Vec::new(),
+      #[cfg(feature = "md5")]
+      md5: Md5::new(),
+    })
+  }
+
+  /// Encodes samples that are interleaved by channel, writing out each
+  /// frame as soon as it fills up.
+  ///
+  /// The samples don't need to line up with the block size, since they are
+  /// held on to until there is enough for a frame.
+  ///
+  /// # Failures
+  ///
+  /// * `io::ErrorKind::InvalidInput` is returned, holding an `InputError`,
+  ///   when the number of samples isn't a multiple of the number of
+  ///   channels, a sample doesn't fit within the bits per sample, or a
+  ///   short block was already given to `Encoder::write_block`. None of the
+  ///   samples get encoded in that case.
+  /// * Any error returned from the writer.
+  pub fn write(&mut self, samples: &[i32]) -> io::Result<()> {
+    try!(self.check_samples(samples));
+
+    self.push_samples(samples)
+  }
+
+  /// Encodes a single block of samples, interleaved by channel, as its own
+  /// frame.
+  ///
+  /// Every block needs to have exactly the block size, per channel, other
+  /// than the last one which is allowed to be shorter. Mixing this with
+  /// `Encoder::write` is fine, as long as it left no partial frame behind.
+  ///
+  /// # Failures
+  ///
+  /// * `io::ErrorKind::InvalidInput` is returned, holding an `InputError`,
+  ///   for the same reasons as `Encoder::write`, along with when the block
+  ///   is larger than the block size or samples from `Encoder::write` are
+  ///   still held.
+  /// * Any error returned from the writer.
+  pub fn write_block(&mut self, samples: &[i32]) -> io::Result<()> {
+    try!(self.check_samples(samples));
+
+    let block_size = samples.len() / self.channels.len();
+
+    if block_size > self.block_size {
+      return Err(io::Error::from(InputError::BlockSizeMismatch {
+        block_size: block_size,
+        expected: self.block_size,
+      }));
+    }
+
+    if !self.channels[0].is_empty() {
+      return Err(io::Error::from(InputError::PartialFrame {
+        held: self.channels[0].len(),
+      }));
+    }
+
+    if block_size == 0 {
+      return Ok(());
+    }
+
+    try!(self.push_samples(samples));
+
+    if block_size < self.block_size {
+      try!(self.write_frame());
+
+      self.is_finished = true;
+    }
+
+    Ok(())
+  }
+
+  // Makes sure the samples are able to be encoded, before any of them are.
+  fn check_samples(&self, samples: &[i32]) -> io::Result<()> {
+    let channels        = self.channels.len();
+    let bits_per_sample = self.info.bits_per_sample.get();
+
+    if self.is_finished && !samples.is_empty() {
+      return Err(io::Error::from(InputError::AfterShortBlock));
+    }
+
+    if samples.len() % channels != 0 {
+      return Err(io::Error::from(InputError::ChannelMismatch {
+        samples: samples.len(),
+        channels: channels,
+      }));
+    }
+
+    let max = (1i64 << (bits_per_sample - 1)) - 1;
+    let min = -max - 1;
+
+    let found = samples.iter().position(|&sample| {
+      (sample as i64) < min || (sample as i64) > max
+    });
+
+    if let Some(index) = found {
+      return Err(io::Error::from(InputError::SampleOutOfRange {
+        index: index,
+        sample: samples[index],
+        bits_per_sample: bits_per_sample,
+      }));
+    }
+
+    Ok(())
+  }
+
+  fn push_samples(&mut self, samples: &[i32]) -> io::Result<()> {
+    let channels = self.channels.len();
+
+    #[cfg(feature = "md5")]
+    hash_samples(&mut self.md5, samples, self.info.bits_per_sample.get());
+
+    for block in samples.chunks(channels) {
+      for (channel, &sample) in self.channels.iter_mut().zip(block) {
+        channel.push(sample as i64);
+      }
+
+      if self.channels[0].len() == self.block_size {
+        try!(self.write_frame());
+      }
+    }
+
+    Ok(())
+  }
+
+  // Encodes the samples held for the current frame and writes it out.
+  fn write_frame(&mut self) -> io::Result<()> {
+    let block_size       = self.channels[0].len();
+    let mut frame_header = Header {
+      block_size: block_size as u32,
+      sample_rate: self.info.sample_rate,
+      channels: self.info.channels,
+      channel_assignment: ChannelAssignment::Independent,
+      bits_per_sample: self.info.bits_per_sample,
+      number: NumberType::Frame(self.frame_number),
+      crc: 0,
+    };
+
+    let buffer = self.channels.iter().fold(Vec::new(), |mut result, channel| {
+      result.extend_from_slice(channel);
+
+      result
+    });
+
+    // The side channel takes a bit more than the others, so decorrelating
+    // is only possible while that still fits within a subframe.
+    let is_stereo = self.channels.len() == 2 &&
+                    usize::from(self.info.bits_per_sample) < MAX_SUBFRAME_BITS;
+
+    let channel_assignments = match self.stereo_mode {
+      StereoMode::Estimate if is_stereo   => {
+        vec![frame::estimate_assignment(&buffer)]
+      }
+      StereoMode::Exhaustive if is_stereo => {
+        vec![ ChannelAssignment::Independent
+            , ChannelAssignment::LeftSide
+            , ChannelAssignment::RightSide
+            , ChannelAssignment::MidpointSide
+            ]
+      }
+      _                                   => {
+        vec![ChannelAssignment::Independent]
+      }
+    };
+
+    self.bytes.clear();
+
+    // Keeps the smallest of the encoded frames.
+    let mut bytes    = Vec::new();
+    let mut smallest = ChannelAssignment::Independent;
+
+    for channel_assignment in channel_assignments {
+      let channels = frame::decorrelate(channel_assignment, &buffer);
+
+      frame_header.channel_assignment = channel_assignment;
+
+      bytes.clear();
+
+      frame::encode(&frame_header, &channels, &mut bytes);
+
+      if self.bytes.is_empty() || bytes.len() < self.bytes.len() {
+        mem::swap(&mut self.bytes, &mut bytes);
+
+        smallest = channel_assignment;
+      }
+    }
+
+    if let Some(floor) = self.frame_size_floor {
+      // Padding is left out of the peak, otherwise each padded frame would
+      // raise the size the next one gets padded toward.
+      self.peak_frame_size = cmp::max(self.peak_frame_size, self.bytes.len());
+
+      let share    = block_size as f64 / self.block_size as f64;
+      let peak     = self.peak_frame_size as f64;
+      let min_size = (peak * floor as f64 * share).ceil() as usize;
+
+      if self.bytes.len() < min_size {
+        let channels = frame::decorrelate(smallest, &buffer);
+
+        frame_header.channel_assignment = smallest;
+
+        self.bytes.clear();
+
+        frame::encode_padded(&frame_header, &channels, &mut self.bytes,
+                             min_size);
+      }
+    }
+
+    try!(self.writer.write_all(&self.bytes));
+
+    let sample_start = self.accumulator.total_samples();
+    let sample_end   = sample_start + block_size as u64;
+
+    if let Some((_, count)) = self.seek_table {
+      // Only one seek point per frame, even when the frame holds more than
+      // one of the targeted samples.
+      if self.seek_points.len() < count && self.seek_target < sample_end {
+        self.seek_points.push(SeekPoint {
+          sample_number: sample_start,
+          stream_offset: self.frame_offset - self.audio_offset,
+          frame_samples: block_size as u16,
+        });
+
+        while self.seek_target < sample_end {
+          self.seek_target += self.seek_point_interval;
+        }
+      }
+    }
+
+    self.accumulator.add_frame(block_size as u16, self.bytes.len());
+
+    self.frame_number += 1;
+    self.frame_offset += self.bytes.len() as u64;
+
+    for channel in &mut self.channels {
+      channel.clear();
+    }
+
+    Ok(())
+  }
+
+  /// Writes out the last frame and fills in `StreamInfo` and the seek
+  /// table, returning the writer positioned at the end of the stream.
+  ///
+  /// The MD5 signature of every sample written gets filled in with the
+  /// `md5` feature enabled, otherwise it's left as all zeros.
+  ///
+  /// Fails when there is an error returned from the writer.
+  pub fn finish(mut self) -> io::Result<W> {
+    if !self.channels[0].is_empty() {
+      try!(self.write_frame());
+    }
+
+    self.accumulator.apply(&mut self.info);
+
+    #[cfg(feature = "md5")]
+    {
+      let md5 = mem::replace(&mut self.md5, Md5::new());
+
+      self.info.md5_sum = md5.finish();
+    }
+
+    try!(self.writer.seek(SeekFrom::Start(self.start + 8)));
+    try!(self.info.to_bytes(&mut self.writer));
+
+    if let Some((offset, count)) = self.seek_table {
+      try!(self.writer.seek(SeekFrom::Start(offset)));
+
+      for point in &self.seek_points {
+        try!(point.to_bytes(&mut self.writer));
+      }
+
+      for _ in self.seek_points.len()..count {
+        let placeholder = SeekPoint {
+          sample_number: PLACEHOLDER,
+          stream_offset: 0,
+          frame_samples: 0,
+        };
+
+        try!(placeholder.to_bytes(&mut self.writer));
+      }
+    }
+
+    try!(self.writer.seek(SeekFrom::Start(self.start + self.frame_offset)));
+
+    Ok(self.writer)
+  }
+}
+
+impl Encoder<Cursor<Vec<u8>>> {
+  /// Constructs an encoder that writes the stream into memory.
+  ///
+  /// When the total samples are known ahead of time, enough memory for the
+  /// largest the stream could be gets reserved up front, up to 64MiB, so
+  /// the buffer rarely needs to grow while encoding.
+  ///
+  /// # Failures
+  ///
+  /// Fails in the same ways as `Encoder::new`.
+  pub fn in_memory(info: StreamInfo, options: EncoderOptions)
+                   -> io::Result<Self> {
+    let audio_size  = max_audio_size(&info, options.block_size);
+    let mut encoder = try!(Encoder::new(Cursor::new(Vec::new()), info,
+                                        options));
+
+    if let Some(size) = audio_size {
+      let size = cmp::min(size, MAX_RESERVED_SIZE);
+
+      encoder.writer.get_mut().reserve_exact(size as usize);
+    }
+
+    Ok(encoder)
+  }
+
+  /// Writes out the last frame, fills in `StreamInfo` and the seek table,
+  /// and returns the bytes of the whole stream.
+  pub fn finalize(self) -> io::Result<Vec<u8>> {
+    self.finish().map(Cursor::into_inner)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use metadata::{Application, Data, Payload, StreamInfo, Type};
+  use stream::StreamBuffer;
+  use utility::{BitsPerSample, SampleRate};
+
+  use std::io::{self, Cursor, ErrorKind};
+
+  fn stream_info(channels: u8, bits_per_sample: u8) -> StreamInfo {
+    let mut info = StreamInfo::default();
+
+    info.sample_rate     = SampleRate::new(44100).unwrap();
+    info.channels        = channels;
+    info.bits_per_sample = BitsPerSample::new(bits_per_sample).unwrap();
+
+    info
+  }
+
+  fn encode(info: StreamInfo, options: EncoderOptions, samples: &[i32])
+            -> Vec<u8> {
+    let writer      = Cursor::new(Vec::new());
+    let mut encoder = Encoder::new(writer, info, options).unwrap();
+
+    encoder.write(samples).unwrap();
+
+    encoder.finish().unwrap().into_inner()
+  }
+
+  #[test]
+  fn test_encode() {
+    let samples = (0..10000).map(|i| {
+      let sample = ((i as f64 / 30.0).sin() * 30000.0) as i32;
+
+      if i % 2 == 0 { sample } else { -sample / 3 }
+    }).collect::<Vec<_>>();
+
+    let bytes      = encode(stream_info(2, 16), Default::default(), &samples);
+    let mut stream = StreamBuffer::from_buffer(&bytes).unwrap();
+    let info       = stream.info();
+
+    assert!(bytes.len() < samples.len() * 2 + 8192);
+    assert_eq!(info.total_samples, 5000);
+    assert_eq!(info.max_block_size, 4096);
+    assert!(info.min_frame_size > 0);
+    assert!(info.min_frame_size <= info.max_frame_size);
+    assert!(stream.iter::<i16>().eq(samples.iter().map(|&s| s as i16)));
+
+    let samples = [-8, 7, 0, 3, -1];
+    let bytes   = encode(stream_info(1, 4), Default::default(), &samples);
+
+    let mut stream = StreamBuffer::from_buffer(&bytes).unwrap();
+
+    assert!(stream.iter::<i8>().eq(samples.iter().map(|&s| s as i8)));
+  }
+
+  #[test]
+  fn test_full_scale() {
+    let mut seed = 1u64;
+
+    // Alternating extremes, where the side channel and the residual of
+    // each prediction reach as far as they're able to.
+    for &bits_per_sample in &[31, 32] {
+      let max     = ((1i64 << (bits_per_sample - 1)) - 1) as i32;
+      let min     = -max - 1;
+      let samples = (0..8192).map(|i| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+
+        match i % 6 {
+          0 | 1 => max,
+          2 | 3 => min,
+          _     => (seed >> 32) as i32 >> (32 - bits_per_sample),
+        }
+      }).collect::<Vec<_>>();
+
+      for &stereo_mode in &[StereoMode::Independent, StereoMode::Estimate,
+                            StereoMode::Exhaustive] {
+        let options = EncoderOptions {
+          stereo_mode: stereo_mode,
+          ..Default::default()
+        };
+
+        let info       = stream_info(2, bits_per_sample);
+        let bytes      = encode(info, options, &samples);
+        let mut stream = StreamBuffer::from_buffer(&bytes).unwrap();
+
+        assert!(stream.iter::<i32>().eq(samples.iter().cloned()),
+                "{}-bit {:?}", bits_per_sample, stereo_mode);
+      }
+    }
+  }
+
+  #[test]
+  fn test_stream_info_accumulator() {
+    let mut accumulator = StreamInfoAccumulator::new();
+    let mut info        = stream_info(2, 16);
+
+    info.min_block_size = 4096;
+    info.max_block_size = 4096;
+    info.min_frame_size = 10;
+
+    accumulator.apply(&mut info);
+
+    assert_eq!((info.min_block_size, info.max_block_size), (4096, 4096));
+    assert_eq!((info.min_frame_size, info.max_frame_size), (0, 0));
+    assert_eq!(info.total_samples, 0);
+
+    accumulator.add_frame(100, 40);
+    accumulator.apply(&mut info);
+
+    assert_eq!((info.min_block_size, info.max_block_size), (100, 100));
+    assert_eq!((info.min_frame_size, info.max_frame_size), (40, 40));
+
+    accumulator.add_frame(1152, 0x1000000);
+    accumulator.add_frame(576, 300);
+    accumulator.add_frame(16, 20);
+    accumulator.apply(&mut info);
+
+    assert_eq!(accumulator.frames(), 4);
+    assert_eq!((info.min_block_size, info.max_block_size), (100, 1152));
+    assert_eq!((info.min_frame_size, info.max_frame_size),
+               (20, MAX_FRAME_SIZE));
+    assert_eq!(info.total_samples, 1844);
+  }
+
+  #[test]
+  fn test_stereo_mode() {
+    let samples = (0..20000).map(|i| {
+      let sample = ((i / 2) as f64 / 40.0).sin() * 20000.0;
+      let noise  = ((i / 2) * 7919 % 61) as f64;
+
+      if i % 2 == 0 {
+        (sample + noise) as i32
+      } else {
+        (sample * 0.9 - noise) as i32
+      }
+    }).collect::<Vec<_>>();
+
+    let modes = [StereoMode::Independent, StereoMode::Estimate,
+                 StereoMode::Exhaustive];
+
+    let lengths = modes.iter().map(|&stereo_mode| {
+      let options = EncoderOptions {
+        padding: 0,
+        stereo_mode: stereo_mode,
+        ..Default::default()
+      };
+
+      let bytes      = encode(stream_info(2, 16), options, &samples);
+      let mut stream = StreamBuffer::from_buffer(&bytes).unwrap();
+
+      assert!(stream.iter::<i16>().eq(samples.iter().map(|&s| s as i16)));
+
+      bytes.len()
+    }).collect::<Vec<_>>();
+
+    assert!(lengths[1] < lengths[0]);
+    assert!(lengths[2] <= lengths[1]);
+  }
+
+  #[test]
+  fn test_metadata_layout() {
+    let options = EncoderOptions {
+      block_size: 1000,
+      padding: 100,
+      metadata: vec![
+        Data::Unknown(7, Payload::from(vec![1, 2, 3])),
+        Data::Application(Application {
+          id: "test".to_owned(),
+          data: Payload::from(vec![4, 5]),
+        }),
+      ],
+      seek_point_interval: Some(SeekPointInterval::Samples(2500)),
+      ..Default::default()
+    };
+
+    let mut info = stream_info(1, 16);
+
+    info.total_samples = 6000;
+
+    let samples = (0..6000).map(|i| i % 1000 - 500).collect::<Vec<_>>();
+    let bytes   = encode(info, options, &samples);
+    let stream  = StreamBuffer::from_buffer(&bytes).unwrap();
+
+    let types = stream.metadata().iter().map(|block| block.data_type())
+                                 .collect::<Vec<_>>();
+
+    assert!(types == [Type::SeekTable, Type::Application, Type::Unknown,
+                      Type::Padding]);
+
+    if let Data::SeekTable(ref seek_points) = stream.metadata()[0].data {
+      let numbers = seek_points.iter().map(|point| point.sample_number)
+                               .collect::<Vec<_>>();
+
+      assert_eq!(numbers, [0, 2000, 5000]);
+      assert_eq!(seek_points[0].stream_offset, 0);
+      assert_eq!(seek_points[1].frame_samples, 1000);
+    }
+
+    assert_eq!(stream.info().total_samples, 6000);
+  }
+
+  #[test]
+  fn test_frame_size_floor() {
+    let mut seed = 1u32;
+
+    // Noise for the first frames and a quiet tone for the rest, which
+    // compresses to far smaller frames.
+    let samples = (0..16384).map(|i| {
+      seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+
+      if i < 4096 {
+        (seed >> 16) as i32 - 32768
+      } else {
+        (i % 64) - 32
+      }
+    }).collect::<Vec<_>>();
+
+    let frame_sizes = |frame_size_floor| {
+      let options = EncoderOptions {
+        block_size: 1024,
+        frame_size_floor: frame_size_floor,
+        ..Default::default()
+      };
+
+      let bytes      = encode(stream_info(2, 16), options, &samples);
+      let mut stream = StreamBuffer::from_buffer(&bytes).unwrap();
+
+      assert!(stream.iter::<i32>().eq(samples.iter().cloned()));
+
+      let info = stream.info();
+
+      (info.min_frame_size as f64, info.max_frame_size as f64)
+    };
+
+    let (min, max) = frame_sizes(None);
+
+    assert!(min < max * 0.5);
+
+    let (min, max) = frame_sizes(Some(0.75));
+
+    assert!(min >= max * 0.75 && min < max);
+
+    let options = EncoderOptions {
+      frame_size_floor: Some(1.5),
+      ..Default::default()
+    };
+
+    assert!(Encoder::in_memory(stream_info(2, 16), options).is_err());
+  }
+
+  #[cfg(feature = "vorbis-comment")]
+  #[test]
+  fn test_embed_riff_info() {
+    use metadata::VorbisComment;
+
+    let wave = b"RIFF\x2c\0\0\0WAVE\
+                 LIST\x20\0\0\0INFO\
+                 INAM\x05\0\0\0Song\0\0\
+                 IART\x05\0\0\0Band\0\0";
+
+    let mut options = EncoderOptions {
+      metadata: vec![
+        Data::VorbisComment(VorbisComment::new()
+                              .with_comment("title", "Kept")),
+      ],
+      ..Default::default()
+    };
+
+    options.embed_riff_info(&wave[..]).unwrap();
+
+    assert_eq!(options.metadata.len(), 1);
+
+    match options.metadata[0] {
+      Data::VorbisComment(ref tags) => {
+        assert_eq!(tags.comments.len(), 2);
+        assert_eq!(tags.comments.get("title"), Some(&"Kept".to_owned()));
+        assert_eq!(tags.comments.get("ARTIST"), Some(&"Band".to_owned()));
+      }
+      _                             => panic!("expected a vorbis comment"),
+    }
+
+    let mut options = EncoderOptions::default();
+
+    options.embed_riff_info(&wave[..]).unwrap();
+
+    assert_eq!(options.metadata.len(), 1);
+    assert!(options.embed_riff_info(&b"RIFF\0\0\0\0WAVE"[..]).is_err());
+    assert_eq!(options.metadata.len(), 1);
+  }
+
+  #[cfg(feature = "vorbis-comment")]
+  #[test]
+  fn test_vendor_string() {
+    use metadata::VorbisComment;
+
+    let samples = (0..2000).map(|i| i % 100).collect::<Vec<_>>();
+
+    // Vendor string and settings of each vorbis comment.
+    let comments = |options: EncoderOptions| {
+      let bytes  = encode(stream_info(1, 16), options, &samples);
+      let stream = StreamBuffer::from_buffer(&bytes).unwrap();
+
+      stream.metadata().iter().filter_map(|block| block.as_vorbis_comment())
+                              .map(|comment| {
+        (comment.vendor_string.clone(),
+         comment.comments.get(SETTINGS_COMMENT).cloned())
+      }).collect::<Vec<_>>()
+    };
+
+    let tagged = |vendor_string: &str| EncoderOptions {
+      metadata: vec![
+        Data::VorbisComment(VorbisComment::new()
+                              .with_vendor_string(vendor_string)),
+      ],
+      ..Default::default()
+    };
+
+    assert!(comments(Default::default()).is_empty());
+    assert_eq!(comments(tagged(""))[0].0, DEFAULT_VENDOR);
+    assert_eq!(comments(tagged("source"))[0].0, "source");
+
+    let options = EncoderOptions {
+      vendor_string: None,
+      ..tagged("")
+    };
+
+    assert_eq!(comments(options)[0].0, "");
+
+    let options = EncoderOptions {
+      block_size: 1000,
+      vendor_string: Some("archive".to_owned()),
+      is_settings_tagged: true,
+      ..Default::default()
+    };
+
+    let settings = "block_size=1000 stereo_mode=Estimate".to_owned();
+
+    assert_eq!(comments(options), [("archive".to_owned(), Some(settings))]);
+  }
+
+  #[test]
+  fn test_seek_point_seconds() {
+    let options = EncoderOptions {
+      block_size: 1000,
+      seek_point_interval: Some(SeekPointInterval::Seconds(2)),
+      ..Default::default()
+    };
+
+    let mut info = stream_info(1, 16);
+
+    info.sample_rate   = SampleRate::new(1000).unwrap();
+    info.total_samples = 7000;
+
+    let samples = (0..7000).map(|i| i % 100).collect::<Vec<_>>();
+    let bytes   = encode(info, options, &samples);
+    let stream  = StreamBuffer::from_buffer(&bytes).unwrap();
+
+    if let Data::SeekTable(ref seek_points) = stream.metadata()[0].data {
+      let numbers = seek_points.iter().map(|point| point.sample_number)
+                               .collect::<Vec<_>>();
+
+      assert_eq!(numbers, [0, 2000, 4000, 6000]);
+    } else {
+      panic!("missing seek table");
+    }
+  }
+
+  #[test]
+  fn test_in_memory() {
+    let samples = (0..9000).map(|i| {
+      let sample = ((i as f64 / 20.0).sin() * 20000.0) as i32;
+
+      if i % 2 == 0 { sample } else { (i * 7919 % 65536) - 32768 }
+    }).collect::<Vec<_>>();
+
+    let mut info = stream_info(2, 16);
+
+    info.total_samples = 4500;
+
+    let bytes       = encode(info, Default::default(), &samples);
+    let mut encoder = Encoder::in_memory(info, Default::default()).unwrap();
+
+    encoder.write(&samples).unwrap();
+
+    let memory = encoder.finalize().unwrap();
+
+    let mut stream = StreamBuffer::from_buffer(&memory).unwrap();
+    let max_size   = max_audio_size(&info, 4096).unwrap();
+    let offset     = stream.audio_offset();
+
+    assert_eq!(memory, bytes);
+    assert!(max_size >= memory.len() as u64 - offset);
+    assert!(memory.capacity() as u64 >= offset + max_size);
+    assert!(stream.iter::<i16>().eq(samples.iter().map(|&s| s as i16)));
+    assert!(max_audio_size(&info, 1000).unwrap() >
+            max_audio_size(&info, 4096).unwrap());
+
+    info.total_samples = 0;
+
+    assert_eq!(max_audio_size(&info, 4096), None);
+
+    // The largest total samples claim terabytes, which only get reserved up
+    // to the cap.
+    info.total_samples = 0xfffffffff;
+
+    let encoder  = Encoder::in_memory(info, Default::default()).unwrap();
+    let capacity = encoder.writer.get_ref().capacity() as u64;
+
+    assert!(capacity >= MAX_RESERVED_SIZE && capacity < 2 * MAX_RESERVED_SIZE);
+  }
+
+  #[test]
+  fn test_invalid_input() {
+    let inputs = vec![
+      (stream_info(0, 16), EncoderOptions::default()),
+      (stream_info(2, 16), EncoderOptions {
+        block_size: 8,
+        ..Default::default()
+      }),
+      (stream_info(2, 16), EncoderOptions {
+        seek_point_interval: Some(SeekPointInterval::Samples(4096)),
+        ..Default::default()
+      }),
+      (stream_info(2, 16), EncoderOptions {
+        seek_point_interval: Some(SeekPointInterval::Seconds(0)),
+        ..Default::default()
+      }),
+      (stream_info(2, 16), EncoderOptions {
+        metadata: vec![Data::StreamInfo(stream_info(2, 16))],
+        ..Default::default()
+      }),
+    ];
+
+    for (info, options) in inputs {
+      let result = Encoder::new(Cursor::new(Vec::new()), info, options);
+
+      assert_eq!(result.err().map(|e| e.kind()),
+                 Some(ErrorKind::InvalidInput));
+    }
+
+    let writer      = Cursor::new(Vec::new());
+    let mut encoder = Encoder::new(writer, stream_info(2, 16),
+                                   Default::default()).unwrap();
+
+    assert_eq!(encoder.write(&[1, 2, 3]).err().map(|e| e.kind()),
+               Some(ErrorKind::InvalidInput));
+  }
+
+  fn input_error(result: io::Result<()>) -> Option<InputError> {
+    result.err().and_then(|error| {
+      error.get_ref().and_then(|inner| {
+        inner.downcast_ref::<InputError>().cloned()
+      })
+    })
+  }
+
+  #[test]
+  fn test_input_error() {
+    let options = EncoderOptions {
+      block_size: 16,
+      padding: 0,
+      ..Default::default()
+    };
+
+    let writer      = Cursor::new(Vec::new());
+    let mut encoder = Encoder::new(writer, stream_info(2, 8), options)
+                        .unwrap();
+
+    assert_eq!(input_error(encoder.write(&[1, 2, 3])),
+               Some(InputError::ChannelMismatch {
+                 samples: 3,
+                 channels: 2,
+               }));
+    assert_eq!(input_error(encoder.write(&[0, 127, -128, 128])),
+               Some(InputError::SampleOutOfRange {
+                 index: 3,
+                 sample: 128,
+                 bits_per_sample: 8,
+               }));
+    assert_eq!(input_error(encoder.write_block(&[0; 34])),
+               Some(InputError::BlockSizeMismatch {
+                 block_size: 17,
+                 expected: 16,
+               }));
+
+    let samples = (0..68).map(|i| i - 34).collect::<Vec<_>>();
+
+    encoder.write(&samples[0..6]).unwrap();
+
+    assert_eq!(input_error(encoder.write_block(&samples[6..38])),
+               Some(InputError::PartialFrame { held: 3 }));
+
+    encoder.write(&samples[6..32]).unwrap();
+    encoder.write_block(&samples[32..64]).unwrap();
+    encoder.write_block(&samples[64..68]).unwrap();
+
+    assert_eq!(input_error(encoder.write(&[0, 0])),
+               Some(InputError::AfterShortBlock));
+    assert_eq!(input_error(encoder.write_block(&[0, 0])),
+               Some(InputError::AfterShortBlock));
+
+    let bytes      = encoder.finish().unwrap().into_inner();
+    let mut stream = StreamBuffer::from_buffer(&bytes).unwrap();
+
+    assert_eq!(stream.info().total_samples, 34);
+    assert!(stream.iter::<i8>().eq(samples.iter().map(|&s| s as i8)));
+  }
+}
@@ -0,0 +1,603 @@
+use codes;
+use frame::{ChannelAssignment, NumberType, Header};
+use subframe::{self, adjust_bits_per_sample, RiceCoding, MAX_FIXED_ORDER};
+use utility::{BitWriter, crc8, crc16};
+
+// Writes the frame or sample number in the same UTF-8 like encoding that is
+// used for parsing it, including the UCS-2 extension for 36 bit numbers.
+fn utf8_number(number: u64, bytes: &mut Vec<u8>) {
+  if number < 0x80 {
+    bytes.push(number as u8);
+
+    return;
+  }
+
+  let length = match number {
+    0x00000080...0x000007ff => 1,
+    0x00000800...0x0000ffff => 2,
+    0x00010000...0x001fffff => 3,
+    0x00200000...0x03ffffff => 4,
+    0x04000000...0x7fffffff => 5,
+    _                       => 6,
+  };
+
+  let prefix = !(0xff >> (length + 1)) as u8;
+
+  bytes.push(prefix | (number >> (6 * length)) as u8);
+
+  for i in (0..length).rev() {
+    bytes.push(0b10000000 | ((number >> (6 * i)) & 0b00111111) as u8);
+  }
+}
+
+// Writes every byte of a frame header, including the CRC-8 at the end.
+fn header(header: &Header, bytes: &mut Vec<u8>) {
+  let (block_byte, block_size)   = codes::block_size_code(header.block_size);
+  let sample_rate                = header.sample_rate.get();
+  let (sample_byte, sample_rate) = codes::sample_rate_code(sample_rate);
+
+  let (is_variable_block_size, number) = match header.number {
+    NumberType::Frame(number)  => (0, number as u64),
+    NumberType::Sample(number) => (1, number),
+  };
+
+  let channel_byte = match header.channel_assignment {
+    ChannelAssignment::Independent  => header.channels - 1,
+    ChannelAssignment::LeftSide     => 0b1000,
+    ChannelAssignment::RightSide    => 0b1001,
+    ChannelAssignment::MidpointSide => 0b1010,
+  };
+  let size_byte    = codes::sample_size_code(header.bits_per_sample.into());
+  let start        = bytes.len();
+
+  bytes.push(0b11111111);
+  bytes.push(0b11111000 | is_variable_block_size);
+  bytes.push((block_byte << 4) | sample_byte);
+  bytes.push((channel_byte << 4) | (size_byte << 1));
+
+  utf8_number(number, bytes);
+
+  for &(value, length) in block_size.iter().chain(sample_rate.iter()) {
+    for i in (0..length).rev() {
+      bytes.push((value >> (8 * i)) as u8);
+    }
+  }
+
+  let crc = crc8(&bytes[start..]);
+
+  bytes.push(crc);
+}
+
+/// Applies a channel assignment to a pair of independent channels, held one
+/// after the other within `buffer`, returning the channels that get stored.
+///
+/// `Independent` leaves the channels as is. The side channel is the left
+/// channel minus the right, and the midpoint channel is their average
+/// rounded down, which leaves out the bit that gets restored from the side
+/// channel when decoding.
+pub fn decorrelate(channel_assignment: ChannelAssignment, buffer: &[i64])
+                   -> Vec<i64> {
+  let block_size    = buffer.len() / 2;
+  let (left, right) = buffer.split_at(block_size);
+  let side          = left.iter().zip(right).map(|(l, r)| l - r);
+
+  let mut result = Vec::with_capacity(buffer.len());
+
+  match channel_assignment {
+    ChannelAssignment::Independent  => result.extend_from_slice(buffer),
+    ChannelAssignment::LeftSide     => {
+      result.extend_from_slice(left);
+      result.extend(side);
+    }
+    ChannelAssignment::RightSide    => {
+      result.extend(side);
+      result.extend_from_slice(right);
+    }
+    ChannelAssignment::MidpointSide => {
+      result.extend(left.iter().zip(right).map(|(l, r)| (l + r) >> 1));
+      result.extend(side);
+    }
+  }
+
+  result
+}
+
+/// Estimates which channel assignment leads to the smallest frame for a
+/// pair of independent channels, held one after the other within `buffer`.
+///
+/// Each of the left, right, midpoint, and side channels is scored by the
+/// sum of its second order fixed residual, which follows the size of the
+/// coded subframe closely without having to encode it. Independent
+/// channels win any ties.
+pub fn estimate_assignment(buffer: &[i64]) -> ChannelAssignment {
+  let block_size = buffer.len() / 2;
+
+  // Left, right, midpoint, then side.
+  let mut sums = [0u64; 4];
+
+  for i in 2..block_size {
+    let mut channels = [[0i64; 3]; 4];
+
+    for j in 0..3 {
+      let left  = buffer[i - 2 + j];
+      let right = buffer[block_size + i - 2 + j];
+
+      channels[0][j] = left;
+      channels[1][j] = right;
+      channels[2][j] = (left + right) >> 1;
+      channels[3][j] = left - right;
+    }
+
+    for (sum, channel) in sums.iter_mut().zip(&channels) {
+      *sum += (channel[2] - 2 * channel[1] + channel[0]).abs() as u64;
+    }
+  }
+
+  let candidates = [
+    (ChannelAssignment::Independent, sums[0] + sums[1]),
+    (ChannelAssignment::LeftSide, sums[0] + sums[3]),
+    (ChannelAssignment::RightSide, sums[3] + sums[1]),
+    (ChannelAssignment::MidpointSide, sums[2] + sums[3]),
+  ];
+
+  candidates.iter().min_by_key(|&&(_, sum)| sum)
+            .map(|&(channel_assignment, _)| channel_assignment)
+            .unwrap_or(ChannelAssignment::Independent)
+}
+
+/// Encodes an audio frame and appends its bytes to `bytes`.
+///
+/// The `crc` field of the header is ignored and gets calculated instead.
+/// `buffer` holds each channel one after the other, `block_size` samples at
+/// a time, with the channel assignment already applied to the samples.
+pub fn encode(frame_header: &Header, buffer: &[i64], bytes: &mut Vec<u8>) {
+  encode_with(frame_header, buffer, bytes, |_, samples, bits, writer| {
+    subframe::encode(samples, bits, writer)
+  });
+}
+
+/// Same as `encode`, except each subframe gets written by `write_subframe`,
+/// which is given the channel, its samples, and its bits per sample.
+pub fn encode_with<F>(frame_header: &Header, buffer: &[i64],
+                      bytes: &mut Vec<u8>, mut write_subframe: F)
+ where F: FnMut(usize, &[i64], usize, &mut BitWriter) {
+  let block_size = frame_header.block_size as usize;
+  let channels   = frame_header.channels as usize;
+  let start      = bytes.len();
+
+  header(frame_header, bytes);
+
+  let mut writer = BitWriter::new();
+
+  for channel in 0..channels {
+    let bits_per_sample = adjust_bits_per_sample(frame_header, channel);
+    let samples         = &buffer[(channel * block_size)..
+                                  ((channel + 1) * block_size)];
+
+    write_subframe(channel, samples, bits_per_sample, &mut writer);
+  }
+
+  bytes.extend_from_slice(&writer.into_bytes());
+
+  let crc = crc16(&bytes[start..]);
+
+  bytes.push((crc >> 8) as u8);
+  bytes.push(crc as u8);
+
+  #[cfg(feature = "debug-validate")]
+  validate(frame_header, buffer, &bytes[start..]);
+}
+
+// Largest partition order used for padded residuals. Every step of padding
+// raises the Rice parameter of a single partition, so smaller partitions
+// get closer to the size being padded toward.
+const PADDING_PARTITION_ORDER: u32 = 8;
+
+// Largest Rice parameter for each coding method, the escape code not being
+// included.
+const MAX_RICE_PARAMETER: u32  = 14;
+const MAX_RICE2_PARAMETER: u32 = 30;
+
+// Fixed prediction subframe that gets padded by raising the Rice parameters
+// of its partitions.
+struct Padded {
+  order: usize,
+  residual: Vec<i64>,
+  folded: Vec<u64>,
+  coding: RiceCoding,
+  max_parameter: u32,
+}
+
+// Number of bits a partition of folded residual values takes up when coded
+// with the Rice parameter `parameter`, without the parameter itself.
+fn partition_bits(folded: &[u64], parameter: u32) -> u64 {
+  folded.iter().fold(0, |bits, &value| {
+    bits + 1 + parameter as u64 + (value >> parameter)
+  })
+}
+
+impl Padded {
+  // Codes a fixed prediction of the given order, split into as many
+  // partitions as the block allows.
+  fn new(order: usize, samples: &[i64], bits_per_sample: usize)
+         -> Option<Padded> {
+    let block_size = samples.len();
+    let residual   = match subframe::fixed_residual(order, samples) {
+      Some(residual) if block_size > order => residual,
+      _                                    => return None,
+    };
+
+    let mut partition_order = PADDING_PARTITION_ORDER;
+
+    while block_size % (1 << partition_order) != 0 ||
+          (block_size >> partition_order) <= order {
+      partition_order -= 1;
+    }
+
+    let folded = residual.iter().map(|&value| {
+      ((value << 1) ^ (value >> 63)) as u64
+    }).collect::<Vec<_>>();
+
+    let mut padded = Padded {
+      order: order,
+      residual: residual,
+      folded: folded,
+      coding: RiceCoding {
+        partition_order: partition_order,
+        parameters: Vec::new(),
+        bit_count: 0,
+      },
+      max_parameter: MAX_RICE_PARAMETER,
+    };
+
+    for i in 0..(1 << partition_order) {
+      let (start, end)   = padded.bounds(i);
+      let partition      = &padded.residual[start..end];
+      let (parameter, _) = subframe::rice_parameter(partition);
+
+      padded.coding.parameters.push(parameter);
+    }
+
+    if padded.coding.is_rice2() {
+      padded.max_parameter = MAX_RICE2_PARAMETER;
+    }
+
+    padded.coding.bit_count = (0..padded.coding.parameters.len())
+      .fold(0, |bits, i| bits + padded.bits(i));
+
+    padded.coding.bit_count += 8 + (order * bits_per_sample) as u64 + 6;
+
+    Some(padded)
+  }
+
+  // Range of the residual within a partition, where the first partition is
+  // shorter by the warm up samples.
+  fn bounds(&self, partition: usize) -> (usize, usize) {
+    let length = (self.residual.len() + self.order) >>
+                 self.coding.partition_order;
+
+    if partition == 0 {
+      (0, length - self.order)
+    } else {
+      let start = partition * length - self.order;
+
+      (start, start + length)
+    }
+  }
+
+  // Bits a partition takes up, including its parameter.
+  fn bits(&self, partition: usize) -> u64 {
+    let (start, end)   = self.bounds(partition);
+    let parameter      = self.coding.parameters[partition];
+    let parameter_size = if self.coding.is_rice2() { 5 } else { 4 };
+
+    parameter_size + partition_bits(&self.folded[start..end], parameter)
+  }
+
+  // Raises the parameter of a partition by one, returning false when it's
+  // already as large as it can get.
+  fn raise(&mut self, partition: usize) -> bool {
+    if self.coding.parameters[partition] >= self.max_parameter {
+      return false;
+    }
+
+    let before = self.bits(partition);
+
+    self.coding.parameters[partition] += 1;
+
+    self.coding.bit_count = self.coding.bit_count - before +
+                            self.bits(partition);
+
+    true
+  }
+}
+
+// Picks the fixed prediction order that takes up the fewest bits once split
+// into the partitions used for padding.
+fn padded_channel(samples: &[i64], bits_per_sample: usize) -> Option<Padded> {
+  (0..(MAX_FIXED_ORDER + 1))
+    .filter_map(|order| Padded::new(order, samples, bits_per_sample))
+    .min_by_key(|padded| padded.coding.bit_count)
+}
+
+/// Encodes an audio frame the same as `encode`, padding it toward
+/// `min_size` bytes when it comes out smaller.
+///
+/// Frames get padded by coding each channel with a fixed prediction and
+/// raising the Rice parameters of its partitions, a few samples at a time,
+/// which only trades away compression since the frame still decodes to the
+/// same samples. A frame can't grow past the largest parameters, so it's
+/// able to fall short of `min_size`, and it can overshoot it by up to a
+/// partition's worth of bits.
+pub fn encode_padded(frame_header: &Header, buffer: &[i64],
+                     bytes: &mut Vec<u8>, min_size: usize) {
+  let block_size = frame_header.block_size as usize;
+  let channels   = frame_header.channels as usize;
+  let start      = bytes.len();
+
+  encode(frame_header, buffer, bytes);
+
+  if bytes.len() - start >= min_size {
+    return;
+  }
+
+  let mut header_bytes = Vec::new();
+
+  header(frame_header, &mut header_bytes);
+
+  let mut padded     = Vec::with_capacity(channels);
+  let mut frame_bits = 0;
+
+  for channel in 0..channels {
+    let bits_per_sample = adjust_bits_per_sample(frame_header, channel);
+    let samples         = &buffer[(channel * block_size)..
+                                  ((channel + 1) * block_size)];
+    let verbatim        = 8 + (block_size * bits_per_sample) as u64;
+    let coded           = padded_channel(samples, bits_per_sample);
+
+    frame_bits += coded.as_ref()
+                       .map_or(verbatim, |coded| coded.coding.bit_count);
+
+    padded.push(coded);
+  }
+
+  let available = min_size.saturating_sub(header_bytes.len() + 2) as u64;
+  let needed    = if available > 0 { (available - 1) * 8 + 1 } else { 0 };
+
+  let mut is_raised = true;
+
+  while frame_bits < needed && is_raised {
+    is_raised = false;
+
+    for coded in padded.iter_mut().filter_map(Option::as_mut) {
+      for partition in 0..coded.coding.parameters.len() {
+        if frame_bits >= needed {
+          break;
+        }
+
+        let before = coded.coding.bit_count;
+
+        if coded.raise(partition) {
+          frame_bits = frame_bits - before + coded.coding.bit_count;
+          is_raised  = true;
+        }
+      }
+    }
+  }
+
+  bytes.truncate(start);
+
+  encode_with(frame_header, buffer, bytes, |channel, samples, bits, writer| {
+    match padded[channel] {
+      Some(ref coded) => {
+        subframe::write_fixed_with(coded.order, samples, &coded.residual,
+                                   &coded.coding, bits, writer)
+      }
+      None            => subframe::write_verbatim(samples, bits, writer),
+    }
+  });
+}
+
+// Panics when the frame that was just written doesn't parse back into the
+// same header and samples, which points at a bug within the encoder.
+#[cfg(feature = "debug-validate")]
+fn validate(frame_header: &Header, buffer: &[i64], bytes: &[u8]) {
+  use frame::frame_parser;
+  use metadata::StreamInfo;
+  use nom::IResult;
+
+  let block_size  = frame_header.block_size as usize;
+  let channels    = frame_header.channels as usize;
+  let stream_info = StreamInfo {
+    min_block_size: frame_header.block_size as u16,
+    max_block_size: frame_header.block_size as u16,
+    sample_rate: frame_header.sample_rate,
+    channels: frame_header.channels,
+    bits_per_sample: frame_header.bits_per_sample,
+    ..Default::default()
+  };
+
+  let mut output = vec![0i64; block_size * channels];
+
+  let frame = match frame_parser(bytes, &stream_info, &mut output) {
+    IResult::Done(rest, frame) => {
+      assert!(rest.is_empty(), "written frame has extra bytes");
+
+      frame
+    }
+    _                          => panic!("written frame fails to parse"),
+  };
+
+  let header = &frame.header;
+
+  assert_eq!(header.block_size, frame_header.block_size);
+  assert_eq!(header.sample_rate, frame_header.sample_rate);
+  assert_eq!(header.channels, frame_header.channels);
+  assert_eq!(header.channel_assignment, frame_header.channel_assignment);
+  assert_eq!(header.bits_per_sample, frame_header.bits_per_sample);
+  assert_eq!(header.number, frame_header.number);
+
+  for channel in 0..channels {
+    let start = channel * block_size;
+    let end   = start + block_size;
+
+    subframe::decode(&frame.subframes[channel], block_size,
+                     &mut output[start..end]);
+  }
+
+  assert!(output[..] == buffer[..(block_size * channels)],
+          "written frame decodes to different samples");
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use frame::{ChannelAssignment, NumberType, Header, frame_parser, decode};
+  use metadata::StreamInfo;
+  use subframe;
+  use utility::{BitsPerSample, SampleRate};
+
+  use nom::IResult;
+
+  fn round_trip(frame_header: Header, buffer: &[i64], expected: &[i64]) {
+    let stream_info = StreamInfo {
+      min_block_size: 16,
+      max_block_size: 16,
+      min_frame_size: 0,
+      max_frame_size: 0,
+      sample_rate: frame_header.sample_rate,
+      channels: frame_header.channels,
+      bits_per_sample: frame_header.bits_per_sample,
+      total_samples: 0,
+      md5_sum: [0; 16],
+    };
+
+    let mut bytes  = Vec::new();
+    let mut output = vec![0; buffer.len()];
+
+    encode(&frame_header, buffer, &mut bytes);
+
+    let frame = match frame_parser(&bytes, &stream_info, &mut output) {
+      IResult::Done(i, frame) => {
+        assert!(i.is_empty());
+
+        frame
+      }
+      _                       => panic!("failed to parse frame"),
+    };
+
+    assert_eq!(frame.header.block_size, frame_header.block_size);
+    assert_eq!(frame.header.sample_rate, frame_header.sample_rate);
+    assert_eq!(frame.header.number, frame_header.number);
+
+    let block_size = frame_header.block_size as usize;
+
+    for channel in 0..(frame_header.channels as usize) {
+      let start = channel * block_size;
+      let end   = start + block_size;
+
+      subframe::decode(&frame.subframes[channel], block_size,
+                       &mut output[start..end]);
+    }
+
+    decode(frame.header.channel_assignment, &mut output);
+
+    assert_eq!(output, expected.iter().map(|&s| s as i32)
+                                      .collect::<Vec<_>>());
+  }
+
+  #[test]
+  fn test_utf8_number() {
+    let inputs  = [0x7f, 0x80, 0x7fffffff, 0xfffffffff];
+    let results = [ &[0x7f][..]
+                  , &[0xc2, 0x80][..]
+                  , &[0xfd, 0xbf, 0xbf, 0xbf, 0xbf, 0xbf][..]
+                  , &[0xfe, 0xbf, 0xbf, 0xbf, 0xbf, 0xbf, 0xbf][..]
+                  ];
+
+    for (&input, &result) in inputs.iter().zip(results.iter()) {
+      let mut bytes = Vec::new();
+
+      utf8_number(input, &mut bytes);
+
+      assert_eq!(&bytes[..], result);
+    }
+  }
+
+  #[test]
+  fn test_encode() {
+    let left  = (0..100).map(|i| (i * 37) % 201 - 100).collect::<Vec<_>>();
+    let right = (0..100).map(|i| 50 - i).collect::<Vec<_>>();
+
+    let mut buffer = left.clone();
+
+    buffer.extend_from_slice(&right);
+
+    round_trip(Header {
+      block_size: 100,
+      sample_rate: SampleRate::new(12000).unwrap(),
+      channels: 2,
+      channel_assignment: ChannelAssignment::Independent,
+      bits_per_sample: BitsPerSample::new(16).unwrap(),
+      number: NumberType::Frame(200),
+      crc: 0,
+    }, &buffer, &buffer);
+
+    let side = left.iter().zip(&right).map(|(l, r)| l - r)
+                   .collect::<Vec<_>>();
+
+    let expected   = buffer;
+    let mut buffer = left.clone();
+
+    buffer.extend_from_slice(&side);
+
+    round_trip(Header {
+      block_size: 100,
+      sample_rate: SampleRate::new(44100).unwrap(),
+      channels: 2,
+      channel_assignment: ChannelAssignment::LeftSide,
+      bits_per_sample: BitsPerSample::new(8).unwrap(),
+      number: NumberType::Sample(0xfffffffff),
+      crc: 0,
+    }, &buffer, &expected);
+  }
+
+  #[test]
+  fn test_decorrelate() {
+    let buffer  = [10i64, -3, 7, 4, 5, 9];
+    let results = [ (ChannelAssignment::Independent, [10, -3, 7, 4, 5, 9])
+                  , (ChannelAssignment::LeftSide, [10, -3, 7, 6, -8, -2])
+                  , (ChannelAssignment::RightSide, [6, -8, -2, 4, 5, 9])
+                  , (ChannelAssignment::MidpointSide, [7, 1, 8, 6, -8, -2])
+                  ];
+
+    for &(channel_assignment, result) in &results {
+      let mut output = decorrelate(channel_assignment, &buffer)
+                         .iter().map(|&s| s as i32).collect::<Vec<_>>();
+
+      assert_eq!(output, result);
+
+      decode(channel_assignment, &mut output);
+
+      assert_eq!(output, [10, -3, 7, 4, 5, 9]);
+    }
+  }
+
+  #[test]
+  fn test_estimate_assignment() {
+    let left  = (0..64).map(|i| (i * 37) % 201 - 100).collect::<Vec<_>>();
+    let quiet = (0..64).map(|i| i % 3).collect::<Vec<_>>();
+
+    let mut buffer = left.clone();
+
+    buffer.extend_from_slice(&left);
+
+    assert_eq!(estimate_assignment(&buffer), ChannelAssignment::LeftSide);
+
+    let mut buffer = left.clone();
+
+    buffer.extend_from_slice(&quiet);
+
+    assert_eq!(estimate_assignment(&buffer), ChannelAssignment::Independent);
+  }
+}
@@ -0,0 +1,271 @@
+// Works out the length of a frame, and where each of its subframes sits, by
+// stepping over the bits of each subframe, without reading any samples or
+// restoring the signal.
+
+use nom::{self, IResult, Needed, Err};
+
+use frame::{header, Header};
+use subframe::{adjust_bits_per_sample, MAX_SUBFRAME_BITS};
+
+use metadata::StreamInfo;
+use utility::ErrorKind;
+
+use std::ops::Range;
+
+// Reason stepping over the bits of a frame stopped early.
+enum Stop {
+  // Needs at least this many bits, from the start of the subframes.
+  Incomplete(usize),
+  Invalid(ErrorKind),
+}
+
+// Reader that only keeps track of how many bits were read.
+struct Bits<'a> {
+  bytes: &'a [u8],
+  position: usize,
+}
+
+impl<'a> Bits<'a> {
+  fn new(bytes: &'a [u8]) -> Self {
+    Bits {
+      bytes: bytes,
+      position: 0,
+    }
+  }
+
+  fn skip(&mut self, count: usize) -> Result<(), Stop> {
+    let end = self.position + count;
+
+    if end > self.bytes.len() * 8 {
+      return Err(Stop::Incomplete(end));
+    }
+
+    self.position = end;
+
+    Ok(())
+  }
+
+  // Reads up to 32 bits as an unsigned number.
+  fn read(&mut self, count: usize) -> Result<u32, Stop> {
+    let start = self.position;
+
+    try!(self.skip(count));
+
+    let value = (start..(start + count)).fold(0u64, |value, bit| {
+      let byte = self.bytes[bit / 8];
+
+      (value << 1) | ((byte >> (7 - bit % 8)) & 1) as u64
+    });
+
+    Ok(value as u32)
+  }
+
+  // Counts the zeros before the next one, stepping past the one.
+  fn unary(&mut self) -> Result<usize, Stop> {
+    let mut count = 0;
+
+    loop {
+      let index  = self.position / 8;
+      let offset = self.position % 8;
+
+      let byte = match self.bytes.get(index) {
+        Some(&byte) => byte << offset,
+        None        => return Err(Stop::Incomplete(self.position + 1)),
+      };
+
+      if byte == 0 {
+        count         += 8 - offset;
+        self.position += 8 - offset;
+      } else {
+        let zeros = byte.leading_zeros() as usize;
+
+        count         += zeros;
+        self.position += zeros + 1;
+
+        return Ok(count);
+      }
+    }
+  }
+}
+
+// Returns the length, in bytes, of the frame at the start of `input`.
+//
+// The CRC-8 of the header gets checked, but the CRC-16 of the whole frame
+// doesn't.
+pub fn frame_length<'a>(input: &'a [u8], stream_info: &StreamInfo)
+                        -> IResult<&'a [u8], usize, ErrorKind> {
+  match frame_bits(input, stream_info, |_| ()) {
+    IResult::Done(i, (_, length)) => IResult::Done(i, length),
+    IResult::Error(error)         => IResult::Error(error),
+    IResult::Incomplete(need)     => IResult::Incomplete(need),
+  }
+}
+
+// Returns the length of the header, the bit range of each subframe, and
+// the length of the whole frame at the start of `input`. Bit ranges count
+// from the start of the frame and lengths are in bytes.
+pub fn frame_layout<'a>(input: &'a [u8], stream_info: &StreamInfo)
+                        -> IResult<&'a [u8],
+                                   (usize, Vec<Range<usize>>, usize),
+                                   ErrorKind> {
+  let mut subframes = Vec::new();
+
+  let result = frame_bits(input, stream_info, |range| {
+    subframes.push(range)
+  });
+
+  match result {
+    IResult::Done(i, (header_length, length)) => {
+      IResult::Done(i, (header_length, subframes, length))
+    }
+    IResult::Error(error)                     => IResult::Error(error),
+    IResult::Incomplete(need)                 => IResult::Incomplete(need),
+  }
+}
+
+// Steps over every subframe of the frame at the start of `input`, handing
+// the bit range of each one to `f`, and returns the length of the header
+// and of the whole frame.
+fn frame_bits<'a, F>(input: &'a [u8], stream_info: &StreamInfo, mut f: F)
+                     -> IResult<&'a [u8], (usize, usize), ErrorKind>
+ where F: FnMut(Range<usize>) {
+  let (i, frame_header) = try_parser!(header(input, stream_info));
+
+  let header_length = input.len() - i.len();
+  let mut bits      = Bits::new(i);
+
+  let result = (0..(frame_header.channels as usize)).fold(Ok(()), |r, c| {
+    r.and_then(|_| {
+      let start = bits.position;
+
+      try!(subframe(&mut bits, &frame_header, c));
+
+      let offset = header_length * 8;
+
+      f((offset + start)..(offset + bits.position));
+
+      Ok(())
+    })
+  });
+
+  // Subframes end on a byte boundary, followed by the two byte CRC-16.
+  let length = match result {
+    Ok(())                       => {
+      header_length + (bits.position + 7) / 8 + 2
+    }
+    Err(Stop::Incomplete(count)) => {
+      return IResult::Incomplete(Needed::Size(header_length +
+                                              (count + 7) / 8 + 2));
+    }
+    Err(Stop::Invalid(kind))     => {
+      return IResult::Error(Err::Position(nom::ErrorKind::Custom(kind),
+                                          input));
+    }
+  };
+
+  if length > input.len() {
+    IResult::Incomplete(Needed::Size(length))
+  } else {
+    IResult::Done(&input[length..], (header_length, length))
+  }
+}
+
+fn subframe(bits: &mut Bits, frame_header: &Header, channel: usize)
+            -> Result<(), Stop> {
+  let block_size = frame_header.block_size as usize;
+  let byte       = try!(bits.read(8));
+
+  if (byte >> 7) != 0 {
+    return Err(Stop::Invalid(ErrorKind::InvalidSubframeHeader));
+  }
+
+  let subframe_type   = ((byte >> 1) & 0b111111) as usize;
+  let mut wasted_bits = 0;
+
+  if (byte & 0b01) == 1 {
+    wasted_bits = try!(bits.unary()) + 1;
+  }
+
+  let bits_per_sample = adjust_bits_per_sample(frame_header, channel)
+                          .saturating_sub(wasted_bits);
+
+  // The samples only get skipped over, so the 33-bit side channel of a
+  // 32-bit stream is fine even though it can't be decoded.
+  if bits_per_sample == 0 || bits_per_sample > MAX_SUBFRAME_BITS + 1 {
+    return Err(Stop::Invalid(ErrorKind::InvalidSubframeHeader));
+  }
+
+  match subframe_type {
+    0b000000            => bits.skip(bits_per_sample),
+    0b000001            => bits.skip(bits_per_sample * block_size),
+    0b001000...0b001100 => {
+      let order = subframe_type & 0b0111;
+
+      try!(bits.skip(order * bits_per_sample));
+
+      residual(bits, order, block_size, ErrorKind::FixedParser)
+    }
+    0b100000...0b111111 => {
+      let order     = (subframe_type & 0b011111) + 1;
+      let error     = ErrorKind::LPCParser;
+
+      try!(bits.skip(order * bits_per_sample));
+
+      let precision = try!(bits.read(4)) as usize;
+
+      // Keeps sync codes from showing up within the subframe.
+      if precision == 0b1111 {
+        return Err(Stop::Invalid(error));
+      }
+
+      // Quantization level followed by the coefficients.
+      try!(bits.skip(5 + order * (precision + 1)));
+
+      residual(bits, order, block_size, error)
+    }
+    _                   => Err(Stop::Invalid(ErrorKind::Unknown)),
+  }
+}
+
+fn residual(bits: &mut Bits, predictor_order: usize, block_size: usize,
+            error: ErrorKind)
+            -> Result<(), Stop> {
+  let (parameter_size, escape_code) = match try!(bits.read(2)) {
+    0 => (4, 0b1111),
+    1 => (5, 0b11111),
+    _ => return Err(Stop::Invalid(error)),
+  };
+
+  let partition_order = try!(bits.read(4));
+  let partitions      = 1 << partition_order;
+
+  // Every partition needs to hold at least the warm up samples and the
+  // partitions need to split the block evenly.
+  if block_size % partitions != 0 ||
+     block_size / partitions < predictor_order {
+    return Err(Stop::Invalid(error));
+  }
+
+  for partition in 0..partitions {
+    let samples = if partition > 0 {
+      block_size / partitions
+    } else {
+      block_size / partitions - predictor_order
+    };
+
+    let parameter = try!(bits.read(parameter_size));
+
+    if parameter == escape_code {
+      let size = try!(bits.read(5)) as usize;
+
+      try!(bits.skip(size * samples));
+    } else {
+      for _ in 0..samples {
+        try!(bits.unary());
+        try!(bits.skip(parameter as usize));
+      }
+    }
+  }
+
+  Ok(())
+}
@@ -1,6 +1,8 @@
 mod types;
 mod parser;
 mod decoder;
+mod encoder;
+mod length;
 
 pub use self::types::{
   MAX_CHANNELS,
@@ -9,5 +11,9 @@ pub use self::types::{
   Header, Footer,
 };
 
-pub use self::parser::frame_parser;
+pub use self::parser::{frame_parser, frame_parser_with_context, header};
 pub use self::decoder::decode;
+pub use self::encoder::{
+  encode, encode_with, encode_padded, decorrelate, estimate_assignment,
+};
+pub use self::length::{frame_layout, frame_length};
@@ -2,27 +2,46 @@ use nom::{
   self,
   be_u8, be_u16,
   IResult,
-  Err,
+  Err, Needed,
 };
 
+use std::cmp;
 use std::mem;
 
 use frame::{
-  MAX_CHANNELS,
+  self, MAX_CHANNELS,
   ChannelAssignment, NumberType,
   Frame,
   Header, Footer,
 };
 use subframe::{subframe_parser, Subframe};
 
+use codes;
 use metadata::StreamInfo;
-use utility::{ErrorKind, Sample, crc8, crc16, to_u32, power_of_two};
+use utility::{
+  BitsPerSample, DecodeContext, ErrorKind, Sample, SampleRate, crc8, crc16,
+  needed_from, to_u32,
+};
 
 /// Parses an audio frame
+#[inline]
 pub fn frame_parser<'a, S>(input: &'a [u8],
                            stream_info: &StreamInfo,
                            buffer: &mut [S])
                            -> IResult<&'a [u8], Frame, ErrorKind>
+ where S: Sample {
+  let mut context = DecodeContext::new();
+
+  frame_parser_with_context(input, stream_info, &mut context, buffer)
+}
+
+/// Parses an audio frame with the options within `context`, which starts
+/// the frame at channel zero.
+pub fn frame_parser_with_context<'a, S>(input: &'a [u8],
+                                        stream_info: &StreamInfo,
+                                        context: &mut DecodeContext,
+                                        buffer: &mut [S])
+                                        -> IResult<&'a [u8], Frame, ErrorKind>
  where S: Sample {
   // Unsafe way to initialize subframe data, but I would rather do this
   // than have `Subframe` derive `Copy` to do something like:
@@ -34,41 +53,97 @@ pub fn frame_parser<'a, S>(input: &'a [u8],
   //                     }; MAX_CHANNELS];
   // ```
   let mut subframes: [Subframe; MAX_CHANNELS] = unsafe { mem::zeroed() };
-  let mut channel = 0;
-
-  let result = chain!(input,
-    frame_header: apply!(header, stream_info) ~
-    bits!(
-      count_slice!(
-        apply!(subframe_parser, &frame_header, &mut channel, buffer),
-        &mut subframes[0..(frame_header.channels as usize)]
-      )
-    ) ~
-    frame_footer: footer,
-    || {
-      Frame {
-        header: frame_header,
-        subframes: subframes,
-        footer: frame_footer,
+
+  let (i, frame_header) = try_parser!(header(input, stream_info));
+
+  // Corrupt or concatenated files can have frames in another format than
+  // the rest of the stream, which would be read back going by `StreamInfo`
+  // and come out misinterleaved.
+  if frame_header.channels != stream_info.channels ||
+     frame_header.bits_per_sample != stream_info.bits_per_sample {
+    return IResult::Error(Err::Position(
+      nom::ErrorKind::Custom(ErrorKind::InvalidFrameFormat), input));
+  }
+
+  // The buffer is sized from the maximum block size, so a frame claiming
+  // more samples than that is corrupt. Samples get read back using the
+  // channels of the stream, which need to fit as well.
+  let channels = cmp::max(frame_header.channels, stream_info.channels);
+  let samples  = frame_header.block_size as usize * channels as usize;
+
+  if samples > buffer.len() {
+    return IResult::Error(Err::Position(
+      nom::ErrorKind::Custom(ErrorKind::InvalidBlockSize), input));
+  }
+
+  context.set_channel(0);
+
+  let channel_slice = &mut subframes[0..(frame_header.channels as usize)];
+  let (i, _)        = try_parser! {
+    needed_from(&input, &i, subframes_parser(i, &frame_header, channel_slice,
+                                             context, buffer))
+  };
+
+  let (i, frame_footer) = try_parser!(needed_from(&input, &i, footer(i)));
+
+  // All frame bytes before the crc-16
+  let end         = (input.len() - i.len()) - 2;
+  let Footer(crc) = frame_footer;
+
+  if crc16(&input[0..end]) == crc {
+    IResult::Done(i, Frame {
+      header: frame_header,
+      subframes: subframes,
+      footer: frame_footer,
+    })
+  } else {
+    IResult::Error(Err::Position(
+      nom::ErrorKind::Custom(ErrorKind::InvalidCRC16), input))
+  }
+}
+
+// Parses a subframe for each channel of the frame, filling `subframes` in
+// order. The subframes are packed bits, so parsing ends at the next byte
+// boundary after the last one.
+fn subframes_parser<'a, S>(input: &'a [u8],
+                           frame_header: &frame::Header,
+                           subframes: &mut [Subframe],
+                           context: &mut DecodeContext,
+                           buffer: &mut [S])
+                           -> IResult<&'a [u8], (), ErrorKind>
+ where S: Sample {
+  let mut mut_input = (input, 0);
+
+  for subframe in subframes {
+    match subframe_parser(mut_input, frame_header, context, buffer) {
+      IResult::Done(i, result) => {
+        mut_input = i;
+
+        *subframe = result;
       }
-    }
-  );
-
-  match result {
-    IResult::Done(i, frame)   => {
-      // All frame bytes before the crc-16
-      let end         = (input.len() - i.len()) - 2;
-      let Footer(crc) = frame.footer;
-
-      if crc16(&input[0..end]) == crc {
-        IResult::Done(i, frame)
-      } else {
-        IResult::Error(Err::Position(
-          nom::ErrorKind::Custom(ErrorKind::InvalidCRC16), input))
+      IResult::Error(_)        => {
+        return IResult::Error(Err::Position(nom::ErrorKind::Count, input));
+      }
+      IResult::Incomplete(_)   => {
+        return IResult::Incomplete(Needed::Unknown);
       }
     }
-    IResult::Error(error)     => IResult::Error(error),
-    IResult::Incomplete(need) => IResult::Incomplete(need),
+  }
+
+  // Skips the padding up to the byte boundary.
+  let (i, offset) = mut_input;
+
+  IResult::Done(&i[((offset + 7) / 8)..], ())
+}
+
+// Takes the next `count` bytes, asking for all of them when there aren't
+// enough.
+#[inline]
+fn take(input: &[u8], count: usize) -> IResult<&[u8], &[u8]> {
+  if input.len() < count {
+    IResult::Incomplete(Needed::Size(count))
+  } else {
+    IResult::Done(&input[count..], &input[0..count])
   }
 }
 
@@ -78,7 +153,7 @@ pub fn frame_parser<'a, S>(input: &'a [u8],
 // is fixed or varied.
 pub fn blocking_strategy(input: &[u8]) -> IResult<&[u8], bool, ErrorKind> {
   let (i, bytes) = try_parser! {
-    to_custom_error!(input, take!(2), BlockingStrategyParser)
+    take(input, 2).map_err(to_custom_error!(BlockingStrategyParser))
   };
 
   let sync_code = ((bytes[0] as u16) << 6) +
@@ -102,7 +177,7 @@ pub fn blocking_strategy(input: &[u8]) -> IResult<&[u8], bool, ErrorKind> {
 // prevent sync code fooling.
 pub fn block_sample(input: &[u8]) -> IResult<&[u8], (u8, u8), ErrorKind> {
   let (i, byte) = try_parser! {
-    to_custom_error!(input, be_u8, BlockingStrategyParser)
+    be_u8(input).map_err(to_custom_error!(BlockingStrategyParser))
   };
 
   let block_byte  = byte >> 4;
@@ -126,7 +201,7 @@ pub fn channel_bits(input: &[u8])
                     -> IResult<&[u8], (ChannelAssignment, u8, u8),
                                ErrorKind> {
   let (i, byte) = try_parser! {
-    to_custom_error!(input, be_u8, ChannelBitsParser)
+    be_u8(input).map_err(to_custom_error!(ChannelBitsParser))
   };
 
   let mut channels       = 2;
@@ -161,7 +236,7 @@ pub fn channel_bits(input: &[u8])
 // branches are valid UTF-8 headers.
 pub fn utf8_header(input: &[u8], is_u64: bool)
                    -> IResult<&[u8], Option<(usize, u8)>, ErrorKind> {
-  map!(input, be_u8, |byte| {
+  be_u8(input).map(|byte| {
     match byte {
       0b00000000...0b01111111 => Some((0, byte)),
       0b11000000...0b11011111 => Some((1, byte & 0b00011111)),
@@ -181,7 +256,7 @@ pub fn number_type(input: &[u8], is_sample: bool,
                    (size, value): (usize, u8))
                    -> IResult<&[u8], NumberType, ErrorKind> {
   let (i, bytes) = try_parser! {
-    to_custom_error!(input, take!(size), UTF8BodyParser)
+    take(input, size).map_err(to_custom_error!(UTF8BodyParser))
   };
 
   let mut result   = value as u64;
@@ -209,117 +284,112 @@ pub fn number_type(input: &[u8], is_sample: bool,
 }
 
 #[inline]
-fn opt_take_u32(input: &[u8], count: usize) -> IResult<&[u8], Option<u32>> {
-  opt!(input, map!(take!(count), to_u32))
+fn take_u32(input: &[u8], count: usize) -> IResult<&[u8], Option<u32>> {
+  take(input, count).map(|bytes| Some(to_u32(bytes)))
 }
 
 pub fn secondary_block_size(input: &[u8], block_byte: u8)
                             -> IResult<&[u8], Option<u32>, ErrorKind> {
-  match block_byte {
-    0b0110 => to_custom_error!(input, apply!(opt_take_u32, 1),
-                               BlockSizeParser),
-    0b0111 => to_custom_error!(input, apply!(opt_take_u32, 2),
-                               BlockSizeParser),
+  let result = match block_byte {
+    0b0110 => take_u32(input, 1),
+    0b0111 => take_u32(input, 2),
     _      => IResult::Done(input, None)
-  }
+  };
+
+  result.map_err(to_custom_error!(BlockSizeParser))
 }
 
 pub fn secondary_sample_rate(input: &[u8], sample_byte: u8)
                              -> IResult<&[u8], Option<u32>, ErrorKind> {
-  match sample_byte {
-    0b1100          => to_custom_error!(input, apply!(opt_take_u32, 1),
-                                        SampleRateParser),
-    0b1101 | 0b1110 => to_custom_error!(input, apply!(opt_take_u32, 2),
-                                        SampleRateParser),
+  let result = match sample_byte {
+    0b1100          => take_u32(input, 1),
+    0b1101 | 0b1110 => take_u32(input, 2),
     _               => IResult::Done(input, None)
-  }
+  };
+
+  result.map_err(to_custom_error!(SampleRateParser))
 }
 
 #[inline]
 pub fn header<'a>(input: &'a [u8], stream_info: &StreamInfo)
                   -> IResult<&'a [u8], Header, ErrorKind> {
-  let result = chain!(input,
-    is_variable_block_size: blocking_strategy ~
-    tuple0: block_sample ~
-    tuple1: channel_bits ~
-    utf8_header_opt: apply!(utf8_header, is_variable_block_size) ~
-    utf8_header_val: expr_opt!(utf8_header_opt) ~
-    number: apply!(number_type, is_variable_block_size, utf8_header_val) ~
-    alt_block_size: apply!(secondary_block_size, tuple0.0) ~
-    alt_sample_rate: apply!(secondary_sample_rate, tuple0.1) ~
-    crc: to_custom_error!(be_u8, CRC8Parser),
-    || {
-      let (block_byte, sample_byte)                 = tuple0;
-      let (channel_assignment, channels, size_byte) = tuple1;
-
-      let block_size = match block_byte {
-        0b0001          => 192,
-        0b0010...0b0101 => 576 * power_of_two(block_byte as u32 - 2),
-        0b0110 | 0b0111 => alt_block_size.unwrap() + 1,
-        0b1000...0b1111 => 256 * power_of_two(block_byte as u32 - 8),
-        _               => unreachable!(),
-      };
-
-      let sample_rate = match sample_byte {
-        0b0000 => stream_info.sample_rate,
-        0b0001 => 88200,
-        0b0010 => 176400,
-        0b0011 => 192000,
-        0b0100 => 8000,
-        0b0101 => 16000,
-        0b0110 => 22050,
-        0b0111 => 24000,
-        0b1000 => 32000,
-        0b1001 => 44100,
-        0b1010 => 48000,
-        0b1011 => 96000,
-        0b1100 => alt_sample_rate.unwrap() * 1000,
-        0b1101 => alt_sample_rate.unwrap(),
-        0b1110 => alt_sample_rate.unwrap() * 10,
-        _      => unreachable!(),
-      };
-
-      let bits_per_sample = match size_byte {
-        0b0000 => stream_info.bits_per_sample as usize,
-        0b0001 => 8,
-        0b0010 => 12,
-        0b0100 => 16,
-        0b0101 => 20,
-        0b0110 => 24,
-        _      => unreachable!(),
-      };
-
-      Header {
-        block_size: block_size,
-        sample_rate: sample_rate,
-        channels: channels,
-        channel_assignment: channel_assignment,
-        bits_per_sample: bits_per_sample,
-        number: number,
-        crc: crc,
-      }
-    }
-  );
-
-  match result {
-    IResult::Done(i, frame_header) => {
-      // All header bytes before the crc-8
-      let end = (input.len() - i.len()) - 1;
-
-      if crc8(&input[0..end]) == frame_header.crc {
-        IResult::Done(i, frame_header)
-      } else {
-        IResult::Error(Err::Position(
-          nom::ErrorKind::Custom(ErrorKind::InvalidCRC8), input))
-      }
+  let (i, is_variable_block_size)    = try_parser!(blocking_strategy(input));
+  let (i, (block_byte, sample_byte)) = try_parser! {
+    needed_from(&input, &i, block_sample(i))
+  };
+  let (i, channel_tuple)             = try_parser! {
+    needed_from(&input, &i, channel_bits(i))
+  };
+  let (i, utf8_header_opt)           = try_parser! {
+    needed_from(&input, &i, utf8_header(i, is_variable_block_size))
+  };
+
+  let utf8_header_val = match utf8_header_opt {
+    Some(value) => value,
+    None        => {
+      return IResult::Error(Err::Position(nom::ErrorKind::ExprOpt, i));
     }
-    IResult::Error(error)          => IResult::Error(error),
-    IResult::Incomplete(need)      => IResult::Incomplete(need),
+  };
+
+  let (i, number)          = try_parser! {
+    needed_from(&input, &i,
+                number_type(i, is_variable_block_size, utf8_header_val))
+  };
+  let (i, alt_block_size)  = try_parser! {
+    needed_from(&input, &i, secondary_block_size(i, block_byte))
+  };
+  let (i, alt_sample_rate) = try_parser! {
+    needed_from(&input, &i, secondary_sample_rate(i, sample_byte))
+  };
+  let (i, crc)             = try_parser! {
+    needed_from(&input, &i, be_u8(i).map_err(to_custom_error!(CRC8Parser)))
+  };
+
+  let (channel_assignment, channels, size_byte) = channel_tuple;
+
+  let stream_rate = stream_info.sample_rate.get();
+  let stream_size = stream_info.bits_per_sample.get() as usize;
+
+  // Every code without a value was turned away while parsing, and the codes
+  // that need one had it parsed from the end of the header.
+  let block_size      = codes::block_size_from_code(block_byte,
+                                                    alt_block_size)
+                          .unwrap();
+  let sample_rate     = codes::sample_rate_from_code(sample_byte,
+                                                     alt_sample_rate,
+                                                     stream_rate)
+                          .unwrap();
+  let bits_per_sample = codes::sample_size_from_code(size_byte, stream_size)
+                          .and_then(|size| BitsPerSample::new(size as u8))
+                          .unwrap();
+
+  // All header bytes before the crc-8
+  let end = (input.len() - i.len()) - 1;
+
+  if crc8(&input[0..end]) != crc {
+    return IResult::Error(Err::Position(
+      nom::ErrorKind::Custom(ErrorKind::InvalidCRC8), input));
+  }
+
+  // A sample rate stored at the end of the header can be zero.
+  if let Some(sample_rate) = SampleRate::new(sample_rate) {
+    IResult::Done(i, Header {
+      block_size: block_size,
+      sample_rate: sample_rate,
+      channels: channels,
+      channel_assignment: channel_assignment,
+      bits_per_sample: bits_per_sample,
+      number: number,
+      crc: crc,
+    })
+  } else {
+    IResult::Error(Err::Position(
+      nom::ErrorKind::Custom(ErrorKind::SampleRateParser), input))
   }
 }
 
 pub fn footer(input: &[u8]) -> IResult<&[u8], Footer, ErrorKind> {
-  to_custom_error!(input, map!(be_u16, Footer), FrameFooterParser)
+  be_u16(input).map(Footer).map_err(to_custom_error!(FrameFooterParser))
 }
 
 #[cfg(test)]
@@ -460,40 +530,59 @@ mod tests {
     let mut info: StreamInfo = Default::default();
     let results  = [ IResult::Done(&[][..], Header {
                        block_size: 4608,
-                       sample_rate: 192000,
+                       sample_rate: SampleRate::new(192000).unwrap(),
                        channels: 2,
                        channel_assignment: ChannelAssignment::Independent,
-                       bits_per_sample: 24,
+                       bits_per_sample: BitsPerSample::new(24).unwrap(),
                        number: NumberType::Frame(65536),
                        crc: 0x2e,
                      })
                    , IResult::Done(&[][..], Header {
                        block_size: 512,
-                       sample_rate: 1000,
+                       sample_rate: SampleRate::new(1000).unwrap(),
                        channels: 2,
                        channel_assignment: ChannelAssignment::MidpointSide,
-                       bits_per_sample: 16,
+                       bits_per_sample: BitsPerSample::new(16).unwrap(),
                        number: NumberType::Sample(68719476732),
                        crc: 0x88,
                      })
                    , IResult::Done(&[][..], Header {
                        block_size: 4096,
-                       sample_rate: 32000,
+                       sample_rate: SampleRate::new(32000).unwrap(),
                        channels: 8,
                        channel_assignment: ChannelAssignment::Independent,
-                       bits_per_sample: 8,
+                       bits_per_sample: BitsPerSample::new(8).unwrap(),
                        number: NumberType::Frame(64),
                        crc: 0x19,
                      })
                   ];
 
-    info.bits_per_sample = 16;
+    info.bits_per_sample = BitsPerSample::new(16).unwrap();
 
     assert_eq!(header(inputs[0], &info), results[0]);
     assert_eq!(header(inputs[1], &info), results[1]);
     assert_eq!(header(inputs[2], &info), results[2]);
   }
 
+  #[test]
+  fn test_block_size_past_buffer() {
+    let input    = &b"\xff\xf8\x53\x1c\xf0\x90\x80\x80\x2e\x00\x00"[..];
+    let mut info = StreamInfo::default();
+
+    info.channels        = 2;
+    info.bits_per_sample = BitsPerSample::new(24).unwrap();
+
+    // The header claims 4608 samples for each of the two channels, which
+    // is twice what the buffer holds.
+    let mut buffer = vec![0; 4608];
+    let kind       = nom::ErrorKind::Custom(ErrorKind::InvalidBlockSize);
+
+    match frame_parser(input, &info, &mut buffer[..]) {
+      IResult::Error(Err::Position(error, _)) => assert_eq!(error, kind),
+      _                                       => panic!("frame was parsed"),
+    }
+  }
+
   #[test]
   fn test_footer() {
     let input  = b"\x03\xe8";
@@ -1,4 +1,5 @@
 use subframe::Subframe;
+use utility::{BitsPerSample, SampleRate};
 
 /// Maximum number of channels supported in the FLAC format.
 pub const MAX_CHANNELS: usize = 8;
@@ -41,13 +42,13 @@ pub struct Header {
   /// Number of samples per subframe.
   pub block_size: u32,
   /// Sample rate in hertz (Hz).
-  pub sample_rate: u32,
+  pub sample_rate: SampleRate,
   /// Number of channels that also represent the number of subframes.
   pub channels: u8,
   /// Channel assignment order.
   pub channel_assignment: ChannelAssignment,
   /// Size, in bits, per sample.
-  pub bits_per_sample: usize,
+  pub bits_per_sample: BitsPerSample,
   /// Numbering scheme used from the frame.
   pub number: NumberType,
   /// CRC-8 of all header bytes before this crc.
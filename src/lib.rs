@@ -26,21 +26,91 @@
 //!   Err(error)     => println!("{:?}", error),
 //! }
 //! ```
+//!
+//! Or, when everything fits in memory, decode the entire file at once.
+//!
+//! ```
+//! match flac::read("path/to/file.flac") {
+//!   Ok((info, samples)) => {
+//!     // Samples are interleaved by channel.
+//!   }
+//!   Err(error)          => println!("{:?}", error),
+//! }
+//! ```
+//!
+//! # Malformed Input
+//!
+//! Data that is corrupt or cut short is met with errors rather than made
+//! up samples. Metadata that can't be parsed fails the constructor, while a
+//! bad frame ends the iterator early, which `Iter::is_total_samples_wrong`
+//! points out when the total samples are known. With a concealer set
+//! through `Stream::set_concealer`, bad frames get replaced instead, see
+//! `flac::conceal`.
+//!
+//! # Determinism
+//!
+//! Decoded samples are bit for bit the same on every platform and with any
+//! optimization settings, so an MD5 taken over them is too. The same goes
+//! for the conversions to 16-bit and floating point samples.
 
 #[macro_use]
 extern crate nom;
+#[cfg(feature = "bytes")]
+extern crate bytes as shared_bytes;
+#[cfg(feature = "async")]
+extern crate futures;
+#[cfg(feature = "playback")]
+extern crate cpal;
 
 #[macro_use]
 mod utility;
 mod frame;
 mod subframe;
+pub mod builder;
+pub mod bytes;
+pub mod codes;
+pub mod crc;
 pub mod metadata;
 pub mod stream;
+pub mod encoder;
+pub mod digest;
+pub mod conceal;
+pub mod crossfade;
+pub mod sink;
+pub mod pcm;
+pub mod prefetch;
+pub mod prelude;
+pub mod sanitize;
+#[cfg(feature = "cue-sheet")]
+pub mod cue;
+#[cfg(feature = "http")]
+pub mod net;
+#[cfg(feature = "corrupt")]
+pub mod corrupt;
+#[cfg(feature = "async")]
+pub mod async_stream;
+#[cfg(feature = "playback")]
+pub mod play;
+mod queue;
+mod simple;
 
+pub use encoder::{
+  Encoder, EncoderOptions, InputError, SeekPointInterval, StereoMode,
+  StreamInfoAccumulator, max_audio_size, DEFAULT_VENDOR, SETTINGS_COMMENT,
+};
 pub use metadata::Metadata;
-pub use stream::{Stream, StreamBuffer, StreamReader};
+#[cfg(feature = "playback")]
+pub use play::play;
+pub use sanitize::sanitize;
+pub use simple::{read, read_from};
+#[cfg(feature = "vorbis-comment")]
+pub use simple::{tags, tags_from};
+pub use stream::{
+  FlacReader, Stream, StreamBuffer, StreamOptions, StreamReader,
+  ChannelOrder, DecodeProgress, DecodeStats, CancelHandle,
+};
 pub use utility::{
-  Sample, SampleSize,
-  StreamProducer, ReadStream, ByteStream,
-  ErrorKind
+  Sample, SampleSize, BitsPerSample, SampleRate,
+  StreamProducer, SeekableProducer, ReadStream, ByteStream,
+  DecodeContext, ErrorKind
 };
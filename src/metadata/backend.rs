@@ -0,0 +1,393 @@
+// Parsing backends for metadata blocks.
+//
+// Every parser in the crate is built on nom, which shows up in the public
+// API only through `StreamProducer::parse` and the `*_parser` functions.
+// Parsers written against `MetadataBackend` don't depend on nom, so each
+// block can move off of it one at a time. The `native-metadata` feature
+// switches metadata parsing over to `NativeBackend`, which still falls
+// back on nom for the blocks it doesn't handle yet.
+
+use nom::{self, IResult, Needed, Err};
+
+#[cfg(any(test, feature = "native-metadata"))]
+use std::str;
+
+use metadata::{Metadata, Data};
+#[cfg(any(test, feature = "native-metadata"))]
+use metadata::{
+  StreamInfo, Application, SeekPoint, PlaceholderPolicy, Payload,
+};
+use metadata::parser;
+use utility::{DecodeContext, ErrorKind};
+#[cfg(any(test, feature = "native-metadata"))]
+use utility::{BitsPerSample, SampleRate, to_u32};
+
+// Size, in bytes, of the header in front of every metadata block.
+const HEADER_SIZE: usize = 4;
+
+// Size, in bytes, of the `StreamInfo` block.
+#[cfg(any(test, feature = "native-metadata"))]
+const STREAM_INFO_SIZE: usize = 34;
+
+// Size, in bytes, of each seek point within a `SeekTable`.
+#[cfg(any(test, feature = "native-metadata"))]
+const SEEK_POINT_SIZE: usize = 18;
+
+// Result of a parser that doesn't depend on nom.
+#[derive(Debug, PartialEq)]
+pub enum Parsed<'a, T> {
+  // Parsed value and the bytes after it.
+  Done(&'a [u8], T),
+  // Number of bytes, from the start of the input, needed to finish.
+  Incomplete(usize),
+  Error(ErrorKind),
+}
+
+impl<'a, T> Parsed<'a, T> {
+  pub fn from_iresult(input: &'a [u8], result: IResult<&'a [u8], T, ErrorKind>)
+                      -> Self {
+    match result {
+      IResult::Done(i, o)                  => Parsed::Done(i, o),
+      IResult::Incomplete(Needed::Size(n)) => Parsed::Incomplete(n),
+      IResult::Incomplete(_)               => {
+        Parsed::Incomplete(input.len() + 1)
+      }
+      IResult::Error(error)                => {
+        match error {
+          Err::Code(k)               |
+          Err::Node(k, _)            |
+          Err::Position(k, _)        |
+          Err::NodePosition(k, _, _) => {
+            if let nom::ErrorKind::Custom(kind) = k {
+              Parsed::Error(kind)
+            } else {
+              Parsed::Error(ErrorKind::Unknown)
+            }
+          }
+        }
+      }
+    }
+  }
+
+  pub fn map<U, F>(self, f: F) -> Parsed<'a, U>
+   where F: FnOnce(T) -> U {
+    match self {
+      Parsed::Done(i, o)         => Parsed::Done(i, f(o)),
+      Parsed::Incomplete(needed) => Parsed::Incomplete(needed),
+      Parsed::Error(kind)        => Parsed::Error(kind),
+    }
+  }
+
+  pub fn into_iresult(self) -> IResult<&'a [u8], T, ErrorKind> {
+    match self {
+      Parsed::Done(i, o)         => IResult::Done(i, o),
+      Parsed::Incomplete(needed) => IResult::Incomplete(Needed::Size(needed)),
+      Parsed::Error(kind)        => {
+        IResult::Error(Err::Code(nom::ErrorKind::Custom(kind)))
+      }
+    }
+  }
+}
+
+// Parser for the header and data of a single metadata block.
+pub trait MetadataBackend {
+  // Parses the block header into whether it's the last block, the block
+  // type, and the length of the data that follows.
+  fn header(input: &[u8]) -> Parsed<(bool, u8, u32)>;
+
+  // Parses the data of a block, with the header already parsed.
+  fn block_data<'a>(input: &'a [u8], block_type: u8, length: u32,
+                    context: &DecodeContext)
+                    -> Parsed<'a, Data>;
+
+  fn metadata<'a>(input: &'a [u8], context: &DecodeContext)
+                  -> Parsed<'a, Metadata> {
+    let (i, (is_last, block_type, length)) = match Self::header(input) {
+      Parsed::Done(i, header)    => (i, header),
+      Parsed::Incomplete(needed) => return Parsed::Incomplete(needed),
+      Parsed::Error(kind)        => return Parsed::Error(kind),
+    };
+
+    match Self::block_data(i, block_type, length, context) {
+      Parsed::Incomplete(needed) => Parsed::Incomplete(HEADER_SIZE + needed),
+      parsed                     => {
+        parsed.map(|data| Metadata::new(is_last, length, data))
+      }
+    }
+  }
+}
+
+// The nom parsers within `metadata::parser`.
+pub struct NomBackend;
+
+impl MetadataBackend for NomBackend {
+  fn header(input: &[u8]) -> Parsed<(bool, u8, u32)> {
+    Parsed::from_iresult(input, parser::header(input))
+  }
+
+  fn block_data<'a>(input: &'a [u8], block_type: u8, length: u32,
+                    context: &DecodeContext)
+                    -> Parsed<'a, Data> {
+    let result = parser::block_data(input, block_type, length, context);
+
+    Parsed::from_iresult(input, result)
+  }
+}
+
+// Hand written parsers, which fall back on `NomBackend` for vorbis comment,
+// cue sheet, and picture blocks.
+//
+// Unlike the nom parsers, the whole length of every block gets consumed,
+// even when the data within it is shorter.
+#[cfg(any(test, feature = "native-metadata"))]
+pub struct NativeBackend;
+
+#[cfg(any(test, feature = "native-metadata"))]
+impl MetadataBackend for NativeBackend {
+  fn header(input: &[u8]) -> Parsed<(bool, u8, u32)> {
+    if input.len() < HEADER_SIZE {
+      return Parsed::Incomplete(HEADER_SIZE);
+    }
+
+    let is_last    = (input[0] >> 7) == 1;
+    let block_type = input[0] & 0b01111111;
+    let length     = to_u32(&input[1..4]);
+
+    Parsed::Done(&input[HEADER_SIZE..], (is_last, block_type, length))
+  }
+
+  fn block_data<'a>(input: &'a [u8], block_type: u8, length: u32,
+                    context: &DecodeContext)
+                    -> Parsed<'a, Data> {
+    let len = length as usize;
+
+    if len > input.len() {
+      return Parsed::Incomplete(len);
+    }
+
+    let (data, rest) = input.split_at(len);
+
+    let result = match block_type {
+      0       => stream_info(data),
+      1       => Ok(Data::Padding(length)),
+      2       => application(data),
+      3       => Ok(seek_table(data, context.placeholder_policy)),
+      4...6   => {
+        return NomBackend::block_data(input, block_type, length, context);
+      }
+      7...126 => Ok(Data::Unknown(block_type, Payload::from(data))),
+      _       => Err(ErrorKind::InvalidBlockType),
+    };
+
+    match result {
+      Ok(data)  => Parsed::Done(rest, data),
+      Err(kind) => Parsed::Error(kind),
+    }
+  }
+}
+
+#[cfg(any(test, feature = "native-metadata"))]
+fn stream_info(data: &[u8]) -> Result<Data, ErrorKind> {
+  if data.len() < STREAM_INFO_SIZE {
+    return Err(ErrorKind::StreamInfoParser);
+  }
+
+  let bytes       = &data[10..18];
+  let mut md5_sum = [0; 16];
+
+  md5_sum.copy_from_slice(&data[18..34]);
+
+  let sample_rate     = ((bytes[0] as u32) << 12) +
+                        ((bytes[1] as u32) << 4)  +
+                        ((bytes[2] as u32) >> 4);
+  let channels        = (bytes[2] >> 1) & 0b0111;
+  let bits_per_sample = ((bytes[2] & 0b01) << 4) + (bytes[3] >> 4);
+  let total_samples   = (((bytes[3] as u64) & 0x0f) << 32) +
+                        ((to_u32(&bytes[4..8]) as u64));
+
+  // Rates and sizes FLAC doesn't support fail the parser.
+  let sample_rate     = try!(SampleRate::new(sample_rate)
+                               .ok_or(ErrorKind::StreamInfoParser));
+  let bits_per_sample = try!(BitsPerSample::new(bits_per_sample + 1)
+                               .ok_or(ErrorKind::StreamInfoParser));
+
+  Ok(Data::StreamInfo(StreamInfo {
+    min_block_size: to_u32(&data[0..2]) as u16,
+    max_block_size: to_u32(&data[2..4]) as u16,
+    min_frame_size: to_u32(&data[4..7]),
+    max_frame_size: to_u32(&data[7..10]),
+    sample_rate: sample_rate,
+    channels: channels + 1,
+    bits_per_sample: bits_per_sample,
+    total_samples: total_samples,
+    md5_sum: md5_sum,
+  }))
+}
+
+#[cfg(any(test, feature = "native-metadata"))]
+fn application(data: &[u8]) -> Result<Data, ErrorKind> {
+  // Too short to even hold the application identifier.
+  if data.len() < 4 {
+    return Err(ErrorKind::ApplicationParser);
+  }
+
+  let id = try!(str::from_utf8(&data[0..4])
+                  .map_err(|_| ErrorKind::ApplicationParser));
+
+  Ok(Data::Application(Application {
+    id: id.to_owned(),
+    data: Payload::from(&data[4..]),
+  }))
+}
+
+#[cfg(any(test, feature = "native-metadata"))]
+fn seek_table(data: &[u8], policy: PlaceholderPolicy) -> Data {
+  let mut is_placeholder_found = false;
+
+  let seek_points = data.chunks(SEEK_POINT_SIZE)
+                        .filter(|bytes| bytes.len() == SEEK_POINT_SIZE)
+                        .map(|bytes| {
+    let sample_number = ((to_u32(&bytes[0..4]) as u64) << 32) +
+                        (to_u32(&bytes[4..8]) as u64);
+    let stream_offset = ((to_u32(&bytes[8..12]) as u64) << 32) +
+                        (to_u32(&bytes[12..16]) as u64);
+
+    SeekPoint {
+      sample_number: sample_number,
+      stream_offset: stream_offset,
+      frame_samples: to_u32(&bytes[16..18]) as u16,
+    }
+  }).filter(|seek_point| {
+    if !seek_point.is_placeholder() {
+      return true;
+    }
+
+    let is_kept = match policy {
+      PlaceholderPolicy::Keep    => true,
+      PlaceholderPolicy::Drop    => false,
+      PlaceholderPolicy::Compact => !is_placeholder_found,
+    };
+
+    is_placeholder_found = true;
+
+    is_kept
+  });
+
+  Data::SeekTable(seek_points.collect())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use utility::ErrorKind;
+
+  use std::fs::File;
+  use std::io::Read;
+
+  // Parses every metadata block after the "fLaC" header.
+  fn blocks<B: MetadataBackend>(bytes: &[u8], policy: PlaceholderPolicy)
+                                -> Vec<(bool, Data)> {
+    let mut input  = &bytes[4..];
+    let mut blocks = Vec::new();
+    let context    = DecodeContext::new().with_max_comments(usize::max_value())
+                                         .with_placeholder_policy(policy);
+
+    loop {
+      match B::metadata(input, &context) {
+        Parsed::Done(i, block) => {
+          let is_last = block.is_last();
+
+          blocks.push((is_last, block.data));
+
+          input = i;
+
+          if is_last {
+            break;
+          }
+        }
+        result                 => panic!("{:?}", result.map(|_| ())),
+      }
+    }
+
+    blocks
+  }
+
+  #[test]
+  fn test_backends() {
+    let filenames = [
+      "tests/assets/input-pictures.flac",
+      "tests/assets/input-SCPAP.flac",
+      "tests/assets/input-SVAUP.flac",
+    ];
+
+    for filename in &filenames {
+      let mut file  = File::open(filename).unwrap();
+      let mut bytes = Vec::new();
+
+      file.read_to_end(&mut bytes).unwrap();
+
+      for &policy in &[PlaceholderPolicy::Keep, PlaceholderPolicy::Drop,
+                       PlaceholderPolicy::Compact] {
+        assert_eq!(blocks::<NativeBackend>(&bytes, policy),
+                   blocks::<NomBackend>(&bytes, policy));
+      }
+    }
+  }
+
+  #[test]
+  fn test_long_stream_info() {
+    let mut file  = File::open("tests/assets/input-SCPAP.flac").unwrap();
+    let mut bytes = Vec::new();
+
+    file.read_to_end(&mut bytes).unwrap();
+
+    let expected = blocks::<NomBackend>(&bytes, PlaceholderPolicy::Keep);
+
+    // `StreamInfo` declaring four bytes past the 34 it uses, which both
+    // backends skip over.
+    bytes[7] = 38;
+    bytes.splice(42..42, vec![0xff; 4]);
+
+    let policy = PlaceholderPolicy::Keep;
+
+    assert_eq!(blocks::<NomBackend>(&bytes, policy), expected);
+    assert_eq!(blocks::<NativeBackend>(&bytes, policy), expected);
+
+    let context = DecodeContext::new();
+
+    assert_eq!(NomBackend::metadata(b"\0\0\0\x02\0\0", &context)
+                 .map(|block| block.data),
+               Parsed::Error(ErrorKind::StreamInfoParser));
+  }
+
+  fn parse(input: &[u8]) -> Parsed<Data> {
+    let context = DecodeContext::new().with_max_comments(0);
+
+    NativeBackend::metadata(input, &context).map(|block| block.data)
+  }
+
+  #[test]
+  fn test_native_backend() {
+    let header = b"\x03\0\0\x14";
+    let table  = b"\0\0\0\0\0\0\x10\0\0\0\0\0\0\0\x02\0\x10\0\xff\xff";
+    let input  = [&header[..], &table[..]].concat();
+
+    let seek_table = Data::SeekTable(vec![SeekPoint {
+      sample_number: 4096,
+      stream_offset: 512,
+      frame_samples: 4096,
+    }]);
+
+    // The two bytes left over from the seek points still get consumed.
+    assert_eq!(parse(&input), Parsed::Done(&[][..], seek_table));
+    assert_eq!(parse(&input[0..10]), Parsed::Incomplete(24));
+    assert_eq!(parse(&input[0..2]), Parsed::Incomplete(4));
+    assert_eq!(parse(b"\0\0\0\x10"), Parsed::Incomplete(20));
+    assert_eq!(parse(b"\0\0\0\x02\0\0"),
+               Parsed::Error(ErrorKind::StreamInfoParser));
+    assert_eq!(parse(b"\x7f\0\0\0"),
+               Parsed::Error(ErrorKind::InvalidBlockType));
+    assert_eq!(parse(b"\x02\0\0\x04\xff\xfe\0\0"),
+               Parsed::Error(ErrorKind::ApplicationParser));
+  }
+}
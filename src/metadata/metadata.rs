@@ -1,17 +1,43 @@
-use std::io;
-use std::u32;
-use std::fs::File;
+use std::io::{
+  self, BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write,
+};
+#[cfg(feature = "picture")]
+use std::{cmp, u16, u32};
+use std::fs::{self, File, OpenOptions};
 
-use utility::{ErrorKind, ReadStream, many_metadata};
+use utility::{DecodeContext, ErrorKind, ReadStream, many_metadata, to_u32};
 
-use metadata::{
-  Metadata, Data,
-  StreamInfo, CueSheet, VorbisComment, Picture,
-  PictureType,
-};
+use metadata::{Metadata, StreamInfo, Type};
+#[cfg(feature = "vorbis-comment")]
+use metadata::VorbisComment;
+#[cfg(feature = "cue-sheet")]
+use metadata::CueSheet;
+#[cfg(feature = "picture")]
+use metadata::{Data, Picture, PictureType, PictureSelector};
+#[cfg(feature = "picture")]
+use metadata::parser;
+
+use digest::Digest;
+#[cfg(feature = "md5")]
+use digest::Md5;
+use encoder::StreamInfoAccumulator;
+use sink::{DigestSink, Sink, TeeSink};
+use stream::{StreamOptions, StreamReader};
+
+// Largest length, in bytes, of the data within a metadata block.
+#[cfg(feature = "picture")]
+const MAX_BLOCK_LENGTH: usize = 0xffffff;
+
+// Size, in bytes, of the data within `StreamInfo`.
+const STREAM_INFO_SIZE: usize = 34;
+
+// Offset, in bytes, to the end of `StreamInfo`, right after the FLAC header
+// and the block header in front of it.
+const STREAM_INFO_END: usize = 42;
 
 // Will return true when the unwrapped value of `option` and `other` match
 // or `option` is `Option::None`, otherwise false.
+#[cfg(feature = "picture")]
 #[inline]
 pub fn optional_eq<T: Eq>(option: Option<T>, other: T) -> bool {
   option.map_or(true, |value| value == other)
@@ -36,7 +62,9 @@ pub fn get_metadata(filename: &str) -> Result<Vec<Metadata>, ErrorKind> {
     let mut stream   = ReadStream::new(file);
     let mut metadata = Vec::new();
 
-    let result = many_metadata(&mut stream, |block| metadata.push(block));
+    let result = many_metadata(&mut stream, &DecodeContext::new(), |block| {
+      metadata.push(block)
+    });
 
     if let Err(kind) = result {
       match kind {
@@ -92,7 +120,7 @@ pub fn get_stream_info(filename: &str) -> Result<StreamInfo, ErrorKind> {
     let mut result = Err(ErrorKind::NotFound);
 
     for block in blocks {
-      if let Data::StreamInfo(stream_info) = block.data {
+      if let Ok(stream_info) = block.into_stream_info() {
         result = Ok(stream_info);
         break;
       }
@@ -139,13 +167,14 @@ pub fn get_stream_info(filename: &str) -> Result<StreamInfo, ErrorKind> {
 /// let vorbis_comment =
 ///   metadata::get_vorbis_comment("path/to/file.flac").unwrap();
 /// ```
+#[cfg(feature = "vorbis-comment")]
 pub fn get_vorbis_comment(filename: &str)
                           -> Result<VorbisComment, ErrorKind> {
   get_metadata(filename).and_then(|blocks| {
     let mut result = Err(ErrorKind::NotFound);
 
     for block in blocks {
-      if let Data::VorbisComment(vorbis_comment) = block.data {
+      if let Ok(vorbis_comment) = block.into_vorbis_comment() {
         result = Ok(vorbis_comment);
         break;
       }
@@ -190,12 +219,13 @@ pub fn get_vorbis_comment(filename: &str)
 ///
 /// let cue_sheet = metadata::get_cue_sheet("path/to/file.flac").unwrap();
 /// ```
+#[cfg(feature = "cue-sheet")]
 pub fn get_cue_sheet(filename: &str) -> Result<CueSheet, ErrorKind> {
   get_metadata(filename).and_then(|blocks| {
     let mut result = Err(ErrorKind::NotFound);
 
     for block in blocks {
-      if let Data::CueSheet(cue_sheet) = block.data {
+      if let Ok(cue_sheet) = block.into_cue_sheet() {
         result = Ok(cue_sheet);
         break;
       }
@@ -261,6 +291,7 @@ pub fn get_cue_sheet(filename: &str) -> Result<CueSheet, ErrorKind> {
 ///                                     None, None, None,
 ///                                     None, None).unwrap();
 /// ```
+#[cfg(feature = "picture")]
 pub fn get_picture(filename: &str,
                    picture_type: Option<PictureType>,
                    mime_type: Option<&str>,
@@ -283,7 +314,7 @@ pub fn get_picture(filename: &str,
     let max_colors_num = max_colors.unwrap_or(max_value);
 
     for block in blocks {
-      if let Data::Picture(picture) = block.data {
+      if let Ok(picture) = block.into_picture() {
         let area = (picture.width as u64) * (picture.height as u64);
 
         if optional_eq(picture_type, picture.picture_type) &&
@@ -306,6 +337,598 @@ pub fn get_picture(filename: &str,
   })
 }
 
+/// Replaces the `Picture` picked out by `selector` within the given FLAC
+/// file with `picture`.
+///
+/// When `selector` is a `PictureSelector::Type` that no picture matches,
+/// `picture` gets added after the last `Picture` instead, or after every
+/// other block when there isn't one. Only the first picture of the type is
+/// replaced.
+///
+/// Padding takes up whatever space the new picture frees up or needs, so
+/// only the metadata blocks get rewritten, in place, as long as they still
+/// fit in front of the audio frames. Otherwise the whole file is written
+/// next to the original, with ".picture" added to the end of the name,
+/// before it replaces the original.
+///
+/// # Failures
+///
+/// * `ErrorKind::NotFound` is returned when `selector` is an index past the
+///   last `Picture`.
+/// * `ErrorKind::IO(io::ErrorKind::NotFound)` is returned when the given
+///   filename isn't found.
+/// * `ErrorKind::IO(io::ErrorKind::InvalidData)` is returned when the file
+///   doesn't start with a FLAC header followed by `StreamInfo`, or a block
+///   has an invalid type.
+/// * `ErrorKind::IO(io::ErrorKind::InvalidInput)` is returned when the
+///   picture is larger than a metadata block can hold.
+/// * Any other error from reading, writing, or renaming the files.
+///
+/// # Examples
+///
+/// ```no_run
+/// use flac::metadata::{self, Picture, PictureSelector, PictureType};
+///
+/// let mut picture = metadata::get_picture("path/to/file.flac",
+///                                         Some(PictureType::FrontCover),
+///                                         None, None, None, None, None,
+///                                         None).unwrap();
+///
+/// picture.description = "Front cover".to_owned();
+///
+/// let selector = PictureSelector::Type(PictureType::FrontCover);
+///
+/// metadata::set_picture("path/to/file.flac", selector, picture).unwrap();
+/// ```
+#[cfg(feature = "picture")]
+pub fn set_picture(filename: &str, selector: PictureSelector,
+                   picture: Picture)
+                   -> Result<(), ErrorKind> {
+  let length    = picture.bytes_len() as u32;
+  let mut block = Vec::new();
+
+  try!(Metadata::new(false, length, Data::Picture(picture))
+         .to_bytes(&mut block)
+         .map_err(|e| ErrorKind::IO(e.kind())));
+
+  edit_blocks(filename, move |blocks| {
+    let positions = select_pictures(blocks, selector);
+
+    if let Some(&position) = positions.first() {
+      blocks[position] = block;
+    } else if let PictureSelector::Type(_) = selector {
+      let position = blocks.iter().rposition(|block| is_picture(block))
+                                  .map_or(blocks.len(), |i| i + 1);
+
+      blocks.insert(position, block);
+    } else {
+      return Err(ErrorKind::NotFound);
+    }
+
+    Ok(())
+  })
+}
+
+/// Removes every `Picture` picked out by `selector` from the given FLAC
+/// file.
+///
+/// The space the pictures took up becomes padding, so only the metadata
+/// blocks get rewritten, in place, without moving the audio frames.
+///
+/// # Failures
+///
+/// * `ErrorKind::NotFound` is returned when no `Picture` matches
+///   `selector`, where the file is left untouched.
+/// * `ErrorKind::IO(io::ErrorKind::NotFound)` is returned when the given
+///   filename isn't found.
+/// * `ErrorKind::IO(io::ErrorKind::InvalidData)` is returned when the file
+///   doesn't start with a FLAC header followed by `StreamInfo`, or a block
+///   has an invalid type.
+/// * Any other error from reading or writing the file.
+///
+/// # Examples
+///
+/// ```no_run
+/// use flac::metadata::{self, PictureSelector, PictureType};
+///
+/// let selector = PictureSelector::Type(PictureType::BackCover);
+///
+/// metadata::remove_picture("path/to/file.flac", selector).unwrap();
+/// ```
+#[cfg(feature = "picture")]
+pub fn remove_picture(filename: &str, selector: PictureSelector)
+                      -> Result<(), ErrorKind> {
+  edit_blocks(filename, |blocks| {
+    let positions = select_pictures(blocks, selector);
+
+    if positions.is_empty() {
+      return Err(ErrorKind::NotFound);
+    }
+
+    for &position in positions.iter().rev() {
+      blocks.remove(position);
+    }
+
+    Ok(())
+  })
+}
+
+/// Removes every metadata block from the given FLAC file, other than
+/// `StreamInfo` and the block types within `keep`.
+///
+/// Kept blocks are copied over byte for byte in their original order, and
+/// the audio frames get copied after them without being decoded. The new
+/// file is written next to the original, with ".strip" added to the end of
+/// the name, before it replaces the original. Blocks of any type that
+/// isn't known are kept with `Type::Unknown`.
+///
+/// # Failures
+///
+/// * `ErrorKind::IO(io::ErrorKind::NotFound)` is returned when the given
+///   filename isn't found.
+/// * `ErrorKind::IO(io::ErrorKind::InvalidData)` is returned when the file
+///   doesn't start with a FLAC header followed by `StreamInfo`, or a block
+///   has an invalid type.
+/// * Any other error from reading, writing, or renaming the files, where
+///   the original file is left untouched.
+///
+/// # Examples
+///
+/// ```no_run
+/// use flac::metadata::{self, Type};
+///
+/// // Leaves only StreamInfo and the seek table.
+/// metadata::strip("path/to/file.flac", &[Type::SeekTable]).unwrap();
+/// ```
+pub fn strip(filename: &str, keep: &[Type]) -> Result<(), ErrorKind> {
+  let stripped = format!("{}.strip", filename);
+
+  let result = File::open(filename).and_then(|file| {
+    let output = try!(File::create(&stripped));
+
+    strip_blocks(BufReader::new(file), BufWriter::new(output), keep)
+  }).and_then(|_| fs::rename(&stripped, filename));
+
+  if result.is_err() {
+    let _ = fs::remove_file(&stripped);
+  }
+
+  result.map_err(|e| ErrorKind::IO(e.kind()))
+}
+
+/// Copies a FLAC stream from `input` to `output`, leaving out every
+/// metadata block other than `StreamInfo` and the block types within
+/// `keep`.
+///
+/// This is `metadata::strip` without going through the file system, for
+/// sandboxes, like WASI, where files can't be renamed.
+///
+/// # Failures
+///
+/// * `ErrorKind::IO(io::ErrorKind::InvalidData)` is returned when the
+///   stream doesn't start with a FLAC header followed by `StreamInfo`, or a
+///   block has an invalid type.
+/// * Any other error from reading or writing, after which `output` holds
+///   whatever was written up to that point.
+pub fn strip_stream<R, W>(input: R, output: W, keep: &[Type])
+                          -> Result<(), ErrorKind>
+ where R: Read,
+       W: Write {
+  strip_blocks(input, output, keep).map_err(|e| ErrorKind::IO(e.kind()))
+}
+
+/// Decodes the audio of the given FLAC file once and rewrites its
+/// `StreamInfo` in place, with the MD5 signature, total samples, and the
+/// block and frame sizes recomputed from the frames.
+///
+/// This is for files whose frames were edited, like by cutting or joining
+/// them, without `StreamInfo` getting updated. Only the 34 bytes of
+/// `StreamInfo` get written, so the rest of the file is left untouched, and
+/// the new `StreamInfo` is returned.
+///
+/// # Failures
+///
+/// * `ErrorKind::IO(io::ErrorKind::NotFound)` is returned when the given
+///   filename isn't found.
+/// * `ErrorKind::IO(io::ErrorKind::InvalidData)` is returned when the file
+///   doesn't start with a FLAC header followed by `StreamInfo`.
+/// * `ErrorKind::InvalidBlockSize` is returned when a frame holds more
+///   samples than `StreamInfo` can hold as a block size.
+/// * Any error from decoding a frame, where the file is left untouched.
+///
+/// # Examples
+///
+/// ```no_run
+/// use flac::metadata;
+///
+/// let stream_info =
+///   metadata::recompute_stream_info("path/to/file.flac").unwrap();
+///
+/// println!("{} samples", stream_info.total_samples);
+/// ```
+#[cfg(feature = "md5")]
+pub fn recompute_stream_info(filename: &str)
+                             -> Result<StreamInfo, ErrorKind> {
+  recompute_stream_info_with(filename, Md5::new())
+}
+
+/// Same as `metadata::recompute_stream_info`, with the signature computed
+/// by the given digest instead of MD5.
+pub fn recompute_stream_info_with<D>(filename: &str, digest: D)
+                                     -> Result<StreamInfo, ErrorKind>
+ where D: Digest {
+  let (mut stream, mut info) = try!(open_stream(filename));
+  let bits_per_sample        = info.bits_per_sample.get();
+  let mut sink               = DigestSink::new(digest, bits_per_sample);
+  let sizes                  = try!(frame_sizes(&mut stream, &mut sink));
+
+  sizes.apply(&mut info);
+
+  info.md5_sum = sink.signature();
+
+  write_stream_info(filename, &info).map(|_| info)
+                                    .map_err(|e| ErrorKind::IO(e.kind()))
+}
+
+/// Recomputes only the minimum and maximum frame sizes within the
+/// `StreamInfo` of a file, and writes them back in place.
+///
+/// The frame sizes are only hints, see `StreamInfo::frame_size_hint`, so
+/// this fixes them without touching anything else, the MD5 signature
+/// included. Every frame still gets decoded to find where it ends.
+///
+/// # Failures
+///
+/// * `ErrorKind::IO(io::ErrorKind::NotFound)` is returned when the given
+///   filename isn't found.
+/// * `ErrorKind::IO(io::ErrorKind::InvalidData)` is returned when the file
+///   doesn't start with a FLAC header followed by `StreamInfo`.
+/// * `ErrorKind::InvalidBlockSize` is returned when a frame holds more
+///   samples than `StreamInfo` can hold as a block size.
+/// * Any error from decoding a frame, where the file is left untouched.
+///
+/// # Examples
+///
+/// ```no_run
+/// use flac::metadata;
+///
+/// let stream_info = metadata::get_stream_info("path/to/file.flac").unwrap();
+///
+/// if !stream_info.has_sane_frame_sizes() {
+///   metadata::recompute_frame_sizes("path/to/file.flac").unwrap();
+/// }
+/// ```
+pub fn recompute_frame_sizes(filename: &str)
+                             -> Result<StreamInfo, ErrorKind> {
+  let (mut stream, mut info) = try!(open_stream(filename));
+  let mut sizes              = StreamInfo::default();
+
+  // A tee without any sinks throws the samples away.
+  try!(frame_sizes(&mut stream, &mut TeeSink::new())).apply(&mut sizes);
+
+  info.min_frame_size = sizes.min_frame_size;
+  info.max_frame_size = sizes.max_frame_size;
+
+  write_stream_info(filename, &info).map(|_| info)
+                                    .map_err(|e| ErrorKind::IO(e.kind()))
+}
+
+// Stream over a file, with a copy of its `StreamInfo` in front.
+type FileStream = StreamReader<io::Chain<Cursor<Vec<u8>>, File>>;
+
+// Opens a file for decoding every frame, along with its `StreamInfo`.
+//
+// Edited frames can be larger than the maximum block size given, which the
+// decode buffer is sized by, so the stream gets the largest one possible
+// instead. The `StreamInfo` returned keeps the one from the file.
+fn open_stream(filename: &str)
+               -> Result<(FileStream, StreamInfo), ErrorKind> {
+  let opened = File::open(filename).and_then(|mut file| {
+    let mut header = [0; STREAM_INFO_END];
+
+    try!(file.read_exact(&mut header));
+
+    if &header[0..4] != b"fLaC" || (header[4] & 0b01111111) != 0 ||
+       to_u32(&header[5..8]) as usize != STREAM_INFO_SIZE {
+      return Err(invalid_data());
+    }
+
+    Ok((file, header))
+  });
+
+  let (file, mut header) = try!(opened.map_err(|e| ErrorKind::IO(e.kind())));
+  let max_block_size     = to_u32(&header[10..12]) as u16;
+
+  header[10] = 0xff;
+  header[11] = 0xff;
+
+  let options = StreamOptions {
+    allow_large_block_size: true,
+    ..Default::default()
+  };
+
+  let reader   = Cursor::new(header.to_vec()).chain(file);
+  let stream   = try!(FileStream::with_options(reader, options));
+  let mut info = stream.info();
+
+  info.max_block_size = max_block_size;
+
+  Ok((stream, info))
+}
+
+// Decodes every frame into `sink`, finishing it, and returns the sizes of
+// the frames.
+fn frame_sizes<K>(stream: &mut FileStream, sink: &mut K)
+                  -> Result<StreamInfoAccumulator, ErrorKind>
+ where K: Sink<i32> {
+  let mut sizes = StreamInfoAccumulator::new();
+
+  {
+    let mut blocks = stream.blocks::<i32>();
+    let mut offset = blocks.decode_progress().byte_offset;
+
+    while let Some(block) = blocks.next() {
+      let block = try!(block);
+      let end   = blocks.decode_progress().byte_offset;
+
+      // A frame can hold 65536 samples, one more than the block sizes
+      // within `StreamInfo` can, which gets rejected rather than wrapping
+      // around to zero.
+      if block.len() > u16::MAX as usize {
+        return Err(ErrorKind::InvalidBlockSize);
+      }
+
+      sizes.add_frame(block.len() as u16, (end - offset) as usize);
+      try!(sink.write_block(&block));
+
+      offset = end;
+    }
+  }
+
+  if let Some(error) = stream.decode_progress().error {
+    return Err(error);
+  }
+
+  try!(sink.finish());
+
+  Ok(sizes)
+}
+
+// Overwrites the data of `StreamInfo`, which always directly follows the
+// FLAC header and its own block header.
+fn write_stream_info(filename: &str, info: &StreamInfo) -> io::Result<()> {
+  let offset    = (STREAM_INFO_END - STREAM_INFO_SIZE) as u64;
+  let mut bytes = Vec::with_capacity(STREAM_INFO_SIZE);
+  let mut file  = try!(OpenOptions::new().write(true).open(filename));
+
+  try!(info.to_bytes(&mut bytes));
+  try!(file.seek(SeekFrom::Start(offset)));
+  try!(file.write_all(&bytes));
+
+  file.flush()
+}
+
+// Copies a FLAC stream from `input` to `output`, leaving out the metadata
+// blocks that aren't kept and recomputing the is last flags.
+fn strip_blocks<R, W>(mut input: R, mut output: W, keep: &[Type])
+                      -> io::Result<()>
+ where R: Read,
+       W: Write {
+  let mut blocks = try!(read_blocks(&mut input, |block_type| {
+    keep.contains(&to_type(block_type))
+  }));
+
+  try!(write_blocks(&mut output, &mut blocks));
+  try!(io::copy(&mut input, &mut output));
+
+  output.flush()
+}
+
+// Reads every metadata block after the FLAC header, with its block header,
+// leaving `input` at the first audio frame. Other than `StreamInfo`, only
+// the block types `keep` returns true for are held onto.
+fn read_blocks<R, F>(input: &mut R, mut keep: F) -> io::Result<Vec<Vec<u8>>>
+ where R: Read,
+       F: FnMut(u8) -> bool {
+  let mut header  = [0; 4];
+  let mut blocks  = Vec::new();
+  let mut is_last = false;
+
+  try!(input.read_exact(&mut header));
+
+  if &header != b"fLaC" {
+    return Err(invalid_data());
+  }
+
+  while !is_last {
+    try!(input.read_exact(&mut header));
+
+    let block_type = header[0] & 0b01111111;
+    let length     = to_u32(&header[1..]) as u64;
+    let is_first   = blocks.is_empty();
+
+    is_last = (header[0] >> 7) == 1;
+
+    if block_type == 127 || (is_first && block_type != 0) {
+      return Err(invalid_data());
+    }
+
+    let mut data = (&mut *input).take(length);
+
+    if is_first || keep(block_type) {
+      let mut block = header.to_vec();
+
+      try!(data.read_to_end(&mut block));
+
+      blocks.push(block);
+    } else {
+      try!(io::copy(&mut data, &mut io::sink()));
+    }
+
+    if data.limit() > 0 {
+      return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                "metadata block cut short"));
+    }
+  }
+
+  Ok(blocks)
+}
+
+// Writes the FLAC header followed by the metadata blocks, recomputing the
+// is last flags.
+fn write_blocks<W>(output: &mut W, blocks: &mut [Vec<u8>]) -> io::Result<()>
+ where W: Write {
+  let last = blocks.len() - 1;
+
+  try!(output.write_all(b"fLaC"));
+
+  for (index, block) in blocks.iter_mut().enumerate() {
+    if index == last {
+      block[0] |= 0b10000000;
+    } else {
+      block[0] &= 0b01111111;
+    }
+
+    try!(output.write_all(block));
+  }
+
+  Ok(())
+}
+
+// Changes the metadata blocks of the given file through `edit`, which gets
+// every block other than padding. Padding fills in the space left in front
+// of the audio frames, so only the blocks are written when they still fit.
+// Otherwise the whole file gets rewritten next to the original, holding on
+// to as much padding as there was before.
+#[cfg(feature = "picture")]
+fn edit_blocks<F>(filename: &str, edit: F) -> Result<(), ErrorKind>
+ where F: FnOnce(&mut Vec<Vec<u8>>) -> Result<(), ErrorKind> {
+  let file       = try!(File::open(filename)
+                          .map_err(|e| ErrorKind::IO(e.kind())));
+  let mut input  = BufReader::new(file);
+  let mut blocks = try!(read_blocks(&mut input, |_| true)
+                          .map_err(|e| ErrorKind::IO(e.kind())));
+
+  let audio_offset = blocks_len(&blocks);
+
+  blocks.retain(|block| !is_padding(block));
+
+  let padding = audio_offset - blocks_len(&blocks);
+
+  try!(edit(&mut blocks));
+
+  let used   = blocks_len(&blocks);
+  let result = if used == audio_offset ||
+                  (used + 4 <= audio_offset &&
+                   audio_offset - used - 4 <= MAX_BLOCK_LENGTH) {
+    if used < audio_offset {
+      blocks.push(padding_block(audio_offset - used - 4));
+    }
+
+    OpenOptions::new().write(true).open(filename).and_then(|mut output| {
+      try!(write_blocks(&mut output, &mut blocks));
+
+      output.flush()
+    })
+  } else {
+    let edited = format!("{}.picture", filename);
+
+    if padding > 4 {
+      blocks.push(padding_block(cmp::min(padding - 4, MAX_BLOCK_LENGTH)));
+    }
+
+    let copied = File::create(&edited).and_then(|output| {
+      let mut output = BufWriter::new(output);
+
+      try!(write_blocks(&mut output, &mut blocks));
+      try!(io::copy(&mut input, &mut output));
+
+      output.flush()
+    });
+
+    // The original file gets closed before it's replaced, since some
+    // platforms, like Windows, don't allow renaming over an open file.
+    drop(input);
+
+    let result = copied.and_then(|_| fs::rename(&edited, filename));
+
+    if result.is_err() {
+      let _ = fs::remove_file(&edited);
+    }
+
+    result
+  };
+
+  result.map_err(|e| ErrorKind::IO(e.kind()))
+}
+
+// Returns the positions, within `blocks`, of every `Picture` that matches
+// `selector`.
+#[cfg(feature = "picture")]
+fn select_pictures(blocks: &[Vec<u8>], selector: PictureSelector)
+                   -> Vec<usize> {
+  let pictures = blocks.iter().enumerate().filter(|&(_, block)| {
+    is_picture(block)
+  });
+
+  pictures.enumerate().filter(|&(index, (_, block))| {
+    match selector {
+      PictureSelector::Index(i)            => index == i,
+      PictureSelector::Type(picture_type) => {
+        block.len() >= 8 &&
+          parser::picture_type(to_u32(&block[4..8])) == picture_type
+      }
+    }
+  }).map(|(_, (position, _))| position).collect()
+}
+
+#[cfg(feature = "picture")]
+fn is_picture(block: &[u8]) -> bool {
+  (block[0] & 0b01111111) == 6
+}
+
+#[cfg(feature = "picture")]
+fn is_padding(block: &[u8]) -> bool {
+  (block[0] & 0b01111111) == 1
+}
+
+// Returns the size, in bytes, of the FLAC header and the given blocks.
+#[cfg(feature = "picture")]
+fn blocks_len(blocks: &[Vec<u8>]) -> usize {
+  blocks.iter().fold(4, |length, block| length + block.len())
+}
+
+// Returns a padding block, with its header, holding `length` bytes.
+pub(crate) fn padding_block(length: usize) -> Vec<u8> {
+  let mut block = vec![0; 4 + length];
+
+  block[0] = 1;
+  block[1] = (length >> 16) as u8;
+  block[2] = (length >> 8) as u8;
+  block[3] = length as u8;
+
+  block
+}
+
+// Returns the `Type` of a metadata block type number, regardless of which
+// block types are parsed.
+fn to_type(block_type: u8) -> Type {
+  match block_type {
+    0 => Type::StreamInfo,
+    1 => Type::Padding,
+    2 => Type::Application,
+    3 => Type::SeekTable,
+    4 => Type::VorbisComment,
+    5 => Type::CueSheet,
+    6 => Type::Picture,
+    _ => Type::Unknown,
+  }
+}
+
+fn invalid_data() -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, "not a valid FLAC stream")
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -313,12 +936,14 @@ mod tests {
 
   use utility::ErrorKind;
 
+  #[cfg(feature = "picture")]
   #[test]
   #[should_panic]
   fn test_panic_optional_eq() {
     assert!(optional_eq(Some(1), 0));
   }
 
+  #[cfg(feature = "picture")]
   #[test]
   fn test_optional_eq() {
     assert!(optional_eq(None, 0), "Should always return true when None");
@@ -337,4 +962,34 @@ mod tests {
                                             io::ErrorKind::InvalidData));
     assert!(result.is_ok());
   }
+
+  #[test]
+  fn test_strip_blocks() {
+    let input = b"fLaC\x00\0\0\x02ab\x01\0\0\x01c\x04\0\0\x01d\
+                  \x09\0\0\x02ef\x86\0\0\x01gframes";
+
+    let mut output = Vec::new();
+
+    assert!(strip_blocks(&input[..], &mut output, &[]).is_ok());
+    assert_eq!(&output[..], &b"fLaC\x80\0\0\x02abframes"[..]);
+
+    output.clear();
+
+    let keep = [Type::VorbisComment, Type::Unknown];
+
+    assert!(strip_blocks(&input[..], &mut output, &keep).is_ok());
+    assert_eq!(&output[..],
+               &b"fLaC\x00\0\0\x02ab\x04\0\0\x01d\x89\0\0\x02efframes"[..]);
+
+    let inputs = [
+      &b"fLaX\x80\0\0\0"[..],
+      &b"fLaC\x81\0\0\0"[..],
+      &b"fLaC\x00\0\0\0\xff\0\0\0"[..],
+      &b"fLaC\x80\0\0\x04ab"[..],
+    ];
+
+    for input in &inputs {
+      assert!(strip_blocks(&input[..], &mut Vec::new(), &[]).is_err());
+    }
+  }
 }
@@ -2,16 +2,46 @@
 
 mod types;
 mod parser;
+mod backend;
 mod metadata;
+#[cfg(feature = "vorbis-comment")]
+mod riff;
 
 pub use self::types::{
-  Metadata, Data, Type,
-  StreamInfo, Application, VorbisComment, CueSheet, Picture,
-  SeekPoint, CueSheetTrack, CueSheetTrackIndex, PictureType,
+  Metadata, Data, Type, Payload,
+  StreamInfo, Application, SeekPoint, PlaceholderPolicy,
+  serialize_blocks, samples_to_duration, duration_to_samples,
 };
 
-pub use self::parser::metadata_parser;
+#[cfg(feature = "vorbis-comment")]
+pub use self::types::VorbisComment;
+#[cfg(feature = "cue-sheet")]
+pub use self::types::{CueSheet, CueSheetTrack, CueSheetTrackIndex, Msf};
+#[cfg(feature = "picture")]
+pub use self::types::{Picture, PictureType, PictureSelector};
+
+pub use self::parser::{
+  metadata_parser, metadata_parser_with_limit, metadata_parser_with_context,
+  SeekPoints,
+};
+
+#[cfg(feature = "vorbis-comment")]
+pub use self::parser::CommentIter;
 
 pub use self::metadata::{
-  get_stream_info, get_vorbis_comment, get_cue_sheet, get_picture,
+  get_stream_info, strip, strip_stream, recompute_stream_info_with,
+  recompute_frame_sizes,
 };
+#[cfg(feature = "md5")]
+pub use self::metadata::recompute_stream_info;
+
+pub(crate) use self::metadata::padding_block;
+
+#[cfg(feature = "vorbis-comment")]
+pub use self::metadata::get_vorbis_comment;
+#[cfg(feature = "vorbis-comment")]
+pub use self::riff::read_riff_info;
+#[cfg(feature = "cue-sheet")]
+pub use self::metadata::get_cue_sheet;
+#[cfg(feature = "picture")]
+pub use self::metadata::{get_picture, set_picture, remove_picture};
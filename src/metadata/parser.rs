@@ -1,34 +1,69 @@
 use nom::{
   self,
-  be_u8, be_u16, be_u32, be_u64,
-  le_u32,
+  be_u8, be_u16, be_u64,
   IResult, Needed,
   Err,
 };
 
+#[cfg(feature = "picture")]
+use nom::be_u32;
+#[cfg(feature = "vorbis-comment")]
+use nom::le_u32;
+
+#[cfg(feature = "vorbis-comment")]
+use std::cmp;
+#[cfg(feature = "vorbis-comment")]
 use std::collections::HashMap;
 
 use metadata::{
-  self, Metadata,
-  StreamInfo, Application, VorbisComment, CueSheet, Picture,
-  SeekPoint, CueSheetTrack, CueSheetTrackIndex, PictureType,
+  self,
+  Metadata, StreamInfo, Application, SeekPoint, PlaceholderPolicy, Payload,
 };
-
-use utility::{ErrorKind, to_u32};
+#[cfg(feature = "vorbis-comment")]
+use metadata::VorbisComment;
+#[cfg(feature = "cue-sheet")]
+use metadata::{CueSheet, CueSheetTrack, CueSheetTrackIndex};
+#[cfg(feature = "picture")]
+use metadata::{Picture, PictureType};
+
+use metadata::backend::MetadataBackend;
+#[cfg(not(feature = "native-metadata"))]
+use metadata::backend::NomBackend as Backend;
+#[cfg(feature = "native-metadata")]
+use metadata::backend::NativeBackend as Backend;
+use utility::{BitsPerSample, DecodeContext, ErrorKind, SampleRate, to_u32};
+
+// Size, in bytes, of the data within `StreamInfo`.
+const STREAM_INFO_SIZE: usize = 34;
 
 /// Parse a metadata block.
+#[inline]
 pub fn metadata_parser(input: &[u8]) -> IResult<&[u8], Metadata, ErrorKind> {
-  chain!(input,
-    block_header: header ~
-    data: apply!(block_data, block_header.1, block_header.2),
-    || { Metadata::new(block_header.0, block_header.2, data) }
-  )
+  metadata_parser_with_context(input, &DecodeContext::new())
+}
+
+/// Parse a metadata block, keeping no more than `max_comments` comments
+/// when it's a vorbis comment block.
+#[inline]
+pub fn metadata_parser_with_limit(input: &[u8], max_comments: usize)
+                                  -> IResult<&[u8], Metadata, ErrorKind> {
+  let context = DecodeContext::new().with_max_comments(max_comments);
+
+  metadata_parser_with_context(input, &context)
+}
+
+/// Parse a metadata block with the options within `context`.
+pub fn metadata_parser_with_context<'a>(input: &'a [u8],
+                                        context: &DecodeContext)
+                                        -> IResult<&'a [u8], Metadata,
+                                                   ErrorKind> {
+  Backend::metadata(input, context).into_iresult()
 }
 
 pub fn stream_info(input: &[u8])
                    -> IResult<&[u8], metadata::Data, ErrorKind> {
   to_custom_error!(input,
-    chain!(
+    map_opt!(chain!(
       min_block_size: be_u16 ~
       max_block_size: be_u16 ~
       min_frame_size: map!(take!(3), to_u32) ~
@@ -48,31 +83,62 @@ pub fn stream_info(input: &[u8])
                               ((bytes[6] as u64) << 8) +
                               (bytes[7] as u64);
 
-        metadata::Data::StreamInfo(StreamInfo {
-          min_block_size: min_block_size,
-          max_block_size: max_block_size,
-          min_frame_size: min_frame_size,
-          max_frame_size: max_frame_size,
-          sample_rate: sample_rate,
-          channels: channels + 1,
-          bits_per_sample: bits_per_sample + 1,
-          total_samples: total_samples,
-          md5_sum: md5_sum,
+        // Rates and sizes FLAC doesn't support fail the parser.
+        let sample_rate     = SampleRate::new(sample_rate);
+        let bits_per_sample = BitsPerSample::new(bits_per_sample + 1);
+
+        sample_rate.and_then(|sample_rate| {
+          bits_per_sample.map(|bits_per_sample| {
+            metadata::Data::StreamInfo(StreamInfo {
+              min_block_size: min_block_size,
+              max_block_size: max_block_size,
+              min_frame_size: min_frame_size,
+              max_frame_size: max_frame_size,
+              sample_rate: sample_rate,
+              channels: channels + 1,
+              bits_per_sample: bits_per_sample,
+              total_samples: total_samples,
+              md5_sum: md5_sum,
+            })
+          })
         })
       }
-    ),
+    ), |data| data),
     StreamInfoParser)
 }
 
+// Parses `StreamInfo` and skips over the rest of its declared length,
+// which can be longer than the 34 bytes it uses.
+fn stream_info_block(input: &[u8], length: u32)
+                     -> IResult<&[u8], metadata::Data, ErrorKind> {
+  let len = length as usize;
+
+  if len < STREAM_INFO_SIZE {
+    return IResult::Error(Err::Code(
+             nom::ErrorKind::Custom(ErrorKind::StreamInfoParser)));
+  }
+
+  match stream_info(&input[0..len]) {
+    IResult::Done(_, data) => IResult::Done(&input[len..], data),
+    result                 => result,
+  }
+}
+
 pub fn padding(input: &[u8], length: u32)
                -> IResult<&[u8], metadata::Data, ErrorKind> {
   to_custom_error!(input,
-    map!(skip_bytes!(length), |_| metadata::Data::Padding(0)),
+    map!(skip_bytes!(length), |_| metadata::Data::Padding(length)),
     PaddingParser)
 }
 
 pub fn application(input: &[u8], length: u32)
                    -> IResult<&[u8], metadata::Data, ErrorKind> {
+  // Too short to even hold the application identifier.
+  if length < 4 {
+    return IResult::Error(Err::Code(
+             nom::ErrorKind::Custom(ErrorKind::ApplicationParser)));
+  }
+
   to_custom_error!(input,
     chain!(
       id: take_str!(4) ~
@@ -80,7 +146,7 @@ pub fn application(input: &[u8], length: u32)
       || {
         metadata::Data::Application(Application {
           id: id.to_owned(),
-          data: data.to_owned(),
+          data: Payload::from(data),
         })
       }
     ),
@@ -102,32 +168,109 @@ named!(seek_point <&[u8], SeekPoint>,
   )
 );
 
-pub fn seek_table(input: &[u8], length: u32)
+pub fn seek_table(input: &[u8], length: u32, policy: PlaceholderPolicy)
                   -> IResult<&[u8], metadata::Data> {
-  let seek_count = (length / 18) as usize;
+  let len = (length as usize / 18) * 18;
+
+  if len > input.len() {
+    return IResult::Incomplete(Needed::Size(len));
+  }
+
+  let (points, rest) = input.split_at(len);
+  let seek_points    = SeekPoints::new(points, policy).collect();
+
+  IResult::Done(rest, metadata::Data::SeekTable(seek_points))
+}
+
+/// An iterator over the points of a seek table block, parsing one point at
+/// a time.
+///
+/// Placeholder points get kept or skipped according to the
+/// `PlaceholderPolicy`, and skipped ones are never turned into a
+/// `SeekPoint`, so going through a table holding thousands of them takes
+/// no memory at all. Iterating stops once less than a whole point is left.
+///
+/// # Examples
+///
+/// ```
+/// use flac::metadata::{PlaceholderPolicy, SeekPoints};
+///
+/// let mut block = vec![0; 16];
+///
+/// block.extend_from_slice(&[0x10, 0]);
+/// block.extend_from_slice(&[0xff; 8]);
+/// block.extend_from_slice(&[0; 10]);
+///
+/// let mut points = SeekPoints::new(&block, PlaceholderPolicy::Drop);
+///
+/// assert_eq!(points.next().map(|point| point.frame_samples), Some(4096));
+/// assert_eq!(points.next(), None);
+/// ```
+pub struct SeekPoints<'a> {
+  input: &'a [u8],
+  policy: PlaceholderPolicy,
+  is_placeholder_found: bool,
+}
+
+impl<'a> SeekPoints<'a> {
+  /// Starts iterating over the data of a seek table block, without the
+  /// metadata block header.
+  pub fn new(input: &'a [u8], policy: PlaceholderPolicy) -> Self {
+    SeekPoints {
+      input: input,
+      policy: policy,
+      is_placeholder_found: false,
+    }
+  }
+}
+
+impl<'a> Iterator for SeekPoints<'a> {
+  type Item = SeekPoint;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    while let IResult::Done(i, seek_point) = seek_point(self.input) {
+      self.input = i;
+
+      if !seek_point.is_placeholder() {
+        return Some(seek_point);
+      }
+
+      let is_kept = match self.policy {
+        PlaceholderPolicy::Keep    => true,
+        PlaceholderPolicy::Drop    => false,
+        PlaceholderPolicy::Compact => !self.is_placeholder_found,
+      };
+
+      self.is_placeholder_found = true;
+
+      if is_kept {
+        return Some(seek_point);
+      }
+    }
+
+    None
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    let remaining = self.input.len() / 18;
 
-  map!(input, count!(seek_point, seek_count), metadata::Data::SeekTable)
+    match self.policy {
+      PlaceholderPolicy::Keep => (remaining, Some(remaining)),
+      _                       => (0, Some(remaining)),
+    }
+  }
 }
 
-pub fn vorbis_comment(input: &[u8])
+#[cfg(feature = "vorbis-comment")]
+pub fn vorbis_comment(input: &[u8], max_comments: usize)
                       -> IResult<&[u8], metadata::Data, ErrorKind> {
   to_custom_error!(input,
     chain!(
-      vendor_string_length: le_u32 ~
-      vendor_string: take_str!(vendor_string_length)  ~
-      number_of_comments: le_u32 ~
-      comment_lines: count!(comment_field, number_of_comments as usize),
+      header: comment_header ~
+      comments: apply!(comment_fields, header.1, max_comments),
       || {
-        let mut comments = HashMap::with_capacity(comment_lines.len());
-
-        for line in comment_lines {
-          let comment: Vec<&str> = line.splitn(2, '=').collect();
-
-          comments.insert(comment[0].to_owned(), comment[1].to_owned());
-        }
-
         metadata::Data::VorbisComment(VorbisComment {
-          vendor_string: vendor_string.to_owned(),
+          vendor_string: header.0.to_owned(),
           comments: comments,
         })
       }
@@ -135,14 +278,151 @@ pub fn vorbis_comment(input: &[u8])
     VorbisCommentParser)
 }
 
-named!(comment_field <&[u8], String>,
+// Parses every comment field, where the number of comments can't be trusted
+// for how much to allocate until it's known to fit within the input. Each
+// field takes up at least four bytes for its length. Fields past
+// `max_comments` are parsed but not kept.
+#[cfg(feature = "vorbis-comment")]
+fn comment_fields(input: &[u8], count: u32, max_comments: usize)
+                  -> IResult<&[u8], HashMap<String, String>> {
+  if count as usize > input.len() / 4 {
+    return IResult::Error(Err::Position(nom::ErrorKind::Count, input));
+  }
+
+  let capacity = cmp::min(count as usize, max_comments);
+
+  let mut comments = HashMap::with_capacity(capacity);
+  let mut slice    = input;
+
+  for index in 0..(count as usize) {
+    let (i, line) = try_parse!(slice, comment_field);
+
+    slice = i;
+
+    if index >= max_comments {
+      continue;
+    }
+
+    if let Some((key, value)) = split_comment(line) {
+      comments.insert(key.to_owned(), value.to_owned());
+    }
+  }
+
+  IResult::Done(slice, comments)
+}
+
+// Splits a comment into its name and value, where lines without a separator
+// aren't valid comments.
+#[cfg(feature = "vorbis-comment")]
+fn split_comment(line: &str) -> Option<(&str, &str)> {
+  line.find('=').map(|index| (&line[0..index], &line[(index + 1)..]))
+}
+
+#[cfg(feature = "vorbis-comment")]
+named!(comment_header <&[u8], (&str, u32)>,
+  chain!(
+    vendor_string_length: le_u32 ~
+    vendor_string: take_str!(vendor_string_length) ~
+    number_of_comments: le_u32,
+    || { (vendor_string, number_of_comments) }
+  )
+);
+
+#[cfg(feature = "vorbis-comment")]
+named!(comment_field <&[u8], &str>,
   chain!(
     comment_length: le_u32 ~
     comment: take_str!(comment_length),
-    || { comment.to_owned() }
+    || { comment }
   )
 );
 
+/// An iterator over the comments of a vorbis comment block, parsing one
+/// comment at a time.
+///
+/// Each comment is borrowed from the block as a name and value pair, so
+/// memory use stays the same no matter how many comments there are.
+/// Comments without an "=" are skipped, and iterating stops at the first
+/// comment that fails to parse.
+///
+/// # Examples
+///
+/// ```
+/// use flac::metadata::CommentIter;
+///
+/// let block    = b"\x03\0\0\0abc\x01\0\0\0\x07\0\0\0title=2";
+/// let mut iter = CommentIter::new(block).unwrap();
+///
+/// assert_eq!(iter.vendor_string(), "abc");
+/// assert_eq!(iter.next(), Some(("title", "2")));
+/// assert_eq!(iter.next(), None);
+/// ```
+#[cfg(feature = "vorbis-comment")]
+pub struct CommentIter<'a> {
+  vendor_string: &'a str,
+  input: &'a [u8],
+  comments_left: u32,
+}
+
+#[cfg(feature = "vorbis-comment")]
+impl<'a> CommentIter<'a> {
+  /// Starts iterating over the data of a vorbis comment block, without the
+  /// metadata block header.
+  ///
+  /// This works with the data of a `Data::Unknown` block when the
+  /// `vorbis-comment` feature leaves it unparsed, or with `Metadata::raw`
+  /// after the first four bytes.
+  ///
+  /// # Failures
+  ///
+  /// * `ErrorKind::VorbisCommentParser` is returned when the vendor string
+  ///   or the number of comments can't be parsed.
+  pub fn new(input: &'a [u8]) -> Result<Self, ErrorKind> {
+    match comment_header(input) {
+      IResult::Done(i, (vendor_string, count)) => {
+        Ok(CommentIter {
+          vendor_string: vendor_string,
+          input: i,
+          comments_left: count,
+        })
+      }
+      _                                        => {
+        Err(ErrorKind::VorbisCommentParser)
+      }
+    }
+  }
+
+  /// Returns the vendor string of the block.
+  #[inline]
+  pub fn vendor_string(&self) -> &'a str {
+    self.vendor_string
+  }
+}
+
+#[cfg(feature = "vorbis-comment")]
+impl<'a> Iterator for CommentIter<'a> {
+  type Item = (&'a str, &'a str);
+
+  fn next(&mut self) -> Option<Self::Item> {
+    while self.comments_left > 0 {
+      self.comments_left -= 1;
+
+      if let IResult::Done(i, line) = comment_field(self.input) {
+        self.input = i;
+
+        if let Some(comment) = split_comment(line) {
+          return Some(comment);
+        }
+      } else {
+        self.comments_left = 0;
+      }
+    }
+
+    None
+  }
+}
+
+#[cfg(feature = "cue-sheet")]
 pub fn cue_sheet(input: &[u8]) -> IResult<&[u8], metadata::Data, ErrorKind> {
   to_custom_error!(input,
     chain!(
@@ -167,6 +447,7 @@ pub fn cue_sheet(input: &[u8]) -> IResult<&[u8], metadata::Data, ErrorKind> {
     CueSheetParser)
 }
 
+#[cfg(feature = "cue-sheet")]
 named!(cue_sheet_track <&[u8], CueSheetTrack>,
   chain!(
     offset: be_u64 ~
@@ -194,6 +475,7 @@ named!(cue_sheet_track <&[u8], CueSheetTrack>,
   )
 );
 
+#[cfg(feature = "cue-sheet")]
 named!(cue_sheet_track_index <&[u8], CueSheetTrackIndex>,
   chain!(
     offset: be_u64 ~
@@ -208,6 +490,36 @@ named!(cue_sheet_track_index <&[u8], CueSheetTrackIndex>,
   )
 );
 
+// Returns the `PictureType` of a picture type number, where the numbers
+// that aren't known are `PictureType::Other`.
+#[cfg(feature = "picture")]
+pub fn picture_type(number: u32) -> PictureType {
+  match number {
+    1  => PictureType::FileIconStandard,
+    2  => PictureType::FileIcon,
+    3  => PictureType::FrontCover,
+    4  => PictureType::BackCover,
+    5  => PictureType::LeafletPage,
+    6  => PictureType::Media,
+    7  => PictureType::LeadArtist,
+    8  => PictureType::Artist,
+    9  => PictureType::Conductor,
+    10 => PictureType::Band,
+    11 => PictureType::Composer,
+    12 => PictureType::Lyricist,
+    13 => PictureType::RecordingLocation,
+    14 => PictureType::DuringRecording,
+    15 => PictureType::DuringPerformance,
+    16 => PictureType::VideoScreenCapture,
+    17 => PictureType::Fish,
+    18 => PictureType::Illustration,
+    19 => PictureType::BandLogo,
+    20 => PictureType::PublisherLogo,
+    _  => PictureType::Other,
+  }
+}
+
+#[cfg(feature = "picture")]
 pub fn picture(input: &[u8]) -> IResult<&[u8], metadata::Data, ErrorKind> {
   to_custom_error!(input,
     chain!(
@@ -223,29 +535,7 @@ pub fn picture(input: &[u8]) -> IResult<&[u8], metadata::Data, ErrorKind> {
       data_length: be_u32 ~
       data: take!(data_length),
       || {
-        let picture_type = match picture_type_num {
-          1  => PictureType::FileIconStandard,
-          2  => PictureType::FileIcon,
-          3  => PictureType::FrontCover,
-          4  => PictureType::BackCover,
-          5  => PictureType::LeafletPage,
-          6  => PictureType::Media,
-          7  => PictureType::LeadArtist,
-          8  => PictureType::Artist,
-          9  => PictureType::Conductor,
-          10 => PictureType::Band,
-          11 => PictureType::Composer,
-          12 => PictureType::Lyricist,
-          13 => PictureType::RecordingLocation,
-          14 => PictureType::DuringRecording,
-          15 => PictureType::DuringPerformance,
-          16 => PictureType::VideoScreenCapture,
-          17 => PictureType::Fish,
-          18 => PictureType::Illustration,
-          19 => PictureType::BandLogo,
-          20 => PictureType::PublisherLogo,
-          _  => PictureType::Other,
-        };
+        let picture_type = picture_type(picture_type_num);
 
         metadata::Data::Picture(Picture {
           picture_type: picture_type,
@@ -255,7 +545,7 @@ pub fn picture(input: &[u8]) -> IResult<&[u8], metadata::Data, ErrorKind> {
           height: height,
           depth: depth,
           colors: colors,
-          data: data.to_owned(),
+          data: Payload::from(data),
         })
       }
     ),
@@ -264,12 +554,14 @@ pub fn picture(input: &[u8]) -> IResult<&[u8], metadata::Data, ErrorKind> {
 
 // As of FLAC v1.3.1, there is support for up to 127 different metadata
 // `Metadata`s but actually 7 that are implemented. When the `Metadata` type
-// isn't recognised, this block gets skipped over with this parser.
-pub fn unknown(input: &[u8], length: u32)
+// isn't recognised, this block gets skipped over with this parser. The same
+// goes for `VorbisComment`, `CueSheet`, and `Picture` when their features
+// are disabled.
+pub fn unknown(input: &[u8], block_type: u8, length: u32)
                -> IResult<&[u8], metadata::Data, ErrorKind> {
   to_custom_error!(input,
     map!(take!(length), |data: &[u8]|
-      metadata::Data::Unknown(data.to_owned())),
+      metadata::Data::Unknown(block_type, Payload::from(data))),
     UnknownParser)
 }
 
@@ -286,8 +578,10 @@ pub fn header(input: &[u8]) -> IResult<&[u8], (bool, u8, u32), ErrorKind> {
   ).map_err(to_custom_error!(MetadataHeaderParser))
 }
 
-pub fn block_data(input: &[u8], block_type: u8, length: u32)
-                  -> IResult<&[u8], metadata::Data, ErrorKind> {
+#[cfg_attr(not(feature = "vorbis-comment"), allow(unused_variables))]
+pub fn block_data<'a>(input: &'a [u8], block_type: u8, length: u32,
+                      context: &DecodeContext)
+                      -> IResult<&'a [u8], metadata::Data, ErrorKind> {
   let len = length as usize;
 
   if len > input.len() {
@@ -297,15 +591,18 @@ pub fn block_data(input: &[u8], block_type: u8, length: u32)
   }
 
   match block_type {
-    0       => stream_info(input),
+    0       => stream_info_block(input, length),
     1       => padding(input, length),
     2       => application(input, length),
-    3       => seek_table(input, length).map_err(
-                 to_custom_error!(SeekTableParser)),
-    4       => vorbis_comment(input),
+    3       => seek_table(input, length, context.placeholder_policy)
+                 .map_err(to_custom_error!(SeekTableParser)),
+    #[cfg(feature = "vorbis-comment")]
+    4       => vorbis_comment(input, context.max_comments),
+    #[cfg(feature = "cue-sheet")]
     5       => cue_sheet(input),
+    #[cfg(feature = "picture")]
     6       => picture(input),
-    7...126 => unknown(input, length),
+    4...126 => unknown(input, block_type, length),
     _       => IResult::Error(Err::Code(
                  nom::ErrorKind::Custom(ErrorKind::InvalidBlockType))),
   }
@@ -314,15 +611,18 @@ pub fn block_data(input: &[u8], block_type: u8, length: u32)
 #[cfg(test)]
 mod tests {
   use super::*;
-  use metadata::{
-    self,
-    StreamInfo, Application, VorbisComment, CueSheet, Picture,
-    SeekPoint, CueSheetTrack, CueSheetTrackIndex, PictureType,
-  };
+  use metadata::{self, StreamInfo, Application, SeekPoint, Payload};
+  #[cfg(feature = "vorbis-comment")]
+  use metadata::VorbisComment;
+  #[cfg(feature = "cue-sheet")]
+  use metadata::{CueSheet, CueSheetTrack, CueSheetTrackIndex};
+  #[cfg(feature = "picture")]
+  use metadata::{Picture, PictureType};
   use utility::ErrorKind;
 
   use nom::{self, IResult, Err};
 
+  #[cfg(feature = "vorbis-comment")]
   use std::collections::HashMap;
 
   #[test]
@@ -346,9 +646,9 @@ mod tests {
         max_block_size: 4608,
         min_frame_size: 14,
         max_frame_size: 16,
-        sample_rate: 8000,
+        sample_rate: SampleRate::new(8000).unwrap(),
         channels: 2,
-        bits_per_sample: 8,
+        bits_per_sample: BitsPerSample::new(8).unwrap(),
         total_samples: 80000,
         md5_sum: [ 0xa0, 0x42, 0x23, 0x7c, 0x54, 0x93, 0xfd, 0xb9, 0x65, 0x6b
                  , 0x94, 0xa8, 0x36, 0x08, 0xd1, 0x1a
@@ -367,9 +667,9 @@ mod tests {
         max_block_size: 4096,
         min_frame_size: 2731,
         max_frame_size: 21253,
-        sample_rate: 48000,
+        sample_rate: SampleRate::new(48000).unwrap(),
         channels: 2,
-        bits_per_sample: 24,
+        bits_per_sample: BitsPerSample::new(24).unwrap(),
         total_samples: 10194762,
         md5_sum: [ 0xc6, 0x16, 0x1b, 0x2b, 0xb3, 0xf8, 0x1c, 0xa6, 0x72, 0x79
                  , 0x1d, 0x96, 0xf0, 0x9d, 0x0b, 0x0c
@@ -378,13 +678,30 @@ mod tests {
 
       assert_eq!(stream_info(input), IResult::Done(&[][..], result));
     }
+
+    {
+      // A sample rate of zero, then three bits per sample.
+      let inputs = [
+        b"\x12\0\x12\0\0\0\x0e\0\0\x10\0\0\x02\x70\0\x01\x38\
+          \x80\xa0\x42\x23\x7c\x54\x93\xfd\xb9\x65\x6b\x94\xa8\x36\
+          \x08\xd1\x1a",
+        b"\x12\0\x12\0\0\0\x0e\0\0\x10\x01\xf4\x02\x20\0\x01\x38\
+          \x80\xa0\x42\x23\x7c\x54\x93\xfd\xb9\x65\x6b\x94\xa8\x36\
+          \x08\xd1\x1a",
+      ];
+      let result = IResult::Error(Err::Code(nom::ErrorKind::Custom(
+                     ErrorKind::StreamInfoParser)));
+
+      assert_eq!(stream_info(inputs[0]), result);
+      assert_eq!(stream_info(inputs[1]), result);
+    }
   }
 
   #[test]
   fn test_padding() {
     let inputs = [b"\0\0\0\0\0\0\0\0\0\0", b"\0\0\0\0\x01\0\0\0\0\0"];
 
-    let result_valid   = IResult::Done(&[][..], metadata::Data::Padding(0));
+    let result_valid   = IResult::Done(&[][..], metadata::Data::Padding(10));
     let result_invalid = IResult::Error(Err::Code(nom::ErrorKind::Custom(
                            ErrorKind::PaddingParser)));
 
@@ -398,16 +715,18 @@ mod tests {
     let results = [
       IResult::Done(&[][..], metadata::Data::Application(Application {
         id: "fake".to_owned(),
-        data: vec![],
+        data: Payload::new(),
       })),
       IResult::Done(&[][..], metadata::Data::Application(Application {
         id: "riff".to_owned(),
-        data: inputs[1][4..].to_owned(),
+        data: Payload::from(&inputs[1][4..]),
       }))
     ];
 
     assert_eq!(application(inputs[0], 4), results[0]);
     assert_eq!(application(inputs[1], 13), results[1]);
+    assert_eq!(application(inputs[0], 2), IResult::Error(Err::Code(
+                 nom::ErrorKind::Custom(ErrorKind::ApplicationParser))));
   }
 
   #[test]
@@ -445,9 +764,35 @@ mod tests {
       }
     ]));
 
-    assert_eq!(seek_table(input, 5 * 18), result);
+    assert_eq!(seek_table(input, 5 * 18, PlaceholderPolicy::Keep), result);
+
+    let points = |policy| {
+      match seek_table(input, 5 * 18, policy) {
+        IResult::Done(_, metadata::Data::SeekTable(points)) => points,
+        result                                             => {
+          panic!("{:?}", result)
+        }
+      }
+    };
+
+    let dropped   = points(PlaceholderPolicy::Drop);
+    let compacted = points(PlaceholderPolicy::Compact);
+
+    assert_eq!(dropped.iter().map(|p| p.sample_number).collect::<Vec<_>>(),
+               [0, 4608]);
+    assert_eq!(compacted.iter().filter(|p| p.is_placeholder()).count(), 1);
+    assert_eq!(compacted.len(), 3);
+
+    // The bytes past the last whole point are left over, while a table cut
+    // short needs the rest of it.
+    assert_eq!(seek_table(&input[0..20], 20, PlaceholderPolicy::Drop),
+               IResult::Done(&input[18..20], metadata::Data::SeekTable(
+                 vec![SeekPoint::new().with_frame_samples(4608)])));
+    assert_eq!(seek_table(&input[0..20], 36, PlaceholderPolicy::Keep),
+               IResult::Incomplete(Needed::Size(36)));
   }
 
+  #[cfg(feature = "vorbis-comment")]
   #[test]
   fn test_vorbis_comment() {
     let input = b"\x20\0\0\0reference libFLAC 1.1.3 20060805\x06\0\0\0\
@@ -476,9 +821,58 @@ mod tests {
         comments: comments,
       }));
 
-    assert_eq!(vorbis_comment(input), result);
+    assert_eq!(vorbis_comment(input, 6), result);
+
+    let inputs  = [ &b"\0\0\0\0\x02\0\0\0\x04\0\0\0none\x03\0\0\0a=b"[..]
+                  , &b"\0\0\0\0\xff\xff\xff\xff\x03\0\0\0a=b"[..]
+                  ];
+    let mut comments = HashMap::new();
+
+    comments.insert("a".to_owned(), "b".to_owned());
+
+    let result = IResult::Done(&[][..],
+      metadata::Data::VorbisComment(VorbisComment{
+        vendor_string: String::new(),
+        comments: comments,
+      }));
+
+    assert_eq!(vorbis_comment(inputs[0], 2), result);
+    assert_eq!(vorbis_comment(inputs[1], 2), IResult::Error(Err::Code(
+                 nom::ErrorKind::Custom(ErrorKind::VorbisCommentParser))));
+
+    let input        = b"\0\0\0\0\x03\0\0\0\x03\0\0\0a=b\x03\0\0\0c=d\
+                         \x03\0\0\0e=f";
+    let mut comments = HashMap::new();
+
+    comments.insert("a".to_owned(), "b".to_owned());
+    comments.insert("c".to_owned(), "d".to_owned());
+
+    let result = IResult::Done(&[][..],
+      metadata::Data::VorbisComment(VorbisComment{
+        vendor_string: String::new(),
+        comments: comments,
+      }));
+
+    assert_eq!(vorbis_comment(input, 2), result);
+  }
+
+  #[cfg(feature = "vorbis-comment")]
+  #[test]
+  fn test_comment_iter() {
+    let input    = b"\x03\0\0\0abc\x04\0\0\0\x03\0\0\0a=b\
+                     \x04\0\0\0none\x05\0\0\0c=d=e\x09\0\0\0f";
+    let mut iter = CommentIter::new(input).unwrap();
+
+    assert_eq!(iter.vendor_string(), "abc");
+    assert_eq!(iter.next(), Some(("a", "b")));
+    assert_eq!(iter.next(), Some(("c", "d=e")));
+    assert_eq!(iter.next(), None);
+    assert_eq!(iter.next(), None);
+
+    assert!(CommentIter::new(b"\x04\0\0\0abc").is_err());
   }
 
+  #[cfg(feature = "cue-sheet")]
   #[test]
   fn test_cue_sheet() {
     let input  = b"1234567890123\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\
@@ -556,6 +950,7 @@ mod tests {
     assert_eq!(cue_sheet(input), result);
   }
 
+  #[cfg(feature = "picture")]
   #[test]
   fn test_picture() {
     let input  = b"\0\0\0\0\0\0\0\x09image/png\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\
@@ -569,7 +964,7 @@ mod tests {
         height: 0,
         depth: 0,
         colors: 0,
-        data: vec![],
+        data: Payload::new(),
       }));
 
     assert_eq!(picture(input), result);
@@ -579,8 +974,8 @@ mod tests {
   fn test_unknown() {
     let input  = b"random data that won't really be parsed anyway.";
     let result = IResult::Done(&[][..],
-                   metadata::Data::Unknown(input[..].to_owned()));
+                   metadata::Data::Unknown(9, Payload::from(&input[..])));
 
-    assert_eq!(unknown(input, 47), result);
+    assert_eq!(unknown(input, 9, 47), result);
   }
 }
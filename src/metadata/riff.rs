@@ -0,0 +1,248 @@
+use std::cmp;
+use std::io::{self, Read};
+
+use utility::ErrorKind;
+
+use metadata::VorbisComment;
+
+// Largest `INFO` list that gets read, in bytes, which is also the most the
+// data of a metadata block can hold.
+const MAX_INFO_LENGTH: u32 = 0xffffff;
+
+// Vorbis comment name for each chunk within an `INFO` list. The first
+// chunk found for a name wins, so `ITRK` is preferred over `IPRT`.
+const INFO_NAMES: [(&'static [u8; 4], &'static str); 9] = [
+  (b"INAM", "TITLE"),
+  (b"IART", "ARTIST"),
+  (b"IPRD", "ALBUM"),
+  (b"ICMT", "COMMENT"),
+  (b"ICRD", "DATE"),
+  (b"IGNR", "GENRE"),
+  (b"ITRK", "TRACKNUMBER"),
+  (b"IPRT", "TRACKNUMBER"),
+  (b"ICOP", "COPYRIGHT"),
+];
+
+/// Reads the tags within the `LIST` `INFO` chunk of a RIFF WAVE file, as a
+/// `VorbisComment`.
+///
+/// The common chunks get mapped to their vorbis comment: `INAM` to
+/// `TITLE`, `IART` to `ARTIST`, `IPRD` to `ALBUM`, `ICMT` to `COMMENT`,
+/// `ICRD` to `DATE`, `IGNR` to `GENRE`, `ITRK` or `IPRT` to `TRACKNUMBER`,
+/// and `ICOP` to `COPYRIGHT`. Any other chunk, along with empty values, is
+/// left out. Values that aren't UTF-8 are read as Latin-1, which is what
+/// most tools write. Chunks get skipped over, so the `LIST` chunk is found
+/// whether it comes before or after the audio, and a file cut short keeps
+/// the tags read before the end.
+///
+/// # Failures
+///
+/// * `ErrorKind::IO(io::ErrorKind::InvalidData)` is returned when the data
+///   doesn't start with a RIFF WAVE header.
+/// * `ErrorKind::NotFound` is returned when there is no `INFO` list.
+/// * `ErrorKind::IO` is returned for any other error from `reader`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use flac::metadata;
+///
+/// use std::fs::File;
+///
+/// let file = File::open("path/to/input.wav").unwrap();
+/// let tags = metadata::read_riff_info(file).unwrap();
+///
+/// println!("{:?}", tags.comments.get("TITLE"));
+/// ```
+pub fn read_riff_info<R: Read>(reader: R)
+                               -> Result<VorbisComment, ErrorKind> {
+  let mut reader = reader;
+  let mut header = [0; 12];
+
+  try!(reader.read_exact(&mut header).map_err(to_error));
+
+  if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+    return Err(ErrorKind::IO(io::ErrorKind::InvalidData));
+  }
+
+  let mut vorbis_comment = VorbisComment::new();
+  let mut is_found       = false;
+
+  loop {
+    let mut chunk = [0; 8];
+
+    match reader.read_exact(&mut chunk) {
+      Ok(())                                                 => (),
+      Err(ref error) if error.kind() == io::ErrorKind::UnexpectedEof => {
+        break;
+      }
+      Err(error)                                             => {
+        return Err(to_error(error));
+      }
+    }
+
+    let size   = le_u32(&chunk[4..8]);
+    let padded = size as u64 + (size & 1) as u64;
+
+    if &chunk[0..4] == b"LIST" && size >= 4 && size <= MAX_INFO_LENGTH {
+      let mut data = vec![0; padded as usize];
+      let length   = try!(read_fully(&mut reader, &mut data));
+
+      if length >= 4 && &data[0..4] == b"INFO" {
+        parse_info(&data[4..length], &mut vorbis_comment);
+
+        is_found = true;
+      }
+
+      if length < data.len() {
+        break;
+      }
+    } else {
+      let skipped = try!(io::copy(&mut reader.by_ref().take(padded),
+                                  &mut io::sink()).map_err(to_error));
+
+      if skipped < padded {
+        break;
+      }
+    }
+  }
+
+  if is_found {
+    Ok(vorbis_comment)
+  } else {
+    Err(ErrorKind::NotFound)
+  }
+}
+
+// Adds the value of each chunk within an `INFO` list that maps to a vorbis
+// comment.
+fn parse_info(data: &[u8], vorbis_comment: &mut VorbisComment) {
+  let mut rest = data;
+
+  while rest.len() >= 8 {
+    let size = le_u32(&rest[4..8]) as usize;
+    let end  = cmp::min(size.saturating_add(8), rest.len());
+    let name = INFO_NAMES.iter().find(|&&(id, _)| id[..] == rest[0..4])
+                         .map(|&(_, name)| name);
+
+    if let Some(name) = name {
+      let value = info_text(&rest[8..end]);
+
+      if !value.is_empty() && !vorbis_comment.comments.contains_key(name) {
+        vorbis_comment.comments.insert(name.to_owned(), value);
+      }
+    }
+
+    rest = &rest[cmp::min(end + (size & 1), rest.len())..];
+  }
+}
+
+// Returns the text of a chunk, which is usually terminated by a null byte.
+fn info_text(bytes: &[u8]) -> String {
+  let bytes = bytes.split(|&byte| byte == 0).next().unwrap_or(&[]);
+  let text  = match String::from_utf8(bytes.to_vec()) {
+    Ok(text) => text,
+    Err(_)   => bytes.iter().map(|&byte| byte as char).collect(),
+  };
+
+  text.trim().to_owned()
+}
+
+// Reads until `buffer` is full or the end of input, returning the number
+// of bytes read.
+fn read_fully<R: Read>(reader: &mut R, buffer: &mut [u8])
+                       -> Result<usize, ErrorKind> {
+  let mut length = 0;
+
+  while length < buffer.len() {
+    match reader.read(&mut buffer[length..]) {
+      Ok(0)                                                   => break,
+      Ok(count)                                               => {
+        length += count;
+      }
+      Err(ref error) if error.kind() == io::ErrorKind::Interrupted => (),
+      Err(error)                                              => {
+        return Err(to_error(error));
+      }
+    }
+  }
+
+  Ok(length)
+}
+
+fn le_u32(bytes: &[u8]) -> u32 {
+  (bytes[0] as u32) | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 |
+  (bytes[3] as u32) << 24
+}
+
+fn to_error(error: io::Error) -> ErrorKind {
+  ErrorKind::IO(error.kind())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::io::Cursor;
+
+  fn chunk(id: &[u8], data: &[u8]) -> Vec<u8> {
+    let size      = data.len() as u32;
+    let mut bytes = id.to_vec();
+
+    bytes.extend_from_slice(&[size as u8, (size >> 8) as u8,
+                              (size >> 16) as u8, (size >> 24) as u8]);
+    bytes.extend_from_slice(data);
+
+    if data.len() % 2 == 1 {
+      bytes.push(0);
+    }
+
+    bytes
+  }
+
+  fn wave(chunks: &[Vec<u8>]) -> Vec<u8> {
+    let body = chunks.iter().fold(b"WAVE".to_vec(), |mut body, chunk| {
+      body.extend_from_slice(chunk);
+
+      body
+    });
+
+    chunk(b"RIFF", &body)
+  }
+
+  #[test]
+  fn test_read_riff_info() {
+    let mut info = b"INFO".to_vec();
+
+    info.extend(chunk(b"INAM", b"Song\0"));
+    info.extend(chunk(b"IART", b"Caf\xe9 Band\0"));
+    info.extend(chunk(b"IPRD", b"  Album  \0"));
+    info.extend(chunk(b"ISFT", b"Some Editor\0"));
+    info.extend(chunk(b"ITRK", b"3\0"));
+    info.extend(chunk(b"IPRT", b"9\0"));
+    info.extend(chunk(b"IGNR", b"\0"));
+
+    // The `LIST` chunk coming after the audio still gets found.
+    let bytes = wave(&[chunk(b"fmt ", &[0; 16]), chunk(b"data", &[1; 7]),
+                       chunk(b"LIST", &info)]);
+    let tags  = read_riff_info(Cursor::new(&bytes)).unwrap();
+
+    assert_eq!(tags, VorbisComment::new().with_comment("TITLE", "Song")
+                                         .with_comment("ARTIST", "Café Band")
+                                         .with_comment("ALBUM", "Album")
+                                         .with_comment("TRACKNUMBER", "3"));
+
+    // A file cut short keeps what was read.
+    let end = bytes.len() - 20;
+
+    assert_eq!(read_riff_info(Cursor::new(&bytes[..end])).unwrap()
+                 .comments.get("TITLE"), Some(&"Song".to_owned()));
+
+    let other = wave(&[chunk(b"LIST", b"adtlnote"), chunk(b"data", &[])]);
+
+    assert_eq!(read_riff_info(Cursor::new(&other)),
+               Err(ErrorKind::NotFound));
+    assert_eq!(read_riff_info(Cursor::new(&b"fLaC\0\0\0\0\0\0\0\0"[..])),
+               Err(ErrorKind::IO(io::ErrorKind::InvalidData)));
+  }
+}
@@ -1,8 +1,48 @@
+use std::cmp;
+#[cfg(feature = "vorbis-comment")]
 use std::collections::HashMap;
+#[cfg(feature = "picture")]
 use std::fmt;
 use std::io;
+use std::mem;
+#[cfg(feature = "cue-sheet")]
+use std::u32;
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::Duration;
 
-use utility::WriteExtension;
+use utility::{BitsPerSample, SampleRate, WriteExtension};
+
+#[cfg(feature = "bytes")]
+use shared_bytes::Bytes;
+
+// Largest length, in bytes, of a metadata block since the length is stored
+// as a 24-bit number within the metadata block header.
+const MAX_BLOCK_LENGTH: usize = 0xffffff;
+
+// Length, in bytes, of the ID of an `Application` block.
+const APPLICATION_ID_LENGTH: usize = 4;
+
+// Sample number of a placeholder `SeekPoint`.
+const PLACEHOLDER: u64 = 0xffffffffffffffff;
+
+// Length, in bytes, of the media catalog number of a `CueSheet`.
+#[cfg(feature = "cue-sheet")]
+const MEDIA_CATALOG_NUMBER_LENGTH: usize = 128;
+
+/// Owned bytes of a block's binary payload, like the data of a picture.
+///
+/// With the `bytes` feature enabled this is `bytes::Bytes`, which is cheap
+/// to clone since the bytes are shared instead of copied.
+#[cfg(feature = "bytes")]
+pub type Payload = Bytes;
+
+/// Owned bytes of a block's binary payload, like the data of a picture.
+///
+/// With the `bytes` feature enabled this is `bytes::Bytes`, which is cheap
+/// to clone since the bytes are shared instead of copied.
+#[cfg(not(feature = "bytes"))]
+pub type Payload = Vec<u8>;
 
 /// Data associated with a single metadata block.
 #[derive(Debug)]
@@ -14,10 +54,15 @@ pub struct Metadata {
   length: u32,
   /// Block data containing one of the eight different types of metadata.
   pub data: Data,
+  /// Original bytes of the block, including the metadata block header, as
+  /// a range within bytes shared by every block of the stream.
+  raw: Option<(Arc<[u8]>, Range<usize>)>,
+  /// Whether the binary payload of the block got dropped to save memory.
+  is_spilled: bool,
 }
 
 /// An enum that represents a metadata block type.
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Type {
   /// Represents the current block is stream information.
   StreamInfo,
@@ -51,6 +96,41 @@ macro_rules! is_block_type (
   );
 );
 
+macro_rules! block_data (
+  ($(
+     $(#[$attr: meta])*
+     ($name: ident, $block_type: ident) -> $data: ty
+   )+) => (
+    $(
+    $(#[$attr])*
+    #[inline]
+    pub fn $name(&self) -> Option<&$data> {
+      match self.data {
+        Data::$block_type(ref data) => Some(data),
+        _                           => None,
+      }
+    }
+    )+
+  );
+);
+
+macro_rules! into_block_data (
+  ($(
+     $(#[$attr: meta])*
+     ($name: ident, $block_type: ident) -> $data: ty
+   )+) => (
+    $(
+    $(#[$attr])*
+    pub fn $name(self) -> Result<$data, Metadata> {
+      match self.data {
+        Data::$block_type(data) => Ok(data),
+        _                       => Err(self),
+      }
+    }
+    )+
+  );
+);
+
 impl Metadata {
   /// Constructs a new `Metadata` struct based on the arguments passed in.
   pub fn new(is_last: bool, length: u32, data: Data) -> Self {
@@ -58,6 +138,8 @@ impl Metadata {
       is_last: is_last,
       length: length,
       data: data,
+      raw: None,
+      is_spilled: false,
     }
   }
 
@@ -67,6 +149,116 @@ impl Metadata {
     self.is_last
   }
 
+  /// Returns the length, in bytes, the block was stored with, not counting
+  /// the metadata block header.
+  #[inline]
+  pub fn length(&self) -> u32 {
+    self.length
+  }
+
+  /// Returns the original bytes, metadata block header included, that this
+  /// block was parsed from.
+  ///
+  /// Only blocks parsed from an in-memory buffer, like with
+  /// `Stream::from_buffer`, hold on to their original bytes. Every other
+  /// block returns `None`. The blocks of a stream share a single copy of
+  /// the metadata, so each block only points into it rather than holding
+  /// bytes of its own.
+  #[inline]
+  pub fn raw(&self) -> Option<&[u8]> {
+    self.raw.as_ref().map(|&(ref bytes, ref range)| &bytes[range.clone()])
+  }
+
+  // Sets the original bytes, metadata block header included, that this
+  // block was parsed from, as a range within `bytes`.
+  pub(crate) fn set_raw(&mut self, bytes: Arc<[u8]>, range: Range<usize>) {
+    debug_assert!(range.end <= bytes.len());
+
+    self.raw = Some((bytes, range));
+  }
+
+  // Drops the original bytes, returning the number of bytes they were
+  // counted as by `heap_size`.
+  pub(crate) fn drop_raw(&mut self) -> usize {
+    self.raw.take().map_or(0, |(_, range)| range.len())
+  }
+
+  /// Returns roughly how many bytes of heap memory the block takes up.
+  ///
+  /// The contents of every string, list, and payload get counted, along
+  /// with the original bytes from `Metadata::raw`, but not the overhead of
+  /// the allocator.
+  pub fn heap_size(&self) -> usize {
+    let data = match self.data {
+      Data::StreamInfo(_)        => 0,
+      Data::Padding(_)           => 0,
+      Data::Application(ref a)   => a.id.len() + a.data.len(),
+      Data::SeekTable(ref s)     => s.len() * mem::size_of::<SeekPoint>(),
+      #[cfg(feature = "vorbis-comment")]
+      Data::VorbisComment(ref v) => {
+        v.comments.iter().fold(v.vendor_string.len(), |result, entry| {
+          result + mem::size_of::<(String, String)>() + entry.0.len() +
+            entry.1.len()
+        })
+      }
+      #[cfg(feature = "cue-sheet")]
+      Data::CueSheet(ref c)      => {
+        c.tracks.iter().fold(c.media_catalog_number.len(), |result, track| {
+          result + mem::size_of::<CueSheetTrack>() + track.isrc.len() +
+            track.indices.len() * mem::size_of::<CueSheetTrackIndex>()
+        })
+      }
+      #[cfg(feature = "picture")]
+      Data::Picture(ref p)       => {
+        p.mime_type.len() + p.description.len() + p.data.len()
+      }
+      Data::Unknown(_, ref u)    => u.len(),
+    };
+
+    data + self.raw.as_ref().map_or(0, |&(_, ref range)| range.len())
+  }
+
+  /// Returns whether the binary payload of the block got dropped through
+  /// `Metadata::spill`.
+  #[inline]
+  pub fn is_spilled(&self) -> bool {
+    self.is_spilled
+  }
+
+  /// Drops the binary payload of an `Application`, `Picture`, or `Unknown`
+  /// block, along with the original bytes of any block, returning the
+  /// number of bytes freed.
+  ///
+  /// The original bytes are shared by every block of a stream, so they
+  /// only get freed once each of those blocks has been spilled.
+  ///
+  /// Everything else about the block stays, like the type and description
+  /// of a picture, so it still describes what was stored. A block with a
+  /// payload dropped can't be written out anymore, see
+  /// `Stream::load_block` for getting it back.
+  pub fn spill(&mut self) -> usize {
+    let before = self.heap_size();
+
+    let payload = match self.data {
+      Data::Application(ref mut a) => Some(&mut a.data),
+      #[cfg(feature = "picture")]
+      Data::Picture(ref mut p)     => Some(&mut p.data),
+      Data::Unknown(_, ref mut u)  => Some(u),
+      _                            => None,
+    };
+
+    if let Some(payload) = payload {
+      if !payload.is_empty() {
+        *payload        = Payload::new();
+        self.is_spilled = true;
+      }
+    }
+
+    self.drop_raw();
+
+    before - self.heap_size()
+  }
+
   /// Returns the metadata block's type.
   pub fn data_type(&self) -> Type {
     match self.data {
@@ -74,10 +266,13 @@ impl Metadata {
       Data::Padding(_)       => Type::Padding,
       Data::Application(_)   => Type::Application,
       Data::SeekTable(_)     => Type::SeekTable,
+      #[cfg(feature = "vorbis-comment")]
       Data::VorbisComment(_) => Type::VorbisComment,
+      #[cfg(feature = "cue-sheet")]
       Data::CueSheet(_)      => Type::CueSheet,
+      #[cfg(feature = "picture")]
       Data::Picture(_)       => Type::Picture,
-      Data::Unknown(_)       => Type::Unknown,
+      Data::Unknown(..)      => Type::Unknown,
     }
   }
 
@@ -100,6 +295,66 @@ impl Metadata {
     (is_unknown) -> Unknown
   }
 
+  block_data! {
+    /// Returns the `StreamInfo` this block holds, if it is one.
+    (as_stream_info, StreamInfo) -> StreamInfo
+    /// Returns the `Application` this block holds, if it is one.
+    (as_application, Application) -> Application
+    /// Returns the seek points this block holds, if it's a seek table.
+    (as_seek_table, SeekTable) -> Vec<SeekPoint>
+    /// Returns the `VorbisComment` this block holds, if it is one.
+    #[cfg(feature = "vorbis-comment")]
+    (as_vorbis_comment, VorbisComment) -> VorbisComment
+    /// Returns the `CueSheet` this block holds, if it is one.
+    #[cfg(feature = "cue-sheet")]
+    (as_cue_sheet, CueSheet) -> CueSheet
+    /// Returns the `Picture` this block holds, if it is one.
+    #[cfg(feature = "picture")]
+    (as_picture, Picture) -> Picture
+  }
+
+  /// Returns the bytes of a block that wasn't parsed, if it is one.
+  #[inline]
+  pub fn as_unknown(&self) -> Option<&Payload> {
+    match self.data {
+      Data::Unknown(_, ref data) => Some(data),
+      _                          => None,
+    }
+  }
+
+  into_block_data! {
+    /// Converts the block into the `StreamInfo` it holds, or gives the block
+    /// back when it's something else.
+    (into_stream_info, StreamInfo) -> StreamInfo
+    /// Converts the block into the `Application` it holds, or gives the
+    /// block back when it's something else.
+    (into_application, Application) -> Application
+    /// Converts the block into the seek points it holds, or gives the block
+    /// back when it isn't a seek table.
+    (into_seek_table, SeekTable) -> Vec<SeekPoint>
+    /// Converts the block into the `VorbisComment` it holds, or gives the
+    /// block back when it's something else.
+    #[cfg(feature = "vorbis-comment")]
+    (into_vorbis_comment, VorbisComment) -> VorbisComment
+    /// Converts the block into the `CueSheet` it holds, or gives the block
+    /// back when it's something else.
+    #[cfg(feature = "cue-sheet")]
+    (into_cue_sheet, CueSheet) -> CueSheet
+    /// Converts the block into the `Picture` it holds, or gives the block
+    /// back when it's something else.
+    #[cfg(feature = "picture")]
+    (into_picture, Picture) -> Picture
+  }
+
+  /// Converts the block into the bytes of a block that wasn't parsed, or
+  /// gives the block back when it's something else.
+  pub fn into_unknown(self) -> Result<Payload, Metadata> {
+    match self.data {
+      Data::Unknown(_, data) => Ok(data),
+      _                      => Err(self),
+    }
+  }
+
   #[inline]
   pub fn bytes_len(&self) -> usize {
     4 + match self.data {
@@ -110,25 +365,85 @@ impl Metadata {
         s.iter().fold(0, |result, seek_point|
           result + seek_point.bytes_len())
       }
+      #[cfg(feature = "vorbis-comment")]
       Data::VorbisComment(ref v) => v.bytes_len(),
+      #[cfg(feature = "cue-sheet")]
       Data::CueSheet(ref c)      => c.bytes_len(),
+      #[cfg(feature = "picture")]
       Data::Picture(ref p)       => p.bytes_len(),
-      Data::Unknown(ref u)       => u.len(),
+      Data::Unknown(_, ref u)    => u.len(),
     }
   }
 
+  /// Writes the metadata block, header included, to the given buffer.
+  ///
+  /// # Failures
+  ///
+  /// * `io::ErrorKind::InvalidInput` is returned when the block is larger
+  ///   than the 24-bit length within the metadata block header can hold.
+  ///   Nothing gets written to the buffer when this happens.
+  /// * `io::ErrorKind::InvalidInput` is returned for an `Unknown` block
+  ///   with a block type of 127 or above, which can't be written.
+  /// * `io::ErrorKind::InvalidInput` is returned when the payload of the
+  ///   block was spilled.
+  /// * Any error returned from writing to the buffer.
   pub fn to_bytes<Write: io::Write>(&self, buffer: &mut Write)
                                     -> io::Result<()> {
-    let byte = if self.is_last {
+    self.write_bytes(self.is_last, buffer)
+  }
+
+  // Writes the metadata block with the given is last flag, rather than the
+  // one stored in the block.
+  #[cfg(not(feature = "debug-validate"))]
+  #[inline]
+  fn write_bytes<Write: io::Write>(&self, is_last: bool, buffer: &mut Write)
+                                   -> io::Result<()> {
+    self.write_block(is_last, buffer)
+  }
+
+  // Same as above, except the written block gets parsed back and checked
+  // against this one before anything reaches the buffer.
+  #[cfg(feature = "debug-validate")]
+  fn write_bytes<Write: io::Write>(&self, is_last: bool, buffer: &mut Write)
+                                   -> io::Result<()> {
+    let mut bytes = Vec::with_capacity(self.bytes_len());
+
+    try!(self.write_block(is_last, &mut bytes));
+
+    validate_block(self, is_last, &bytes);
+
+    buffer.write_all(&bytes)
+  }
+
+  fn write_block<Write: io::Write>(&self, is_last: bool, buffer: &mut Write)
+                                   -> io::Result<()> {
+    let length = self.bytes_len() - 4;
+    let byte   = if is_last {
       0b10000000
     } else {
       0b00000000
     };
 
+    if self.is_spilled {
+      return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                "block payload was spilled"));
+    }
+
+    if length > MAX_BLOCK_LENGTH {
+      return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                "metadata block is larger than 24-bit \
+                                 length allows"));
+    }
+
+    if let Data::Unknown(block_type, _) = self.data {
+      if block_type >= 127 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  "unknown metadata block type is invalid"));
+      }
+    }
+
     match self.data {
       Data::StreamInfo(ref stream_info)       => {
-        let length = stream_info.bytes_len();
-
         try!(buffer.write_u8(byte + 0));
 
         try!(buffer.write_be_u24(length as u32));
@@ -137,20 +452,18 @@ impl Metadata {
 
         Ok(())
       }
-      Data::Padding(length)                   => {
-        let padding = vec![0; length as usize];
+      Data::Padding(_)                        => {
+        let padding = vec![0; length];
 
         try!(buffer.write_u8(byte + 1));
 
-        try!(buffer.write_be_u24(length));
+        try!(buffer.write_be_u24(length as u32));
 
         try!(buffer.write_all(&padding));
 
         Ok(())
       }
       Data::Application(ref application)      => {
-        let length = application.bytes_len();
-
         try!(buffer.write_u8(byte + 2));
 
         try!(buffer.write_be_u24(length as u32));
@@ -160,9 +473,6 @@ impl Metadata {
         Ok(())
       }
       Data::SeekTable(ref seek_points)        => {
-        let length = seek_points.iter().fold(0, |result, seek_point|
-                       result + seek_point.bytes_len());
-
         try!(buffer.write_u8(byte + 3));
 
         try!(buffer.write_be_u24(length as u32));
@@ -173,9 +483,8 @@ impl Metadata {
 
         Ok(())
       }
+      #[cfg(feature = "vorbis-comment")]
       Data::VorbisComment(ref vorbis_comment) => {
-        let length = vorbis_comment.bytes_len();
-
         try!(buffer.write_u8(byte + 4));
 
         try!(buffer.write_be_u24(length as u32));
@@ -184,9 +493,8 @@ impl Metadata {
 
         Ok(())
       }
+      #[cfg(feature = "cue-sheet")]
       Data::CueSheet(ref cue_sheet)           => {
-        let length = cue_sheet.bytes_len();
-
         try!(buffer.write_u8(byte + 5));
 
         try!(buffer.write_be_u24(length as u32));
@@ -195,9 +503,8 @@ impl Metadata {
 
         Ok(())
       }
+      #[cfg(feature = "picture")]
       Data::Picture(ref picture)              => {
-        let length = picture.bytes_len();
-
         try!(buffer.write_u8(byte + 6));
 
         try!(buffer.write_be_u24(length as u32));
@@ -206,10 +513,8 @@ impl Metadata {
 
         Ok(())
       }
-      Data::Unknown(ref unknown)              => {
-        let length = unknown.len();
-
-        try!(buffer.write_u8(byte + 7));
+      Data::Unknown(block_type, ref unknown)  => {
+        try!(buffer.write_u8(byte + block_type));
 
         try!(buffer.write_be_u24(length as u32));
 
@@ -221,6 +526,95 @@ impl Metadata {
   }
 }
 
+// Panics when the bytes written for `block` don't parse back into the same
+// block, which points at a bug within one of the serializers.
+#[cfg(feature = "debug-validate")]
+fn validate_block(block: &Metadata, is_last: bool, bytes: &[u8]) {
+  use metadata::metadata_parser_with_limit;
+  use nom::IResult;
+  use std::usize;
+
+  assert_eq!(bytes.len(), block.bytes_len(),
+             "written metadata block has the wrong length");
+
+  match metadata_parser_with_limit(bytes, usize::MAX) {
+    IResult::Done(rest, parsed) => {
+      assert!(rest.is_empty(), "written metadata block has extra bytes");
+      assert_eq!(parsed.is_last, is_last);
+      assert_eq!(parsed.data, block.data);
+    }
+    result                      => {
+      panic!("written metadata block fails to parse: {:?}", result);
+    }
+  }
+}
+
+/// Serializes a sequence of metadata blocks, headers included.
+///
+/// The is last flag of each block gets recomputed, so only the final block
+/// is marked as the last one, no matter what each block was constructed
+/// with. The "fLaC" header isn't included.
+///
+/// # Failures
+///
+/// * `io::ErrorKind::InvalidInput` is returned when there are no blocks,
+///   when the first block isn't `StreamInfo`, or when `StreamInfo` shows up
+///   anywhere after the first block.
+/// * `io::ErrorKind::InvalidInput` is returned when any block is larger
+///   than the 24-bit length within the metadata block header can hold.
+///
+/// # Examples
+///
+/// ```
+/// use flac::BitsPerSample;
+/// use flac::metadata::{self, Data, Metadata, StreamInfo};
+///
+/// let mut stream_info = StreamInfo::default();
+///
+/// stream_info.channels        = 2;
+/// stream_info.bits_per_sample = BitsPerSample::new(16).unwrap();
+///
+/// let blocks = [
+///   Metadata::new(true, 34, Data::StreamInfo(stream_info)),
+///   Metadata::new(true, 8, Data::Padding(8)),
+/// ];
+///
+/// let bytes = metadata::serialize_blocks(&blocks).unwrap();
+///
+/// assert_eq!(bytes[0], 0x00);
+/// assert_eq!(bytes[38], 0x81);
+/// ```
+pub fn serialize_blocks(blocks: &[Metadata]) -> io::Result<Vec<u8>> {
+  let invalid_input = |message| {
+    Err(io::Error::new(io::ErrorKind::InvalidInput, message))
+  };
+
+  if blocks.is_empty() {
+    return invalid_input("no metadata blocks to serialize");
+  }
+
+  if !blocks[0].is_stream_info() {
+    return invalid_input("first metadata block isn't StreamInfo");
+  }
+
+  if blocks[1..].iter().any(|block| block.is_stream_info()) {
+    return invalid_input("StreamInfo after the first metadata block");
+  }
+
+  let last     = blocks.len() - 1;
+  let capacity = blocks.iter().fold(0, |result, block| {
+    result + block.bytes_len()
+  });
+
+  let mut bytes = Vec::with_capacity(capacity);
+
+  for (index, block) in blocks.iter().enumerate() {
+    try!(block.write_bytes(index == last, &mut bytes));
+  }
+
+  Ok(bytes)
+}
+
 /// General enum that hold all the different metadata block data.
 #[derive(Debug, PartialEq, Eq)]
 pub enum Data {
@@ -232,14 +626,21 @@ pub enum Data {
   Application(Application),
   /// Table of multiple points to seek, or skip, to within the FLAC file.
   SeekTable(Vec<SeekPoint>),
+  #[cfg(feature = "vorbis-comment")]
   /// Stores human-readable name/value pairs.
   VorbisComment(VorbisComment),
+  #[cfg(feature = "cue-sheet")]
   /// Stores cue information
   CueSheet(CueSheet),
+  #[cfg(feature = "picture")]
   /// Stores pictures associated with the FLAC file.
   Picture(Picture),
-  /// A type of block data that isn't know or doesn't match the type above.
-  Unknown(Vec<u8>),
+  /// A type of block data that isn't know or doesn't match the type above,
+  /// along with the block type it was stored as.
+  ///
+  /// The block type gets written back out as is, so blocks pass through
+  /// untouched. It needs to be below 127, which is an invalid block type.
+  Unknown(u8, Payload),
 }
 
 /// Information regarding the entire audio stream.
@@ -256,11 +657,11 @@ pub struct StreamInfo {
   /// the value isn't know.
   pub max_frame_size: u32,
   /// Sample rate in hertz (Hz).
-  pub sample_rate: u32,
+  pub sample_rate: SampleRate,
   /// Number of channels. FLAC supports one to eight channel.
   pub channels: u8,
   /// Bits per sample. FLAC supports four to thirty-two bits per sample.
-  pub bits_per_sample: u8,
+  pub bits_per_sample: BitsPerSample,
   /// Total samples in the stream. A value of zero means the number is
   /// unknown.
   pub total_samples: u64,
@@ -283,6 +684,90 @@ impl StreamInfo {
     self.min_block_size == self.max_block_size
   }
 
+  /// Returns whether the stream holds audio going by its sample rate, see
+  /// `SampleRate::is_audio_rate`.
+  #[inline]
+  pub fn is_audio_rate(&self) -> bool {
+    self.sample_rate.is_audio_rate()
+  }
+
+  /// Returns the minimum and maximum frame size, in bytes, treating the
+  /// ones stored within `StreamInfo` as hints.
+  ///
+  /// Decoding never relies on the stored frame sizes, so streams with the
+  /// wrong ones still play, and plenty of encoders and editors leave them
+  /// that way. Each size gets clamped between the smallest and largest
+  /// frame the rest of `StreamInfo` allows, with unknown sizes, stored as
+  /// zero, taking on those limits instead. The largest frame stores every
+  /// sample uncoded, which no sane encoder goes over.
+  ///
+  /// `metadata::recompute_frame_sizes` fixes the stored sizes of a file.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use flac::BitsPerSample;
+  /// use flac::metadata::StreamInfo;
+  ///
+  /// let mut info = StreamInfo::default();
+  ///
+  /// info.max_block_size  = 4096;
+  /// info.channels        = 2;
+  /// info.bits_per_sample = BitsPerSample::new(16).unwrap();
+  /// info.min_frame_size  = 1;
+  /// info.max_frame_size  = 0xffffff;
+  ///
+  /// assert_eq!(info.frame_size_hint(), (14, 17428));
+  /// assert!(!info.has_sane_frame_sizes());
+  /// ```
+  pub fn frame_size_hint(&self) -> (u32, u32) {
+    let (smallest, largest) = self.frame_size_limits();
+
+    let min = if self.min_frame_size == 0 {
+      smallest
+    } else {
+      cmp::min(cmp::max(self.min_frame_size, smallest), largest)
+    };
+    let max = if self.max_frame_size == 0 {
+      largest
+    } else {
+      cmp::min(cmp::max(self.max_frame_size, min), largest)
+    };
+
+    (min, max)
+  }
+
+  /// Returns true when both frame sizes are known, in order, and within
+  /// the limits `StreamInfo::frame_size_hint` clamps them to.
+  pub fn has_sane_frame_sizes(&self) -> bool {
+    let (smallest, largest) = self.frame_size_limits();
+
+    self.min_frame_size >= smallest &&
+    self.min_frame_size <= self.max_frame_size &&
+    self.max_frame_size <= largest
+  }
+
+  // Smallest and largest frame sizes, in bytes, the block size, channels,
+  // and bits per sample allow.
+  //
+  // The smallest frame has the shortest header and constant subframes,
+  // while the largest has the longest header and stores each sample with
+  // an extra bit, which covers the side channel.
+  fn frame_size_limits(&self) -> (u32, u32) {
+    let block_size = match self.max_block_size {
+      0    => 0xffff,
+      size => size as u64,
+    };
+    let channels   = cmp::max(self.channels, 1) as u64;
+    let bits       = self.bits_per_sample.get() as u64;
+    let smallest   = 6 + (channels * (8 + bits) + 7) / 8 + 2;
+    let largest    = 16 + channels +
+                     (block_size * channels * (bits + 1) + 7) / 8 + 2;
+
+    // Frame sizes are stored with 24 bits.
+    (smallest as u32, cmp::min(largest, 0xffffff) as u32)
+  }
+
   #[inline]
   pub fn bytes_len(&self) -> usize {
     34
@@ -296,14 +781,17 @@ impl StreamInfo {
     try!(buffer.write_be_u24(self.min_frame_size));
     try!(buffer.write_be_u24(self.max_frame_size));
 
+    let sample_rate     = self.sample_rate.get();
+    let bits_per_sample = self.bits_per_sample.get();
+
     let bytes = [
-      (self.sample_rate >> 12) as u8,
-      (self.sample_rate >> 4) as u8,
+      (sample_rate >> 12) as u8,
+      (sample_rate >> 4) as u8,
 
-      ((self.sample_rate << 4) as u8) | ((self.channels - 1) << 1) |
-      ((self.bits_per_sample - 1) >> 4),
+      ((sample_rate << 4) as u8) | ((self.channels - 1) << 1) |
+      ((bits_per_sample - 1) >> 4),
 
-      ((self.bits_per_sample - 1) << 4) | ((self.total_samples >> 32) as u8),
+      ((bits_per_sample - 1) << 4) | ((self.total_samples >> 32) as u8),
     ];
 
     try!(buffer.write_all(&bytes));
@@ -319,11 +807,42 @@ impl StreamInfo {
 pub struct Application {
   /// Registered application ID.
   pub id: String,
-  /// Data used by the third-party application.
-  pub data: Vec<u8>,
+  /// Data used by the third-party application, which `bytes::Cursor`
+  /// reads through.
+  pub data: Payload,
+}
+
+impl Default for Application {
+  fn default() -> Self {
+    Application {
+      id: fixed_length("", APPLICATION_ID_LENGTH),
+      data: Payload::new(),
+    }
+  }
 }
 
 impl Application {
+  /// Constructs an application block with a blank ID and no data.
+  #[inline]
+  pub fn new() -> Self {
+    Default::default()
+  }
+
+  /// Sets the registered application ID, which gets padded with null
+  /// characters, or cut off, to four bytes.
+  pub fn with_id(mut self, id: &str) -> Self {
+    self.id = fixed_length(id, APPLICATION_ID_LENGTH);
+
+    self
+  }
+
+  /// Sets the data used by the third-party application.
+  pub fn with_data<D: Into<Payload>>(mut self, data: D) -> Self {
+    self.data = data.into();
+
+    self
+  }
+
   #[inline]
   pub fn bytes_len(&self) -> usize {
     4 + self.data.len()
@@ -338,7 +857,7 @@ impl Application {
 }
 
 /// Seek, or skip, to a point within the FLAC file.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Default, PartialEq, Eq)]
 pub struct SeekPoint {
   /// Sample number of the first sample in the target frame.
   pub sample_number: u64,
@@ -349,6 +868,41 @@ pub struct SeekPoint {
 }
 
 impl SeekPoint {
+  /// Constructs a seek point to the first sample of the stream.
+  #[inline]
+  pub fn new() -> Self {
+    Default::default()
+  }
+
+  /// Sets the sample number of the first sample in the target frame.
+  pub fn with_sample_number(mut self, sample_number: u64) -> Self {
+    self.sample_number = sample_number;
+
+    self
+  }
+
+  /// Sets the byte offset of the target frame's header, from the first
+  /// frame of the stream.
+  pub fn with_stream_offset(mut self, stream_offset: u64) -> Self {
+    self.stream_offset = stream_offset;
+
+    self
+  }
+
+  /// Sets the number of samples in the target frame.
+  pub fn with_frame_samples(mut self, frame_samples: u16) -> Self {
+    self.frame_samples = frame_samples;
+
+    self
+  }
+
+  /// Returns whether this is a placeholder point, which reserves room
+  /// within the table and doesn't point anywhere.
+  #[inline]
+  pub fn is_placeholder(&self) -> bool {
+    self.sample_number == PLACEHOLDER
+  }
+
   pub fn bytes_len(&self) -> usize {
     18
   }
@@ -363,8 +917,32 @@ impl SeekPoint {
   }
 }
 
+/// What happens to the placeholder points of a `SeekTable` block when it
+/// gets parsed.
+///
+/// Encoders often reserve room for thousands of seek points, written as
+/// placeholders until they get filled in. None of them point anywhere, so
+/// seeking skips over them no matter which policy gets used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlaceholderPolicy {
+  /// Keep every placeholder point, which is the default.
+  Keep,
+  /// Drop every placeholder point.
+  Drop,
+  /// Keep only the first placeholder point, so the table is still known to
+  /// have room for more.
+  Compact,
+}
+
+impl Default for PlaceholderPolicy {
+  fn default() -> Self {
+    PlaceholderPolicy::Keep
+  }
+}
+
 /// Stores human-readable name/value pairs.
-#[derive(Debug, PartialEq, Eq)]
+#[cfg(feature = "vorbis-comment")]
+#[derive(Debug, Default, PartialEq, Eq)]
 pub struct VorbisComment {
   /// Vendor name.
   pub vendor_string: String,
@@ -373,7 +951,124 @@ pub struct VorbisComment {
   pub comments: HashMap<String, String>,
 }
 
+#[cfg(feature = "vorbis-comment")]
 impl VorbisComment {
+  /// Constructs a vorbis comment with no vendor name or comments.
+  #[inline]
+  pub fn new() -> Self {
+    Default::default()
+  }
+
+  /// Sets the vendor name.
+  pub fn with_vendor_string(mut self, vendor_string: &str) -> Self {
+    self.vendor_string = vendor_string.to_owned();
+
+    self
+  }
+
+  /// Adds a comment, replacing any comment that already has the same name.
+  pub fn with_comment(mut self, name: &str, value: &str) -> Self {
+    self.comments.insert(name.to_owned(), value.to_owned());
+
+    self
+  }
+
+  /// Fills in `template` with the comments, for naming a file after its
+  /// tags.
+  ///
+  /// Each `{name}` gets replaced by the comment with that name, ignoring
+  /// case, or by nothing when there isn't one. `{name:0N}` pads a value
+  /// that's a number with zeros up to `N` digits, and `{{` and `}}` stand
+  /// for a literal brace. Within the values, characters that aren't allowed
+  /// in a filename on some platform, path separators included, get
+  /// replaced by `_`, so a tag never moves the file somewhere else. The
+  /// rest of the template is kept as is, which leaves it free to name
+  /// directories.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use flac::metadata::VorbisComment;
+  ///
+  /// let tags = VorbisComment::new().with_comment("TRACKNUMBER", "7")
+  ///                                .with_comment("TITLE", "Either/Or?");
+  ///
+  /// assert_eq!(tags.format_filename("{tracknumber:02} - {title}.wav"),
+  ///            "07 - Either_Or_.wav");
+  /// ```
+  pub fn format_filename(&self, template: &str) -> String {
+    let mut filename = String::with_capacity(template.len());
+    let mut rest     = template;
+
+    while let Some(start) = rest.find(|c| c == '{' || c == '}') {
+      filename.push_str(&rest[..start]);
+
+      let tail = &rest[start..];
+
+      if tail.starts_with("{{") || tail.starts_with("}}") {
+        filename.push_str(&tail[..1]);
+
+        rest = &tail[2..];
+        continue;
+      }
+
+      match tail.find('}') {
+        Some(end) if tail.starts_with('{') => {
+          filename.push_str(&self.filename_field(&tail[1..end]));
+
+          rest = &tail[(end + 1)..];
+        }
+        // A brace that isn't part of a field is kept as is.
+        _                                  => {
+          filename.push_str(&tail[..1]);
+
+          rest = &tail[1..];
+        }
+      }
+    }
+
+    filename.push_str(rest);
+
+    filename
+  }
+
+  // Returns the value of a field within a filename template, as the name of
+  // a comment optionally followed by `:0` and a width.
+  fn filename_field(&self, field: &str) -> String {
+    let mut parts = field.splitn(2, ':');
+    let name      = parts.next().unwrap_or("");
+    let width     = parts.next().and_then(|format| {
+      if format.starts_with('0') { format[1..].parse().ok() } else { None }
+    }).unwrap_or(0);
+
+    let value = self.comments.iter().find(|&(key, _)| {
+      key.eq_ignore_ascii_case(name)
+    }).map_or("", |(_, value)| value.trim());
+
+    let is_number = !value.is_empty() &&
+                    value.bytes().all(|byte| byte.is_ascii_digit());
+    let mut value = value.chars().map(|c| {
+      match c {
+        '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+        _ if c.is_control()                                  => '_',
+        _                                                    => c,
+      }
+    }).collect::<String>();
+
+    // A value of only dots would name the current or parent directory.
+    if !value.is_empty() && value.chars().all(|c| c == '.') {
+      value = value.replace('.', "_");
+    }
+
+    if is_number && value.len() < width {
+      let padding = "0".repeat(width - value.len());
+
+      value.insert_str(0, &padding);
+    }
+
+    value
+  }
+
   pub fn bytes_len(&self) -> usize {
     let vendor_bytes   = self.vendor_string.as_bytes();
     let vendor_length  = vendor_bytes.len();
@@ -417,10 +1112,128 @@ impl VorbisComment {
   }
 }
 
+/// Converts a number of samples, at the given sample rate, into a
+/// `Duration`.
+///
+/// A sample rate of zero always gives a zero length `Duration`.
+pub fn samples_to_duration(samples: u64, sample_rate: u32) -> Duration {
+  if sample_rate == 0 {
+    return Duration::new(0, 0);
+  }
+
+  let sample_rate = sample_rate as u64;
+  let remainder   = samples % sample_rate;
+  let nanoseconds = remainder * NANOS_PER_SECOND / sample_rate;
+
+  Duration::new(samples / sample_rate, nanoseconds as u32)
+}
+
+/// Converts a `Duration` into the number of samples, rounded down, at the
+/// given sample rate.
+///
+/// Durations too long to count in samples give `u64::MAX`.
+pub fn duration_to_samples(duration: Duration, sample_rate: u32) -> u64 {
+  let sample_rate = sample_rate as u64;
+  let nanoseconds = duration.subsec_nanos() as u64;
+
+  duration.as_secs().saturating_mul(sample_rate)
+                    .saturating_add(nanoseconds * sample_rate /
+                                    NANOS_PER_SECOND)
+}
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+// Pads `string` with null characters, or cuts it off without splitting a
+// character, to be `length` bytes long, for the fixed length strings within
+// metadata blocks.
+fn fixed_length(string: &str, length: usize) -> String {
+  let mut result = String::with_capacity(length);
+
+  for character in string.chars() {
+    if result.len() + character.len_utf8() > length {
+      break;
+    }
+
+    result.push(character);
+  }
+
+  while result.len() < length {
+    result.push('\0');
+  }
+
+  result
+}
+
+// Samples per second of Compact Disc Digital Audio.
+#[cfg(feature = "cue-sheet")]
+const CD_SAMPLE_RATE: u32 = 44100;
+
+// A Compact Disc is divided into 75 frames per second, which are each 588
+// samples long.
+#[cfg(feature = "cue-sheet")]
+const CD_FRAMES_PER_SECOND: u64 = 75;
+#[cfg(feature = "cue-sheet")]
+const CD_FRAME_SAMPLES: u64 = 588;
+
+// Track number reserved for the lead-out track of a Compact Disc.
+#[cfg(feature = "cue-sheet")]
+const CD_LEAD_OUT: u8 = 170;
+
+/// A position on a Compact Disc, in minutes, seconds, and frames.
+///
+/// There are 75 frames to each second, which makes each frame 588 samples
+/// long at 44.1kHz.
+#[cfg(feature = "cue-sheet")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Msf {
+  /// Whole minutes.
+  pub minutes: u32,
+  /// Seconds past the minutes, from 0 to 59.
+  pub seconds: u8,
+  /// Frames past the seconds, from 0 to 74.
+  pub frames: u8,
+}
+
+#[cfg(feature = "cue-sheet")]
+impl Msf {
+  /// Converts a sample offset of Compact Disc audio into a position.
+  ///
+  /// Returns `None` when the offset doesn't land on a frame, or the minutes
+  /// don't fit within a `u32`.
+  pub fn from_samples(samples: u64) -> Option<Msf> {
+    if samples % CD_FRAME_SAMPLES != 0 {
+      return None;
+    }
+
+    let frames  = samples / CD_FRAME_SAMPLES;
+    let seconds = frames / CD_FRAMES_PER_SECOND;
+    let minutes = seconds / 60;
+
+    if minutes > u32::MAX as u64 {
+      return None;
+    }
+
+    Some(Msf {
+      minutes: minutes as u32,
+      seconds: (seconds % 60) as u8,
+      frames: (frames % CD_FRAMES_PER_SECOND) as u8,
+    })
+  }
+
+  /// Returns the sample offset of the position.
+  pub fn to_samples(&self) -> u64 {
+    let seconds = self.minutes as u64 * 60 + self.seconds as u64;
+    let frames  = seconds * CD_FRAMES_PER_SECOND + self.frames as u64;
+
+    frames * CD_FRAME_SAMPLES
+  }
+}
+
 /// Stores cue information.
 ///
 /// Generally for storing information from Compact Disk Digital Audio, but
 /// can be used as a cueing mechanism for playback.
+#[cfg(feature = "cue-sheet")]
 #[derive(Debug, PartialEq, Eq)]
 pub struct CueSheet {
   /// Media catalog number.
@@ -433,7 +1246,90 @@ pub struct CueSheet {
   pub tracks: Vec<CueSheetTrack>,
 }
 
+#[cfg(feature = "cue-sheet")]
+impl Default for CueSheet {
+  fn default() -> Self {
+    CueSheet {
+      media_catalog_number: fixed_length("", MEDIA_CATALOG_NUMBER_LENGTH),
+      lead_in: 0,
+      is_cd: false,
+      tracks: Vec::new(),
+    }
+  }
+}
+
+#[cfg(feature = "cue-sheet")]
 impl CueSheet {
+  /// Constructs a cue sheet with a blank media catalog number and no
+  /// tracks, which isn't for a Compact Disc.
+  #[inline]
+  pub fn new() -> Self {
+    Default::default()
+  }
+
+  /// Sets the media catalog number, which gets padded with null
+  /// characters, or cut off, to 128 bytes.
+  pub fn with_media_catalog_number(mut self, number: &str) -> Self {
+    self.media_catalog_number = fixed_length(number,
+                                             MEDIA_CATALOG_NUMBER_LENGTH);
+
+    self
+  }
+
+  /// Sets the number of lead-in samples.
+  pub fn with_lead_in(mut self, lead_in: u64) -> Self {
+    self.lead_in = lead_in;
+
+    self
+  }
+
+  /// Sets whether the cue sheet corresponds to a Compact Disc.
+  pub fn with_cd(mut self, is_cd: bool) -> Self {
+    self.is_cd = is_cd;
+
+    self
+  }
+
+  /// Adds a track after the tracks already within the cue sheet.
+  pub fn with_track(mut self, track: CueSheetTrack) -> Self {
+    self.tracks.push(track);
+
+    self
+  }
+
+  /// Returns true when the cue sheet follows the constraints of Compact
+  /// Disc Digital Audio.
+  ///
+  /// Besides being marked as a Compact Disc, the stream needs to be 16-bit
+  /// stereo at 44.1kHz, the lead-in has to be at least two seconds, and
+  /// every track and index offset needs to land on a frame. Tracks are
+  /// numbered from 1 to 99, followed by the lead-out track numbered 170.
+  pub fn is_valid_cd(&self, stream_info: &StreamInfo) -> bool {
+    let is_cd_stream = stream_info.sample_rate.get() == CD_SAMPLE_RATE &&
+                       stream_info.channels == 2 &&
+                       stream_info.bits_per_sample.get() == 16;
+    let lead_in      = 2 * CD_SAMPLE_RATE as u64;
+
+    if !self.is_cd || !is_cd_stream || self.lead_in < lead_in ||
+       self.tracks.len() > 100 {
+      return false;
+    }
+
+    let (lead_out, tracks) = match self.tracks.split_last() {
+      Some(split) => split,
+      None        => return false,
+    };
+
+    let is_on_frame = |offset: u64| offset % CD_FRAME_SAMPLES == 0;
+
+    lead_out.number == CD_LEAD_OUT && is_on_frame(lead_out.offset) &&
+      tracks.iter().all(|track| {
+        track.number >= 1 && track.number <= 99 &&
+          is_on_frame(track.offset) &&
+          track.indices.iter().all(|index| is_on_frame(index.offset))
+      })
+  }
+
   #[inline]
   pub fn bytes_len(&self) -> usize {
     self.tracks.iter().fold(0, |result, track| {
@@ -469,6 +1365,7 @@ impl CueSheet {
 }
 
 /// Track information inside a cue sheet.
+#[cfg(feature = "cue-sheet")]
 #[derive(Debug, PartialEq, Eq)]
 pub struct CueSheetTrack {
   /// Track offset, in samples, relative to the beginning of the FLAC audio
@@ -486,7 +1383,22 @@ pub struct CueSheetTrack {
   pub indices: Vec<CueSheetTrackIndex>,
 }
 
+#[cfg(feature = "cue-sheet")]
 impl CueSheetTrack {
+  /// Returns the time the track starts at, given the sample rate of the
+  /// stream.
+  #[inline]
+  pub fn time(&self, sample_rate: u32) -> Duration {
+    samples_to_duration(self.offset, sample_rate)
+  }
+
+  /// Returns the Compact Disc position the track starts at, when the offset
+  /// lands on a frame.
+  #[inline]
+  pub fn msf(&self) -> Option<Msf> {
+    Msf::from_samples(self.offset)
+  }
+
   pub fn bytes_len(&self) -> usize {
     let num_indices = self.indices.len();
 
@@ -527,6 +1439,7 @@ impl CueSheetTrack {
 }
 
 /// An index point within a track, inside of a cue sheet.
+#[cfg(feature = "cue-sheet")]
 #[derive(Debug, PartialEq, Eq)]
 pub struct CueSheetTrackIndex {
   /// Offset, in samples, relative to the track offset of the index point.
@@ -535,7 +1448,29 @@ pub struct CueSheetTrackIndex {
   pub number: u8,
 }
 
+#[cfg(feature = "cue-sheet")]
 impl CueSheetTrackIndex {
+  /// Returns the offset, in samples, from the beginning of the stream to
+  /// the index point within the given track.
+  #[inline]
+  pub fn stream_offset(&self, track: &CueSheetTrack) -> u64 {
+    track.offset.saturating_add(self.offset)
+  }
+
+  /// Returns the time of the index point within the given track, counting
+  /// from the beginning of the stream.
+  #[inline]
+  pub fn time(&self, track: &CueSheetTrack, sample_rate: u32) -> Duration {
+    samples_to_duration(self.stream_offset(track), sample_rate)
+  }
+
+  /// Returns the Compact Disc position of the index point within the given
+  /// track, when it lands on a frame.
+  #[inline]
+  pub fn msf(&self, track: &CueSheetTrack) -> Option<Msf> {
+    Msf::from_samples(self.stream_offset(track))
+  }
+
   #[inline]
   pub fn bytes_len(&self) -> usize {
     12
@@ -556,6 +1491,7 @@ impl CueSheetTrackIndex {
 /// More than likely these pictures will be cover art, but you can have more
 /// than one within a file, which are distinguished by `PictureType`and it's
 /// mime type string.
+#[cfg(feature = "picture")]
 #[derive(Debug, PartialEq, Eq)]
 pub struct Picture {
   /// Picture type, based on the `ID3v2` APIC frame.
@@ -573,10 +1509,89 @@ pub struct Picture {
   /// Number of colors used.
   pub colors: u32,
   /// Binary picture data.
-  pub data: Vec<u8>,
+  pub data: Payload,
+}
+
+#[cfg(feature = "picture")]
+impl Default for Picture {
+  fn default() -> Self {
+    Picture {
+      picture_type: PictureType::Other,
+      mime_type: String::new(),
+      description: String::new(),
+      width: 0,
+      height: 0,
+      depth: 0,
+      colors: 0,
+      data: Payload::new(),
+    }
+  }
 }
 
+#[cfg(feature = "picture")]
 impl Picture {
+  /// Constructs a picture of the type `PictureType::Other` with no data.
+  #[inline]
+  pub fn new() -> Self {
+    Default::default()
+  }
+
+  /// Sets the picture type.
+  pub fn with_picture_type(mut self, picture_type: PictureType) -> Self {
+    self.picture_type = picture_type;
+
+    self
+  }
+
+  /// Sets the MIME type, like "image/png".
+  pub fn with_mime_type(mut self, mime_type: &str) -> Self {
+    self.mime_type = mime_type.to_owned();
+
+    self
+  }
+
+  /// Sets the string describing the picture.
+  pub fn with_description(mut self, description: &str) -> Self {
+    self.description = description.to_owned();
+
+    self
+  }
+
+  /// Sets the width of the picture in pixels.
+  pub fn with_width(mut self, width: u32) -> Self {
+    self.width = width;
+
+    self
+  }
+
+  /// Sets the height of the picture in pixels.
+  pub fn with_height(mut self, height: u32) -> Self {
+    self.height = height;
+
+    self
+  }
+
+  /// Sets the color depth of the picture in bits-per-pixel.
+  pub fn with_depth(mut self, depth: u32) -> Self {
+    self.depth = depth;
+
+    self
+  }
+
+  /// Sets the number of colors used, for indexed pictures.
+  pub fn with_colors(mut self, colors: u32) -> Self {
+    self.colors = colors;
+
+    self
+  }
+
+  /// Sets the binary picture data.
+  pub fn with_data<D: Into<Payload>>(mut self, data: D) -> Self {
+    self.data = data.into();
+
+    self
+  }
+
   pub fn bytes_len(&self) -> usize {
     let mime_type       = self.mime_type.as_bytes();
     let mime_type_len   = mime_type.len();
@@ -639,6 +1654,7 @@ impl Picture {
 }
 
 /// The picture type according to the `ID3v2` attached picture frame.
+#[cfg(feature = "picture")]
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum PictureType {
   /// Other picture type not categorized in this enum.
@@ -685,6 +1701,19 @@ pub enum PictureType {
   PublisherLogo,
 }
 
+/// Which `Picture` blocks of a file `metadata::set_picture` and
+/// `metadata::remove_picture` work on.
+#[cfg(feature = "picture")]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PictureSelector {
+  /// Picture at the given index, counting only the `Picture` blocks in the
+  /// order they appear within the file.
+  Index(usize),
+  /// Pictures of the given type.
+  Type(PictureType),
+}
+
+#[cfg(feature = "picture")]
 impl fmt::Display for PictureType {
   fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
     write!(formatter, "{}", match *self {
@@ -717,7 +1746,10 @@ impl fmt::Display for PictureType {
 mod tests {
   use super::*;
 
+  #[cfg(feature = "vorbis-comment")]
   use std::collections::HashMap;
+  use std::time::Duration;
+  use std::u64;
 
   #[test]
   fn test_is_varied_block_size() {
@@ -749,6 +1781,40 @@ mod tests {
     assert!(!info.is_fixed_block_size());
   }
 
+  #[test]
+  fn test_block_data_accessors() {
+    let stream_info: StreamInfo = Default::default();
+    let block = Metadata::new(true, 34, Data::StreamInfo(stream_info));
+
+    assert_eq!(block.as_stream_info(), Some(&stream_info));
+    assert!(block.as_seek_table().is_none());
+    assert!(block.as_unknown().is_none());
+
+    let block = match block.into_seek_table() {
+      Ok(_)      => panic!("stream info isn't a seek table"),
+      Err(block) => block,
+    };
+
+    assert_eq!(block.into_stream_info().ok(), Some(stream_info));
+
+    let seek_point = SeekPoint {
+      sample_number: 0,
+      stream_offset: 0,
+      frame_samples: 4096,
+    };
+    let data       = Data::SeekTable(vec![seek_point]);
+    let block      = Metadata::new(true, 18, data);
+    let expected   = SeekPoint {
+      sample_number: 0,
+      stream_offset: 0,
+      frame_samples: 4096,
+    };
+
+    assert_eq!(block.as_seek_table().map(|s| &s[..]), Some(&[expected][..]));
+    assert!(block.as_stream_info().is_none());
+    assert_eq!(block.into_seek_table().ok().map(|s| s.len()), Some(1));
+  }
+
   #[test]
   fn test_stream_info_to_bytes() {
     {
@@ -757,9 +1823,9 @@ mod tests {
         max_block_size: 4608,
         min_frame_size: 14,
         max_frame_size: 16,
-        sample_rate: 8000,
+        sample_rate: SampleRate::new(8000).unwrap(),
         channels: 2,
-        bits_per_sample: 8,
+        bits_per_sample: BitsPerSample::new(8).unwrap(),
         total_samples: 80000,
         md5_sum: [ 0xa0, 0x42, 0x23, 0x7c, 0x54, 0x93, 0xfd, 0xb9, 0x65, 0x6b
                  , 0x94, 0xa8, 0x36, 0x08, 0xd1, 0x1a
@@ -783,9 +1849,9 @@ mod tests {
         max_block_size: 4096,
         min_frame_size: 2731,
         max_frame_size: 21253,
-        sample_rate: 48000,
+        sample_rate: SampleRate::new(48000).unwrap(),
         channels: 2,
-        bits_per_sample: 24,
+        bits_per_sample: BitsPerSample::new(24).unwrap(),
         total_samples: 10194762,
         md5_sum: [ 0xc6, 0x16, 0x1b, 0x2b, 0xb3, 0xf8, 0x1c, 0xa6, 0x72, 0x79
                  , 0x1d, 0x96, 0xf0, 0x9d, 0x0b, 0x0c
@@ -815,12 +1881,109 @@ mod tests {
     assert_eq!(&bytes[..], &result[..]);
   }
 
+  #[test]
+  fn test_block_length_limit() {
+    {
+      let input = Metadata::new(false, 0xffffff, Data::Padding(0xffffff));
+
+      let mut bytes = Vec::with_capacity(input.bytes_len());
+
+      assert!(input.to_bytes(&mut bytes).is_ok());
+      assert_eq!(&bytes[0..4], b"\x01\xff\xff\xff");
+      assert_eq!(bytes.len(), input.bytes_len());
+    }
+
+    {
+      let input = Metadata::new(false, 0, Data::Padding(0x1000000));
+
+      let mut bytes = Vec::new();
+      let result    = input.to_bytes(&mut bytes);
+
+      assert_eq!(result.err().map(|e| e.kind()),
+                 Some(io::ErrorKind::InvalidInput));
+      assert!(bytes.is_empty());
+    }
+  }
+
+  #[cfg(feature = "picture")]
+  #[test]
+  fn test_picture_length_limit() {
+    let data  = Payload::from(vec![0; 0xffffff - 31]);
+    let input = Metadata::new(true, 0, Data::Picture(Picture {
+      picture_type: PictureType::FrontCover,
+      mime_type: "image/png".to_owned(),
+      description: "ümlaut".to_owned(),
+      width: 0,
+      height: 0,
+      depth: 0,
+      colors: 0,
+      data: data,
+    }));
+
+    let mut bytes = Vec::new();
+
+    assert!(input.to_bytes(&mut bytes).is_err());
+    assert!(bytes.is_empty());
+  }
+
+  #[test]
+  fn test_serialize_blocks() {
+    let stream_info = || {
+      let mut info = StreamInfo::default();
+
+      info.channels        = 2;
+      info.bits_per_sample = BitsPerSample::new(16).unwrap();
+
+      Data::StreamInfo(info)
+    };
+
+    {
+      let unknown = Payload::from(vec![1, 2, 3]);
+      let blocks  = [
+        Metadata::new(true, 34, stream_info()),
+        Metadata::new(true, 2, Data::Padding(2)),
+        Metadata::new(false, 3, Data::Unknown(7, unknown)),
+      ];
+
+      let bytes = serialize_blocks(&blocks).unwrap();
+
+      assert_eq!(bytes.len(), 38 + 6 + 7);
+      assert_eq!(bytes[0], 0x00);
+      assert_eq!(&bytes[38..44], b"\x01\0\0\x02\0\0");
+      assert_eq!(&bytes[44..], b"\x87\0\0\x03\x01\x02\x03");
+    }
+
+    {
+      let blocks = [Metadata::new(false, 34, stream_info())];
+
+      let bytes = serialize_blocks(&blocks).unwrap();
+
+      assert_eq!(bytes[0], 0x80);
+    }
+
+    let errors = [
+      vec![],
+      vec![Metadata::new(true, 2, Data::Padding(2))],
+      vec![
+        Metadata::new(false, 34, stream_info()),
+        Metadata::new(true, 34, stream_info()),
+      ],
+    ];
+
+    for blocks in &errors {
+      let result = serialize_blocks(blocks);
+
+      assert_eq!(result.err().map(|e| e.kind()),
+                 Some(io::ErrorKind::InvalidInput));
+    }
+  }
+
   #[test]
   fn test_application_to_bytes() {
     {
       let application = Application {
         id: "fake".to_owned(),
-        data: vec![],
+        data: Payload::new(),
       };
 
       let input  = Metadata::new(true, 4, Data::Application(application));
@@ -835,7 +1998,7 @@ mod tests {
     {
       let application = Application {
         id: "riff".to_owned(),
-        data: b"fake data"[..].to_owned(),
+        data: Payload::from(&b"fake data"[..]),
       };
 
       let input  = Metadata::new(false, 13, Data::Application(application));
@@ -892,6 +2055,7 @@ mod tests {
     assert_eq!(&bytes[..], &result[..]);
   }
 
+  #[cfg(feature = "vorbis-comment")]
   #[test]
   fn test_vorbis_comment_to_bytes() {
     let mut comments = HashMap::with_capacity(6);
@@ -951,6 +2115,126 @@ mod tests {
     assert_eq!(&bytes[..], &result[..]);
   }
 
+  #[cfg(feature = "vorbis-comment")]
+  #[test]
+  fn test_format_filename() {
+    let tags = VorbisComment::new().with_comment("TrackNumber", "3")
+                                   .with_comment("ARTIST", "AC/DC")
+                                   .with_comment("ALBUM", "..")
+                                   .with_comment("TITLE", " What? <Now>\t");
+
+    assert_eq!(tags.format_filename("{tracknumber} - {title}.wav"),
+               "3 - What_ _Now_.wav");
+    assert_eq!(tags.format_filename("{artist}/{album}/{TRACKNUMBER:03}"),
+               "AC_DC/__/003");
+    assert_eq!(tags.format_filename("{{{tracknumber}}} {genre}.flac"),
+               "{3} .flac");
+    assert_eq!(tags.format_filename("{artist:02} {title"), "AC_DC {title");
+    assert_eq!(tags.format_filename("}{}"), "}");
+  }
+
+  #[test]
+  fn test_duration_conversion() {
+    assert_eq!(samples_to_duration(0, 44100), Duration::new(0, 0));
+    assert_eq!(samples_to_duration(66150, 44100),
+               Duration::new(1, 500_000_000));
+    assert_eq!(samples_to_duration(1, 48000), Duration::new(0, 20833));
+    assert_eq!(samples_to_duration(100, 0), Duration::new(0, 0));
+    assert_eq!(samples_to_duration(u64::MAX, 1), Duration::new(u64::MAX, 0));
+    assert_eq!(samples_to_duration(u64::MAX, 655350),
+               Duration::new(u64::MAX / 655350, 900_000_000));
+
+    assert_eq!(duration_to_samples(Duration::new(1, 500_000_000), 44100),
+               66150);
+    assert_eq!(duration_to_samples(Duration::new(0, 20833), 48000), 0);
+    assert_eq!(duration_to_samples(Duration::new(u64::MAX, 0), 44100),
+               u64::MAX);
+    assert_eq!(duration_to_samples(Duration::new(3, 999_999_999), 1), 3);
+    assert_eq!(duration_to_samples(Duration::new(0, 999_999_999), 655350),
+               655349);
+  }
+
+  #[cfg(feature = "cue-sheet")]
+  #[test]
+  fn test_msf() {
+    let msf = Msf { minutes: 2, seconds: 3, frames: 4 };
+
+    assert_eq!(msf.to_samples(), ((2 * 60 + 3) * 75 + 4) * 588);
+    assert_eq!(Msf::from_samples(msf.to_samples()), Some(msf));
+    assert_eq!(Msf::from_samples(588 * 75 * 60 - 588),
+               Some(Msf { minutes: 0, seconds: 59, frames: 74 }));
+    assert_eq!(Msf::from_samples(589), None);
+  }
+
+  #[cfg(feature = "cue-sheet")]
+  #[test]
+  fn test_cue_sheet_positions() {
+    let track = |offset, number, indices| {
+      CueSheetTrack {
+        offset: offset,
+        number: number,
+        isrc: String::new(),
+        is_audio: true,
+        is_pre_emphasis: false,
+        indices: indices,
+      }
+    };
+
+    let mut cue_sheet = CueSheet {
+      media_catalog_number: String::new(),
+      lead_in: 88200,
+      is_cd: true,
+      tracks: vec![
+        track(0, 1, vec![CueSheetTrackIndex { offset: 0, number: 1 }]),
+        track(44100, 2, vec![
+          CueSheetTrackIndex { offset: 0, number: 0 },
+          CueSheetTrackIndex { offset: 1176, number: 1 },
+        ]),
+        track(88200, 170, vec![]),
+      ],
+    };
+
+    let mut info = StreamInfo {
+      sample_rate: SampleRate::new(44100).unwrap(),
+      channels: 2,
+      bits_per_sample: BitsPerSample::new(16).unwrap(),
+      ..Default::default()
+    };
+
+    {
+      let second = &cue_sheet.tracks[1];
+      let index  = &second.indices[1];
+
+      assert_eq!(second.time(44100), Duration::new(1, 0));
+      assert_eq!(second.msf(), Some(Msf { minutes: 0, seconds: 1,
+                                          frames: 0 }));
+      assert_eq!(index.stream_offset(second), 45276);
+      assert_eq!(index.time(second, 44100), Duration::new(1, 26_666_666));
+      assert_eq!(index.msf(second), Some(Msf { minutes: 0, seconds: 1,
+                                               frames: 2 }));
+    }
+
+    assert!(cue_sheet.is_valid_cd(&info));
+
+    info.sample_rate = SampleRate::new(48000).unwrap();
+
+    assert!(!cue_sheet.is_valid_cd(&info));
+
+    info.sample_rate = SampleRate::new(44100).unwrap();
+
+    cue_sheet.tracks[1].indices[1].offset = 1000;
+
+    assert_eq!(cue_sheet.tracks[1].indices[1].msf(&cue_sheet.tracks[1]),
+               None);
+    assert!(!cue_sheet.is_valid_cd(&info));
+
+    cue_sheet.tracks[1].indices[1].offset = 1176;
+    cue_sheet.tracks[2].number            = 3;
+
+    assert!(!cue_sheet.is_valid_cd(&info));
+  }
+
+  #[cfg(feature = "cue-sheet")]
   #[test]
   fn test_cue_sheet_to_bytes() {
     let cue_sheet = CueSheet {
@@ -1033,6 +2317,7 @@ mod tests {
     assert_eq!(&bytes[..], &result[..]);
   }
 
+  #[cfg(feature = "picture")]
   #[test]
   fn test_picture_to_bytes() {
     let picture = Picture {
@@ -1043,7 +2328,7 @@ mod tests {
       height: 0,
       depth: 0,
       colors: 0,
-      data: vec![],
+      data: Payload::new(),
     };
 
     let input  = Metadata::new(false, 41, Data::Picture(picture));
@@ -1056,17 +2341,125 @@ mod tests {
     assert_eq!(&bytes[..], &result[..]);
   }
 
+  #[test]
+  fn test_block_builders() {
+    // Blocks built from the defaults are written at their expected length.
+    fn check_length(data: Data) {
+      let block = Metadata::new(true, 0, data);
+
+      let mut bytes = Vec::new();
+
+      assert!(block.to_bytes(&mut bytes).is_ok());
+      assert_eq!(bytes.len(), block.bytes_len());
+    }
+
+    let application = Application::new().with_id("riff")
+                                         .with_data(&b"fake data"[..]);
+
+    assert_eq!(application, Application {
+      id: "riff".to_owned(),
+      data: Payload::from(&b"fake data"[..]),
+    });
+    assert_eq!(Application::new().with_id("ab").id, "ab\0\0");
+    assert_eq!(Application::new().with_id("abcdef").id, "abcd");
+
+    let seek_point = SeekPoint::new().with_sample_number(4096)
+                                     .with_stream_offset(8192)
+                                     .with_frame_samples(4096);
+
+    assert_eq!(seek_point, SeekPoint {
+      sample_number: 4096,
+      stream_offset: 8192,
+      frame_samples: 4096,
+    });
+
+    check_length(Data::Application(Application::new()));
+    check_length(Data::SeekTable(vec![SeekPoint::new()]));
+
+    #[cfg(feature = "vorbis-comment")]
+    {
+      let vorbis_comment = VorbisComment::new().with_vendor_string("flac")
+                                               .with_comment("TITLE", "1")
+                                               .with_comment("TITLE", "2");
+
+      assert_eq!(vorbis_comment.vendor_string, "flac");
+      assert_eq!(vorbis_comment.comments.get("TITLE"),
+                 Some(&"2".to_owned()));
+
+      check_length(Data::VorbisComment(VorbisComment::new()));
+    }
+
+    #[cfg(feature = "cue-sheet")]
+    {
+      let track = CueSheetTrack {
+        offset: 0,
+        number: 170,
+        isrc: "\0".repeat(12),
+        is_audio: true,
+        is_pre_emphasis: false,
+        indices: Vec::new(),
+      };
+
+      let cue_sheet = CueSheet::new().with_media_catalog_number("1234")
+                                     .with_lead_in(88200)
+                                     .with_cd(true)
+                                     .with_track(track);
+
+      assert_eq!(cue_sheet.media_catalog_number.len(), 128);
+      assert!(cue_sheet.media_catalog_number.starts_with("1234\0"));
+      assert_eq!(cue_sheet.lead_in, 88200);
+      assert!(cue_sheet.is_cd);
+      assert_eq!(cue_sheet.tracks.len(), 1);
+
+      check_length(Data::CueSheet(cue_sheet));
+      check_length(Data::CueSheet(CueSheet::new()));
+    }
+
+    #[cfg(feature = "picture")]
+    {
+      let picture = Picture::new().with_picture_type(PictureType::FrontCover)
+                                  .with_mime_type("image/png")
+                                  .with_description("cover")
+                                  .with_width(600)
+                                  .with_height(600)
+                                  .with_depth(24)
+                                  .with_colors(0)
+                                  .with_data(vec![1, 2, 3]);
+
+      assert_eq!(picture, Picture {
+        picture_type: PictureType::FrontCover,
+        mime_type: "image/png".to_owned(),
+        description: "cover".to_owned(),
+        width: 600,
+        height: 600,
+        depth: 24,
+        colors: 0,
+        data: Payload::from(vec![1, 2, 3]),
+      });
+
+      check_length(Data::Picture(Picture::new()));
+    }
+  }
+
   #[test]
   fn test_unknown_to_bytes() {
-    let unknown = Data::Unknown(b"random data that won't really be parsed \
-                                  anyway."[..].to_owned());
+    let unknown = Data::Unknown(42, Payload::from(
+                    &b"random data that won't really be parsed anyway."[..]));
     let input   = Metadata::new(true, 47, unknown);
-    let result  = b"\x87\0\0\x2frandom data that won't really be parsed \
+    let result  = b"\xaa\0\0\x2frandom data that won't really be parsed \
                     anyway.";
 
     let mut bytes = Vec::with_capacity(input.bytes_len());
 
     assert!(input.to_bytes(&mut bytes).is_ok());
     assert_eq!(&bytes[..], &result[..]);
+
+    let invalid = Metadata::new(true, 0, Data::Unknown(127, Payload::new()));
+
+    bytes.clear();
+
+    assert_eq!(invalid.to_bytes(&mut bytes).unwrap_err().kind(),
+               io::ErrorKind::InvalidInput);
+    assert!(bytes.is_empty());
   }
 }
@@ -0,0 +1,668 @@
+//! Streaming FLAC from a remote resource with HTTP range requests.
+//!
+//! Only available with the `http` feature enabled. There is no HTTP client
+//! bundled with this module, every request goes through a `Transport` so
+//! any client can be plugged in.
+//!
+//! # Examples
+//!
+//! ```
+//! use flac::StreamReader;
+//! use flac::net::{HttpRangeSource, Transport};
+//!
+//! use std::io;
+//!
+//! struct Client;
+//!
+//! impl Transport for Client {
+//!   fn fetch(&mut self, start: u64, end: u64) -> io::Result<Vec<u8>> {
+//!     // Send a request with the header `Range: bytes=<start>-<end - 1>`.
+//!     Err(io::Error::new(io::ErrorKind::NotFound, "not found"))
+//!   }
+//! }
+//!
+//! match HttpRangeSource::new(Client) {
+//!   Ok(source) => {
+//!     let mut stream = StreamReader::<HttpRangeSource<Client>>::new(source)
+//!                        .unwrap();
+//!
+//!     // Play from 30:00, which only fetches bytes from that frame onward.
+//!     let sample_rate = stream.info().sample_rate.get() as u64;
+//!
+//!     stream.seek(30 * 60 * sample_rate).unwrap();
+//!
+//!     for sample in stream.iter::<i16>() {
+//!       // Iterate over each decoded sample
+//!     }
+//!   }
+//!   Err(error) => println!("{:?}", error),
+//! }
+//! ```
+
+use bytes::Cursor;
+use utility::to_u32;
+
+use std::cmp;
+use std::io::{self, Read, Seek, SeekFrom};
+
+// Default number of bytes requested at a time for the audio frames.
+const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+// Sample number of a seek point that only holds space within the table.
+const PLACEHOLDER: u64 = 0xffffffffffffffff;
+
+/// An interface for fetching byte ranges of a remote resource.
+///
+/// Each call maps to one HTTP request with the header
+/// `Range: bytes=<start>-<end - 1>`.
+pub trait Transport {
+  /// Fetches the bytes within the range `start..end` of the resource.
+  ///
+  /// When the range goes past the end of the resource, only the bytes up to
+  /// the end get returned.
+  fn fetch(&mut self, start: u64, end: u64) -> io::Result<Vec<u8>>;
+
+  /// Returns the length, in bytes, of the entire resource.
+  ///
+  /// This is only needed for seeking relative to the end of the resource,
+  /// which is something that decoding never does.
+  fn length(&mut self) -> io::Result<u64> {
+    Err(io::Error::new(io::ErrorKind::Other, "unknown resource length"))
+  }
+}
+
+/// How far ahead of playback `HttpRangeSource` fetches the frames that
+/// seek points lead to.
+///
+/// `Stream::seek` lands on the frame of a seek point whenever the stream
+/// has a seek table, so holding those frames ahead of time makes seeking
+/// forward to one of them not wait on a request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SeekPrefetch {
+  /// Number of seek points past the playhead to fetch ahead, which
+  /// defaults to four.
+  pub seek_points: usize,
+  /// Most bytes held in chunks fetched ahead at once, which defaults to
+  /// four chunks of the default size.
+  ///
+  /// Each seek point takes a whole chunk, so seek points that don't fit
+  /// within what is left of the budget get skipped.
+  pub budget: usize,
+}
+
+impl Default for SeekPrefetch {
+  fn default() -> Self {
+    SeekPrefetch {
+      seek_points: 4,
+      budget: 4 * DEFAULT_CHUNK_SIZE,
+    }
+  }
+}
+
+/// A `Read` and `Seek` source of bytes for a FLAC resource over HTTP.
+///
+/// The "fLaC" header and every metadata block are fetched up front, one
+/// request per block, so exactly those bytes are transferred. Audio frames
+/// are fetched in chunks only when they get read, which means seeking with
+/// `Stream::seek` skips fetching every frame before the seek point.
+///
+/// With `HttpRangeSource::with_seek_prefetch`, the chunks at the upcoming
+/// points of the seek table get fetched along with each chunk playback
+/// needs, trading extra transfer for instant seeks.
+pub struct HttpRangeSource<T: Transport> {
+  transport: T,
+  header: Vec<u8>,
+  chunk: Vec<u8>,
+  chunk_start: u64,
+  chunk_size: usize,
+  position: u64,
+  seek_offsets: Vec<u64>,
+  prefetch: Option<SeekPrefetch>,
+  prefetched: Vec<(u64, Vec<u8>)>,
+}
+
+impl<T> HttpRangeSource<T> where T: Transport {
+  /// Constructs a source that fetches the header and metadata through the
+  /// given transport.
+  ///
+  /// # Failures
+  ///
+  /// * `io::ErrorKind::InvalidData` is returned when the resource doesn't
+  ///   start with the "fLaC" header.
+  /// * `io::ErrorKind::UnexpectedEof` is returned when the resource ends
+  ///   before the last metadata block does.
+  /// * Any error returned from the transport.
+  #[inline]
+  pub fn new(transport: T) -> io::Result<Self> {
+    Self::with_chunk_size(transport, DEFAULT_CHUNK_SIZE)
+  }
+
+  /// Constructs a source that fetches audio frames `chunk_size` bytes at a
+  /// time.
+  ///
+  /// Fails in the same way as `HttpRangeSource::new` does.
+  pub fn with_chunk_size(mut transport: T, chunk_size: usize)
+                         -> io::Result<Self> {
+    let header       = try!(fetch_header(&mut transport));
+    let seek_offsets = seek_offsets(&header);
+
+    Ok(HttpRangeSource {
+      transport: transport,
+      header: header,
+      chunk: Vec::new(),
+      chunk_start: 0,
+      chunk_size: cmp::max(1, chunk_size),
+      position: 0,
+      seek_offsets: seek_offsets,
+      prefetch: None,
+      prefetched: Vec::new(),
+    })
+  }
+
+  /// Fetches the chunks at upcoming seek points ahead of playback, going by
+  /// `prefetch`.
+  ///
+  /// Nothing gets fetched ahead for a stream without a seek table.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use flac::net::{HttpRangeSource, SeekPrefetch, Transport};
+  ///
+  /// use std::io;
+  ///
+  /// struct Client;
+  ///
+  /// impl Transport for Client {
+  ///   fn fetch(&mut self, start: u64, end: u64) -> io::Result<Vec<u8>> {
+  ///     Err(io::Error::new(io::ErrorKind::NotFound, "not found"))
+  ///   }
+  /// }
+  ///
+  /// let prefetch = SeekPrefetch {
+  ///   seek_points: 8,
+  ///   ..Default::default()
+  /// };
+  ///
+  /// let source = HttpRangeSource::new(Client)
+  ///   .map(|source| source.with_seek_prefetch(prefetch));
+  ///
+  /// assert!(source.is_err());
+  /// ```
+  pub fn with_seek_prefetch(mut self, prefetch: SeekPrefetch) -> Self {
+    self.prefetch = Some(prefetch);
+
+    self
+  }
+
+  /// Returns the bytes of the "fLaC" header and every metadata block.
+  #[inline]
+  pub fn header(&self) -> &[u8] {
+    &self.header
+  }
+
+  /// Returns the offset, in bytes, of the first audio frame.
+  #[inline]
+  pub fn audio_offset(&self) -> u64 {
+    self.header.len() as u64
+  }
+
+  /// Returns the number of bytes held in chunks fetched ahead of playback.
+  pub fn prefetched(&self) -> usize {
+    self.prefetched.iter().fold(0, |total, &(_, ref bytes)| {
+      total + bytes.len()
+    })
+  }
+
+  /// Returns the underlying transport.
+  #[inline]
+  pub fn into_inner(self) -> T {
+    self.transport
+  }
+
+  // Makes the chunk that holds the current position the one being read,
+  // taking it from the chunks fetched ahead when one of them holds it.
+  fn load_chunk(&mut self) -> io::Result<()> {
+    let position = self.position;
+    let index    = self.prefetched.iter().position(|&(start, ref bytes)| {
+      start <= position && position < start + bytes.len() as u64
+    });
+
+    if let Some(index) = index {
+      let (start, bytes) = self.prefetched.swap_remove(index);
+
+      self.chunk       = bytes;
+      self.chunk_start = start;
+    } else {
+      let end = position + self.chunk_size as u64;
+
+      self.chunk       = try!(self.transport.fetch(position, end));
+      self.chunk_start = position;
+    }
+
+    self.prefetch_ahead();
+
+    Ok(())
+  }
+
+  // Fetches the chunks at the seek points following the current chunk,
+  // dropping held chunks that aren't at one of those anymore.
+  //
+  // Fetching ahead is only a hint, so a failed request stops it for now
+  // rather than failing the read that triggered it.
+  fn prefetch_ahead(&mut self) {
+    let prefetch = match self.prefetch {
+      Some(prefetch) => prefetch,
+      None           => return,
+    };
+
+    let chunk_end = self.chunk_start + self.chunk.len() as u64;
+    let upcoming  = self.seek_offsets.iter()
+                        .cloned()
+                        .filter(|&offset| offset >= chunk_end)
+                        .take(prefetch.seek_points)
+                        .collect::<Vec<_>>();
+
+    self.prefetched.retain(|&(start, _)| upcoming.contains(&start));
+
+    let mut held = self.prefetched();
+
+    for offset in upcoming {
+      let is_held = self.prefetched.iter().any(|&(start, ref bytes)| {
+        start <= offset && offset < start + bytes.len() as u64
+      });
+
+      if is_held {
+        continue;
+      }
+
+      if held + self.chunk_size > prefetch.budget {
+        break;
+      }
+
+      let end = offset + self.chunk_size as u64;
+
+      match self.transport.fetch(offset, end) {
+        Ok(ref bytes) if bytes.is_empty() => break,
+        Ok(bytes)                         => {
+          held += bytes.len();
+
+          self.prefetched.push((offset, bytes));
+        }
+        Err(_)                            => break,
+      }
+    }
+  }
+}
+
+// Byte offsets of the frames that the seek points within the header lead
+// to, in order and without placeholders.
+fn seek_offsets(header: &[u8]) -> Vec<u64> {
+  let audio_offset = header.len() as u64;
+  let mut offsets  = Vec::new();
+  let mut offset   = 4;
+
+  while offset + 4 <= header.len() {
+    let block_type = header[offset] & 0b01111111;
+    let length     = to_u32(&header[(offset + 1)..(offset + 4)]) as usize;
+    let start      = offset + 4;
+    let end        = cmp::min(start + length, header.len());
+
+    if block_type == 3 {
+      let mut cursor = Cursor::new(&header[start..end]);
+
+      while let (Ok(sample_number), Ok(stream_offset), Ok(_)) =
+                (cursor.read_be_u64(), cursor.read_be_u64(),
+                 cursor.read_be_u16()) {
+        if sample_number != PLACEHOLDER {
+          offsets.push(audio_offset + stream_offset);
+        }
+      }
+    }
+
+    offset = end;
+  }
+
+  offsets.sort();
+  offsets.dedup();
+
+  offsets
+}
+
+// Fetches exactly `length` bytes starting at `start`.
+fn fetch_exact<T>(transport: &mut T, start: u64, length: u64)
+                  -> io::Result<Vec<u8>>
+ where T: Transport {
+  let bytes = try!(transport.fetch(start, start + length));
+
+  if (bytes.len() as u64) < length {
+    Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                       "resource ended within the metadata"))
+  } else {
+    Ok(bytes)
+  }
+}
+
+// Fetches the "fLaC" header along with every metadata block.
+//
+// Each request grabs a metadata block along with the header of the block
+// after it, so the length of the next request is always known.
+fn fetch_header<T: Transport>(transport: &mut T) -> io::Result<Vec<u8>> {
+  let mut bytes  = try!(fetch_exact(transport, 0, 8));
+  let mut offset = 4;
+
+  if &bytes[0..4] != b"fLaC" {
+    return Err(io::Error::new(io::ErrorKind::InvalidData,
+                              "missing the \"fLaC\" header"));
+  }
+
+  loop {
+    let is_last     = (bytes[offset] >> 7) == 1;
+    let length      = to_u32(&bytes[(offset + 1)..(offset + 4)]) as u64;
+    let next_header = if is_last { 0 } else { 4 };
+    let start       = bytes.len() as u64;
+
+    let block = try!(fetch_exact(transport, start, length + next_header));
+
+    bytes.extend_from_slice(&block);
+
+    if is_last {
+      break;
+    }
+
+    offset = bytes.len() - 4;
+  }
+
+  Ok(bytes)
+}
+
+impl<T> Read for HttpRangeSource<T> where T: Transport {
+  fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+    let header_len = self.header.len() as u64;
+
+    if self.position < header_len {
+      let start  = self.position as usize;
+      let length = cmp::min(buffer.len(), self.header.len() - start);
+
+      buffer[0..length].copy_from_slice(&self.header[start..(start + length)]);
+
+      self.position += length as u64;
+
+      return Ok(length);
+    }
+
+    let chunk_end = self.chunk_start + self.chunk.len() as u64;
+
+    if self.position < self.chunk_start || self.position >= chunk_end {
+      try!(self.load_chunk());
+    }
+
+    let start  = (self.position - self.chunk_start) as usize;
+    let length = cmp::min(buffer.len(), self.chunk.len() - start);
+
+    buffer[0..length].copy_from_slice(&self.chunk[start..(start + length)]);
+
+    self.position += length as u64;
+
+    Ok(length)
+  }
+}
+
+impl<T> Seek for HttpRangeSource<T> where T: Transport {
+  fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+    let offset = match position {
+      SeekFrom::Start(offset)   => Some(offset),
+      SeekFrom::Current(offset) => move_by(self.position, offset),
+      SeekFrom::End(offset)     => {
+        move_by(try!(self.transport.length()), offset)
+      }
+    };
+
+    match offset {
+      Some(offset) => {
+        self.position = offset;
+
+        Ok(offset)
+      }
+      None         => Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                         "seek to an invalid position")),
+    }
+  }
+}
+
+// Moves `position` by a signed amount without going through `i64`, which
+// would wrap for positions past `i64::MAX`. `None` is returned when the
+// result would be negative or overflow.
+fn move_by(position: u64, offset: i64) -> Option<u64> {
+  if offset < 0 {
+    position.checked_sub(offset.wrapping_neg() as u64)
+  } else {
+    position.checked_add(offset as u64)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use encoder::{Encoder, EncoderOptions, SeekPointInterval};
+  use metadata::StreamInfo;
+  use stream::StreamReader;
+  use utility::{BitsPerSample, SampleRate};
+
+  use std::io::{self, Read, Seek, SeekFrom};
+  use std::fs::File;
+
+  struct Memory {
+    bytes: Vec<u8>,
+    requests: Vec<(u64, u64)>,
+  }
+
+  impl Memory {
+    fn new(filename: &str) -> Self {
+      let mut file  = File::open(filename).unwrap();
+      let mut bytes = Vec::new();
+
+      file.read_to_end(&mut bytes).unwrap();
+
+      Memory::from_bytes(bytes)
+    }
+
+    fn from_bytes(bytes: Vec<u8>) -> Self {
+      Memory {
+        bytes: bytes,
+        requests: Vec::new(),
+      }
+    }
+
+    fn transferred(&self) -> u64 {
+      self.requests.iter().fold(0, |total, &(start, end)| {
+        let end = cmp::min(end, self.bytes.len() as u64);
+
+        total + end.saturating_sub(start)
+      })
+    }
+  }
+
+  impl Transport for Memory {
+    fn fetch(&mut self, start: u64, end: u64) -> io::Result<Vec<u8>> {
+      let length = self.bytes.len();
+      let start  = cmp::min(start as usize, length);
+      let end    = cmp::min(end as usize, length);
+
+      self.requests.push((start as u64, end as u64));
+
+      Ok(self.bytes[start..end].to_vec())
+    }
+
+    fn length(&mut self) -> io::Result<u64> {
+      Ok(self.bytes.len() as u64)
+    }
+  }
+
+  #[test]
+  fn test_fetch_header() {
+    let transport = Memory::new("tests/assets/input-pictures.flac");
+    let bytes     = transport.bytes.clone();
+    let source    = HttpRangeSource::new(transport).unwrap();
+    let offset    = source.audio_offset();
+
+    assert_eq!(source.header(), &bytes[0..(offset as usize)]);
+
+    let transport = source.into_inner();
+
+    assert_eq!(transport.transferred(), offset);
+  }
+
+  #[test]
+  fn test_invalid_header() {
+    let mut transport = Memory::new("tests/assets/input-pictures.flac");
+
+    transport.bytes[0] = b'F';
+
+    let result = HttpRangeSource::new(transport);
+
+    assert_eq!(result.err().map(|e| e.kind()),
+               Some(io::ErrorKind::InvalidData));
+  }
+
+  #[test]
+  fn test_read_and_seek() {
+    let transport  = Memory::new("tests/assets/input-SCPAP.flac");
+    let bytes      = transport.bytes.clone();
+    let mut source = HttpRangeSource::with_chunk_size(transport, 100)
+                       .unwrap();
+    let mut buffer = Vec::new();
+
+    source.read_to_end(&mut buffer).unwrap();
+
+    assert_eq!(buffer, bytes);
+
+    let mut buffer = [0; 10];
+
+    assert_eq!(source.seek(SeekFrom::End(-10)).unwrap(),
+               (bytes.len() - 10) as u64);
+    source.read_exact(&mut buffer).unwrap();
+    assert_eq!(&buffer[..], &bytes[(bytes.len() - 10)..]);
+
+    assert!(source.seek(SeekFrom::Current(-(bytes.len() as i64) - 1))
+                  .is_err());
+
+    let far = 0x8000000000000000;
+
+    assert_eq!(source.seek(SeekFrom::Start(far)).unwrap(), far);
+    assert_eq!(source.seek(SeekFrom::Current(-1)).unwrap(), far - 1);
+    assert!(source.seek(SeekFrom::Start(u64::max_value())).is_ok());
+    assert!(source.seek(SeekFrom::Current(1)).is_err());
+  }
+
+  #[test]
+  fn test_decode() {
+    let filename = "tests/assets/input-SCPAP.flac";
+    let source   = HttpRangeSource::new(Memory::new(filename)).unwrap();
+
+    let mut remote = StreamReader::<HttpRangeSource<Memory>>::new(source)
+                       .unwrap();
+    let mut local  = StreamReader::<File>::from_file(filename).unwrap();
+
+    assert_eq!(remote.audio_offset(), local.audio_offset());
+    assert!(remote.iter::<i16>().eq(local.iter::<i16>()));
+  }
+
+  // Stream with a seek point every 4096 samples.
+  fn encode_seek_table() -> Vec<u8> {
+    let mut info = StreamInfo::default();
+
+    info.sample_rate     = SampleRate::new(44100).unwrap();
+    info.channels        = 1;
+    info.bits_per_sample = BitsPerSample::new(16).unwrap();
+    info.total_samples   = 32768;
+
+    let options = EncoderOptions {
+      block_size: 1024,
+      seek_point_interval: Some(SeekPointInterval::Samples(4096)),
+      ..Default::default()
+    };
+
+    let samples     = (0..32768).map(|i| (i * 37 % 2000) - 1000)
+                                .collect::<Vec<_>>();
+    let mut encoder = Encoder::new(io::Cursor::new(Vec::new()), info,
+                                   options).unwrap();
+
+    encoder.write(&samples).unwrap();
+
+    encoder.finish().unwrap().into_inner()
+  }
+
+  #[test]
+  fn test_seek_prefetch() {
+    let bytes    = encode_seek_table();
+    let prefetch = SeekPrefetch {
+      seek_points: 2,
+      budget: 512,
+    };
+
+    let mut source = HttpRangeSource::with_chunk_size(
+                       Memory::from_bytes(bytes.clone()), 256).unwrap()
+                       .with_seek_prefetch(prefetch);
+    let offsets    = source.seek_offsets.clone();
+    let mut buffer = [0; 16];
+
+    assert_eq!(offsets.len(), 8);
+    assert_eq!(offsets[0], source.audio_offset());
+
+    source.seek(SeekFrom::Start(offsets[0])).unwrap();
+    source.read_exact(&mut buffer).unwrap();
+
+    assert_eq!(source.prefetched(), 512);
+
+    // Landing on a seek point reads from the chunk fetched ahead.
+    let start = offsets[2] as usize;
+
+    source.seek(SeekFrom::Start(offsets[2])).unwrap();
+    source.read_exact(&mut buffer).unwrap();
+
+    assert_eq!(&buffer[..], &bytes[start..(start + 16)]);
+    assert_eq!(source.transport.requests.iter()
+                     .filter(|&&(start, _)| start == offsets[2])
+                     .count(), 1);
+
+    // Moving on fetches the seek points past the new playhead instead.
+    assert_eq!(source.prefetched(), 512);
+    assert!(source.prefetched.iter().all(|&(start, _)| start > offsets[2]));
+
+    // Nothing fits within a budget smaller than a chunk.
+    let prefetch = SeekPrefetch {
+      seek_points: 2,
+      budget: 255,
+    };
+
+    let mut source = HttpRangeSource::with_chunk_size(
+                       Memory::from_bytes(bytes.clone()), 256).unwrap()
+                       .with_seek_prefetch(prefetch);
+
+    source.seek(SeekFrom::Start(offsets[0])).unwrap();
+    source.read_exact(&mut buffer).unwrap();
+
+    assert_eq!(source.prefetched(), 0);
+  }
+
+  #[test]
+  fn test_seek_prefetch_decode() {
+    let bytes  = encode_seek_table();
+    let source = HttpRangeSource::with_chunk_size(
+                   Memory::from_bytes(bytes.clone()), 300).unwrap()
+                   .with_seek_prefetch(Default::default());
+
+    let mut remote = StreamReader::<HttpRangeSource<Memory>>::new(source)
+                       .unwrap();
+    let mut local  = StreamReader::<io::Cursor<Vec<u8>>>::new(
+                       io::Cursor::new(bytes)).unwrap();
+
+    assert!(remote.iter::<i16>().eq(local.iter::<i16>()));
+
+    for &sample_number in &[20000, 4096, 12288, 100] {
+      assert_eq!(remote.seek(sample_number), local.seek(sample_number));
+      assert!(remote.iter::<i16>().eq(local.iter::<i16>()));
+    }
+  }
+}
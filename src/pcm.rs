@@ -0,0 +1,453 @@
+//! Raw PCM bytes from decoded samples.
+//!
+//! Audio hardware and file formats disagree on how samples get laid out as
+//! bytes, so `PcmFormat` picks the byte order and whether samples are
+//! signed or unsigned. Each sample takes up the fewest whole bytes that fit
+//! the bits per sample of the stream, the same layout the MD5 signature
+//! within `StreamInfo` is calculated from. For devices that only play
+//! certain formats, `OutputPlan` works out how to get the audio into one
+//! of them.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use flac::StreamReader;
+//! use flac::pcm::{Endian, PcmFormat, PcmReader};
+//!
+//! use std::fs::File;
+//! use std::io;
+//!
+//! let mut stream = StreamReader::<File>::from_file("path/to/file.flac")
+//!                    .unwrap();
+//!
+//! let format = PcmFormat {
+//!   endian: Endian::Big,
+//!   is_signed: true,
+//! };
+//!
+//! let mut reader = PcmReader::new(&mut stream, format);
+//! let mut output = File::create("path/to/file.raw").unwrap();
+//!
+//! io::copy(&mut reader, &mut output).unwrap();
+//! ```
+
+use metadata::StreamInfo;
+use sink::DecimateSink;
+use stream::{Blocks, Stream};
+use utility::{ErrorKind, StreamProducer};
+
+use std::{cmp, io};
+
+/// Order of the bytes within each sample.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Endian {
+  /// Least significant byte first.
+  Little,
+  /// Most significant byte first.
+  Big,
+}
+
+/// Layout of the bytes of each sample.
+///
+/// The default is signed and little endian, which is what WAVE uses for
+/// everything other than 8-bit samples.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PcmFormat {
+  /// Order of the bytes within each sample.
+  pub endian: Endian,
+  /// Whether samples are signed, otherwise they're offset to be unsigned,
+  /// like 8-bit WAVE where silence is 128.
+  pub is_signed: bool,
+}
+
+impl Default for PcmFormat {
+  fn default() -> Self {
+    PcmFormat {
+      endian: Endian::Little,
+      is_signed: true,
+    }
+  }
+}
+
+impl PcmFormat {
+  /// Writes `sample` into `output`, taking up all of its bytes.
+  ///
+  /// Unsigned samples get offset by half the range of `output`, so a sample
+  /// of zero becomes 128 for a single byte and 32768 for two bytes.
+  pub fn pack(&self, sample: i64, output: &mut [u8]) {
+    let width  = output.len();
+    let sample = if self.is_signed || width == 0 {
+      sample
+    } else {
+      sample.wrapping_add(1 << (width * 8 - 1))
+    };
+
+    for (i, byte) in output.iter_mut().enumerate() {
+      let shift = match self.endian {
+        Endian::Little => i,
+        Endian::Big    => width - 1 - i,
+      };
+
+      *byte = (sample >> (shift * 8)) as u8;
+    }
+  }
+}
+
+/// Returns the number of bytes each sample takes up with the given bits
+/// per sample.
+#[inline]
+pub fn sample_width(bits_per_sample: u8) -> usize {
+  (bits_per_sample as usize + 7) / 8
+}
+
+/// Sample formats stored as a packed array of bytes rather than an integer,
+/// like the ones pro-audio interchange formats use.
+///
+/// Samples go through `Stream::iter_packed` straight into their packed
+/// form. Their value is kept as is, so a 16-bit sample packed as 24-bit is
+/// still within the range of 16 bits.
+pub trait PackedSample: Sized {
+  /// The number of bits within the packed bytes that hold the sample.
+  fn bits() -> usize;
+
+  /// Packs `sample`, returning `None` when it doesn't fit within
+  /// `PackedSample::bits`.
+  fn pack(sample: i64) -> Option<Self>;
+
+  /// Unpacks the sample back into an i32.
+  fn unpack(&self) -> i32;
+}
+
+/// A 24-bit sample packed into three little endian bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Packed24(pub [u8; 3]);
+
+/// A 20-bit sample right justified within three little endian bytes, with
+/// the top four bits extending the sign.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Packed20(pub [u8; 3]);
+
+macro_rules! packed (
+  ($packed: ident, $bits: expr) => (
+    impl PackedSample for $packed {
+      #[inline]
+      fn bits() -> usize { $bits }
+
+      #[inline]
+      fn pack(sample: i64) -> Option<Self> {
+        let max = (1 << ($bits - 1)) - 1;
+        let min = -max - 1;
+
+        if sample < min || sample > max {
+          None
+        } else {
+          Some($packed([sample as u8, (sample >> 8) as u8,
+                        (sample >> 16) as u8]))
+        }
+      }
+
+      #[inline]
+      fn unpack(&self) -> i32 {
+        let bytes = self.0;
+        let value = (bytes[0] as i32) | ((bytes[1] as i32) << 8) |
+                    ((bytes[2] as i32) << 16);
+
+        (value << (32 - $bits)) >> (32 - $bits)
+      }
+    }
+  )
+);
+
+packed!(Packed24, 24);
+packed!(Packed20, 20);
+
+/// Sample format, sample rate, and channel count an audio device takes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OutputFormat {
+  /// Bits per sample the device plays.
+  pub bits_per_sample: u8,
+  /// Samples per second the device plays.
+  pub sample_rate: u32,
+  /// Number of channels the device plays.
+  pub channels: u8,
+}
+
+/// Conversions that turn decoded audio into one of the formats a device
+/// supports, picked by `Stream::negotiate_output`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OutputPlan {
+  /// Format, out of the supported ones, the audio gets converted to.
+  pub format: OutputFormat,
+  /// Bits each sample gets shifted left by, or right by when negative, to
+  /// reach the bits per sample of the device.
+  pub shift: i32,
+  /// Number of samples, per channel, behind each one the device plays,
+  /// which is one when the sample rates match.
+  pub decimation: usize,
+  /// Whether every channel gets mixed down to one.
+  pub is_downmix: bool,
+}
+
+impl OutputPlan {
+  /// Picks the plan turning audio described by `info` into one of the
+  /// `supported` formats with the least loss.
+  ///
+  /// Matching the sample rate matters most, followed by keeping every
+  /// channel and then every bit of each sample. Formats only reachable
+  /// through a resampler, or with more channels than the stream, are left
+  /// out. Earlier formats win ties, so devices can list their preferred
+  /// format first. `None` is returned when none of the formats work.
+  pub fn negotiate(info: &StreamInfo, supported: &[OutputFormat])
+                   -> Option<OutputPlan> {
+    let bits_per_sample = info.bits_per_sample.get() as i32;
+
+    let plans = supported.iter().filter_map(|&format| {
+      let sample_rate = format.sample_rate;
+
+      if sample_rate == 0 || info.sample_rate.get() % sample_rate != 0 ||
+         format.bits_per_sample == 0 {
+        return None;
+      }
+
+      let is_downmix = if format.channels == info.channels {
+        false
+      } else if format.channels == 1 {
+        true
+      } else {
+        return None;
+      };
+
+      Some(OutputPlan {
+        format: format,
+        shift: format.bits_per_sample as i32 - bits_per_sample,
+        decimation: (info.sample_rate.get() / sample_rate) as usize,
+        is_downmix: is_downmix,
+      })
+    });
+
+    plans.min_by_key(|plan| {
+      let lost_bits  = cmp::max(-plan.shift, 0);
+      let added_bits = cmp::max(plan.shift, 0);
+
+      (plan.decimation, plan.is_downmix, lost_bits, added_bits)
+    })
+  }
+
+  /// Returns true when the audio goes to the device as it was decoded.
+  #[inline]
+  pub fn is_passthrough(&self) -> bool {
+    self.shift == 0 && self.decimation == 1 && !self.is_downmix
+  }
+
+  /// Converts a decoded sample to the bits per sample of the device.
+  ///
+  /// Dropping bits truncates the sample, without any dither.
+  #[inline]
+  pub fn convert(&self, sample: i64) -> i64 {
+    if self.shift >= 0 {
+      sample << self.shift
+    } else {
+      sample >> -self.shift
+    }
+  }
+
+  /// Wraps `sink` with the decimation and downmix of the plan, writing
+  /// samples that are still at the bits per sample of the stream.
+  ///
+  /// Decimation averages each group of samples, to cut down on aliasing.
+  pub fn decimate<K>(&self, sink: K) -> DecimateSink<K> {
+    DecimateSink::new(sink, self.decimation)
+      .with_low_pass(self.decimation > 1)
+      .with_mono(self.is_downmix)
+  }
+}
+
+/// Reader of raw PCM bytes, with samples interleaved by channel, decoded
+/// from a stream a frame at a time.
+///
+/// A frame that fails to decode comes back as an error with the kind
+/// `io::ErrorKind::InvalidData`, once the bytes before it have been read.
+pub struct PcmReader<'a, P>
+ where P: 'a + StreamProducer {
+  blocks: Blocks<'a, P, i64>,
+  format: PcmFormat,
+  width: usize,
+  bytes: Vec<u8>,
+  position: usize,
+}
+
+impl<'a, P> PcmReader<'a, P> where P: StreamProducer {
+  /// Constructs a reader of the audio within `stream`, laid out in the
+  /// given format.
+  pub fn new(stream: &'a mut Stream<P>, format: PcmFormat) -> Self {
+    let width = sample_width(stream.info().bits_per_sample.get());
+
+    PcmReader {
+      blocks: stream.blocks::<i32>(),
+      format: format,
+      width: width,
+      bytes: Vec::new(),
+      position: 0,
+    }
+  }
+
+  // Packs the next block into the byte buffer, returning false at the end
+  // of the stream.
+  fn fill(&mut self) -> io::Result<bool> {
+    let block = match self.blocks.next() {
+      Some(Ok(block)) => block,
+      Some(Err(kind)) => return Err(invalid_data(kind)),
+      None            => {
+        return match self.blocks.decode_progress().error {
+          Some(kind) => Err(invalid_data(kind)),
+          None       => Ok(false),
+        };
+      }
+    };
+
+    let width = self.width;
+
+    self.bytes.resize(block.samples.len() * width, 0);
+    self.position = 0;
+
+    for (&sample, bytes) in block.samples.iter()
+                                 .zip(self.bytes.chunks_mut(width)) {
+      self.format.pack(sample as i64, bytes);
+    }
+
+    Ok(true)
+  }
+}
+
+impl<'a, P> io::Read for PcmReader<'a, P> where P: StreamProducer {
+  fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+    while self.position == self.bytes.len() {
+      if buffer.is_empty() || !try!(self.fill()) {
+        return Ok(0);
+      }
+    }
+
+    let bytes  = &self.bytes[self.position..];
+    let length = cmp::min(bytes.len(), buffer.len());
+
+    buffer[0..length].copy_from_slice(&bytes[0..length]);
+
+    self.position += length;
+
+    Ok(length)
+  }
+}
+
+fn invalid_data(kind: ErrorKind) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", kind))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use utility::{BitsPerSample, SampleRate};
+
+  fn pack(format: PcmFormat, sample: i64, width: usize) -> Vec<u8> {
+    let mut bytes = vec![0; width];
+
+    format.pack(sample, &mut bytes);
+
+    bytes
+  }
+
+  #[test]
+  fn test_pack() {
+    let little          = PcmFormat::default();
+    let big             = PcmFormat { endian: Endian::Big, ..little };
+    let unsigned_little = PcmFormat { is_signed: false, ..little };
+    let unsigned_big    = PcmFormat { is_signed: false, ..big };
+
+    assert_eq!(pack(little, -2, 2), vec![0xfe, 0xff]);
+    assert_eq!(pack(big, -2, 2), vec![0xff, 0xfe]);
+    assert_eq!(pack(little, 0x123456, 3), vec![0x56, 0x34, 0x12]);
+    assert_eq!(pack(big, 0x123456, 3), vec![0x12, 0x34, 0x56]);
+    assert_eq!(pack(unsigned_little, 0, 1), vec![0x80]);
+    assert_eq!(pack(unsigned_little, -128, 1), vec![0x00]);
+    assert_eq!(pack(unsigned_little, 127, 1), vec![0xff]);
+    assert_eq!(pack(unsigned_little, -1, 2), vec![0xff, 0x7f]);
+    assert_eq!(pack(unsigned_big, -1, 2), vec![0x7f, 0xff]);
+  }
+
+  #[test]
+  fn test_packed() {
+    assert_eq!(Packed24::pack(0x123456), Some(Packed24([0x56, 0x34, 0x12])));
+    assert_eq!(Packed24::pack(-1), Some(Packed24([0xff, 0xff, 0xff])));
+    assert_eq!(Packed24::pack(-0x800000).map(|p| p.unpack()), Some(-0x800000));
+    assert_eq!(Packed24::pack(0x800000), None);
+    assert_eq!(Packed20::pack(0x7ffff), Some(Packed20([0xff, 0xff, 0x07])));
+    assert_eq!(Packed20::pack(-0x80000), Some(Packed20([0x00, 0x00, 0xf8])));
+    assert_eq!(Packed20::pack(0x80000), None);
+    assert_eq!(Packed20::pack(-0x80001), None);
+
+    for &sample in &[0, 1, -1, 0x7ffff, -0x80000, 12345, -54321] {
+      assert_eq!(Packed20::pack(sample).map(|p| p.unpack() as i64),
+                 Some(sample));
+      assert_eq!(Packed24::pack(sample).map(|p| p.unpack() as i64),
+                 Some(sample));
+    }
+
+    // Only the low 20 bits count, whatever the top four bits hold.
+    assert_eq!(Packed20([0x00, 0x00, 0x08]).unpack(), -0x80000);
+  }
+
+  #[test]
+  fn test_negotiate_output() {
+    let mut info = StreamInfo::default();
+
+    info.sample_rate     = SampleRate::new(88200).unwrap();
+    info.channels        = 2;
+    info.bits_per_sample = BitsPerSample::new(24).unwrap();
+
+    let format = |bits_per_sample, sample_rate, channels| {
+      OutputFormat {
+        bits_per_sample: bits_per_sample,
+        sample_rate: sample_rate,
+        channels: channels,
+      }
+    };
+
+    let negotiate = |supported: &[OutputFormat]| {
+      OutputPlan::negotiate(&info, supported).map(|plan| {
+        (plan.format, plan.shift, plan.decimation, plan.is_downmix)
+      })
+    };
+
+    let exact    = format(24, 88200, 2);
+    let wider    = format(32, 88200, 2);
+    let lower    = format(16, 88200, 2);
+    let mono     = format(24, 88200, 1);
+    let halved   = format(24, 44100, 2);
+    let resample = format(24, 48000, 2);
+
+    assert_eq!(negotiate(&[lower, wider, exact]), Some((exact, 0, 1, false)));
+    assert_eq!(negotiate(&[lower, wider]), Some((wider, 8, 1, false)));
+    assert_eq!(negotiate(&[mono, lower]), Some((lower, -8, 1, false)));
+    assert_eq!(negotiate(&[halved, mono]), Some((mono, 0, 1, true)));
+    assert_eq!(negotiate(&[resample, halved]), Some((halved, 0, 2, false)));
+    assert_eq!(negotiate(&[resample, format(24, 88200, 6)]), None);
+    assert_eq!(negotiate(&[]), None);
+
+    let plan = OutputPlan::negotiate(&info, &[lower]).unwrap();
+
+    assert!(!plan.is_passthrough());
+    assert_eq!(plan.convert(-0x123456), -0x1235);
+    assert_eq!(plan.decimate(()).factor(), 1);
+    assert!(OutputPlan::negotiate(&info, &[exact]).unwrap().is_passthrough());
+    assert_eq!(OutputPlan::negotiate(&info, &[wider]).unwrap().convert(-1),
+               -256);
+  }
+
+  #[test]
+  fn test_sample_width() {
+    assert_eq!(sample_width(4), 1);
+    assert_eq!(sample_width(8), 1);
+    assert_eq!(sample_width(12), 2);
+    assert_eq!(sample_width(24), 3);
+    assert_eq!(sample_width(32), 4);
+  }
+}
@@ -0,0 +1,374 @@
+//! Playing a FLAC file on the default audio device.
+//!
+//! Only available with the `playback` feature enabled, which pulls in
+//! `cpal` for talking to the device. `play` goes through the same output
+//! path an application would: the device gets asked which formats it
+//! plays, `OutputPlan` picks the one reachable with the least loss, and the
+//! decoded blocks get decimated, mixed down, and converted to it before
+//! being handed over. It blocks until the last sample has been played,
+//! which makes it handy for a quick listen while working on the decoder.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! // Listen from the start, then again from ten seconds in.
+//! flac::play("path/to/file.flac").unwrap();
+//! flac::play::play_from("path/to/file.flac", 10 * 44100).unwrap();
+//! ```
+
+use metadata::StreamInfo;
+use pcm::{OutputFormat, OutputPlan};
+use sink::Sink;
+use stream::{Block, Stream, StreamReader};
+use utility::{ErrorKind, StreamProducer};
+
+use cpal::{
+  self, SampleFormat, SizedSample, SupportedStreamConfig,
+  SupportedStreamConfigRange,
+};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+use std::fs::File;
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TryRecvError};
+
+// Number of decoded blocks waiting on the device at most.
+const QUEUE_LENGTH: usize = 4;
+
+// Largest decimation tried when looking for a sample rate the device
+// plays, going down to a twelfth of the rate of the stream.
+const MAX_DECIMATION: u32 = 12;
+
+/// Reason playback failed or never started.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PlayError {
+  /// Error from opening, seeking, or decoding the stream.
+  Decode(ErrorKind),
+  /// There is no default output device.
+  NoDevice,
+  /// None of the formats the device plays can be reached from the stream
+  /// without a resampler.
+  UnsupportedFormat,
+  /// Error reported by the device, as its message.
+  Device(String),
+}
+
+impl From<ErrorKind> for PlayError {
+  fn from(kind: ErrorKind) -> Self {
+    PlayError::Decode(kind)
+  }
+}
+
+/// Plays the file at `filename` on the default output device, returning
+/// once all of it has been played.
+///
+/// # Failures
+///
+/// Fails in the same way as `play_from` does.
+#[inline]
+pub fn play(filename: &str) -> Result<(), PlayError> {
+  play_from(filename, 0)
+}
+
+/// Plays the file at `filename` on the default output device, starting
+/// at the frame holding `sample_number`.
+///
+/// # Failures
+///
+/// * `PlayError::Decode` is returned when the file fails to open, seek, or
+///   decode. Samples before the frame that failed still get played.
+/// * `PlayError::NoDevice` is returned when there is no output device.
+/// * `PlayError::UnsupportedFormat` is returned when the device needs a
+///   resampler, or more channels than the file has.
+/// * `PlayError::Device` is returned for anything the device reports.
+pub fn play_from(filename: &str, sample_number: u64)
+                 -> Result<(), PlayError> {
+  let mut stream = try!(StreamReader::<File>::from_file(filename));
+  let host       = cpal::default_host();
+  let device     = try!(host.default_output_device()
+                            .ok_or(PlayError::NoDevice));
+
+  let ranges = try!(device.supported_output_configs()
+                          .map_err(device_error))
+                 .collect::<Vec<_>>();
+  let info    = stream.info();
+  let configs = output_formats(&info, &ranges);
+  let formats = configs.iter().map(|&(format, _)| format)
+                              .collect::<Vec<_>>();
+
+  let plan   = try!(stream.negotiate_output(&formats)
+                          .ok_or(PlayError::UnsupportedFormat));
+  let config = configs.iter().find(|&&(format, _)| format == plan.format)
+                      .map(|&(_, ref config)| config.clone())
+                      .unwrap();
+
+  if sample_number > 0 {
+    try!(stream.seek(sample_number));
+  }
+
+  let (sender, receiver) = mpsc::sync_channel(QUEUE_LENGTH);
+  let (done, finished)   = mpsc::channel();
+
+  let bits_per_sample = plan.format.bits_per_sample;
+  let scale           = 1.0 / (1u64 << (bits_per_sample - 1)) as f32;
+
+  let output = match config.sample_format() {
+    SampleFormat::I16 => {
+      open(&device, &config, receiver, done, |sample| sample as i16)
+    }
+    SampleFormat::I32 => {
+      open(&device, &config, receiver, done, |sample| sample as i32)
+    }
+    _                 => {
+      open(&device, &config, receiver, done,
+           move |sample| sample as f32 * scale)
+    }
+  };
+
+  let output = try!(output);
+
+  try!(output.play().map_err(device_error));
+
+  let result = feed(&mut stream, plan, sender);
+
+  // The device finishes what it was handed even when decoding stopped
+  // early, since the sender got dropped either way.
+  let played = match finished.recv() {
+    Ok(Some(message)) => Err(PlayError::Device(message)),
+    _                 => Ok(()),
+  };
+
+  try!(result);
+
+  played
+}
+
+// Lists the formats, out of the ranges the device supports, that are
+// within reach of the stream, along with the config to open the device
+// with for each.
+//
+// Devices taking floating point samples get the bits per sample of the
+// stream, since nothing gets lost converting to them.
+fn output_formats(info: &StreamInfo, ranges: &[SupportedStreamConfigRange])
+                  -> Vec<(OutputFormat, SupportedStreamConfig)> {
+  let mut formats = Vec::new();
+
+  for &range in ranges {
+    let bits_per_sample = match range.sample_format() {
+      SampleFormat::I16 => 16,
+      SampleFormat::I32 => 32,
+      SampleFormat::F32 => info.bits_per_sample.get(),
+      _                 => continue,
+    };
+
+    if range.channels() > u8::max_value() as u16 {
+      continue;
+    }
+
+    let stream_rate = info.sample_rate.get();
+
+    for factor in 1..(MAX_DECIMATION + 1) {
+      let sample_rate = stream_rate / factor;
+
+      if stream_rate % factor != 0 ||
+         sample_rate < range.min_sample_rate().0 ||
+         sample_rate > range.max_sample_rate().0 {
+        continue;
+      }
+
+      let format = OutputFormat {
+        bits_per_sample: bits_per_sample,
+        sample_rate: sample_rate,
+        channels: range.channels() as u8,
+      };
+
+      let config = range.with_sample_rate(cpal::SampleRate(sample_rate));
+
+      formats.push((format, config));
+    }
+  }
+
+  formats
+}
+
+// Opens the device for samples of type `T`, made from the ones of each
+// block by `to_sample`. Silence gets played whenever decoding falls
+// behind, and `done` gets sent `None` once every block has been played,
+// or the message of an error from the device.
+fn open<D, T, F>(device: &D, config: &SupportedStreamConfig,
+                 receiver: Receiver<Vec<i64>>, done: Sender<Option<String>>,
+                 to_sample: F)
+                 -> Result<D::Stream, PlayError>
+ where D: DeviceTrait,
+       T: SizedSample,
+       F: Fn(i64) -> T + Send + 'static {
+  let errors       = done.clone();
+  let mut samples  = Vec::new();
+  let mut position = 0;
+  let mut is_done  = false;
+
+  let output = device.build_output_stream(&config.config(),
+    move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+      for value in data.iter_mut() {
+        while position == samples.len() && !is_done {
+          match receiver.try_recv() {
+            Ok(block)                       => {
+              samples  = block;
+              position = 0;
+            }
+            Err(TryRecvError::Empty)        => break,
+            Err(TryRecvError::Disconnected) => {
+              is_done = true;
+
+              let _ = done.send(None);
+            }
+          }
+        }
+
+        *value = if position < samples.len() {
+          position += 1;
+
+          to_sample(samples[position - 1])
+        } else {
+          to_sample(0)
+        };
+      }
+    },
+    move |error| {
+      let _ = errors.send(Some(error.to_string()));
+    },
+    None);
+
+  output.map_err(device_error)
+}
+
+// Decodes the rest of the stream through the conversions of `plan`,
+// sending each block to the device.
+fn feed<P>(stream: &mut Stream<P>, plan: OutputPlan,
+           sender: SyncSender<Vec<i64>>)
+           -> Result<u64, ErrorKind>
+ where P: StreamProducer {
+  let sink = DeviceSink {
+    plan: plan,
+    sender: sender,
+  };
+
+  stream.decode_into::<i32, _>(plan.decimate(sink))
+}
+
+// Sink handing blocks to the device, converted to its bits per sample.
+struct DeviceSink {
+  plan: OutputPlan,
+  sender: SyncSender<Vec<i64>>,
+}
+
+impl Sink<i32> for DeviceSink {
+  fn write_block(&mut self, block: &Block<i32>) -> Result<(), ErrorKind> {
+    let samples = block.samples.iter()
+                       .map(|&sample| self.plan.convert(sample as i64))
+                       .collect();
+
+    // The device only hangs up when playback has been torn down.
+    self.sender.send(samples).map_err(|_| ErrorKind::Cancelled)
+  }
+}
+
+fn device_error<E: ToString>(error: E) -> PlayError {
+  PlayError::Device(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use stream::StreamReader;
+  use utility::{BitsPerSample, SampleRate};
+
+  use cpal::SupportedBufferSize;
+
+  use std::fs::File;
+  use std::thread;
+
+  fn range(channels: u16, min: u32, max: u32, sample_format: SampleFormat)
+           -> SupportedStreamConfigRange {
+    SupportedStreamConfigRange::new(channels, cpal::SampleRate(min),
+                                    cpal::SampleRate(max),
+                                    SupportedBufferSize::Unknown,
+                                    sample_format)
+  }
+
+  #[test]
+  fn test_output_formats() {
+    let mut info = StreamInfo::default();
+
+    info.sample_rate     = SampleRate::new(96000).unwrap();
+    info.channels        = 2;
+    info.bits_per_sample = BitsPerSample::new(24).unwrap();
+
+    let ranges  = [ range(2, 44100, 48000, SampleFormat::I16)
+                  , range(1, 8000, 96000, SampleFormat::F32)
+                  , range(2, 8000, 192000, SampleFormat::U8)
+                  ];
+    let formats = output_formats(&info, &ranges);
+    let rates   = formats.iter().map(|&(format, _)| format.sample_rate)
+                                .collect::<Vec<_>>();
+
+    assert_eq!(rates, [48000, 96000, 48000, 32000, 24000, 19200, 16000,
+                       12000, 9600, 8000]);
+    assert_eq!(formats[0].0.bits_per_sample, 16);
+    assert_eq!(formats[1].0.bits_per_sample, 24);
+    assert_eq!(formats[1].0.channels, 1);
+    assert_eq!(formats[1].1.sample_format(), SampleFormat::F32);
+
+    // Keeping the sample rate wins over keeping both channels.
+    let supported = formats.iter().map(|&(format, _)| format)
+                                  .collect::<Vec<_>>();
+    let plan      = OutputPlan::negotiate(&info, &supported).unwrap();
+
+    assert_eq!(plan.format, formats[1].0);
+  }
+
+  #[test]
+  fn test_feed() {
+    let filename   = "tests/assets/input-SCPAP.flac";
+    let mut stream = StreamReader::<File>::from_file(filename).unwrap();
+    let info       = stream.info();
+
+    // Mixed down to mono, at half the rate and eight bits.
+    let format = OutputFormat {
+      bits_per_sample: 8,
+      sample_rate: info.sample_rate.get() / 2,
+      channels: 1,
+    };
+
+    let plan  = stream.negotiate_output(&[format]).unwrap();
+    let start = stream.seek(5000).unwrap() as usize;
+
+    assert!(plan.is_downmix && plan.decimation == 2 && plan.shift == -8);
+
+    let (sender, receiver) = mpsc::sync_channel(QUEUE_LENGTH);
+    let device             = thread::spawn(move || {
+      receiver.iter().flat_map(|block| block).collect::<Vec<_>>()
+    });
+
+    let written = feed(&mut stream, plan, sender).unwrap();
+    let played  = device.join().unwrap();
+
+    let mut reference = StreamReader::<File>::from_file(filename).unwrap();
+    let samples       = reference.iter::<i16>().collect::<Vec<_>>();
+    let channels      = info.channels as usize;
+    let frames        = samples[(start * channels)..].chunks(channels * 2);
+
+    let expected = frames.map(|group| {
+      let sums = group.chunks(channels).fold(vec![0; channels], |sums, s| {
+        sums.iter().zip(s).map(|(&sum, &s)| sum + s as i64).collect()
+      });
+      let average = sums.iter().map(|&sum| sum / (group.len() / channels)
+                                                  as i64)
+                                .fold(0, |mix, value| mix + value);
+
+      (average / channels as i64) >> 8
+    }).collect::<Vec<_>>();
+
+    assert_eq!(written as usize, samples.len() / channels - start);
+    assert_eq!(played, expected);
+  }
+}
@@ -0,0 +1,83 @@
+//! Decoding ahead of playback on a background thread.
+//!
+//! Reading from slow storage can stall now and then, which shows up as
+//! dropouts when frames get decoded right as they're played. `Prefetcher`
+//! decodes up to a set number of frames ahead on a separate thread, so a
+//! stall only eats into the frames that are already decoded.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use flac::StreamReader;
+//! use flac::prefetch;
+//!
+//! use std::fs::File;
+//!
+//! let stream = StreamReader::<File>::from_file("path/to/file.flac")
+//!                .unwrap();
+//!
+//! // Keep up to eight frames decoded ahead of playback.
+//! for block in prefetch::prefetch::<i16, _>(stream, 8) {
+//!   let block = block.unwrap();
+//!
+//!   // Samples are interleaved by channel within `block.samples`.
+//! }
+//! ```
+
+use queue::{self, Queue};
+use stream::{Block, Stream};
+use utility::{ErrorKind, Sample, SampleSize, StreamProducer};
+
+use std::sync::Arc;
+
+/// An iterator over the decoded blocks of a FLAC stream, which get decoded
+/// ahead of time on a separate thread.
+///
+/// Dropping the iterator tells the decoding thread to stop, without
+/// waiting for it, so the thread exits on its own once the frame it's on is
+/// decoded. The error of a frame that fails to decode is the last item of
+/// the iterator.
+pub struct Prefetcher<S> {
+  queue: Arc<Queue<S>>,
+}
+
+/// Starts decoding the stream on a separate thread, holding no more than
+/// `depth` decoded blocks that haven't been taken yet.
+///
+/// A depth of zero is treated as one, since the next block needs to be
+/// decoded before it can be taken.
+pub fn prefetch<S, P>(stream: Stream<P>, depth: usize)
+                      -> Prefetcher<<S::Extended as Sample>::Normal>
+ where S: SampleSize + 'static,
+       P: StreamProducer + Send + 'static,
+       <S::Extended as Sample>::Normal: Send + 'static {
+  Prefetcher {
+    queue: queue::spawn::<S, P>(stream, depth),
+  }
+}
+
+impl<S> Prefetcher<S> {
+  /// Returns the number of decoded blocks waiting to be taken.
+  pub fn buffered(&self) -> usize {
+    self.queue.len()
+  }
+
+  /// Returns the largest number of decoded blocks held at once.
+  pub fn depth(&self) -> usize {
+    self.queue.limit()
+  }
+}
+
+impl<S> Iterator for Prefetcher<S> {
+  type Item = Result<Block<S>, ErrorKind>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    self.queue.pop()
+  }
+}
+
+impl<S> Drop for Prefetcher<S> {
+  fn drop(&mut self) {
+    self.queue.close();
+  }
+}
@@ -0,0 +1,35 @@
+//! Commonly used types and traits, brought in with a single import.
+//!
+//! Decoding usually needs types from the crate root along with traits like
+//! `StreamProducer` and `Sample`, while looking through metadata needs
+//! types from `flac::metadata`. Glob importing the prelude brings in all of
+//! them at once. `metadata::Type` comes in as `MetadataType`, since a bare
+//! `Type` is too easy to mix up with anything else in scope.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use flac::prelude::*;
+//! use std::fs::File;
+//!
+//! let mut stream = StreamReader::<File>::from_file("path/to/file.flac")
+//!                    .unwrap();
+//!
+//! let pictures = stream.metadata().iter().filter(|block| {
+//!   block.data_type() == MetadataType::Picture
+//! }).count();
+//!
+//! let samples = stream.iter::<i16>().collect::<Vec<_>>();
+//! ```
+
+pub use stream::{
+  Stream, StreamReader, StreamBuffer, StreamOptions, FlacReader,
+  PushDecoder, FrameFeed,
+};
+pub use encoder::{Encoder, EncoderOptions};
+pub use metadata::{Metadata, Data, StreamInfo, Type as MetadataType};
+pub use sink::Sink;
+pub use utility::{
+  Sample, SampleSize, StreamProducer, SeekableProducer, ReadStream,
+  ByteStream, ErrorKind,
+};
@@ -0,0 +1,203 @@
+// Decoding blocks on a separate thread into a bounded queue, shared by
+// `prefetch` and `async_stream`.
+//
+// The decoding thread waits whenever the queue holds `limit` blocks, so a
+// slow consumer holds back the decoding instead of letting decoded samples
+// pile up. The lock is only held to push or pop a block, never while a
+// frame gets decoded.
+
+#[cfg(feature = "async")]
+use futures::Async;
+#[cfg(feature = "async")]
+use futures::task::{self, Task};
+
+use stream::{Block, Stream};
+use utility::{ErrorKind, Sample, SampleSize, StreamProducer};
+
+use std::cmp;
+use std::thread;
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+pub type Item<S> = Result<Block<S>, ErrorKind>;
+
+struct State<S> {
+  blocks: VecDeque<Item<S>>,
+  limit: usize,
+  is_done: bool,
+  is_dropped: bool,
+  // Task of a `futures::Stream` waiting on the next block.
+  #[cfg(feature = "async")]
+  task: Option<Task>,
+}
+
+pub struct Queue<S> {
+  state: Mutex<State<S>>,
+  // Signaled when a block gets taken, making space for another.
+  space: Condvar,
+  // Signaled when a block gets decoded or decoding is done.
+  ready: Condvar,
+}
+
+impl<S> Queue<S> {
+  fn new(limit: usize) -> Self {
+    Queue {
+      state: Mutex::new(State {
+        blocks: VecDeque::new(),
+        limit: cmp::max(limit, 1),
+        is_done: false,
+        is_dropped: false,
+        #[cfg(feature = "async")]
+        task: None,
+      }),
+      space: Condvar::new(),
+      ready: Condvar::new(),
+    }
+  }
+
+  #[inline]
+  fn lock(&self) -> MutexGuard<State<S>> {
+    self.state.lock().unwrap_or_else(|error| error.into_inner())
+  }
+
+  // Wakes up whoever is waiting on the next block, after one got pushed or
+  // decoding is done.
+  fn notify_ready(&self, _state: &mut State<S>) {
+    self.ready.notify_one();
+
+    #[cfg(feature = "async")]
+    {
+      if let Some(task) = _state.task.take() {
+        task.notify();
+      }
+    }
+  }
+
+  // Waits for space within the queue, returning false once the consumer
+  // is gone.
+  fn wait_for_space(&self) -> bool {
+    let mut state = self.lock();
+
+    while !state.is_dropped && state.blocks.len() >= state.limit {
+      state = self.space.wait(state)
+                .unwrap_or_else(|error| error.into_inner());
+    }
+
+    !state.is_dropped
+  }
+
+  fn push(&self, block: Item<S>) {
+    let mut state = self.lock();
+
+    state.blocks.push_back(block);
+
+    self.notify_ready(&mut state);
+  }
+
+  pub fn len(&self) -> usize {
+    self.lock().blocks.len()
+  }
+
+  pub fn limit(&self) -> usize {
+    self.lock().limit
+  }
+
+  // Takes the next block, waiting for it to be decoded. Returns `None`
+  // once decoding is done and every block has been taken.
+  pub fn pop(&self) -> Option<Item<S>> {
+    let mut state = self.lock();
+
+    loop {
+      if let Some(block) = state.blocks.pop_front() {
+        self.space.notify_one();
+
+        return Some(block);
+      }
+
+      if state.is_done {
+        return None;
+      }
+
+      state = self.ready.wait(state)
+                .unwrap_or_else(|error| error.into_inner());
+    }
+  }
+
+  // Takes the next block without waiting, leaving the current task to be
+  // notified when there isn't one yet.
+  #[cfg(feature = "async")]
+  pub fn poll(&self) -> Async<Option<Item<S>>> {
+    let mut state = self.lock();
+
+    match state.blocks.pop_front() {
+      Some(block) => {
+        self.space.notify_one();
+
+        Async::Ready(Some(block))
+      }
+      None        => {
+        if state.is_done {
+          Async::Ready(None)
+        } else {
+          state.task = Some(task::current());
+
+          Async::NotReady
+        }
+      }
+    }
+  }
+
+  // Tells the decoding thread to stop, without waiting for it.
+  pub fn close(&self) {
+    self.lock().is_dropped = true;
+    self.space.notify_one();
+  }
+}
+
+// Marks decoding as done once the decoding thread exits, even by panicking,
+// so the consumer never waits on a thread that's gone.
+struct Finish<S>(Arc<Queue<S>>);
+
+impl<S> Drop for Finish<S> {
+  fn drop(&mut self) {
+    let mut state = self.0.lock();
+
+    state.is_done = true;
+
+    self.0.notify_ready(&mut state);
+  }
+}
+
+// Starts decoding the stream on a separate thread, into a queue holding no
+// more than `limit` blocks. A limit of zero is treated as one.
+pub fn spawn<S, P>(stream: Stream<P>, limit: usize)
+                   -> Arc<Queue<<S::Extended as Sample>::Normal>>
+ where S: SampleSize + 'static,
+       P: StreamProducer + Send + 'static,
+       <S::Extended as Sample>::Normal: Send + 'static {
+  let queue   = Arc::new(Queue::new(limit));
+  let decoder = queue.clone();
+
+  thread::spawn(move || {
+    let _finish    = Finish(decoder.clone());
+    let mut stream = stream;
+    let mut blocks = stream.blocks::<S>();
+
+    while decoder.wait_for_space() {
+      match blocks.next() {
+        Some(block) => decoder.push(block),
+        None        => {
+          // A frame that fails to decode ends the blocks without an error,
+          // which is kept in the progress instead.
+          if let Some(kind) = blocks.decode_progress().error {
+            decoder.push(Err(kind));
+          }
+
+          return;
+        }
+      }
+    }
+  });
+
+  queue
+}
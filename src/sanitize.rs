@@ -0,0 +1,590 @@
+//! Rewriting of FLAC streams into a cleaned up copy.
+//!
+//! Files out in the wild pick up all sorts of oddities from the tools that
+//! touched them: metadata blocks of types nobody knows, more than one vorbis
+//! comment, lower case comment names, padding scattered between blocks, and
+//! frames that got damaged along the way. `sanitize` takes care of all of
+//! them at once, leaving a copy that sticks to the format as closely as
+//! possible.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use flac::sanitize::{self, SanitizePolicy};
+//! use std::fs::File;
+//!
+//! let input  = File::open("path/to/weird.flac").unwrap();
+//! let output = File::create("path/to/clean.flac").unwrap();
+//! let report = sanitize::sanitize(input, output, SanitizePolicy::default())
+//!                .unwrap();
+//!
+//! if !report.is_clean() {
+//!   println!("{:?}", report);
+//! }
+//! ```
+
+use encoder::StreamInfoAccumulator;
+use frame::{frame_parser, MAX_CHANNELS};
+use metadata::{
+  Data, Metadata, StreamInfo, metadata_parser_with_limit, padding_block,
+};
+#[cfg(feature = "vorbis-comment")]
+use metadata::VorbisComment;
+use utility::{ErrorKind, MAX_COMMENTS, to_u32};
+
+use nom::IResult;
+
+use std::cmp;
+use std::io::{self, Read, Write};
+
+// Largest length, in bytes, of the data within a metadata block.
+const MAX_BLOCK_LENGTH: usize = 0xffffff;
+
+// Largest block size, in samples, a frame header is able to hold.
+const MAX_BLOCK_SIZE: usize = 65536;
+
+/// What gets cleaned up when sanitizing a stream.
+///
+/// Metadata blocks that fail to parse and frames that fail to decode are
+/// always left out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SanitizePolicy {
+  /// Keeps metadata blocks with a type outside of the ones the format
+  /// defines, which are left out by default.
+  pub keep_unknown: bool,
+  /// Keeps every seek table, vorbis comment, and cue sheet, rather than
+  /// only the first of each, which is all the format allows. A second
+  /// `StreamInfo` is left out either way.
+  pub keep_duplicates: bool,
+  /// Changes the names of vorbis comments to upper case, which is how
+  /// nearly every tool expects to find them. On by default.
+  pub normalize_comments: bool,
+  /// Replaces every padding block with a single one at the end of the
+  /// metadata, holding all of their space. On by default.
+  pub merge_padding: bool,
+}
+
+impl Default for SanitizePolicy {
+  fn default() -> Self {
+    SanitizePolicy {
+      keep_unknown: false,
+      keep_duplicates: false,
+      normalize_comments: true,
+      merge_padding: true,
+    }
+  }
+}
+
+/// What was changed while sanitizing a stream.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SanitizeReport {
+  /// Number of metadata blocks left out for having an unknown type.
+  pub unknown_blocks: usize,
+  /// Number of metadata blocks left out for being a second `StreamInfo`,
+  /// seek table, vorbis comment, or cue sheet.
+  pub duplicate_blocks: usize,
+  /// Number of metadata blocks left out for failing to parse.
+  pub corrupt_blocks: usize,
+  /// Number of padding blocks merged together, zero when there was no more
+  /// than one.
+  pub merged_padding: usize,
+  /// Number of vorbis comments that had their name changed to upper case.
+  pub renamed_comments: usize,
+  /// Number of vorbis comments left out for having the same name as another
+  /// one, once both are in upper case.
+  pub dropped_comments: usize,
+  /// Number of frames copied over.
+  pub frames: u64,
+  /// Number of times the audio failed to decode, each followed by skipping
+  /// to the next frame sync code.
+  pub dropped_frames: u64,
+  /// Number of audio bytes left out, from frames that failed to decode.
+  pub skipped_bytes: u64,
+}
+
+impl SanitizeReport {
+  /// Returns true when nothing needed to be changed, other than recomputing
+  /// `StreamInfo`.
+  pub fn is_clean(&self) -> bool {
+    self.unknown_blocks == 0 && self.duplicate_blocks == 0 &&
+      self.corrupt_blocks == 0 && self.merged_padding == 0 &&
+      self.renamed_comments == 0 && self.dropped_comments == 0 &&
+      self.dropped_frames == 0
+  }
+}
+
+/// Writes a cleaned up copy of the FLAC stream from `input` to `output`,
+/// returning what got changed along the way.
+///
+/// Metadata blocks are dropped, merged, and normalized following `policy`,
+/// with every other block copied over byte for byte. Only frames that
+/// decode, CRC-16 included, get copied over, so damaged audio is left out
+/// rather than being passed on. `StreamInfo` gets its block sizes, frame
+/// sizes, and total samples recomputed from the frames that were kept, and
+/// its MD5 signature cleared when any audio was left out, since it would no
+/// longer match.
+///
+/// Frames keep the frame or sample number they were stored with, which
+/// leaves a gap in the numbering wherever a frame was dropped.
+///
+/// # Failures
+///
+/// * `ErrorKind::IO(io::ErrorKind::InvalidData)` is returned when the input
+///   doesn't start with a FLAC header followed by `StreamInfo`, or the
+///   metadata is cut short.
+/// * `ErrorKind::StreamInfoParser` is returned when `StreamInfo` itself
+///   fails to parse.
+/// * Any error from reading or writing, after which `output` holds
+///   whatever was written up to that point.
+pub fn sanitize<R, W>(input: R, output: W, policy: SanitizePolicy)
+                      -> Result<SanitizeReport, ErrorKind>
+ where R: Read,
+       W: Write {
+  let mut input  = input;
+  let mut output = output;
+  let mut bytes  = Vec::new();
+
+  try!(input.read_to_end(&mut bytes).map_err(|e| ErrorKind::IO(e.kind())));
+
+  let mut report = SanitizeReport::default();
+
+  let (mut info, mut blocks, audio_offset) =
+    try!(sanitize_metadata(&bytes, policy, &mut report));
+
+  let mut frames = Vec::with_capacity(bytes.len() - audio_offset);
+
+  sanitize_frames(&bytes[audio_offset..], &mut info, &mut frames,
+                  &mut report);
+
+  write_stream(&mut output, &info, &mut blocks, &frames)
+    .map_err(|e| ErrorKind::IO(e.kind()))
+    .map(|_| report)
+}
+
+// Goes through every metadata block, returning `StreamInfo`, the blocks
+// that are kept, with their headers, and the offset to the first frame.
+fn sanitize_metadata(bytes: &[u8], policy: SanitizePolicy,
+                     report: &mut SanitizeReport)
+                     -> Result<(StreamInfo, Vec<Vec<u8>>, usize), ErrorKind> {
+  if bytes.len() < 4 || &bytes[0..4] != b"fLaC" {
+    return Err(invalid_data());
+  }
+
+  let mut info    = None;
+  let mut blocks  = Vec::new();
+  let mut seen    = [false; 7];
+  let mut padding = Vec::new();
+  let mut offset  = 4;
+  let mut is_last = false;
+
+  while !is_last {
+    if bytes.len() < offset + 4 {
+      return Err(invalid_data());
+    }
+
+    let block_type = bytes[offset] & 0b01111111;
+    let length     = to_u32(&bytes[(offset + 1)..(offset + 4)]) as usize;
+    let end        = offset + 4 + length;
+
+    is_last = (bytes[offset] >> 7) == 1;
+
+    if block_type == 127 || bytes.len() < end ||
+       (info.is_none() && block_type != 0) {
+      return Err(invalid_data());
+    }
+
+    let raw = &bytes[offset..end];
+
+    offset = end;
+
+    if info.is_none() {
+      match metadata_parser_with_limit(raw, MAX_COMMENTS) {
+        IResult::Done(_, Metadata { data: Data::StreamInfo(stream_info),
+                                    .. }) => {
+          info    = Some(stream_info);
+          seen[0] = true;
+
+          continue;
+        }
+        _                                 => {
+          return Err(ErrorKind::StreamInfoParser);
+        }
+      }
+    }
+
+    if block_type > 6 {
+      if policy.keep_unknown {
+        blocks.push(raw.to_vec());
+      } else {
+        report.unknown_blocks += 1;
+      }
+
+      continue;
+    }
+
+    let block = match metadata_parser_with_limit(raw, MAX_COMMENTS) {
+      IResult::Done(_, block) => block,
+      _                       => {
+        report.corrupt_blocks += 1;
+
+        continue;
+      }
+    };
+
+    // Only a single `StreamInfo`, seek table, vorbis comment, and cue sheet
+    // are allowed within a stream. Decoders only ever look at the first
+    // `StreamInfo`, so there's no keeping another one.
+    let is_unique    = block_type == 3 || block_type == 4 || block_type == 5;
+    let is_duplicate = seen[block_type as usize] &&
+                       (block_type == 0 ||
+                        (is_unique && !policy.keep_duplicates));
+
+    if is_duplicate {
+      report.duplicate_blocks += 1;
+
+      continue;
+    }
+
+    seen[block_type as usize] = true;
+
+    if block_type == 1 && policy.merge_padding {
+      padding.push(length);
+
+      continue;
+    }
+
+    if policy.normalize_comments {
+      if let Some(bytes) = normalize_comments(&block, report) {
+        blocks.push(bytes);
+
+        continue;
+      }
+    }
+
+    blocks.push(raw.to_vec());
+  }
+
+  if !padding.is_empty() {
+    // The block headers of the padding blocks being merged away are put
+    // towards the padding as well.
+    let length = padding.iter().fold(0, |result, length| result + length) +
+                 (padding.len() - 1) * 4;
+
+    if padding.len() > 1 {
+      report.merged_padding = padding.len();
+    }
+
+    blocks.push(padding_block(cmp::min(length, MAX_BLOCK_LENGTH)));
+  }
+
+  Ok((info.unwrap_or_default(), blocks, offset))
+}
+
+// Returns the block with the names of its comments in upper case, when it's
+// a vorbis comment that has any names in lower case. Of the comments that
+// end up with the same name only one is kept.
+#[cfg(feature = "vorbis-comment")]
+fn normalize_comments(block: &Metadata, report: &mut SanitizeReport)
+                      -> Option<Vec<u8>> {
+  let comment = match block.data {
+    Data::VorbisComment(ref comment) => comment,
+    _                                => return None,
+  };
+
+  let renamed = comment.comments.keys().filter(|name| {
+    name.to_uppercase() != **name
+  }).count();
+
+  if renamed == 0 {
+    return None;
+  }
+
+  let mut normalized = VorbisComment::new()
+                         .with_vendor_string(&comment.vendor_string);
+  let mut names      = comment.comments.keys().collect::<Vec<_>>();
+  let mut dropped    = 0;
+
+  // Names that are already in upper case win over the ones that only match
+  // once changed, with the rest going by the order of their names.
+  names.sort_by_key(|name| (name.to_uppercase() != **name, *name));
+
+  for name in names {
+    let upper = name.to_uppercase();
+
+    if normalized.comments.contains_key(&upper) {
+      dropped += 1;
+    } else {
+      normalized.comments.insert(upper, comment.comments[name].clone());
+    }
+  }
+
+  let data  = Data::VorbisComment(normalized);
+  let block = Metadata::new(false, 0, data);
+  let mut bytes = Vec::with_capacity(block.bytes_len());
+
+  match block.to_bytes(&mut bytes) {
+    Ok(_)  => {
+      report.renamed_comments += renamed;
+      report.dropped_comments += dropped;
+
+      Some(bytes)
+    }
+    Err(_) => None,
+  }
+}
+
+#[cfg(not(feature = "vorbis-comment"))]
+fn normalize_comments(_: &Metadata, _: &mut SanitizeReport)
+                      -> Option<Vec<u8>> {
+  None
+}
+
+// Copies every frame that decodes into `output`, skipping over whatever
+// doesn't up to the next frame sync code, and updates `info` to match the
+// frames that were kept.
+fn sanitize_frames(audio: &[u8], info: &mut StreamInfo, output: &mut Vec<u8>,
+                   report: &mut SanitizeReport) {
+  let mut buffer      = vec![0i32; MAX_BLOCK_SIZE * MAX_CHANNELS];
+  let mut accumulator = StreamInfoAccumulator::new();
+  let mut position    = 0;
+
+  while position < audio.len() {
+    let input = &audio[position..];
+
+    if let IResult::Done(rest, frame) = frame_parser(input, info,
+                                                     &mut buffer) {
+      let length = input.len() - rest.len();
+
+      output.extend_from_slice(&input[0..length]);
+      accumulator.add_frame(frame.header.block_size as u16, length);
+
+      position       += length;
+      report.frames  += 1;
+
+      continue;
+    }
+
+    // The failed frame starts with a sync code of its own.
+    let skipped = input.windows(2).skip(1).position(|bytes| {
+      bytes[0] == 0xff && (bytes[1] & 0xfe) == 0xf8
+    }).map_or(input.len(), |found| found + 1);
+
+    position              += skipped;
+    report.dropped_frames += 1;
+    report.skipped_bytes  += skipped as u64;
+  }
+
+  accumulator.apply(info);
+
+  if report.dropped_frames > 0 {
+    info.md5_sum = [0; 16];
+  }
+}
+
+// Writes the FLAC header, `StreamInfo`, the rest of the metadata blocks
+// with the is last flags recomputed, and the frames.
+fn write_stream<W>(output: &mut W, info: &StreamInfo, blocks: &mut [Vec<u8>],
+                   frames: &[u8])
+                   -> io::Result<()>
+ where W: Write {
+  let is_last     = blocks.is_empty();
+  let stream_info = Metadata::new(is_last, 34, Data::StreamInfo(*info));
+
+  try!(output.write_all(b"fLaC"));
+  try!(stream_info.to_bytes(output));
+
+  let last = blocks.len().saturating_sub(1);
+
+  for (index, block) in blocks.iter_mut().enumerate() {
+    if index == last {
+      block[0] |= 0b10000000;
+    } else {
+      block[0] &= 0b01111111;
+    }
+
+    try!(output.write_all(block));
+  }
+
+  try!(output.write_all(frames));
+
+  output.flush()
+}
+
+fn invalid_data() -> ErrorKind {
+  ErrorKind::IO(io::ErrorKind::InvalidData)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use encoder::{Encoder, EncoderOptions};
+  use metadata::{Data, Payload, StreamInfo, Type};
+  use stream::StreamBuffer;
+  use utility::{BitsPerSample, SampleRate};
+
+  use std::io::Cursor;
+
+  fn encoded(metadata: Vec<Data>) -> (Vec<u8>, Vec<i32>) {
+    let mut info = StreamInfo::default();
+
+    info.sample_rate     = SampleRate::new(44100).unwrap();
+    info.channels        = 1;
+    info.bits_per_sample = BitsPerSample::new(16).unwrap();
+
+    let options = EncoderOptions {
+      block_size: 1024,
+      padding: 100,
+      metadata: metadata,
+      ..Default::default()
+    };
+
+    let samples = (0..8192).map(|i| (i * 7919 % 2001) - 1000)
+                           .collect::<Vec<_>>();
+
+    let mut encoder = Encoder::new(Cursor::new(Vec::new()), info, options)
+                        .unwrap();
+
+    encoder.write(&samples).unwrap();
+
+    (encoder.finish().unwrap().into_inner(), samples)
+  }
+
+  #[test]
+  fn test_sanitize_clean() {
+    let (bytes, samples) = encoded(Vec::new());
+    let mut output       = Vec::new();
+
+    let report = sanitize(&bytes[..], &mut output, Default::default())
+                   .unwrap();
+
+    assert!(report.is_clean());
+    assert_eq!(report.frames, 8);
+
+    let mut stream = StreamBuffer::from_buffer(&output).unwrap();
+
+    assert_eq!(stream.info().total_samples, 8192);
+    assert!(stream.iter::<i32>().eq(samples.into_iter()));
+  }
+
+  #[test]
+  fn test_sanitize() {
+    let (mut bytes, samples) = encoded(vec![
+      Data::Padding(20),
+      Data::Unknown(7, Payload::from(vec![1, 2, 3])),
+    ]);
+
+    let audio_offset = StreamBuffer::from_buffer(&bytes).unwrap()
+                                                       .audio_offset();
+
+    // Breaks the CRC-16 of the last frame.
+    let last = bytes.len() - 1;
+
+    bytes[last] ^= 0xff;
+
+    let mut output = Vec::new();
+    let report     = sanitize(&bytes[..], &mut output, Default::default())
+                       .unwrap();
+
+    assert_eq!(report.unknown_blocks, 1);
+    assert_eq!(report.merged_padding, 2);
+    assert_eq!(report.frames, 7);
+    assert_eq!(report.dropped_frames, 1);
+    assert!(!report.is_clean());
+
+    let mut stream = StreamBuffer::from_buffer(&output).unwrap();
+    let info       = stream.info();
+
+    let types = stream.metadata().iter().map(|block| block.data_type())
+                                 .collect::<Vec<_>>();
+
+    assert!(types == [Type::Padding]);
+    assert_eq!(stream.metadata()[0].length(), 124);
+    assert_eq!(stream.audio_offset(), audio_offset - 7);
+    assert_eq!(info.total_samples, 7168);
+    assert_eq!(info.md5_sum, [0; 16]);
+    assert!(stream.iter::<i32>().eq(samples.into_iter().take(7168)));
+
+    let policy = SanitizePolicy {
+      keep_unknown: true,
+      merge_padding: false,
+      ..Default::default()
+    };
+
+    let mut output = Vec::new();
+    let report     = sanitize(&bytes[..], &mut output, policy).unwrap();
+    let stream     = StreamBuffer::from_buffer(&output).unwrap();
+
+    assert_eq!((report.unknown_blocks, report.merged_padding), (0, 0));
+    assert_eq!(stream.metadata().len(), 3);
+
+    // A second copy of `StreamInfo` is left out even when keeping
+    // duplicates.
+    let stream_info = bytes[4..42].to_vec();
+
+    for (index, &byte) in stream_info.iter().enumerate() {
+      bytes.insert(42 + index, byte);
+    }
+
+    let policy = SanitizePolicy {
+      keep_duplicates: true,
+      ..Default::default()
+    };
+
+    let mut output = Vec::new();
+    let report     = sanitize(&bytes[..], &mut output, policy).unwrap();
+    let stream     = StreamBuffer::from_buffer(&output).unwrap();
+
+    let types = stream.metadata().iter().map(|block| block.data_type())
+                                 .collect::<Vec<_>>();
+
+    assert_eq!(report.duplicate_blocks, 1);
+    assert!(types == [Type::Padding]);
+  }
+
+  #[cfg(feature = "vorbis-comment")]
+  #[test]
+  fn test_sanitize_comments() {
+    let comments = VorbisComment::new().with_vendor_string("test")
+                                       .with_comment("Artist", "Someone")
+                                       .with_comment("TITLE", "Something")
+                                       .with_comment("title", "Other");
+
+    let (mut bytes, _) = encoded(vec![Data::VorbisComment(comments)]);
+
+    // A second copy of the vorbis comment, right after the first.
+    let length = {
+      let stream = StreamBuffer::from_buffer(&bytes).unwrap();
+
+      assert!(stream.metadata()[0].is_vorbis_comment());
+
+      stream.metadata()[0].length() as usize + 4
+    };
+
+    let block = bytes[42..(42 + length)].to_vec();
+    let end   = 42 + length;
+
+    for (index, &byte) in block.iter().enumerate() {
+      bytes.insert(end + index, byte);
+    }
+
+    let mut output = Vec::new();
+    let report     = sanitize(&bytes[..], &mut output, Default::default())
+                       .unwrap();
+
+    assert_eq!(report.duplicate_blocks, 1);
+    assert_eq!(report.renamed_comments, 2);
+    assert_eq!(report.dropped_comments, 1);
+
+    let stream  = StreamBuffer::from_buffer(&output).unwrap();
+    let comment = stream.metadata().iter()
+                        .filter_map(|block| block.as_vorbis_comment())
+                        .collect::<Vec<_>>();
+
+    assert_eq!(comment.len(), 1);
+    assert_eq!(comment[0].vendor_string, "test");
+    assert_eq!(comment[0].comments.get("ARTIST").map(|s| &s[..]),
+               Some("Someone"));
+    assert_eq!(comment[0].comments.get("TITLE").map(|s| &s[..]),
+               Some("Something"));
+  }
+}
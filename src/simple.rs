@@ -0,0 +1,135 @@
+#[cfg(feature = "vorbis-comment")]
+use metadata;
+use metadata::StreamInfo;
+use stream::StreamReader;
+use utility::ErrorKind;
+
+#[cfg(feature = "vorbis-comment")]
+use std::collections::HashMap;
+use std::cmp;
+use std::fs::File;
+use std::io::Read;
+
+// Most samples allocated up front from the total within `StreamInfo`,
+// which comes straight from the stream and can claim any size. Past this
+// the samples grow as they get decoded.
+const MAX_PREALLOCATED_SAMPLES: u64 = 1 << 20;
+
+/// Decodes an entire FLAC file, returning its `StreamInfo` along with every
+/// sample.
+///
+/// Samples are interleaved by channel, so for a stereo file the left and
+/// right samples alternate. Use `StreamReader` instead when the file is
+/// too large to keep in memory all at once.
+///
+/// # Failures
+///
+/// Fails in the same way as `Stream::from_file` does.
+///
+/// # Examples
+///
+/// ```
+/// match flac::read("path/to/file.flac") {
+///   Ok((info, samples)) => {
+///     println!("{} channels at {}Hz", info.channels,
+///              info.sample_rate.get());
+///     println!("{} samples", samples.len());
+///   }
+///   Err(error)          => println!("{:?}", error),
+/// }
+/// ```
+pub fn read(filename: &str) -> Result<(StreamInfo, Vec<i32>), ErrorKind> {
+  File::open(filename).map_err(|e| ErrorKind::IO(e.kind()))
+                      .and_then(read_from)
+}
+
+/// Decodes an entire FLAC stream from a reader, returning its `StreamInfo`
+/// along with every sample.
+///
+/// Nothing here goes through a file path, which makes it usable within
+/// sandboxes, like WASI, where paths aren't available.
+///
+/// # Failures
+///
+/// Fails in the same way as `Stream::new` does.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::io;
+///
+/// let (info, samples) = flac::read_from(io::stdin()).unwrap();
+/// ```
+pub fn read_from<R: Read>(reader: R)
+                          -> Result<(StreamInfo, Vec<i32>), ErrorKind> {
+  let mut stream = try!(StreamReader::<R>::new(reader));
+
+  let info     = stream.info();
+  let claimed  = info.total_samples.saturating_mul(info.channels as u64);
+  let capacity = cmp::min(claimed, MAX_PREALLOCATED_SAMPLES) as usize;
+
+  let mut samples = Vec::with_capacity(capacity);
+
+  samples.extend(stream.iter::<i32>());
+
+  Ok((info, samples))
+}
+
+/// Reads the tags, also known as the vorbis comments, of a FLAC file.
+///
+/// A file without any tags returns an empty `HashMap`.
+///
+/// # Failures
+///
+/// Fails in the same way as `metadata::get_vorbis_comment` does, other
+/// than when there are no tags.
+///
+/// # Examples
+///
+/// ```
+/// match flac::tags("path/to/file.flac") {
+///   Ok(tags)   => {
+///     for (name, value) in &tags {
+///       println!("{}: {}", name, value);
+///     }
+///   }
+///   Err(error) => println!("{:?}", error),
+/// }
+/// ```
+#[cfg(feature = "vorbis-comment")]
+pub fn tags(filename: &str) -> Result<HashMap<String, String>, ErrorKind> {
+  match metadata::get_vorbis_comment(filename) {
+    Ok(vorbis_comment)       => Ok(vorbis_comment.comments),
+    Err(ErrorKind::NotFound) => Ok(HashMap::new()),
+    Err(error)               => Err(error),
+  }
+}
+
+/// Reads the tags, also known as the vorbis comments, of a FLAC stream
+/// from a reader.
+///
+/// Only the metadata gets read, and a stream without any tags returns an
+/// empty `HashMap`.
+///
+/// # Failures
+///
+/// Fails in the same way as `Stream::new` does.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::io;
+///
+/// let tags = flac::tags_from(io::stdin()).unwrap();
+/// ```
+#[cfg(feature = "vorbis-comment")]
+pub fn tags_from<R: Read>(reader: R)
+                          -> Result<HashMap<String, String>, ErrorKind> {
+  let stream = try!(StreamReader::<R>::new(reader));
+
+  let comments = stream.metadata().iter().filter_map(|block| {
+    block.as_vorbis_comment().map(|v| v.comments.clone())
+  }).next();
+
+  Ok(comments.unwrap_or_else(HashMap::new))
+}
@@ -0,0 +1,830 @@
+//! Destinations for decoded blocks.
+//!
+//! A `Sink` takes each block of samples as it gets decoded, through
+//! `Stream::decode_into`. To hand the same blocks to more than one sink,
+//! like playing the audio while checking its MD5 signature, `TeeSink`
+//! forwards every block to each of the sinks within it, so the stream only
+//! gets decoded once. `DecimateSink` sits in front of another sink, handing
+//! it a fraction of the samples for quick previews, while `PeakSink` keeps
+//! the sample and true peak of each channel.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use flac::StreamReader;
+//! use flac::sink::{DigestSink, Sink, TeeSink};
+//! use flac::stream::Block;
+//! # #[cfg(feature = "md5")]
+//! use flac::digest::Md5;
+//!
+//! use std::fs::File;
+//!
+//! // Keeps the peak of every block, for drawing a waveform.
+//! struct Peaks(Vec<i16>);
+//!
+//! impl Sink<i16> for Peaks {
+//!   fn write_block(&mut self, block: &Block<i16>)
+//!                  -> Result<(), flac::ErrorKind> {
+//!     let peak = block.samples.iter().map(|s| s.saturating_abs()).max();
+//!
+//!     self.0.push(peak.unwrap_or(0));
+//!
+//!     Ok(())
+//!   }
+//! }
+//!
+//! # #[cfg(feature = "md5")]
+//! # fn main() {
+//! let mut stream = StreamReader::<File>::from_file("path/to/file.flac")
+//!                    .unwrap();
+//!
+//! let info       = stream.info();
+//! let mut peaks  = Peaks(Vec::new());
+//! let mut digest = DigestSink::new(Md5::new(), info.bits_per_sample.get());
+//!
+//! {
+//!   let mut tee = TeeSink::new();
+//!
+//!   tee.push(&mut peaks);
+//!   tee.push(&mut digest);
+//!
+//!   stream.decode_into::<i16, _>(&mut tee).unwrap();
+//! }
+//!
+//! if digest.signature() != info.md5_sum {
+//!   println!("decoded audio doesn't match the signature");
+//! }
+//! # }
+//! # #[cfg(not(feature = "md5"))]
+//! # fn main() {}
+//! ```
+
+use digest::Digest;
+use pcm::{PcmFormat, sample_width};
+use stream::Block;
+use utility::{ErrorKind, Sample, SampleSize};
+
+use std::cmp;
+use std::f64;
+use std::mem;
+
+/// Destination for blocks of decoded samples.
+pub trait Sink<S> {
+  /// Takes the next decoded block.
+  ///
+  /// An error stops decoding, and gets returned from `Stream::decode_into`.
+  fn write_block(&mut self, block: &Block<S>) -> Result<(), ErrorKind>;
+
+  /// Called once every block has been written.
+  fn finish(&mut self) -> Result<(), ErrorKind> {
+    Ok(())
+  }
+}
+
+impl<'a, S, K> Sink<S> for &'a mut K
+ where K: Sink<S> + ?Sized {
+  #[inline]
+  fn write_block(&mut self, block: &Block<S>) -> Result<(), ErrorKind> {
+    (**self).write_block(block)
+  }
+
+  #[inline]
+  fn finish(&mut self) -> Result<(), ErrorKind> {
+    (**self).finish()
+  }
+}
+
+/// Sink forwarding every block to each of the sinks within it, in the
+/// order they were added.
+///
+/// The first sink to return an error stops the block from reaching the
+/// sinks after it.
+pub struct TeeSink<'a, S> {
+  sinks: Vec<Box<Sink<S> + 'a>>,
+}
+
+impl<'a, S> TeeSink<'a, S> {
+  /// Constructs a tee without any sinks.
+  pub fn new() -> Self {
+    TeeSink {
+      sinks: Vec::new(),
+    }
+  }
+
+  /// Adds a sink that every block gets forwarded to.
+  ///
+  /// Passing in `&mut sink` keeps hold of the sink, so it can be looked at
+  /// once decoding is done.
+  pub fn push<K>(&mut self, sink: K)
+   where K: Sink<S> + 'a {
+    self.sinks.push(Box::new(sink));
+  }
+
+  /// Returns the number of sinks blocks get forwarded to.
+  #[inline]
+  pub fn len(&self) -> usize {
+    self.sinks.len()
+  }
+
+  /// Returns true when there are no sinks to forward blocks to.
+  #[inline]
+  pub fn is_empty(&self) -> bool {
+    self.sinks.is_empty()
+  }
+}
+
+impl<'a, S> Default for TeeSink<'a, S> {
+  #[inline]
+  fn default() -> Self {
+    TeeSink::new()
+  }
+}
+
+impl<'a, S> Sink<S> for TeeSink<'a, S> {
+  fn write_block(&mut self, block: &Block<S>) -> Result<(), ErrorKind> {
+    for sink in &mut self.sinks {
+      try!(sink.write_block(block));
+    }
+
+    Ok(())
+  }
+
+  fn finish(&mut self) -> Result<(), ErrorKind> {
+    for sink in &mut self.sinks {
+      try!(sink.finish());
+    }
+
+    Ok(())
+  }
+}
+
+/// Sink feeding samples into a hash, the same way the MD5 signature within
+/// `StreamInfo` is calculated.
+///
+/// The signature only matches when the blocks are in the channel order
+/// FLAC stores them in, which is the default `ChannelOrder`.
+///
+/// Writing a block of samples narrower than the bits per sample fails with
+/// `ErrorKind::Unsupported`, since the samples could have been truncated.
+pub struct DigestSink<D> {
+  digest: Option<D>,
+  bits_per_sample: usize,
+  width: usize,
+  signature: [u8; 16],
+}
+
+impl<D> DigestSink<D> where D: Digest {
+  /// Constructs a sink for samples of the given bits per sample.
+  pub fn new(digest: D, bits_per_sample: u8) -> Self {
+    DigestSink {
+      digest: Some(digest),
+      bits_per_sample: bits_per_sample as usize,
+      width: sample_width(bits_per_sample),
+      signature: [0; 16],
+    }
+  }
+
+  /// Returns the signature of every sample written, which is all zeros
+  /// until the sink is finished.
+  #[inline]
+  pub fn signature(&self) -> [u8; 16] {
+    self.signature
+  }
+}
+
+impl<D, S> Sink<S> for DigestSink<D>
+ where D: Digest,
+       S: Copy + Into<i64> {
+  fn write_block(&mut self, block: &Block<S>) -> Result<(), ErrorKind> {
+    let format    = PcmFormat::default();
+    let mut bytes = [0; 8];
+    let bytes     = &mut bytes[0..self.width];
+
+    if mem::size_of::<S>() * 8 < self.bits_per_sample {
+      return Err(ErrorKind::Unsupported);
+    }
+
+    if let Some(ref mut digest) = self.digest {
+      for &sample in &block.samples {
+        format.pack(sample.into(), bytes);
+        digest.update(bytes);
+      }
+    }
+
+    Ok(())
+  }
+
+  fn finish(&mut self) -> Result<(), ErrorKind> {
+    if let Some(digest) = self.digest.take() {
+      self.signature = digest.finish();
+    }
+
+    Ok(())
+  }
+}
+
+/// Returns the largest `DecimateSink` factor that keeps the sample rate at
+/// or above `target_rate`.
+///
+/// # Examples
+///
+/// ```
+/// use flac::sink::decimation_factor;
+///
+/// assert_eq!(decimation_factor(44100, 8000), 5);
+/// assert_eq!(decimation_factor(48000, 8000), 6);
+/// assert_eq!(decimation_factor(8000, 44100), 1);
+/// ```
+pub fn decimation_factor(sample_rate: u32, target_rate: u32) -> usize {
+  cmp::max(sample_rate / cmp::max(target_rate, 1), 1) as usize
+}
+
+/// Sink keeping every `factor`th sample of each channel, for quick low rate
+/// previews without a resampler.
+///
+/// Input samples get split into groups of `factor`, one after the other,
+/// and each group turns into a single output sample. By default that's the
+/// first sample of the group, which aliases anything above the new Nyquist
+/// frequency. `DecimateSink::with_low_pass` averages the whole group
+/// instead, a simple low-pass filter that tames most of the aliasing.
+/// `DecimateSink::with_mono` also mixes the channels down to one.
+///
+/// Blocks written to the inner sink number their samples at the lower
+/// rate, starting from zero.
+///
+/// # Examples
+///
+/// ```no_run
+/// use flac::StreamReader;
+/// use flac::sink::{DecimateSink, Sink, decimation_factor};
+/// use flac::stream::Block;
+///
+/// use std::fs::File;
+///
+/// struct Preview(Vec<i16>);
+///
+/// impl Sink<i16> for Preview {
+///   fn write_block(&mut self, block: &Block<i16>)
+///                  -> Result<(), flac::ErrorKind> {
+///     self.0.extend_from_slice(&block.samples);
+///
+///     Ok(())
+///   }
+/// }
+///
+/// let mut stream = StreamReader::<File>::from_file("path/to/file.flac")
+///                    .unwrap();
+///
+/// // Roughly 8kHz mono.
+/// let factor      = decimation_factor(stream.info().sample_rate.get(), 8000);
+/// let mut preview = DecimateSink::new(Preview(Vec::new()), factor)
+///                     .with_low_pass(true)
+///                     .with_mono(true);
+///
+/// stream.decode_into::<i16, _>(&mut preview).unwrap();
+///
+/// let samples = preview.into_inner().0;
+/// ```
+pub struct DecimateSink<K> {
+  sink: K,
+  factor: usize,
+  is_low_pass: bool,
+  is_mono: bool,
+  // Samples, per channel, of the current group seen so far.
+  phase: usize,
+  // Sum of each channel within the current group, for the low-pass.
+  sums: Vec<i64>,
+  sample_number: u64,
+}
+
+impl<K> DecimateSink<K> {
+  /// Constructs a sink keeping one of every `factor` samples, per channel,
+  /// and writing them to `sink`.
+  ///
+  /// A factor of zero is treated as one, which keeps every sample.
+  pub fn new(sink: K, factor: usize) -> Self {
+    DecimateSink {
+      sink: sink,
+      factor: cmp::max(factor, 1),
+      is_low_pass: false,
+      is_mono: false,
+      phase: 0,
+      sums: Vec::new(),
+      sample_number: 0,
+    }
+  }
+
+  /// Averages each group of samples, rather than keeping the first one.
+  pub fn with_low_pass(mut self, is_low_pass: bool) -> Self {
+    self.is_low_pass = is_low_pass;
+
+    self
+  }
+
+  /// Mixes every channel down to one, by averaging them.
+  pub fn with_mono(mut self, is_mono: bool) -> Self {
+    self.is_mono = is_mono;
+
+    self
+  }
+
+  /// Returns the number of input samples, per channel, behind each output
+  /// sample.
+  #[inline]
+  pub fn factor(&self) -> usize {
+    self.factor
+  }
+
+  /// Returns the sink the decimated blocks get written to.
+  #[inline]
+  pub fn into_inner(self) -> K {
+    self.sink
+  }
+
+  // Adds a single output sample from each channel, or from all of them
+  // mixed together.
+  fn push<S>(&self, values: &[i64], samples: &mut Vec<S>)
+             -> Result<(), ErrorKind>
+   where S: SampleSize,
+         S::Extended: Sample<Normal = S> {
+    if self.is_mono {
+      let sum = values.iter().fold(0, |sum, &value| sum + value);
+
+      samples.push(try!(to_sample(sum / cmp::max(values.len(), 1) as i64)));
+    } else {
+      for &value in values {
+        samples.push(try!(to_sample(value)));
+      }
+    }
+
+    Ok(())
+  }
+
+  // Writes the decimated samples to the inner sink as a single block.
+  fn write_samples<S>(&mut self, samples: Vec<S>, channels: usize)
+                      -> Result<(), ErrorKind>
+   where K: Sink<S> {
+    if samples.is_empty() {
+      return Ok(());
+    }
+
+    let block = Block {
+      sample_number: self.sample_number,
+      channels: channels,
+      samples: samples,
+    };
+
+    self.sample_number += block.len() as u64;
+
+    self.sink.write_block(&block)
+  }
+}
+
+// Turns an average, which always fits back within the sample size it came
+// from, back into a sample.
+fn to_sample<S>(value: i64) -> Result<S, ErrorKind>
+ where S: SampleSize,
+       S::Extended: Sample<Normal = S> {
+  let sample = S::Extended::from_i32_lossy(value as i32);
+
+  S::Extended::to_normal(sample).ok_or(ErrorKind::Unsupported)
+}
+
+impl<K, S> Sink<S> for DecimateSink<K>
+ where K: Sink<S>,
+       S: SampleSize + Copy + Into<i64>,
+       S::Extended: Sample<Normal = S> {
+  fn write_block(&mut self, block: &Block<S>) -> Result<(), ErrorKind> {
+    let channels = cmp::max(block.channels, 1);
+    let outputs  = if self.is_mono { 1 } else { channels };
+
+    // Groups don't carry over between blocks of different channels.
+    if self.sums.len() != channels {
+      self.sums  = vec![0; channels];
+      self.phase = 0;
+    }
+
+    let capacity    = (block.len() / self.factor + 1) * outputs;
+    let mut samples = Vec::with_capacity(capacity);
+    let mut values  = vec![0; channels];
+
+    for frame in block.samples.chunks(channels) {
+      if self.is_low_pass {
+        for (sum, &sample) in self.sums.iter_mut().zip(frame) {
+          *sum += sample.into();
+        }
+      } else if self.phase == 0 {
+        for (value, &sample) in values.iter_mut().zip(frame) {
+          *value = sample.into();
+        }
+
+        try!(self.push(&values, &mut samples));
+      }
+
+      self.phase += 1;
+
+      if self.phase == self.factor {
+        if self.is_low_pass {
+          for (value, sum) in values.iter_mut().zip(&mut self.sums) {
+            *value = *sum / self.factor as i64;
+            *sum   = 0;
+          }
+
+          try!(self.push(&values, &mut samples));
+        }
+
+        self.phase = 0;
+      }
+    }
+
+    self.write_samples(samples, outputs)
+  }
+
+  fn finish(&mut self) -> Result<(), ErrorKind> {
+    // Averages whatever is left of the last group.
+    if self.is_low_pass && self.phase > 0 {
+      let phase       = self.phase as i64;
+      let outputs     = if self.is_mono { 1 } else { self.sums.len() };
+      let values      = self.sums.iter().map(|&sum| sum / phase)
+                                        .collect::<Vec<_>>();
+      let mut samples = Vec::with_capacity(outputs);
+
+      try!(self.push(&values, &mut samples));
+      try!(self.write_samples::<S>(samples, outputs));
+
+      self.phase = 0;
+    }
+
+    self.sink.finish()
+  }
+}
+
+// Samples, around each point between two samples, used to estimate the
+// audio at that point for the true peak.
+const TRUE_PEAK_TAPS: usize = 12;
+
+// Points looked at per sample for the true peak, including the sample.
+const OVERSAMPLING: usize = 4;
+
+// Peaks of a single channel, along with the last few samples of it.
+struct ChannelPeaks {
+  history: [f64; TRUE_PEAK_TAPS],
+  position: usize,
+  sample_peak: f64,
+  true_peak: f64,
+}
+
+impl ChannelPeaks {
+  fn new() -> Self {
+    ChannelPeaks {
+      history: [0.0; TRUE_PEAK_TAPS],
+      position: 0,
+      sample_peak: 0.0,
+      true_peak: 0.0,
+    }
+  }
+
+  // Adds the next sample, checking the points between the two samples in
+  // the middle of the history.
+  fn push(&mut self, sample: f64, filters: &[[f64; TRUE_PEAK_TAPS]]) {
+    let magnitude = sample.abs();
+
+    self.history[self.position] = sample;
+    self.position = (self.position + 1) % TRUE_PEAK_TAPS;
+
+    if magnitude > self.sample_peak {
+      self.sample_peak = magnitude;
+    }
+
+    let mut peak = magnitude;
+
+    for filter in filters {
+      let mut value = 0.0;
+
+      for (i, coefficient) in filter.iter().enumerate() {
+        value += coefficient *
+                 self.history[(self.position + i) % TRUE_PEAK_TAPS];
+      }
+
+      peak = peak.max(value.abs());
+    }
+
+    if peak > self.true_peak {
+      self.true_peak = peak;
+    }
+  }
+}
+
+// Windowed sinc filters for each of the points between two samples, where
+// the two samples are in the middle of `TRUE_PEAK_TAPS` of them.
+fn true_peak_filters() -> Vec<[f64; TRUE_PEAK_TAPS]> {
+  let half = (TRUE_PEAK_TAPS / 2) as f64;
+
+  (1..OVERSAMPLING).map(|phase| {
+    let offset     = phase as f64 / OVERSAMPLING as f64;
+    let mut filter = [0.0; TRUE_PEAK_TAPS];
+
+    for (i, coefficient) in filter.iter_mut().enumerate() {
+      let x      = offset - (i as f64 - (half - 1.0));
+      let sinc   = (f64::consts::PI * x).sin() / (f64::consts::PI * x);
+      let window = 0.5 + 0.5 * (f64::consts::PI * x / half).cos();
+
+      *coefficient = sinc * window;
+    }
+
+    filter
+  }).collect()
+}
+
+/// Sink keeping the peak of each channel, both of the samples themselves
+/// and of the audio between them.
+///
+/// Peaks are relative to full scale for the bits per sample, so a peak of
+/// `1.0` is the loudest a sample can be. The true peak is estimated by
+/// oversampling four times, like ITU-R BS.1770, and catches peaks that only
+/// happen once the audio gets turned back into a signal, which is where
+/// clipping shows up after lossy encoding or resampling. That estimate
+/// lags a few samples behind, so the true peaks aren't final until the
+/// sink is finished.
+///
+/// # Examples
+///
+/// ```no_run
+/// use flac::StreamReader;
+/// use flac::sink::PeakSink;
+///
+/// use std::fs::File;
+///
+/// let mut stream = StreamReader::<File>::from_file("path/to/file.flac")
+///                    .unwrap();
+///
+/// let mut peaks = PeakSink::new(stream.info().bits_per_sample.get());
+///
+/// stream.decode_into::<i32, _>(&mut peaks).unwrap();
+///
+/// for (channel, peak) in peaks.true_peaks().iter().enumerate() {
+///   println!("channel {}: {:.1} dBTP", channel, 20.0 * peak.log10());
+/// }
+/// ```
+pub struct PeakSink {
+  scale: f64,
+  filters: Vec<[f64; TRUE_PEAK_TAPS]>,
+  channels: Vec<ChannelPeaks>,
+  is_finished: bool,
+}
+
+impl PeakSink {
+  /// Constructs a sink for samples of the given bits per sample.
+  pub fn new(bits_per_sample: u8) -> Self {
+    let bits_per_sample = cmp::max(bits_per_sample, 1) as u32;
+
+    PeakSink {
+      scale: 1.0 / (1u64 << (bits_per_sample - 1)) as f64,
+      filters: true_peak_filters(),
+      channels: Vec::new(),
+      is_finished: false,
+    }
+  }
+
+  /// Returns the largest sample, relative to full scale, of each channel.
+  pub fn sample_peaks(&self) -> Vec<f64> {
+    self.channels.iter().map(|channel| channel.sample_peak).collect()
+  }
+
+  /// Returns the estimated true peak, relative to full scale, of each
+  /// channel.
+  ///
+  /// This is never below the sample peak, and only takes in the last few
+  /// samples once the sink is finished.
+  pub fn true_peaks(&self) -> Vec<f64> {
+    self.channels.iter().map(|channel| channel.true_peak).collect()
+  }
+}
+
+impl<S> Sink<S> for PeakSink where S: Copy + Into<i64> {
+  fn write_block(&mut self, block: &Block<S>) -> Result<(), ErrorKind> {
+    let channels = cmp::max(block.channels, 1);
+
+    while self.channels.len() < channels {
+      self.channels.push(ChannelPeaks::new());
+    }
+
+    for frame in block.samples.chunks(channels) {
+      for (channel, &sample) in self.channels.iter_mut().zip(frame) {
+        let sample: i64 = sample.into();
+
+        channel.push(sample as f64 * self.scale, &self.filters);
+      }
+    }
+
+    Ok(())
+  }
+
+  fn finish(&mut self) -> Result<(), ErrorKind> {
+    // Silence after the last sample brings it to the middle of the history.
+    if !self.is_finished {
+      for channel in &mut self.channels {
+        for _ in 0..(TRUE_PEAK_TAPS / 2) {
+          channel.push(0.0, &self.filters);
+        }
+      }
+
+      self.is_finished = true;
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use stream::Block;
+  use utility::ErrorKind;
+
+  // Records every block, failing once it has been given `limit` of them.
+  struct Recorder {
+    blocks: Vec<u64>,
+    limit: usize,
+    is_finished: bool,
+  }
+
+  impl Recorder {
+    fn new(limit: usize) -> Self {
+      Recorder {
+        blocks: Vec::new(),
+        limit: limit,
+        is_finished: false,
+      }
+    }
+  }
+
+  impl Sink<i16> for Recorder {
+    fn write_block(&mut self, block: &Block<i16>) -> Result<(), ErrorKind> {
+      if self.blocks.len() == self.limit {
+        return Err(ErrorKind::Unknown);
+      }
+
+      self.blocks.push(block.sample_number);
+
+      Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), ErrorKind> {
+      self.is_finished = true;
+
+      Ok(())
+    }
+  }
+
+  fn block(sample_number: u64) -> Block<i16> {
+    Block {
+      sample_number: sample_number,
+      channels: 2,
+      samples: vec![1, -1, 256, -256],
+    }
+  }
+
+  #[test]
+  fn test_tee_sink() {
+    let mut first  = Recorder::new(3);
+    let mut second = Recorder::new(1);
+
+    {
+      let mut tee = TeeSink::new();
+
+      assert!(tee.is_empty());
+
+      tee.push(&mut first);
+      tee.push(&mut second);
+
+      assert_eq!(tee.len(), 2);
+      assert_eq!(tee.write_block(&block(0)), Ok(()));
+      assert_eq!(tee.write_block(&block(2)), Err(ErrorKind::Unknown));
+      assert_eq!(tee.finish(), Ok(()));
+    }
+
+    assert_eq!(first.blocks, vec![0, 2]);
+    assert_eq!(second.blocks, vec![0]);
+    assert!(first.is_finished && second.is_finished);
+  }
+
+  // Keeps every byte fed into it as the "signature".
+  struct Bytes(Vec<u8>);
+
+  impl Digest for Bytes {
+    fn update(&mut self, bytes: &[u8]) {
+      self.0.extend_from_slice(bytes);
+    }
+
+    fn finish(self) -> [u8; 16] {
+      let mut signature = [0; 16];
+
+      signature[0..self.0.len()].copy_from_slice(&self.0);
+
+      signature
+    }
+  }
+
+  #[test]
+  fn test_digest_sink() {
+    let mut sink = DigestSink::new(Bytes(Vec::new()), 12);
+
+    assert_eq!(sink.write_block(&block(0)), Ok(()));
+    assert_eq!(sink.signature(), [0; 16]);
+    assert_eq!(Sink::<i16>::finish(&mut sink), Ok(()));
+    assert_eq!(&sink.signature()[0..10],
+               &[1, 0, 0xff, 0xff, 0, 1, 0, 0xff, 0, 0]);
+
+    let mut sink = DigestSink::new(Bytes(Vec::new()), 24);
+
+    assert_eq!(sink.write_block(&block(0)), Err(ErrorKind::Unsupported));
+  }
+
+  // Keeps every block written to it.
+  struct Blocks(Vec<Block<i16>>);
+
+  impl Sink<i16> for Blocks {
+    fn write_block(&mut self, block: &Block<i16>) -> Result<(), ErrorKind> {
+      self.0.push(block.clone());
+
+      Ok(())
+    }
+  }
+
+  // Decimates the stereo samples, split across two blocks, returning each
+  // block that came out.
+  fn decimate(is_low_pass: bool, is_mono: bool) -> Vec<(u64, Vec<i16>)> {
+    let mut sink = DecimateSink::new(Blocks(Vec::new()), 3)
+                     .with_low_pass(is_low_pass)
+                     .with_mono(is_mono);
+
+    let first  = Block {
+      sample_number: 0,
+      channels: 2,
+      samples: vec![0, 10, 3, 13, 6, 16, 9, 19],
+    };
+    let second = Block {
+      sample_number: 4,
+      channels: 2,
+      samples: vec![12, 22, 15, 25, 18, 28, 21, 31],
+    };
+
+    assert_eq!(sink.write_block(&first), Ok(()));
+    assert_eq!(sink.write_block(&second), Ok(()));
+    assert_eq!(Sink::<i16>::finish(&mut sink), Ok(()));
+
+    sink.into_inner().0.into_iter().map(|block| {
+      (block.sample_number, block.samples)
+    }).collect()
+  }
+
+  #[test]
+  fn test_decimate_sink() {
+    assert_eq!(decimate(false, false),
+               vec![(0, vec![0, 10, 9, 19]), (2, vec![18, 28])]);
+    assert_eq!(decimate(false, true),
+               vec![(0, vec![5, 14]), (2, vec![23])]);
+    assert_eq!(decimate(true, false),
+               vec![(0, vec![3, 13]), (1, vec![12, 22]), (2, vec![19, 29])]);
+    assert_eq!(decimate(true, true),
+               vec![(0, vec![8]), (1, vec![17]), (2, vec![24])]);
+
+    assert_eq!(decimation_factor(44100, 0), 44100);
+    assert_eq!(DecimateSink::new(Blocks(Vec::new()), 0).factor(), 1);
+  }
+
+  #[test]
+  fn test_peak_sink() {
+    let mut sink = PeakSink::new(16);
+
+    // A quarter of the sample rate, a half of full scale in height, with
+    // every sample landing halfway between the peaks.
+    let samples = (0..2000).map(|i| {
+      let phase = f64::consts::PI * (i / 2) as f64 / 2.0 +
+                  f64::consts::PI / 4.0;
+      let scale = if i % 2 == 0 { 16384.0 } else { 8192.0 };
+
+      (phase.sin() * scale).round() as i16
+    }).collect::<Vec<_>>();
+
+    for (number, chunk) in samples.chunks(200).enumerate() {
+      let block = Block {
+        sample_number: number as u64 * 100,
+        channels: 2,
+        samples: chunk.to_vec(),
+      };
+
+      assert_eq!(sink.write_block(&block), Ok(()));
+    }
+
+    assert_eq!(Sink::<i16>::finish(&mut sink), Ok(()));
+
+    let sample_peaks = sink.sample_peaks();
+    let true_peaks   = sink.true_peaks();
+
+    assert_eq!(sample_peaks, vec![11585.0 / 32768.0, 5793.0 / 32768.0]);
+    assert!((true_peaks[0] - 0.5).abs() < 0.01, "{:?}", true_peaks);
+    assert!((true_peaks[1] - 0.25).abs() < 0.01, "{:?}", true_peaks);
+  }
+}
@@ -2,219 +2,3579 @@ use metadata;
 use frame;
 use subframe;
 
-use metadata::{Metadata, StreamInfo};
-use frame::frame_parser;
+use metadata::{Metadata, PlaceholderPolicy, SeekPoint, StreamInfo, Type};
+use conceal::{Conceal, LostFrame};
+use crc::update_crc16;
+use digest::Digest;
+use sink::{DigestSink, Sink};
+use pcm::{OutputFormat, OutputPlan, PackedSample, PcmFormat, sample_width};
+use frame::{
+  frame_parser, frame_parser_with_context, header, Footer, Frame,
+};
 use utility::{
-  ErrorKind, ByteStream, ReadStream, Sample, SampleSize, StreamProducer,
-  many_metadata,
+  DecodeContext, ErrorKind, ByteStream, ReadStream, Sample, SampleRate,
+  SampleSize, SeekableProducer, StreamProducer, MAX_COMMENTS, many_blocks,
+  many_metadata, metadata_block, skip_metadata, to_u32,
 };
 
-use std::io;
-use std::usize;
-use std::fs::File;
+use nom::{self, Err, IResult, Needed};
+
+use std::cmp;
+use std::i16;
+use std::io;
+use std::mem;
+use std::usize;
+use std::fs::File;
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::slice;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::VecDeque;
+
+#[cfg(feature = "md5")]
+use digest::Md5;
+
+#[cfg(feature = "cue-sheet")]
+use cue::{self, CueTracks, Pregap};
+
+#[cfg(feature = "metrics")]
+use std::time::{Duration, Instant};
+
+pub use frame::{ChannelAssignment, Header, NumberType};
+
+// Largest block size, in samples, the FLAC subset allows.
+const MAX_SUBSET_BLOCK_SIZE: u16 = 16384;
+
+// Largest block size, in samples, the FLAC subset allows when the sample
+// rate is 48kHz or lower.
+const MAX_SUBSET_BLOCK_SIZE_48KHZ: u16 = 4608;
+
+// Largest length, in bytes, of a frame header, with the longest coded
+// number and the sample rate and block size stored at the end.
+const MAX_FRAME_HEADER_LENGTH: usize = 16;
+
+// Number of the most recently decoded frames `DecodeStats` averages over.
+const STATS_WINDOW: usize = 32;
+
+// FLAC channel taken for each output channel in the WAVE channel order,
+// indexed by the number of channels minus one. FLAC already uses the WAVE
+// order for every channel count.
+const WAVE_ORDER: [&'static [usize]; 8] = [
+  &[0],
+  &[0, 1],
+  &[0, 1, 2],
+  &[0, 1, 2, 3],
+  &[0, 1, 2, 3, 4],
+  &[0, 1, 2, 3, 4, 5],
+  &[0, 1, 2, 3, 4, 5, 6],
+  &[0, 1, 2, 3, 4, 5, 6, 7],
+];
+
+// FLAC channel taken for each output channel in the CoreAudio channel
+// order, indexed by the number of channels minus one. The layouts are
+// `MPEG_3_0_A`, `Quadraphonic`, `MPEG_5_0_A`, `MPEG_5_1_A`, `MPEG_6_1_A`,
+// and `MPEG_7_1_C` for three up to eight channels, where the back center of
+// 6.1 and the side channels of 7.1 get moved.
+const CORE_AUDIO_ORDER: [&'static [usize]; 8] = [
+  &[0],
+  &[0, 1],
+  &[0, 1, 2],
+  &[0, 1, 2, 3],
+  &[0, 1, 2, 3, 4],
+  &[0, 1, 2, 3, 4, 5],
+  &[0, 1, 2, 3, 5, 6, 4],
+  &[0, 1, 2, 3, 6, 7, 4, 5],
+];
+
+/// Order of the channels within each sample that gets decoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChannelOrder {
+  /// The order channels are stored in within FLAC.
+  Flac,
+  /// The order of the channel mask within WAVE files.
+  Wave,
+  /// The order of the standard CoreAudio layouts used by CAF files.
+  CoreAudio,
+}
+
+impl ChannelOrder {
+  /// Returns which FLAC channel ends up at each position of the output for
+  /// the given number of channels.
+  ///
+  /// Channel counts outside of one up to eight are left in the FLAC order,
+  /// which gets returned as an empty slice.
+  pub fn mapping(&self, channels: usize) -> &'static [usize] {
+    if channels == 0 || channels > WAVE_ORDER.len() {
+      return &[];
+    }
+
+    match *self {
+      ChannelOrder::Flac      => WAVE_ORDER[channels - 1],
+      ChannelOrder::Wave      => WAVE_ORDER[channels - 1],
+      ChannelOrder::CoreAudio => CORE_AUDIO_ORDER[channels - 1],
+    }
+  }
+}
+
+impl Default for ChannelOrder {
+  #[inline]
+  fn default() -> Self {
+    ChannelOrder::Flac
+  }
+}
+
+/// Options for constraining what a FLAC stream is allowed to decode and
+/// how the samples are handed out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StreamOptions {
+  /// Allow block sizes larger than what the FLAC subset permits, which is
+  /// 16384 samples and 4608 samples for sample rates of 48kHz or lower.
+  ///
+  /// The decode buffer gets allocated from the maximum block size, so this
+  /// is disabled by default to keep unexpectedly large allocations from
+  /// happening.
+  pub allow_large_block_size: bool,
+  /// Order of the channels within each sample returned from `Stream::iter`.
+  ///
+  /// Downstream containers like WAVE and CAF expect surround channels in a
+  /// specific order, which isn't always the same as FLAC's.
+  pub channel_order: ChannelOrder,
+  /// Largest number of comments kept from a `VorbisComment` block, which
+  /// defaults to 65536.
+  ///
+  /// Every comment still gets parsed, but the ones past this are dropped,
+  /// since a block can declare millions of them.
+  pub max_comments: usize,
+  /// What happens to the placeholder points of the `SeekTable` block,
+  /// which are all kept by default.
+  ///
+  /// Dropping them saves holding onto thousands of points in tables that
+  /// reserve room for more, and seeking never uses them either way.
+  pub placeholder_policy: PlaceholderPolicy,
+  /// Parse only `StreamInfo` when opening the stream, seeking over the rest
+  /// of the metadata blocks until `Stream::load_metadata` gets called.
+  ///
+  /// This cuts the time to the first audio frame for playback, where tags
+  /// are shown later or never. The producer needs to be able to seek, like
+  /// the ones behind `Stream::from_file` and `Stream::from_buffer`,
+  /// otherwise opening the stream fails with `ErrorKind::Unsupported`.
+  pub is_metadata_deferred: bool,
+  /// Most heap memory, in bytes, the metadata blocks of the stream are
+  /// allowed to take up, going by `Metadata::heap_size`. `None`, the
+  /// default, leaves them uncapped.
+  ///
+  /// Past the cap, every block first lets go of its original bytes from
+  /// `Metadata::raw`, which all share one copy of the metadata. Then the
+  /// largest blocks get spilled with `Metadata::spill`, which drops
+  /// payloads like the data of pictures, until the rest fit or there is
+  /// nothing left to spill. `Stream::load_block` reads a spilled
+  /// block back on demand, which keeps a server holding many streams open
+  /// from holding every picture within them too.
+  pub max_metadata_size: Option<usize>,
+  /// Limits bounding how much work decoding a single frame takes, for
+  /// real-time use, see `RealtimeProfile`. `None`, the default, leaves
+  /// frames as large as the stream makes them.
+  pub realtime: Option<RealtimeProfile>,
+}
+
+impl StreamOptions {
+  /// Returns the `DecodeContext` the frame and metadata parsers get passed
+  /// with these options.
+  pub fn context(&self) -> DecodeContext {
+    DecodeContext::new().with_max_comments(self.max_comments)
+                        .with_placeholder_policy(self.placeholder_policy)
+  }
+}
+
+impl Default for StreamOptions {
+  fn default() -> Self {
+    StreamOptions {
+      allow_large_block_size: false,
+      channel_order: Default::default(),
+      max_comments: MAX_COMMENTS,
+      placeholder_policy: PlaceholderPolicy::Keep,
+      is_metadata_deferred: false,
+      max_metadata_size: None,
+      realtime: None,
+    }
+  }
+}
+
+/// Limits on a stream that put a bound on the work of decoding any single
+/// frame, for real-time monitoring that needs a frame decoded within a
+/// fixed amount of time.
+///
+/// A stream with larger blocks, more channels, or more metadata than the
+/// profile allows gets turned away with `ErrorKind::Unsupported` when
+/// moving on to its audio frames. Each frame then gets parsed from at most
+/// `RealtimeProfile::max_frame_bytes` bytes, which is as large as a frame
+/// gets with every sample stored uncoded, and a frame needing more fails
+/// with `ErrorKind::Unsupported` rather than being read further. Since a
+/// sample takes at most 32 multiply-adds to restore, the highest order of
+/// LPC, the work of a frame is bounded by its bytes and by
+/// `max_block_size` times `max_channels` samples, no matter what the data
+/// within the frame claims.
+///
+/// # Examples
+///
+/// ```
+/// use flac::{ErrorKind, StreamOptions, StreamReader};
+/// use flac::stream::RealtimeProfile;
+///
+/// use std::fs::File;
+///
+/// let options = StreamOptions {
+///   realtime: Some(RealtimeProfile {
+///     max_block_size: 1024,
+///     ..Default::default()
+///   }),
+///   ..Default::default()
+/// };
+///
+/// // Blocks of 4096 samples are more than the profile allows.
+/// let result = StreamReader::<File>::from_file_with_options(
+///                "tests/assets/input-SVAUP.flac", options);
+///
+/// assert_eq!(result.err(), Some(ErrorKind::Unsupported));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RealtimeProfile {
+  /// Largest maximum block size accepted, in samples per channel, which
+  /// defaults to 4608, the largest the FLAC subset allows at 48kHz.
+  pub max_block_size: u16,
+  /// Largest number of channels accepted, which defaults to 8, allowing
+  /// every stream.
+  pub max_channels: u8,
+  /// Most bytes all of the metadata blocks are allowed to take up within
+  /// the stream, which defaults to 64KiB, leaving out streams with large
+  /// pictures embedded.
+  pub max_metadata_length: u64,
+}
+
+impl RealtimeProfile {
+  /// Returns the most bytes a frame of the stream gets parsed from, which
+  /// is the size of its largest frame with every sample stored uncoded,
+  /// with an extra bit for a side channel.
+  pub fn max_frame_bytes(&self, info: &StreamInfo) -> usize {
+    let block_size = cmp::min(info.max_block_size, self.max_block_size);
+    let channels   = info.channels as usize;
+    let bits       = block_size as usize * channels *
+                     (info.bits_per_sample.get() as usize + 1);
+
+    // Each subframe header can spend up to four more bytes on the number
+    // of wasted bits.
+    MAX_FRAME_HEADER_LENGTH + channels * 5 + (bits + 7) / 8 + 2
+  }
+
+  // Turns away streams past any of the limits.
+  fn check(&self, info: &StreamInfo, audio_offset: u64)
+           -> Result<(), ErrorKind> {
+    let metadata_length = audio_offset.saturating_sub(4);
+
+    if info.max_block_size > self.max_block_size ||
+       info.channels > self.max_channels ||
+       metadata_length > self.max_metadata_length {
+      Err(ErrorKind::Unsupported)
+    } else {
+      Ok(())
+    }
+  }
+}
+
+impl Default for RealtimeProfile {
+  fn default() -> Self {
+    RealtimeProfile {
+      max_block_size: MAX_SUBSET_BLOCK_SIZE_48KHZ,
+      max_channels: 8,
+      max_metadata_length: 64 * 1024,
+    }
+  }
+}
+
+/// A break in the numbering of two consecutive frames.
+///
+/// Each frame is expected to start on the sample right after the last
+/// sample of the frame before it. Breaks like these are usually caused by
+/// badly concatenated or truncated files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Discontinuity {
+  /// Index of the frame, counting from zero, that breaks the numbering.
+  pub frame_index: u64,
+  /// Sample number the frame was expected to start at.
+  pub expected: u64,
+  /// Sample number the frame actually starts at.
+  pub found: u64,
+}
+
+/// Location and checksum of a single frame within the stream.
+///
+/// The frame has already been checked against its CRC-16, so the checksum
+/// matches the bytes within the range.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FrameRange {
+  /// Byte offsets the frame starts and ends at, counting from the start of
+  /// the stream.
+  pub range: Range<u64>,
+  /// CRC-16 stored in the footer of the frame.
+  pub crc: u16,
+}
+
+/// Where the header and each subframe sit within a single frame, for tools
+/// that extract or patch individual subframes.
+///
+/// Subframes aren't byte aligned, so their ranges are in bits, counting
+/// from the first bit of the frame. The ranges follow each other in
+/// channel order, the first starting right after the header. Padding up to
+/// the next byte and the CRC-16 come after the last one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FrameLayout {
+  /// Length of the frame header in bytes.
+  pub header_length: usize,
+  /// Bit range of each subframe, including its own header and wasted bits.
+  pub subframes: Vec<Range<usize>>,
+  /// Length of the whole frame in bytes.
+  pub length: usize,
+}
+
+impl Discontinuity {
+  /// Returns true when samples are missing before the frame.
+  #[inline]
+  pub fn is_gap(&self) -> bool {
+    self.found > self.expected
+  }
+
+  /// Returns true when the frame starts on samples that were already
+  /// covered by a previous frame.
+  #[inline]
+  pub fn is_overlap(&self) -> bool {
+    self.found < self.expected
+  }
+}
+
+/// Number of bytes taken up by each part of a FLAC stream.
+///
+/// Metadata blocks are counted along with their four byte block headers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MetadataSizeReport {
+  /// The "fLaC" marker at the start of the stream.
+  pub marker: u64,
+  /// The `StreamInfo` block.
+  pub stream_info: u64,
+  /// Every `Padding` block.
+  pub padding: u64,
+  /// Every `Application` block.
+  pub application: u64,
+  /// Every `SeekTable` block.
+  pub seek_table: u64,
+  /// Every `VorbisComment` block.
+  pub vorbis_comment: u64,
+  /// Every `CueSheet` block.
+  pub cue_sheet: u64,
+  /// Every `Picture` block.
+  pub picture: u64,
+  /// Every block of a type that isn't known.
+  pub unknown: u64,
+  /// Audio frames, from the start of the first frame to the end of the
+  /// last.
+  pub audio: u64,
+}
+
+impl MetadataSizeReport {
+  /// Returns the bytes taken up by the marker and every metadata block.
+  pub fn metadata(&self) -> u64 {
+    self.marker + self.stream_info + self.padding + self.application +
+    self.seek_table + self.vorbis_comment + self.cue_sheet + self.picture +
+    self.unknown
+  }
+
+  /// Returns the bytes taken up by the whole stream.
+  #[inline]
+  pub fn total(&self) -> u64 {
+    self.metadata() + self.audio
+  }
+}
+
+/// Returns the length, in bytes, of the frame that starts at the beginning
+/// of `input`, without decoding any of its samples.
+///
+/// Only the frame header and the sizes of each subframe get parsed, so this
+/// is much cheaper than decoding when all that's needed is where the next
+/// frame starts. The CRC-8 of the frame header gets checked, but the CRC-16
+/// of the whole frame doesn't. When `input` ends before the frame does,
+/// `ErrorKind::Incomplete` holds at least the number of bytes needed.
+///
+/// # Examples
+///
+/// ```
+/// use flac::StreamBuffer;
+/// use flac::stream::frame_length;
+///
+/// use std::fs::File;
+/// use std::io::Read;
+///
+/// let mut bytes = Vec::new();
+/// let mut file  = File::open("tests/assets/input-SVAUP.flac").unwrap();
+///
+/// file.read_to_end(&mut bytes).unwrap();
+///
+/// let mut stream = StreamBuffer::from_buffer(&bytes).unwrap();
+/// let range      = stream.frame_ranges().next().unwrap().range;
+/// let frame      = &bytes[(range.start as usize)..];
+///
+/// assert_eq!(frame_length(frame, &stream.info()).unwrap() as u64,
+///            range.end - range.start);
+/// ```
+pub fn frame_length(input: &[u8], stream_info: &StreamInfo)
+                    -> Result<usize, ErrorKind> {
+  ByteStream::new(input).parse(|i| frame::frame_length(i, stream_info))
+}
+
+/// Parses the header of the frame that starts at the beginning of `input`,
+/// returning it along with its length in bytes.
+///
+/// Nothing besides the bytes and `StreamInfo` is needed, so tools that
+/// scan arbitrary data for frames, like ones recovering audio from a damaged
+/// disk, can check each sync code they come across. The header gets
+/// validated the same way it does while decoding, with
+/// `ErrorKind::InvalidCRC8` returned when its CRC-8 doesn't match.
+///
+/// # Examples
+///
+/// ```
+/// use flac::StreamBuffer;
+/// use flac::stream::parse_frame_header;
+///
+/// use std::fs::File;
+/// use std::io::Read;
+///
+/// let mut bytes = Vec::new();
+/// let mut file  = File::open("tests/assets/input-SVAUP.flac").unwrap();
+///
+/// file.read_to_end(&mut bytes).unwrap();
+///
+/// let stream = StreamBuffer::from_buffer(&bytes).unwrap();
+/// let info   = stream.info();
+///
+/// // Counts every place a valid frame header starts at.
+/// let frames = (0..(bytes.len() - 1)).filter(|&offset| {
+///   bytes[offset] == 0xff && (bytes[offset + 1] & 0xfe) == 0xf8 &&
+///   parse_frame_header(&bytes[offset..], &info).is_ok()
+/// }).count();
+///
+/// assert!(frames > 0);
+/// ```
+pub fn parse_frame_header(input: &[u8], stream_info: &StreamInfo)
+                          -> Result<(Header, usize), ErrorKind> {
+  ByteStream::new(input).parse(|i| {
+    match header(i, stream_info) {
+      IResult::Done(rest, header) => {
+        let length = i.len() - rest.len();
+
+        IResult::Done(rest, (header, length))
+      }
+      IResult::Error(error)       => IResult::Error(error),
+      IResult::Incomplete(need)   => IResult::Incomplete(need),
+    }
+  })
+}
+
+/// Returns the layout of the frame that starts at the beginning of `input`,
+/// without decoding any of its samples.
+///
+/// The bits of each subframe get stepped over in the same way as
+/// `frame_length`, which fails in the same cases.
+///
+/// # Examples
+///
+/// ```
+/// use flac::StreamBuffer;
+/// use flac::stream::frame_layout;
+///
+/// use std::fs::File;
+/// use std::io::Read;
+///
+/// let mut bytes = Vec::new();
+/// let mut file  = File::open("tests/assets/input-SVAUP.flac").unwrap();
+///
+/// file.read_to_end(&mut bytes).unwrap();
+///
+/// let mut stream = StreamBuffer::from_buffer(&bytes).unwrap();
+/// let range      = stream.frame_ranges().next().unwrap().range;
+/// let frame      = &bytes[(range.start as usize)..];
+/// let layout     = frame_layout(frame, &stream.info()).unwrap();
+///
+/// assert_eq!(layout.subframes.len(), stream.info().channels as usize);
+/// assert_eq!(layout.subframes[0].start, layout.header_length * 8);
+/// ```
+pub fn frame_layout(input: &[u8], stream_info: &StreamInfo)
+                    -> Result<FrameLayout, ErrorKind> {
+  let result = ByteStream::new(input).parse(|i| {
+    frame::frame_layout(i, stream_info)
+  });
+
+  result.map(|(header_length, subframes, length)| {
+    FrameLayout {
+      header_length: header_length,
+      subframes: subframes,
+      length: length,
+    }
+  })
+}
+
+// Size and, with the `metrics` feature, decode time of a single frame.
+#[derive(Clone, Copy, Debug)]
+struct FrameStat {
+  bytes: usize,
+  samples: usize,
+  #[cfg(feature = "metrics")]
+  time: Duration,
+}
+
+/// Rolling statistics on the frames a stream has decoded.
+///
+/// Averages only cover the most recent frames, so they follow changes in
+/// bitrate over the stream, which makes them useful for sizing how far
+/// ahead a streaming client needs to fetch. Decode times are only measured
+/// with the `metrics` feature enabled.
+#[derive(Clone, Debug)]
+pub struct DecodeStats {
+  frames: u64,
+  bytes: u64,
+  sample_rate: SampleRate,
+  // Frame sizes within `StreamInfo`, where zero means it's unknown.
+  min_frame_size: u32,
+  max_frame_size: u32,
+  frame_size_mismatches: u64,
+  recent: VecDeque<FrameStat>,
+}
+
+impl DecodeStats {
+  fn new(info: &StreamInfo) -> Self {
+    DecodeStats {
+      frames: 0,
+      bytes: 0,
+      sample_rate: info.sample_rate,
+      min_frame_size: info.min_frame_size,
+      max_frame_size: info.max_frame_size,
+      frame_size_mismatches: 0,
+      recent: VecDeque::with_capacity(STATS_WINDOW),
+    }
+  }
+
+  fn push(&mut self, stat: FrameStat) {
+    if self.recent.len() == STATS_WINDOW {
+      self.recent.pop_front();
+    }
+
+    let bytes = stat.bytes as u32;
+
+    if (self.min_frame_size > 0 && bytes < self.min_frame_size) ||
+       (self.max_frame_size > 0 && bytes > self.max_frame_size) {
+      self.frame_size_mismatches += 1;
+    }
+
+    self.frames += 1;
+    self.bytes  += stat.bytes as u64;
+
+    self.recent.push_back(stat);
+  }
+
+  /// Returns the number of frames decoded so far.
+  #[inline]
+  pub fn frames(&self) -> u64 {
+    self.frames
+  }
+
+  /// Returns the number of encoded bytes within the frames decoded so far.
+  #[inline]
+  pub fn bytes(&self) -> u64 {
+    self.bytes
+  }
+
+  /// Returns the number of frames decoded so far with a size outside of
+  /// the minimum and maximum frame sizes within `StreamInfo`.
+  ///
+  /// Those sizes are only hints, so frames outside of them still decode.
+  /// Anything other than zero is a warning that the sizes are wrong, which
+  /// `metadata::recompute_frame_sizes` fixes.
+  #[inline]
+  pub fn frame_size_mismatches(&self) -> u64 {
+    self.frame_size_mismatches
+  }
+
+  /// Returns the average size, in bytes, of the recently decoded frames.
+  ///
+  /// `None` is returned when no frame has been decoded yet.
+  pub fn average_frame_size(&self) -> Option<f64> {
+    let bytes = self.recent.iter().fold(0, |total, stat| total + stat.bytes);
+
+    self.average(bytes as f64)
+  }
+
+  /// Returns the average number of samples, per channel, within the
+  /// recently decoded frames.
+  ///
+  /// `None` is returned when no frame has been decoded yet.
+  pub fn average_block_size(&self) -> Option<f64> {
+    let samples = self.recent.iter().fold(0, |total, stat| {
+      total + stat.samples
+    });
+
+    self.average(samples as f64)
+  }
+
+  /// Returns the number of encoded bytes needed for each second of audio,
+  /// going by the recently decoded frames.
+  ///
+  /// `None` is returned when no frame has been decoded yet or the sample
+  /// rate is unknown.
+  pub fn bytes_per_second(&self) -> Option<f64> {
+    let (bytes, samples) = self.recent.iter().fold((0, 0), |total, stat| {
+      (total.0 + stat.bytes, total.1 + stat.samples)
+    });
+
+    if samples == 0 {
+      None
+    } else {
+      Some(bytes as f64 * self.sample_rate.get() as f64 / samples as f64)
+    }
+  }
+
+  /// Returns the average time it took to parse and decode each of the
+  /// recently decoded frames.
+  ///
+  /// `None` is returned when no frame has been decoded yet.
+  #[cfg(feature = "metrics")]
+  pub fn average_decode_time(&self) -> Option<Duration> {
+    let time = self.recent.iter().fold(Duration::new(0, 0), |total, stat| {
+      total + stat.time
+    });
+
+    if self.recent.is_empty() {
+      None
+    } else {
+      Some(time / self.recent.len() as u32)
+    }
+  }
+
+  /// Returns the number of frames that get decoded each second, going by
+  /// the recently decoded frames.
+  ///
+  /// `None` is returned when no frame has been decoded yet or the frames
+  /// took no measurable time.
+  #[cfg(feature = "metrics")]
+  pub fn frames_per_second(&self) -> Option<f64> {
+    self.average_decode_time().and_then(|time| {
+      let seconds = time.as_secs() as f64 +
+                    time.subsec_nanos() as f64 / 1_000_000_000.0;
+
+      if seconds > 0.0 {
+        Some(1.0 / seconds)
+      } else {
+        None
+      }
+    })
+  }
+
+  // Divides the total by the number of recent frames.
+  fn average(&self, total: f64) -> Option<f64> {
+    if self.recent.is_empty() {
+      None
+    } else {
+      Some(total / self.recent.len() as f64)
+    }
+  }
+}
+
+/// How far decoding got, meant for finding out how much of a damaged
+/// stream is recoverable.
+///
+/// Only frames decoded into samples, or concealed in place of ones that
+/// failed to decode, count toward the progress, which starts over whenever
+/// the stream is rewound or seeks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DecodeProgress {
+  /// Number of frames decoded successfully.
+  pub frames: u64,
+  /// Sample number right after the last sample decoded successfully.
+  pub samples: u64,
+  /// Offset, in bytes, from the beginning of the stream to right after the
+  /// last frame decoded successfully.
+  pub byte_offset: u64,
+  /// Total samples from `StreamInfo`, where zero means it's unknown.
+  pub total_samples: u64,
+  /// Number of frames that failed to decode and were replaced by the
+  /// concealer from `Stream::set_concealer`.
+  pub concealed: u64,
+  /// Error that stopped decoding, which is `None` while decoding is still
+  /// going or once the stream has ended cleanly.
+  pub error: Option<ErrorKind>,
+}
+
+impl DecodeProgress {
+  /// Returns the fraction of the total samples that were decoded, from zero
+  /// to one.
+  ///
+  /// `None` is returned when the total samples are unknown.
+  pub fn fraction(&self) -> Option<f64> {
+    if self.total_samples == 0 {
+      None
+    } else {
+      let samples = cmp::min(self.samples, self.total_samples);
+
+      Some(samples as f64 / self.total_samples as f64)
+    }
+  }
+}
+
+/// Shared flag for stopping a decode from another thread.
+///
+/// The flag is checked before each frame, so decoding stops within a frame
+/// of the handle being cancelled, with `ErrorKind::Cancelled` as the error.
+/// Clones share the same flag, which lets a single handle cancel any number
+/// of streams, see `Stream::set_cancel_handle`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use flac::StreamReader;
+/// use std::fs::File;
+/// use std::thread;
+///
+/// let mut stream = StreamReader::<File>::from_file("path/to/file.flac")
+///                    .unwrap();
+/// let handle     = stream.cancel_handle();
+///
+/// thread::spawn(move || handle.cancel());
+///
+/// let samples = stream.iter::<i16>().collect::<Vec<_>>();
+///
+/// if stream.decode_progress().error == Some(flac::ErrorKind::Cancelled) {
+///   println!("cancelled after {} samples", samples.len());
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CancelHandle {
+  cancelled: Arc<AtomicBool>,
+}
+
+impl CancelHandle {
+  /// Constructs a handle that isn't cancelled.
+  #[inline]
+  pub fn new() -> Self {
+    Default::default()
+  }
+
+  /// Stops decoding of every stream sharing the handle, before their next
+  /// frame.
+  #[inline]
+  pub fn cancel(&self) {
+    self.cancelled.store(true, Ordering::SeqCst);
+  }
+
+  /// Returns true when the handle has been cancelled.
+  #[inline]
+  pub fn is_cancelled(&self) -> bool {
+    self.cancelled.load(Ordering::SeqCst)
+  }
+
+  /// Lets decoding go on again, for reusing the handle after a cancel.
+  #[inline]
+  pub fn reset(&self) {
+    self.cancelled.store(false, Ordering::SeqCst);
+  }
+}
+
+/// Entry point for reading a FLAC stream one phase at a time.
+///
+/// Each constructor parses only the metadata, handing back a
+/// `MetadataPhase`. Audio can't be decoded until `MetadataPhase::into_audio`
+/// turns it into a `Stream`, so tools that only look at metadata never set
+/// up anything for decoding. The constructors on `Stream` go through both
+/// phases at once.
+///
+/// # Examples
+///
+/// ```no_run
+/// use flac::FlacReader;
+///
+/// let reader = FlacReader::from_file("path/to/file.flac").unwrap();
+///
+/// println!("{} metadata blocks", reader.metadata().len());
+///
+/// let mut stream = reader.into_audio().unwrap();
+/// let samples    = stream.iter::<i16>().collect::<Vec<_>>();
+/// ```
+pub struct FlacReader;
+
+impl FlacReader {
+  /// Parses the metadata of a FLAC stream read from `reader`.
+  ///
+  /// # Failures
+  ///
+  /// * `ErrorKind::IO(io::ErrorKind::InvalidData)` is returned when the data
+  ///   isn't valid FLAC data.
+  /// * Several different parser specific errors that are structured as
+  ///   `ErrorKind::<parser_name>Parser`.
+  #[inline]
+  pub fn new<R>(reader: R) -> Result<MetadataPhase<ReadStream<R>>, ErrorKind>
+   where R: io::Read {
+    FlacReader::with_options(reader, Default::default())
+  }
+
+  /// Parses the metadata of a FLAC stream read from `reader` with the given
+  /// options.
+  #[inline]
+  pub fn with_options<R>(reader: R, options: StreamOptions)
+                         -> Result<MetadataPhase<ReadStream<R>>, ErrorKind>
+   where R: io::Read {
+    MetadataPhase::from_stream_producer(ReadStream::new(reader), options)
+  }
+
+  /// Parses the metadata of the FLAC file with the given file name.
+  ///
+  /// Fails in the same way as `FlacReader::new` does, along with
+  /// `ErrorKind::IO(io::ErrorKind::NotFound)` when the given filename isn't
+  /// found.
+  #[inline]
+  pub fn from_file(filename: &str)
+                   -> Result<MetadataPhase<ReadStream<File>>, ErrorKind> {
+    FlacReader::from_file_with_options(filename, Default::default())
+  }
+
+  /// Parses the metadata of the FLAC file with the given file name and
+  /// options.
+  pub fn from_file_with_options(filename: &str, options: StreamOptions)
+                                -> Result<MetadataPhase<ReadStream<File>>,
+                                          ErrorKind> {
+    File::open(filename).map_err(|e| ErrorKind::IO(e.kind()))
+                        .and_then(|file| {
+      let producer = ReadStream::seekable(file);
+
+      MetadataPhase::from_stream_producer(producer, options)
+    })
+  }
+
+  /// Parses the metadata of a FLAC stream held entirely within `buffer`.
+  ///
+  /// Every metadata block also keeps a copy of its original bytes, which is
+  /// available through `Metadata::raw`.
+  #[inline]
+  pub fn from_buffer(buffer: &[u8])
+                     -> Result<MetadataPhase<ByteStream>, ErrorKind> {
+    FlacReader::from_buffer_with_options(buffer, Default::default())
+  }
+
+  /// Parses the metadata of a FLAC stream held entirely within `buffer`
+  /// with the given options.
+  pub fn from_buffer_with_options(buffer: &[u8], options: StreamOptions)
+                                  -> Result<MetadataPhase<ByteStream>,
+                                            ErrorKind> {
+    let producer = ByteStream::new(buffer);
+
+    MetadataPhase::from_stream_producer(producer, options).map(|mut phase| {
+      attach_raw(buffer, phase.audio_offset, &mut phase.metadata);
+
+      cap_metadata(&mut phase.metadata, phase.options.max_metadata_size);
+
+      phase
+    })
+  }
+}
+
+/// FLAC stream with its metadata parsed, positioned at the first audio
+/// frame.
+pub struct MetadataPhase<P: StreamProducer> {
+  info: StreamInfo,
+  metadata: Vec<Metadata>,
+  audio_offset: u64,
+  // Offset of the metadata blocks after `StreamInfo` when their parsing
+  // got deferred.
+  deferred: Option<u64>,
+  options: StreamOptions,
+  producer: P,
+}
+
+impl<P> MetadataPhase<P> where P: StreamProducer {
+  fn from_stream_producer(mut producer: P, options: StreamOptions)
+                          -> Result<Self, ErrorKind> {
+    let mut stream_info = Default::default();
+    let mut metadata    = Vec::new();
+    let mut deferred    = None;
+
+    let audio_offset = if options.is_metadata_deferred {
+      let (block, start, end) = try!(skip_metadata(&mut producer,
+                                                   &options.context()));
+
+      if let metadata::Data::StreamInfo(info) = block.data {
+        stream_info = info;
+      }
+
+      if start < end {
+        deferred = Some(start);
+      }
+
+      end
+    } else {
+      let add_block = |block: Metadata| {
+        if let metadata::Data::StreamInfo(info) = block.data {
+          stream_info = info;
+        } else {
+          metadata.push(block);
+        }
+      };
+
+      try!(many_metadata(&mut producer, &options.context(), add_block))
+    };
+
+    cap_metadata(&mut metadata, options.max_metadata_size);
+
+    Ok(MetadataPhase {
+      info: stream_info,
+      metadata: metadata,
+      audio_offset: audio_offset,
+      deferred: deferred,
+      options: options,
+      producer: producer,
+    })
+  }
+
+  /// Returns information for the stream.
+  #[inline]
+  pub fn info(&self) -> StreamInfo {
+    self.info
+  }
+
+  /// Returns every metadata block other than `StreamInfo`, which is in
+  /// `MetadataPhase::info`.
+  ///
+  /// Blocks are kept in the order they were stored in, so the block at
+  /// index `i` was block `i + 1` of the stream.
+  #[inline]
+  pub fn metadata(&self) -> &[Metadata] {
+    &self.metadata
+  }
+
+  /// Returns an iterator over the metadata blocks of the given type, along
+  /// with the position each was stored at, see `Stream::metadata_of_type`.
+  #[inline]
+  pub fn metadata_of_type(&self, block_type: Type) -> MetadataOfType {
+    MetadataOfType::new(&self.metadata, block_type)
+  }
+
+  /// Returns the offset, in bytes, from the beginning of the stream to the
+  /// first audio frame.
+  #[inline]
+  pub fn audio_offset(&self) -> u64 {
+    self.audio_offset
+  }
+
+  /// Returns roughly how many bytes of heap memory the metadata blocks
+  /// take up, see `Metadata::heap_size`.
+  #[inline]
+  pub fn metadata_heap_size(&self) -> usize {
+    metadata_heap_size(&self.metadata)
+  }
+
+  /// Returns `StreamInfo` and the rest of the metadata blocks, leaving the
+  /// audio frames unread.
+  #[inline]
+  pub fn into_metadata(self) -> (StreamInfo, Vec<Metadata>) {
+    (self.info, self.metadata)
+  }
+
+  /// Moves on to decoding the audio frames.
+  ///
+  /// # Failures
+  ///
+  /// * `ErrorKind::Unsupported` is returned when the stream uses block
+  ///   sizes larger than the FLAC subset allows, unless allowed by the
+  ///   options the metadata was parsed with, or when the stream is past
+  ///   the limits of their `RealtimeProfile`.
+  pub fn into_audio(self) -> Result<Stream<P>, ErrorKind> {
+    let info    = self.info;
+    let options = self.options;
+
+    if !options.allow_large_block_size && !is_subset_block_size(&info) {
+      return Err(ErrorKind::Unsupported);
+    }
+
+    if let Some(ref realtime) = options.realtime {
+      try!(realtime.check(&info, self.audio_offset));
+    }
+
+    Ok(Stream {
+      info: info,
+      metadata: self.metadata,
+      audio_offset: self.audio_offset,
+      deferred: self.deferred,
+      max_metadata_size: options.max_metadata_size,
+      channel_order: options.channel_order,
+      context: options.context(),
+      frame_limit: options.realtime.map(|realtime| {
+        realtime.max_frame_bytes(&info)
+      }),
+      stats: DecodeStats::new(&info),
+      progress: DecodeProgress {
+        byte_offset: self.audio_offset,
+        total_samples: info.total_samples,
+        ..Default::default()
+      },
+      concealer: None,
+      previous: Vec::new(),
+      frame_buffer: Vec::new(),
+      cancel: CancelHandle::new(),
+      producer: self.producer,
+    })
+  }
+}
+
+/// FLAC stream that decodes and hold file information.
+pub struct Stream<P: StreamProducer> {
+  info: StreamInfo,
+  metadata: Vec<Metadata>,
+  audio_offset: u64,
+  deferred: Option<u64>,
+  max_metadata_size: Option<usize>,
+  channel_order: ChannelOrder,
+  context: DecodeContext,
+  // Most bytes a frame gets parsed from, set by `RealtimeProfile`.
+  frame_limit: Option<usize>,
+  stats: DecodeStats,
+  progress: DecodeProgress,
+  concealer: Option<Box<Conceal + Send>>,
+  previous: Vec<i32>,
+  frame_buffer: Vec<i32>,
+  cancel: CancelHandle,
+  producer: P,
+}
+
+/// Alias for a FLAC stream produced from `Read`.
+pub type StreamReader<R>  = Stream<ReadStream<R>>;
+
+/// Alias for a FLAC stream produced from a byte stream buffer.
+pub type StreamBuffer<'a> = Stream<ByteStream<'a>>;
+
+impl<P> Stream<P> where P: StreamProducer {
+  /// Constructor for the default state of a FLAC stream.
+  #[inline]
+  pub fn new<R: io::Read>(reader: R) -> Result<StreamReader<R>, ErrorKind> {
+    Stream::<ReadStream<R>>::with_options(reader, Default::default())
+  }
+
+  /// Constructor for a FLAC stream with the given options.
+  #[inline]
+  pub fn with_options<R>(reader: R, options: StreamOptions)
+                         -> Result<StreamReader<R>, ErrorKind>
+   where R: io::Read {
+    let producer = ReadStream::new(reader);
+
+    Stream::from_stream_producer(producer, options)
+  }
+
+  /// Returns information for the current stream.
+  #[inline]
+  pub fn info(&self) -> StreamInfo {
+    self.info
+  }
+
+  /// Picks how to convert the decoded audio for a device that plays any
+  /// of the `supported` formats.
+  ///
+  /// See `OutputPlan::negotiate` for how the plan gets picked. `None` is
+  /// returned when none of the formats can be reached without a resampler.
+  ///
+  /// # Examples
+  ///
+  /// ```no_run
+  /// use flac::StreamReader;
+  /// use flac::pcm::OutputFormat;
+  /// use flac::sink::Sink;
+  /// use flac::stream::Block;
+  ///
+  /// use std::fs::File;
+  ///
+  /// struct Device(Vec<i32>);
+  ///
+  /// impl Sink<i32> for Device {
+  ///   fn write_block(&mut self, block: &Block<i32>)
+  ///                  -> Result<(), flac::ErrorKind> {
+  ///     self.0.extend_from_slice(&block.samples);
+  ///
+  ///     Ok(())
+  ///   }
+  /// }
+  ///
+  /// let mut stream = StreamReader::<File>::from_file("path/to/file.flac")
+  ///                    .unwrap();
+  ///
+  /// let supported = [
+  ///   OutputFormat { bits_per_sample: 16, sample_rate: 48000, channels: 2 },
+  ///   OutputFormat { bits_per_sample: 16, sample_rate: 44100, channels: 2 },
+  /// ];
+  ///
+  /// let plan       = stream.negotiate_output(&supported).unwrap();
+  /// let mut device = plan.decimate(Device(Vec::new()));
+  ///
+  /// stream.decode_into::<i32, _>(&mut device).unwrap();
+  ///
+  /// let samples = device.into_inner().0.iter()
+  ///                     .map(|&sample| plan.convert(sample as i64) as i16)
+  ///                     .collect::<Vec<_>>();
+  /// ```
+  #[inline]
+  pub fn negotiate_output(&self, supported: &[OutputFormat])
+                          -> Option<OutputPlan> {
+    OutputPlan::negotiate(&self.info, supported)
+  }
+
+  /// Returns a slice of `Metadata`
+  ///
+  /// This slice excludes `StreamInfo`, which is located in `Stream::info`.
+  /// Everything else is related to metadata for the FLAC stream is in the
+  /// slice.
+  ///
+  /// Blocks are kept in the order they were stored in, so the block at
+  /// index `i` was block `i + 1` of the stream, right after `StreamInfo`.
+  /// Writing the blocks back in slice order puts each one where it came
+  /// from.
+  ///
+  /// With `StreamOptions::is_metadata_deferred`, the slice stays empty until
+  /// `Stream::load_metadata` gets called.
+  #[inline]
+  pub fn metadata(&self) -> &[Metadata] {
+    &self.metadata
+  }
+
+  /// Parses the metadata blocks skipped over when opening the stream with
+  /// `StreamOptions::is_metadata_deferred`, returning the same slice as
+  /// `Stream::metadata`.
+  ///
+  /// Only the first call parses anything. Decoding carries on from the
+  /// frame it was at, since the producer seeks back once the blocks are
+  /// parsed.
+  ///
+  /// # Failures
+  ///
+  /// * `ErrorKind::Unsupported` is returned when the producer isn't able
+  ///   to seek.
+  /// * Errors from parsing the blocks are returned as is, leaving them
+  ///   deferred.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use flac::{StreamOptions, StreamReader};
+  /// use std::fs::File;
+  ///
+  /// let options = StreamOptions {
+  ///   is_metadata_deferred: true,
+  ///   ..Default::default()
+  /// };
+  ///
+  /// let filename   = "tests/assets/input-SCPAP.flac";
+  /// let mut stream = StreamReader::<File>::from_file_with_options(filename,
+  ///                                                               options)
+  ///                    .unwrap();
+  ///
+  /// // Playback starts right away.
+  /// assert_eq!(stream.iter::<i16>().take(4096).count(), 4096);
+  ///
+  /// assert!(stream.metadata().is_empty());
+  /// assert!(!stream.load_metadata().unwrap().is_empty());
+  /// ```
+  pub fn load_metadata(&mut self) -> Result<&[Metadata], ErrorKind> {
+    if let Some(offset) = self.deferred {
+      let position     = self.producer.position();
+      let mut metadata = Vec::new();
+
+      let result = seek_producer(&mut self.producer, offset).and_then(|_| {
+        many_blocks(&mut self.producer, &self.context,
+                    |block| metadata.push(block))
+      });
+
+      try!(seek_producer(&mut self.producer, position));
+      try!(result);
+
+      cap_metadata(&mut metadata, self.max_metadata_size);
+
+      self.metadata = metadata;
+      self.deferred = None;
+    }
+
+    Ok(&self.metadata)
+  }
+
+  /// Parses the block at `index` within `Stream::metadata` again, straight
+  /// from the producer, which brings back the payload of a block spilled to
+  /// stay within `StreamOptions::max_metadata_size`.
+  ///
+  /// The block gets returned rather than kept, so the metadata stays within
+  /// the cap. Decoding carries on from the frame it was at, since the
+  /// producer seeks back once the block is parsed.
+  ///
+  /// # Failures
+  ///
+  /// * `ErrorKind::NotFound` is returned when there is no block at `index`.
+  /// * `ErrorKind::Unsupported` is returned when the producer isn't able
+  ///   to seek.
+  /// * Errors from parsing the block are returned as is.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use flac::{StreamOptions, StreamReader};
+  /// use std::fs::File;
+  ///
+  /// let options = StreamOptions {
+  ///   max_metadata_size: Some(1024),
+  ///   ..Default::default()
+  /// };
+  ///
+  /// let filename   = "tests/assets/input-pictures.flac";
+  /// let mut stream = StreamReader::<File>::from_file_with_options(filename,
+  ///                                                               options)
+  ///                    .unwrap();
+  ///
+  /// assert!(stream.metadata_heap_size() <= 1024);
+  ///
+  /// let index = stream.metadata().iter().position(|block| {
+  ///   block.is_spilled()
+  /// }).unwrap();
+  ///
+  /// let spilled = stream.metadata()[index].heap_size();
+  /// let block   = stream.load_block(index).unwrap();
+  ///
+  /// assert!(!block.is_spilled());
+  /// assert!(block.heap_size() > spilled);
+  /// ```
+  pub fn load_block(&mut self, index: usize) -> Result<Metadata, ErrorKind> {
+    if index >= self.metadata.len() {
+      return Err(ErrorKind::NotFound);
+    }
+
+    // Blocks are stored right before the first frame, so the block starts
+    // where every block from it onward ends.
+    let length = self.metadata[index..].iter().fold(0, |result, block| {
+      result + 4 + block.length() as u64
+    });
+    let offset = try!(self.audio_offset.checked_sub(length)
+                        .ok_or(ErrorKind::NotFound));
+
+    let position = self.producer.position();
+
+    let result = seek_producer(&mut self.producer, offset).and_then(|_| {
+      metadata_block(&mut self.producer, &self.context)
+    });
+
+    try!(seek_producer(&mut self.producer, position));
+
+    result
+  }
+
+  /// Returns roughly how many bytes of heap memory the metadata blocks
+  /// take up, see `Metadata::heap_size`.
+  #[inline]
+  pub fn metadata_heap_size(&self) -> usize {
+    metadata_heap_size(&self.metadata)
+  }
+
+  /// Returns an iterator over the metadata blocks of the given type, along
+  /// with the position each was stored at.
+  ///
+  /// Positions count every block of the stream, with `StreamInfo` at zero,
+  /// and are one more than the index within `Stream::metadata`.
+  ///
+  /// # Examples
+  ///
+  /// ```no_run
+  /// use flac::StreamReader;
+  /// use flac::metadata::Type;
+  /// use std::fs::File;
+  ///
+  /// let stream = StreamReader::<File>::from_file("path/to/file.flac")
+  ///                .unwrap();
+  ///
+  /// for (position, block) in stream.metadata_of_type(Type::Picture) {
+  ///   println!("picture at block {}: {} bytes", position, block.length());
+  /// }
+  /// ```
+  #[inline]
+  pub fn metadata_of_type(&self, block_type: Type) -> MetadataOfType {
+    MetadataOfType::new(&self.metadata, block_type)
+  }
+
+  /// Returns the offset, in bytes, from the beginning of the stream to the
+  /// first audio frame.
+  #[inline]
+  pub fn audio_offset(&self) -> u64 {
+    self.audio_offset
+  }
+
+  /// Returns rolling statistics on the frames decoded so far.
+  #[inline]
+  pub fn stats(&self) -> &DecodeStats {
+    &self.stats
+  }
+
+  /// Returns how far decoding got, along with the error that stopped it.
+  ///
+  /// # Examples
+  ///
+  /// ```no_run
+  /// use flac::StreamReader;
+  /// use std::fs::File;
+  ///
+  /// let mut stream = StreamReader::<File>::from_file("path/to/file.flac")
+  ///                    .unwrap();
+  ///
+  /// let samples  = stream.iter::<i16>().collect::<Vec<_>>();
+  /// let progress = stream.decode_progress();
+  ///
+  /// if let Some(error) = progress.error {
+  ///   let percent = progress.fraction().unwrap_or(0.0) * 100.0;
+  ///
+  ///   println!("{:?} after {} frames, {:.0}% recoverable", error,
+  ///            progress.frames, percent);
+  /// }
+  /// ```
+  #[inline]
+  pub fn decode_progress(&self) -> DecodeProgress {
+    self.progress
+  }
+
+  /// Decodes past frames that fail to decode, replacing their audio with
+  /// what the given concealer supplies.
+  ///
+  /// Without a concealer, which is the default, decoding stops at the
+  /// first frame that fails. See `flac::conceal` for the policies that
+  /// come with the crate.
+  pub fn set_concealer<C>(&mut self, concealer: C)
+   where C: Conceal + Send + 'static {
+    self.concealer = Some(Box::new(concealer));
+  }
+
+  /// Removes the concealer, so decoding stops at the first frame that
+  /// fails again.
+  pub fn clear_concealer(&mut self) {
+    self.concealer = None;
+
+    self.previous.clear();
+  }
+
+  /// Returns the handle that stops decoding of the stream, which can be
+  /// sent to another thread.
+  ///
+  /// Once cancelled, decoding stops before the next frame with
+  /// `ErrorKind::Cancelled`, which `Stream::decode_progress` also holds.
+  /// Every frame decoded before then is kept, so progress can be resumed
+  /// after `CancelHandle::reset`.
+  #[inline]
+  pub fn cancel_handle(&self) -> CancelHandle {
+    self.cancel.clone()
+  }
+
+  /// Replaces the handle that stops decoding of the stream, for sharing a
+  /// single handle between many streams.
+  #[inline]
+  pub fn set_cancel_handle(&mut self, handle: CancelHandle) {
+    self.cancel = handle;
+  }
+
+  /// Constructs a decoder with the given file name.
+  ///
+  /// # Failures
+  ///
+  /// * `ErrorKind::IO(io::ErrorKind::NotFound)` is returned when the given
+  ///   filename isn't found.
+  /// * `ErrorKind::IO(io::ErrorKind::InvalidData)` is returned when the data
+  ///   within the file isn't valid FLAC data.
+  /// * Several different parser specific errors that are structured as
+  ///   `ErrorKind::<parser_name>Parser`.
+  /// * Several different invalidation specific errors that are
+  ///   structured as `ErrorKind::Invalid<invalidation_name>`.
+  /// * `ErrorKind::Unsupported` is returned when the stream uses block
+  ///   sizes larger than the FLAC subset allows.
+  #[inline]
+  pub fn from_file(filename: &str) -> Result<StreamReader<File>, ErrorKind> {
+    Stream::<ReadStream<File>>::from_file_with_options(filename,
+                                                       Default::default())
+  }
+
+  /// Constructs a decoder with the given file name and options.
+  ///
+  /// Fails in the same way as `Stream::from_file` does, other than what
+  /// the given options allow.
+  #[inline]
+  pub fn from_file_with_options(filename: &str, options: StreamOptions)
+                                -> Result<StreamReader<File>, ErrorKind> {
+    File::open(filename).map_err(|e| ErrorKind::IO(e.kind()))
+                        .and_then(|file| {
+      let producer = ReadStream::seekable(file);
+
+      Stream::from_stream_producer(producer, options)
+    })
+  }
+
+  /// Constructs a decoder with the given buffer.
+  ///
+  /// This constructor assumes that an entire FLAC file is in the buffer.
+  /// Every metadata block also keeps a copy of its original bytes, which is
+  /// available through `Metadata::raw`.
+  ///
+  /// # Failures
+  ///
+  /// * `ErrorKind::IO(io::ErrorKind::InvalidData)` is returned when the data
+  ///   within the file isn't valid FLAC data.
+  /// * Several different parser specific errors that are structured as
+  ///   `ErrorKind::<parser_name>Parser`.
+  /// * Several different invalidation specific errors that are
+  ///   structured as `ErrorKind::Invalid<invalidation_name>`.
+  /// * `ErrorKind::Unsupported` is returned when the stream uses block
+  ///   sizes larger than the FLAC subset allows.
+  #[inline]
+  pub fn from_buffer(buffer: &[u8]) -> Result<StreamBuffer, ErrorKind> {
+    Stream::<ByteStream>::from_buffer_with_options(buffer, Default::default())
+  }
+
+  /// Constructs a decoder with the given buffer and options.
+  ///
+  /// Fails in the same way as `Stream::from_buffer` does, other than what
+  /// the given options allow.
+  pub fn from_buffer_with_options(buffer: &[u8], options: StreamOptions)
+                                  -> Result<StreamBuffer, ErrorKind> {
+    FlacReader::from_buffer_with_options(buffer, options)
+      .and_then(MetadataPhase::into_audio)
+  }
+
+  /// Constructs a decoder over any `StreamProducer` with the given
+  /// options, for sources that aren't a plain reader or buffer.
+  ///
+  /// Fails in the same way as `Stream::from_file` does, other than what
+  /// the given options allow.
+  pub fn from_stream_producer(producer: P, options: StreamOptions)
+                              -> Result<Self, ErrorKind> {
+    MetadataPhase::from_stream_producer(producer, options)
+      .and_then(MetadataPhase::into_audio)
+  }
+
+  /// Returns an iterator over the decoded samples.
+  #[inline]
+  pub fn iter<S: SampleSize>(&mut self) -> Iter<P, S::Extended> {
+    self.iter_with::<S>(false)
+  }
+
+  /// Returns an iterator over approximately decoded samples.
+  ///
+  /// **The samples are lossy.** Subframes using linear predictive coding
+  /// skip restoring the signal, the most expensive part of decoding, and
+  /// approximate it from the warm up values and residual instead. This is
+  /// meant for things like waveform previews, where the rough shape and
+  /// loudness matter but exactness doesn't. Use `Stream::iter` for the
+  /// actual samples.
+  #[inline]
+  pub fn iter_approximate<S>(&mut self) -> Iter<P, S::Extended>
+   where S: SampleSize {
+    self.iter_with::<S>(true)
+  }
+
+  /// Returns an iterator over the decoded samples, each packed into bytes
+  /// by `pcm::PackedSample`.
+  ///
+  /// In the same way `Stream::iter` does for samples too large for `S`,
+  /// the iterator stops at the first sample that doesn't fit within the
+  /// packed format.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use flac::StreamReader;
+  /// use flac::pcm::Packed24;
+  ///
+  /// use std::fs::File;
+  ///
+  /// let filename   = "tests/assets/input-SVAUP.flac";
+  /// let mut stream = StreamReader::<File>::from_file(filename).unwrap();
+  /// let packed     = stream.iter_packed::<Packed24>().collect::<Vec<_>>();
+  ///
+  /// assert_eq!(packed.len() as u64, stream.info().total_samples * 2);
+  /// ```
+  #[inline]
+  pub fn iter_packed<T: PackedSample>(&mut self) -> PackedIter<P, T> {
+    PackedIter {
+      iter: self.iter_with::<i32>(false),
+      phantom: PhantomData,
+    }
+  }
+
+  /// Returns an iterator over the decoded samples, each along with its
+  /// sample number and channel.
+  ///
+  /// Samples come out in the same order as `Stream::iter`, as tuples of
+  /// `(sample_number, channel, value)`. The sample number counts from the
+  /// start of the stream, even after seeking, and the channel is the
+  /// position within the interleaved output, which follows the
+  /// `ChannelOrder` of the stream. This is meant for debugging, like
+  /// tracking down swapped channels or lining samples up against another
+  /// decoder.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use flac::StreamReader;
+  ///
+  /// use std::fs::File;
+  ///
+  /// let filename   = "tests/assets/input-SVAUP.flac";
+  /// let mut stream = StreamReader::<File>::from_file(filename).unwrap();
+  ///
+  /// for (sample_number, channel, value) in stream.iter_positioned::<i16>()
+  ///                                              .take(4) {
+  ///   println!("{:>8} {} {:>6}", sample_number, channel, value);
+  /// }
+  /// ```
+  #[inline]
+  pub fn iter_positioned<S: SampleSize>(&mut self)
+                                        -> PositionedIter<P, S::Extended> {
+    let sample_number = self.progress.samples;
+
+    PositionedIter {
+      iter: self.iter_with::<S>(false),
+      sample_number: sample_number,
+    }
+  }
+
+  /// Decodes the next frame straight into `output` as interleaved 16-bit
+  /// samples, returning the number of samples written for each channel.
+  ///
+  /// This is the fast path for the common case of playing back 16-bit
+  /// audio. The frame gets decoded into a buffer the stream reuses, then a
+  /// single pass interleaves it into `output`, shifting samples to 16 bits
+  /// when the stream has a different bits per sample and clamping them to
+  /// the range of `i16`, without going through `Sample` for each one.
+  /// Channels are laid out in the same order `Stream::iter` returns them,
+  /// and zero is returned once there are no frames left.
+  ///
+  /// # Failures
+  ///
+  /// * `ErrorKind::OutputTooSmall` is returned when `output` can't hold a
+  ///   frame of the maximum block size within `StreamInfo`, along with the
+  ///   length it needs.
+  /// * Any error that stopped decoding, which `Stream::decode_progress` also
+  ///   holds.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use flac::StreamReader;
+  ///
+  /// use std::fs::File;
+  ///
+  /// let filename   = "tests/assets/input-SVAUP.flac";
+  /// let mut stream = StreamReader::<File>::from_file(filename).unwrap();
+  /// let info       = stream.info();
+  /// let channels   = info.channels as usize;
+  /// let mut output = vec![0; info.max_block_size as usize * channels];
+  /// let mut total  = 0;
+  ///
+  /// loop {
+  ///   let block_size = stream.next_frame_into_i16(&mut output).unwrap();
+  ///
+  ///   if block_size == 0 {
+  ///     break;
+  ///   }
+  ///
+  ///   // Hand `&output[..(block_size * channels)]` off to the audio device.
+  ///   total += block_size as u64;
+  /// }
+  ///
+  /// assert_eq!(total, info.total_samples);
+  /// ```
+  pub fn next_frame_into_i16(&mut self, output: &mut [i16])
+                             -> Result<usize, ErrorKind> {
+    let channels    = self.info.channels as usize;
+    let buffer_size = self.info.max_block_size as usize * channels;
+    let shift       = self.info.bits_per_sample.get() as i32 - 16;
+    let mapping     = self.channel_order.mapping(channels);
+
+    if output.len() < buffer_size {
+      return Err(ErrorKind::OutputTooSmall(buffer_size));
+    }
+
+    // Taken out of the stream while decoding, so the stream itself is free
+    // to borrow.
+    let mut buffer = mem::replace(&mut self.frame_buffer, Vec::new());
+
+    buffer.resize(buffer_size, 0);
+
+    let result = self.next_frame(&mut buffer, false);
+
+    let block_size = match result {
+      Some(block_size) => block_size,
+      None             => {
+        self.frame_buffer = buffer;
+
+        return self.progress.error.map_or(Ok(0), Err);
+      }
+    };
+
+    for channel in 0..channels {
+      let source  = mapping.get(channel).map_or(channel, |&c| c);
+      let start   = source * block_size;
+      let samples = &buffer[start..(start + block_size)];
+      let outputs = output[channel..].iter_mut().step_by(channels);
+
+      for (sample, &value) in outputs.zip(samples) {
+        let value = if shift >= 0 { value >> shift } else { value << -shift };
+
+        *sample = if value > i16::MAX as i32 {
+          i16::MAX
+        } else if value < i16::MIN as i32 {
+          i16::MIN
+        } else {
+          value as i16
+        };
+      }
+    }
+
+    self.frame_buffer = buffer;
+
+    Ok(block_size)
+  }
+
+  /// Returns an iterator over the decoded frames, each as a `Block` of
+  /// samples.
+  ///
+  /// Sample numbers of the blocks carry on from wherever decoding left off,
+  /// like after seeking or a previous iterator.
+  pub fn blocks<S: SampleSize>(&mut self) -> Blocks<P, S::Extended> {
+    let channels    = self.info.channels as usize;
+    let buffer_size = self.info.max_block_size as usize * channels;
+    let mapping     = self.channel_order.mapping(channels);
+    let start       = self.progress.samples;
+
+    Blocks {
+      stream: self,
+      sample_number: start,
+      is_finished: false,
+      mapping: mapping,
+      buffer: vec![S::Extended::from_i8(0); buffer_size],
+    }
+  }
+
+  /// Returns an iterator over fixed size windows of the decoded samples,
+  /// mixed down to mono and scaled between -1.0 and 1.0, for feeding into a
+  /// spectrogram or FFT.
+  ///
+  /// Each window holds `size` samples and starts `hop` samples after the
+  /// one before it, so windows overlap whenever `hop` is smaller than
+  /// `size`. Samples get mixed straight from each decoded frame, without
+  /// going through `Stream::iter`. The last window gets padded with zeros
+  /// when the stream ends partway through it, so every sample ends up
+  /// within some window. Zero for either `size` or `hop` is treated as one.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use flac::StreamReader;
+  /// use std::fs::File;
+  ///
+  /// let filename   = "tests/assets/input-SCPAP.flac";
+  /// let mut stream = StreamReader::<File>::from_file(filename).unwrap();
+  ///
+  /// for window in stream.analysis_windows(2048, 512) {
+  ///   assert_eq!(window.len(), 2048);
+  ///
+  ///   // Apply a window function and hand it to an FFT.
+  /// }
+  /// ```
+  pub fn analysis_windows(&mut self, size: usize, hop: usize)
+                          -> AnalysisWindows<P> {
+    let channels    = self.info.channels as usize;
+    let buffer_size = self.info.max_block_size as usize * channels;
+    let bits        = self.info.bits_per_sample.get() - 1;
+    let scale       = 1.0 / ((1u64 << bits) as f32 * channels as f32);
+
+    AnalysisWindows {
+      stream: self,
+      size: cmp::max(size, 1),
+      hop: cmp::max(hop, 1),
+      scale: scale,
+      pending: VecDeque::new(),
+      covered: 0,
+      skip: 0,
+      is_finished: false,
+      buffer: vec![0; buffer_size],
+    }
+  }
+
+  /// Returns an iterator over the tracks of the stream's cue sheet, each an
+  /// iterator over the samples of that track.
+  ///
+  /// The gap before each track ends the track before it, see
+  /// `Stream::cue_tracks_with` for handling it differently. A stream
+  /// without a `CueSheet` has no tracks.
+  #[cfg(feature = "cue-sheet")]
+  #[inline]
+  pub fn cue_tracks<S: SampleSize>(&mut self) -> CueTracks<P, S::Extended> {
+    self.cue_tracks_with::<S>(Default::default())
+  }
+
+  /// Returns an iterator over the tracks of the stream's cue sheet, with
+  /// the gap before each track handled by `pregap`.
+  ///
+  /// Deferred metadata gets loaded first, with a stream whose metadata
+  /// fails to load having no tracks. Tracks flagged with pre-emphasis
+  /// within the cue sheet are marked as such, see
+  /// `CueTracks::with_deemphasis`.
+  #[cfg(feature = "cue-sheet")]
+  pub fn cue_tracks_with<S: SampleSize>(&mut self, pregap: Pregap)
+                                        -> CueTracks<P, S::Extended> {
+    let _ = self.load_metadata();
+
+    let (ranges, emphasized) = self.metadata.iter()
+      .filter_map(|block| block.as_cue_sheet())
+      .next()
+      .map_or_else(|| (Vec::new(), Vec::new()), |cue_sheet| {
+        let emphasized = cue_sheet.tracks.iter()
+                                  .filter(|track| track.is_pre_emphasis)
+                                  .map(|track| track.number)
+                                  .collect::<Vec<_>>();
+
+        (cue::track_ranges(cue_sheet, pregap), emphasized)
+      });
+
+    CueTracks::new(self.blocks::<S>(), ranges).with_pre_emphasis(&emphasized)
+  }
+
+  fn iter_with<S: SampleSize>(&mut self, approximate: bool)
+                              -> Iter<P, S::Extended> {
+    let samples_left = self.info.total_samples;
+    let channels     = self.info.channels as usize;
+    let block_size   = self.info.max_block_size as usize;
+    let buffer_size  = block_size * channels;
+    let mapping      = self.channel_order.mapping(channels);
+
+    Iter {
+      stream: self,
+      channel: 0,
+      block_size: 0,
+      sample_index: 0,
+      samples_left: samples_left,
+      is_total_wrong: false,
+      approximate: approximate,
+      mapping: mapping,
+      buffer: vec![S::Extended::from_i8(0); buffer_size]
+    }
+  }
+
+  fn next_frame<S>(&mut self, buffer: &mut [S], approximate: bool)
+                   -> Option<usize>
+   where S: Sample {
+    let stream_info = &self.info;
+    let context     = &mut self.context;
+    let frame_limit = self.frame_limit;
+
+    loop {
+      if self.cancel.is_cancelled() {
+        self.progress.error = Some(ErrorKind::Cancelled);
+
+        return None;
+      }
+
+      #[cfg(feature = "metrics")]
+      let start = Instant::now();
+
+      let result = self.producer.parse(|i| {
+        let input = match frame_limit {
+          Some(limit) if i.len() > limit => &i[..limit],
+          _                              => i,
+        };
+
+        match frame_parser_with_context(input, stream_info, context, buffer) {
+          IResult::Done(rest, frame) => {
+            let bytes = input.len() - rest.len();
+
+            IResult::Done(&i[bytes..], (frame, bytes))
+          }
+          IResult::Error(error)      => IResult::Error(error),
+          IResult::Incomplete(need)  => {
+            let needed = match need {
+              Needed::Size(size) => size,
+              Needed::Unknown    => input.len(),
+            };
+
+            // A frame running past the limit is never going to be finished.
+            let is_too_large = input.len() < i.len() ||
+                               frame_limit.map_or(false, |limit| {
+                                 needed > limit
+                               });
+
+            if is_too_large {
+              IResult::Error(Err::Code(
+                nom::ErrorKind::Custom(ErrorKind::Unsupported)))
+            } else {
+              IResult::Incomplete(need)
+            }
+          }
+        }
+      });
+
+      match result {
+        Ok((frame, bytes))       => {
+          let block_size = decode_frame(&frame, buffer, approximate);
+
+          self.stats.push(FrameStat {
+            bytes: bytes,
+            samples: block_size,
+            #[cfg(feature = "metrics")]
+            time: start.elapsed(),
+          });
+
+          self.progress.frames      += 1;
+          self.progress.samples     += block_size as u64;
+          self.progress.byte_offset += bytes as u64;
+
+          // Kept around for concealing the frame after it.
+          if self.concealer.is_some() {
+            let length  = block_size * self.info.channels as usize;
+            let samples = buffer[0..length].iter();
+
+            self.previous.clear();
+            self.previous.extend(samples.map(|&s| S::to_i32_lossy(s)));
+          }
+
+          return Some(block_size);
+        }
+        Err(ErrorKind::Continue)   => continue,
+        Err(ErrorKind::EndOfInput) => return None,
+        Err(error)                 => {
+          if self.concealer.is_some() {
+            if let Some(block_size) = self.conceal_frame(buffer, error) {
+              return Some(block_size);
+            }
+          }
+
+          self.progress.error = Some(error);
+
+          return None;
+        }
+      }
+    }
+  }
+
+  // Skips over a frame that failed to decode, up to the next frame sync
+  // code, and fills the buffer with audio from the concealer in its place.
+  // `None` is returned when there is nothing left to conceal.
+  fn conceal_frame<S>(&mut self, buffer: &mut [S], error: ErrorKind)
+                      -> Option<usize>
+   where S: Sample {
+    let channels       = self.info.channels as usize;
+    let max_block_size = self.info.max_block_size as usize;
+    let total_samples  = self.info.total_samples;
+    let stream_info    = &self.info;
+
+    // The header is usually still intact when only the audio is damaged.
+    let block_size = self.producer.parse(|i| {
+      let block_size = match header(i, stream_info) {
+        IResult::Done(_, header) => header.block_size as usize,
+        _                        => max_block_size,
+      };
+
+      IResult::Done(i, block_size)
+    }).unwrap_or(max_block_size);
+
+    let mut block_size = cmp::min(block_size, max_block_size);
+    let mut skipped    = 0;
+
+    loop {
+      let result = self.producer.parse(|i| {
+        // The failed frame starts with a sync code of its own.
+        let found = i.windows(2).skip(1).position(|bytes| {
+          bytes[0] == 0xff && (bytes[1] & 0xfe) == 0xf8
+        });
+
+        let length = match found {
+          Some(position)                      => position + 1,
+          // Keep what could be the first half of a sync code.
+          None if i.len() > 1 &&
+                  i[i.len() - 1] == 0xff      => i.len() - 1,
+          None                                => i.len(),
+        };
+
+        IResult::Done(&i[length..], (length, found.is_some()))
+      });
+
+      match result {
+        Ok((length, is_found))     => {
+          skipped += length;
+
+          if is_found {
+            break;
+          }
+        }
+        Err(ErrorKind::Continue)   => continue,
+        Err(ErrorKind::EndOfInput) => break,
+        Err(_)                     => return None,
+      }
+    }
+
+    if total_samples > 0 {
+      let samples_left = total_samples.saturating_sub(self.progress.samples);
+
+      block_size = cmp::min(block_size as u64, samples_left) as usize;
+    }
+
+    if block_size == 0 {
+      return None;
+    }
+
+    let frame = LostFrame {
+      sample_number: self.progress.samples,
+      block_size: block_size,
+      channels: channels,
+      bits_per_sample: self.info.bits_per_sample.get() as usize,
+      error: error,
+    };
+
+    let mut output = vec![0; block_size * channels];
+
+    if let Some(ref mut concealer) = self.concealer {
+      concealer.conceal(&frame, &self.previous, &mut output);
+    }
+
+    for (sample, &value) in buffer.iter_mut().zip(&output) {
+      *sample = S::from_i32_lossy(value);
+    }
+
+    self.previous = output;
+
+    self.progress.concealed   += 1;
+    self.progress.samples     += block_size as u64;
+    self.progress.byte_offset += skipped as u64;
+
+    Some(block_size)
+  }
+}
+
+impl<P> Stream<P> where P: StreamProducer {
+  /// Checks that the frame and sample numbers of every remaining frame
+  /// increase contiguously, returning each place where they don't.
+  ///
+  /// The sample number a frame starts at comes from its header, with frame
+  /// numbers being multiplied by the block size of the stream, and needs to
+  /// match the sample right after the previous frame. The first frame
+  /// checked is expected to start at sample zero. Every frame gets parsed
+  /// along the way, so the stream needs to be rewound before it can be
+  /// decoded afterwards.
+  ///
+  /// # Failures
+  ///
+  /// Any error from parsing a frame, other than reaching the end of the
+  /// stream, gets returned, along with `ErrorKind::Cancelled` when the
+  /// stream's `CancelHandle` is cancelled.
+  pub fn check_continuity(&mut self)
+                          -> Result<Vec<Discontinuity>, ErrorKind> {
+    let channels    = self.info.channels as usize;
+    let block_size  = self.info.max_block_size as u64;
+    let stream_info = &self.info;
+    let context     = &mut self.context;
+
+    let mut buffer          = vec![0i64; block_size as usize * channels];
+    let mut discontinuities = Vec::new();
+    let mut frame_index     = 0;
+    let mut expected        = 0;
+
+    loop {
+      if self.cancel.is_cancelled() {
+        return Err(ErrorKind::Cancelled);
+      }
+
+      let result = self.producer.parse(|i| {
+        frame_parser_with_context(i, stream_info, context, &mut buffer)
+      });
+
+      let header = match result {
+        Ok(frame)                  => frame.header,
+        Err(ErrorKind::Continue)   => continue,
+        Err(ErrorKind::EndOfInput) => break,
+        Err(error)                 => return Err(error),
+      };
+
+      let found = match header.number {
+        NumberType::Frame(number)  => number as u64 * block_size,
+        NumberType::Sample(number) => number,
+      };
+
+      if found != expected {
+        discontinuities.push(Discontinuity {
+          frame_index: frame_index,
+          expected: expected,
+          found: found,
+        });
+      }
+
+      frame_index += 1;
+      expected     = found + header.block_size as u64;
+    }
+
+    Ok(discontinuities)
+  }
+
+  /// Decodes every remaining sample into the given hash, returning the
+  /// resulting signature.
+  ///
+  /// Samples are fed in the same layout the MD5 signature within
+  /// `StreamInfo` is calculated from: interleaved by channel, little
+  /// endian, and as many bytes each as it takes to fit the bits per sample.
+  ///
+  /// # Failures
+  ///
+  /// Any error from decoding a frame, other than reaching the end of the
+  /// stream, gets returned.
+  pub fn md5_sum_with<D: Digest>(&mut self, digest: D)
+                                 -> Result<[u8; 16], ErrorKind> {
+    let mut sink = DigestSink::new(digest, self.info.bits_per_sample.get());
+
+    try!(self.decode_into::<i32, _>(&mut sink));
+
+    Ok(sink.signature())
+  }
+
+  /// Decodes every remaining sample and checks them against the MD5
+  /// signature within `StreamInfo`, using the given hash.
+  ///
+  /// An encoder that didn't calculate the signature leaves it as all
+  /// zeros, in which case there is nothing to check against and `true` is
+  /// returned without decoding anything.
+  ///
+  /// # Failures
+  ///
+  /// Fails in the same way as `Stream::md5_sum_with` does.
+  pub fn verify_with<D: Digest>(&mut self, digest: D)
+                                -> Result<bool, ErrorKind> {
+    let expected = self.info.md5_sum;
+
+    if expected == [0; 16] {
+      return Ok(true);
+    }
+
+    self.md5_sum_with(digest).map(|signature| signature == expected)
+  }
+
+  /// Decodes every remaining sample and checks them against the MD5
+  /// signature within `StreamInfo`.
+  ///
+  /// Only available with the `md5` feature enabled, otherwise use
+  /// `Stream::verify_with` with a hash of your own.
+  ///
+  /// # Examples
+  ///
+  /// ```no_run
+  /// use flac::StreamReader;
+  /// use std::fs::File;
+  ///
+  /// let mut stream = StreamReader::<File>::from_file("path/to/file.flac")
+  ///                    .unwrap();
+  ///
+  /// if !stream.verify().unwrap() {
+  ///   println!("decoded audio doesn't match the signature");
+  /// }
+  /// ```
+  #[cfg(feature = "md5")]
+  #[inline]
+  pub fn verify(&mut self) -> Result<bool, ErrorKind> {
+    self.verify_with(Md5::new())
+  }
+
+  /// Decodes every remaining frame into the given sink, returning the
+  /// number of samples, per channel, that were written.
+  ///
+  /// Use a `sink::TeeSink` for handing the blocks to more than one sink.
+  ///
+  /// # Failures
+  ///
+  /// Any error from decoding a frame, other than reaching the end of the
+  /// stream, or from the sink gets returned, in which case the sink doesn't
+  /// get finished.
+  pub fn decode_into<S, K>(&mut self, sink: K) -> Result<u64, ErrorKind>
+   where S: SampleSize,
+         K: Sink<<S::Extended as Sample>::Normal> {
+    let mut sink    = sink;
+    let mut samples = 0;
+
+    for block in self.blocks::<S>() {
+      let block = try!(block);
+
+      samples += block.len() as u64;
+
+      try!(sink.write_block(&block));
+    }
+
+    if let Some(error) = self.progress.error {
+      return Err(error);
+    }
+
+    try!(sink.finish());
+
+    Ok(samples)
+  }
+}
+
+impl<P> Stream<P> where P: SeekableProducer {
+  /// Moves the stream back to the first audio frame.
+  ///
+  /// The position of the first audio frame is remembered from when the
+  /// metadata was parsed, so none of the metadata gets parsed again.
+  pub fn rewind(&mut self) -> Result<(), ErrorKind> {
+    try!(self.producer.seek(self.audio_offset));
+
+    self.reset_progress(0, self.audio_offset);
+
+    Ok(())
+  }
+
+  // Starts the decode progress over from the given position.
+  fn reset_progress(&mut self, samples: u64, byte_offset: u64) {
+    self.progress = DecodeProgress {
+      samples: samples,
+      byte_offset: byte_offset,
+      total_samples: self.info.total_samples,
+      ..Default::default()
+    };
+
+    self.previous.clear();
+  }
+
+  /// Returns the number of bytes each type of metadata block takes up,
+  /// along with the audio frames.
+  ///
+  /// The audio gets measured by stepping over every frame with
+  /// `frame_length`, without decoding any of them, and the stream is moved
+  /// back to where it was afterwards. Anything after the frames that isn't
+  /// a frame, like a trailing tag, returns the error it failed to parse
+  /// with.
+  ///
+  /// # Examples
+  ///
+  /// ```no_run
+  /// use flac::StreamReader;
+  /// use std::fs::File;
+  ///
+  /// let mut stream = StreamReader::<File>::from_file("path/to/file.flac")
+  ///                    .unwrap();
+  /// let report     = stream.metadata_size_report().unwrap();
+  ///
+  /// if report.picture + report.padding > report.audio / 10 {
+  ///   println!("artwork and padding take up {} bytes",
+  ///            report.picture + report.padding);
+  /// }
+  /// ```
+  pub fn metadata_size_report(&mut self)
+                              -> Result<MetadataSizeReport, ErrorKind> {
+    let mut report = MetadataSizeReport {
+      marker: 4,
+      ..Default::default()
+    };
+
+    try!(self.load_metadata());
+
+    for block in &self.metadata {
+      let size = 4 + block.length() as u64;
+
+      match block.data_type() {
+        metadata::Type::StreamInfo    => report.stream_info += size,
+        metadata::Type::Padding       => report.padding += size,
+        metadata::Type::Application   => report.application += size,
+        metadata::Type::SeekTable     => report.seek_table += size,
+        metadata::Type::VorbisComment => report.vorbis_comment += size,
+        metadata::Type::CueSheet      => report.cue_sheet += size,
+        metadata::Type::Picture       => report.picture += size,
+        metadata::Type::Unknown       => report.unknown += size,
+      }
+    }
+
+    // `StreamInfo` can declare a length past the 34 bytes it uses, so it
+    // takes up whatever the other blocks leave before the audio.
+    report.stream_info += self.audio_offset.saturating_sub(report.metadata());
+
+    let position = self.producer.position();
+
+    try!(self.producer.seek(self.audio_offset));
+
+    let result = self.audio_size();
+
+    try!(self.producer.seek(position));
+
+    report.audio = try!(result);
+
+    Ok(report)
+  }
+
+  // Steps over every frame from the current position, returning the number
+  // of bytes they take up.
+  fn audio_size(&mut self) -> Result<u64, ErrorKind> {
+    let stream_info = &self.info;
+    let producer    = &mut self.producer;
+    let start       = producer.position();
+
+    loop {
+      let result = producer.parse(|i| frame::frame_length(i, stream_info));
+
+      match result {
+        Ok(_)                      |
+        Err(ErrorKind::Continue)   => continue,
+        Err(ErrorKind::EndOfInput) => return Ok(producer.position() - start),
+        Err(kind)                  => return Err(kind),
+      }
+    }
+  }
+
+  /// Returns an iterator over the byte range and CRC-16 of every remaining
+  /// frame.
+  ///
+  /// Frames are parsed and checked against their CRC-16 without being
+  /// decoded. The iterator stops at the first frame that fails to parse, or
+  /// once the stream's `CancelHandle` is cancelled, so the stream needs to
+  /// be rewound before it can be decoded afterwards.
+  pub fn frame_ranges(&mut self) -> FrameRanges<P> {
+    let channels    = self.info.channels as usize;
+    let buffer_size = self.info.max_block_size as usize * channels;
+
+    FrameRanges {
+      stream: self,
+      buffer: vec![0; buffer_size],
+    }
+  }
+
+  /// Returns a window of decoded samples reaching `radius` samples on
+  /// either side of its focus.
+  ///
+  /// Nothing gets decoded until `Window::focus` is called. The stream is
+  /// left wherever the window last decoded up to.
+  pub fn window<S: SampleSize>(&mut self, radius: u64)
+                               -> Window<P, S::Extended> {
+    let channels    = self.info.channels as usize;
+    let buffer_size = self.info.max_block_size as usize * channels;
+    let mapping     = self.channel_order.mapping(channels);
+
+    Window {
+      stream: self,
+      radius: radius,
+      start: 0,
+      end: 0,
+      is_positioned: false,
+      mapping: mapping,
+      samples: Vec::new(),
+      buffer: vec![S::Extended::from_i8(0); buffer_size],
+    }
+  }
+
+  /// Moves the stream to the frame closest to, without going past, the
+  /// given sample number.
+  ///
+  /// The frame is found through the `SeekTable` metadata block, so only the
+  /// bytes from that frame onward need to be read. When there is no
+  /// `SeekTable`, or no seek point before the sample number, the stream
+  /// moves back to the first frame. The sample number of the frame the
+  /// stream moved to is returned. Deferred metadata gets loaded first, for
+  /// its `SeekTable`.
+  pub fn seek(&mut self, sample_number: u64) -> Result<u64, ErrorKind> {
+    let mut sample = 0;
+    let mut offset = 0;
+
+    try!(self.load_metadata());
+
+    for block in &self.metadata {
+      if let metadata::Data::SeekTable(ref seek_points) = block.data {
+        for seek_point in seek_points {
+          if is_seek_point_before(seek_point, sample_number) &&
+             seek_point.sample_number >= sample {
+            sample = seek_point.sample_number;
+            offset = seek_point.stream_offset;
+          }
+        }
+      }
+    }
+
+    let byte_offset = self.audio_offset.saturating_add(offset);
+
+    try!(self.producer.seek(byte_offset));
+
+    self.reset_progress(sample, byte_offset);
+
+    Ok(sample)
+  }
+}
+
+// Sums up the heap memory taken up by every block.
+fn metadata_heap_size(metadata: &[Metadata]) -> usize {
+  metadata.iter().fold(0, |result, block| result + block.heap_size())
+}
+
+// Points each block at its original bytes within `buffer`, with every
+// block sharing a single copy of the metadata.
+//
+// Blocks get walked by the length declared within each header, the one of
+// `StreamInfo` included, the same as the parser does. A header that doesn't
+// match the block parsed at its position leaves the rest of the blocks
+// without their original bytes, rather than pointing them at the wrong ones.
+fn attach_raw(buffer: &[u8], audio_offset: u64, metadata: &mut [Metadata]) {
+  let end = cmp::min(audio_offset, buffer.len() as u64) as usize;
+
+  // Skip over the "fLaC" header and the `StreamInfo` block, which isn't
+  // apart of the metadata.
+  let mut offset = match buffer.get(5..8) {
+    Some(bytes) if !metadata.is_empty() => 4 + 4 + to_u32(bytes) as usize,
+    _                                   => return,
+  };
+
+  let shared = Arc::<[u8]>::from(&buffer[0..end]);
+
+  for block in metadata {
+    let header = match shared.get(offset..(offset + 4)) {
+      Some(header) => header,
+      None         => break,
+    };
+
+    let block_end = offset + 4 + block.length() as usize;
+
+    if to_u32(&header[1..4]) != block.length() ||
+       (header[0] >> 7 == 1) != block.is_last() || block_end > end {
+      break;
+    }
+
+    if !block.is_spilled() {
+      block.set_raw(shared.clone(), offset..block_end);
+    }
+
+    offset = block_end;
+  }
+}
+
+// Spills the blocks taking up the most memory first, until every block
+// fits within `max_size` bytes or there is nothing left to spill.
+fn cap_metadata(metadata: &mut [Metadata], max_size: Option<usize>) {
+  let max_size = match max_size {
+    Some(max_size) => max_size,
+    None           => return,
+  };
+
+  let mut size = metadata_heap_size(metadata);
+
+  if size <= max_size {
+    return;
+  }
+
+  // The original bytes of every block point into one shared copy of the
+  // metadata, which stays around for as long as any block holds on to it.
+  for block in metadata.iter_mut() {
+    size -= block.drop_raw();
+  }
+
+  let mut order = (0..metadata.len()).collect::<Vec<_>>();
+
+  order.sort_by(|&a, &b| {
+    metadata[b].heap_size().cmp(&metadata[a].heap_size())
+  });
+
+  for index in order {
+    if size <= max_size {
+      break;
+    }
+
+    size -= metadata[index].spill();
+  }
+}
+
+// Moves the producer to `offset`, for producers that are only known to be a
+// `StreamProducer`.
+fn seek_producer<P>(producer: &mut P, offset: u64) -> Result<(), ErrorKind>
+ where P: StreamProducer {
+  producer.seek_if_supported(offset).unwrap_or(Err(ErrorKind::Unsupported))
+}
+
+// Checks whether the seek point is usable for seeking to the sample number,
+// which means it isn't a placeholder and starts at or before the sample.
+fn is_seek_point_before(seek_point: &SeekPoint, sample_number: u64) -> bool {
+  !seek_point.is_placeholder() && seek_point.sample_number <= sample_number
+}
+
+// Restores the samples of every channel within a parsed frame, returning
+// the block size of the frame.
+fn decode_frame<S>(frame: &Frame, buffer: &mut [S], approximate: bool)
+                   -> usize
+ where S: Sample {
+  let channels   = frame.header.channels as usize;
+  let block_size = frame.header.block_size as usize;
+  let subframes  = frame.subframes[0..channels].iter();
+
+  for (channel, subframe) in subframes.enumerate() {
+    let start  = channel * block_size;
+    let end    = (channel + 1) * block_size;
+    let output = &mut buffer[start..end];
+
+    if approximate {
+      subframe::decode_approximate(&subframe, block_size, output);
+    } else {
+      subframe::decode(&subframe, block_size, output);
+    }
+  }
+
+  // Only the samples of this frame, since the last frame of a stream is
+  // usually shorter than the buffer.
+  let samples = &mut buffer[0..(block_size * channels)];
+
+  frame::decode(frame.header.channel_assignment, samples);
+
+  block_size
+}
+
+// Interleaves the first `block_size` samples of each channel within
+// `buffer`, which holds the channels one after another, onto the end of
+// `samples`. The channel taken for each position comes from `mapping`, with
+// positions past its end taking the channel of the same number.
+//
+// Fails with `ErrorKind::Unsupported` when a sample doesn't fit within
+// `S::Normal`, leaving the samples interleaved before it.
+fn interleave<S>(buffer: &[S], block_size: usize, channels: usize,
+                 mapping: &[usize], samples: &mut Vec<S::Normal>)
+                 -> Result<(), ErrorKind>
+ where S: Sample {
+  for i in 0..block_size {
+    for position in 0..channels {
+      let channel = mapping.get(position).map_or(position, |&c| c);
+
+      match S::to_normal(buffer[i + channel * block_size]) {
+        Some(sample) => samples.push(sample),
+        None         => return Err(ErrorKind::Unsupported),
+      }
+    }
+  }
+
+  Ok(())
+}
+
+// Checks whether the maximum block size stays within the limits of the FLAC
+// subset, which depends on the sample rate of the stream.
+fn is_subset_block_size(stream_info: &StreamInfo) -> bool {
+  let max_block_size = if stream_info.sample_rate.get() <= 48000 {
+    MAX_SUBSET_BLOCK_SIZE_48KHZ
+  } else {
+    MAX_SUBSET_BLOCK_SIZE
+  };
+
+  stream_info.max_block_size <= max_block_size
+}
+
+/// An iterator over a reference of the decoded FLAC stream.
+pub struct Iter<'a, P, S>
+ where P: 'a + StreamProducer,
+       S: Sample{
+  stream: &'a mut Stream<P>,
+  channel: usize,
+  block_size: usize,
+  sample_index: usize,
+  samples_left: u64,
+  is_total_wrong: bool,
+  approximate: bool,
+  mapping: &'static [usize],
+  buffer: Vec<S>,
+}
+
+impl<'a, P, S> Iter<'a, P, S>
+ where P: StreamProducer,
+       S: Sample {
+  /// Returns true when the decoded frames disagree with the total samples
+  /// within `StreamInfo`.
+  ///
+  /// This is only known once a frame goes past the total samples, or once
+  /// the stream ends before reaching it. A total of zero means the total is
+  /// unknown, so it is never wrong.
+  #[inline]
+  pub fn is_total_samples_wrong(&self) -> bool {
+    self.is_total_wrong
+  }
+
+  /// Returns rolling statistics on the frames decoded so far.
+  #[inline]
+  pub fn stats(&self) -> &DecodeStats {
+    self.stream.stats()
+  }
+}
+
+impl<'a, P, S> Iter<'a, P, S>
+ where P: StreamProducer,
+       S: Sample {
+  /// Writes as many of the upcoming samples as fit within `output`, laid
+  /// out in the given PCM format, and returns the number of bytes written.
+  ///
+  /// Samples are interleaved by channel, in the same order the iterator
+  /// returns them, and each one takes up the fewest whole bytes that fit
+  /// the bits per sample of the stream, no matter the size of `S`. Only
+  /// whole samples get written, so fewer bytes than `output` holds are
+  /// written at the end of the stream or when less than a sample is left.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use flac::StreamReader;
+  /// use flac::pcm::PcmFormat;
+  ///
+  /// use std::fs::File;
+  ///
+  /// let filename   = "tests/assets/input-SVAUP.flac";
+  /// let mut stream = StreamReader::<File>::from_file(filename).unwrap();
+  /// let mut iter   = stream.iter::<i16>();
+  /// let mut output = [0; 4096];
+  ///
+  /// loop {
+  ///   let written = iter.write_samples_to(&mut output, PcmFormat::default());
+  ///
+  ///   if written == 0 {
+  ///     break;
+  ///   }
+  ///
+  ///   // Hand `&output[..written]` off to the audio device.
+  /// }
+  /// ```
+  pub fn write_samples_to(&mut self, output: &mut [u8], format: PcmFormat)
+                          -> usize {
+    let width       = sample_width(self.stream.info.bits_per_sample.get());
+    let mut written = 0;
+
+    while width > 0 && output.len() - written >= width {
+      if self.sample_index == self.block_size && !self.next_block() {
+        break;
+      }
+
+      let sample = S::to_i32_lossy(self.next_sample()) as i64;
+
+      format.pack(sample, &mut output[written..(written + width)]);
+
+      written += width;
+    }
+
+    written
+  }
+
+  // Decodes the next frame into the buffer, returning false once there are
+  // no frames left.
+  fn next_block(&mut self) -> bool {
+    let buffer      = &mut self.buffer;
+    let approximate = self.approximate;
+
+    let is_total_known = self.stream.info.total_samples > 0;
+
+    if let Some(block_size) = self.stream.next_frame(buffer, approximate) {
+      self.sample_index = 0;
+      self.block_size   = block_size;
+
+      if is_total_known && (block_size as u64) > self.samples_left {
+        self.is_total_wrong = true;
+      }
+
+      true
+    } else {
+      if is_total_known && self.samples_left > 0 {
+        self.is_total_wrong = true;
+      }
+
+      false
+    }
+  }
+
+  // Takes the sample of the current channel from the decoded frame and
+  // moves on to the next channel. There needs to be a sample left within
+  // the frame.
+  #[inline]
+  fn next_sample(&mut self) -> S {
+    let channels = self.stream.info.channels as usize;
+    let channel  = self.mapping.get(self.channel).map_or(self.channel,
+                                                         |&c| c);
+    let index    = self.sample_index + (channel * self.block_size);
+    let sample   = unsafe { *self.buffer.get_unchecked(index) };
+
+    self.channel += 1;
+
+    // Reset current channel
+    if self.channel == channels {
+      self.channel       = 0;
+      self.sample_index += 1;
+      self.samples_left  = self.samples_left.saturating_sub(1);
+    }
+
+    sample
+  }
+}
+
+impl<'a, P, S> Iter<'a, P, S>
+ where P: SeekableProducer,
+       S: Sample {
+  /// Moves the iterator back to the first sample of the stream.
+  pub fn rewind(&mut self) -> Result<(), ErrorKind> {
+    try!(self.stream.rewind());
+
+    self.channel      = 0;
+    self.block_size   = 0;
+    self.sample_index = 0;
+    self.samples_left = self.stream.info.total_samples;
+
+    self.is_total_wrong = false;
+
+    Ok(())
+  }
+}
+
+impl<'a, P, S> Iterator for Iter<'a, P, S>
+ where P: StreamProducer,
+       S: Sample {
+  type Item = S::Normal;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.sample_index == self.block_size && !self.next_block() {
+      return None;
+    }
+
+    S::to_normal(self.next_sample())
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    let channels = self.stream.info.channels as u64;
+
+    // The total samples are either unknown or can't be trusted, so nothing
+    // is known about how many samples are left.
+    if self.stream.info.total_samples == 0 || self.is_total_wrong {
+      return (0, None);
+    }
+
+    // Samples are counted per channel, with the channels of the current
+    // sample that were already returned taken out.
+    let samples_left = self.samples_left.saturating_mul(channels)
+                                        .saturating_sub(self.channel as u64);
+    let max_value    = usize::max_value() as u64;
+
+    // The stream can still end early, being cut short or failing to decode,
+    // so only the upper bound is known. There is a chance that samples_left
+    // will be larger than a usize since it is a u64. Make the upper bound
+    // None when it is.
+    if samples_left > max_value {
+      (0, None)
+    } else {
+      (0, Some(samples_left as usize))
+    }
+  }
+}
+
+/// An iterator over the decoded samples packed into bytes, see
+/// `Stream::iter_packed`.
+pub struct PackedIter<'a, P, T>
+ where P: 'a + StreamProducer {
+  iter: Iter<'a, P, i64>,
+  phantom: PhantomData<T>,
+}
+
+impl<'a, P, T> PackedIter<'a, P, T>
+ where P: SeekableProducer {
+  /// Moves the iterator back to the first sample of the stream.
+  #[inline]
+  pub fn rewind(&mut self) -> Result<(), ErrorKind> {
+    self.iter.rewind()
+  }
+}
+
+impl<'a, P, T> Iterator for PackedIter<'a, P, T>
+ where P: StreamProducer,
+       T: PackedSample {
+  type Item = T;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.iter.sample_index == self.iter.block_size &&
+       !self.iter.next_block() {
+      return None;
+    }
+
+    T::pack(self.iter.next_sample())
+  }
+
+  #[inline]
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.iter.size_hint()
+  }
+}
+
+/// An iterator over the decoded samples along with their positions, see
+/// `Stream::iter_positioned`.
+pub struct PositionedIter<'a, P, S>
+ where P: 'a + StreamProducer,
+       S: Sample {
+  iter: Iter<'a, P, S>,
+  // Sample number of the first sample within the current frame.
+  sample_number: u64,
+}
+
+impl<'a, P, S> PositionedIter<'a, P, S>
+ where P: StreamProducer,
+       S: Sample {
+  /// Returns true when the decoded frames disagree with the total samples
+  /// within `StreamInfo`, the same as `Iter::is_total_samples_wrong`.
+  #[inline]
+  pub fn is_total_samples_wrong(&self) -> bool {
+    self.iter.is_total_samples_wrong()
+  }
+}
+
+impl<'a, P, S> Iterator for PositionedIter<'a, P, S>
+ where P: StreamProducer,
+       S: Sample {
+  type Item = (u64, usize, S::Normal);
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.iter.sample_index == self.iter.block_size {
+      if !self.iter.next_block() {
+        return None;
+      }
+
+      // Progress is already past the frame that was just decoded.
+      self.sample_number = self.iter.stream.progress.samples -
+                           self.iter.block_size as u64;
+    }
+
+    let sample_number = self.sample_number + self.iter.sample_index as u64;
+    let channel       = self.iter.channel;
+
+    S::to_normal(self.iter.next_sample()).map(|value| {
+      (sample_number, channel, value)
+    })
+  }
+
+  #[inline]
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.iter.size_hint()
+  }
+}
+
+/// An iterator over the byte range and CRC-16 of each frame within a
+/// stream.
+pub struct FrameRanges<'a, P>
+ where P: 'a + SeekableProducer {
+  stream: &'a mut Stream<P>,
+  buffer: Vec<i64>,
+}
+
+impl<'a, P> Iterator for FrameRanges<'a, P>
+ where P: SeekableProducer {
+  type Item = FrameRange;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let stream      = &mut *self.stream;
+    let stream_info = &stream.info;
+    let context     = &mut stream.context;
+    let buffer      = &mut self.buffer;
+
+    loop {
+      if stream.cancel.is_cancelled() {
+        return None;
+      }
+
+      let start  = stream.producer.position();
+      let result = stream.producer.parse(|i| {
+        frame_parser_with_context(i, stream_info, context, buffer)
+      });
+
+      match result {
+        Ok(frame)                => {
+          let Footer(crc) = frame.footer;
+
+          return Some(FrameRange {
+            range: start..stream.producer.position(),
+            crc: crc,
+          });
+        }
+        Err(ErrorKind::Continue) => continue,
+        Err(_)                   => return None,
+      }
+    }
+  }
+}
+
+/// An iterator over the metadata blocks of a single type, along with the
+/// position each block was stored at within the stream.
+pub struct MetadataOfType<'a> {
+  blocks: slice::Iter<'a, Metadata>,
+  position: usize,
+  block_type: Type,
+}
+
+impl<'a> MetadataOfType<'a> {
+  fn new(blocks: &'a [Metadata], block_type: Type) -> Self {
+    MetadataOfType {
+      blocks: blocks.iter(),
+      // `StreamInfo` is always the first block and never within the slice.
+      position: 1,
+      block_type: block_type,
+    }
+  }
+}
+
+impl<'a> Iterator for MetadataOfType<'a> {
+  type Item = (usize, &'a Metadata);
+
+  fn next(&mut self) -> Option<Self::Item> {
+    while let Some(block) = self.blocks.next() {
+      let position = self.position;
+
+      self.position += 1;
+
+      if block.data_type() == self.block_type {
+        return Some((position, block));
+      }
+    }
+
+    None
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (0, self.blocks.size_hint().1)
+  }
+}
+
+/// Samples of a single decoded frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Block<S> {
+  /// Sample number of the first sample within the block.
+  pub sample_number: u64,
+  /// Number of channels the samples are interleaved by.
+  pub channels: usize,
+  /// Samples interleaved by channel, in the channel order of the stream.
+  pub samples: Vec<S>,
+}
+
+impl<S> Block<S> {
+  /// Returns the number of samples, per channel, within the block.
+  #[inline]
+  pub fn len(&self) -> usize {
+    if self.channels == 0 { 0 } else { self.samples.len() / self.channels }
+  }
+
+  /// Returns true when the block holds no samples.
+  #[inline]
+  pub fn is_empty(&self) -> bool {
+    self.samples.is_empty()
+  }
+}
+
+/// An iterator over the decoded frames of a stream.
+///
+/// Iteration stops at the end of the stream, or at the first frame that
+/// fails to decode. A sample that doesn't fit within the requested size is
+/// returned as `ErrorKind::Unsupported`, ending the iteration.
+pub struct Blocks<'a, P, S>
+ where P: 'a + StreamProducer,
+       S: Sample {
+  stream: &'a mut Stream<P>,
+  sample_number: u64,
+  is_finished: bool,
+  mapping: &'static [usize],
+  buffer: Vec<S>,
+}
+
+impl<'a, P, S> Blocks<'a, P, S>
+ where P: StreamProducer,
+       S: Sample {
+  /// Returns how far decoding has gotten, which puts the byte offset at the
+  /// end of the last block returned.
+  #[inline]
+  pub fn decode_progress(&self) -> DecodeProgress {
+    self.stream.decode_progress()
+  }
+
+  /// Returns the `StreamInfo` of the stream the blocks are decoded from.
+  #[inline]
+  pub fn info(&self) -> StreamInfo {
+    self.stream.info()
+  }
+}
+
+impl<'a, P, S> Iterator for Blocks<'a, P, S>
+ where P: StreamProducer,
+       S: Sample {
+  type Item = Result<Block<S::Normal>, ErrorKind>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.is_finished {
+      return None;
+    }
+
+    let channels   = self.stream.info.channels as usize;
+    let block_size = match self.stream.next_frame(&mut self.buffer, false) {
+      Some(block_size) => block_size,
+      None             => {
+        self.is_finished = true;
+
+        return None;
+      }
+    };
+
+    let mut samples = Vec::with_capacity(block_size * channels);
+    let result      = interleave(&self.buffer, block_size, channels,
+                                 self.mapping, &mut samples);
+
+    if let Err(error) = result {
+      self.is_finished = true;
+
+      return Some(Err(error));
+    }
+
+    let block = Block {
+      sample_number: self.sample_number,
+      channels: channels,
+      samples: samples,
+    };
+
+    self.sample_number += block_size as u64;
+
+    Some(Ok(block))
+  }
+}
+
+/// An iterator over overlapping windows of mono samples, see
+/// `Stream::analysis_windows`.
+///
+/// Iteration stops at the end of the stream, or at the first frame that
+/// fails to decode, which `AnalysisWindows::decode_progress` holds the
+/// error of.
+pub struct AnalysisWindows<'a, P>
+ where P: 'a + StreamProducer {
+  stream: &'a mut Stream<P>,
+  size: usize,
+  hop: usize,
+  scale: f32,
+  // Mixed samples from the start of the next window onward.
+  pending: VecDeque<f32>,
+  // Number of samples at the front of `pending` already within a window.
+  covered: usize,
+  // Number of samples left to drop before the next window starts, when the
+  // hop is larger than the window.
+  skip: usize,
+  is_finished: bool,
+  buffer: Vec<i64>,
+}
+
+impl<'a, P> AnalysisWindows<'a, P> where P: StreamProducer {
+  /// Returns how far decoding has gotten.
+  #[inline]
+  pub fn decode_progress(&self) -> DecodeProgress {
+    self.stream.decode_progress()
+  }
+
+  // Decodes the next frame and mixes its samples onto the end of
+  // `pending`, returning false once there are no frames left.
+  fn mix_frame(&mut self) -> bool {
+    let channels   = self.stream.info.channels as usize;
+    let block_size = match self.stream.next_frame(&mut self.buffer, false) {
+      Some(block_size) => block_size,
+      None             => return false,
+    };
+
+    for i in 0..block_size {
+      let sum = (0..channels).fold(0, |result, channel| {
+        result + self.buffer[i + channel * block_size]
+      });
+
+      if self.skip > 0 {
+        self.skip -= 1;
+      } else {
+        self.pending.push_back(sum as f32 * self.scale);
+      }
+    }
+
+    true
+  }
+}
+
+impl<'a, P> Iterator for AnalysisWindows<'a, P> where P: StreamProducer {
+  type Item = Vec<f32>;
 
-/// FLAC stream that decodes and hold file information.
-pub struct Stream<P: StreamProducer> {
-  info: StreamInfo,
-  metadata: Vec<Metadata>,
-  producer: P,
+  fn next(&mut self) -> Option<Self::Item> {
+    while !self.is_finished && self.pending.len() < self.size {
+      self.is_finished = !self.mix_frame();
+    }
+
+    // Only samples that no window has covered yet are worth padding out.
+    if self.pending.len() <= self.covered {
+      return None;
+    }
+
+    let mut window = self.pending.iter().take(self.size).cloned()
+                                 .collect::<Vec<_>>();
+
+    window.resize(self.size, 0.0);
+
+    let hop = cmp::min(self.hop, self.pending.len());
+
+    self.pending.drain(0..hop);
+
+    self.skip    = self.hop - hop;
+    self.covered = self.size.saturating_sub(self.hop);
+
+    Some(window)
+  }
 }
 
-/// Alias for a FLAC stream produced from `Read`.
-pub type StreamReader<R>  = Stream<ReadStream<R>>;
+/// Decoded samples held around a focus position, meant for scrubbing back
+/// and forth through a stream.
+///
+/// Moving the focus forward only decodes the frames past the samples that
+/// are already held. Moving it backward, or far enough forward that nothing
+/// held would be kept, seeks through the `SeekTable` instead of decoding
+/// from the first frame. Samples are interleaved by channel, in the channel
+/// order of the stream.
+pub struct Window<'a, P, S>
+ where P: 'a + SeekableProducer,
+       S: Sample {
+  stream: &'a mut Stream<P>,
+  radius: u64,
+  start: u64,
+  end: u64,
+  is_positioned: bool,
+  mapping: &'static [usize],
+  samples: Vec<S::Normal>,
+  buffer: Vec<S>,
+}
 
-/// Alias for a FLAC stream produced from a byte stream buffer.
-pub type StreamBuffer<'a> = Stream<ByteStream<'a>>;
+impl<'a, P, S> Window<'a, P, S>
+ where P: SeekableProducer,
+       S: Sample {
+  /// Moves the focus to the given sample number, decoding everything
+  /// within the radius of it.
+  ///
+  /// Fewer samples end up being held near the end of the stream, or when a
+  /// frame fails to decode.
+  ///
+  /// Fails when the stream fails to seek.
+  pub fn focus(&mut self, sample_number: u64) -> Result<(), ErrorKind> {
+    let low  = sample_number.saturating_sub(self.radius);
+    let high = sample_number.saturating_add(self.radius).saturating_add(1);
 
-impl<P> Stream<P> where P: StreamProducer {
-  /// Constructor for the default state of a FLAC stream.
-  #[inline]
-  pub fn new<R: io::Read>(reader: R) -> Result<StreamReader<R>, ErrorKind> {
-    let producer = ReadStream::new(reader);
+    // Seeking only pays off when none of the held samples would be kept,
+    // and the gap is larger than the window itself.
+    let gap       = self.radius.saturating_mul(2);
+    let is_behind = low < self.start;
+    let is_ahead  = low > self.end.saturating_add(gap);
+
+    if !self.is_positioned || is_behind || is_ahead {
+      let sample = try!(self.stream.seek(low));
+
+      self.start         = sample;
+      self.end           = sample;
+      self.is_positioned = true;
+
+      self.samples.clear();
+    }
+
+    self.drop_before(low);
 
-    Stream::from_stream_producer(producer)
+    while self.end < high {
+      if !self.decode_frame() {
+        break;
+      }
+
+      self.drop_before(low);
+    }
+
+    Ok(())
   }
 
-  /// Returns information for the current stream.
+  /// Returns the sample number of the first sample held.
   #[inline]
-  pub fn info(&self) -> StreamInfo {
-    self.info
+  pub fn start(&self) -> u64 {
+    self.start
   }
 
-  /// Returns a slice of `Metadata`
-  ///
-  /// This slice excludes `StreamInfo`, which is located in `Stream::info`.
-  /// Everything else is related to metadata for the FLAC stream is in the
-  /// slice.
+  /// Returns every sample held, interleaved by channel.
   #[inline]
-  pub fn metadata(&self) -> &[Metadata] {
-    &self.metadata
+  pub fn samples(&self) -> &[S::Normal] {
+    &self.samples
   }
 
-  /// Constructs a decoder with the given file name.
-  ///
-  /// # Failures
-  ///
-  /// * `ErrorKind::IO(io::ErrorKind::NotFound)` is returned when the given
-  ///   filename isn't found.
-  /// * `ErrorKind::IO(io::ErrorKind::InvalidData)` is returned when the data
-  ///   within the file isn't valid FLAC data.
-  /// * Several different parser specific errors that are structured as
-  ///   `ErrorKind::<parser_name>Parser`.
-  /// * Several different invalidation specific errors that are
-  ///   structured as `ErrorKind::Invalid<invalidation_name>`.
+  /// Returns the sample of each channel at the given sample number, when
+  /// it's held.
+  pub fn get(&self, sample_number: u64) -> Option<&[S::Normal]> {
+    if sample_number < self.start || sample_number >= self.end {
+      return None;
+    }
+
+    let channels = self.stream.info.channels as usize;
+    let index    = (sample_number - self.start) as usize * channels;
+
+    self.samples.get(index..(index + channels))
+  }
+
+  // Removes the samples that come before the given sample number.
+  fn drop_before(&mut self, sample_number: u64) {
+    let channels = self.stream.info.channels as usize;
+    let count    = cmp::min(sample_number, self.end)
+                     .saturating_sub(self.start);
+
+    if count > 0 {
+      self.samples.drain(0..(count as usize * channels));
+
+      self.start += count;
+    }
+  }
+
+  // Decodes the next frame onto the end of the samples held, returning
+  // false when there are no more frames.
+  fn decode_frame(&mut self) -> bool {
+    let channels   = self.stream.info.channels as usize;
+    let block_size = match self.stream.next_frame(&mut self.buffer, false) {
+      Some(block_size) => block_size,
+      None             => return false,
+    };
+
+    for i in 0..block_size {
+      for position in 0..channels {
+        let channel = self.mapping.get(position).map_or(position, |&c| c);
+        let sample  = self.buffer[i + channel * block_size];
+
+        match S::to_normal(sample) {
+          Some(sample) => self.samples.push(sample),
+          None         => {
+            // Keep only whole samples, since the rest of the frame is lost.
+            let length = self.samples.len() - position;
+
+            self.samples.truncate(length);
+
+            self.end          += i as u64;
+            self.is_positioned = false;
+
+            return false;
+          }
+        }
+      }
+    }
+
+    self.end += block_size as u64;
+
+    true
+  }
+}
+
+// Part of the stream a `PushDecoder` expects next.
+#[derive(PartialEq, Eq)]
+enum PushState {
+  Header,
+  Metadata,
+  Frames,
+}
+
+/// Decoder for FLAC bytes that arrive a piece at a time, like over a
+/// network, rather than being read from a source.
+///
+/// Each frame gets decoded and handed out as soon as its last byte is
+/// pushed, since the end of a frame is known once its CRC-16 footer has
+/// been parsed. No more than a single frame's worth of bytes is held, and
+/// none of the decoded samples are held onto between calls. Samples are
+/// interleaved by channel, in the FLAC channel order.
+///
+/// # Examples
+///
+/// ```no_run
+/// use flac::stream::PushDecoder;
+///
+/// # let packets: Vec<Vec<u8>> = Vec::new();
+/// let mut decoder = PushDecoder::new::<i16>();
+///
+/// for packet in packets {
+///   decoder.push(&packet, |samples| {
+///     // Play back the samples of a single frame.
+///   }).unwrap();
+/// }
+/// ```
+pub struct PushDecoder<S: Sample> {
+  info: Option<StreamInfo>,
+  metadata: Vec<Metadata>,
+  bytes: Vec<u8>,
+  buffer: Vec<S>,
+  samples: Vec<S::Normal>,
+  state: PushState,
+  // Number of bytes, from the start of `bytes`, that `crc` covers.
+  scanned: usize,
+  crc: u16,
+  // Number of bytes of the next frame after which it gets parsed even
+  // without the CRC-16 coming to zero, doubling with every try.
+  next_parse: usize,
+  error: Option<ErrorKind>,
+}
+
+impl<S> PushDecoder<S> where S: Sample {
+  /// Constructs a decoder that hands out samples of type `T`.
+  pub fn new<T>() -> Self
+   where T: SampleSize<Extended = S> {
+    PushDecoder {
+      info: None,
+      metadata: Vec::new(),
+      bytes: Vec::new(),
+      buffer: Vec::new(),
+      samples: Vec::new(),
+      state: PushState::Header,
+      scanned: 0,
+      crc: 0,
+      next_parse: MAX_FRAME_HEADER_LENGTH,
+      error: None,
+    }
+  }
+
+  /// Returns the information of the stream, once all of the metadata has
+  /// been pushed.
   #[inline]
-  pub fn from_file(filename: &str) -> Result<StreamReader<File>, ErrorKind> {
-    File::open(filename).map_err(|e| ErrorKind::IO(e.kind()))
-                        .and_then(|file| {
-      let producer = ReadStream::new(file);
+  pub fn info(&self) -> Option<StreamInfo> {
+    if self.state == PushState::Frames { self.info } else { None }
+  }
 
-      Stream::from_stream_producer(producer)
-    })
+  /// Returns every metadata block other than `StreamInfo`, once all of the
+  /// metadata has been pushed.
+  #[inline]
+  pub fn metadata(&self) -> &[Metadata] {
+    &self.metadata
   }
 
-  /// Constructs a decoder with the given buffer.
+  /// Appends bytes of the stream, calling `f` with the samples of every
+  /// frame that's now complete, in order.
   ///
-  /// This constructor assumes that an entire FLAC file is in the buffer.
+  /// The metadata at the start of the stream gets parsed first, which
+  /// hands out no samples. Pushed bytes are only gone over once, with a
+  /// frame getting parsed when the CRC-16 of the bytes since the end of
+  /// the last frame matches, instead of on every push.
   ///
   /// # Failures
   ///
-  /// * `ErrorKind::IO(io::ErrorKind::InvalidData)` is returned when the data
-  ///   within the file isn't valid FLAC data.
-  /// * Several different parser specific errors that are structured as
-  ///   `ErrorKind::<parser_name>Parser`.
-  /// * Several different invalidation specific errors that are
-  ///   structured as `ErrorKind::Invalid<invalidation_name>`.
-  #[inline]
-  pub fn from_buffer(buffer: &[u8]) -> Result<StreamBuffer, ErrorKind> {
-    let producer = ByteStream::new(buffer);
+  /// * `ErrorKind::Unsupported` is returned when the maximum block size is
+  ///   larger than the FLAC subset allows, or a sample doesn't fit within
+  ///   the sample type.
+  /// * Any error from parsing the metadata or a frame. The decoder can't
+  ///   continue past these, and every later push fails with the same
+  ///   error.
+  pub fn push<F>(&mut self, bytes: &[u8], f: F) -> Result<(), ErrorKind>
+   where F: FnMut(&[S::Normal]) {
+    if let Some(error) = self.error {
+      return Err(error);
+    }
+
+    self.bytes.extend_from_slice(bytes);
+
+    let result = if self.state != PushState::Frames {
+      self.push_metadata().and_then(|is_parsed| {
+        if is_parsed { self.push_frames(f) } else { Ok(()) }
+      })
+    } else {
+      self.push_frames(f)
+    };
 
-    Stream::from_stream_producer(producer)
+    if let Err(error) = result {
+      self.error = Some(error);
+    }
+
+    result
   }
 
-  fn from_stream_producer(mut producer: P) -> Result<Self, ErrorKind> {
-    let mut stream_info = Default::default();
-    let mut metadata    = Vec::new();
+  // Decodes every frame that's complete within the pushed bytes, dropping
+  // their bytes afterwards.
+  fn push_frames<F>(&mut self, mut f: F) -> Result<(), ErrorKind>
+   where F: FnMut(&[S::Normal]) {
+    let mut start = 0;
 
-    many_metadata(&mut producer, |block| {
-      if let metadata::Data::StreamInfo(info) = block.data {
-        stream_info = info;
-      } else {
-        metadata.push(block);
-      }
-    }).map(|_| {
-      Stream {
-        info: stream_info,
-        metadata: metadata,
-        producer: producer,
+    while self.scanned < self.bytes.len() {
+      self.crc      = update_crc16(self.crc, self.bytes[self.scanned]);
+      self.scanned += 1;
+
+      // A frame ends where the CRC-16 over all of it, footer included,
+      // comes to zero. Parsing once the bytes double as well makes sure a
+      // damaged frame, whose CRC-16 never matches, still fails.
+      let length = self.scanned - start;
+
+      if self.crc != 0 && length < self.next_parse {
+        continue;
       }
-    })
-  }
 
-  /// Returns an iterator over the decoded samples.
-  #[inline]
-  pub fn iter<S: SampleSize>(&mut self) -> Iter<P, S::Extended> {
-    let samples_left = self.info.total_samples;
-    let channels     = self.info.channels as usize;
-    let block_size   = self.info.max_block_size as usize;
-    let buffer_size  = block_size * channels;
+      let info         = self.info.as_ref().unwrap();
+      let buffer       = &mut self.buffer;
+      let mut producer = ByteStream::new(&self.bytes[start..self.scanned]);
 
-    Iter {
-      stream: self,
-      channel: 0,
-      block_size: 0,
-      sample_index: 0,
-      samples_left: samples_left,
-      buffer: vec![S::Extended::from_i8(0); buffer_size]
+      match producer.parse(|i| frame_parser(i, info, buffer)) {
+        Ok(frame)                     => {
+          let block_size = decode_frame(&frame, buffer, false);
+          let channels   = frame.header.channels as usize;
+
+          self.samples.clear();
+
+          try!(interleave(buffer, block_size, channels, &[],
+                          &mut self.samples));
+
+          f(&self.samples);
+
+          start          += length - producer.len();
+          self.scanned    = start;
+          self.crc        = 0;
+          self.next_parse = MAX_FRAME_HEADER_LENGTH;
+        }
+        Err(ErrorKind::Incomplete(_)) |
+        Err(ErrorKind::EndOfInput)    => {
+          if length >= self.next_parse {
+            self.next_parse = length * 2;
+          }
+        }
+        Err(error)                    => return Err(error),
+      }
     }
+
+    self.bytes.drain(0..start);
+
+    self.scanned -= start;
+
+    Ok(())
   }
 
-  fn next_frame<S>(&mut self, buffer: &mut [S]) -> Option<usize>
-   where S: Sample {
-    let stream_info = &self.info;
+  // Parses the "fLaC" header and every metadata block that's been pushed
+  // in full, dropping their bytes, and returns false until the last block
+  // is parsed.
+  fn push_metadata(&mut self) -> Result<bool, ErrorKind> {
+    if self.state == PushState::Header {
+      if self.bytes.len() < 4 {
+        return Ok(false);
+      }
+
+      if &self.bytes[0..4] != b"fLaC" {
+        return Err(ErrorKind::HeaderParser);
+      }
+
+      self.bytes.drain(0..4);
+
+      self.state = PushState::Metadata;
+    }
 
     loop {
-      match self.producer.parse(|i| frame_parser(i, stream_info, buffer)) {
-        Ok(frame)                => {
-          let channels   = frame.header.channels as usize;
-          let block_size = frame.header.block_size as usize;
-          let subframes  = frame.subframes[0..channels].iter();
+      // Waits for the whole block, going by the length within its header,
+      // so no block gets parsed more than once.
+      if self.bytes.len() < 4 ||
+         self.bytes.len() < 4 + to_u32(&self.bytes[1..4]) as usize {
+        return Ok(false);
+      }
 
-          for (channel, subframe) in subframes.enumerate() {
-            let start  = channel * block_size;
-            let end    = (channel + 1) * block_size;
-            let output = &mut buffer[start..end];
+      let (block, consumed) = {
+        let mut producer = ByteStream::new(&self.bytes);
+        let block        = try!(metadata_block(&mut producer,
+                                               &DecodeContext::new()));
 
-            subframe::decode(&subframe, block_size, output);
-          }
+        (block, self.bytes.len() - producer.len())
+      };
 
-          frame::decode(frame.header.channel_assignment, buffer);
+      self.bytes.drain(0..consumed);
 
-          return Some(block_size);
+      let is_last = block.is_last();
+
+      match block.data {
+        metadata::Data::StreamInfo(info) if self.info.is_none() => {
+          if !is_subset_block_size(&info) {
+            return Err(ErrorKind::Unsupported);
+          }
+
+          self.info = Some(info);
         }
-        Err(ErrorKind::Continue) => continue,
-        Err(_)                   => return None,
+        _ if self.info.is_none() => return Err(ErrorKind::NotFound),
+        _                        => self.metadata.push(block),
+      }
+
+      if is_last {
+        break;
       }
     }
+
+    let info        = self.info.unwrap();
+    let buffer_size = info.max_block_size as usize * info.channels as usize;
+
+    self.state   = PushState::Frames;
+    self.buffer  = vec![S::from_i8(0); buffer_size];
+    self.samples = Vec::with_capacity(buffer_size);
+
+    Ok(true)
   }
 }
 
-/// An iterator over a reference of the decoded FLAC stream.
-pub struct Iter<'a, P, S>
- where P: 'a + StreamProducer,
-       S: Sample{
-  stream: &'a mut Stream<P>,
-  channel: usize,
-  block_size: usize,
-  sample_index: usize,
-  samples_left: u64,
+/// Decoder for single, complete frames handed over by the demuxer of a
+/// container, like MP4 or Matroska, with `StreamInfo` given separately.
+///
+/// Containers store each FLAC frame as its own packet and keep the metadata
+/// within their codec configuration, so there's no "fLaC" header or
+/// metadata blocks in front of the frames. The decode buffer and the
+/// position within the stream are kept between frames. Samples are
+/// interleaved by channel, in the FLAC channel order.
+///
+/// # Examples
+///
+/// ```no_run
+/// use flac::stream::FrameFeed;
+///
+/// # let codec_config: Vec<u8> = Vec::new();
+/// # let packets: Vec<Vec<u8>> = Vec::new();
+/// // The `dfLa` box of MP4, or `CodecPrivate` of Matroska.
+/// let mut feed = FrameFeed::from_codec_config::<i16>(&codec_config)
+///                  .unwrap();
+///
+/// for packet in packets {
+///   let samples = feed.push_frame(&packet).unwrap();
+///
+///   // Play back the samples of a single frame.
+/// }
+/// ```
+pub struct FrameFeed<S: Sample> {
+  info: StreamInfo,
+  metadata: Vec<Metadata>,
   buffer: Vec<S>,
+  samples: Vec<S::Normal>,
+  position: u64,
 }
 
-impl<'a, P, S> Iterator for Iter<'a, P, S>
- where P: StreamProducer,
-       S: Sample {
-  type Item = S::Normal;
+impl<S> FrameFeed<S> where S: Sample {
+  /// Constructs a decoder for the frames of a stream with the given
+  /// information, handing out samples of type `T`.
+  ///
+  /// # Failures
+  ///
+  /// * `ErrorKind::Unsupported` is returned when the maximum block size is
+  ///   larger than the FLAC subset allows.
+  pub fn new<T>(info: StreamInfo) -> Result<Self, ErrorKind>
+   where T: SampleSize<Extended = S> {
+    FrameFeed::with_metadata(info, Vec::new())
+  }
 
-  fn next(&mut self) -> Option<Self::Item> {
-    if self.sample_index == self.block_size {
-      let buffer = &mut self.buffer;
+  /// Constructs a decoder from the FLAC codec configuration of a
+  /// container, handing out samples of type `T`.
+  ///
+  /// The configuration is the metadata blocks at the start of a stream,
+  /// like the payload of the `dfLa` box within MP4 after its version and
+  /// flags, optionally starting with the "fLaC" header, like the
+  /// `CodecPrivate` of Matroska.
+  ///
+  /// # Failures
+  ///
+  /// * `ErrorKind::NotFound` is returned when there's no `StreamInfo`.
+  /// * `ErrorKind::Unsupported` is returned when the maximum block size is
+  ///   larger than the FLAC subset allows.
+  /// * Any error from parsing the metadata blocks.
+  pub fn from_codec_config<T>(config: &[u8]) -> Result<Self, ErrorKind>
+   where T: SampleSize<Extended = S> {
+    let mut bytes = Vec::with_capacity(config.len() + 4);
 
-      if let Some(block_size) = self.stream.next_frame(buffer) {
-        self.sample_index = 0;
-        self.block_size   = block_size;
-      } else {
-        return None;
-      }
+    if !config.starts_with(b"fLaC") {
+      bytes.extend_from_slice(b"fLaC");
     }
 
-    let channels = self.stream.info.channels as usize;
-    let index    = self.sample_index + (self.channel * self.block_size);
-    let sample   = unsafe { *self.buffer.get_unchecked(index) };
+    bytes.extend_from_slice(config);
 
-    self.channel += 1;
+    let mut info     = None;
+    let mut metadata = Vec::new();
 
-    // Reset current channel
-    if self.channel == channels {
-      self.channel       = 0;
-      self.sample_index += 1;
-      self.samples_left -= 1;
+    {
+      let mut producer = ByteStream::new(&bytes);
+
+      try!(many_metadata(&mut producer, &DecodeContext::new(), |block| {
+        if let metadata::Data::StreamInfo(stream_info) = block.data {
+          info = Some(stream_info);
+        } else {
+          metadata.push(block);
+        }
+      }));
     }
 
-    S::to_normal(sample)
+    match info {
+      Some(info) => FrameFeed::with_metadata(info, metadata),
+      None       => Err(ErrorKind::NotFound),
+    }
   }
 
-  fn size_hint(&self) -> (usize, Option<usize>) {
-    let samples_left = self.samples_left as usize;
-    let max_value    = usize::max_value() as u64;
+  fn with_metadata(info: StreamInfo, metadata: Vec<Metadata>)
+                   -> Result<Self, ErrorKind> {
+    if !is_subset_block_size(&info) {
+      return Err(ErrorKind::Unsupported);
+    }
 
-    // There is a chance that samples_left will be larger than a usize since
-    // it is a u64. Make the upper bound None when it is.
-    if self.samples_left > max_value {
-      (samples_left, None)
-    } else {
-      (samples_left, Some(samples_left))
+    let buffer_size = info.max_block_size as usize * info.channels as usize;
+
+    Ok(FrameFeed {
+      info: info,
+      metadata: metadata,
+      buffer: vec![S::from_i8(0); buffer_size],
+      samples: Vec::with_capacity(buffer_size),
+      position: 0,
+    })
+  }
+
+  /// Returns information for the stream.
+  #[inline]
+  pub fn info(&self) -> StreamInfo {
+    self.info
+  }
+
+  /// Returns every metadata block other than `StreamInfo` from the codec
+  /// configuration.
+  #[inline]
+  pub fn metadata(&self) -> &[Metadata] {
+    &self.metadata
+  }
+
+  /// Returns the sample number right after the last frame decoded, which
+  /// is where the next frame is expected to start.
+  #[inline]
+  pub fn position(&self) -> u64 {
+    self.position
+  }
+
+  /// Decodes a single, complete frame, returning its samples.
+  ///
+  /// The position of the frame comes from the frame header, so frames can
+  /// be pushed out of order, like after the container seeks.
+  ///
+  /// # Failures
+  ///
+  /// * `ErrorKind::Incomplete` is returned when the frame is cut short.
+  /// * `ErrorKind::TrailingBytes` is returned when there are bytes left
+  ///   after the frame, which means the packet holds more than a single
+  ///   frame.
+  /// * `ErrorKind::Unsupported` is returned when a sample doesn't fit
+  ///   within the sample type.
+  /// * Any error from parsing the frame.
+  pub fn push_frame(&mut self, frame: &[u8])
+                    -> Result<&[S::Normal], ErrorKind> {
+    let info         = &self.info;
+    let buffer       = &mut self.buffer;
+    let mut producer = ByteStream::new(frame);
+
+    let frame = try!(producer.parse(|i| frame_parser(i, info, buffer)));
+
+    if !producer.is_empty() {
+      return Err(ErrorKind::TrailingBytes);
     }
+
+    let block_size = decode_frame(&frame, buffer, false);
+    let channels   = frame.header.channels as usize;
+
+    self.samples.clear();
+
+    try!(interleave(buffer, block_size, channels, &[], &mut self.samples));
+
+    let start = match frame.header.number {
+      NumberType::Frame(number)  => {
+        number as u64 * info.max_block_size as u64
+      }
+      NumberType::Sample(number) => number,
+    };
+
+    self.position = start + block_size as u64;
+
+    Ok(&self.samples)
   }
 }
 
@@ -1,6 +1,8 @@
 use subframe::{self, Subframe, MAX_FIXED_ORDER, MAX_LPC_ORDER};
 use utility::Sample;
 
+use std::cmp;
+
 // Restore the original signal from a fixed linear prediction.
 //
 // Signal restoration is accomplished by summing up the residual and the
@@ -74,6 +76,38 @@ pub fn lpc_restore_signal<S: Sample>(quantization_level: i8,
   }
 }
 
+// Approximate the original signal without any linear prediction.
+//
+// Every residual value gets the last warm up value added to it, which skips
+// all the multiplications needed for restoring the signal. The result is
+// far from the original signal, but it starts from the right value and
+// follows the energy of the residual, which is enough for a rough sketch
+// of the signal.
+//
+// The sums are done in 64 bits and clamped to what `S` can hold, up to 32
+// bits, since a residual value far from the warm up can push past it.
+//
+// This function also assumes that `output` already has the warm up values
+// in it.
+pub fn approximate_signal<S: Sample>(order: usize,
+                                     block_size: usize,
+                                     output: &mut [S]) {
+  if order == 0 {
+    return;
+  }
+
+  let bits = cmp::min(S::size_extended(), 32);
+  let min  = S::to_i32_lossy(S::min_for_bps(bits)) as i64;
+  let max  = S::to_i32_lossy(S::max_for_bps(bits)) as i64;
+  let base = S::to_i32_lossy(output[order - 1]) as i64;
+
+  for value in &mut output[order..block_size] {
+    let sum = S::to_i32_lossy(*value) as i64 + base;
+
+    *value = S::from_i32_lossy(cmp::max(min, cmp::min(max, sum)) as i32);
+  }
+}
+
 /// Decodes the current subframe.
 ///
 /// * `Constant` - fills the length of `output` with the constant value
@@ -132,6 +166,35 @@ pub fn decode<S>(subframe: &Subframe, block_size: usize, output: &mut [S])
   }
 }
 
+/// Decodes the current subframe, approximating `LPC` subframes.
+///
+/// Rather than restoring the signal of the finite impulse response linear
+/// prediction, the last warm up value gets added to each residual value.
+/// Every other subframe gets decoded the same way `decode` does it.
+pub fn decode_approximate<S>(subframe: &Subframe, block_size: usize,
+                             output: &mut [S])
+ where S: Sample {
+  if let subframe::Data::LPC(ref lpc) = subframe.data {
+    let order = lpc.order as usize;
+
+    for i in 0..order {
+      let warmup = S::from_i32_lossy(lpc.warmup[i]);
+
+      output[i] = warmup;
+    }
+
+    approximate_signal(order, block_size, output);
+
+    if subframe.wasted_bits > 0 {
+      for value in output {
+        *value <<= subframe.wasted_bits;
+      }
+    }
+  } else {
+    decode(subframe, block_size, output);
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -200,10 +263,7 @@ mod tests {
           method_type: CodingMethod::PartitionedRice,
           data: PartitionedRice {
             order: 0,
-            contents: PartitionedRiceContents {
-              capacity: 0,
-              data: Vec::new(),
-            },
+            contents: PartitionedRiceContents::new(0),
           },
         },
         order: 3,
@@ -220,10 +280,7 @@ mod tests {
           method_type: CodingMethod::PartitionedRice,
           data: PartitionedRice {
             order: 0,
-            contents: PartitionedRiceContents {
-              capacity: 0,
-              data: Vec::new(),
-            },
+            contents: PartitionedRiceContents::new(0),
           },
         },
         order: 7,
@@ -272,6 +329,70 @@ mod tests {
     }
   }
 
+  #[test]
+  fn test_approximate_signal() {
+    let mut output = [-796, -547, -285, -32, 199, 443, 670, -2, -23, 14, 6, 3
+                     , -4, 12, -2, 10];
+
+    approximate_signal(7, 16, &mut output);
+
+    assert_eq!(&output, &[-796, -547, -285, -32, 199, 443, 670, 668, 647, 684
+                         , 676, 673, 666, 682, 668, 680]);
+
+    let mut output = [i32::max_value(), 1, -1, i32::min_value()];
+
+    approximate_signal(1, 4, &mut output);
+
+    assert_eq!(&output, &[i32::max_value(), i32::max_value(), 2147483646, -1]);
+
+    let min        = i16::min_value();
+    let mut output = [min, -1, 0];
+
+    approximate_signal(1, 3, &mut output);
+
+    assert_eq!(&output, &[min, min, min]);
+  }
+
+  #[test]
+  fn test_decode_approximate() {
+    let mut output = [0; 4];
+
+    let constant = Subframe {
+      data: subframe::Data::Constant(3),
+      wasted_bits: 1,
+    };
+
+    decode_approximate(&constant, 4, &mut output);
+    assert_eq!(&output, &[6, 6, 6, 6]);
+
+    let lpc = Subframe {
+      data: subframe::Data::LPC(LPC {
+        entropy_coding_method: EntropyCodingMethod {
+          method_type: CodingMethod::PartitionedRice,
+          data: PartitionedRice {
+            order: 0,
+            contents: PartitionedRiceContents::new(0),
+          },
+        },
+        order: 2,
+        qlp_coeff_precision: 0,
+        quantization_level: 9,
+        qlp_coefficients: [1042, -399, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
+                          ,0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        warmup: [-796, -547, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
+                ,0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        residual: vec![-2, 10],
+      }),
+      wasted_bits: 0,
+    };
+
+    output[2] = -2;
+    output[3] = 10;
+
+    decode_approximate(&lpc, 4, &mut output);
+    assert_eq!(&output, &[-796, -547, -549, -537]);
+  }
+
   #[test]
   fn test_wasted_bit_decode() {
     let mut output = [0; 4];
@@ -0,0 +1,628 @@
+use subframe::MAX_FIXED_ORDER;
+use utility::{BitWriter, Sample};
+
+use std::cmp;
+use std::io;
+
+// Largest Rice parameter for each coding method, the escape code not being
+// included.
+const MAX_RICE_PARAMETER: u32  = 14;
+const MAX_RICE2_PARAMETER: u32 = 30;
+
+// Largest partition order tried when searching for the smallest residual.
+// The format allows up to 15, but the gains past 8 are close to nothing.
+const MAX_PARTITION_ORDER: u32 = 8;
+
+// Largest partition order the format allows.
+const MAX_FORMAT_PARTITION_ORDER: u32 = 15;
+
+/// How a residual is split into partitions and Rice coded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RiceCoding {
+  /// Number of partitions the residual is split into, as a power of two.
+  pub partition_order: u32,
+  /// Rice parameter of each partition, in order.
+  pub parameters: Vec<u32>,
+  /// Estimated number of bits the coded partitions take up, including the
+  /// parameter of each partition but not the coding method or partition
+  /// order. The estimate never falls short of the actual size.
+  pub bit_count: u64,
+}
+
+impl RiceCoding {
+  /// Returns true when a parameter is too large for the four bit parameters
+  /// of the first coding method, needing the five bits of the second.
+  pub fn is_rice2(&self) -> bool {
+    self.parameters.iter().any(|&parameter| parameter > MAX_RICE_PARAMETER)
+  }
+}
+
+// Residual of a prediction along with how it gets Rice coded.
+struct Residual {
+  order: usize,
+  values: Vec<i64>,
+  coding: RiceCoding,
+}
+
+// Zigzag encoding of a signed residual, mapping it to an unsigned value.
+#[inline]
+fn fold(value: i64) -> u64 {
+  ((value << 1) ^ (value >> 63)) as u64
+}
+
+// Calculates the residual of a fixed linear prediction.
+//
+// The polynomials are the same ones used for restoring the signal, only
+// this time the prediction gets subtracted from each sample. `None` is
+// returned when a residual value doesn't fit within 32 bits, which is the
+// limit decoders are able to handle.
+pub fn fixed_residual(order: usize, samples: &[i64]) -> Option<Vec<i64>> {
+  debug_assert!(order <= MAX_FIXED_ORDER);
+
+  let polynomial = [ &[][..]
+                   , &[1][..]
+                   , &[-1, 2][..]
+                   , &[1, -3, 3][..]
+                   , &[-1, 4, -6, 4][..]
+                   ];
+
+  let coefficients = polynomial[order];
+  let length       = samples.len().saturating_sub(order);
+
+  let mut residual = Vec::with_capacity(length);
+
+  for i in 0..length {
+    let offset     = i + order;
+    let prediction = coefficients.iter()
+                      .zip(&samples[i..offset])
+                      .fold(0, |result, (coefficient, signal)|
+                         result + coefficient * signal);
+    let value      = samples[offset] - prediction;
+
+    if value < i64::min_for_bps(32) || value > i64::max_for_bps(32) {
+      return None;
+    }
+
+    residual.push(value);
+  }
+
+  Some(residual)
+}
+
+// Finds the Rice parameter for a partition, and the bits it takes to code
+// the partition with it, from the sum and count of its folded residual.
+//
+// The parameter is estimated from the mean of the partition, with both of
+// its neighbors also tried since the estimate is only close. Full scale
+// 32-bit residuals have a mean past what the largest parameter covers, so
+// the range tried is capped on both ends, leaving the largest parameter
+// with a longer unary part.
+fn estimate_parameter(sum: u64, count: u64) -> (u32, u64) {
+  if count == 0 {
+    return (0, 0);
+  }
+
+  let mean      = sum / count;
+  let estimate  = 64 - mean.leading_zeros();
+  let end       = cmp::min(estimate + 1, MAX_RICE2_PARAMETER);
+  let start     = cmp::min(estimate.saturating_sub(1), end);
+
+  let mut parameter = start;
+  let mut bit_count = u64::max_value();
+
+  for candidate in start..(end + 1) {
+    let bits = count * (candidate as u64 + 1) + (sum >> candidate);
+
+    if bits < bit_count {
+      parameter = candidate;
+      bit_count = bits;
+    }
+  }
+
+  (parameter, bit_count)
+}
+
+/// Estimates the Rice parameter for a partition of residual values.
+///
+/// The estimate comes from the mean of the zigzag encoded values, which
+/// are the absolute values doubled, with the parameters right below and
+/// above it also tried. Both the parameter and an estimate of the bits the
+/// values take up when coded with it get returned, which is never less
+/// than the actual size.
+///
+/// # Examples
+///
+/// ```
+/// use flac::encoder::rice_parameter;
+///
+/// assert_eq!(rice_parameter(&[]), (0, 0));
+/// assert_eq!(rice_parameter(&[0, 0, 0, 0]), (0, 4));
+/// assert_eq!(rice_parameter(&[12, -9, 15, -14]).0, 4);
+/// ```
+pub fn rice_parameter(residual: &[i64]) -> (u32, u64) {
+  let sum = residual.iter().fold(0, |sum, &value| sum + fold(value));
+
+  estimate_parameter(sum, residual.len() as u64)
+}
+
+/// Searches for the partition order, and the Rice parameter of each of the
+/// partitions, that leads to the smallest coded residual.
+///
+/// `order` is the number of warm up samples the prediction used, which the
+/// first partition is shorter by, and the block size is the length of the
+/// residual plus the order. Only partition orders that split the block
+/// evenly, up to `max_partition_order`, are tried. The encoder searches up
+/// to a partition order of 8.
+///
+/// # Examples
+///
+/// ```
+/// use flac::encoder::search_partitions;
+///
+/// let residual = [0, 1, -1, 0, 60, -80, 72, -45];
+/// let coding   = search_partitions(0, &residual, 8);
+///
+/// assert_eq!(coding.partition_order, 1);
+/// assert_eq!(coding.parameters.len(), 2);
+/// ```
+pub fn search_partitions(order: usize, residual: &[i64],
+                         max_partition_order: u32) -> RiceCoding {
+  let folded     = residual.iter().map(|&value| fold(value))
+                                  .collect::<Vec<_>>();
+  let block_size = residual.len() + order;
+  let max_order  = cmp::min(max_partition_order, MAX_FORMAT_PARTITION_ORDER);
+
+  let mut result = RiceCoding {
+    partition_order: 0,
+    parameters: Vec::new(),
+    bit_count: u64::max_value(),
+  };
+
+  for partition_order in 0..(max_order + 1) {
+    let partitions = 1 << partition_order;
+
+    if block_size % partitions != 0 || block_size / partitions < order {
+      break;
+    }
+
+    let mut parameters = Vec::with_capacity(partitions);
+    let mut bit_count  = 0;
+    let mut start      = 0;
+
+    for i in 0..partitions {
+      let count = if i == 0 {
+        (block_size / partitions) - order
+      } else {
+        block_size / partitions
+      };
+      let end   = start + count;
+      let sum   = folded[start..end].iter().fold(0, |sum, &value| sum + value);
+
+      let (parameter, bits) = estimate_parameter(sum, count as u64);
+
+      parameters.push(parameter);
+
+      bit_count += bits;
+      start      = end;
+    }
+
+    let parameter_size = if parameters.iter().any(|&parameter| {
+      parameter > MAX_RICE_PARAMETER
+    }) { 5 } else { 4 };
+
+    bit_count += (partitions * parameter_size) as u64;
+
+    if bit_count < result.bit_count {
+      result.partition_order = partition_order;
+      result.parameters      = parameters;
+      result.bit_count       = bit_count;
+    }
+  }
+
+  result
+}
+
+/// Writes the residual with the given coding into bytes, in the same form
+/// it takes within a subframe.
+///
+/// This starts with the two bit coding method and four bit partition order,
+/// and the last byte gets padded with zero bits.
+///
+/// # Failures
+///
+/// * `io::ErrorKind::InvalidInput` is returned when the coding doesn't fit
+///   the residual, like having a number of parameters other than two to
+///   the power of the partition order, partitions that don't split the
+///   block evenly or are shorter than `order`, or a parameter too large to
+///   be coded.
+///
+/// # Examples
+///
+/// ```
+/// use flac::encoder::{encode_residual, search_partitions};
+///
+/// let residual = [3, -2, 5, -7, 1, 0];
+/// let coding   = search_partitions(2, &residual, 8);
+/// let bytes    = encode_residual(2, &residual, &coding).unwrap();
+///
+/// assert!(bytes.len() as u64 <= (6 + coding.bit_count + 7) / 8);
+/// ```
+pub fn encode_residual(order: usize, residual: &[i64], coding: &RiceCoding)
+                       -> io::Result<Vec<u8>> {
+  if !is_valid_coding(order, residual, coding) {
+    return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                              "Rice coding doesn't fit the residual"));
+  }
+
+  let mut writer = BitWriter::new();
+
+  write_residual(order, residual, coding, &mut writer);
+
+  Ok(writer.into_bytes())
+}
+
+// Checks that the partitions of the coding split the block the same way a
+// decoder splits it, and that every parameter is able to be coded.
+fn is_valid_coding(order: usize, residual: &[i64], coding: &RiceCoding)
+                   -> bool {
+  let partitions = coding.parameters.len();
+  let block_size = residual.len() + order;
+
+  coding.partition_order <= MAX_FORMAT_PARTITION_ORDER &&
+  partitions == 1 << coding.partition_order &&
+  block_size % partitions == 0 && block_size / partitions >= order &&
+  coding.parameters.iter().all(|&parameter| {
+    parameter <= MAX_RICE2_PARAMETER
+  })
+}
+
+fn write_residual(order: usize, residual: &[i64], coding: &RiceCoding,
+                  writer: &mut BitWriter) {
+  let (method, parameter_size) = if coding.is_rice2() {
+    (1, 5)
+  } else {
+    (0, 4)
+  };
+
+  writer.write(method, 2);
+  writer.write(coding.partition_order as u64, 4);
+
+  let partitions = coding.parameters.len();
+  let block_size = (residual.len() + order) / partitions;
+
+  let mut start = 0;
+
+  for (i, &parameter) in coding.parameters.iter().enumerate() {
+    let end = if i == 0 {
+      block_size - order
+    } else {
+      start + block_size
+    };
+
+    writer.write(parameter as u64, parameter_size);
+
+    for &value in &residual[start..end] {
+      let folded = fold(value);
+
+      writer.write_unary(folded >> parameter);
+      writer.write(folded, parameter as usize);
+    }
+
+    start = end;
+  }
+}
+
+// Writes the eight bit subframe header for a six bit subframe type, without
+// any wasted bits.
+fn write_header(subframe_type: usize, writer: &mut BitWriter) {
+  writer.write((subframe_type << 1) as u64, 8);
+}
+
+/// Writes a constant subframe, where every sample is `sample`.
+pub fn write_constant(sample: i64, bits_per_sample: usize,
+                      writer: &mut BitWriter) {
+  write_header(0b000000, writer);
+
+  writer.write_signed(sample, bits_per_sample);
+}
+
+/// Writes a verbatim subframe, with every sample stored as is.
+pub fn write_verbatim(samples: &[i64], bits_per_sample: usize,
+                      writer: &mut BitWriter) {
+  write_header(0b000001, writer);
+
+  for &sample in samples {
+    writer.write_signed(sample, bits_per_sample);
+  }
+}
+
+/// Writes a fixed linear prediction subframe, with `residual` coming from
+/// `fixed_residual` for the same order and samples.
+pub fn write_fixed(order: usize, samples: &[i64], residual: &[i64],
+                   bits_per_sample: usize, writer: &mut BitWriter) {
+  let coding = search_partitions(order, residual, MAX_PARTITION_ORDER);
+
+  write_fixed_with(order, samples, residual, &coding, bits_per_sample,
+                   writer);
+}
+
+/// Same as `write_fixed`, except the residual gets Rice coded with `coding`
+/// instead of searching for the smallest one.
+pub fn write_fixed_with(order: usize, samples: &[i64], residual: &[i64],
+                        coding: &RiceCoding, bits_per_sample: usize,
+                        writer: &mut BitWriter) {
+  write_header(0b001000 | order, writer);
+
+  for &sample in &samples[0..order] {
+    writer.write_signed(sample, bits_per_sample);
+  }
+
+  write_residual(order, residual, coding, writer);
+}
+
+// Calculates the residual of a FIR linear prediction, the same one used
+// for restoring the signal with the coefficients in the same order.
+//
+// `None` is returned when a residual value doesn't fit within 32 bits, or
+// when the prediction itself overflows, which large coefficients over
+// full scale samples are able to do.
+pub fn lpc_residual(coefficients: &[i32], shift: u32, samples: &[i64])
+                    -> Option<Vec<i64>> {
+  let order  = coefficients.len();
+  let length = samples.len().saturating_sub(order);
+
+  let mut residual = Vec::with_capacity(length);
+
+  for i in 0..length {
+    let offset         = i + order;
+    let mut prediction = Some(0i64);
+
+    for (&coefficient, &signal) in coefficients.iter().rev()
+                                               .zip(&samples[i..offset]) {
+      prediction = prediction.and_then(|prediction| {
+        (coefficient as i64).checked_mul(signal)
+                            .and_then(|term| prediction.checked_add(term))
+      });
+    }
+
+    let value = prediction.and_then(|prediction| {
+      samples[offset].checked_sub(prediction >> shift)
+    }).filter(|&value| {
+      value >= i64::min_for_bps(32) && value <= i64::max_for_bps(32)
+    });
+
+    match value {
+      Some(value) => residual.push(value),
+      None        => return None,
+    }
+  }
+
+  Some(residual)
+}
+
+/// Writes a FIR linear prediction subframe, with `residual` coming from
+/// `lpc_residual` for the same coefficients, shift, and samples.
+///
+/// Every coefficient needs to fit within `precision` bits.
+pub fn write_lpc(coefficients: &[i32], precision: u32, shift: u32,
+                 samples: &[i64], residual: &[i64], bits_per_sample: usize,
+                 writer: &mut BitWriter) {
+  let order  = coefficients.len();
+  let coding = search_partitions(order, residual, MAX_PARTITION_ORDER);
+
+  write_header(0b100000 | (order - 1), writer);
+
+  for &sample in &samples[0..order] {
+    writer.write_signed(sample, bits_per_sample);
+  }
+
+  writer.write((precision - 1) as u64, 4);
+  writer.write(shift as u64, 5);
+
+  for &coefficient in coefficients {
+    writer.write_signed(coefficient as i64, precision as usize);
+  }
+
+  write_residual(order, residual, &coding, writer);
+}
+
+/// Encodes a single channel of audio data.
+///
+/// The smallest of a constant, verbatim, or fixed linear prediction
+/// subframe is written, with each sample taking up `bits_per_sample` bits
+/// when stored uncoded.
+pub fn encode(samples: &[i64], bits_per_sample: usize,
+              writer: &mut BitWriter) {
+  let block_size = samples.len();
+  let first      = samples.first().cloned().unwrap_or(0);
+
+  if samples.iter().all(|&sample| sample == first) {
+    write_constant(first, bits_per_sample, writer);
+
+    return;
+  }
+
+  let max_order    = cmp::min(MAX_FIXED_ORDER, block_size - 1);
+  let mut best     = None::<Residual>;
+  let mut best_len = (bits_per_sample * block_size) as u64;
+
+  for order in 0..(max_order + 1) {
+    if let Some(values) = fixed_residual(order, samples) {
+      let coding   = search_partitions(order, &values,
+                                       MAX_PARTITION_ORDER);
+      let length   = (6 + order * bits_per_sample) as u64 +
+                     coding.bit_count;
+      let residual = Residual {
+        order: order,
+        values: values,
+        coding: coding,
+      };
+
+      if length < best_len {
+        best     = Some(residual);
+        best_len = length;
+      }
+    }
+  }
+
+  match best {
+    Some(residual) => {
+      write_fixed_with(residual.order, samples, &residual.values,
+                       &residual.coding, bits_per_sample, writer);
+    }
+    None           => write_verbatim(samples, bits_per_sample, writer),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use frame::{Header, ChannelAssignment, NumberType};
+  use subframe::{self, subframe_parser};
+  use utility::{BitWriter, BitsPerSample, DecodeContext, SampleRate};
+
+  use nom::IResult;
+
+  use std::i32;
+
+  fn round_trip(samples: &[i64], bits_per_sample: usize) -> Vec<u8> {
+    let header = Header {
+      block_size: samples.len() as u32,
+      sample_rate: SampleRate::new(44100).unwrap(),
+      channels: 1,
+      channel_assignment: ChannelAssignment::Independent,
+      bits_per_sample: BitsPerSample::new(bits_per_sample as u8).unwrap(),
+      number: NumberType::Frame(0),
+      crc: 0,
+    };
+
+    let mut writer = BitWriter::new();
+
+    encode(samples, bits_per_sample, &mut writer);
+
+    let bytes       = writer.into_bytes();
+    let mut context = DecodeContext::new();
+    let mut buffer  = vec![0; samples.len()];
+
+    let subframe = match subframe_parser((&bytes, 0), &header, &mut context,
+                                         &mut buffer) {
+      IResult::Done(_, subframe) => subframe,
+      _                          => panic!("failed to parse subframe"),
+    };
+
+    subframe::decode(&subframe, samples.len(), &mut buffer);
+
+    assert_eq!(buffer, samples.iter().map(|&s| s as i32)
+                                     .collect::<Vec<_>>());
+
+    bytes
+  }
+
+  #[test]
+  fn test_fixed_residual() {
+    let samples = [1, 2, 4, 8, 16, 32];
+
+    assert_eq!(fixed_residual(0, &samples), Some(vec![1, 2, 4, 8, 16, 32]));
+    assert_eq!(fixed_residual(1, &samples), Some(vec![1, 2, 4, 8, 16]));
+    assert_eq!(fixed_residual(2, &samples), Some(vec![1, 2, 4, 8]));
+    assert_eq!(fixed_residual(4, &samples), Some(vec![1, 2]));
+    assert_eq!(fixed_residual(1, &[i32::MIN as i64, i32::MAX as i64]),
+               None);
+  }
+
+  #[test]
+  fn test_rice_coding() {
+    let quiet_loud = (0..64).map(|i| {
+      if i < 32 { i % 3 - 1 } else { (i % 7 - 3) * 1000 }
+    }).collect::<Vec<_>>();
+    let large      = [1 << 20, -(1 << 21), 1 << 22, -(1 << 20)];
+
+    let coding = search_partitions(0, &quiet_loud, 8);
+    let single = search_partitions(0, &quiet_loud, 0);
+
+    assert!(coding.partition_order >= 1);
+    assert!(coding.parameters[0] < coding.parameters[1]);
+    assert!(coding.bit_count < single.bit_count);
+    assert!(!coding.is_rice2());
+    assert_eq!(single.partition_order, 0);
+    assert_eq!(single.parameters, vec![rice_parameter(&quiet_loud).0]);
+
+    let coding = search_partitions(2, &large, 8);
+
+    assert!(coding.is_rice2());
+    assert_eq!(coding.partition_order, 0);
+
+    for &(order, residual) in &[(0, &quiet_loud[..]), (2, &large[..])] {
+      let coding = search_partitions(order, residual, 15);
+      let bytes  = encode_residual(order, residual, &coding).unwrap();
+
+      assert!(bytes.len() as u64 <= (6 + coding.bit_count + 7) / 8);
+      assert_eq!(bytes[0] >> 6, if coding.is_rice2() { 1 } else { 0 });
+      assert_eq!((bytes[0] >> 2) & 0x0f, coding.partition_order as u8);
+    }
+
+    // Codings that don't fit the residual, with no parameters, more
+    // partitions than the partition order gives, partitions shorter than
+    // the order, and a parameter taken by the escape code.
+    let codings = [
+      (0, 0, vec![]),
+      (0, 1, vec![4, 4, 4]),
+      (32, 2, vec![4, 4, 4, 4]),
+      (0, 0, vec![31]),
+    ];
+
+    for &(order, partition_order, ref parameters) in &codings {
+      let coding = RiceCoding {
+        partition_order: partition_order,
+        parameters: parameters.clone(),
+        bit_count: 0,
+      };
+
+      assert_eq!(encode_residual(order, &quiet_loud, &coding)
+                   .map_err(|error| error.kind()),
+                 Err(io::ErrorKind::InvalidInput));
+    }
+  }
+
+  #[test]
+  fn test_encode() {
+    let constant = [-5; 64];
+    let sine     = (0..256).map(|i| {
+      ((i as f64 / 10.0).sin() * 20000.0) as i64
+    }).collect::<Vec<_>>();
+    let noise    = (0..64).map(|i| {
+      if i % 2 == 0 { 32767 } else { -32768 }
+    }).collect::<Vec<_>>();
+
+    assert_eq!(round_trip(&constant, 16), vec![0b00000000, 0xff, 0xfb]);
+    assert!(round_trip(&sine, 16).len() < sine.len() * 2);
+    assert_eq!(round_trip(&noise, 16)[0] >> 1, 0b000001);
+    assert_eq!(round_trip(&[7], 8), vec![0b00000000, 7]);
+    round_trip(&[1, -1], 24);
+  }
+
+  #[test]
+  fn test_full_scale() {
+    let (min, max) = (i32::MIN as i64, i32::MAX as i64);
+
+    // Folded residuals past what the largest Rice parameter covers.
+    let residual = [min, max, min + 1, max - 1];
+    let coding   = search_partitions(0, &residual, 8);
+
+    assert_eq!(rice_parameter(&residual).0, MAX_RICE2_PARAMETER);
+    assert_eq!(coding.parameters, [MAX_RICE2_PARAMETER]);
+    assert!(coding.bit_count < 64 * residual.len() as u64);
+
+    // A prediction that overflows, rather than wrapping around.
+    assert_eq!(lpc_residual(&[i32::MAX; 3], 0, &[min * 2; 4]), None);
+    assert_eq!(lpc_residual(&[1], 0, &[min, max]), None);
+    assert_eq!(lpc_residual(&[1], 0, &[max, max]), Some(vec![0]));
+
+    let square = (0..64).map(|i| {
+      if (i / 2) % 2 == 0 { max } else { min }
+    }).collect::<Vec<_>>();
+
+    round_trip(&square, 32);
+    round_trip(&[min, max, max, min, 0, min], 32);
+  }
+}
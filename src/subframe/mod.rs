@@ -1,14 +1,20 @@
 mod types;
 mod parser;
 mod decoder;
+mod encoder;
 
 pub use self::types::{
-  MAX_FIXED_ORDER, MAX_LPC_ORDER,
+  MAX_FIXED_ORDER, MAX_LPC_ORDER, MAX_SUBFRAME_BITS,
   Subframe,
   Data,
   Fixed, LPC,
   EntropyCodingMethod, CodingMethod, PartitionedRice, PartitionedRiceContents,
 };
 
-pub use self::parser::subframe_parser;
-pub use self::decoder::decode;
+pub use self::parser::{subframe_parser, adjust_bits_per_sample};
+pub use self::decoder::{decode, decode_approximate};
+pub use self::encoder::{
+  encode, encode_residual, rice_parameter, search_partitions, RiceCoding,
+  fixed_residual, lpc_residual,
+  write_constant, write_verbatim, write_fixed, write_fixed_with, write_lpc,
+};
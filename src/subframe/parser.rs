@@ -2,7 +2,10 @@ use nom::{self, IResult, Err, Needed};
 
 use frame::{self, ChannelAssignment};
 use subframe::{self, Subframe, CodingMethod, PartitionedRiceContents};
-use utility::{ErrorKind, Sample, power_of_two};
+use utility::{
+  DecodeContext, ErrorKind, Sample, needed_from, power_of_two, take_bits,
+  take_signed_bits,
+};
 
 // Parser used to parse unary notation. Naming the parser `leading_zeros`
 // was something that felt more clear in the code. It actually tells the
@@ -55,7 +58,7 @@ pub fn leading_zeros(input: (&[u8], usize)) -> IResult<(&[u8], usize), u32> {
 pub fn adjust_bits_per_sample(frame_header: &frame::Header,
                               channel: usize)
                               -> usize {
-  let bits_per_sample = frame_header.bits_per_sample;
+  let bits_per_sample = usize::from(frame_header.bits_per_sample);
 
   match frame_header.channel_assignment {
     ChannelAssignment::Independent  => bits_per_sample,
@@ -80,39 +83,53 @@ pub fn adjust_bits_per_sample(frame_header: &frame::Header,
 /// Parse a single channel of audio data.
 pub fn subframe_parser<'a, S>(input: (&'a [u8], usize),
                               frame_header: &frame::Header,
-                              channel: &mut usize,
+                              context: &mut DecodeContext,
                               buffer: &mut [S])
                               -> IResult<(&'a [u8], usize), Subframe,
                                          ErrorKind>
  where S: Sample {
+  let channel         = context.channel();
   let block_size      = frame_header.block_size as usize;
-  let bits_per_sample = adjust_bits_per_sample(frame_header, *channel);
-  let start           = *channel * block_size;
-  let end             = (*channel + 1) * block_size;
-  let buffer_slice    = &mut buffer[start..end];
-
-  chain!(input,
-    subframe_header: header ~
-    wasted_bits: map!(
-      cond!(subframe_header.1,
-      to_custom_error!(leading_zeros, LeadingZerosParser)),
-      |option: Option<u32>| option.map_or(0, |zeros| zeros + 1)
-    ) ~
-    subframe_data: apply!(data,
-      bits_per_sample - (wasted_bits as usize),
-      block_size, subframe_header.0,
-      buffer_slice),
-    || {
-      // Iterate over the current channel being parsed. This probably should
-      // be abstracted away, but for now this is the solution.
-      *channel += 1;
-
-      Subframe {
-        data: subframe_data,
-        wasted_bits: wasted_bits,
+  let bits_per_sample = adjust_bits_per_sample(frame_header, channel);
+  let start           = channel * block_size;
+  let end             = (channel + 1) * block_size;
+
+  let buffer_slice = match buffer.get_mut(start..end) {
+    Some(slice) => slice,
+    None        => {
+      return IResult::Error(Err::Position(
+        nom::ErrorKind::Custom(ErrorKind::InvalidBlockSize), input));
+    }
+  };
+
+  let (i, (subframe_type, has_wasted_bits)) = try_parser!(header(input));
+
+  // Wasted bits are stored in unary, with the count being one more than
+  // the number of leading zeros.
+  let (i, wasted_bits) = if has_wasted_bits {
+    match leading_zeros(i) {
+      IResult::Done(rest, zeros) => (rest, zeros + 1),
+      IResult::Error(_)          => (i, 0),
+      IResult::Incomplete(need)  => {
+        return needed_from(&input, &i, IResult::Incomplete(need));
       }
     }
-  )
+  } else {
+    (i, 0)
+  };
+
+  let bits_per_sample    = bits_per_sample.saturating_sub(wasted_bits as usize);
+  let (i, subframe_data) = try_parser! {
+    needed_from(&input, &i, data(i, bits_per_sample, block_size,
+                                 subframe_type, buffer_slice))
+  };
+
+  context.set_channel(channel + 1);
+
+  IResult::Done(i, Subframe {
+    data: subframe_data,
+    wasted_bits: wasted_bits,
+  })
 }
 
 // Parses the first byte of the subframe. The first bit must be zero to
@@ -121,7 +138,7 @@ pub fn subframe_parser<'a, S>(input: (&'a [u8], usize),
 pub fn header(input: (&[u8], usize))
               -> IResult<(&[u8], usize), (usize, bool), ErrorKind> {
   let (i, byte) = try_parser! {
-    to_custom_error!(input, take_bits!(u8, 8), SubframeHeaderParser)
+    take_bits(input, 8).map_err(to_custom_error!(SubframeHeaderParser))
   };
 
   let is_valid        = (byte >> 7) == 0;
@@ -143,6 +160,13 @@ fn data<'a, S>(input: (&'a [u8], usize),
                buffer: &mut [S])
                -> IResult<(&'a [u8], usize), subframe::Data, ErrorKind>
  where S: Sample{
+  // Either every bit got wasted, or the side channel of a 32 bit stream
+  // needs more bits than a sample is able to hold.
+  if bits_per_sample == 0 || bits_per_sample > subframe::MAX_SUBFRAME_BITS {
+    return IResult::Error(Err::Position(
+      nom::ErrorKind::Custom(ErrorKind::InvalidSubframeHeader), input));
+  }
+
   match subframe_type {
     0b000000            => constant(input, bits_per_sample),
     0b000001            => verbatim(input, bits_per_sample, block_size)
@@ -159,9 +183,9 @@ fn data<'a, S>(input: (&'a [u8], usize),
 
 pub fn constant(input: (&[u8], usize), bits_per_sample: usize)
                 -> IResult<(&[u8], usize), subframe::Data, ErrorKind> {
-  to_custom_error!(input,
-    map!(take_signed_bits!(bits_per_sample), subframe::Data::Constant),
-    ConstantParser)
+  take_signed_bits(input, bits_per_sample)
+    .map(subframe::Data::Constant)
+    .map_err(to_custom_error!(ConstantParser))
 }
 
 pub fn fixed<'a, S>(input: (&'a [u8], usize),
@@ -173,33 +197,59 @@ pub fn fixed<'a, S>(input: (&'a [u8], usize),
  where S: Sample {
   let mut warmup = [0; subframe::MAX_FIXED_ORDER];
 
-  to_custom_error!(input,
-    chain!(
-      count_slice!(take_signed_bits!(bits_per_sample),
-                   &mut warmup[0..order]) ~
-      entropy_coding_method: apply!(residual, order, block_size, buffer),
-      || {
-        subframe::Data::Fixed(subframe::Fixed {
-          entropy_coding_method: entropy_coding_method,
-          order: order as u8,
-          warmup: warmup,
-          residual: Vec::new(),
-        })
+  let (i, _)                     = try_parser! {
+    warmup_samples(input, bits_per_sample, &mut warmup[0..order])
+      .map_err(to_custom_error!(FixedParser))
+  };
+  let (i, entropy_coding_method) = try_parser! {
+    needed_from(&input, &i, residual(i, order, block_size, buffer)
+                              .map_err(to_custom_error!(FixedParser)))
+  };
+
+  IResult::Done(i, subframe::Data::Fixed(subframe::Fixed {
+    entropy_coding_method: entropy_coding_method,
+    order: order as u8,
+    warmup: warmup,
+    residual: Vec::new(),
+  }))
+}
+
+// Parses the unencoded samples that start off a predicted subframe.
+fn warmup_samples<'a>(input: (&'a [u8], usize),
+                      bits_per_sample: usize,
+                      warmup: &mut [i32])
+                      -> IResult<(&'a [u8], usize), ()> {
+  let mut mut_input = input;
+
+  for sample in warmup {
+    match take_signed_bits(mut_input, bits_per_sample) {
+      IResult::Done(i, value) => {
+        mut_input = i;
+
+        *sample = value;
       }
-    ),
-    FixedParser)
+      IResult::Error(_)       => {
+        return IResult::Error(Err::Position(nom::ErrorKind::Count, input));
+      }
+      IResult::Incomplete(_)  => {
+        return IResult::Incomplete(Needed::Unknown);
+      }
+    }
+  }
+
+  IResult::Done(mut_input, ())
 }
 
 // This parser finds the bit length for each quantized linear predictor
 // coefficient. To preven sync fooling, four bit value cant be all onces.
 fn qlp_coefficient_precision(input: (&[u8], usize))
                              -> IResult<(&[u8], usize), u8> {
-  let (i, precision) = try_parse!(input, take_bits!(u8, 4));
+  let (i, precision) = try_parser!(take_bits(input, 4));
 
   if precision == 0b1111 {
     IResult::Error(Err::Position(nom::ErrorKind::Digit, input))
   } else {
-    IResult::Done(i, precision + 1)
+    IResult::Done(i, precision as u8 + 1)
   }
 }
 
@@ -213,45 +263,77 @@ pub fn lpc<'a, S>(input: (&'a [u8], usize),
   let mut warmup           = [0; subframe::MAX_LPC_ORDER];
   let mut qlp_coefficients = [0; subframe::MAX_LPC_ORDER];
 
-  to_custom_error!(input,
-    chain!(
-      count_slice!(take_signed_bits!(bits_per_sample),
-                   &mut warmup[0..order]) ~
-      qlp_coeff_precision: qlp_coefficient_precision ~
-      quantization_level: take_signed_bits!(i8, 5) ~
-      count_slice!(
-        take_signed_bits!(qlp_coeff_precision as usize),
-        &mut qlp_coefficients[0..order]
-      ) ~
-      entropy_coding_method: apply!(residual, order, block_size, buffer),
-      || {
-        subframe::Data::LPC(subframe::LPC {
-          entropy_coding_method: entropy_coding_method,
-          order: order as u8,
-          qlp_coeff_precision: qlp_coeff_precision,
-          quantization_level: quantization_level,
-          qlp_coefficients: qlp_coefficients,
-          warmup: warmup,
-          residual: Vec::new(),
-        })
-      }
-    ),
-    LPCParser)
+  let (i, _)                     = try_parser! {
+    warmup_samples(input, bits_per_sample, &mut warmup[0..order])
+      .map_err(to_custom_error!(LPCParser))
+  };
+  let (i, qlp_coeff_precision)   = try_parser! {
+    needed_from(&input, &i, qlp_coefficient_precision(i)
+                              .map_err(to_custom_error!(LPCParser)))
+  };
+  let (i, quantization_level)    = try_parser! {
+    needed_from(&input, &i, quantization_level(i)
+                              .map_err(to_custom_error!(LPCParser)))
+  };
+  let (i, _)                     = try_parser! {
+    needed_from(&input, &i,
+                warmup_samples(i, qlp_coeff_precision as usize,
+                               &mut qlp_coefficients[0..order])
+                  .map_err(to_custom_error!(LPCParser)))
+  };
+  let (i, entropy_coding_method) = try_parser! {
+    needed_from(&input, &i, residual(i, order, block_size, buffer)
+                              .map_err(to_custom_error!(LPCParser)))
+  };
+
+  IResult::Done(i, subframe::Data::LPC(subframe::LPC {
+    entropy_coding_method: entropy_coding_method,
+    order: order as u8,
+    qlp_coeff_precision: qlp_coeff_precision,
+    quantization_level: quantization_level,
+    qlp_coefficients: qlp_coefficients,
+    warmup: warmup,
+    residual: Vec::new(),
+  }))
+}
+
+// Parses the shift, in bits, applied to the predicted samples.
+fn quantization_level(input: (&[u8], usize))
+                      -> IResult<(&[u8], usize), i8> {
+  take_signed_bits(input, 5).map(|level| level as i8)
 }
 
 pub fn verbatim(input: (&[u8], usize),
                 bits_per_sample: usize,
                 block_size: usize)
                 -> IResult<(&[u8], usize), subframe::Data> {
-  map!(input, count!(take_signed_bits!(bits_per_sample), block_size),
-       subframe::Data::Verbatim)
+  let mut mut_input = input;
+  let mut samples   = Vec::with_capacity(block_size);
+
+  for _ in 0..block_size {
+    match take_signed_bits(mut_input, bits_per_sample) {
+      IResult::Done(i, value) => {
+        mut_input = i;
+
+        samples.push(value);
+      }
+      IResult::Error(_)       => {
+        return IResult::Error(Err::Position(nom::ErrorKind::Count, input));
+      }
+      IResult::Incomplete(_)  => {
+        return IResult::Incomplete(Needed::Unknown);
+      }
+    }
+  }
+
+  IResult::Done(mut_input, subframe::Data::Verbatim(samples))
 }
 
 // Parser for figuring out the partitioned Rice coding, which there are only
 // two, and the parser with fail when value is greater than one.
 fn coding_method(input: (&[u8], usize))
                  -> IResult<(&[u8], usize), CodingMethod> {
-  let (i, method) = try_parse!(input, take_bits!(u8, 2));
+  let (i, method) = try_parser!(take_bits(input, 2));
 
   match method {
     0 => IResult::Done(i, CodingMethod::PartitionedRice),
@@ -267,10 +349,8 @@ fn residual<'a, S>(input: (&'a [u8], usize),
                    -> IResult<(&'a [u8], usize),
                               subframe::EntropyCodingMethod>
  where S: Sample {
-  let (i, data) = try_parse!(input,
-                    pair!(coding_method, take_bits!(u32, 4)));
-
-  let (method, order) = data;
+  let (i, method) = try_parser!(coding_method(input));
+  let (i, order)  = try_parser!(needed_from(&input, &i, take_bits(i, 4)));
 
   rice_partition(i, order, predictor_order, block_size, method, buffer)
 }
@@ -291,7 +371,18 @@ fn rice_partition<'a, S>(input: (&'a [u8], usize),
 
   // Adjust block size to not include allocation for warm up samples
   let partitions = power_of_two(partition_order) as usize;
-  let residual   = &mut buffer[predictor_order..];
+
+  // Every partition needs to hold at least the warm up samples, which also
+  // keeps the warm up samples within the block. The partitions also need to
+  // split the block evenly, otherwise samples at the end of the block would
+  // never be read.
+  if block_size % partitions != 0 ||
+     block_size / partitions < predictor_order ||
+     buffer.len() < block_size {
+    return IResult::Error(Err::Position(nom::ErrorKind::Count, input));
+  }
+
+  let residual = &mut buffer[predictor_order..];
 
   let mut mut_input = input;
   let mut sample    = 0;
@@ -308,23 +399,16 @@ fn rice_partition<'a, S>(input: (&'a [u8], usize),
     let start = sample;
     let end   = sample + offset;
 
-    let result = chain!(mut_input,
-      rice_parameter: take_bits!(u32, param_size) ~
-      size: cond!(rice_parameter == escape_code, take_bits!(usize, 5)) ~
-      apply!(residual_data,
-        size, rice_parameter,
-        &mut contents.raw_bits()[partition],
-        &mut residual[start..end]
-      ),
-      || { rice_parameter }
-    );
+    let result = rice_partition_data(mut_input, param_size, escape_code,
+                                     &mut contents.raw_bits_mut()[partition],
+                                     &mut residual[start..end]);
 
     match result {
       IResult::Done(i, parameter) => {
         mut_input = i;
         sample    = end;
 
-        contents.parameters()[partition] = parameter;
+        contents.parameters_mut()[partition] = parameter;
       }
       IResult::Error(error)       => return IResult::Error(error),
       IResult::Incomplete(need)   => return IResult::Incomplete(need),
@@ -342,6 +426,35 @@ fn rice_partition<'a, S>(input: (&'a [u8], usize),
   IResult::Done(mut_input, entropy_coding_method)
 }
 
+// Parses the Rice parameter of a single partition, followed by its
+// residual, which is unencoded when the parameter is the escape code.
+fn rice_partition_data<'a, S>(input: (&'a [u8], usize),
+                              param_size: usize,
+                              escape_code: u32,
+                              raw_bit: &mut u32,
+                              samples: &mut [S])
+                              -> IResult<(&'a [u8], usize), u32>
+ where S: Sample {
+  let (i, rice_parameter) = try_parser!(take_bits(input, param_size));
+
+  let (i, size) = if rice_parameter == escape_code {
+    let (rest, size) = try_parser! {
+      needed_from(&input, &i, take_bits(i, 5))
+    };
+
+    (rest, Some(size as usize))
+  } else {
+    (i, None)
+  };
+
+  let (i, _) = try_parser! {
+    needed_from(&input, &i,
+                residual_data(i, size, rice_parameter, raw_bit, samples))
+  };
+
+  IResult::Done(i, rice_parameter)
+}
+
 fn residual_data<'a, S>(input: (&'a [u8], usize),
                         option: Option<usize>,
                         rice_parameter: u32,
@@ -370,8 +483,17 @@ fn unencoded_residuals<'a, S>(input: (&'a [u8], usize),
 
   *raw_bit = bits_per_sample as u32;
 
+  // A size of zero has no bits to read, with every residual being zero.
+  if bits_per_sample == 0 {
+    for sample in samples {
+      *sample = S::from_i8(0);
+    }
+
+    return IResult::Done(input, ());
+  }
+
   for sample in samples {
-    match take_signed_bits!(mut_input, bits_per_sample) {
+    match take_signed_bits(mut_input, bits_per_sample) {
       IResult::Done(i, value) => {
         mut_input = i;
         count    += 1;
@@ -412,15 +534,7 @@ fn encoded_residuals<'a, S>(input: (&'a [u8], usize),
   *raw_bit = 0;
 
   for sample in samples {
-    let result = chain!(mut_input,
-      quotient: leading_zeros ~
-      // TODO: Figure out the varied remainder bit size
-      remainder: take_bits!(u32, parameter as usize),
-      || {
-        let value = quotient * modulus + remainder;
-
-        S::from_i32_lossy(((value as i32) >> 1) ^ -((value as i32) & 1))
-      });
+    let result = rice_sample(mut_input, parameter, modulus);
 
     match result {
       IResult::Done(i, value) => {
@@ -447,13 +561,42 @@ fn encoded_residuals<'a, S>(input: (&'a [u8], usize),
   }
 }
 
+// Parses a single Rice coded sample, a unary quotient followed by the
+// remainder, and folds it back into a signed value.
+//
+// With the five bit parameters of the second coding method going up to 30,
+// the folded value takes up all 32 bits, so it gets unfolded as unsigned.
+// Values past 32 bits can't come from a valid residual and fail instead of
+// wrapping.
+#[inline]
+fn rice_sample<S>(input: (&[u8], usize), parameter: u32, modulus: u32)
+                  -> IResult<(&[u8], usize), S>
+ where S: Sample {
+  let (i, quotient)  = try_parser!(leading_zeros(input));
+  // TODO: Figure out the varied remainder bit size
+  let (i, remainder) = try_parser! {
+    needed_from(&input, &i, take_bits(i, parameter as usize))
+  };
+
+  let value = match quotient.checked_mul(modulus)
+                            .and_then(|value| value.checked_add(remainder)) {
+    Some(value) => value,
+    None        => {
+      return IResult::Error(Err::Position(nom::ErrorKind::Count, input));
+    }
+  };
+
+  IResult::Done(i, S::from_i32_lossy(((value >> 1) as i32) ^
+                                     -((value & 1) as i32)))
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
   use nom::{self, IResult, Err, Needed};
 
   use frame::{self, ChannelAssignment, NumberType};
-  use utility::ErrorKind;
+  use utility::{BitsPerSample, ErrorKind, SampleRate};
 
   use subframe::{
     Data,
@@ -462,6 +605,16 @@ mod tests {
     PartitionedRiceContents,
   };
 
+  fn contents(parameters: &[u32], raw_bits: &[u32])
+              -> PartitionedRiceContents {
+    let mut contents = PartitionedRiceContents::new(parameters.len());
+
+    contents.parameters_mut().copy_from_slice(parameters);
+    contents.raw_bits_mut().copy_from_slice(raw_bits);
+
+    contents
+  }
+
   #[test]
   fn test_leading_zeros() {
     let inputs  = [ (&[0b10000000][..], 0)
@@ -521,10 +674,10 @@ mod tests {
   fn test_adjust_bits_per_sample() {
     let mut frame_header = frame::Header {
       block_size: 512,
-      sample_rate: 41000,
+      sample_rate: SampleRate::new(41000).unwrap(),
       channels: 2,
       channel_assignment: ChannelAssignment::Independent,
-      bits_per_sample: 16,
+      bits_per_sample: BitsPerSample::new(16).unwrap(),
       number: NumberType::Sample(40),
       crc: 0xc4,
     };
@@ -589,10 +742,7 @@ mod tests {
                         method_type: CodingMethod::PartitionedRice,
                         data: PartitionedRice {
                           order: 0,
-                          contents: PartitionedRiceContents {
-                            capacity: 1,
-                            data: vec![8, 0],
-                          },
+                          contents: contents(&[8], &[0]),
                         },
                       },
                       order: 4,
@@ -604,10 +754,7 @@ mod tests {
                         method_type: CodingMethod::PartitionedRice2,
                         data: PartitionedRice {
                           order: 1,
-                          contents: PartitionedRiceContents {
-                            capacity: 2,
-                            data: vec![31, 31, 16, 6],
-                          },
+                          contents: contents(&[31, 31], &[16, 6]),
                         },
                       },
                       order: 2,
@@ -628,6 +775,119 @@ mod tests {
     assert_eq!(&buffer[2..10], residuals[1]);
   }
 
+  #[test]
+  fn test_tiny_partitions() {
+    let result = IResult::Done((&[][..], 0), Data::Fixed(Fixed {
+      entropy_coding_method: EntropyCodingMethod {
+        method_type: CodingMethod::PartitionedRice,
+        data: PartitionedRice {
+          order: 1,
+          contents: contents(&[0, 0], &[0, 0]),
+        },
+      },
+      order: 0,
+      warmup: [0; 4],
+      residual: Vec::new(),
+    }));
+
+    let mut buffer = [1; 16];
+
+    // Two partitions of a single sample each.
+    assert_eq!(fixed((&b"\x04\x21"[..], 0), 0, 8, 2, &mut buffer), result);
+    assert_eq!(&buffer[0..2], &[0, 0]);
+
+    // Partitions that don't split the block evenly.
+    assert!(fixed((&b"\x04\x21"[..], 0), 0, 8, 3, &mut buffer).is_err());
+    assert!(fixed((&b"\x14\x21"[..], 0), 0, 8, 16, &mut buffer).is_err());
+  }
+
+  #[test]
+  fn test_rice2_edges() {
+    use subframe::{RiceCoding, encode_residual};
+    use utility::BitWriter;
+
+    let mut writer = BitWriter::new();
+
+    // Second coding method, split into four partitions.
+    writer.write(0b01, 2);
+    writer.write(2, 4);
+
+    // The largest parameter, with residuals taking up all 32 bits.
+    writer.write(30, 5);
+
+    for &value in &[i32::max_value(), i32::min_value(), -1, 1 << 30] {
+      let folded = ((value << 1) ^ (value >> 31)) as u32 as u64;
+
+      writer.write_unary(folded >> 30);
+      writer.write(folded, 30);
+    }
+
+    // Escaped partitions, where zero bits leaves nothing to read.
+    writer.write(0b11111, 5);
+    writer.write(0, 5);
+
+    writer.write(0b11111, 5);
+    writer.write(17, 5);
+
+    for &value in &[-65536, 65535, 3, -3] {
+      writer.write_signed(value, 17);
+    }
+
+    writer.write(0b11111, 5);
+    writer.write(31, 5);
+
+    for &value in &[-(1 << 30), (1 << 30) - 1, 0, -1] {
+      writer.write_signed(value, 31);
+    }
+
+    let bytes      = writer.into_bytes();
+    let mut buffer = [1; 16];
+    let result     = EntropyCodingMethod {
+      method_type: CodingMethod::PartitionedRice2,
+      data: PartitionedRice {
+        order: 2,
+        contents: contents(&[30, 31, 31, 31], &[0, 0, 17, 31]),
+      },
+    };
+
+    match residual((&bytes[..], 0), 0, 16, &mut buffer) {
+      IResult::Done(_, method) => assert_eq!(method, result),
+      result                   => panic!("{:?}", result),
+    }
+
+    assert_eq!(buffer, [i32::max_value(), i32::min_value(), -1, 1 << 30,
+                        0, 0, 0, 0,
+                        -65536, 65535, 3, -3,
+                        -(1 << 30), (1 << 30) - 1, 0, -1]);
+
+    // Residuals past 32 bits fail rather than wrapping around.
+    let mut writer = BitWriter::new();
+
+    writer.write(0b01, 2);
+    writer.write(0, 4);
+    writer.write(30, 5);
+    writer.write_unary(4);
+    writer.write(0, 30);
+
+    let bytes = writer.into_bytes();
+
+    assert!(residual((&bytes[..], 0), 0, 1, &mut buffer).is_err());
+
+    // What the encoder writes with the largest parameter reads back the
+    // same.
+    let values = [i32::max_value() as i64, i32::min_value() as i64, 0, 7];
+    let coding = RiceCoding {
+      partition_order: 0,
+      parameters: vec![30],
+      bit_count: 0,
+    };
+    let bytes  = encode_residual(0, &values, &coding).unwrap();
+
+    assert!(coding.is_rice2());
+    assert!(residual((&bytes[..], 0), 0, 4, &mut buffer).is_done());
+    assert_eq!(&buffer[0..4], &[i32::max_value(), i32::min_value(), 0, 7]);
+  }
+
   #[test]
   fn test_lpc() {
     let inputs  = [ (&b"\xe8\0\x40\xaf\x74\x73\x19\0\x75\x81\xe8\x16\0\x05\
@@ -642,10 +902,7 @@ mod tests {
                         method_type: CodingMethod::PartitionedRice,
                         data: PartitionedRice {
                           order: 0,
-                          contents: PartitionedRiceContents {
-                            capacity: 1,
-                            data: vec![15, 8],
-                          },
+                          contents: contents(&[15], &[8]),
                         },
                       },
                       order: 4,
@@ -666,10 +923,7 @@ mod tests {
                         method_type: CodingMethod::PartitionedRice2,
                         data: PartitionedRice {
                           order: 1,
-                          contents: PartitionedRiceContents {
-                            capacity: 2,
-                            data: vec![3, 5, 0, 0],
-                          },
+                          contents: contents(&[3, 5], &[0, 0]),
                         },
                       },
                       order: 8,
@@ -4,6 +4,13 @@ pub const MAX_FIXED_ORDER: usize = 4;
 /// Maximum LPC order permitted by the format.
 pub const MAX_LPC_ORDER: usize   = 32;
 
+/// Largest number of bits per sample within a subframe that gets decoded,
+/// and that the encoder writes, the extra bit of a side channel included.
+///
+/// The side channel of a 32-bit stream needs 33 bits, so 32-bit stereo
+/// streams get encoded with independent channels.
+pub const MAX_SUBFRAME_BITS: usize = 32;
+
 /// A single channel of audio data.
 pub struct Subframe {
   /// Data containing one of the four different types of subframes.
@@ -85,36 +92,72 @@ pub struct PartitionedRice {
 }
 
 /// Contents of a Rice partitioned residual.
-#[derive(Debug, PartialEq, Eq)]
+///
+/// The Rice parameter and the width of the unencoded samples for every
+/// partition are held within a single buffer, with all the parameters
+/// first followed by all the widths, so the buffer is always twice the
+/// number of partitions long. A width of zero means the partition is Rice
+/// coded, otherwise its samples are stored as is, using that many bits.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct PartitionedRiceContents {
-  /// Size of `parameters` and `raw_bits` within the data buffer.
-  pub capacity: usize,
-  /// Data buffer containing both `parameters` and `raw_bits`.
-  pub data: Vec<u32>,
+  partitions: usize,
+  data: Vec<u32>,
 }
 
 impl PartitionedRiceContents {
-  pub fn new(capacity: usize) -> PartitionedRiceContents {
-    let full_capacity = capacity * 2;
-    let mut data      = Vec::with_capacity(full_capacity);
-
-    unsafe { data.set_len(full_capacity) }
-
+  /// Constructs the contents for the given number of partitions, with
+  /// every parameter and width set to zero.
+  pub fn new(partitions: usize) -> PartitionedRiceContents {
     PartitionedRiceContents {
-      capacity: capacity,
-      data: data,
+      partitions: partitions,
+      data: vec![0; partitions * 2],
     }
   }
 
-  /// Rice parameters for each context.
+  /// Rice parameter of each partition.
+  #[allow(dead_code)]
   #[inline]
-  pub fn parameters(&mut self) -> &mut [u32] {
-    &mut self.data[0..self.capacity]
+  pub fn parameters(&self) -> &[u32] {
+    &self.data[0..self.partitions]
   }
 
-  /// Widths for escaped-coded partitions.
+  /// Width, in bits, of the unencoded samples of each partition.
+  #[allow(dead_code)]
   #[inline]
-  pub fn raw_bits(&mut self) -> &mut [u32] {
-    &mut self.data[self.capacity..]
+  pub fn raw_bits(&self) -> &[u32] {
+    &self.data[self.partitions..]
+  }
+
+  /// Mutable Rice parameter of each partition.
+  #[inline]
+  pub fn parameters_mut(&mut self) -> &mut [u32] {
+    &mut self.data[0..self.partitions]
+  }
+
+  /// Mutable width, in bits, of the unencoded samples of each partition.
+  #[inline]
+  pub fn raw_bits_mut(&mut self) -> &mut [u32] {
+    &mut self.data[self.partitions..]
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_partitioned_rice_contents() {
+    let mut contents = PartitionedRiceContents::new(2);
+
+    assert_eq!(contents.parameters_mut(), &[0, 0]);
+    assert_eq!(contents.raw_bits_mut(), &[0, 0]);
+
+    contents.parameters_mut()[1] = 14;
+    contents.raw_bits_mut()[0]   = 7;
+
+    assert_eq!(contents.parameters(), &[0, 14]);
+    assert_eq!(contents.raw_bits(), &[7, 0]);
+    assert!(PartitionedRiceContents::new(0).parameters().is_empty());
+    assert!(PartitionedRiceContents::new(0).raw_bits().is_empty());
   }
 }
@@ -0,0 +1,109 @@
+use super::MAX_COMMENTS;
+
+use metadata::PlaceholderPolicy;
+
+/// Options, and where parsing is within a frame, passed through the frame,
+/// subframe, and metadata parsers.
+///
+/// Every option a parser deep within a frame or metadata block needs comes
+/// from here, so new ones are able to reach those parsers without changing
+/// the signature of each parser in between.
+///
+/// # Examples
+///
+/// ```
+/// use flac::DecodeContext;
+/// use flac::metadata::metadata_parser_with_context;
+///
+/// let context = DecodeContext::new().with_max_comments(16);
+/// let bytes   = b"\x81\0\0\x04\0\0\0\0";
+///
+/// let (_, block) = metadata_parser_with_context(bytes, &context).unwrap();
+///
+/// assert!(block.is_padding());
+/// assert_eq!(context.max_comments, 16);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecodeContext {
+  /// Largest number of comments kept from a `VorbisComment` block, with
+  /// the ones past it being parsed and then dropped.
+  pub max_comments: usize,
+  /// What happens to the placeholder points of a `SeekTable` block.
+  pub placeholder_policy: PlaceholderPolicy,
+  channel: usize,
+}
+
+impl DecodeContext {
+  /// Constructs a context with the default options, at the first channel.
+  #[inline]
+  pub fn new() -> Self {
+    Default::default()
+  }
+
+  /// Sets the largest number of comments kept from a `VorbisComment`
+  /// block.
+  pub fn with_max_comments(mut self, max_comments: usize) -> Self {
+    self.max_comments = max_comments;
+
+    self
+  }
+
+  /// Sets what happens to the placeholder points of a `SeekTable` block.
+  pub fn with_placeholder_policy(mut self,
+                                 placeholder_policy: PlaceholderPolicy)
+                                 -> Self {
+    self.placeholder_policy = placeholder_policy;
+
+    self
+  }
+
+  /// Returns the channel of the next subframe to be parsed.
+  ///
+  /// The frame parser starts each frame at channel zero, and every
+  /// subframe parsed moves it to the next channel.
+  #[inline]
+  pub fn channel(&self) -> usize {
+    self.channel
+  }
+
+  /// Sets the channel of the next subframe to be parsed, for parsing
+  /// subframes outside of a frame.
+  #[inline]
+  pub fn set_channel(&mut self, channel: usize) {
+    self.channel = channel;
+  }
+}
+
+impl Default for DecodeContext {
+  fn default() -> Self {
+    DecodeContext {
+      max_comments: MAX_COMMENTS,
+      placeholder_policy: PlaceholderPolicy::Keep,
+      channel: 0,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use utility::MAX_COMMENTS;
+  use metadata::PlaceholderPolicy;
+
+  #[test]
+  fn test_decode_context() {
+    let mut context = DecodeContext::new();
+
+    assert_eq!(context.max_comments, MAX_COMMENTS);
+    assert_eq!(context.placeholder_policy, PlaceholderPolicy::Keep);
+    assert_eq!(context.channel(), 0);
+
+    context.set_channel(3);
+
+    let context = context.with_max_comments(2)
+                         .with_placeholder_policy(PlaceholderPolicy::Drop);
+
+    assert_eq!((context.max_comments, context.channel()), (2, 3));
+    assert_eq!(context.placeholder_policy, PlaceholderPolicy::Drop);
+  }
+}
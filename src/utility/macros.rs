@@ -25,62 +25,7 @@ macro_rules! skip_bytes (
     }
   );
   ($input: expr, $length: expr) => (
-    skip_bytes!($input, $length, 0);
-  );
-);
-
-// A parser combiner for previously allocated buffers that can be passed
-// in as mutable slices. The macro will parse and fill the total length of
-// the passed in slice.
-macro_rules! count_slice (
-  ($input: expr, $submac: ident!( $($args:tt)* ), $result: expr) => (
-    {
-      let mut input    = $input;
-      let mut count    = 0;
-      let mut is_error = false;
-
-      for result in $result {
-        match $submac!(input, $($args)*) {
-          $crate::nom::IResult::Done(i, o)    => {
-            *result = o;
-
-            input   = i;
-            count  += 1;
-          }
-          $crate::nom::IResult::Error(_)      => {
-            is_error = true;
-            break;
-          }
-          $crate::nom::IResult::Incomplete(_) => break,
-        }
-      }
-
-      if is_error {
-        $crate::nom::IResult::Error($crate::nom::Err::Position(
-          $crate::nom::ErrorKind::Count, $input))
-      } else if count == $result.len() {
-        $crate::nom::IResult::Done(input, ())
-      } else {
-        $crate::nom::IResult::Incomplete($crate::nom::Needed::Unknown)
-      }
-    }
-  );
-  ($i: expr, $f: expr, $count: expr) => (
-    count_slice!($i, call!($f), $count);
-  );
-);
-
-// A parser combinator for converting parsed unsigned numbers into signed
-// two's complement based numbers. Without an explicit type passed in, the
-// default return type is `i32`.
-macro_rules! take_signed_bits (
-  ($input: expr, $signed_type: ty, $count: expr) => (
-    map!($input, take_bits!(u32, $count), |value| {
-      ::utility::extend_sign(value, $count) as $signed_type
-    });
-  );
-  ($input: expr, $count: expr) => (
-    take_signed_bits!($input, i32, $count);
+    skip_bytes!($input, $length, 0)
   );
 );
 
@@ -92,7 +37,7 @@ macro_rules! to_custom_error (
       $crate::nom::ErrorKind::Custom(::utility::ErrorKind::$error_type)))
   );
   ($input: expr, $f: expr, $error_type: ident) => (
-    to_custom_error!($input, call!($f), $error_type);
+    to_custom_error!($input, call!($f), $error_type)
   );
   ($error_type: ident) => (
     |_| $crate::nom::Err::Code($crate::nom::ErrorKind::Custom(
@@ -1,24 +1,237 @@
-mod crc;
+mod context;
 #[macro_use]
 mod macros;
 mod types;
 
-pub use self::crc::{crc8, crc16};
-pub use self::types::{ErrorKind, ByteStream, ReadStream};
+pub use self::context::DecodeContext;
+pub use crc::{crc8, crc16};
+pub use self::types::{
+  ErrorKind, ByteStream, ReadStream, BitWriter, BitsPerSample, SampleRate,
+};
 
-use nom::{self, IResult};
-use metadata::{Metadata, metadata_parser};
+use nom::{self, IResult, InputLength, Needed};
+use metadata::{Metadata, metadata_parser_with_context};
 
 use std::ops::{Add, AddAssign, BitAnd, BitOr, Mul, Sub, Shl, ShlAssign, Shr};
 use std::io;
 
+// Number of comments kept from a vorbis comment block by default.
+pub const MAX_COMMENTS: usize = 65536;
+
 /// An interface for parsing through some type of producer to a byte stream.
 ///
-/// External parsers get passed in and consumes the bytes held internally
-/// and outputs the `Result` of that parser.
+/// Producers only need to hand out the bytes they hold, read more when asked
+/// to, and keep track of how far into the byte stream they are. That keeps
+/// the trait object safe, so a `Box<StreamProducer>` works anywhere a
+/// producer does, and sources outside of this crate, like encrypted
+/// containers or archives, can plug into `Stream` directly.
+///
+/// External parsers get passed in through `parse`, which consumes the bytes
+/// held internally and outputs the `Result` of that parser.
+///
+/// # Examples
+///
+/// ```
+/// use flac::{ErrorKind, Stream, StreamProducer};
+/// use std::fs::File;
+/// use std::io::Read;
+///
+/// // Producer over bytes that have already been pulled out of a container.
+/// struct Unpacked {
+///   bytes: Vec<u8>,
+///   offset: usize,
+/// }
+///
+/// impl StreamProducer for Unpacked {
+///   fn fill(&mut self, _: usize) -> Result<&[u8], ErrorKind> {
+///     Ok(&self.bytes[self.offset..])
+///   }
+///
+///   fn consume(&mut self, amount: usize) {
+///     self.offset += amount;
+///   }
+///
+///   fn position(&self) -> u64 {
+///     self.offset as u64
+///   }
+/// }
+///
+/// let mut bytes = Vec::new();
+///
+/// File::open("tests/assets/input-SVAUP.flac")
+///   .and_then(|mut file| file.read_to_end(&mut bytes))
+///   .unwrap();
+///
+/// let producer: Box<StreamProducer> = Box::new(Unpacked {
+///   bytes: bytes,
+///   offset: 0,
+/// });
+///
+/// let mut stream = Stream::from_stream_producer(producer, Default::default())
+///   .unwrap();
+///
+/// let info    = stream.info();
+/// let samples = info.total_samples * info.channels as u64;
+///
+/// assert_eq!(stream.iter::<i32>().count() as u64, samples);
+/// ```
 pub trait StreamProducer {
+  /// Returns the bytes that haven't been consumed yet, reading more from the
+  /// source until there are at least `needed` of them or the source has run
+  /// out.
+  fn fill(&mut self, needed: usize) -> Result<&[u8], ErrorKind>;
+
+  /// Marks the first `amount` bytes returned from `fill` as consumed.
+  fn consume(&mut self, amount: usize);
+
+  /// Returns the offset, in bytes, from the beginning of the byte stream to
+  /// the next byte that will be parsed.
+  fn position(&self) -> u64;
+
+  /// Moves to the given offset, in bytes, from the beginning of the byte
+  /// stream, returning `None` when the source isn't able to move at all.
+  fn seek_if_supported(&mut self, offset: u64)
+                       -> Option<Result<(), ErrorKind>> {
+    let _ = offset;
+
+    None
+  }
+
+  /// Runs the parser `f` over the bytes that haven't been consumed yet,
+  /// consuming the ones it parsed.
+  ///
+  /// # Failures
+  ///
+  /// * `ErrorKind::EndOfInput` is returned when there are no more bytes.
+  /// * `ErrorKind::Continue` is returned when the parser needed more bytes
+  ///   and they've been read in, meaning the parser should be run again.
+  /// * `ErrorKind::Incomplete` is returned when the parser needed more bytes
+  ///   than the source has left.
   fn parse<F, T>(&mut self, f: F) -> Result<T, ErrorKind>
-   where F: FnOnce(&[u8]) -> IResult<&[u8], T, ErrorKind>;
+   where Self: Sized, F: FnOnce(&[u8]) -> IResult<&[u8], T, ErrorKind> {
+    let (available, result) = {
+      let bytes = try!(self.fill(1));
+
+      if bytes.is_empty() {
+        return Err(ErrorKind::EndOfInput);
+      }
+
+      (bytes.len(), from_iresult(bytes.len(), f(bytes)))
+    };
+
+    match result {
+      Ok((consumed, o))                  => {
+        self.consume(consumed);
+
+        Ok(o)
+      }
+      Err(ErrorKind::Incomplete(needed)) => {
+        // Reading ahead here means the next call has what the parser asked
+        // for, unless the source had nothing more to give.
+        let filled = try!(self.fill(needed)).len();
+
+        if filled > available {
+          Err(ErrorKind::Continue)
+        } else {
+          Err(ErrorKind::Incomplete(needed))
+        }
+      }
+      Err(kind)                          => Err(kind),
+    }
+  }
+}
+
+/// A `StreamProducer` that is able to move to a different part of the byte
+/// stream.
+pub trait SeekableProducer: StreamProducer {
+  /// Moves to the given offset, in bytes, from the beginning of the byte
+  /// stream.
+  fn seek(&mut self, offset: u64) -> Result<(), ErrorKind>;
+}
+
+impl<P> StreamProducer for Box<P> where P: StreamProducer + ?Sized {
+  #[inline]
+  fn fill(&mut self, needed: usize) -> Result<&[u8], ErrorKind> {
+    (**self).fill(needed)
+  }
+
+  #[inline]
+  fn consume(&mut self, amount: usize) {
+    (**self).consume(amount)
+  }
+
+  #[inline]
+  fn position(&self) -> u64 {
+    (**self).position()
+  }
+
+  #[inline]
+  fn seek_if_supported(&mut self, offset: u64)
+                       -> Option<Result<(), ErrorKind>> {
+    (**self).seek_if_supported(offset)
+  }
+}
+
+impl<'a, P> StreamProducer for &'a mut P where P: StreamProducer + ?Sized {
+  #[inline]
+  fn fill(&mut self, needed: usize) -> Result<&[u8], ErrorKind> {
+    (**self).fill(needed)
+  }
+
+  #[inline]
+  fn consume(&mut self, amount: usize) {
+    (**self).consume(amount)
+  }
+
+  #[inline]
+  fn position(&self) -> u64 {
+    (**self).position()
+  }
+
+  #[inline]
+  fn seek_if_supported(&mut self, offset: u64)
+                       -> Option<Result<(), ErrorKind>> {
+    (**self).seek_if_supported(offset)
+  }
+}
+
+impl<P> SeekableProducer for Box<P> where P: SeekableProducer + ?Sized {
+  #[inline]
+  fn seek(&mut self, offset: u64) -> Result<(), ErrorKind> {
+    (**self).seek(offset)
+  }
+}
+
+// Converts the result of a parser, run over `available` bytes, to the number
+// of bytes it consumed and its output.
+fn from_iresult<T>(available: usize, result: IResult<&[u8], T, ErrorKind>)
+                   -> Result<(usize, T), ErrorKind> {
+  match result {
+    IResult::Done(i, o)    => Ok((available - i.len(), o)),
+    IResult::Incomplete(n) => {
+      let mut needed = available + 1024;
+
+      if let Needed::Size(size) = n {
+        needed = size;
+      }
+
+      Err(ErrorKind::Incomplete(needed))
+    }
+    IResult::Error(e)      => {
+      match e {
+        nom::Err::Code(k)               |
+        nom::Err::Node(k, _)            |
+        nom::Err::Position(k, _)        |
+        nom::Err::NodePosition(k, _, _) => {
+          if let nom::ErrorKind::Custom(kind) = k {
+            Err(kind)
+          } else {
+            Err(ErrorKind::Unknown)
+          }
+        }
+      }
+    },
+  }
 }
 
 /// An abstraction trait for keeping different sized integers.
@@ -57,6 +270,30 @@ pub trait Sample: PartialEq + Eq + Sized + Clone + Copy +
 
   /// Convert an i32 into a `Sample`.
   fn from_i32_lossy(sample: i32) -> Self;
+
+  /// Convert a `Sample` into an i32, truncating samples larger than 32
+  /// bits.
+  fn to_i32_lossy(sample: Self) -> i32;
+
+  /// The largest value a sample of `bits_per_sample` bits can hold.
+  ///
+  /// Bit depths larger than `Sample::size_extended` are treated as the
+  /// extended size, and a bit depth of zero can only hold zero.
+  fn max_for_bps(bits_per_sample: usize) -> Self;
+
+  /// The smallest value a sample of `bits_per_sample` bits can hold.
+  ///
+  /// Bit depths larger than `Sample::size_extended` are treated as the
+  /// extended size, and a bit depth of zero can only hold zero.
+  fn min_for_bps(bits_per_sample: usize) -> Self;
+
+  /// The factor for scaling a sample of `bits_per_sample` bits into the
+  /// range of -1.0 to 1.0, which is the same as dividing by it.
+  ///
+  /// This is `2^(bits_per_sample - 1)`, so the smallest sample maps to
+  /// exactly -1.0 and the largest sample just under 1.0. A bit depth of
+  /// zero leaves samples unscaled.
+  fn scale_for_bps(bits_per_sample: usize) -> f64;
 }
 
 /// A trait for defining the size of a sample.
@@ -222,6 +459,45 @@ pub fn extend_sign(value: u32, bit_count: usize) -> i32 {
   }
 }
 
+// Reads up to 32 bits as an unsigned number, starting `offset` bits into
+// the first byte of the input. The bits are read a byte at a time rather
+// than one by one, which matters for the residuals where this gets called
+// for nearly every sample.
+//
+// NOTE: Not having enough bits asks for `count` more of them, relative to
+// where the read started, same as nom's `take_bits!` did.
+#[inline]
+pub fn take_bits(input: (&[u8], usize), count: usize)
+                 -> IResult<(&[u8], usize), u32> {
+  let (bytes, offset) = input;
+  let end             = offset + count;
+
+  debug_assert!(offset < 8 && count <= 32);
+
+  if count == 0 {
+    return IResult::Done(input, 0);
+  } else if bytes.len() * 8 < end {
+    return IResult::Incomplete(Needed::Size(count));
+  }
+
+  // At most five bytes, for 32 bits that start at the end of a byte.
+  let length = (end + 7) / 8;
+  let value  = bytes[0..length].iter().fold(0u64, |value, &byte| {
+    (value << 8) | byte as u64
+  });
+  let mask   = (1u64 << count) - 1;
+
+  IResult::Done((&bytes[(end / 8)..], end % 8),
+                ((value >> (length * 8 - end)) & mask) as u32)
+}
+
+// Same as `take_bits`, with the bits read as a two's complement number.
+#[inline]
+pub fn take_signed_bits(input: (&[u8], usize), count: usize)
+                        -> IResult<(&[u8], usize), i32> {
+  take_bits(input, count).map(|value| extend_sign(value, count))
+}
+
 // Bit shifted version for two to the power of a given exponent.
 #[inline]
 pub fn power_of_two(exponent: u32) -> u32 {
@@ -230,6 +506,23 @@ pub fn power_of_two(exponent: u32) -> u32 {
   1 << exponent
 }
 
+// Makes the size needed by a parser that started at `rest` relative to
+// `input` instead, for parsers that run one after another. Sizes are in
+// bytes for byte slices and in bits for bit inputs.
+#[inline]
+pub fn needed_from<I, O, E>(input: &I, rest: &I, result: IResult<I, O, E>)
+                            -> IResult<I, O, E>
+ where I: InputLength {
+  match result {
+    IResult::Incomplete(Needed::Size(size)) => {
+      let consumed = input.input_len() - rest.input_len();
+
+      IResult::Incomplete(Needed::Size(consumed + size))
+    }
+    result                                  => result,
+  }
+}
+
 #[derive(PartialEq, Eq)]
 enum ParserState {
   Header,
@@ -237,7 +530,8 @@ enum ParserState {
   Metadata
 }
 
-fn parser<'a>(input: &'a [u8], state: &mut ParserState)
+fn parser<'a>(input: &'a [u8], state: &mut ParserState,
+              context: &DecodeContext)
               -> IResult<&'a [u8], Metadata, ErrorKind> {
   let mut slice = input;
   let error     = nom::Err::Code(nom::ErrorKind::Custom(ErrorKind::Unknown));
@@ -253,7 +547,9 @@ fn parser<'a>(input: &'a [u8], state: &mut ParserState)
 
   match *state {
     ParserState::StreamInfo => {
-      let (i, block) = try_parse!(slice, metadata_parser);
+      let (i, block) = try_parse!(slice,
+                                  apply!(metadata_parser_with_context,
+                                         context));
 
       if block.is_stream_info() {
         *state = ParserState::Metadata;
@@ -263,19 +559,63 @@ fn parser<'a>(input: &'a [u8], state: &mut ParserState)
         IResult::Error(error)
       }
     }
-    ParserState::Metadata   => metadata_parser(slice),
+    ParserState::Metadata   => {
+      metadata_parser_with_context(slice, context)
+    }
     _                       => IResult::Error(error),
   }
 }
 
-pub fn many_metadata<S, F>(stream: &mut S, mut f: F) -> Result<(), ErrorKind>
+// Parses the single metadata block the stream is positioned at.
+pub fn metadata_block<S>(stream: &mut S, context: &DecodeContext)
+                         -> Result<Metadata, ErrorKind>
+ where S: StreamProducer {
+  loop {
+    match stream.parse(|i| metadata_parser_with_context(i, context)) {
+      Err(ErrorKind::Continue) => continue,
+      result                   => return result,
+    }
+  }
+}
+
+// Parses the "fLaC" header and every metadata block, returning the number
+// of bytes consumed.
+pub fn many_metadata<S, F>(stream: &mut S, context: &DecodeContext, f: F)
+                           -> Result<u64, ErrorKind>
  where S: StreamProducer,
        F: FnMut(Metadata) {
-  let mut state  = ParserState::Header;
-  let mut result = Ok(());
+  parse_blocks(stream, ParserState::Header, context, f)
+}
+
+// Parses metadata blocks, starting at the one the stream is positioned at,
+// up to and including the last one, returning the number of bytes consumed.
+pub fn many_blocks<S, F>(stream: &mut S, context: &DecodeContext, f: F)
+                         -> Result<u64, ErrorKind>
+ where S: StreamProducer,
+       F: FnMut(Metadata) {
+  parse_blocks(stream, ParserState::Metadata, context, f)
+}
+
+fn parse_blocks<S, F>(stream: &mut S, mut state: ParserState,
+                      context: &DecodeContext, mut f: F)
+                      -> Result<u64, ErrorKind>
+ where S: StreamProducer,
+       F: FnMut(Metadata) {
+  let mut consumed = 0;
+  let mut result   = Ok(());
 
   loop {
-    match stream.parse(|i| parser(i, &mut state)) {
+    let parsed = stream.parse(|i| {
+      let iresult = parser(i, &mut state, context);
+
+      if let IResult::Done(remaining, _) = iresult {
+        consumed += (i.len() - remaining.len()) as u64;
+      }
+
+      iresult
+    });
+
+    match parsed {
       Ok(block)                => {
         let is_last = block.is_last();
 
@@ -294,7 +634,78 @@ pub fn many_metadata<S, F>(stream: &mut S, mut f: F) -> Result<(), ErrorKind>
     }
   }
 
-  result
+  result.map(|_| consumed)
+}
+
+// Parses the "fLaC" header and `StreamInfo`, then moves past the rest of
+// the metadata blocks going only by their headers, seeking over the data of
+// each one. Returns `StreamInfo` along with the offsets of the block after
+// it and of the first audio frame, which are the same when `StreamInfo` is
+// the only block.
+//
+// Fails with `ErrorKind::Unsupported` when there are blocks to seek over
+// and the stream isn't able to seek.
+pub fn skip_metadata<S>(stream: &mut S, context: &DecodeContext)
+                        -> Result<(Metadata, u64, u64), ErrorKind>
+ where S: StreamProducer {
+  let mut state    = ParserState::Header;
+  let mut consumed = 0;
+  let stream_info;
+
+  loop {
+    let parsed = stream.parse(|i| {
+      let iresult = parser(i, &mut state, context);
+
+      if let IResult::Done(remaining, _) = iresult {
+        consumed += (i.len() - remaining.len()) as u64;
+      }
+
+      iresult
+    });
+
+    match parsed {
+      Ok(block)                => {
+        stream_info = block;
+
+        break;
+      }
+      Err(ErrorKind::Continue) => continue,
+      Err(e)                   => return Err(e),
+    }
+  }
+
+  let start       = consumed;
+  let mut is_last = stream_info.is_last();
+
+  while !is_last {
+    let (last, length) = match stream.parse(block_header) {
+      Ok(header)               => header,
+      Err(ErrorKind::Continue) => continue,
+      Err(e)                   => return Err(e),
+    };
+
+    consumed += 4 + length as u64;
+    is_last   = last;
+
+    match stream.seek_if_supported(consumed) {
+      Some(result) => try!(result),
+      None         => return Err(ErrorKind::Unsupported),
+    }
+  }
+
+  Ok((stream_info, start, consumed))
+}
+
+// Parses the header in front of a metadata block into whether it's the
+// last block and the length of its data.
+fn block_header(input: &[u8]) -> IResult<&[u8], (bool, u32), ErrorKind> {
+  if input.len() < 4 {
+    IResult::Incomplete(Needed::Size(4))
+  } else {
+    let is_last = (input[0] >> 7) == 1;
+
+    IResult::Done(&input[4..], (is_last, to_u32(&input[1..4])))
+  }
 }
 
 #[cfg(test)]
@@ -335,6 +746,31 @@ mod tests {
     assert_eq!(extend_sign(2147483647, 32), 2147483647);
   }
 
+  #[test]
+  fn test_take_bits() {
+    let bytes = [0b10110011, 0xff, 0x00, 0x5a, 0xc3];
+
+    assert_eq!(take_bits((&bytes[..], 0), 0),
+               IResult::Done((&bytes[..], 0), 0));
+    assert_eq!(take_bits((&bytes[..], 0), 3),
+               IResult::Done((&bytes[..], 3), 0b101));
+    assert_eq!(take_bits((&bytes[..], 3), 5),
+               IResult::Done((&bytes[1..], 0), 0b10011));
+    assert_eq!(take_bits((&bytes[..], 6), 12),
+               IResult::Done((&bytes[2..], 2), 0b111111111100));
+    assert_eq!(take_bits((&bytes[1..], 0), 32),
+               IResult::Done((&bytes[5..], 0), 0xff005ac3));
+    assert_eq!(take_bits((&bytes[..], 7), 32),
+               IResult::Done((&bytes[4..], 7), 0xff802d61));
+    assert_eq!(take_bits((&bytes[3..], 2), 15),
+               IResult::Incomplete(Needed::Size(15)));
+
+    assert_eq!(take_signed_bits((&bytes[..], 0), 3),
+               IResult::Done((&bytes[..], 3), -3));
+    assert_eq!(take_signed_bits((&bytes[..], 4), 4),
+               IResult::Done((&bytes[1..], 0), 3));
+  }
+
   #[test]
   #[should_panic]
   fn test_panic_power_of_two() {
@@ -1,10 +1,8 @@
-use nom::{self, IResult, Needed};
-
-use std::io::{self, Read};
+use std::io::{self, Read, Seek, SeekFrom};
 use std::ptr;
 use std::cmp;
 
-use super::{Sample, StreamProducer};
+use super::{Sample, StreamProducer, SeekableProducer};
 
 /// Represent the different kinds of errors.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -89,9 +87,181 @@ pub enum ErrorKind {
   InvalidCRC16,
   /// A subframe header that could cause sync-fooling.
   InvalidSubframeHeader,
+  /// A frame with more samples than the maximum block size within
+  /// `StreamInfo` allows.
+  InvalidBlockSize,
+  /// A frame with channels or bits per sample different from `StreamInfo`,
+  /// which FLAC doesn't allow to change within a stream.
+  InvalidFrameFormat,
+  /// Bytes left over after something that was expected to take up the
+  /// whole input, like a packet holding more than a single frame.
+  TrailingBytes,
+  // Output
+  /// An output buffer too small to hold a whole frame, along with the number
+  /// of samples it needs to hold.
+  OutputTooSmall(usize),
   // Not Found
   /// Some metadata block was not found with a specific filter.
   NotFound,
+  // Unsupported
+  /// The stream needs something that has to be explicitly allowed, like
+  /// block sizes larger than the FLAC subset permits.
+  Unsupported,
+  // Cancelled
+  /// Decoding was stopped through a `stream::CancelHandle`.
+  Cancelled,
+}
+
+// Lowest sample rate, in hertz, `SampleRate::is_audio_rate` counts as audio.
+const MIN_AUDIO_RATE: u32 = 8000;
+
+/// Number of bits within each sample, limited to what FLAC supports.
+///
+/// # Examples
+///
+/// ```
+/// use flac::BitsPerSample;
+///
+/// let bits_per_sample = BitsPerSample::new(16).unwrap();
+///
+/// assert_eq!(bits_per_sample.get(), 16);
+/// assert!(BitsPerSample::new(33).is_none());
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BitsPerSample(u8);
+
+impl BitsPerSample {
+  /// Smallest number of bits per sample FLAC supports.
+  #[inline]
+  pub fn min_value() -> Self {
+    BitsPerSample(4)
+  }
+
+  /// Largest number of bits per sample FLAC supports.
+  #[inline]
+  pub fn max_value() -> Self {
+    BitsPerSample(32)
+  }
+
+  /// Constructs a `BitsPerSample`, returning `None` when the value is
+  /// outside of 4 to 32 bits.
+  pub fn new(bits_per_sample: u8) -> Option<Self> {
+    if bits_per_sample >= 4 && bits_per_sample <= 32 {
+      Some(BitsPerSample(bits_per_sample))
+    } else {
+      None
+    }
+  }
+
+  /// Returns the number of bits.
+  #[inline]
+  pub fn get(&self) -> u8 {
+    self.0
+  }
+}
+
+impl Default for BitsPerSample {
+  /// Defaults to 16 bits, the same as audio CDs.
+  #[inline]
+  fn default() -> Self {
+    BitsPerSample(16)
+  }
+}
+
+impl From<BitsPerSample> for u8 {
+  #[inline]
+  fn from(bits_per_sample: BitsPerSample) -> u8 {
+    bits_per_sample.0
+  }
+}
+
+impl From<BitsPerSample> for usize {
+  #[inline]
+  fn from(bits_per_sample: BitsPerSample) -> usize {
+    bits_per_sample.0 as usize
+  }
+}
+
+/// Number of samples per second, in hertz, limited to what a FLAC frame
+/// header is able to hold.
+///
+/// # Examples
+///
+/// ```
+/// use flac::SampleRate;
+///
+/// let sample_rate = SampleRate::new(44100).unwrap();
+///
+/// assert_eq!(sample_rate.get(), 44100);
+/// assert!(SampleRate::new(0).is_none());
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SampleRate(u32);
+
+impl SampleRate {
+  /// Smallest sample rate FLAC supports.
+  #[inline]
+  pub fn min_value() -> Self {
+    SampleRate(1)
+  }
+
+  /// Largest sample rate FLAC supports.
+  #[inline]
+  pub fn max_value() -> Self {
+    SampleRate(655350)
+  }
+
+  /// Constructs a `SampleRate`, returning `None` when the value is outside
+  /// of 1Hz to 655350Hz.
+  pub fn new(sample_rate: u32) -> Option<Self> {
+    if sample_rate >= 1 && sample_rate <= 655350 {
+      Some(SampleRate(sample_rate))
+    } else {
+      None
+    }
+  }
+
+  /// Returns the number of samples per second.
+  #[inline]
+  pub fn get(&self) -> u32 {
+    self.0
+  }
+
+  /// Returns whether the sample rate is high enough to be audio, which is
+  /// taken to be 8kHz and up, the lowest rate with a code of its own.
+  ///
+  /// FLAC allows rates as low as 1Hz, which get used for storing things
+  /// like sensor readings rather than sound. Applications that play or
+  /// resample audio can use this to handle such streams some other way.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use flac::SampleRate;
+  ///
+  /// assert!(SampleRate::new(44100).unwrap().is_audio_rate());
+  /// assert!(SampleRate::new(8000).unwrap().is_audio_rate());
+  /// assert!(!SampleRate::new(1).unwrap().is_audio_rate());
+  /// ```
+  #[inline]
+  pub fn is_audio_rate(&self) -> bool {
+    self.0 >= MIN_AUDIO_RATE
+  }
+}
+
+impl Default for SampleRate {
+  /// Defaults to 44.1kHz, the same as audio CDs.
+  #[inline]
+  fn default() -> Self {
+    SampleRate(44100)
+  }
+}
+
+impl From<SampleRate> for u32 {
+  #[inline]
+  fn from(sample_rate: SampleRate) -> u32 {
+    sample_rate.0
+  }
 }
 
 /// Structure that hold a slice of bytes.
@@ -123,42 +293,37 @@ impl<'a> ByteStream<'a> {
 }
 
 impl<'a> StreamProducer for ByteStream<'a> {
-  fn parse<F, T>(&mut self, f: F) -> Result<T, ErrorKind>
-   where F: FnOnce(&[u8]) -> IResult<&[u8], T, ErrorKind> {
-    if self.is_empty() {
-      return Err(ErrorKind::EndOfInput);
-    }
+  #[inline]
+  fn fill(&mut self, _: usize) -> Result<&[u8], ErrorKind> {
+    Ok(&self.bytes[self.offset..])
+  }
 
-    match f(&self.bytes[self.offset..]) {
-      IResult::Done(i, o)    => {
-        self.offset += self.len() - i.len();
+  #[inline]
+  fn consume(&mut self, amount: usize) {
+    self.offset += amount;
+  }
 
-        Ok(o)
-      }
-      IResult::Incomplete(n) => {
-        let mut needed = self.len();
+  #[inline]
+  fn position(&self) -> u64 {
+    self.offset as u64
+  }
 
-        if let Needed::Size(size) = n {
-          needed = size;
-        }
+  #[inline]
+  fn seek_if_supported(&mut self, offset: u64)
+                       -> Option<Result<(), ErrorKind>> {
+    Some(self.seek(offset))
+  }
+}
 
-        Err(ErrorKind::Incomplete(needed))
-      }
-      IResult::Error(e)      => {
-        match e {
-          nom::Err::Code(k)               |
-          nom::Err::Node(k, _)            |
-          nom::Err::Position(k, _)        |
-          nom::Err::NodePosition(k, _, _) => {
-            if let nom::ErrorKind::Custom(kind) = k {
-              Err(kind)
-            } else {
-              Err(ErrorKind::Unknown)
-            }
-          }
-        }
-      },
+impl<'a> SeekableProducer for ByteStream<'a> {
+  fn seek(&mut self, offset: u64) -> Result<(), ErrorKind> {
+    if offset > self.bytes.len() as u64 {
+      return Err(ErrorKind::EndOfInput);
     }
+
+    self.offset = offset as usize;
+
+    Ok(())
   }
 }
 
@@ -180,14 +345,8 @@ impl Buffer {
 
   // Explicitly set the buffer capacity.
   pub fn with_capacity(capacity: usize) -> Self {
-    let mut buffer = Vec::with_capacity(capacity);
-
-    unsafe {
-      buffer.set_len(capacity);
-    }
-
     Buffer {
-      data: buffer,
+      data: vec![0; capacity],
       filled: 0,
       offset: 0,
     }
@@ -199,18 +358,6 @@ impl Buffer {
     self.filled - self.offset
   }
 
-  // Return true if buffer contains no more bytes.
-  #[inline]
-  pub fn is_empty(&self) -> bool {
-    self.len() == 0
-  }
-
-  // The set length of the unlining buffer.
-  #[inline]
-  pub fn capacity(&self) -> usize {
-    self.data.len()
-  }
-
   // Return a reference to the slice of unread bytes.
   pub fn as_slice(&self) -> &[u8] {
     &self.data[self.offset..self.filled]
@@ -236,9 +383,7 @@ impl Buffer {
 
       let capacity = self.data.capacity();
 
-      unsafe {
-        self.data.set_len(capacity);
-      }
+      self.data.resize(capacity, 0);
     }
 
     if self.data.len() - self.filled < size  {
@@ -260,6 +405,12 @@ impl Buffer {
   pub fn consume(&mut self, consumed: usize) {
     self.offset += consumed;
   }
+
+  // Discard every byte that hasn't been consumed yet.
+  pub fn clear(&mut self) {
+    self.filled = 0;
+    self.offset = 0;
+  }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -268,21 +419,24 @@ enum ParserState {
   EndOfInput,
 }
 
+// Most bytes the buffer of a `ReadStream` grows by ahead of a read.
+const MAX_FILL_STEP: usize = 64 * 1024;
+
 fn fill<R: Read>(buffer: &mut Buffer, reader: &mut R, needed: usize)
                  -> io::Result<usize> {
   let mut read = 0;
 
-  if buffer.len() < needed {
-    buffer.resize(needed);
+  while buffer.len() < needed {
+    // The size needed comes from the stream, which can claim any size, so
+    // the buffer only grows as far as there are bytes to read.
+    buffer.resize(cmp::min(needed, buffer.len() + MAX_FILL_STEP));
 
-    while buffer.len() < needed {
-      let size_read = try!(buffer.fill(reader));
+    let size_read = try!(buffer.fill(reader));
 
-      if size_read > 0 {
-        read += size_read;
-      } else {
-        break;
-      }
+    if size_read > 0 {
+      read += size_read;
+    } else {
+      break;
     }
   }
 
@@ -293,8 +447,9 @@ fn fill<R: Read>(buffer: &mut Buffer, reader: &mut R, needed: usize)
 pub struct ReadStream<R: Read> {
   reader: R,
   buffer: Buffer,
-  needed: usize,
+  position: u64,
   state: ParserState,
+  seek: Option<fn(&mut R, u64) -> io::Result<u64>>,
 }
 
 impl<R> ReadStream<R> where R: Read {
@@ -303,92 +458,93 @@ impl<R> ReadStream<R> where R: Read {
     ReadStream {
       reader: reader,
       buffer: Buffer::new(),
-      needed: 0,
+      position: 0,
       state: ParserState::Incomplete,
+      seek: None,
     }
   }
 
-  // Fill the stream with bytes from a `Read` source.
-  fn fill(&mut self) -> io::Result<usize> {
-    let needed = cmp::max(1, self.needed);
+  // Move to `offset`, only going through `seek` when the bytes in between
+  // aren't already buffered.
+  fn move_to(&mut self, offset: u64,
+             seek: fn(&mut R, u64) -> io::Result<u64>)
+             -> Result<(), ErrorKind> {
+    let buffered = self.buffer.len() as u64;
 
-    fill(&mut self.buffer, &mut self.reader, needed).map(|consumed| {
-      if self.buffer.len() < needed {
-        self.state = ParserState::EndOfInput;
-      }
+    // Reuse the bytes already read when moving forward within the buffer.
+    if offset >= self.position && offset - self.position <= buffered {
+      self.buffer.consume((offset - self.position) as usize);
+    } else {
+      try!(seek(&mut self.reader, offset).map_err(|e| ErrorKind::IO(e.kind())));
 
-      consumed
-    })
+      self.buffer.clear();
+    }
+
+    self.position = offset;
+    self.state    = ParserState::Incomplete;
+
+    Ok(())
   }
 }
 
-fn from_iresult<T>(buffer: &Buffer, result: IResult<&[u8], T, ErrorKind>)
-                   -> Result<(usize, T), ErrorKind> {
-  match result {
-    IResult::Done(i, o)    => Ok((buffer.len() - i.len(), o)),
-    IResult::Incomplete(n) => {
-      let mut needed = buffer.capacity() + 1024;
+impl<R> ReadStream<R> where R: Read + Seek {
+  /// Constructor for `ReadStream` based on a source that can also seek.
+  ///
+  /// Unlike `ReadStream::new`, the stream is able to move through
+  /// `StreamProducer::seek_if_supported`, even after being boxed as a
+  /// `Box<StreamProducer>`.
+  pub fn seekable(reader: R) -> Self {
+    let mut stream = ReadStream::new(reader);
 
-      if let Needed::Size(size) = n {
-        needed = size;
-      }
+    stream.seek = Some(seek_from_start::<R>);
 
-      Err(ErrorKind::Incomplete(needed))
-    }
-    IResult::Error(e)      => {
-      match e {
-        nom::Err::Code(k)               |
-        nom::Err::Node(k, _)            |
-        nom::Err::Position(k, _)        |
-        nom::Err::NodePosition(k, _, _) => {
-          if let nom::ErrorKind::Custom(kind) = k {
-            Err(kind)
-          } else {
-            Err(ErrorKind::Unknown)
-          }
-        }
-      }
-    },
+    stream
   }
 }
 
-impl<R> StreamProducer for ReadStream<R> where R: Read {
-  fn parse<F, T>(&mut self, f: F) -> Result<T, ErrorKind>
-   where F: FnOnce(&[u8]) -> IResult<&[u8], T, ErrorKind> {
-    if self.state != ParserState::EndOfInput {
-      try!(self.fill().map_err(|e| ErrorKind::IO(e.kind())));
-    }
+fn seek_from_start<R: Seek>(reader: &mut R, offset: u64) -> io::Result<u64> {
+  reader.seek(SeekFrom::Start(offset))
+}
 
-    let mut buffer = &mut self.buffer;
+impl<R> StreamProducer for ReadStream<R> where R: Read {
+  fn fill(&mut self, needed: usize) -> Result<&[u8], ErrorKind> {
+    let needed = cmp::max(1, needed);
 
-    if buffer.is_empty() {
-      self.state = ParserState::EndOfInput;
+    // Once the reader has run out there's no point in asking it again.
+    if self.state != ParserState::EndOfInput && self.buffer.len() < needed {
+      try!(fill(&mut self.buffer, &mut self.reader, needed)
+             .map_err(|e| ErrorKind::IO(e.kind())));
 
-      return Err(ErrorKind::EndOfInput);
+      if self.buffer.len() < needed {
+        self.state = ParserState::EndOfInput;
+      }
     }
 
-    let result = {
-      let iresult = f(buffer.as_slice());
+    Ok(self.buffer.as_slice())
+  }
 
-      from_iresult(&buffer, iresult)
-    };
+  #[inline]
+  fn consume(&mut self, amount: usize) {
+    self.buffer.consume(amount);
 
-    match result {
-      Ok((consumed, o)) => {
-        buffer.consume(consumed);
+    self.position += amount as u64;
+  }
 
-        Ok(o)
-      }
-      Err(kind)         => {
-        if let ErrorKind::Incomplete(needed) = kind {
-          self.needed = needed;
+  #[inline]
+  fn position(&self) -> u64 {
+    self.position
+  }
 
-          Err(ErrorKind::Continue)
-        } else {
-          Err(kind)
-        }
-      }
-    }
+  fn seek_if_supported(&mut self, offset: u64)
+                       -> Option<Result<(), ErrorKind>> {
+    self.seek.map(|seek| self.move_to(offset, seek))
+  }
+}
+
+impl<R> SeekableProducer for ReadStream<R> where R: Read + Seek {
+  #[inline]
+  fn seek(&mut self, offset: u64) -> Result<(), ErrorKind> {
+    self.move_to(offset, seek_from_start::<R>)
   }
 }
 
@@ -404,10 +560,8 @@ macro_rules! sample (
       fn size_extended() -> usize { $bits_per_sample * 2 }
 
       fn to_normal(sample: Self) -> Option<Self::Normal> {
-        use std::$normal;
-
-        let min = $normal::min_value() as $extended;
-        let max = $normal::max_value() as $extended;
+        let min = Self::min_for_bps(Self::size());
+        let max = Self::max_for_bps(Self::size());
 
         if sample >= min && sample <= max {
           Some(sample as $normal)
@@ -446,6 +600,43 @@ macro_rules! sample (
       fn from_i32_lossy(sample: i32) -> Self {
         sample as Self
       }
+
+      #[inline]
+      fn to_i32_lossy(sample: Self) -> i32 {
+        sample as i32
+      }
+
+      fn max_for_bps(bits_per_sample: usize) -> Self {
+        use std::$extended;
+
+        if bits_per_sample == 0 {
+          0
+        } else if bits_per_sample >= Self::size_extended() {
+          $extended::max_value()
+        } else {
+          (1 << (bits_per_sample - 1)) - 1
+        }
+      }
+
+      fn min_for_bps(bits_per_sample: usize) -> Self {
+        use std::$extended;
+
+        if bits_per_sample == 0 {
+          0
+        } else if bits_per_sample >= Self::size_extended() {
+          $extended::min_value()
+        } else {
+          -(1 << (bits_per_sample - 1))
+        }
+      }
+
+      #[inline]
+      fn scale_for_bps(bits_per_sample: usize) -> f64 {
+        match bits_per_sample {
+          0    => 1.0,
+          bits => (1u64 << (cmp::min(bits, 64) - 1)) as f64,
+        }
+      }
     }
   )
 );
@@ -454,27 +645,115 @@ sample!(i8, i16, 8);
 sample!(i16, i32, 16);
 sample!(i32, i64, 32);
 
+// Writer of values with arbitrary bit lengths into a vector of bytes.
+//
+// Bits are written most significant first, which is the order every
+// value in a FLAC frame is stored in.
+pub struct BitWriter {
+  bytes: Vec<u8>,
+  bits: u64,
+  count: usize,
+}
+
+impl BitWriter {
+  // Default constructor for `BitWriter`
+  pub fn new() -> Self {
+    BitWriter {
+      bytes: Vec::new(),
+      bits: 0,
+      count: 0,
+    }
+  }
+
+  // Writes the lowest `bit_count` bits of `value`, up to 32 bits.
+  pub fn write(&mut self, value: u64, bit_count: usize) {
+    debug_assert!(bit_count <= 32);
+
+    let mask = (1 << bit_count) - 1;
+
+    self.bits   = (self.bits << bit_count) | (value & mask);
+    self.count += bit_count;
+
+    while self.count >= 8 {
+      self.count -= 8;
+
+      self.bytes.push((self.bits >> self.count) as u8);
+    }
+  }
+
+  // Writes a signed value in two's complement, up to 32 bits.
+  #[inline]
+  pub fn write_signed(&mut self, value: i64, bit_count: usize) {
+    self.write(value as u64, bit_count)
+  }
+
+  // Writes `zeros` in unary notation, which is that number of zero bits
+  // followed by a one.
+  pub fn write_unary(&mut self, zeros: u64) {
+    let mut zeros = zeros;
+
+    while zeros >= 32 {
+      self.write(0, 32);
+
+      zeros -= 32;
+    }
+
+    self.write(1, zeros as usize + 1);
+  }
+
+  // Pads the remaining bits of the current byte with zeros.
+  pub fn align(&mut self) {
+    let remaining = (8 - self.count % 8) % 8;
+
+    self.write(0, remaining);
+  }
+
+  // Returns the written bytes, aligning to the next byte first.
+  pub fn into_bytes(mut self) -> Vec<u8> {
+    self.align();
+
+    self.bytes
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
-  use utility::{Sample, StreamProducer};
+  use utility::{Sample, StreamProducer, SeekableProducer};
   use nom::{self, IResult};
 
   use std::{i8, i16, i32};
+  use std::io::Cursor;
 
   #[inline]
   fn be_u32(input: &[u8]) -> IResult<&[u8], u32, ErrorKind> {
     to_custom_error!(input, nom::be_u32, Unknown)
   }
 
+  #[test]
+  fn test_bit_writer() {
+    let mut writer = BitWriter::new();
+
+    writer.write(0b101, 3);
+    writer.write_signed(-1, 4);
+    writer.write_unary(3);
+    writer.write(0xabcdef, 24);
+    writer.write_unary(40);
+
+    let bytes = writer.into_bytes();
+
+    assert_eq!(bytes, vec![0b10111110, 0b00110101, 0b01111001, 0b10111101,
+                           0b11100000, 0, 0, 0, 0, 0b00010000]);
+  }
+
   #[test]
   fn test_buffer() {
     let mut buffer = Buffer::new();
     let bytes      = b"Hello World";
     let mut reader = &bytes[..];
 
-    assert!(buffer.is_empty());
-    assert_eq!(buffer.capacity(), 1024);
+    assert_eq!(buffer.len(), 0);
+    assert_eq!(buffer.data.len(), 1024);
 
     let bytes_read = buffer.fill(&mut reader).unwrap_or(0);
     let bytes_len  = bytes.len();
@@ -484,7 +763,7 @@ mod tests {
     assert_eq!(buffer.as_slice(), bytes);
 
     buffer.resize(512);
-    assert_eq!(buffer.capacity(), 1024);
+    assert_eq!(buffer.data.len(), 1024);
   }
 
   #[test]
@@ -510,6 +789,75 @@ mod tests {
     assert_eq!(result, 1214606444)
   }
 
+  #[test]
+  fn test_byte_stream_seek() {
+    let bytes      = b"Hello World";
+    let mut stream = ByteStream::new(bytes);
+
+    assert_eq!(stream.parse(be_u32).unwrap_or(0), 1214606444);
+    assert_eq!(stream.position(), 4);
+
+    assert!(stream.seek(0).is_ok());
+    assert_eq!(stream.position(), 0);
+    assert_eq!(stream.parse(be_u32).unwrap_or(0), 1214606444);
+
+    assert_eq!(stream.seek(12), Err(ErrorKind::EndOfInput));
+  }
+
+  #[test]
+  fn test_read_stream_seek() {
+    let bytes      = b"Hello World";
+    let mut stream = ReadStream::new(Cursor::new(&bytes[..]));
+
+    assert_eq!(stream.parse(be_u32).unwrap_or(0), 1214606444);
+    assert_eq!(stream.position(), 4);
+
+    assert!(stream.seek(6).is_ok());
+    assert_eq!(stream.position(), 6);
+    assert_eq!(stream.parse(be_u32).unwrap_or(0), 1466921580);
+
+    assert!(stream.seek(0).is_ok());
+    assert_eq!(stream.parse(be_u32).unwrap_or(0), 1214606444);
+  }
+
+  #[test]
+  fn test_seek_if_supported() {
+    let bytes = b"Hello World";
+
+    let mut unseekable = ReadStream::new(Cursor::new(&bytes[..]));
+    let mut seekable   = ReadStream::seekable(Cursor::new(&bytes[..]));
+
+    assert!(unseekable.seek_if_supported(6).is_none());
+    assert_eq!(seekable.seek_if_supported(6), Some(Ok(())));
+    assert_eq!(seekable.parse(be_u32).unwrap_or(0), 1466921580);
+
+    let mut boxed: Box<StreamProducer> = Box::new(ByteStream::new(bytes));
+
+    assert_eq!(boxed.seek_if_supported(6), Some(Ok(())));
+    assert_eq!(boxed.parse(be_u32).unwrap_or(0), 1466921580);
+    assert_eq!(boxed.position(), 10);
+    assert_eq!(boxed.parse(be_u32), Err(ErrorKind::Incomplete(4)));
+    assert_eq!(boxed.seek_if_supported(12), Some(Err(ErrorKind::EndOfInput)));
+  }
+
+  #[test]
+  fn test_read_stream_continue() {
+    let bytes      = b"Hello World";
+    let mut stream = ReadStream::new(&bytes[..]);
+
+    assert_eq!(stream.fill(2).map(|bytes| bytes.len()), Ok(bytes.len()));
+    assert_eq!(stream.parse(be_u32).unwrap_or(0), 1214606444);
+
+    stream.consume(6);
+
+    assert_eq!(stream.position(), 10);
+    assert_eq!(stream.parse(be_u32), Err(ErrorKind::Incomplete(4)));
+
+    stream.consume(1);
+
+    assert_eq!(stream.parse(be_u32), Err(ErrorKind::EndOfInput));
+  }
+
   #[test]
   fn test_sample_to_normal() {
     {
@@ -561,6 +909,41 @@ mod tests {
     assert_eq!(<i64 as Sample>::size_extended(), 64);
   }
 
+  #[test]
+  fn test_bps_limits() {
+    assert_eq!(<i16 as Sample>::max_for_bps(8), 127);
+    assert_eq!(<i16 as Sample>::min_for_bps(8), -128);
+    assert_eq!(<i16 as Sample>::max_for_bps(16), i16::max_value());
+
+    assert_eq!(<i32 as Sample>::max_for_bps(12), 2047);
+    assert_eq!(<i32 as Sample>::min_for_bps(17), -65536);
+
+    assert_eq!(<i64 as Sample>::max_for_bps(24), 8388607);
+    assert_eq!(<i64 as Sample>::min_for_bps(32), i32::min_value() as i64);
+    assert_eq!(<i64 as Sample>::min_for_bps(64), i64::min_value());
+
+    assert_eq!(<i16 as Sample>::max_for_bps(0), 0);
+    assert_eq!(<i64 as Sample>::min_for_bps(0), 0);
+    assert_eq!(<i32 as Sample>::scale_for_bps(0), 1.0);
+
+    assert_eq!(<i32 as Sample>::scale_for_bps(16), 32768.0);
+    assert_eq!(<i64 as Sample>::scale_for_bps(24), 8388608.0);
+  }
+
+  #[test]
+  fn test_validated_units() {
+    assert_eq!(BitsPerSample::new(3), None);
+    assert_eq!(BitsPerSample::new(4), Some(BitsPerSample::min_value()));
+    assert_eq!(BitsPerSample::new(32), Some(BitsPerSample::max_value()));
+    assert_eq!(usize::from(BitsPerSample::new(24).unwrap()), 24);
+
+    assert_eq!(SampleRate::new(0), None);
+    assert_eq!(SampleRate::new(1), Some(SampleRate::min_value()));
+    assert_eq!(SampleRate::new(655350), Some(SampleRate::max_value()));
+    assert_eq!(SampleRate::new(655351), None);
+    assert_eq!(u32::from(SampleRate::new(48000).unwrap()), 48000);
+  }
+
   #[test]
   fn test_from_i8() {
     let min  = i8::min_value();
@@ -628,4 +1011,13 @@ mod tests {
     assert_eq!(<i64 as Sample>::from_i32_lossy(zero), zero as i64);
     assert_eq!(<i64 as Sample>::from_i32_lossy(max), max as i64);
   }
+
+  #[test]
+  fn test_to_i32_lossy() {
+    assert_eq!(<i16 as Sample>::to_i32_lossy(-1), -1);
+    assert_eq!(<i32 as Sample>::to_i32_lossy(i32::max_value()),
+               i32::max_value());
+    assert_eq!(<i64 as Sample>::to_i32_lossy(-5), -5);
+    assert_eq!(<i64 as Sample>::to_i32_lossy(1 << 32), 0);
+  }
 }
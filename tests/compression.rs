@@ -0,0 +1,200 @@
+// Compression regression tests for the encoder.
+//
+// Each signal within the corpus gets encoded and the size of the resulting
+// stream compared against the size recorded for it, so a change to the
+// predictor search or the Rice parameter estimation that makes any of them
+// larger gets caught. The signals are synthesized with integer math only,
+// keeping them the same on every platform. When a change makes a signal
+// compress noticeably better, the recorded size needs to be lowered to
+// match, which keeps the table close enough to catch the next regression.
+
+extern crate flac;
+
+use flac::{
+  BitsPerSample, ByteStream, Encoder, EncoderOptions, SampleRate, StereoMode,
+  Stream,
+};
+use flac::metadata::StreamInfo;
+
+use std::io::Cursor;
+
+// Samples per channel of every signal, two seconds at 44.1kHz.
+const LENGTH: usize = 88200;
+
+// Sine oscillator going by the "magic circle" recurrence, where `step` is
+// about `2 * sin(pi * frequency / sample_rate)` as a 16.16 fixed point
+// number.
+struct Oscillator {
+  x: i64,
+  y: i64,
+  step: i64,
+}
+
+impl Oscillator {
+  fn new(amplitude: i64, step: i64) -> Self {
+    Oscillator {
+      x: amplitude,
+      y: 0,
+      step: step,
+    }
+  }
+
+  fn next(&mut self) -> i64 {
+    self.x -= (self.step * self.y) >> 16;
+    self.y += (self.step * self.x) >> 16;
+
+    self.x
+  }
+}
+
+// Linear congruential generator for noise, returning values within
+// `-amplitude..amplitude`.
+struct Noise(u32);
+
+impl Noise {
+  fn next(&mut self, amplitude: i64) -> i64 {
+    self.0 = self.0.wrapping_mul(1664525).wrapping_add(1013904223);
+
+    ((self.0 >> 8) as i64 % (amplitude * 2)) - amplitude
+  }
+}
+
+fn stream_info(channels: u8, bits_per_sample: u8) -> StreamInfo {
+  let mut info = StreamInfo::default();
+
+  info.sample_rate     = SampleRate::new(44100).unwrap();
+  info.channels        = channels;
+  info.bits_per_sample = BitsPerSample::new(bits_per_sample).unwrap();
+  info.total_samples   = LENGTH as u64;
+
+  info
+}
+
+// A single steady tone, around 1kHz.
+fn sine_mono() -> Vec<i32> {
+  let mut tone = Oscillator::new(20000, 9330);
+
+  (0..LENGTH).map(|_| tone.next() as i32).collect()
+}
+
+// Quiet noise on both channels, with nothing in common between them.
+fn noise_stereo() -> Vec<i32> {
+  let mut noise = Noise(1);
+
+  (0..(LENGTH * 2)).map(|_| noise.next(2048) as i32).collect()
+}
+
+// Three tones mixed differently into each channel, so the channels are
+// close but not the same.
+fn chord_stereo() -> Vec<i32> {
+  let mut low   = Oscillator::new(9000, 2335);
+  let mut mid   = Oscillator::new(7000, 2942);
+  let mut high  = Oscillator::new(5000, 3499);
+  let mut noise = Noise(2);
+
+  (0..LENGTH).flat_map(|_| {
+    let (low, mid, high) = (low.next(), mid.next(), high.next());
+    let left             = low + mid + high / 2 + noise.next(16);
+    let right            = low / 2 + mid + high + noise.next(16);
+
+    vec![left as i32, right as i32]
+  }).collect()
+}
+
+// Notes with harmonics that decay and change four times a second, over a
+// bed of low noise, like a plucked instrument recorded at 24 bits.
+fn music_24bit() -> Vec<i32> {
+  let steps     = [2335, 2621, 2942, 3117, 3499, 3927, 4406, 4668];
+  let note_len  = LENGTH / 8;
+  let mut noise = Noise(3);
+  let mut notes = Vec::new();
+
+  for (i, &step) in steps.iter().enumerate() {
+    let mut root   = Oscillator::new(3000000, step);
+    let mut fifth  = Oscillator::new(1500000, step * 3 / 2);
+    let mut octave = Oscillator::new(800000, step * 2);
+
+    for j in 0..note_len {
+      let decay = (note_len - j) as i64;
+      let value = (root.next() + fifth.next() + octave.next()) * decay /
+                  note_len as i64;
+      let pan   = (i as i64 + 1) * 8;
+
+      notes.push(value * pan / 72 + noise.next(64));
+      notes.push(value * (72 - pan) / 72 + noise.next(64));
+    }
+  }
+
+  notes.into_iter().map(|sample| sample as i32).collect()
+}
+
+fn encode(info: StreamInfo, stereo_mode: StereoMode, samples: &[i32])
+          -> Vec<u8> {
+  let options = EncoderOptions {
+    stereo_mode: stereo_mode,
+    ..Default::default()
+  };
+
+  let writer      = Cursor::new(Vec::new());
+  let mut encoder = Encoder::new(writer, info, options).unwrap();
+
+  encoder.write(samples).unwrap();
+
+  encoder.finish().unwrap().into_inner()
+}
+
+// Encodes the signal, checking it decodes back the same, and compares the
+// size of the stream against the recorded one.
+fn check_size(name: &str, info: StreamInfo, stereo_mode: StereoMode,
+              samples: &[i32], expected: usize) {
+  let bytes = encode(info, stereo_mode, samples);
+
+  let mut stream = Stream::<ByteStream>::from_buffer(&bytes).unwrap();
+
+  assert!(stream.iter::<i32>().eq(samples.iter().cloned()),
+          "{} ({:?}) doesn't decode back the same", name, stereo_mode);
+
+  assert!(bytes.len() <= expected,
+          "{} ({:?}) grew from {} to {} bytes", name, stereo_mode, expected,
+          bytes.len());
+  assert!(bytes.len() * 100 >= expected * 99,
+          "{} ({:?}) shrank from {} to {} bytes, lower the recorded size",
+          name, stereo_mode, expected, bytes.len());
+}
+
+#[test]
+fn test_sine_mono() {
+  let samples = sine_mono();
+
+  check_size("sine_mono", stream_info(1, 16), StereoMode::Estimate,
+             &samples, 62107);
+}
+
+#[test]
+fn test_noise_stereo() {
+  let samples = noise_stereo();
+  let info    = stream_info(2, 16);
+
+  check_size("noise_stereo", info, StereoMode::Independent, &samples, 284066);
+  check_size("noise_stereo", info, StereoMode::Estimate, &samples, 284066);
+}
+
+#[test]
+fn test_chord_stereo() {
+  let samples = chord_stereo();
+  let info    = stream_info(2, 16);
+
+  check_size("chord_stereo", info, StereoMode::Independent, &samples,
+             160676);
+  check_size("chord_stereo", info, StereoMode::Estimate, &samples, 160635);
+  check_size("chord_stereo", info, StereoMode::Exhaustive, &samples, 160515);
+}
+
+#[test]
+fn test_music_24bit() {
+  let samples = music_24bit();
+  let info    = stream_info(2, 24);
+
+  check_size("music_24bit", info, StereoMode::Estimate, &samples, 233877);
+  check_size("music_24bit", info, StereoMode::Exhaustive, &samples, 230822);
+}
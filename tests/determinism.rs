@@ -0,0 +1,143 @@
+// Tests that decoding comes out the same on every platform.
+//
+// Each fixture gets decoded through the different paths, with the output
+// hashed and compared against an MD5 recorded when the test was written.
+// The hashes are the same no matter the target, pointer width, or whether
+// the build is optimized, so running this on every target in CI, along
+// with a release build for the target CPU, catches any platform specific
+// difference. A mismatch is a bug to fix, never a reason to record a new
+// hash, unless the change to the output was intentional on every target.
+
+extern crate flac;
+extern crate crypto;
+
+use crypto::digest::Digest;
+use crypto::md5::Md5;
+use flac::{ReadStream, Stream};
+use flac::sink::Sink;
+use flac::stream::Block;
+
+use std::fs::File;
+
+// Filename of each fixture, with the MD5 of its samples and of its analysis
+// windows.
+const RECORDED: [(&'static str, &'static str, &'static str); 6] = [
+  ( "tests/assets/input-mono-8bit.flac"
+  , "3097c76b15e14352eeec3b7ead83ee73"
+  , "76d953b41ed9a9e72a8976baeda5c680"
+  ),
+  ( "tests/assets/input-pictures.flac"
+  , "9174ea5776375f11f92a3fcc0081aec6"
+  , "0144a631efc50d51095268bbb0ea0c3b"
+  ),
+  ( "tests/assets/input-rice2-escape.flac"
+  , "8f32fac8bdaa43e28896e87621029fe9"
+  , "065939f9ef8a66d7876ad020b98d3321"
+  ),
+  ( "tests/assets/input-SCPAP.flac"
+  , "10c246a6899d194f92ff1520c114684e"
+  , "f5d8d2bfbbca26e64933760e2833fd91"
+  ),
+  ( "tests/assets/input-stereo-8bit.flac"
+  , "dde4330412ca69c5bb5eb1ada3243f84"
+  , "01fdd0985745d09173925628ac6fb080"
+  ),
+  ( "tests/assets/input-SVAUP.flac"
+  , "10c246a6899d194f92ff1520c114684e"
+  , "f5d8d2bfbbca26e64933760e2833fd91"
+  ),
+];
+
+// Keeps every sample written to it.
+struct Collect(Vec<i32>);
+
+impl Sink<i32> for Collect {
+  fn write_block(&mut self, block: &Block<i32>)
+                 -> Result<(), flac::ErrorKind> {
+    self.0.extend_from_slice(&block.samples);
+
+    Ok(())
+  }
+}
+
+fn open(filename: &str) -> Stream<ReadStream<File>> {
+  Stream::<ReadStream<File>>::from_file(filename).unwrap()
+}
+
+// MD5 of 32-bit values, each as little endian bytes.
+fn hash<I>(values: I) -> String
+ where I: Iterator<Item = u32> {
+  let mut md5 = Md5::new();
+
+  for value in values {
+    md5.input(&[value as u8, (value >> 8) as u8, (value >> 16) as u8,
+                (value >> 24) as u8]);
+  }
+
+  md5.result_str()
+}
+
+#[test]
+fn test_recorded_hashes() {
+  for &(filename, samples, windows) in &RECORDED {
+    let mut stream = open(filename);
+
+    assert_eq!(hash(stream.iter::<i32>().map(|s| s as u32)), samples,
+               "{} decoded differently", filename);
+
+    let mut stream = open(filename);
+    let values     = stream.analysis_windows(2048, 512)
+                           .flat_map(|window| window)
+                           .map(|value| value.to_bits());
+
+    assert_eq!(hash(values), windows,
+               "{} has different analysis windows", filename);
+  }
+}
+
+#[test]
+fn test_decode_paths() {
+  for &(filename, _, _) in &RECORDED {
+    let samples = open(filename).iter::<i32>().collect::<Vec<_>>();
+
+    let mut stream = open(filename);
+    let blocks     = stream.blocks::<i32>()
+                           .flat_map(|block| block.unwrap().samples)
+                           .collect::<Vec<_>>();
+
+    assert_eq!(blocks, samples, "{} blocks differ", filename);
+
+    let mut stream = open(filename);
+    let mut sink   = Collect(Vec::new());
+
+    stream.decode_into::<i32, _>(&mut sink).unwrap();
+
+    assert_eq!(sink.0, samples, "{} sink differs", filename);
+
+    // Samples scaled to 16 bits, which is what `next_frame_into_i16`
+    // hands out no matter the bits per sample of the stream.
+    let mut stream = open(filename);
+    let info       = stream.info();
+    let channels   = info.channels as usize;
+    let shift      = info.bits_per_sample.get() as i32 - 16;
+    let mut output = vec![0; info.max_block_size as usize * channels];
+    let mut frames = Vec::new();
+
+    loop {
+      let block_size = stream.next_frame_into_i16(&mut output).unwrap();
+
+      if block_size == 0 {
+        break;
+      }
+
+      frames.extend_from_slice(&output[0..(block_size * channels)]);
+    }
+
+    let scaled = samples.iter().map(|&sample| {
+      if shift >= 0 { sample >> shift } else { sample << -shift }
+    });
+
+    assert!(frames.iter().map(|&sample| sample as i32).eq(scaled),
+            "{} differs at 16 bits", filename);
+  }
+}
@@ -0,0 +1,166 @@
+// Differential tests against libFLAC, only built with the `difftest`
+// feature.
+//
+// Fixtures get decoded by both this crate and the `flac` tool that comes
+// with libFLAC, comparing the samples one by one. Streams from `Encoder`
+// get decoded by libFLAC as well, since decoding them with this crate alone
+// would hide a mistake made the same way on both sides. The tool is looked
+// up through the `FLAC` environment variable, then the `PATH`, and the
+// tests pass without comparing anything when it can't be found.
+
+#![cfg(feature = "difftest")]
+
+extern crate flac;
+
+use flac::{ByteStream, Encoder, EncoderOptions, Stream, StereoMode};
+use flac::metadata::StreamInfo;
+
+use std::env;
+use std::thread;
+use std::fs::File;
+use std::io::{Cursor, Read, Write};
+use std::process::{Command, Stdio};
+
+const FIXTURES: [&'static str; 4] = [
+  "tests/assets/input-pictures.flac",
+  "tests/assets/input-rice2-escape.flac",
+  "tests/assets/input-SCPAP.flac",
+  "tests/assets/input-SVAUP.flac",
+];
+
+// Returns the command for running the `flac` tool, when it's available.
+fn reference() -> Option<String> {
+  let command = env::var("FLAC").unwrap_or_else(|_| "flac".to_owned());
+  let status  = Command::new(&command).arg("--version")
+                                      .stdout(Stdio::null())
+                                      .stderr(Stdio::null())
+                                      .status();
+
+  match status {
+    Ok(ref status) if status.success() => Some(command),
+    _                                  => {
+      println!("skipping, the flac tool from libFLAC wasn't found");
+
+      None
+    }
+  }
+}
+
+// Decodes a FLAC stream with libFLAC into interleaved samples.
+fn reference_decode(command: &str, bytes: &[u8], info: &StreamInfo)
+                    -> Vec<i32> {
+  let mut child = Command::new(command)
+                    .args(&["--decode", "--stdout", "--silent",
+                            "--force-raw-format", "--endian=little",
+                            "--sign=signed", "-"])
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .spawn()
+                    .unwrap();
+
+  // Written from another thread so a full stdout can't block the input.
+  let mut stdin = child.stdin.take().unwrap();
+  let input     = bytes.to_vec();
+  let writer    = thread::spawn(move || stdin.write_all(&input));
+
+  let mut output = Vec::new();
+
+  child.stdout.take().unwrap().read_to_end(&mut output).unwrap();
+
+  writer.join().unwrap().unwrap();
+
+  assert!(child.wait().unwrap().success(), "libFLAC failed to decode");
+
+  let width = (usize::from(info.bits_per_sample) + 7) / 8;
+  let shift = 32 - width * 8;
+
+  output.chunks(width).map(|bytes| {
+    let sample = bytes.iter().rev().fold(0u32, |sample, &byte| {
+      (sample << 8) | byte as u32
+    });
+
+    // Sign extend from the width of the sample.
+    ((sample << shift) as i32) >> shift
+  }).collect()
+}
+
+// Compares samples one by one, pointing out the first that differs.
+fn compare(name: &str, channels: usize, samples: &[i32], expected: &[i32]) {
+  let mismatch = samples.iter().zip(expected).position(|(a, b)| a != b);
+
+  if let Some(index) = mismatch {
+    panic!("{}: sample {} of channel {} is {}, libFLAC has {}", name,
+           index / channels, index % channels, samples[index],
+           expected[index]);
+  }
+
+  assert_eq!(samples.len(), expected.len(),
+             "{}: different number of samples", name);
+}
+
+fn read_file(filename: &str) -> Vec<u8> {
+  let mut file  = File::open(filename).unwrap();
+  let mut bytes = Vec::new();
+
+  file.read_to_end(&mut bytes).unwrap();
+
+  bytes
+}
+
+#[test]
+fn test_decode() {
+  let command = match reference() {
+    Some(command) => command,
+    None          => return,
+  };
+
+  for filename in &FIXTURES {
+    let bytes      = read_file(filename);
+    let mut stream = Stream::<ByteStream>::from_buffer(&bytes).unwrap();
+    let info       = stream.info();
+    let samples    = stream.iter::<i32>().collect::<Vec<_>>();
+    let expected   = reference_decode(&command, &bytes, &info);
+
+    compare(filename, info.channels as usize, &samples, &expected);
+  }
+}
+
+#[test]
+fn test_encode() {
+  let command = match reference() {
+    Some(command) => command,
+    None          => return,
+  };
+
+  let stereo_modes = [
+    StereoMode::Independent,
+    StereoMode::Estimate,
+    StereoMode::Exhaustive,
+  ];
+
+  for filename in &FIXTURES {
+    let (info, samples) = flac::read(filename).unwrap();
+
+    for &block_size in &[192, 1152, 4096] {
+      for &stereo_mode in &stereo_modes {
+        let options = EncoderOptions {
+          block_size: block_size,
+          stereo_mode: stereo_mode,
+          ..Default::default()
+        };
+
+        let output      = Cursor::new(Vec::new());
+        let mut encoder = Encoder::new(output, info, options).unwrap();
+
+        encoder.write(&samples).unwrap();
+
+        let bytes    = encoder.finish().unwrap().into_inner();
+        let expected = reference_decode(&command, &bytes, &info);
+        let name     = format!("{} ({}, {:?})", filename, block_size,
+                               stereo_mode);
+
+        compare(&name, info.channels as usize, &samples, &expected);
+      }
+    }
+  }
+}
@@ -0,0 +1,124 @@
+// Round trip tests for the encoder, with the `md5` feature enabled.
+//
+// Every signal gets encoded and decoded back, and the MD5 signature of the
+// decoded samples has to match the one the encoder wrote into `StreamInfo`,
+// which in turn has to match one calculated from the input separately. The
+// signals cover each channel count, a spread of bits per sample, and every
+// stereo mode, along with streams that end on a partial frame.
+
+#![cfg(feature = "md5")]
+
+extern crate crypto;
+extern crate flac;
+
+use crypto::digest::Digest;
+use crypto::md5::Md5;
+use flac::{
+  BitsPerSample, ByteStream, Encoder, EncoderOptions, SampleRate, StereoMode,
+  Stream,
+};
+use flac::encoder::check_round_trip;
+use flac::metadata::StreamInfo;
+
+fn stream_info(channels: u8, bits_per_sample: u8) -> StreamInfo {
+  let mut info = StreamInfo::default();
+
+  info.sample_rate     = SampleRate::new(44100).unwrap();
+  info.channels        = channels;
+  info.bits_per_sample = BitsPerSample::new(bits_per_sample).unwrap();
+
+  info
+}
+
+// Ramps and noise mixed together, using as much of the bits per sample as
+// there is.
+fn signal(channels: usize, bits_per_sample: u8, length: usize) -> Vec<i32> {
+  let max        = (1i64 << (bits_per_sample - 1)) - 1;
+  let mut random = 1u32;
+
+  (0..(length * channels)).map(|i| {
+    random = random.wrapping_mul(1664525).wrapping_add(1013904223);
+
+    let ramp  = ((i / channels) as i64 * 97 % (max + 1)) - max / 2;
+    let noise = (random >> 16) as i64 % (max / 8 + 1);
+
+    (ramp + noise).max(-max - 1).min(max) as i32
+  }).collect()
+}
+
+// Signature of the samples as they'd be laid out by a decoder, calculated
+// without going through the crate.
+fn md5_of(samples: &[i32], bits_per_sample: u8) -> [u8; 16] {
+  let width       = (bits_per_sample as usize + 7) / 8;
+  let mut md5     = Md5::new();
+  let mut md5_sum = [0; 16];
+
+  for &sample in samples {
+    let bytes = [sample as u8, (sample >> 8) as u8, (sample >> 16) as u8,
+                 (sample >> 24) as u8];
+
+    md5.input(&bytes[0..width]);
+  }
+
+  md5.result(&mut md5_sum);
+
+  md5_sum
+}
+
+#[test]
+fn test_md5_round_trip() {
+  let depths = [4, 8, 12, 16, 20, 24, 32];
+
+  for channels in 1..9 {
+    for &bits_per_sample in &depths {
+      let info    = stream_info(channels, bits_per_sample);
+      let samples = signal(channels as usize, bits_per_sample, 5000);
+
+      let mut encoder = Encoder::in_memory(info, Default::default()).unwrap();
+
+      encoder.write(&samples).unwrap();
+
+      let bytes      = encoder.finalize().unwrap();
+      let mut stream = Stream::<ByteStream>::from_buffer(&bytes).unwrap();
+
+      assert_eq!(stream.info().md5_sum, md5_of(&samples, bits_per_sample),
+                 "{} channels at {} bits", channels, bits_per_sample);
+      assert_eq!(stream.verify(), Ok(true),
+                 "{} channels at {} bits", channels, bits_per_sample);
+    }
+  }
+}
+
+#[test]
+fn test_check_round_trip() {
+  let modes = [
+    StereoMode::Independent,
+    StereoMode::Estimate,
+    StereoMode::Exhaustive,
+  ];
+
+  for &stereo_mode in &modes {
+    for &block_size in &[16, 192, 4096] {
+      for &length in &[0, 1, 4095, 4097] {
+        let options = EncoderOptions {
+          block_size: block_size,
+          stereo_mode: stereo_mode,
+          ..Default::default()
+        };
+
+        let samples = signal(2, 16, length);
+
+        assert!(check_round_trip(stream_info(2, 16), options, &samples)
+                  .unwrap(),
+                "{:?} with blocks of {} over {} samples", stereo_mode,
+                block_size, length);
+      }
+    }
+  }
+
+  // Samples the stream can't hold never make it to decoding.
+  let samples = [0, 1 << 16];
+
+  assert!(check_round_trip(stream_info(1, 16), Default::default(),
+                           &samples).is_err());
+}
@@ -0,0 +1,173 @@
+// Tests on streams past 4GB, only built with the `expensive-tests` feature.
+//
+// Offsets within a stream are kept as `u64`, no matter the size of `usize`,
+// so a file larger than the address space of a 32-bit target still decodes
+// while only a window of it is ever held in memory. The stream here is made
+// up on the fly by wedging padding blocks between the metadata and the
+// frames of a fixture, which pushes every frame past 4GB without needing
+// the disk space for it. Reading through the padding takes a while, so
+// running these with `--release` is recommended.
+
+#![cfg(feature = "expensive-tests")]
+
+extern crate flac;
+
+use flac::{ByteStream, ReadStream, Stream};
+use flac::metadata::Data;
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+
+// Largest length a metadata block is able to store.
+const PADDING_LENGTH: u64 = 0xffffff;
+
+// Enough padding blocks to push the first frame past 4GB.
+const PADDING_BLOCKS: u64 = 257;
+
+// A FLAC file with padding blocks placed after its metadata, where only the
+// original file is held in memory.
+struct PaddedSource {
+  header: Vec<u8>,
+  frames: Vec<u8>,
+  blocks: u64,
+  position: u64,
+}
+
+impl PaddedSource {
+  fn new(bytes: &[u8], audio_offset: usize, blocks: u64) -> Self {
+    let mut header = bytes[0..audio_offset].to_vec();
+    let mut offset = 4;
+
+    // The last metadata block of the file no longer is.
+    while offset < header.len() {
+      let length = ((header[offset + 1] as usize) << 16) +
+                   ((header[offset + 2] as usize) << 8) +
+                   (header[offset + 3] as usize);
+
+      header[offset] &= 0b01111111;
+
+      offset += 4 + length;
+    }
+
+    PaddedSource {
+      header: header,
+      frames: bytes[audio_offset..].to_vec(),
+      blocks: blocks,
+      position: 0,
+    }
+  }
+
+  fn padding_len(&self) -> u64 {
+    self.blocks * (4 + PADDING_LENGTH)
+  }
+
+  fn len(&self) -> u64 {
+    self.header.len() as u64 + self.padding_len() + self.frames.len() as u64
+  }
+}
+
+impl Read for PaddedSource {
+  fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+    let header_end  = self.header.len() as u64;
+    let padding_end = header_end + self.padding_len();
+    let position    = self.position;
+
+    let length = if position < header_end {
+      try!((&self.header[(position as usize)..]).read(buffer))
+    } else if position < padding_end {
+      let index  = (position - header_end) / (4 + PADDING_LENGTH);
+      let offset = (position - header_end) % (4 + PADDING_LENGTH);
+
+      if offset < 4 {
+        let is_last = index + 1 == self.blocks;
+        let block   = [if is_last { 0x81 } else { 0x01 }, 0xff, 0xff, 0xff];
+
+        try!((&block[(offset as usize)..]).read(buffer))
+      } else {
+        let left   = 4 + PADDING_LENGTH - offset;
+        let length = if left < buffer.len() as u64 {
+          left as usize
+        } else {
+          buffer.len()
+        };
+
+        for byte in &mut buffer[0..length] {
+          *byte = 0;
+        }
+
+        length
+      }
+    } else if position - padding_end < self.frames.len() as u64 {
+      let start = (position - padding_end) as usize;
+
+      try!((&self.frames[start..]).read(buffer))
+    } else {
+      0
+    };
+
+    self.position += length as u64;
+
+    Ok(length)
+  }
+}
+
+impl Seek for PaddedSource {
+  fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+    self.position = match position {
+      SeekFrom::Start(offset)   => offset,
+      SeekFrom::Current(offset) => (self.position as i64 + offset) as u64,
+      SeekFrom::End(offset)     => (self.len() as i64 + offset) as u64,
+    };
+
+    Ok(self.position)
+  }
+}
+
+#[test]
+fn test_past_4gb() {
+  let mut file  = File::open("tests/assets/input-SCPAP.flac").unwrap();
+  let mut bytes = Vec::new();
+
+  file.read_to_end(&mut bytes).unwrap();
+
+  let mut local    = Stream::<ByteStream>::from_buffer(&bytes).unwrap();
+  let samples      = local.iter::<i16>().collect::<Vec<_>>();
+  let channels     = local.info().channels as usize;
+  let audio_offset = local.audio_offset();
+
+  let source   = PaddedSource::new(&bytes, audio_offset as usize,
+                                   PADDING_BLOCKS);
+  let shift    = source.padding_len();
+  let producer = ReadStream::seekable(source);
+
+  let mut stream = Stream::<ReadStream<PaddedSource>>::from_stream_producer(
+                     producer, Default::default()).unwrap();
+
+  assert!(stream.audio_offset() > u32::max_value() as u64);
+  assert_eq!(stream.audio_offset(), audio_offset + shift);
+  assert!(stream.iter::<i16>().eq(samples.iter().cloned()));
+  assert_eq!(stream.decode_progress().byte_offset,
+             bytes.len() as u64 + shift);
+
+  // Seek points are relative to the first frame, so they land past 4GB.
+  let sample_number = stream.metadata().iter().filter_map(|block| {
+    if let Data::SeekTable(ref seek_points) = block.data {
+      seek_points.iter().map(|point| point.sample_number)
+                        .filter(|&number| number != 0xffffffffffffffff)
+                        .max()
+    } else {
+      None
+    }
+  }).next().unwrap();
+
+  assert_eq!(stream.seek(sample_number + 1), Ok(sample_number));
+  assert!(stream.decode_progress().byte_offset > u32::max_value() as u64);
+
+  let start = sample_number as usize * channels;
+
+  assert!(stream.iter::<i16>().eq(samples[start..].iter().cloned()));
+
+  // Going back to the first frame skips the padding entirely.
+  assert!(stream.rewind().is_ok());
+  assert!(stream.iter::<i16>().eq(samples.iter().cloned()));
+}
@@ -1,8 +1,15 @@
 extern crate flac;
 
-use flac::{metadata, ErrorKind};
+use flac::metadata;
+#[cfg(any(feature = "vorbis-comment", feature = "cue-sheet",
+          feature = "picture"))]
+use flac::ErrorKind;
+#[cfg(feature = "picture")]
 use flac::metadata::{Picture, PictureType};
 
+use std::fs::File;
+
+#[cfg(feature = "picture")]
 fn compare_all_but_data(picture: &Picture, other_picture: &Picture) -> bool {
   (picture.picture_type == other_picture.picture_type) &&
     (picture.mime_type == other_picture.mime_type) &&
@@ -20,6 +27,7 @@ fn test_get_stream_info() {
   assert!(result.is_ok());
 }
 
+#[cfg(feature = "vorbis-comment")]
 #[test]
 fn test_get_vorbis_comment() {
   let tags    = metadata::get_vorbis_comment("tests/assets/input-SVAUP.flac");
@@ -29,6 +37,24 @@ fn test_get_vorbis_comment() {
   assert_eq!(no_tags.unwrap_err(), ErrorKind::NotFound);
 }
 
+#[cfg(feature = "vorbis-comment")]
+#[test]
+fn test_tags() {
+  let tags    = flac::tags("tests/assets/input-SVAUP.flac").unwrap();
+  let no_tags = flac::tags("tests/assets/input-SCPAP.flac").unwrap();
+
+  let vorbis_comment =
+    metadata::get_vorbis_comment("tests/assets/input-SVAUP.flac").unwrap();
+
+  assert_eq!(tags, vorbis_comment.comments);
+  assert!(no_tags.is_empty());
+
+  let file = File::open("tests/assets/input-SVAUP.flac").unwrap();
+
+  assert_eq!(flac::tags_from(file).unwrap(), tags);
+}
+
+#[cfg(feature = "cue-sheet")]
 #[test]
 fn test_get_cue_sheet() {
   let cue_sheet    = metadata::get_cue_sheet("tests/assets/input-SCPAP.flac");
@@ -38,6 +64,7 @@ fn test_get_cue_sheet() {
   assert_eq!(no_cue_sheet.unwrap_err(), ErrorKind::NotFound);
 }
 
+#[cfg(feature = "picture")]
 #[test]
 fn test_get_picture() {
   let picture_file = "tests/assets/input-pictures.flac";
@@ -57,7 +84,7 @@ fn test_get_picture() {
     height: 14,
     depth: 24,
     colors: 128,
-    data: vec![],
+    data: metadata::Payload::new(),
   };
 
   let one_option = metadata::get_picture(picture_file,
@@ -71,7 +98,7 @@ fn test_get_picture() {
     height: 32,
     depth: 32,
     colors: 0,
-    data: vec![],
+    data: metadata::Payload::new(),
   };
 
   let no_option = metadata::get_picture(picture_file, None, None, None, None,
@@ -84,7 +111,7 @@ fn test_get_picture() {
     height: 47,
     depth: 24,
     colors: 23,
-    data: vec![],
+    data: metadata::Payload::new(),
   };
   let no_picture = metadata::get_picture("tests/assets/input-SVAUP.flac",
                                          None, None, None, None, None, None,
@@ -98,3 +125,410 @@ fn test_get_picture() {
           "No constraint option");
   assert_eq!(no_picture.unwrap_err(), ErrorKind::NotFound);
 }
+
+#[test]
+fn test_strip() {
+  use flac::metadata::Type;
+
+  use std::env;
+  use std::fs;
+
+  let filename = "tests/assets/input-SCPAP.flac";
+  let path     = env::temp_dir().join("flac-test-strip.flac");
+  let stripped = path.to_str().unwrap();
+
+  fs::copy(filename, &path).unwrap();
+
+  assert!(metadata::strip(stripped, &[Type::SeekTable]).is_ok());
+
+  {
+    let stream = flac::StreamReader::<File>::from_file(stripped).unwrap();
+    let blocks = stream.metadata();
+
+    assert_eq!(blocks.len(), 1);
+    assert!(blocks[0].is_seek_table() && blocks[0].is_last());
+  }
+
+  assert!(fs::metadata(stripped).unwrap().len() <
+          fs::metadata(filename).unwrap().len());
+
+  let (info, samples) = flac::read(filename).unwrap();
+
+  assert_eq!(flac::read(stripped).unwrap(), (info, samples.clone()));
+
+  fs::remove_file(&path).unwrap();
+
+  let mut output = Vec::new();
+
+  metadata::strip_stream(File::open(filename).unwrap(), &mut output, &[])
+    .unwrap();
+
+  assert_eq!(flac::read_from(&output[..]).unwrap(), (info, samples));
+
+  assert!(metadata::strip("non-existent/file.flac", &[]).is_err());
+}
+
+#[test]
+#[cfg(feature = "md5")]
+fn test_recompute_stream_info() {
+  use std::env;
+  use std::fs::{self, OpenOptions};
+  use std::io::{Seek, SeekFrom, Write};
+
+  let filename = "tests/assets/input-SCPAP.flac";
+  let path     = env::temp_dir().join("flac-test-recompute.flac");
+  let fixed    = path.to_str().unwrap();
+
+  fs::copy(filename, &path).unwrap();
+
+  // Clears the block sizes, frame sizes, total samples, and MD5 signature,
+  // leaving the sample rate, channels, and bits per sample.
+  {
+    let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+
+    file.seek(SeekFrom::Start(8)).unwrap();
+    file.write_all(&[0; 10]).unwrap();
+    file.seek(SeekFrom::Start(22)).unwrap();
+    file.write_all(&[0; 20]).unwrap();
+  }
+
+  let info = metadata::get_stream_info(filename).unwrap();
+
+  assert!(metadata::get_stream_info(fixed).unwrap() != info);
+  assert_eq!(metadata::recompute_stream_info(fixed).unwrap(), info);
+  assert_eq!(metadata::get_stream_info(fixed).unwrap(), info);
+  assert_eq!(fs::metadata(fixed).unwrap().len(),
+             fs::metadata(filename).unwrap().len());
+
+  fs::remove_file(&path).unwrap();
+
+  assert!(metadata::recompute_stream_info("non-existent/file.flac")
+            .is_err());
+}
+
+#[test]
+fn test_recompute_frame_sizes() {
+  use std::env;
+  use std::fs::{self, OpenOptions};
+  use std::io::{Seek, SeekFrom, Write};
+
+  let filename = "tests/assets/input-SCPAP.flac";
+  let path     = env::temp_dir().join("flac-test-frame-sizes.flac");
+  let fixed    = path.to_str().unwrap();
+
+  fs::copy(filename, &path).unwrap();
+
+  // Swaps in frame sizes that are too small for any frame to fit.
+  {
+    let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+
+    file.seek(SeekFrom::Start(12)).unwrap();
+    file.write_all(&[0, 0, 1, 0, 0, 2]).unwrap();
+  }
+
+  let info  = metadata::get_stream_info(filename).unwrap();
+  let wrong = metadata::get_stream_info(fixed).unwrap();
+  let hint  = wrong.frame_size_hint();
+
+  assert!(info.has_sane_frame_sizes());
+  assert!(!wrong.has_sane_frame_sizes());
+  assert!(hint.0 > 2 && hint.0 == hint.1);
+
+  {
+    let mut stream = flac::StreamReader::<File>::from_file(fixed).unwrap();
+    let samples    = stream.iter::<i32>().count() as u64;
+
+    assert_eq!(samples, info.total_samples * info.channels as u64);
+    assert!(stream.stats().frames() > 0);
+    assert_eq!(stream.stats().frame_size_mismatches(),
+               stream.stats().frames());
+  }
+
+  assert_eq!(metadata::recompute_frame_sizes(fixed).unwrap(), info);
+  assert_eq!(metadata::get_stream_info(fixed).unwrap(), info);
+
+  fs::remove_file(&path).unwrap();
+}
+
+fn crc8(bytes: &[u8]) -> u8 {
+  bytes.iter().fold(0, |crc, &byte| {
+    (0..8).fold(crc ^ byte, |crc, _| {
+      if crc & 0x80 == 0 { crc << 1 } else { (crc << 1) ^ 0x07 }
+    })
+  })
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+  bytes.iter().fold(0, |crc, &byte| {
+    (0..8).fold(crc ^ ((byte as u16) << 8), |crc, _| {
+      if crc & 0x8000 == 0 { crc << 1 } else { (crc << 1) ^ 0x8005 }
+    })
+  })
+}
+
+#[test]
+fn test_recompute_large_block_size() {
+  use std::env;
+  use std::fs;
+
+  let path     = env::temp_dir().join("flac-test-large-block-size.flac");
+  let filename = path.to_str().unwrap();
+
+  // Mono stream at 44.1kHz and 16 bits, with a single silent frame of
+  // 65536 samples, one more than `StreamInfo` can hold as a block size.
+  let mut bytes = b"fLaC\x80\x00\x00\x22\xff\xff\xff\xff".to_vec();
+
+  bytes.extend_from_slice(&[0; 6]);
+  bytes.extend_from_slice(&[0x0a, 0xc4, 0x40, 0xf0, 0x00, 0x01, 0x00, 0x00]);
+  bytes.extend_from_slice(&[0; 16]);
+
+  let start = bytes.len();
+
+  bytes.extend_from_slice(&[0xff, 0xf8, 0x70, 0x00, 0x00, 0xff, 0xff]);
+
+  let crc = crc8(&bytes[start..]);
+
+  bytes.push(crc);
+  bytes.extend_from_slice(&[0x00, 0x00, 0x00]);
+
+  let crc = crc16(&bytes[start..]);
+
+  bytes.extend_from_slice(&[(crc >> 8) as u8, crc as u8]);
+
+  fs::write(&path, &bytes).unwrap();
+
+  assert_eq!(metadata::recompute_frame_sizes(filename).err(),
+             Some(flac::ErrorKind::InvalidBlockSize));
+  assert_eq!(fs::read(&path).unwrap(), bytes);
+
+  fs::remove_file(&path).unwrap();
+}
+
+#[test]
+#[cfg(feature = "picture")]
+fn test_set_and_remove_picture() {
+  use flac::metadata::PictureSelector;
+
+  use std::env;
+  use std::fs;
+
+  let filename = "tests/assets/input-pictures.flac";
+  let path     = env::temp_dir().join("flac-test-picture.flac");
+  let edited   = path.to_str().unwrap();
+  let length   = fs::metadata(filename).unwrap().len();
+  let samples  = flac::read(filename).unwrap().1;
+
+  let pictures = |filename: &str| {
+    let stream = flac::StreamReader::<File>::from_file(filename).unwrap();
+
+    stream.metadata().iter().filter_map(|block| {
+      block.as_picture().map(|picture| picture.picture_type)
+    }).collect::<Vec<_>>()
+  };
+
+  let picture = |data: Vec<u8>| {
+    Picture {
+      picture_type: PictureType::FrontCover,
+      mime_type: "image/png".to_owned(),
+      description: "cover".to_owned(),
+      width: 1,
+      height: 1,
+      depth: 24,
+      colors: 0,
+      data: metadata::Payload::from(data),
+    }
+  };
+
+  fs::copy(filename, &path).unwrap();
+
+  // Fits within the padding, so the file stays the same size.
+  let selector = PictureSelector::Type(PictureType::FrontCover);
+
+  metadata::set_picture(edited, selector, picture(vec![1; 64])).unwrap();
+
+  assert_eq!(fs::metadata(edited).unwrap().len(), length);
+  assert_eq!(pictures(edited).len(), 14);
+  assert_eq!(metadata::get_picture(edited, Some(PictureType::FrontCover),
+                                   None, None, None, None, None, None),
+             Ok(picture(vec![1; 64])));
+
+  metadata::remove_picture(edited, PictureSelector::Type(PictureType::Other))
+    .unwrap();
+
+  assert_eq!(fs::metadata(edited).unwrap().len(), length);
+  assert_eq!(pictures(edited), vec![PictureType::FrontCover,
+                                    PictureType::FileIconStandard,
+                                    PictureType::BackCover,
+                                    PictureType::FileIcon]);
+
+  // Too large for the padding, so the whole file gets rewritten.
+  let selector = PictureSelector::Index(3);
+
+  metadata::set_picture(edited, selector, picture(vec![2; 32768])).unwrap();
+
+  assert!(fs::metadata(edited).unwrap().len() > length);
+  assert_eq!(pictures(edited), vec![PictureType::FrontCover,
+                                    PictureType::FileIconStandard,
+                                    PictureType::BackCover,
+                                    PictureType::FrontCover]);
+  assert_eq!(flac::read(edited).unwrap().1, samples);
+
+  let selector = PictureSelector::Index(4);
+
+  assert_eq!(metadata::set_picture(edited, selector, picture(vec![])),
+             Err(ErrorKind::NotFound));
+  assert_eq!(metadata::remove_picture(edited, selector),
+             Err(ErrorKind::NotFound));
+
+  fs::remove_file(&path).unwrap();
+}
+
+// Xorshift generator, seeded so the randomized tests repeat across runs.
+struct Random(u64);
+
+impl Random {
+  fn next(&mut self) -> u64 {
+    self.0 ^= self.0 << 13;
+    self.0 ^= self.0 >> 7;
+    self.0 ^= self.0 << 17;
+
+    self.0
+  }
+
+  fn below(&mut self, bound: u64) -> u64 {
+    self.next() % bound
+  }
+
+  fn bytes(&mut self, length: usize) -> Vec<u8> {
+    (0..length).map(|_| self.next() as u8).collect()
+  }
+
+  // Printable ASCII, except for "=" when `is_name` is set.
+  fn text(&mut self, length: usize, is_name: bool) -> String {
+    (0..length).map(|_| {
+      let c = (b' ' + self.below(95) as u8) as char;
+
+      if is_name && c == '=' { 'A' } else { c }
+    }).collect()
+  }
+}
+
+fn random_block(random: &mut Random) -> metadata::Data {
+  use flac::metadata::{Application, Data, SeekPoint};
+
+  match random.below(6) {
+    0 => Data::Padding(random.below(4096) as u32),
+    1 => {
+      let id   = random.text(4, false);
+      let size = random.below(512) as usize;
+
+      Data::Application(Application::new().with_id(&id)
+                                          .with_data(random.bytes(size)))
+    }
+    2 => {
+      let count  = random.below(32);
+      let points = (0..count).map(|_| {
+        SeekPoint::new().with_sample_number(random.next())
+                        .with_stream_offset(random.next())
+                        .with_frame_samples(random.next() as u16)
+      }).collect();
+
+      Data::SeekTable(points)
+    }
+    #[cfg(feature = "vorbis-comment")]
+    3 => {
+      use flac::metadata::VorbisComment;
+
+      let vendor_length = random.below(64) as usize;
+      let mut comment   = VorbisComment::new()
+                            .with_vendor_string(&random.text(vendor_length,
+                                                             false));
+
+      for _ in 0..random.below(16) {
+        let name_length  = 1 + random.below(16) as usize;
+        let value_length = random.below(64) as usize;
+        let name         = random.text(name_length, true);
+        let value        = random.text(value_length, false);
+
+        comment = comment.with_comment(&name, &value);
+      }
+
+      Data::VorbisComment(comment)
+    }
+    #[cfg(feature = "picture")]
+    4 => {
+      let mime_length = random.below(32) as usize;
+      let text_length = random.below(64) as usize;
+      let data_length = random.below(1024) as usize;
+
+      Data::Picture(Picture::new()
+                      .with_picture_type(PictureType::FrontCover)
+                      .with_mime_type(&random.text(mime_length, false))
+                      .with_description(&random.text(text_length, false))
+                      .with_width(random.next() as u32)
+                      .with_height(random.next() as u32)
+                      .with_depth(random.next() as u32)
+                      .with_colors(random.next() as u32)
+                      .with_data(random.bytes(data_length)))
+    }
+    _ => {
+      let size       = random.below(256) as usize;
+      let block_type = 7 + random.below(120) as u8;
+
+      Data::Unknown(block_type, random.bytes(size).into())
+    }
+  }
+}
+
+#[test]
+fn test_serialize_random_blocks() {
+  use flac::{BitsPerSample, SampleRate};
+  use flac::metadata::{Data, Metadata, StreamInfo};
+
+  let mut random = Random(0x2545f4914f6cdd1d);
+
+  for _ in 0..200 {
+    let min_block_size = 16 + random.below(4096) as u16;
+    let stream_info    = StreamInfo {
+      min_block_size: min_block_size,
+      max_block_size: min_block_size + random.below(4096) as u16,
+      min_frame_size: random.below(1 << 24) as u32,
+      max_frame_size: random.below(1 << 24) as u32,
+      sample_rate: SampleRate::new(1 + random.below(655350) as u32).unwrap(),
+      channels: 1 + random.below(8) as u8,
+      bits_per_sample: BitsPerSample::new(4 + random.below(29) as u8)
+                         .unwrap(),
+      total_samples: random.below(1 << 36),
+      md5_sum: [random.next() as u8; 16],
+    };
+
+    let mut blocks = vec![Metadata::new(false, 34,
+                                        Data::StreamInfo(stream_info))];
+
+    for _ in 0..(1 + random.below(6)) {
+      let data = random_block(&mut random);
+
+      blocks.push(Metadata::new(false, 0, data));
+    }
+
+    let bytes  = metadata::serialize_blocks(&blocks).unwrap();
+    let length = blocks.iter().fold(0, |sum, block| {
+      sum + block.bytes_len()
+    });
+
+    assert_eq!(bytes.len(), length);
+
+    let mut input = &bytes[..];
+
+    for (index, block) in blocks.iter().enumerate() {
+      let (rest, parsed) = metadata::metadata_parser(input).unwrap();
+
+      assert_eq!(parsed.is_last(), index == blocks.len() - 1);
+      assert_eq!(parsed.data, block.data);
+
+      input = rest;
+    }
+
+    assert!(input.is_empty());
+  }
+}
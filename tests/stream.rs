@@ -1,11 +1,20 @@
 extern crate flac;
 extern crate crypto;
+#[cfg(feature = "async")]
+extern crate futures;
 
 use crypto::digest::Digest;
 use crypto::md5::Md5;
-use flac::{Stream, ReadStream};
+use flac::{Stream, StreamOptions, ByteStream, ReadStream, ErrorKind};
+use flac::{BitsPerSample, CancelHandle, ChannelOrder, SampleRate};
+use flac::{Encoder, EncoderOptions, SeekPointInterval};
+use flac::metadata::{Metadata, Data, StreamInfo};
+use flac::stream::{Discontinuity, PushDecoder};
 use std::cmp;
 use std::fs::File;
+use std::io::{Cursor, Read};
+use std::sync::{Arc, Mutex};
+use std::thread;
 
 fn to_bytes(value: i32, buffer: &mut [u8]) {
   buffer[0] = value as u8;
@@ -24,6 +33,7 @@ fn get_offset(sample_size: usize) -> usize {
 fn test_decoded_md5_sum() {
   let filenames = [
     "tests/assets/input-pictures.flac",
+    "tests/assets/input-rice2-escape.flac",
     "tests/assets/input-SCPAP.flac",
     "tests/assets/input-SVAUP.flac",
   ];
@@ -37,7 +47,7 @@ fn test_decoded_md5_sum() {
       Stream::<ReadStream<File>>::from_file(filenames[0]).unwrap();
 
     let info   = stream.info();
-    let offset = get_offset(info.bits_per_sample as usize);
+    let offset = get_offset(usize::from(info.bits_per_sample));
 
     for sample in stream.iter::<i32>() {
       to_bytes(sample as i32, &mut buffer);
@@ -57,7 +67,7 @@ fn test_decoded_md5_sum() {
       Stream::<ReadStream<File>>::from_file(filenames[1]).unwrap();
 
     let info   = stream.info();
-    let offset = get_offset(info.bits_per_sample as usize);
+    let offset = get_offset(usize::from(info.bits_per_sample));
 
     for sample in stream.iter::<i32>() {
       to_bytes(sample as i32, &mut buffer);
@@ -77,7 +87,7 @@ fn test_decoded_md5_sum() {
       Stream::<ReadStream<File>>::from_file(filenames[2]).unwrap();
 
     let info   = stream.info();
-    let offset = get_offset(info.bits_per_sample as usize);
+    let offset = get_offset(usize::from(info.bits_per_sample));
 
     for sample in stream.iter::<i32>() {
       to_bytes(sample as i32, &mut buffer);
@@ -90,3 +100,2670 @@ fn test_decoded_md5_sum() {
     assert_eq!(md5_sum, info.md5_sum);
   }
 }
+
+#[test]
+fn test_from_buffer_raw_metadata() {
+  let mut file   = File::open("tests/assets/input-pictures.flac").unwrap();
+  let mut buffer = Vec::new();
+
+  file.read_to_end(&mut buffer).unwrap();
+
+  let stream = Stream::<ByteStream>::from_buffer(&buffer).unwrap();
+
+  for block in stream.metadata() {
+    let raw = block.raw().unwrap();
+
+    assert_eq!((raw[0] >> 7) == 1, block.is_last());
+
+    if block.is_picture() {
+      let mut bytes = Vec::with_capacity(block.bytes_len());
+
+      assert!(block.to_bytes(&mut bytes).is_ok());
+      assert_eq!(&bytes[..], raw);
+    }
+  }
+
+  // The blocks follow one another up to the first audio frame.
+  let length = stream.metadata().iter().fold(4 + 4 + 34, |length, block| {
+    length + block.raw().unwrap().len()
+  });
+
+  assert_eq!(length as u64, stream.audio_offset());
+
+  // `StreamInfo` declaring four bytes past the 34 it uses, which get
+  // skipped over along with it.
+  let blocks = stream.metadata().iter().map(|block| block.raw().unwrap())
+                     .collect::<Vec<_>>();
+  let mut long = buffer.clone();
+
+  long[7] = 38;
+  long.splice(42..42, vec![0xff; 4]);
+
+  let stream = Stream::<ByteStream>::from_buffer(&long).unwrap();
+
+  assert_eq!(stream.audio_offset(), length as u64 + 4);
+  assert!(stream.metadata().iter().map(|block| block.raw().unwrap())
+                .eq(blocks.into_iter()));
+}
+
+#[test]
+fn test_large_block_size_options() {
+  let stream_info = StreamInfo {
+    min_block_size: 8192,
+    max_block_size: 8192,
+    min_frame_size: 0,
+    max_frame_size: 0,
+    sample_rate: SampleRate::new(44100).unwrap(),
+    channels: 2,
+    bits_per_sample: BitsPerSample::new(16).unwrap(),
+    total_samples: 0,
+    md5_sum: [0; 16],
+  };
+
+  let block      = Metadata::new(true, 34, Data::StreamInfo(stream_info));
+  let mut buffer = b"fLaC".to_vec();
+
+  assert!(block.to_bytes(&mut buffer).is_ok());
+
+  let options = StreamOptions {
+    allow_large_block_size: true,
+    ..Default::default()
+  };
+
+  let default_result = Stream::<ByteStream>::from_buffer(&buffer);
+  let allowed_result = Stream::<ByteStream>::from_buffer_with_options(
+                         &buffer, options);
+
+  assert_eq!(default_result.err(), Some(ErrorKind::Unsupported));
+  assert_eq!(allowed_result.ok().map(|stream| stream.info()),
+             Some(stream_info));
+}
+
+#[test]
+fn test_seek() {
+  let filename = "tests/assets/input-SCPAP.flac";
+
+  let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+  let channels   = stream.info().channels as usize;
+  let samples    = stream.iter::<i16>().collect::<Vec<_>>();
+
+  let sample_numbers = stream.metadata().iter().filter_map(|block| {
+    if let Data::SeekTable(ref seek_points) = block.data {
+      Some(seek_points.iter().map(|point| point.sample_number)
+                              .filter(|&number| number != 0xffffffffffffffff)
+                              .collect())
+    } else {
+      None
+    }
+  }).next().unwrap_or_else(Vec::new);
+
+  assert!(!sample_numbers.is_empty());
+
+  for sample_number in sample_numbers {
+    assert_eq!(stream.seek(sample_number + 1).unwrap(), sample_number);
+
+    let start = sample_number as usize * channels;
+
+    assert!(stream.iter::<i16>().eq(samples[start..].iter().cloned()));
+  }
+
+  assert_eq!(stream.seek(0).unwrap(), 0);
+  assert!(stream.iter::<i16>().eq(samples.iter().cloned()));
+}
+
+#[test]
+fn test_read() {
+  let filename = "tests/assets/input-SVAUP.flac";
+
+  let (info, samples) = flac::read(filename).unwrap();
+
+  let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+  let file       = File::open(filename).unwrap();
+
+  assert_eq!(flac::read_from(file).unwrap(), (info, samples.clone()));
+
+  assert_eq!(info, stream.info());
+  assert_eq!(samples.len() as u64, info.total_samples * info.channels as u64);
+  assert!(stream.iter::<i32>().eq(samples.clone().into_iter()));
+
+  // A total number of samples far past what the stream holds doesn't get
+  // allocated up front.
+  let mut buffer = Vec::new();
+
+  File::open(filename).unwrap().read_to_end(&mut buffer).unwrap();
+
+  buffer[21] |= 0x0f;
+
+  for byte in &mut buffer[22..26] {
+    *byte = 0xff;
+  }
+
+  let (forged, decoded) = flac::read_from(&buffer[..]).unwrap();
+
+  assert_eq!(forged.total_samples, 0xfffffffff);
+  assert_eq!(decoded, samples);
+
+  assert_eq!(flac::read("tests/assets/missing.flac").err(),
+             Some(ErrorKind::IO(std::io::ErrorKind::NotFound)));
+}
+
+#[test]
+fn test_rewind() {
+  let filename = "tests/assets/input-SVAUP.flac";
+
+  let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+  let samples    = stream.iter::<i16>().collect::<Vec<_>>();
+  let channels   = stream.info().channels as usize;
+  let total      = stream.info().total_samples as usize * channels;
+
+  assert!(stream.rewind().is_ok());
+  assert!(stream.iter::<i16>().eq(samples.iter().cloned()));
+  assert!(stream.rewind().is_ok());
+
+  let mut iter = stream.iter::<i16>();
+
+  assert_eq!(iter.by_ref().take(100).count(), 100);
+  assert!(iter.rewind().is_ok());
+  assert_eq!(iter.size_hint(), (0, Some(total)));
+  assert!(iter.eq(samples.iter().cloned()));
+}
+
+#[test]
+fn test_iter_approximate() {
+  let filename = "tests/assets/input-SVAUP.flac";
+
+  let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+  let samples    = stream.iter::<i16>().collect::<Vec<_>>();
+
+  assert!(stream.rewind().is_ok());
+
+  let approximation = stream.iter_approximate::<i16>().collect::<Vec<_>>();
+
+  assert_eq!(approximation.len(), samples.len());
+}
+
+#[test]
+fn test_encode() {
+  let filename = "tests/assets/input-SVAUP.flac";
+
+  let (info, samples) = flac::read(filename).unwrap();
+
+  let options = EncoderOptions {
+    block_size: 1152,
+    seek_point_interval: Some(SeekPointInterval::Samples(1000)),
+    ..Default::default()
+  };
+
+  let mut encoder = Encoder::new(Cursor::new(Vec::new()), info, options)
+                      .unwrap();
+
+  encoder.write(&samples).unwrap();
+
+  let bytes      = encoder.finish().unwrap().into_inner();
+  let mut stream = Stream::<ByteStream>::from_buffer(&bytes).unwrap();
+  let channels   = info.channels as usize;
+
+  assert_eq!(stream.info().total_samples, info.total_samples);
+  assert!(stream.iter::<i32>().eq(samples.iter().cloned()));
+
+  let sample_number = info.total_samples / 2;
+  let point         = stream.seek(sample_number).unwrap();
+  let start         = point as usize * channels;
+
+  assert!(point > 0 && point <= sample_number);
+  assert!(stream.iter::<i32>().eq(samples[start..].iter().cloned()));
+}
+
+// Encodes the samples with the given block size, returning the stream.
+fn encode_blocks(info: StreamInfo, samples: &[i32], block_size: u16)
+                 -> Vec<u8> {
+  let options = EncoderOptions {
+    block_size: block_size,
+    ..Default::default()
+  };
+
+  let mut encoder = Encoder::new(Cursor::new(Vec::new()), info, options)
+                      .unwrap();
+
+  encoder.write(samples).unwrap();
+
+  encoder.finish().unwrap().into_inner()
+}
+
+// Checks that every frame decodes to the expected samples, with the block
+// sizes and frame lengths matching up.
+fn check_blocks(bytes: &[u8], samples: &[i32], block_sizes: &[usize]) {
+  use flac::stream::frame_length;
+
+  let mut stream = Stream::<ByteStream>::from_buffer(bytes).unwrap();
+  let info       = stream.info();
+
+  assert!(stream.iter::<i32>().eq(samples.iter().cloned()));
+
+  stream.rewind().unwrap();
+
+  let sizes = stream.blocks::<i32>().map(|block| block.unwrap().len())
+                    .collect::<Vec<_>>();
+
+  assert_eq!(sizes, block_sizes);
+
+  stream.rewind().unwrap();
+
+  let ranges = stream.frame_ranges().collect::<Vec<_>>();
+
+  assert_eq!(ranges.len(), block_sizes.len());
+
+  for frame in ranges {
+    let start  = frame.range.start as usize;
+    let length = (frame.range.end - frame.range.start) as usize;
+
+    assert_eq!(frame_length(&bytes[start..], &info), Ok(length));
+  }
+}
+
+#[test]
+fn test_tiny_blocks() {
+  let (mut info, samples) = flac::read("tests/assets/input-SVAUP.flac")
+                              .unwrap();
+
+  let channels = info.channels as usize;
+  let length   = 16 * 7 + 1;
+
+  info.total_samples = length as u64;
+
+  let signals = [
+    samples[0..(length * channels)].to_vec(),
+    vec![0; length * channels],
+    (0..(length * channels)).map(|i| (i as i32 % 37) - 18).collect(),
+    (0..(length * channels)).map(|i| {
+      if i % 2 == 0 { 32767 } else { -32768 }
+    }).collect(),
+  ];
+
+  let mut block_sizes = vec![16; 7];
+
+  block_sizes.push(1);
+
+  for signal in &signals {
+    let bytes = encode_blocks(info, signal, 16);
+
+    check_blocks(&bytes, signal, &block_sizes);
+
+    let stream = Stream::<ByteStream>::from_buffer(&bytes).unwrap();
+    let info   = stream.info();
+
+    assert_eq!((info.min_block_size, info.max_block_size), (16, 16));
+  }
+}
+
+#[test]
+fn test_single_frame() {
+  let (mut info, samples) = flac::read("tests/assets/input-SVAUP.flac")
+                              .unwrap();
+
+  let channels = info.channels as usize;
+
+  for &length in &[1, 16, 100] {
+    let signal = &samples[0..(length * channels)];
+
+    info.total_samples = length as u64;
+
+    let bytes = encode_blocks(info, signal, 4096);
+
+    check_blocks(&bytes, signal, &[length]);
+
+    let mut decoder = PushDecoder::new::<i32>();
+    let mut decoded = Vec::new();
+
+    decoder.push(&bytes, |frame| decoded.extend_from_slice(frame)).unwrap();
+
+    assert_eq!(decoded, signal);
+  }
+}
+
+#[test]
+fn test_encode_random_frames() {
+  // Xorshift generator, seeded so every run encodes the same frames.
+  let mut state = 0x9e3779b97f4a7c15u64;
+  let mut next  = move || {
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+
+    state
+  };
+
+  for _ in 0..40 {
+    let channels        = 1 + (next() % 8) as usize;
+    let bits_per_sample = [8, 12, 16, 20, 24][(next() % 5) as usize];
+    let block_size      = 16 + (next() % 4593) as usize;
+    let length          = 1 + (next() % (block_size as u64 * 3)) as usize;
+    let shift           = 64 - bits_per_sample;
+
+    let mut info = StreamInfo::default();
+
+    info.sample_rate     = SampleRate::new(44100).unwrap();
+    info.channels        = channels as u8;
+    info.bits_per_sample = BitsPerSample::new(bits_per_sample as u8).unwrap();
+    info.total_samples   = length as u64;
+
+    // Full scale noise, quiet noise, or silence.
+    let samples = (0..(length * channels)).map(|_| {
+      match next() % 3 {
+        0 => ((next() << shift) as i64 >> shift) as i32,
+        1 => (next() % 33) as i32 - 16,
+        _ => 0,
+      }
+    }).collect::<Vec<_>>();
+
+    let mut block_sizes = vec![block_size; length / block_size];
+
+    if length % block_size != 0 {
+      block_sizes.push(length % block_size);
+    }
+
+    let bytes = encode_blocks(info, &samples, block_size as u16);
+
+    check_blocks(&bytes, &samples, &block_sizes);
+  }
+}
+
+#[test]
+fn test_check_continuity() {
+  let (info, samples) = flac::read("tests/assets/input-SVAUP.flac").unwrap();
+
+  let options = EncoderOptions {
+    block_size: 1152,
+    padding: 0,
+    seek_point_interval: Some(SeekPointInterval::Samples(1152)),
+    ..Default::default()
+  };
+
+  let mut encoder = Encoder::new(Cursor::new(Vec::new()), info, options)
+                      .unwrap();
+
+  encoder.write(&samples).unwrap();
+
+  let bytes = encoder.finish().unwrap().into_inner();
+
+  let (audio_offset, offsets) = {
+    let mut stream = Stream::<ByteStream>::from_buffer(&bytes).unwrap();
+
+    assert_eq!(stream.check_continuity(), Ok(vec![]));
+
+    let offsets = match stream.metadata()[0].data {
+      Data::SeekTable(ref seek_points) => {
+        seek_points.iter().map(|point| point.stream_offset as usize)
+                          .collect::<Vec<_>>()
+      }
+      _                                => panic!("missing seek table"),
+    };
+
+    (stream.audio_offset() as usize, offsets)
+  };
+
+  // Leave out the second frame.
+  let mut gap = bytes[0..(audio_offset + offsets[1])].to_vec();
+
+  gap.extend_from_slice(&bytes[(audio_offset + offsets[2])..]);
+
+  // Repeat every frame.
+  let mut overlap = bytes.clone();
+
+  overlap.extend_from_slice(&bytes[audio_offset..]);
+
+  let mut stream = Stream::<ByteStream>::from_buffer(&gap).unwrap();
+
+  assert_eq!(stream.check_continuity(), Ok(vec![Discontinuity {
+    frame_index: 1,
+    expected: 1152,
+    found: 2304,
+  }]));
+
+  let mut stream = Stream::<ByteStream>::from_buffer(&overlap).unwrap();
+  let result     = stream.check_continuity().unwrap();
+
+  assert_eq!(result.len(), 1);
+  assert!(result[0].is_overlap());
+  assert_eq!(result[0].found, 0);
+  assert_eq!(result[0].expected, info.total_samples);
+}
+
+#[test]
+fn test_wrong_total_samples() {
+  let mut file   = File::open("tests/assets/input-SVAUP.flac").unwrap();
+  let mut buffer = Vec::new();
+
+  file.read_to_end(&mut buffer).unwrap();
+
+  let samples = {
+    let mut stream = Stream::<ByteStream>::from_buffer(&buffer).unwrap();
+    let mut iter   = stream.iter::<i16>();
+
+    assert_eq!(iter.size_hint(), (0, Some(11760)));
+    assert_eq!(iter.next().map(|_| iter.size_hint()), Some((0, Some(11759))));
+
+    let samples = iter.by_ref().collect::<Vec<_>>();
+
+    assert!(!iter.is_total_samples_wrong());
+
+    samples
+  };
+
+  // Total samples of 1000 and 10000, where the actual total is 5880.
+  let totals = [[0x00, 0x00, 0x03, 0xe8], [0x00, 0x00, 0x27, 0x10]];
+
+  for total in &totals {
+    buffer[22..26].copy_from_slice(total);
+
+    let mut stream = Stream::<ByteStream>::from_buffer(&buffer).unwrap();
+    let mut iter   = stream.iter::<i16>();
+
+    assert_eq!(iter.by_ref().count(), samples.len() + 1);
+    assert!(iter.is_total_samples_wrong());
+    assert_eq!(iter.size_hint(), (0, None));
+  }
+}
+
+#[test]
+fn test_channel_order() {
+  for channels in 3..9 {
+    for order in &[ChannelOrder::Wave, ChannelOrder::CoreAudio] {
+      let mut mapping = order.mapping(channels).to_vec();
+
+      mapping.sort();
+
+      assert_eq!(mapping, (0..channels).collect::<Vec<_>>());
+    }
+
+    assert_eq!(ChannelOrder::Wave.mapping(channels),
+               ChannelOrder::Flac.mapping(channels));
+  }
+
+  assert_eq!(ChannelOrder::CoreAudio.mapping(6), [0, 1, 2, 3, 4, 5]);
+  assert_eq!(ChannelOrder::CoreAudio.mapping(7), [0, 1, 2, 3, 5, 6, 4]);
+  assert_eq!(ChannelOrder::CoreAudio.mapping(8), [0, 1, 2, 3, 6, 7, 4, 5]);
+  assert!(ChannelOrder::CoreAudio.mapping(9).is_empty());
+
+  let mut info = StreamInfo::default();
+
+  info.sample_rate     = SampleRate::new(48000).unwrap();
+  info.channels        = 8;
+  info.bits_per_sample = BitsPerSample::new(16).unwrap();
+
+  let samples = (0..800).map(|i| i % 8).collect::<Vec<_>>();
+
+  let mut encoder = Encoder::new(Cursor::new(Vec::new()), info,
+                                 Default::default()).unwrap();
+
+  encoder.write(&samples).unwrap();
+
+  let bytes   = encoder.finish().unwrap().into_inner();
+  let options = StreamOptions {
+    channel_order: ChannelOrder::CoreAudio,
+    ..Default::default()
+  };
+
+  let mut stream = Stream::<ByteStream>::from_buffer_with_options(&bytes,
+                                                                  options)
+                     .unwrap();
+
+  let expected = [0, 1, 2, 3, 6, 7, 4, 5].iter().cycle().take(800)
+                                         .cloned();
+
+  assert!(stream.iter::<i16>().eq(expected));
+}
+
+// Finds the offset of the first metadata block of the given type.
+fn block_offset(buffer: &[u8], block_type: u8) -> Option<usize> {
+  let mut offset = 4;
+
+  while offset + 4 <= buffer.len() {
+    let header = &buffer[offset..(offset + 4)];
+    let length = ((header[1] as usize) << 16) +
+                 ((header[2] as usize) << 8) +
+                 (header[3] as usize);
+
+    if header[0] & 0b01111111 == block_type {
+      return Some(offset);
+    }
+
+    offset += 4 + length;
+  }
+
+  None
+}
+
+fn crc8(bytes: &[u8]) -> u8 {
+  bytes.iter().fold(0, |crc, &byte| {
+    (0..8).fold(crc ^ byte, |crc, _| {
+      if crc & 0b10000000 == 0 { crc << 1 } else { (crc << 1) ^ 0b0111 }
+    })
+  })
+}
+
+// Decodes every sample it's able to, both from a buffer and from a reader,
+// returning how many samples came out of the buffer.
+fn decode_malformed(buffer: &[u8]) -> usize {
+  if let Ok(mut stream) = Stream::<ReadStream<&[u8]>>::new(buffer) {
+    for _ in stream.iter::<i32>() {}
+  }
+
+  Stream::<ByteStream>::from_buffer(buffer).map(|mut stream| {
+    stream.iter::<i32>().count()
+  }).unwrap_or(0)
+}
+
+// Damages bytes all over each stream, decoding every one of them, which
+// has to end in an error or fewer samples rather than a panic.
+#[test]
+fn test_damaged_input() {
+  let filenames = [
+    "tests/assets/input-pictures.flac",
+    "tests/assets/input-rice2-escape.flac",
+    "tests/assets/input-SCPAP.flac",
+    "tests/assets/input-SVAUP.flac",
+  ];
+
+  // Xorshift generator, seeded so every run damages the same bytes.
+  let mut state = 0x2545f4914f6cdd1du64;
+  let mut next  = move || {
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+
+    state
+  };
+
+  for filename in &filenames {
+    let mut file   = File::open(filename).unwrap();
+    let mut buffer = Vec::new();
+
+    file.read_to_end(&mut buffer).unwrap();
+
+    for _ in 0..500 {
+      let mut damaged = buffer.clone();
+
+      for _ in 0..(1 + next() % 8) {
+        let index = (next() % damaged.len() as u64) as usize;
+
+        damaged[index] = next() as u8;
+      }
+
+      decode_malformed(&damaged);
+    }
+  }
+}
+
+#[test]
+fn test_malformed_input() {
+  let mut file   = File::open("tests/assets/input-SVAUP.flac").unwrap();
+  let mut buffer = Vec::new();
+
+  file.read_to_end(&mut buffer).unwrap();
+
+  let total  = decode_malformed(&buffer);
+  let stream = Stream::<ByteStream>::from_buffer(&buffer).unwrap();
+  let audio  = stream.audio_offset() as usize;
+
+  // Cut off at any point, including halfway through a frame.
+  for length in (0..buffer.len()).filter(|length| length % 16 == 0) {
+    assert!(decode_malformed(&buffer[0..length]) < total);
+  }
+
+  // Number of comments far larger than the block could hold.
+  let mut comments = buffer.clone();
+  let offset       = block_offset(&comments, 4).unwrap() + 4;
+  let vendor       = comments[offset] as usize;
+
+  for byte in &mut comments[(offset + 4 + vendor)..(offset + 8 + vendor)] {
+    *byte = 0xff;
+  }
+
+  let result = Stream::<ByteStream>::from_buffer(&comments);
+
+  assert_eq!(result.is_err(), cfg!(feature = "vorbis-comment"));
+
+  // Block length that doesn't match what gets parsed.
+  let mut length = buffer.clone();
+  let offset     = block_offset(&length, 4).unwrap();
+
+  for byte in &mut length[(offset + 1)..(offset + 4)] {
+    *byte = 0;
+  }
+
+  decode_malformed(&length);
+
+  // Frames larger than the maximum block size within `StreamInfo`.
+  let mut block_size = buffer.clone();
+
+  block_size[10] = 0;
+  block_size[11] = 16;
+
+  assert_eq!(decode_malformed(&block_size), 0);
+
+  // Subframes of the first frame, right after the frame header.
+  let header   = (5..17).find(|&length| {
+    crc8(&buffer[audio..(audio + length)]) == buffer[audio + length]
+  }).unwrap();
+  let subframe = audio + header + 1;
+
+  let inputs = [
+    // Constant subframe with all 16 bits being wasted.
+    &[0b00000001, 0b00000000, 0b00000001][..],
+    // Fixed subframe, with an order of four, where there are more
+    // partitions than samples.
+    &[0b00011000, 0, 0, 0, 0, 0, 0, 0, 0, 0b00111100][..],
+  ];
+
+  for input in &inputs {
+    let mut subframes = buffer.clone();
+
+    subframes[subframe..(subframe + input.len())].copy_from_slice(input);
+
+    assert!(decode_malformed(&subframes) < total);
+  }
+}
+
+#[test]
+fn test_window() {
+  let filenames = [
+    "tests/assets/input-SCPAP.flac",
+    "tests/assets/input-SVAUP.flac",
+  ];
+
+  for filename in &filenames {
+    let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+
+    let samples  = stream.iter::<i16>().collect::<Vec<_>>();
+    let info     = stream.info();
+    let channels = info.channels as usize;
+    let total    = info.total_samples;
+
+    let mut window = stream.window::<i16>(500);
+
+    for &focus in &[0, 1000, 3000, 200, 5000, total - 1, 10] {
+      window.focus(focus).unwrap();
+
+      let start = focus.saturating_sub(500);
+      let end   = cmp::min(focus + 501, total);
+
+      let held   = window.samples();
+      let offset = start as usize * channels;
+
+      assert_eq!(window.start(), start);
+      assert!(offset + held.len() >= end as usize * channels);
+      assert_eq!(held, &samples[offset..(offset + held.len())]);
+
+      let index = focus as usize * channels;
+
+      assert_eq!(window.get(focus), Some(&samples[index..(index + channels)]));
+      assert_eq!(window.get(total), None);
+    }
+  }
+}
+
+#[test]
+fn test_frame_ranges() {
+  let filenames = [
+    "tests/assets/input-SCPAP.flac",
+    "tests/assets/input-SVAUP.flac",
+  ];
+
+  for filename in &filenames {
+    let mut file   = File::open(filename).unwrap();
+    let mut buffer = Vec::new();
+
+    file.read_to_end(&mut buffer).unwrap();
+
+    let mut stream = Stream::<ByteStream>::from_buffer(&buffer).unwrap();
+    let mut reader = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+
+    let ranges = stream.frame_ranges().collect::<Vec<_>>();
+    let first  = &ranges[0];
+    let last   = &ranges[ranges.len() - 1];
+
+    assert_eq!(first.range.start, stream.audio_offset());
+    assert_eq!(last.range.end, buffer.len() as u64);
+    assert_eq!(reader.frame_ranges().collect::<Vec<_>>(), ranges);
+
+    for pair in ranges.windows(2) {
+      assert_eq!(pair[0].range.end, pair[1].range.start);
+    }
+
+    for frame in &ranges {
+      let end = frame.range.end as usize;
+      let crc = ((buffer[end - 2] as u16) << 8) | buffer[end - 1] as u16;
+
+      assert_eq!(frame.crc, crc);
+    }
+  }
+}
+
+#[test]
+fn test_frame_length() {
+  use flac::stream::frame_length;
+
+  let filenames = [
+    "tests/assets/input-SCPAP.flac",
+    "tests/assets/input-SVAUP.flac",
+    "tests/assets/input-pictures.flac",
+  ];
+
+  for filename in &filenames {
+    let mut file   = File::open(filename).unwrap();
+    let mut buffer = Vec::new();
+
+    file.read_to_end(&mut buffer).unwrap();
+
+    let mut stream = Stream::<ByteStream>::from_buffer(&buffer).unwrap();
+    let info       = stream.info();
+
+    for frame in stream.frame_ranges() {
+      let start  = frame.range.start as usize;
+      let end    = frame.range.end as usize;
+      let length = end - start;
+
+      assert_eq!(frame_length(&buffer[start..], &info), Ok(length));
+
+      match frame_length(&buffer[start..(end - 1)], &info) {
+        Err(ErrorKind::Incomplete(needed)) => assert!(needed >= length - 1),
+        result                             => panic!("{:?}", result),
+      }
+    }
+
+    let offset = stream.audio_offset() as usize;
+
+    assert!(frame_length(&buffer[(offset + 1)..], &info).is_err());
+  }
+
+  let mut info = StreamInfo::default();
+
+  info.channels        = 2;
+  info.bits_per_sample = BitsPerSample::new(32).unwrap();
+
+  // Left and side channels of 192 samples, both constant, with the side
+  // channel taking up 33 bits.
+  let mut frame = vec![0xff, 0xf8, 0x10, 0x80, 0x00];
+  let crc       = crc8(&frame);
+
+  frame.push(crc);
+  frame.extend_from_slice(&[0; 13]);
+
+  assert_eq!(frame_length(&frame, &info), Ok(19));
+}
+
+#[test]
+fn test_frame_layout() {
+  use flac::stream::frame_layout;
+
+  let filenames = [
+    "tests/assets/input-SCPAP.flac",
+    "tests/assets/input-SVAUP.flac",
+    "tests/assets/input-pictures.flac",
+  ];
+
+  for filename in &filenames {
+    let mut file   = File::open(filename).unwrap();
+    let mut buffer = Vec::new();
+
+    file.read_to_end(&mut buffer).unwrap();
+
+    let mut stream = Stream::<ByteStream>::from_buffer(&buffer).unwrap();
+    let info       = stream.info();
+
+    for frame in stream.frame_ranges() {
+      let start  = frame.range.start as usize;
+      let end    = frame.range.end as usize;
+      let layout = frame_layout(&buffer[start..], &info).unwrap();
+
+      assert_eq!(layout.length, end - start);
+      assert_eq!(layout.subframes.len(), info.channels as usize);
+
+      let mut position = layout.header_length * 8;
+
+      for range in &layout.subframes {
+        let byte = buffer[start + range.start / 8];
+
+        // Every subframe header starts with a zero bit.
+        assert_eq!(range.start, position);
+        assert_eq!((byte << (range.start % 8)) & 0x80, 0);
+
+        position = range.end;
+      }
+
+      assert_eq!((position + 7) / 8 + 2, layout.length);
+
+      match frame_layout(&buffer[start..(end - 1)], &info) {
+        Err(ErrorKind::Incomplete(_)) => (),
+        result                        => panic!("{:?}", result),
+      }
+    }
+  }
+}
+
+#[test]
+fn test_metadata_size_report() {
+  let filenames = [
+    "tests/assets/input-SCPAP.flac",
+    "tests/assets/input-SVAUP.flac",
+    "tests/assets/input-pictures.flac",
+  ];
+
+  for filename in &filenames {
+    let mut file   = File::open(filename).unwrap();
+    let mut buffer = Vec::new();
+
+    file.read_to_end(&mut buffer).unwrap();
+
+    let (_, samples) = flac::read(filename).unwrap();
+
+    let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+    let report     = stream.metadata_size_report().unwrap();
+
+    assert_eq!(report.marker, 4);
+    assert_eq!(report.stream_info, 38);
+    assert_eq!(report.metadata(), stream.audio_offset());
+    assert_eq!(report.total(), buffer.len() as u64);
+
+    // Measuring the audio leaves the stream where it was.
+    assert_eq!(stream.iter::<i32>().collect::<Vec<_>>(), samples);
+
+    let mut stream = Stream::<ByteStream>::from_buffer(&buffer).unwrap();
+
+    assert_eq!(stream.metadata_size_report().unwrap(), report);
+  }
+
+  // `StreamInfo` declaring four bytes past what it uses.
+  let mut file   = File::open("tests/assets/input-SCPAP.flac").unwrap();
+  let mut buffer = Vec::new();
+
+  file.read_to_end(&mut buffer).unwrap();
+
+  let length = buffer.len();
+
+  buffer[7] = 38;
+  buffer.splice(42..42, vec![0; 4]);
+
+  let mut stream = Stream::<ByteStream>::from_buffer(&buffer).unwrap();
+  let report     = stream.metadata_size_report().unwrap();
+
+  assert_eq!(report.stream_info, 42);
+  assert_eq!(report.total(), length as u64 + 4);
+}
+
+#[cfg(feature = "picture")]
+#[test]
+fn test_metadata_size_report_pictures() {
+  let filename   = "tests/assets/input-pictures.flac";
+  let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+  let report     = stream.metadata_size_report().unwrap();
+
+  let pictures = stream.metadata().iter().filter(|block| block.is_picture())
+                                  .fold(0, |total, block| {
+    total + 4 + block.length() as u64
+  });
+
+  assert!(pictures > 0);
+  assert_eq!(report.picture, pictures);
+}
+
+#[cfg(feature = "vorbis-comment")]
+#[test]
+fn test_max_comments() {
+  use flac::metadata::CommentIter;
+
+  let mut file   = File::open("tests/assets/input-SVAUP.flac").unwrap();
+  let mut buffer = Vec::new();
+
+  file.read_to_end(&mut buffer).unwrap();
+
+  let options = StreamOptions {
+    max_comments: 0,
+    ..Default::default()
+  };
+
+  let default_stream = Stream::<ByteStream>::from_buffer(&buffer).unwrap();
+  let limited_stream = Stream::<ByteStream>::from_buffer_with_options(
+                         &buffer, options).unwrap();
+
+  let block = default_stream.metadata().iter()
+                            .find(|block| block.is_vorbis_comment())
+                            .unwrap();
+  let iter  = CommentIter::new(&block.raw().unwrap()[4..]).unwrap();
+
+  if let Data::VorbisComment(ref vorbis_comment) = block.data {
+    assert!(!vorbis_comment.comments.is_empty());
+    assert_eq!(iter.vendor_string(), vorbis_comment.vendor_string);
+
+    for (name, value) in iter {
+      assert_eq!(vorbis_comment.comments.get(name).map(|v| &v[..]),
+                 Some(value));
+    }
+  }
+
+  for block in limited_stream.metadata() {
+    if let Data::VorbisComment(ref vorbis_comment) = block.data {
+      assert!(vorbis_comment.comments.is_empty());
+    }
+  }
+}
+
+#[test]
+fn test_push_decoder() {
+  let filename = "tests/assets/input-SVAUP.flac";
+
+  let mut file   = File::open(filename).unwrap();
+  let mut buffer = Vec::new();
+
+  file.read_to_end(&mut buffer).unwrap();
+
+  let (expected, frame_ends) = {
+    let mut stream = Stream::<ByteStream>::from_buffer(&buffer).unwrap();
+
+    let samples = stream.iter::<i16>().collect::<Vec<_>>();
+
+    stream.rewind().unwrap();
+
+    let ends = stream.frame_ranges().map(|frame| frame.range.end as usize)
+                     .collect::<Vec<_>>();
+
+    (samples, ends)
+  };
+
+  let mut decoder = PushDecoder::new::<i16>();
+  let mut samples = Vec::new();
+
+  // Every frame has to be handed out on the push of its last byte.
+  for (index, byte) in buffer.iter().enumerate() {
+    let mut frames = 0;
+
+    decoder.push(&[*byte], |frame| {
+      samples.extend_from_slice(frame);
+
+      frames += 1;
+    }).unwrap();
+
+    let is_frame_end = frame_ends.contains(&(index + 1));
+
+    assert_eq!(frames, if is_frame_end { 1 } else { 0 });
+  }
+
+  assert_eq!(decoder.info(), Some(flac::read(filename).unwrap().0));
+  assert_eq!(samples, expected);
+
+  let mut decoder = PushDecoder::new::<i16>();
+  let mut frames  = 0;
+
+  decoder.push(&buffer, |_| frames += 1).unwrap();
+
+  assert_eq!(frames, frame_ends.len());
+
+  let mut decoder = PushDecoder::new::<i16>();
+
+  assert_eq!(decoder.push(b"fLaX", |_| {}), Err(ErrorKind::HeaderParser));
+
+  // A damaged frame never has a matching CRC-16, but still fails once the
+  // bytes after it come in.
+  let middle = (frame_ends[0] + frame_ends[1]) / 2;
+
+  buffer[middle] ^= 0xff;
+
+  let mut decoder = PushDecoder::new::<i16>();
+  let mut frames  = 0;
+  let mut result  = Ok(());
+
+  for byte in &buffer {
+    result = decoder.push(&[*byte], |_| frames += 1);
+
+    if result.is_err() {
+      break;
+    }
+  }
+
+  assert_eq!(frames, 1);
+  assert!(result.is_err());
+  assert_eq!(decoder.push(&[], |_| {}), result);
+}
+
+#[test]
+fn test_frame_feed() {
+  use flac::stream::FrameFeed;
+
+  let filename = "tests/assets/input-SVAUP.flac";
+
+  let mut file   = File::open(filename).unwrap();
+  let mut buffer = Vec::new();
+
+  file.read_to_end(&mut buffer).unwrap();
+
+  let (info, expected) = flac::read(filename).unwrap();
+
+  let mut stream = Stream::<ByteStream>::from_buffer(&buffer).unwrap();
+  let offset     = stream.audio_offset() as usize;
+  let frames     = stream.frame_ranges().map(|frame| {
+    &buffer[(frame.range.start as usize)..(frame.range.end as usize)]
+  }).collect::<Vec<_>>();
+
+  let mut feed    = FrameFeed::new::<i32>(info).unwrap();
+  let mut samples = Vec::new();
+
+  for frame in &frames {
+    samples.extend_from_slice(feed.push_frame(frame).unwrap());
+  }
+
+  assert_eq!(samples, expected);
+  assert_eq!(feed.position(), info.total_samples);
+
+  // Frames carry their own position, so going back needs nothing else.
+  let length = feed.push_frame(frames[0]).unwrap().len();
+
+  assert_eq!(feed.position() as usize, length / info.channels as usize);
+
+  // Matroska keeps the "fLaC" header while MP4 leaves it out.
+  for config in &[&buffer[..offset], &buffer[4..offset]] {
+    let mut feed = FrameFeed::from_codec_config::<i32>(config).unwrap();
+
+    assert_eq!(feed.info(), info);
+    assert_eq!(feed.metadata().len(), stream.metadata().len());
+    assert_eq!(feed.push_frame(frames[0]).unwrap(),
+               &expected[..length]);
+  }
+
+  let last    = frames[frames.len() - 1];
+  let doubled = [frames[0], frames[1]].concat();
+
+  assert_eq!(feed.push_frame(&doubled), Err(ErrorKind::TrailingBytes));
+
+  match feed.push_frame(&last[..(last.len() - 1)]) {
+    Err(ErrorKind::Incomplete(_)) => (),
+    result                        => panic!("{:?}", result),
+  }
+}
+
+#[test]
+fn test_decode_stats() {
+  let filename = "tests/assets/input-SVAUP.flac";
+
+  let mut buffer = Vec::new();
+
+  File::open(filename).unwrap().read_to_end(&mut buffer).unwrap();
+
+  let mut stream = Stream::<ByteStream>::from_buffer(&buffer).unwrap();
+  let ranges     = stream.frame_ranges().collect::<Vec<_>>();
+  let info       = stream.info();
+
+  assert!(stream.rewind().is_ok());
+  assert_eq!(stream.stats().frames(), 0);
+  assert_eq!(stream.stats().average_frame_size(), None);
+
+  {
+    let mut iter = stream.iter::<i16>();
+
+    assert!(iter.next().is_some());
+    assert_eq!(iter.stats().frames(), 1);
+    assert_eq!(iter.stats().bytes(), ranges[0].range.end -
+                                     ranges[0].range.start);
+  }
+
+  let mut stream = Stream::<ByteStream>::from_buffer(&buffer).unwrap();
+
+  assert_eq!(stream.iter::<i16>().count(),
+             info.total_samples as usize * info.channels as usize);
+
+  let stats   = stream.stats();
+  let recent  = cmp::min(ranges.len(), 32);
+  let skipped = ranges.len() - recent;
+  let samples = info.total_samples -
+                skipped as u64 * info.max_block_size as u64;
+  let size    = ranges[skipped..].iter().fold(0, |total, frame| {
+    total + (frame.range.end - frame.range.start)
+  });
+
+  assert_eq!(stats.frames(), ranges.len() as u64);
+  assert_eq!(stats.bytes(), buffer.len() as u64 - stream.audio_offset());
+  assert_eq!(stats.average_frame_size(), Some(size as f64 / recent as f64));
+  assert_eq!(stats.average_block_size(),
+             Some(samples as f64 / recent as f64));
+  assert!(stats.bytes_per_second().unwrap() > 0.0);
+
+  if cfg!(feature = "metrics") {
+    assert!(average_decode_time(stats));
+  }
+}
+
+#[cfg(feature = "metrics")]
+fn average_decode_time(stats: &flac::DecodeStats) -> bool {
+  stats.average_decode_time().is_some()
+}
+
+#[cfg(not(feature = "metrics"))]
+fn average_decode_time(_: &flac::DecodeStats) -> bool {
+  true
+}
+
+#[test]
+fn test_blocks() {
+  let filename = "tests/assets/input-SVAUP.flac";
+
+  let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+  let samples    = stream.iter::<i16>().collect::<Vec<_>>();
+  let channels   = stream.info().channels as usize;
+
+  assert!(stream.rewind().is_ok());
+
+  let blocks = stream.blocks::<i16>().collect::<Result<Vec<_>, _>>()
+                 .unwrap();
+
+  let mut sample_number = 0;
+
+  for block in &blocks {
+    assert_eq!(block.sample_number, sample_number);
+    assert_eq!(block.channels, channels);
+
+    sample_number += block.len() as u64;
+  }
+
+  assert_eq!(blocks.iter().flat_map(|b| b.samples.iter().cloned())
+                   .collect::<Vec<_>>(), samples);
+
+  // Sample numbers carry on from wherever seeking lands.
+  let start = stream.seek(20000).unwrap();
+  let block = stream.blocks::<i16>().next().unwrap().unwrap();
+
+  assert!(start > 0);
+  assert_eq!(block.sample_number, start);
+  assert_eq!(block.samples[0], samples[start as usize * channels]);
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn test_async_blocks() {
+  use futures::Stream as FutureStream;
+  use std::thread;
+  use std::time::Duration;
+
+  let filename = "tests/assets/input-SVAUP.flac";
+
+  let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+  let samples    = stream.iter::<i16>().collect::<Vec<_>>();
+
+  let stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+  let blocks = flac::async_stream::blocks::<i16, _>(stream, 1);
+
+  thread::sleep(Duration::from_millis(50));
+
+  assert_eq!(blocks.limit(), 1);
+  assert!(blocks.buffered_len() <= 1);
+
+  let decoded = blocks.wait().map(|block| block.unwrap().samples)
+                      .collect::<Vec<_>>().concat();
+
+  assert_eq!(decoded, samples);
+
+  // A frame that fails to decode hands out its error as the last item.
+  let mut file   = File::open(filename).unwrap();
+  let mut buffer = Vec::new();
+
+  file.read_to_end(&mut buffer).unwrap();
+
+  let ranges = Stream::<ByteStream>::from_buffer(&buffer).unwrap()
+                 .frame_ranges().collect::<Vec<_>>();
+  let last   = &ranges[ranges.len() - 1].range;
+
+  buffer[((last.start + last.end) / 2) as usize] ^= 0xff;
+
+  let stream =
+    Stream::<ReadStream<Cursor<Vec<u8>>>>::new(Cursor::new(buffer)).unwrap();
+  let blocks = flac::async_stream::blocks::<i16, _>(stream, 1).wait()
+                 .collect::<Vec<_>>();
+
+  assert_eq!(blocks.len(), ranges.len());
+  assert!(blocks[blocks.len() - 1].is_err());
+}
+
+#[test]
+fn test_decode_progress() {
+  let mut file   = File::open("tests/assets/input-SVAUP.flac").unwrap();
+  let mut buffer = Vec::new();
+
+  file.read_to_end(&mut buffer).unwrap();
+
+  let mut stream = Stream::<ByteStream>::from_buffer(&buffer).unwrap();
+  let ranges     = stream.frame_ranges().collect::<Vec<_>>();
+  let total      = stream.info().total_samples;
+
+  assert!(stream.rewind().is_ok());
+  assert_eq!(stream.decode_progress().byte_offset, stream.audio_offset());
+
+  stream.iter::<i16>().count();
+
+  let progress = stream.decode_progress();
+
+  assert_eq!(progress.error, None);
+  assert_eq!(progress.frames, ranges.len() as u64);
+  assert_eq!(progress.samples, total);
+  assert_eq!(progress.byte_offset, buffer.len() as u64);
+  assert_eq!(progress.fraction(), Some(1.0));
+
+  assert!(stream.rewind().is_ok());
+  assert_eq!(stream.decode_progress().samples, 0);
+
+  // Cut off halfway through the last frame.
+  let last       = &ranges[ranges.len() - 1].range;
+  let length     = (last.start + (last.end - last.start) / 2) as usize;
+  let mut stream = Stream::<ByteStream>::from_buffer(&buffer[0..length])
+                     .unwrap();
+
+  stream.iter::<i16>().count();
+
+  let progress = stream.decode_progress();
+
+  assert!(progress.error.is_some());
+  assert_eq!(progress.frames, ranges.len() as u64 - 1);
+  assert_eq!(progress.byte_offset, last.start);
+  assert!(progress.fraction().unwrap() < 1.0);
+}
+
+#[test]
+fn test_cancel_handle() {
+  let filename = "tests/assets/input-SVAUP.flac";
+
+  let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+  let samples    = stream.iter::<i16>().collect::<Vec<_>>();
+  let handle     = stream.cancel_handle();
+
+  assert!(stream.rewind().is_ok());
+
+  let mut decoded = Vec::new();
+
+  for block in stream.blocks::<i16>().take(3) {
+    decoded.extend(block.unwrap().samples);
+
+    handle.cancel();
+  }
+
+  let progress = stream.decode_progress();
+
+  assert_eq!(progress.error, Some(ErrorKind::Cancelled));
+  assert_eq!(progress.frames, 1);
+  assert_eq!(stream.check_continuity(), Err(ErrorKind::Cancelled));
+  assert_eq!(stream.frame_ranges().count(), 0);
+
+  // Picks up from the frame after the last one decoded.
+  handle.reset();
+
+  decoded.extend(stream.blocks::<i16>().flat_map(|block| {
+    block.unwrap().samples
+  }));
+
+  assert!(decoded == samples);
+
+  // Shared with another stream and cancelled from another thread.
+  let shared     = CancelHandle::new();
+  let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+
+  stream.set_cancel_handle(shared.clone());
+
+  let cancel = shared.clone();
+
+  thread::spawn(move || cancel.cancel()).join().unwrap();
+
+  assert!(stream.cancel_handle().is_cancelled());
+  assert_eq!(stream.md5_sum_with(ByteCounter(0)),
+             Err(ErrorKind::Cancelled));
+  assert_eq!(stream.decode_progress().frames, 0);
+}
+
+// Counts the bytes fed into it instead of hashing them.
+struct ByteCounter(u64);
+
+impl flac::digest::Digest for ByteCounter {
+  fn update(&mut self, bytes: &[u8]) {
+    self.0 += bytes.len() as u64;
+  }
+
+  fn finish(self) -> [u8; 16] {
+    let mut signature = [0; 16];
+
+    for i in 0..8 {
+      signature[i] = (self.0 >> (i * 8)) as u8;
+    }
+
+    signature
+  }
+}
+
+#[test]
+fn test_verify() {
+  let filenames = [
+    "tests/assets/input-pictures.flac",
+    "tests/assets/input-SCPAP.flac",
+    "tests/assets/input-SVAUP.flac",
+  ];
+
+  for filename in &filenames {
+    let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+
+    let info      = stream.info();
+    let width     = get_offset(usize::from(info.bits_per_sample)) as u64;
+    let expected  = info.total_samples * info.channels as u64 * width;
+    let signature = stream.md5_sum_with(ByteCounter(0)).unwrap();
+
+    let mut count = 0;
+
+    for i in 0..8 {
+      count |= (signature[i] as u64) << (i * 8);
+    }
+
+    assert_eq!(count, expected);
+
+    // A signature that doesn't match the decoded samples.
+    let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+
+    assert_eq!(stream.verify_with(ByteCounter(0)), Ok(false));
+  }
+}
+
+#[cfg(feature = "md5")]
+#[test]
+fn test_verify_md5() {
+  let filenames = [
+    "tests/assets/input-pictures.flac",
+    "tests/assets/input-SCPAP.flac",
+    "tests/assets/input-SVAUP.flac",
+  ];
+
+  for filename in &filenames {
+    let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+
+    assert_eq!(stream.verify(), Ok(true));
+  }
+}
+
+#[test]
+fn test_conceal() {
+  use flac::conceal::{Conceal, LostFrame, RepeatLast, Silence};
+
+  let (info, samples) = flac::read("tests/assets/input-SVAUP.flac").unwrap();
+
+  let options = EncoderOptions {
+    block_size: 1152,
+    ..Default::default()
+  };
+
+  let mut encoder = Encoder::new(Cursor::new(Vec::new()), info, options)
+                      .unwrap();
+
+  encoder.write(&samples).unwrap();
+
+  let buffer = encoder.finish().unwrap().into_inner();
+
+  let mut stream = Stream::<ByteStream>::from_buffer(&buffer).unwrap();
+  let ranges     = stream.frame_ranges().collect::<Vec<_>>();
+  let info       = stream.info();
+  let expected   = samples;
+
+  // Damage the audio of the third frame, leaving its header intact.
+  let damaged     = &ranges[2].range;
+  let middle      = (damaged.start + damaged.end) / 2;
+  let mut corrupt = buffer.clone();
+
+  corrupt[middle as usize] ^= 0xff;
+
+  let mut stream = Stream::<ByteStream>::from_buffer(&corrupt).unwrap();
+
+  stream.iter::<i32>().count();
+
+  assert!(stream.decode_progress().error.is_some());
+
+  let lost     = Arc::new(Mutex::new(Vec::new()));
+  let recorder = lost.clone();
+
+  let mut stream = Stream::<ByteStream>::from_buffer(&corrupt).unwrap();
+
+  stream.set_concealer(move |frame: &LostFrame, previous: &[i32],
+                             output: &mut [i32]| {
+    recorder.lock().unwrap().push(*frame);
+
+    Silence.conceal(frame, previous, output);
+  });
+
+  let samples  = stream.iter::<i32>().collect::<Vec<_>>();
+  let progress = stream.decode_progress();
+  let lost     = lost.lock().unwrap();
+
+  let channels   = info.channels as usize;
+  let block_size = info.max_block_size as usize;
+  let start      = 2 * block_size * channels;
+  let end        = start + block_size * channels;
+
+  assert_eq!(progress.error, None);
+  assert_eq!(progress.concealed, 1);
+  assert_eq!(progress.samples, info.total_samples);
+  assert_eq!(progress.byte_offset, buffer.len() as u64);
+  assert_eq!(lost.len(), 1);
+  assert_eq!(lost[0].sample_number, 2 * block_size as u64);
+  assert_eq!(lost[0].block_size, block_size);
+  assert_eq!(lost[0].channels, channels);
+  assert_eq!(samples.len(), expected.len());
+  assert_eq!(&samples[0..start], &expected[0..start]);
+  assert_eq!(&samples[end..], &expected[end..]);
+  assert!(samples[start..end].iter().all(|&sample| sample == 0));
+
+  // Read sources skip over the damage the same way.
+  let mut stream =
+    Stream::<ReadStream<Cursor<Vec<u8>>>>::new(Cursor::new(corrupt)).unwrap();
+
+  stream.set_concealer(RepeatLast);
+
+  let samples  = stream.iter::<i32>().collect::<Vec<_>>();
+  let previous = start - block_size * channels;
+
+  assert_eq!(stream.decode_progress().concealed, 1);
+  assert_eq!(samples.len(), expected.len());
+  assert_eq!(&samples[start..end], &expected[previous..start]);
+  assert_eq!(&samples[end..], &expected[end..]);
+}
+
+#[test]
+fn test_decode_into() {
+  use flac::sink::{DigestSink, Sink, TeeSink};
+  use flac::stream::Block;
+
+  struct Collect(Vec<i32>);
+
+  impl Sink<i32> for Collect {
+    fn write_block(&mut self, block: &Block<i32>) -> Result<(), ErrorKind> {
+      self.0.extend_from_slice(&block.samples);
+
+      Ok(())
+    }
+  }
+
+  let filename = "tests/assets/input-SCPAP.flac";
+
+  let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+  let expected   = stream.iter::<i32>().collect::<Vec<_>>();
+  let info       = stream.info();
+
+  let mut stream  = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+  let mut samples = Collect(Vec::new());
+  let mut counter = DigestSink::new(ByteCounter(0),
+                                    info.bits_per_sample.get());
+
+  {
+    let mut tee = TeeSink::new();
+
+    tee.push(&mut samples);
+    tee.push(&mut counter);
+
+    assert_eq!(stream.decode_into::<i32, _>(tee), Ok(info.total_samples));
+  }
+
+  let width     = get_offset(usize::from(info.bits_per_sample));
+  let signature = counter.signature();
+
+  let mut count = 0;
+
+  for i in 0..8 {
+    count |= (signature[i] as usize) << (i * 8);
+  }
+
+  assert_eq!(samples.0, expected);
+  assert_eq!(count, expected.len() * width);
+}
+
+#[test]
+fn test_pcm_reader() {
+  use flac::pcm::{Endian, PcmFormat, PcmReader};
+
+  let filename = "tests/assets/input-SCPAP.flac";
+
+  let (info, samples) = flac::read(filename).unwrap();
+
+  assert_eq!(info.bits_per_sample.get(), 16);
+
+  let format = PcmFormat {
+    endian: Endian::Big,
+    is_signed: false,
+  };
+
+  let expected = samples.iter().flat_map(|&sample| {
+    let sample = (sample + 0x8000) as u16;
+
+    vec![(sample >> 8) as u8, sample as u8]
+  }).collect::<Vec<_>>();
+
+  let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+  let mut bytes  = Vec::new();
+
+  PcmReader::new(&mut stream, format).read_to_end(&mut bytes).unwrap();
+
+  assert_eq!(bytes, expected);
+
+  // A corrupt frame stops reading with an error.
+  let mut file   = File::open(filename).unwrap();
+  let mut buffer = Vec::new();
+
+  file.read_to_end(&mut buffer).unwrap();
+
+  let length = buffer.len();
+
+  buffer[length - 3] ^= 0xff;
+
+  let mut stream = Stream::<ByteStream>::from_buffer(&buffer).unwrap();
+  let mut reader = PcmReader::new(&mut stream, format);
+  let error      = reader.read_to_end(&mut Vec::new()).unwrap_err();
+
+  assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_write_samples_to() {
+  use flac::pcm::{Endian, PcmFormat};
+
+  let filename = "tests/assets/input-SCPAP.flac";
+
+  let (_, samples) = flac::read(filename).unwrap();
+
+  let format = PcmFormat {
+    endian: Endian::Big,
+    is_signed: false,
+  };
+
+  let expected = samples.iter().flat_map(|&sample| {
+    let sample = (sample + 0x8000) as u16;
+
+    vec![(sample >> 8) as u8, sample as u8]
+  }).collect::<Vec<_>>();
+
+  let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+  let mut iter   = stream.iter::<i16>();
+  let mut bytes  = Vec::new();
+
+  // An odd length leaves a byte unused each time, and has samples from
+  // separate frames written together.
+  let mut output = [0; 1023];
+
+  loop {
+    let written = iter.write_samples_to(&mut output, format);
+
+    if written == 0 {
+      break;
+    }
+
+    assert_eq!(written % 2, 0);
+
+    bytes.extend_from_slice(&output[..written]);
+  }
+
+  assert_eq!(bytes, expected);
+  assert_eq!(iter.write_samples_to(&mut output[..1], format), 0);
+}
+
+#[cfg(feature = "cue-sheet")]
+#[test]
+fn test_cue_tracks() {
+  use flac::cue::Pregap;
+
+  let filename = "tests/assets/input-SCPAP.flac";
+
+  let (info, samples) = flac::read(filename).unwrap();
+
+  let channels = info.channels as usize;
+  let middle   = 2940 * channels;
+
+  let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+  let tracks     = stream.cue_tracks::<i32>().map(|track| {
+    (track.number(), track.range(), track.collect::<Vec<_>>())
+  }).collect::<Vec<_>>();
+
+  assert_eq!(tracks, vec![
+    (1, 0..2940, samples[..middle].to_vec()),
+    (2, 2940..5880, samples[middle..].to_vec()),
+  ]);
+
+  // Tracks that get skipped still leave the next track in place.
+  let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+  let mut tracks = stream.cue_tracks_with::<i32>(Pregap::Prepend);
+  let first      = tracks.next().unwrap();
+  let second     = tracks.next().unwrap();
+
+  assert_eq!(second.collect::<Vec<_>>(), &samples[middle..]);
+  assert_eq!(first.error(), None);
+  assert_eq!(first.count(), 0);
+  assert!(tracks.next().is_none());
+
+  // A frame that fails to decode shows up as the error of the track.
+  let mut file   = File::open(filename).unwrap();
+  let mut buffer = Vec::new();
+
+  file.read_to_end(&mut buffer).unwrap();
+
+  let last = {
+    let mut stream = Stream::<ByteStream>::from_buffer(&buffer).unwrap();
+
+    stream.frame_ranges().last().unwrap().range
+  };
+
+  buffer[(last.start + last.end) as usize / 2] ^= 0xff;
+
+  let mut stream = Stream::<ByteStream>::from_buffer(&buffer).unwrap();
+  let mut tracks = stream.cue_tracks::<i32>();
+  let mut first  = tracks.next().unwrap();
+
+  assert_eq!(first.by_ref().count(), middle);
+  assert_eq!(first.error(), None);
+
+  let mut second = tracks.next().unwrap();
+
+  assert!(second.by_ref().count() < samples.len() - middle);
+  assert!(second.error().is_some());
+  assert_eq!(second.error(), second.decode_progress().error);
+}
+
+#[cfg(feature = "cue-sheet")]
+#[test]
+fn test_cue_tracks_deemphasis() {
+  use flac::cue::{self, Pregap};
+
+  // The fixture is silent, which de-emphasis leaves alone, so its cue
+  // sheet gets paired with a square wave close to the highest frequency.
+  let filename = "tests/assets/input-SCPAP.flac";
+  let info     = flac::metadata::get_stream_info(filename).unwrap();
+  let channels = info.channels as usize;
+  let middle   = 2940 * channels;
+  let samples  = (0..(info.total_samples as usize * channels)).map(|i| {
+    if (i / (channels * 2)) % 2 == 0 { 8000 } else { -8000 }
+  }).collect::<Vec<i32>>();
+
+  let mut cue_sheet = flac::metadata::get_cue_sheet(filename).unwrap();
+
+  cue_sheet.tracks[1].is_pre_emphasis = true;
+
+  let options = EncoderOptions {
+    metadata: vec![Data::CueSheet(cue_sheet)],
+    ..Default::default()
+  };
+
+  let mut encoder = Encoder::new(Cursor::new(Vec::new()), info, options)
+                      .unwrap();
+
+  encoder.write(&samples).unwrap();
+
+  let bytes = encoder.finish().unwrap().into_inner();
+
+  // Flagged tracks keep their pre-emphasis unless asked otherwise.
+  let mut stream = Stream::<ByteStream>::from_buffer(&bytes).unwrap();
+  let tracks     = stream.cue_tracks::<i32>().map(|track| {
+    (track.is_pre_emphasis(), track.collect::<Vec<_>>())
+  }).collect::<Vec<_>>();
+
+  assert_eq!(tracks, vec![
+    (false, samples[..middle].to_vec()),
+    (true, samples[middle..].to_vec()),
+  ]);
+
+  let mut stream = Stream::<ByteStream>::from_buffer(&bytes).unwrap();
+  let tracks     = stream.cue_tracks::<i32>().with_deemphasis(true)
+                         .map(|track| track.collect::<Vec<_>>())
+                         .collect::<Vec<_>>();
+
+  assert_eq!(tracks[0], &samples[..middle]);
+  assert_eq!(tracks[1].len(), samples.len() - middle);
+  assert!(tracks[1] != &samples[middle..]);
+
+  // De-emphasis cuts high frequencies, once past the first samples the
+  // filter starts out settled on.
+  let peak = |values: &[i32]| {
+    values[(channels * 16)..].iter().map(|value| value.abs()).max().unwrap()
+  };
+
+  assert!(peak(&tracks[1]) < peak(&samples[middle..]));
+
+  let mut stream = Stream::<ByteStream>::from_buffer(&bytes).unwrap();
+  let mut flags  = Vec::new();
+
+  cue::export_tracks(&mut stream, Pregap::Append, |track| {
+    flags.push(track.is_pre_emphasis);
+
+    Ok((Cursor::new(Vec::new()), EncoderOptions::default()))
+  }).unwrap();
+
+  assert_eq!(flags, vec![false, true]);
+}
+
+#[cfg(feature = "cue-sheet")]
+#[test]
+fn test_export_tracks() {
+  use flac::cue::{self, Pregap};
+
+  let filename = "tests/assets/input-SCPAP.flac";
+
+  let (info, samples) = flac::read(filename).unwrap();
+
+  let channels = info.channels as usize;
+  let middle   = 2940 * channels;
+
+  let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+  let mut ranges = Vec::new();
+
+  let writers = cue::export_tracks(&mut stream, Pregap::Append, |track| {
+    ranges.push((track.number, track.range.clone()));
+
+    Ok((Cursor::new(Vec::new()), EncoderOptions::default()))
+  }).unwrap();
+
+  assert_eq!(ranges, vec![(1, 0..2940), (2, 2940..5880)]);
+  assert_eq!(writers.len(), 2);
+
+  let expected = [&samples[..middle], &samples[middle..]];
+
+  for (index, writer) in writers.into_iter().enumerate() {
+    let bytes      = writer.into_inner();
+    let mut stream = Stream::<ByteStream>::from_buffer(&bytes).unwrap();
+
+    assert_eq!(stream.info().total_samples, 2940);
+    assert!(stream.iter::<i32>().eq(expected[index].iter().cloned()));
+
+    #[cfg(feature = "vorbis-comment")]
+    {
+      let tags = flac::tags_from(Cursor::new(&bytes[..])).unwrap();
+
+      assert_eq!(tags.get("TRACKNUMBER"), Some(&(index + 1).to_string()));
+      assert_eq!(tags.get("TRACKTOTAL"), Some(&"2".to_owned()));
+    }
+  }
+
+  let mut stream = Stream::<ReadStream<File>>::from_file(
+                     "tests/assets/input-SVAUP.flac").unwrap();
+  let result     = cue::export_tracks(&mut stream, Pregap::Append, |_| {
+    Ok((Cursor::new(Vec::new()), EncoderOptions::default()))
+  });
+
+  assert_eq!(result.err(), Some(ErrorKind::NotFound));
+
+  // Frames that end partway through the second track.
+  let mut file   = File::open(filename).unwrap();
+  let mut buffer = Vec::new();
+
+  file.read_to_end(&mut buffer).unwrap();
+
+  let first = {
+    let mut stream = Stream::<ByteStream>::from_buffer(&buffer).unwrap();
+
+    stream.frame_ranges().next().unwrap().range
+  };
+
+  buffer.truncate(first.end as usize);
+
+  let mut stream = Stream::<ByteStream>::from_buffer(&buffer).unwrap();
+  let mut count  = 0;
+  let result     = cue::export_tracks(&mut stream, Pregap::Append, |_| {
+    count += 1;
+
+    Ok((Cursor::new(Vec::new()), EncoderOptions::default()))
+  });
+
+  assert_eq!(result.err(),
+             Some(ErrorKind::IO(std::io::ErrorKind::UnexpectedEof)));
+  assert_eq!(count, 2);
+}
+
+#[cfg(all(feature = "cue-sheet", feature = "vorbis-comment"))]
+#[test]
+fn test_export_files() {
+  use flac::cue::{self, Pregap};
+
+  use std::env;
+  use std::fs;
+
+  let filename  = "tests/assets/input-SCPAP.flac";
+  let directory = env::temp_dir().join("flac-test-export-files");
+
+  let _ = fs::remove_dir_all(&directory);
+
+  let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+  let paths      = cue::export_files(&mut stream, Pregap::Append, &directory,
+                                     "{tracktotal}/{tracknumber:02}.flac",
+                                     |_| EncoderOptions::default()).unwrap();
+
+  assert_eq!(paths, vec![directory.join("2/01.flac"),
+                         directory.join("2/02.flac")]);
+
+  for path in &paths {
+    let stream = Stream::<ReadStream<File>>::from_file(path.to_str().unwrap())
+                   .unwrap();
+
+    assert_eq!(stream.info().total_samples, 2940);
+  }
+
+  fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_flac_reader() {
+  use flac::FlacReader;
+
+  let filename = "tests/assets/input-pictures.flac";
+
+  let stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+  let reader = FlacReader::from_file(filename).unwrap();
+
+  assert_eq!(reader.info(), stream.info());
+  assert_eq!(reader.metadata().len(), stream.metadata().len());
+  assert_eq!(reader.audio_offset(), stream.audio_offset());
+
+  let mut stream = reader.into_audio().unwrap();
+  let samples    = stream.iter::<i32>().collect::<Vec<_>>();
+
+  assert_eq!(samples, flac::read(filename).unwrap().1);
+
+  // Metadata still gets parsed for streams that can't be decoded with the
+  // options given.
+  let stream_info = StreamInfo {
+    max_block_size: 8192,
+    sample_rate: SampleRate::new(44100).unwrap(),
+    channels: 2,
+    bits_per_sample: BitsPerSample::new(16).unwrap(),
+    ..Default::default()
+  };
+
+  let block      = Metadata::new(true, 34, Data::StreamInfo(stream_info));
+  let mut buffer = b"fLaC".to_vec();
+
+  block.to_bytes(&mut buffer).unwrap();
+
+  let (info, metadata) = FlacReader::from_buffer(&buffer).unwrap()
+                                                       .into_metadata();
+
+  assert_eq!(info, stream_info);
+  assert!(metadata.is_empty());
+
+  let reader = FlacReader::from_buffer(&buffer).unwrap();
+
+  assert_eq!(reader.into_audio().err(), Some(ErrorKind::Unsupported));
+}
+
+#[test]
+fn test_realtime_profile() {
+  use flac::builder::{FrameBuilder, SubframeKind};
+  use flac::stream::RealtimeProfile;
+
+  let options = StreamOptions {
+    realtime: Some(RealtimeProfile::default()),
+    ..Default::default()
+  };
+
+  // Streams within the limits decode the same as without them.
+  let filename    = "tests/assets/input-SVAUP.flac";
+  let mut stream  = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+  let mut limited = Stream::<ReadStream<File>>::from_file_with_options(
+                      filename, options).unwrap();
+
+  assert!(limited.iter::<i16>().eq(stream.iter::<i16>()));
+  assert_eq!(limited.decode_progress().error, None);
+
+  let strict = StreamOptions {
+    realtime: Some(RealtimeProfile {
+      max_metadata_length: 1024,
+      ..Default::default()
+    }),
+    ..Default::default()
+  };
+
+  let result = Stream::<ReadStream<File>>::from_file_with_options(
+                 "tests/assets/input-pictures.flac", strict);
+
+  assert_eq!(result.err(), Some(ErrorKind::Unsupported));
+
+  // A frame larger than it would be with every sample stored uncoded.
+  let samples = (0..64).map(|i| {
+    if i % 2 == 0 { 32767 } else { -32768 }
+  }).collect::<Vec<i32>>();
+
+  let stream_info = StreamInfo {
+    min_block_size: 64,
+    max_block_size: 64,
+    sample_rate: SampleRate::new(44100).unwrap(),
+    channels: 1,
+    bits_per_sample: BitsPerSample::new(16).unwrap(),
+    ..Default::default()
+  };
+
+  let frame = FrameBuilder::new(44100, 16)
+                .with_frame_number(0)
+                .with_channel(&samples, SubframeKind::Fixed(4))
+                .encode()
+                .unwrap();
+
+  assert!(frame.len() > RealtimeProfile::default()
+                          .max_frame_bytes(&stream_info));
+
+  let block      = Metadata::new(true, 34, Data::StreamInfo(stream_info));
+  let mut buffer = b"fLaC".to_vec();
+
+  block.to_bytes(&mut buffer).unwrap();
+  buffer.extend_from_slice(&frame);
+
+  let mut stream  = Stream::<ByteStream>::from_buffer(&buffer).unwrap();
+  let mut limited = Stream::<ByteStream>::from_buffer_with_options(
+                      &buffer, options).unwrap();
+
+  assert!(stream.iter::<i32>().eq(samples.iter().cloned()));
+  assert_eq!(limited.iter::<i32>().count(), 0);
+  assert_eq!(limited.decode_progress().error, Some(ErrorKind::Unsupported));
+}
+
+#[test]
+fn test_prefetch() {
+  use flac::prefetch;
+
+  let filename = "tests/assets/input-SCPAP.flac";
+
+  let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+  let expected   = stream.blocks::<i16>().map(|block| block.unwrap())
+                                         .collect::<Vec<_>>();
+
+  let stream     = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+  let prefetcher = prefetch::prefetch::<i16, _>(stream, 0);
+
+  assert_eq!(prefetcher.depth(), 1);
+  assert_eq!(prefetcher.map(|block| block.unwrap()).collect::<Vec<_>>(),
+             expected);
+
+  // Dropping part way through stops the decoding thread.
+  let stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+
+  let mut prefetcher = prefetch::prefetch::<i16, _>(stream, 2);
+
+  assert_eq!(prefetcher.next(), Some(Ok(expected[0].clone())));
+  assert!(prefetcher.buffered() <= 2);
+
+  // A frame that fails to decode hands out its error as the last block.
+  let mut file   = File::open(filename).unwrap();
+  let mut buffer = Vec::new();
+
+  file.read_to_end(&mut buffer).unwrap();
+
+  let ranges = Stream::<ByteStream>::from_buffer(&buffer).unwrap()
+                 .frame_ranges().collect::<Vec<_>>();
+  let last   = &ranges[ranges.len() - 1].range;
+
+  buffer[((last.start + last.end) / 2) as usize] ^= 0xff;
+
+  let stream =
+    Stream::<ReadStream<Cursor<Vec<u8>>>>::new(Cursor::new(buffer)).unwrap();
+  let blocks = prefetch::prefetch::<i16, _>(stream, 2).collect::<Vec<_>>();
+
+  assert_eq!(blocks.len(), expected.len());
+  assert!(blocks[blocks.len() - 1].is_err());
+}
+
+#[test]
+fn test_metadata_order() {
+  use flac::metadata::Type;
+
+  let filename = "tests/assets/input-pictures.flac";
+
+  let mut file   = File::open(filename).unwrap();
+  let mut buffer = Vec::new();
+
+  file.read_to_end(&mut buffer).unwrap();
+
+  let stream = Stream::<ByteStream>::from_buffer(&buffer).unwrap();
+  let offset = stream.audio_offset() as usize;
+
+  // Laying the blocks out in slice order, right after the stream marker and
+  // `StreamInfo`, gives back the bytes they were parsed from.
+  let mut blocks = buffer[0..42].to_vec();
+
+  for block in stream.metadata() {
+    blocks.extend_from_slice(block.raw().unwrap());
+  }
+
+  assert_eq!(&blocks[..], &buffer[0..offset]);
+
+  // Pictures are kept as unknown blocks without the `picture` feature.
+  let block_type = if cfg!(feature = "picture") {
+    Type::Picture
+  } else {
+    Type::Unknown
+  };
+  let pictures   = stream.metadata_of_type(block_type).collect::<Vec<_>>();
+
+  assert!(!pictures.is_empty());
+
+  for &(position, block) in &pictures {
+    assert_eq!(block.data_type(), block_type);
+    assert!(position >= 1);
+    assert!(&stream.metadata()[position - 1] as *const Metadata ==
+            block as *const Metadata);
+  }
+
+  assert_eq!(stream.metadata_of_type(Type::StreamInfo).count(), 0);
+}
+
+// Signal the 8-bit fixtures were encoded from, in sections of a ramp, a full
+// scale square wave, noise, silence, then a quiet tone. Only the noise
+// depends on `seed`.
+fn signal_8bit(length: usize, seed: u32) -> Vec<i32> {
+  let mut state = seed;
+
+  (0..length).map(|i| {
+    state = state.wrapping_mul(1103515245).wrapping_add(12345);
+
+    match (i / 1000) % 5 {
+      0 => (i % 256) as i32 - 128,
+      1 => if (i / 50) % 2 == 0 { 127 } else { -128 },
+      2 => ((state >> 16) & 0xff) as i32 - 128,
+      3 => 0,
+      _ => (i % 8) as i32 - 4,
+    }
+  }).collect()
+}
+
+// Checks every sample size decodes the 8-bit fixture to `expected`, along
+// with the MD5 signature of the decoded samples.
+fn check_8bit(filename: &str, expected: &[i32]) {
+  let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+  let info       = stream.info();
+
+  assert_eq!(info.bits_per_sample.get(), 8);
+  assert_eq!(info.total_samples * info.channels as u64,
+             expected.len() as u64);
+
+  let samples = stream.iter::<i8>().collect::<Vec<_>>();
+
+  assert!(samples.iter().map(|&sample| sample as i32)
+                 .eq(expected.iter().cloned()));
+
+  let mut md5     = Md5::new();
+  let mut md5_sum = [0; 16];
+  let bytes       = samples.iter().map(|&sample| sample as u8)
+                                  .collect::<Vec<_>>();
+
+  md5.input(&bytes);
+  md5.result(&mut md5_sum);
+
+  assert_eq!(md5_sum, info.md5_sum);
+
+  assert!(stream.rewind().is_ok());
+  assert!(stream.iter::<i16>().map(|sample| sample as i32)
+                .eq(expected.iter().cloned()));
+
+  assert!(stream.rewind().is_ok());
+  assert!(stream.iter::<i32>().eq(expected.iter().cloned()));
+
+  assert!(stream.rewind().is_ok());
+
+  let mut blocks = Vec::new();
+
+  for block in stream.blocks::<i8>() {
+    let samples = block.unwrap().samples;
+
+    blocks.extend(samples.into_iter().map(|sample| sample as i32));
+  }
+
+  assert_eq!(blocks, expected);
+}
+
+#[test]
+fn test_mono_8bit() {
+  let expected = signal_8bit(5000, 1);
+
+  check_8bit("tests/assets/input-mono-8bit.flac", &expected);
+}
+
+#[test]
+fn test_stereo_8bit() {
+  let left     = signal_8bit(5000, 1);
+  let right    = signal_8bit(5000, 2);
+  let expected = left.iter().zip(&right).flat_map(|(&l, &r)| vec![l, r])
+                         .collect::<Vec<_>>();
+
+  // Side channels of an 8-bit stream take up 9 bits, which only fit once
+  // decoded into the extended `i16` samples.
+  check_8bit("tests/assets/input-stereo-8bit.flac", &expected);
+}
+
+#[test]
+fn test_i8_out_of_range() {
+  let mut info = StreamInfo::default();
+
+  info.sample_rate     = SampleRate::new(8000).unwrap();
+  info.channels        = 1;
+  info.bits_per_sample = BitsPerSample::new(16).unwrap();
+
+  // A ramp that leaves the range of an `i8` at sample 300.
+  let samples = (0..1000).map(|i| i / 2 - 22).collect::<Vec<_>>();
+  let bytes   = encode_blocks(info, &samples, 256);
+
+  // Samples aren't clamped, the iterator stops at the first one that doesn't
+  // fit within an `i8`.
+  let mut stream = Stream::<ByteStream>::from_buffer(&bytes).unwrap();
+  let narrow     = stream.iter::<i8>().collect::<Vec<_>>();
+
+  assert_eq!(narrow.len(), 300);
+  assert!(narrow.iter().map(|&sample| sample as i32)
+                .eq(samples[0..300].iter().cloned()));
+
+  // Blocks before the one holding the sample still decode.
+  assert!(stream.rewind().is_ok());
+
+  let blocks = stream.blocks::<i8>().collect::<Vec<_>>();
+
+  assert_eq!(blocks.len(), 2);
+  assert!(blocks[0].is_ok());
+  assert_eq!(blocks[1], Err(ErrorKind::Unsupported));
+}
+
+#[test]
+fn test_iter_packed() {
+  use flac::pcm::{Packed20, Packed24, PackedSample};
+
+  let mut info = StreamInfo::default();
+
+  info.sample_rate     = SampleRate::new(48000).unwrap();
+  info.channels        = 2;
+  info.bits_per_sample = BitsPerSample::new(24).unwrap();
+
+  // A ramp that leaves the range of 20 bits at sample 1536.
+  let samples = (0..4000).map(|i| (i - 512) * 512).collect::<Vec<_>>();
+  let bytes   = encode_blocks(info, &samples, 1024);
+
+  let mut stream = Stream::<ByteStream>::from_buffer(&bytes).unwrap();
+
+  assert!(stream.iter_packed::<Packed24>().map(|sample| sample.unpack())
+                .eq(samples.iter().cloned()));
+
+  let mut packed = stream.iter_packed::<Packed20>();
+
+  assert!(packed.rewind().is_ok());
+  assert_eq!(packed.size_hint(), (0, Some(samples.len())));
+
+  let narrow = packed.collect::<Vec<_>>();
+
+  assert_eq!(narrow.len(), 1536);
+  assert_eq!(narrow[0], Packed20([0x00, 0x00, 0xfc]));
+  assert!(narrow.iter().map(PackedSample::unpack)
+                .eq(samples[0..1536].iter().cloned()));
+}
+
+#[test]
+fn test_deferred_metadata() {
+  let filename = "tests/assets/input-SCPAP.flac";
+  let options  = StreamOptions {
+    is_metadata_deferred: true,
+    ..Default::default()
+  };
+
+  let mut eager  = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+  let samples    = eager.iter::<i16>().collect::<Vec<_>>();
+  let mut stream = Stream::<ReadStream<File>>::from_file_with_options(
+                     filename, options).unwrap();
+
+  assert_eq!(stream.info(), eager.info());
+  assert_eq!(stream.audio_offset(), eager.audio_offset());
+  assert!(stream.metadata().is_empty());
+
+  // Loading the metadata partway through leaves decoding where it was.
+  let mut decoded = stream.blocks::<i16>().take(2).flat_map(|block| {
+    block.unwrap().samples
+  }).collect::<Vec<_>>();
+
+  {
+    let metadata = stream.load_metadata().unwrap();
+
+    assert_eq!(metadata.len(), eager.metadata().len());
+    assert!(metadata.iter().map(|block| &block.data)
+                    .eq(eager.metadata().iter().map(|block| &block.data)));
+  }
+
+  decoded.extend(stream.blocks::<i16>().flat_map(|block| {
+    block.unwrap().samples
+  }));
+
+  assert!(decoded == samples);
+
+  // Seeking goes through the seek table, loading it when needed.
+  let mut stream = Stream::<ReadStream<File>>::from_file_with_options(
+                     filename, options).unwrap();
+  let seek_point = eager.seek(samples.len() as u64 / 4).unwrap();
+
+  assert_eq!(stream.seek(samples.len() as u64 / 4), Ok(seek_point));
+  assert!(!stream.metadata().is_empty());
+
+  // Plain readers aren't able to seek over the blocks.
+  let mut file  = File::open(filename).unwrap();
+  let mut bytes = Vec::new();
+
+  file.read_to_end(&mut bytes).unwrap();
+
+  let result = Stream::<ReadStream<&[u8]>>::with_options(&bytes[..], options);
+
+  assert_eq!(result.err(), Some(ErrorKind::Unsupported));
+
+  let mut stream = Stream::<ByteStream>::from_buffer_with_options(&bytes,
+                                                                  options)
+                     .unwrap();
+
+  assert!(stream.iter::<i16>().eq(samples.iter().cloned()));
+  assert_eq!(stream.load_metadata().unwrap().len(), eager.metadata().len());
+}
+
+#[test]
+fn test_rice2_escape() {
+  // The first frame has one partition with the largest Rice parameter of
+  // the second coding method, and escaped partitions of 0, 17, and 1 bits.
+  // The second escapes the partition holding the warm up samples of a
+  // fixed prediction with 2 bits and uses the largest parameter of the
+  // first coding method. The last frame escapes every residual with 31
+  // bits.
+  let first  = (0..4096).map(|i| {
+    match i {
+      0...1023    => (i * 7919 % 16777216) - 8388608,
+      1024...2047 => 0,
+      2048...3071 => (i * 31 % 131072) - 65536,
+      _           => -(i % 2),
+    }
+  });
+  let second = (0..4096).map(|i| i * i / 64 - 100000);
+  let third  = (0..100).map(|i| if i % 2 == 0 { 8388607 } else { -8388608 });
+
+  let expected = first.chain(second).chain(third).collect::<Vec<i64>>();
+
+  let filename   = "tests/assets/input-rice2-escape.flac";
+  let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+
+  assert_eq!(stream.info().bits_per_sample.get(), 24);
+  assert!(stream.iter::<i32>().map(|sample| sample as i64)
+                .eq(expected.iter().cloned()));
+}
+
+#[test]
+fn test_frame_format_change() {
+  let mut info = StreamInfo::default();
+
+  info.sample_rate     = SampleRate::new(44100).unwrap();
+  info.channels        = 1;
+  info.bits_per_sample = BitsPerSample::new(16).unwrap();
+
+  let samples = (0..2048).map(|i| (i % 300) - 150).collect::<Vec<_>>();
+  let mono    = encode_blocks(info, &samples, 1024);
+
+  // Appends the frames of another stream to the mono one, like a file that
+  // got concatenated.
+  let concatenate = |info: StreamInfo, samples: &[i32]| {
+    let other  = encode_blocks(info, samples, 1024);
+    let offset = Stream::<ByteStream>::from_buffer(&other).unwrap()
+                   .audio_offset() as usize;
+
+    let mut bytes = mono.clone();
+
+    bytes.extend_from_slice(&other[offset..]);
+
+    bytes
+  };
+
+  let mut stereo = info;
+  let mut wider  = info;
+
+  stereo.channels       = 2;
+  wider.bits_per_sample = BitsPerSample::new(24).unwrap();
+
+  let interleaved = samples.iter().flat_map(|&s| vec![s, -s])
+                           .collect::<Vec<_>>();
+
+  for bytes in &[concatenate(stereo, &interleaved),
+                 concatenate(wider, &samples)] {
+    let mut stream = Stream::<ByteStream>::from_buffer(bytes).unwrap();
+
+    assert!(stream.iter::<i16>().map(|sample| sample as i32)
+                  .eq(samples.iter().cloned()));
+
+    let progress = stream.decode_progress();
+
+    assert_eq!(progress.frames, 2);
+    assert_eq!(progress.error, Some(ErrorKind::InvalidFrameFormat));
+  }
+}
+
+#[test]
+fn test_block_size_past_buffer() {
+  use flac::stream::frame_length;
+
+  let mut info = StreamInfo::default();
+
+  info.sample_rate     = SampleRate::new(44100).unwrap();
+  info.channels        = 1;
+  info.bits_per_sample = BitsPerSample::new(16).unwrap();
+
+  let samples   = (0..3072).map(|i| (i % 300) - 150).collect::<Vec<_>>();
+  let mut bytes = encode_blocks(info, &samples, 1024);
+
+  let (start, header) = {
+    let stream = Stream::<ByteStream>::from_buffer(&bytes).unwrap();
+    let audio  = stream.audio_offset() as usize;
+    let start  = audio + frame_length(&bytes[audio..], &stream.info())
+                           .unwrap();
+    let header = (5..17).find(|&length| {
+      crc8(&bytes[start..(start + length)]) == bytes[start + length]
+    }).unwrap();
+
+    (start, header)
+  };
+
+  // The second frame claims 2048 samples, past the maximum block size of
+  // the stream, with its CRC-8 still matching.
+  bytes[start + 2]      = (bytes[start + 2] & 0x0f) | 0b10110000;
+  bytes[start + header] = crc8(&bytes[start..(start + header)]);
+
+  let mut stream = Stream::<ByteStream>::from_buffer(&bytes).unwrap();
+
+  assert!(stream.iter::<i16>().map(|sample| sample as i32)
+                .eq(samples[0..1024].iter().cloned()));
+
+  let progress = stream.decode_progress();
+
+  assert_eq!(progress.frames, 1);
+  assert_eq!(progress.error, Some(ErrorKind::InvalidBlockSize));
+}
+
+#[test]
+fn test_next_frame_into_i16() {
+  // Reads every frame through `next_frame_into_i16` into a single vector.
+  fn decode_all(filename: &str) -> Vec<i16> {
+    let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+    let info       = stream.info();
+    let channels   = info.channels as usize;
+    let mut output = vec![0; info.max_block_size as usize * channels];
+    let mut result = Vec::new();
+
+    loop {
+      let block_size = stream.next_frame_into_i16(&mut output).unwrap();
+
+      if block_size == 0 {
+        break;
+      }
+
+      result.extend_from_slice(&output[0..(block_size * channels)]);
+    }
+
+    result
+  }
+
+  for &filename in &["tests/assets/input-SVAUP.flac",
+                     "tests/assets/input-SCPAP.flac"] {
+    let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+
+    assert!(decode_all(filename).into_iter().eq(stream.iter::<i16>()));
+  }
+
+  // Samples wider than 16 bits only keep their upper bits.
+  let filename   = "tests/assets/input-rice2-escape.flac";
+  let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+
+  assert!(decode_all(filename).into_iter().map(|sample| sample as i32)
+                              .eq(stream.iter::<i32>().map(|s| s >> 8)));
+
+  // Narrower ones get scaled up to fill the range of `i16`.
+  let filename   = "tests/assets/input-mono-8bit.flac";
+  let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+
+  assert!(decode_all(filename).into_iter().map(|sample| sample as i32)
+                              .eq(stream.iter::<i32>().map(|s| s << 8)));
+
+  let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+  let info       = stream.info();
+  let mut output = vec![0; info.max_block_size as usize - 1];
+
+  assert_eq!(stream.next_frame_into_i16(&mut output),
+             Err(ErrorKind::OutputTooSmall(info.max_block_size as usize)));
+}
+
+#[test]
+fn test_parse_frame_header() {
+  use flac::stream::parse_frame_header;
+
+  let mut file   = File::open("tests/assets/input-SCPAP.flac").unwrap();
+  let mut buffer = Vec::new();
+
+  file.read_to_end(&mut buffer).unwrap();
+
+  let mut stream = Stream::<ByteStream>::from_buffer(&buffer).unwrap();
+  let info       = stream.info();
+  let starts     = stream.frame_ranges().map(|frame| frame.range.start)
+                                        .collect::<Vec<_>>();
+
+  // Scanning the whole file, headers only parse where frames start.
+  let found = (0..buffer.len()).filter(|&offset| {
+    parse_frame_header(&buffer[offset..], &info).is_ok()
+  }).map(|offset| offset as u64).collect::<Vec<_>>();
+
+  assert_eq!(found, starts);
+
+  let start          = starts[0] as usize;
+  let (header, size) = parse_frame_header(&buffer[start..], &info).unwrap();
+
+  assert_eq!(header.channels, info.channels);
+  assert_eq!(header.bits_per_sample, info.bits_per_sample);
+
+  match parse_frame_header(&buffer[start..(start + size - 1)], &info) {
+    Err(ErrorKind::Incomplete(_)) => (),
+    result                        => panic!("{:?}", result),
+  }
+
+  // Flipping a bit of the header makes its CRC-8 wrong.
+  let mut bytes = buffer[start..(start + size)].to_vec();
+
+  bytes[2] ^= 0x01;
+
+  assert_eq!(parse_frame_header(&bytes, &info).map(|(_, size)| size),
+             Err(ErrorKind::InvalidCRC8));
+}
+
+#[test]
+fn test_metadata_cap() {
+  let filename = "tests/assets/input-pictures.flac";
+  let options  = StreamOptions {
+    max_metadata_size: Some(2048),
+    ..Default::default()
+  };
+
+  let mut file   = File::open(filename).unwrap();
+  let mut buffer = Vec::new();
+
+  file.read_to_end(&mut buffer).unwrap();
+
+  let uncapped = Stream::<ByteStream>::from_buffer(&buffer).unwrap();
+  let samples  = Stream::<ByteStream>::from_buffer(&buffer).unwrap()
+                   .iter::<i32>().collect::<Vec<_>>();
+
+  // Raw bytes are counted too, so the buffer starts out above the cap.
+  assert!(uncapped.metadata_heap_size() > 2048);
+
+  let mut buffer_stream =
+    Stream::<ByteStream>::from_buffer_with_options(&buffer, options)
+      .unwrap();
+  let mut file_stream   =
+    Stream::<ReadStream<File>>::from_file_with_options(filename, options)
+      .unwrap();
+
+  let spilled = {
+    let blocks = buffer_stream.metadata();
+
+    assert_eq!(blocks.len(), uncapped.metadata().len());
+    assert!(buffer_stream.metadata_heap_size() <= 2048);
+
+    // Any block still holding its original bytes keeps the copy of the
+    // whole metadata, pictures included, from being freed.
+    assert!(uncapped.metadata().iter().all(|block| block.raw().is_some()));
+    assert!(blocks.iter().all(|block| block.raw().is_none()));
+    assert!(file_stream.metadata_heap_size() <= 2048);
+
+    blocks.iter().enumerate().filter(|&(_, block)| block.is_spilled())
+          .map(|(index, _)| index).collect::<Vec<_>>()
+  };
+
+  assert!(!spilled.is_empty());
+
+  for &index in &spilled {
+    let mut bytes = Vec::new();
+
+    assert!(buffer_stream.metadata()[index].to_bytes(&mut bytes).is_err());
+
+    let mut expected = Vec::new();
+
+    uncapped.metadata()[index].to_bytes(&mut expected).unwrap();
+
+    for stream_bytes in vec![buffer_stream.load_block(index),
+                             file_stream.load_block(index)] {
+      let mut bytes = Vec::new();
+
+      stream_bytes.unwrap().to_bytes(&mut bytes).unwrap();
+
+      assert_eq!(bytes, expected);
+    }
+  }
+
+  // Loading blocks leaves decoding where it was.
+  assert!(buffer_stream.iter::<i32>().eq(samples.iter().cloned()));
+  assert!(file_stream.iter::<i32>().eq(samples.iter().cloned()));
+
+  let length = uncapped.metadata().len();
+
+  assert_eq!(file_stream.load_block(length).map(|_| ()),
+             Err(ErrorKind::NotFound));
+}
+
+#[test]
+fn test_extreme_sample_rates() {
+  use flac::metadata::samples_to_duration;
+  use flac::pcm::OutputFormat;
+  use std::time::Duration;
+
+  let samples = (0..3000).map(|i| (i * 7 % 200) - 100).collect::<Vec<_>>();
+
+  // Rates with a code of their own, stored within each frame header, and
+  // only stored within `StreamInfo`.
+  for &sample_rate in &[1, 3, 1000, 65535, 65536, 655350, 655349] {
+    let mut info = StreamInfo::default();
+
+    info.sample_rate     = SampleRate::new(sample_rate).unwrap();
+    info.channels        = 1;
+    info.bits_per_sample = BitsPerSample::new(16).unwrap();
+    info.total_samples   = samples.len() as u64;
+
+    let options = EncoderOptions {
+      block_size: 1024,
+      seek_point_interval: Some(SeekPointInterval::Seconds(1)),
+      ..Default::default()
+    };
+
+    let mut encoder = Encoder::new(Cursor::new(Vec::new()), info, options)
+                        .unwrap();
+
+    encoder.write(&samples).unwrap();
+
+    let bytes      = encoder.finish().unwrap().into_inner();
+    let mut stream = Stream::<ByteStream>::from_buffer(&bytes).unwrap();
+    let info       = stream.info();
+
+    assert_eq!(info.sample_rate.get(), sample_rate);
+    assert_eq!(info.is_audio_rate(), sample_rate >= 8000);
+    assert!(stream.iter::<i32>().eq(samples.iter().cloned()));
+    assert!(stream.stats().bytes_per_second().unwrap() > 0.0);
+
+    let duration = samples_to_duration(info.total_samples, sample_rate);
+
+    if sample_rate == 1 {
+      assert_eq!(duration, Duration::new(3000, 0));
+    }
+
+    // Seek points land on frames, with one for every frame at the lowest
+    // rates.
+    let sample = stream.seek(2500).unwrap() as usize;
+
+    assert!(sample <= 2500 && sample % 1024 == 0);
+    assert!(sample_rate > 3 || sample == 2048);
+    assert!(stream.iter::<i32>().eq(samples[sample..].iter().cloned()));
+
+    let format = OutputFormat {
+      bits_per_sample: 16,
+      sample_rate: sample_rate,
+      channels: 1,
+    };
+
+    assert!(stream.negotiate_output(&[format]).is_some());
+  }
+}
+
+#[test]
+fn test_analysis_windows() {
+  let filename   = "tests/assets/input-SCPAP.flac";
+  let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+  let info       = stream.info();
+  let channels   = info.channels as usize;
+  let scale      = 1.0 / ((1u64 << (info.bits_per_sample.get() - 1)) as f32 *
+                          channels as f32);
+
+  let samples = stream.iter::<i32>().collect::<Vec<_>>();
+  let mono    = samples.chunks(channels).map(|sample| {
+    sample.iter().fold(0i64, |sum, &value| sum + value as i64) as f32 * scale
+  }).collect::<Vec<_>>();
+
+  assert!(mono.iter().all(|&sample| sample >= -1.0 && sample < 1.0));
+
+  for &(size, hop) in &[(2048, 512), (1000, 1000), (256, 1000), (1, 1)] {
+    stream.rewind().unwrap();
+
+    let windows = stream.analysis_windows(size, hop).collect::<Vec<_>>();
+
+    // Windows keep coming until one reaches the last sample.
+    let mut start = 0;
+
+    for window in &windows {
+      let end = cmp::min(start + size, mono.len());
+
+      assert_eq!(window.len(), size);
+      assert_eq!(&window[0..(end - start)], &mono[start..end]);
+      assert!(window[(end - start)..].iter().all(|&sample| sample == 0.0));
+
+      start += hop;
+    }
+
+    let last = start - hop;
+
+    assert!(last < mono.len());
+    assert!(last + size >= mono.len() || last + hop >= mono.len());
+  }
+}
+
+#[test]
+fn test_iter_positioned() {
+  let filename   = "tests/assets/input-SVAUP.flac";
+  let mut stream = Stream::<ReadStream<File>>::from_file(filename).unwrap();
+  let channels   = stream.info().channels as usize;
+  let samples    = stream.iter::<i16>().collect::<Vec<_>>();
+
+  stream.rewind().unwrap();
+
+  let positioned = stream.iter_positioned::<i16>().collect::<Vec<_>>();
+
+  assert_eq!(positioned.len(), samples.len());
+
+  for (i, &(sample_number, channel, value)) in positioned.iter().enumerate() {
+    assert_eq!(sample_number, (i / channels) as u64);
+    assert_eq!(channel, i % channels);
+    assert_eq!(value, samples[i]);
+  }
+
+  // Sample numbers carry on from wherever seeking lands.
+  let start = stream.seek(20000).unwrap();
+  let first = stream.iter_positioned::<i16>().next().unwrap();
+
+  assert!(start > 0);
+  assert_eq!(first, (start, 0, samples[start as usize * channels]));
+}